@@ -20,7 +20,7 @@ limitations under the License.
 // This is done by reading the hyperlight-wasm-runtime binary into a static byte array named WASM_RUNTIME.
 // this build script writes the code to do that to a file named built.rs in the OUT_DIR.
 // this file is included in lib.rs.
-// The hyperlight-wasm-runtime binary is expected to be in the x64/{config} directory.
+// The hyperlight-wasm-runtime binary is built from source, or taken prebuilt from the path in HYPERLIGHT_WASM_RUNTIME.
 
 use std::fs::OpenOptions;
 use std::io::Write;
@@ -108,14 +108,21 @@ fn find_target_dir() -> PathBuf {
     target_dir.to_path_buf()
 }
 
-fn build_wasm_runtime() -> PathBuf {
+/// Build the hyperlight-wasm-runtime, for components of the world in WIT_WORLD
+/// if it is set and `modules_only` is false, or for modules otherwise
+fn build_wasm_runtime(modules_only: bool) -> PathBuf {
     let profile = env::var_os("PROFILE").unwrap();
 
     // Get the current target directory.
     let target_dir = find_target_dir();
     // Do not use the target directory directly, as it is locked by cargo with the current build
-    // and would result in a deadlock
-    let target_dir = target_dir.join("hyperlight-wasm-runtime");
+    // and would result in a deadlock. The runtime for modules built alongside the runtime for
+    // components gets its own directory, so that the two builds do not replace each other.
+    let target_dir = target_dir.join(if modules_only {
+        "hyperlight-wasm-runtime-modules"
+    } else {
+        "hyperlight-wasm-runtime"
+    });
 
     let manifest_path = get_wasm_runtime_manifest_path();
     let runtime_dir = manifest_path.parent().unwrap();
@@ -125,8 +132,6 @@ fn build_wasm_runtime() -> PathBuf {
     }
 
     println!("cargo::rerun-if-changed={}", runtime_dir.display());
-    println!("cargo::rerun-if-env-changed=WIT_WORLD");
-    println!("cargo::rerun-if-env-changed=WIT_WORLD_NAME");
     // the PROFILE env var unfortunately only gives us 1 bit of "dev or release"
     let cargo_profile = if profile == "debug" { "dev" } else { "release" };
 
@@ -142,6 +147,9 @@ fn build_wasm_runtime() -> PathBuf {
         .arg(&manifest_path)
         .arg("--locked")
         .env_clear_cargo();
+    if modules_only {
+        cmd = cmd.env_remove("WIT_WORLD").env_remove("WIT_WORLD_NAME");
+    }
 
     // LTS is the runtime default; wasmtime_latest opts into the latest version.
     if std::env::var("CARGO_FEATURE_WASMTIME_LATEST").is_ok() {
@@ -159,6 +167,10 @@ fn build_wasm_runtime() -> PathBuf {
     if std::env::var("CARGO_FEATURE_PULLEY").is_ok() {
         cmd = cmd.arg("--features").arg("pulley");
     }
+    // Add --features gc if the gc feature is enabled
+    if std::env::var("CARGO_FEATURE_GC").is_ok() {
+        cmd = cmd.arg("--features").arg("gc");
+    }
     // Enable the "trace_guest" feature if the corresponding Cargo feature is enabled
     if std::env::var("CARGO_FEATURE_TRACE_GUEST").is_ok() {
         cmd = cmd.arg("--features").arg("trace_guest");
@@ -189,46 +201,148 @@ fn build_wasm_runtime() -> PathBuf {
     }
 }
 
-fn main() -> Result<()> {
-    let wasm_runtime_resource = build_wasm_runtime();
-
-    let out_dir = env::var_os("OUT_DIR").unwrap();
-    let dest_path = Path::new(&out_dir).join("wasm_runtime_resource.rs");
-    let contents = format!(
-        "pub (super) static WASM_RUNTIME: [u8; include_bytes!({name:?}).len()] = *include_bytes!({name:?});",
-        name = wasm_runtime_resource.as_os_str()
-    );
-
-    fs::write(dest_path, contents).unwrap();
+/// The prebuilt hyperlight-wasm-runtime named by HYPERLIGHT_WASM_RUNTIME, if
+/// set, which is embedded in place of building the runtime from source. This
+/// allows hyperlight-wasm to be built without the runtime's sources or the
+/// guest toolchain.
+fn prebuilt_wasm_runtime() -> Option<PathBuf> {
+    println!("cargo::rerun-if-env-changed=HYPERLIGHT_WASM_RUNTIME");
+    let path = env::var_os("HYPERLIGHT_WASM_RUNTIME")?;
+    let path = PathBuf::from(path);
+    let path = path.canonicalize().unwrap_or_else(|e| {
+        panic!(
+            "could not find the hyperlight-wasm-runtime named by HYPERLIGHT_WASM_RUNTIME ({:?}): {e}",
+            path
+        )
+    });
+    println!("cargo::rerun-if-changed={}", path.display());
 
-    // get the wasmtime version number from the hyperlight-wasm-runtime metadata
+    // The runtime features are fixed when the runtime is built
+    let features: Vec<&str> = ["wasmtime_latest", "gdb", "pulley", "gc", "trace_guest"]
+        .into_iter()
+        .filter(|feature| {
+            env::var_os(format!("CARGO_FEATURE_{}", feature.to_uppercase())).is_some()
+        })
+        .collect();
+    if !features.is_empty() {
+        println!(
+            "cargo:warning=using the prebuilt hyperlight-wasm-runtime {}, which must have been built with the features {}",
+            path.display(),
+            features.join(", ")
+        );
+    }
+    Some(path)
+}
 
-    let wasm_runtime_bytes = fs::read(&wasm_runtime_resource).unwrap();
-    let elf = goblin::elf::Elf::parse(&wasm_runtime_bytes).unwrap();
+/// The wasmtime version recorded in the .note_hyperlight_metadata section of
+/// the hyperlight-wasm-runtime binary, checking that the binary is a guest
+/// this crate can embed
+fn wasm_runtime_wasmtime_version(
+    wasm_runtime_resource: &Path,
+    wasm_runtime_bytes: &[u8],
+) -> String {
+    let elf = goblin::elf::Elf::parse(wasm_runtime_bytes).unwrap_or_else(|e| {
+        panic!(
+            "hyperlight-wasm-runtime {:?} is not an ELF binary: {e}",
+            wasm_runtime_resource
+        )
+    });
+    assert!(
+        elf.header.e_machine == goblin::elf::header::EM_X86_64 && elf.is_64,
+        "hyperlight-wasm-runtime {:?} is not an x86_64 binary",
+        wasm_runtime_resource
+    );
 
     // the hyperlight-wasm-runtime binary has a section named .note_hyperlight_metadata that contains the wasmtime version number
     // this section is added to the hyperlight-wasm-runtime binary by the build.rs script in the hyperlight-wasm-runtime crate
     let section_name = ".note_hyperlight_metadata";
-    let wasmtime_version_number = if let Some(header) = elf.section_headers.iter().find(|hdr| {
+    let Some(header) = elf.section_headers.iter().find(|hdr| {
         if let Some(name) = elf.shdr_strtab.get_at(hdr.sh_name) {
             name == section_name
         } else {
             false
         }
-    }) {
-        let start = header.sh_offset as usize;
-        let size = header.sh_size as usize;
-        let end = start + size;
-        let metadata_bytes = &wasm_runtime_bytes[start..end];
-        // convert the metadata bytes to a string
-        if let Some(null_pos) = metadata_bytes.iter().position(|&b| b == 0) {
-            std::str::from_utf8(&metadata_bytes[..null_pos]).unwrap()
-        } else {
-            std::str::from_utf8(metadata_bytes).unwrap()
-        }
+    }) else {
+        panic!(
+            "{section_name} section not found in hyperlight-wasm-runtime binary {:?}",
+            wasm_runtime_resource
+        );
+    };
+    let start = header.sh_offset as usize;
+    let size = header.sh_size as usize;
+    let end = start + size;
+    let metadata_bytes = wasm_runtime_bytes.get(start..end).unwrap_or_else(|| {
+        panic!(
+            "{section_name} section of hyperlight-wasm-runtime binary {:?} is truncated",
+            wasm_runtime_resource
+        )
+    });
+    // convert the metadata bytes to a string
+    let metadata_bytes = match metadata_bytes.iter().position(|&b| b == 0) {
+        Some(null_pos) => &metadata_bytes[..null_pos],
+        None => metadata_bytes,
+    };
+    let version = std::str::from_utf8(metadata_bytes)
+        .ok()
+        .filter(|version| {
+            let parts: Vec<&str> = version.split('.').collect();
+            parts.len() >= 3 && parts[..3].iter().all(|part| part.parse::<u64>().is_ok())
+        })
+        .unwrap_or_else(|| {
+            panic!(
+                "{section_name} section of hyperlight-wasm-runtime binary {:?} does not hold a wasmtime version",
+                wasm_runtime_resource
+            )
+        });
+    version.to_string()
+}
+
+fn main() -> Result<()> {
+    println!("cargo::rerun-if-env-changed=WIT_WORLD");
+    println!("cargo::rerun-if-env-changed=WIT_WORLD_NAME");
+    let prebuilt_wasm_runtime = prebuilt_wasm_runtime();
+    let is_component_runtime = env::var_os("WIT_WORLD").is_some();
+    // A build for components also embeds a runtime for modules, so that each sandbox can
+    // choose which it runs, unless the runtime is prebuilt
+    let wasm_module_runtime_resource = if is_component_runtime && prebuilt_wasm_runtime.is_none() {
+        Some(build_wasm_runtime(true))
     } else {
-        panic!(".note_hyperlight_metadata section not found in hyperlight-wasm-runtime binary");
+        None
     };
+    let wasm_runtime_resource = prebuilt_wasm_runtime.unwrap_or_else(|| build_wasm_runtime(false));
+
+    let out_dir = env::var_os("OUT_DIR").unwrap();
+    let dest_path = Path::new(&out_dir).join("wasm_runtime_resource.rs");
+    let mut contents = format!(
+        "pub (super) static WASM_RUNTIME: [u8; include_bytes!({name:?}).len()] = *include_bytes!({name:?});\n",
+        name = wasm_runtime_resource.as_os_str()
+    );
+    contents.push_str(&format!(
+        "pub (super) static WASM_RUNTIME_KIND: RuntimeKind = RuntimeKind::{kind};\n",
+        kind = if is_component_runtime {
+            "Component"
+        } else {
+            "Module"
+        }
+    ));
+    contents.push_str(&match &wasm_module_runtime_resource {
+        Some(resource) => {
+            let bytes = fs::read(resource).unwrap();
+            wasm_runtime_wasmtime_version(resource, &bytes);
+            format!(
+                "pub (super) static WASM_MODULE_RUNTIME: Option<&[u8]> = Some(include_bytes!({name:?}));\n",
+                name = resource.as_os_str()
+            )
+        }
+        None => "pub (super) static WASM_MODULE_RUNTIME: Option<&[u8]> = None;\n".to_string(),
+    });
+
+    fs::write(dest_path, contents).unwrap();
+
+    // get the wasmtime version number from the hyperlight-wasm-runtime metadata
+    let wasm_runtime_bytes = fs::read(&wasm_runtime_resource).unwrap();
+    let wasmtime_version_number =
+        wasm_runtime_wasmtime_version(&wasm_runtime_resource, &wasm_runtime_bytes);
 
     // write the build information to the built.rs file
     write_built_file()?;
@@ -264,8 +378,7 @@ fn main() -> Result<()> {
     writeln!(file, "{}", wasm_runtime_wasmtime_version).unwrap();
 
     // Calculate the blake3 hash of the hyperlight-wasm-runtime file and write it to the wasm_runtime_resource.rs file so we can include it in the binary
-    let hyperlight_wasm_runtime = fs::read(wasm_runtime_resource).unwrap();
-    let hash = blake3::hash(&hyperlight_wasm_runtime);
+    let hash = blake3::hash(&wasm_runtime_bytes);
     let hash_str = format!("static WASM_RUNTIME_BLAKE3_HASH: &str = \"{}\";", hash);
 
     writeln!(file, "{}", hash_str).unwrap();
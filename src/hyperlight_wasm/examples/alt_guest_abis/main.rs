@@ -0,0 +1,56 @@
+/*
+Copyright 2025 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+// Runs the AssemblyScript and TinyGo sample guests, which must first be
+// built with `just build-alt-guest-examples`.
+
+use examples_common::get_wasm_module_path;
+use hyperlight_wasm::{GuestAbi, Result, SandboxBuilder};
+
+fn main() -> Result<()> {
+    for (module, guest_abi) in [
+        ("hello_as.aot", GuestAbi::AssemblyScript),
+        ("hello_tinygo.aot", GuestAbi::TinyGo),
+    ] {
+        let mut proto_wasm_sandbox = SandboxBuilder::new().build()?;
+        proto_wasm_sandbox.register("HostLog", |msg: String| {
+            println!("guest says: {msg}");
+            msg.len() as i32
+        })?;
+
+        let mut loaded_wasm_sandbox = proto_wasm_sandbox
+            .load_runtime()?
+            .with_guest_abi(guest_abi)
+            .load_module(get_wasm_module_path(module)?)?;
+
+        let greeting: String =
+            loaded_wasm_sandbox.call_guest_function("Greet", "Hyperlight".to_string())?;
+        println!("{module}: Greet returned {greeting:?}");
+
+        let logged: i32 = loaded_wasm_sandbox
+            .call_guest_function("LogMessage", "logging from the guest".to_string())?;
+        println!("{module}: LogMessage returned {logged}");
+
+        let buffer = vec![0u8; 16];
+        let len: i32 = loaded_wasm_sandbox
+            .call_guest_function("ByteLength", (buffer.clone(), buffer.len() as i32))?;
+        println!("{module}: ByteLength returned {len}");
+
+        let collected = loaded_wasm_sandbox.hint_gc()?;
+        println!("{module}: hint_gc collected: {collected}");
+    }
+    Ok(())
+}
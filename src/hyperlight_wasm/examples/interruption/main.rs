@@ -19,12 +19,13 @@ limitations under the License.
 //! 2. Interrupt long-running guest code from another thread
 //! 3. Detect when a sandbox is poisoned
 //! 4. Recover a poisoned sandbox using `restore()` or `unload_module()`
+//! 5. Bound how long a guest call runs for with a timeout
 
 use std::thread;
 use std::time::Duration;
 
 use examples_common::get_wasm_module_path;
-use hyperlight_wasm::{HyperlightError, Result, SandboxBuilder};
+use hyperlight_wasm::{GuestCallTimeout, HyperlightError, Result, SandboxBuilder};
 
 fn get_time_since_boot_microsecond() -> Result<i64> {
     let res = std::time::SystemTime::now()
@@ -138,6 +139,25 @@ fn main() -> Result<()> {
 
     println!("   HelloWorld returned: {}", result);
 
+    // Timeouts interrupt the call without a thread of our own. They can
+    // also be set for every call with SandboxBuilder::with_guest_call_timeout
+    println!("\n10. Calling a long-running guest function with a 500ms timeout...");
+    let wasm_sandbox = new_loaded.unload_module()?;
+    let mut loaded = wasm_sandbox.load_module(get_wasm_module_path("RunWasm.aot")?)?;
+    let result = loaded.call_guest_function_with_timeout::<i32>(
+        "KeepCPUBusy",
+        100000i32,
+        Duration::from_millis(500),
+    );
+    match result {
+        Ok(_) => panic!("   Guest function completed (unexpected!)"),
+        Err(e) => match GuestCallTimeout::from_error(&e) {
+            Some(timeout) => println!("   {}", timeout),
+            None => panic!("   Unexpected error: {:?}", e),
+        },
+    }
+    println!("   is_poisoned: {}", loaded.is_poisoned()?);
+
     println!("\n=== Example Complete ===");
     Ok(())
 }
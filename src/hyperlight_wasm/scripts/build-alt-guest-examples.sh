@@ -0,0 +1,58 @@
+#!/usr/bin/env bash
+
+# Builds the AssemblyScript and TinyGo sample guests. Requires npm and tinygo
+# to be installed.
+
+set -o errexit
+set -o nounset
+set -o pipefail
+
+pushd "$(dirname "${BASH_SOURCE[0]}")/../../tests"
+OUTPUT_DIR="../../x64/${1:-"debug"}"
+BUILD_TYPE="${1:-"debug"}"
+FEATURES="${2:-""}"
+mkdir -p ${OUTPUT_DIR}
+OUTPUT_DIR=$(realpath $OUTPUT_DIR)
+
+AOT_FEATURES=""
+AOT_DEBUG_FLAGS=""
+
+if [[ "$FEATURES" == *"gdb"* ]]; then
+    AOT_FEATURES="--features gdb"
+    AOT_DEBUG_FLAGS="--debug"
+fi
+
+if [[ "$FEATURES" == *"wasmtime_latest"* ]]; then
+    AOT_VERSION_FLAGS="--wasmtime-version latest"
+else
+    AOT_VERSION_FLAGS=""
+fi
+
+echo Building AssemblyScript guests
+for GUEST_DIR in ${PWD}/assemblyscript_guests/*/; do
+    GUEST_NAME=$(basename ${GUEST_DIR})
+    echo Building ${GUEST_NAME}
+    pushd ${GUEST_DIR}
+    npm install --no-audit --no-fund
+    npx asc assembly/index.ts --target ${BUILD_TYPE} -o ${OUTPUT_DIR}/${GUEST_NAME}.wasm
+    popd
+    cargo run ${AOT_FEATURES} -p hyperlight-wasm-aot compile ${AOT_DEBUG_FLAGS} ${AOT_VERSION_FLAGS} ${OUTPUT_DIR}/${GUEST_NAME}.wasm ${OUTPUT_DIR}/${GUEST_NAME}.aot
+done
+
+if [[ "$BUILD_TYPE" == "debug" ]]; then
+    TINYGO_FLAGS="-opt=1"
+else
+    TINYGO_FLAGS="-opt=2 -no-debug"
+fi
+
+echo Building TinyGo guests
+for GUEST_DIR in ${PWD}/tinygo_guests/*/; do
+    GUEST_NAME=$(basename ${GUEST_DIR})
+    echo Building ${GUEST_NAME}
+    pushd ${GUEST_DIR}
+    tinygo build -target=wasm-unknown ${TINYGO_FLAGS} -o ${OUTPUT_DIR}/${GUEST_NAME}.wasm .
+    popd
+    cargo run ${AOT_FEATURES} -p hyperlight-wasm-aot compile ${AOT_DEBUG_FLAGS} ${AOT_VERSION_FLAGS} ${OUTPUT_DIR}/${GUEST_NAME}.wasm ${OUTPUT_DIR}/${GUEST_NAME}.aot
+done
+
+popd
@@ -0,0 +1,383 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Precompile WebAssembly modules and components for the Wasm runtime
+//! embedded in this crate, without running the `hyperlight-wasm-aot`
+//! binary.
+//!
+//! Artifacts are compiled for the same target and wasmtime version as
+//! the embedded runtime, so they are guaranteed to load into sandboxes
+//! built by this build of the crate. Whether the input is a module or a
+//! component is detected from its header.
+//!
+//! With this feature enabled, sandboxes also precompile guests that are
+//! not yet precompiled as they are loaded, see
+//! [`SandboxBuilder::with_host_precompilation`]. An
+//! [`AotCache`](crate::aot::AotCache) keeps the artifacts on disk, so
+//! that each guest is only compiled once for each set of sandbox
+//! settings.
+//!
+//! ```no_run
+//! let wasm = std::fs::read("module.wasm").unwrap();
+//! let artifact = hyperlight_wasm::aot::compile(&wasm).unwrap();
+//! std::fs::write("module.aot", artifact).unwrap();
+//! ```
+
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use hyperlight_host::{Result, new_error};
+use hyperlight_wasm_aot::validate::RuntimeExpectation;
+use hyperlight_wasm_aot::{CompileOptions, WasmtimeVersion};
+use hyperlight_wasm_runtime::runtime_config::RuntimeConfig;
+use hyperlight_wasm_runtime::runtime_config::{OptLevel, WasmFeatures};
+
+use crate::SandboxBuilder;
+
+/// Precompile the WebAssembly module or component in `wasm` for
+/// sandboxes built with the default settings of [`SandboxBuilder`]
+pub fn compile(wasm: &[u8]) -> Result<Vec<u8>> {
+    HostPrecompiler::new(&RuntimeConfig::default()).compile(wasm)
+}
+
+/// Precompile the WebAssembly module or component in `wasm` for
+/// sandboxes built by `builder`, enabling NaN canonicalization, epoch
+/// interruption and deterministic relaxed SIMD if the builder requires
+/// them, and with the builder's WebAssembly features and optimization
+/// level
+pub fn compile_for_sandbox(wasm: &[u8], builder: &SandboxBuilder) -> Result<Vec<u8>> {
+    HostPrecompiler::new(builder.runtime_config()).compile(wasm)
+}
+
+/// A directory of precompiled artifacts, keyed by a hash of the
+/// WebAssembly they were compiled from and the settings they were
+/// compiled with, see
+/// [`SandboxBuilder::with_aot_cache`](crate::SandboxBuilder::with_aot_cache)
+/// and
+/// [`WasmSandbox::load_module_cached`](crate::WasmSandbox::load_module_cached).
+///
+/// An artifact found in the cache is only reused if it is compatible
+/// with the embedded runtime; otherwise it is compiled again and
+/// replaced. Artifacts are written to a temporary file and renamed into
+/// place, so processes can share a cache directory. Nothing is ever
+/// removed from the cache, so delete the directory to reclaim its space.
+///
+/// ```no_run
+/// use hyperlight_wasm::aot::AotCache;
+///
+/// let cache = AotCache::new("/var/cache/hyperlight-wasm");
+/// let wasm = std::fs::read("module.wasm").unwrap();
+/// // Compiled the first time, read from the cache after that
+/// let artifact = cache.compile(&wasm).unwrap();
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AotCache {
+    dir: PathBuf,
+}
+
+// Distinguishes the temporary files of concurrent writes from one process
+static NEXT_TEMP_FILE: AtomicU64 = AtomicU64::new(0);
+
+impl AotCache {
+    /// A cache keeping its artifacts in `dir`, which is created when the
+    /// first artifact is written
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// The directory the artifacts are kept in
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Precompile the WebAssembly module or component in `wasm` for
+    /// sandboxes built with the default settings of [`SandboxBuilder`],
+    /// as [`compile`] does, reusing the artifact in the cache if there
+    /// is one
+    pub fn compile(&self, wasm: &[u8]) -> Result<Vec<u8>> {
+        HostPrecompiler::new(&RuntimeConfig::default())
+            .with_cache(Some(self.clone()))
+            .compile(wasm)
+    }
+
+    /// Precompile the WebAssembly module or component in `wasm` for
+    /// sandboxes built by `builder`, as [`compile_for_sandbox`] does,
+    /// reusing the artifact in the cache if there is one
+    pub fn compile_for_sandbox(&self, wasm: &[u8], builder: &SandboxBuilder) -> Result<Vec<u8>> {
+        HostPrecompiler::new(builder.runtime_config())
+            .with_cache(Some(self.clone()))
+            .compile(wasm)
+    }
+
+    /// The path of the artifact compiled from `wasm` with `options`
+    fn path(&self, wasm: &[u8], options: &CompileOptions, wasmtime_version: &str) -> PathBuf {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(wasm);
+        hasher.update(format!("{:?}", options).as_bytes());
+        hasher.update(wasmtime_version.as_bytes());
+        self.dir.join(format!("{}.aot", hasher.finalize().to_hex()))
+    }
+
+    /// The artifact compiled from `wasm` with `options`, read from the
+    /// cache if it is there and compatible with the embedded runtime,
+    /// or compiled and written to the cache if not
+    fn get_or_compile(&self, wasm: &[u8], options: &CompileOptions) -> Result<Vec<u8>> {
+        let runtime = RuntimeExpectation::new(options.clone());
+        let path = self.path(wasm, options, &runtime.wasmtime_version);
+        if let Ok(artifact) = std::fs::read(&path) {
+            let report = hyperlight_wasm_aot::validate::validate(&artifact, &runtime);
+            if report.compatible {
+                tracing::debug!("reusing the cached artifact {}", path.display());
+                return Ok(artifact);
+            }
+            tracing::debug!(
+                "replacing the cached artifact {}: {}",
+                path.display(),
+                report.problems.join("; ")
+            );
+        }
+
+        let artifact = hyperlight_wasm_aot::precompile(wasm, options)
+            .map_err(|e| new_error!("failed to precompile WebAssembly: {}", e))?;
+        std::fs::create_dir_all(&self.dir).map_err(|e| {
+            new_error!(
+                "failed to create the AOT cache {}: {}",
+                self.dir.display(),
+                e
+            )
+        })?;
+        let temp = path.with_extension(format!(
+            "{}.{}.tmp",
+            std::process::id(),
+            NEXT_TEMP_FILE.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::write(&temp, &artifact)
+            .and_then(|()| std::fs::rename(&temp, &path))
+            .map_err(|e| {
+                let _ = std::fs::remove_file(&temp);
+                new_error!("failed to write {} to the AOT cache: {}", path.display(), e)
+            })?;
+        Ok(artifact)
+    }
+}
+
+/// Precompiles the guests loaded into a sandbox that are not yet
+/// precompiled, with the sandbox's settings
+#[derive(Clone, Debug)]
+pub(crate) struct HostPrecompiler {
+    canonicalize_nans: bool,
+    epoch_interruption: bool,
+    deterministic_relaxed_simd: bool,
+    wasm_features: WasmFeatures,
+    opt_level: OptLevel,
+    // Where the artifacts are kept, see SandboxBuilder::with_aot_cache
+    pub(crate) cache: Option<AotCache>,
+}
+
+impl HostPrecompiler {
+    pub(crate) fn new(runtime_config: &RuntimeConfig) -> Self {
+        Self {
+            canonicalize_nans: runtime_config.canonicalize_nans,
+            epoch_interruption: runtime_config.epoch_interruption,
+            deterministic_relaxed_simd: runtime_config.deterministic_relaxed_simd,
+            wasm_features: runtime_config.wasm_features,
+            opt_level: runtime_config.opt_level,
+            cache: None,
+        }
+    }
+
+    /// This precompiler, keeping its artifacts in `cache`
+    pub(crate) fn with_cache(mut self, cache: Option<AotCache>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    fn compile(&self, wasm: &[u8]) -> Result<Vec<u8>> {
+        if !hyperlight_wasm_aot::is_wasm(wasm) {
+            return Err(new_error!(
+                "cannot precompile input that is not a WebAssembly module or component"
+            ));
+        }
+        let options = CompileOptions {
+            component: hyperlight_wasm_aot::is_component(wasm),
+            debug: false,
+            minimal: false,
+            pulley: cfg!(feature = "pulley"),
+            canonicalize_nans: self.canonicalize_nans,
+            epoch_interruption: self.epoch_interruption,
+            deterministic_relaxed_simd: self.deterministic_relaxed_simd,
+            wasm_features: hyperlight_wasm_aot::WasmFeatures {
+                simd: self.wasm_features.simd,
+                relaxed_simd: self.wasm_features.relaxed_simd,
+                bulk_memory: self.wasm_features.bulk_memory,
+                tail_call: self.wasm_features.tail_call,
+                multi_memory: self.wasm_features.multi_memory,
+            },
+            opt_level: match self.opt_level {
+                OptLevel::None => hyperlight_wasm_aot::OptLevel::None,
+                OptLevel::Speed => hyperlight_wasm_aot::OptLevel::Speed,
+                OptLevel::SpeedAndSize => hyperlight_wasm_aot::OptLevel::SpeedAndSize,
+            },
+            wasmtime_version: if cfg!(feature = "wasmtime_latest") {
+                WasmtimeVersion::Latest
+            } else {
+                WasmtimeVersion::Lts
+            },
+        };
+        match &self.cache {
+            Some(cache) => cache.get_or_compile(wasm, &options),
+            None => hyperlight_wasm_aot::precompile(wasm, &options)
+                .map_err(|e| new_error!("failed to precompile WebAssembly: {}", e)),
+        }
+    }
+
+    /// Precompile `bytes` if they are a module or component, or return
+    /// `None` if they are already precompiled
+    pub(crate) fn precompile(&self, bytes: &[u8]) -> Result<Option<Vec<u8>>> {
+        if !hyperlight_wasm_aot::is_wasm(bytes) {
+            return Ok(None);
+        }
+        tracing::debug!("precompiling a guest that is not precompiled on the host");
+        self.compile(bytes).map(Some)
+    }
+
+    /// Precompile the file at `file` if it is a module or component, or
+    /// return `None` if it is already precompiled, without reading the
+    /// rest of the file
+    pub(crate) fn precompile_file(&self, file: &Path) -> Result<Option<Vec<u8>>> {
+        // A module or component is identified by its 8 byte header
+        let mut header = Vec::with_capacity(8);
+        File::open(file)?.take(8).read_to_end(&mut header)?;
+        if !hyperlight_wasm_aot::is_wasm(&header) {
+            return Ok(None);
+        }
+        self.precompile(&std::fs::read(file)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyperlight_wasm_runtime::runtime_config::{OptLevel, RuntimeConfig, WasmFeatures};
+
+    use super::{AotCache, HostPrecompiler, compile};
+
+    // An empty core module and an empty component
+    const EMPTY_MODULE: &[u8] = b"\0asm\x01\0\0\0";
+    const EMPTY_COMPONENT: &[u8] = b"\0asm\x0d\0\x01\0";
+
+    #[test]
+    fn test_compile() {
+        let module = compile(EMPTY_MODULE).unwrap();
+        assert!(!module.is_empty());
+        let component = compile(EMPTY_COMPONENT).unwrap();
+        assert!(!component.is_empty());
+
+        // Artifacts that are already precompiled are not WebAssembly
+        assert!(compile(&module).is_err());
+        assert!(compile(b"not wasm").is_err());
+    }
+
+    #[test]
+    fn test_compile_deterministic_relaxed_simd() {
+        let config = RuntimeConfig {
+            deterministic_relaxed_simd: true,
+            ..Default::default()
+        };
+        let deterministic = HostPrecompiler::new(&config).compile(EMPTY_MODULE).unwrap();
+
+        // The setting is recorded in the artifact, so that sandboxes with
+        // a different setting refuse to load it
+        assert_ne!(deterministic, compile(EMPTY_MODULE).unwrap());
+    }
+
+    #[test]
+    fn test_compile_wasm_features() {
+        let config = RuntimeConfig {
+            wasm_features: WasmFeatures {
+                simd: false,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let without_simd = HostPrecompiler::new(&config).compile(EMPTY_MODULE).unwrap();
+        // The features are recorded in the artifact too
+        assert_ne!(without_simd, compile(EMPTY_MODULE).unwrap());
+
+        let config = RuntimeConfig {
+            opt_level: OptLevel::None,
+            ..Default::default()
+        };
+        HostPrecompiler::new(&config).compile(EMPTY_MODULE).unwrap();
+    }
+
+    #[test]
+    fn test_host_precompiler() {
+        let precompiler = HostPrecompiler::new(&RuntimeConfig::default());
+        let artifact = precompiler.precompile(EMPTY_MODULE).unwrap().unwrap();
+        assert!(precompiler.precompile(&artifact).unwrap().is_none());
+
+        let dir = std::env::temp_dir().join(format!("hlwasm-aot-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let wasm_file = dir.join("empty.wasm");
+        std::fs::write(&wasm_file, EMPTY_MODULE).unwrap();
+        assert!(precompiler.precompile_file(&wasm_file).unwrap().is_some());
+        let aot_file = dir.join("empty.aot");
+        std::fs::write(&aot_file, &artifact).unwrap();
+        assert!(precompiler.precompile_file(&aot_file).unwrap().is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_aot_cache() {
+        let dir = std::env::temp_dir().join(format!("hlwasm-aot-cache-{}", std::process::id()));
+        let cache = AotCache::new(&dir);
+        let cached_files = || std::fs::read_dir(&dir).unwrap().count();
+
+        let artifact = cache.compile(EMPTY_MODULE).unwrap();
+        assert_eq!(artifact, compile(EMPTY_MODULE).unwrap());
+        assert_eq!(cached_files(), 1);
+        assert_eq!(cache.compile(EMPTY_MODULE).unwrap(), artifact);
+        assert_eq!(cached_files(), 1);
+
+        // Other inputs and settings are cached separately
+        cache.compile(EMPTY_COMPONENT).unwrap();
+        let config = RuntimeConfig {
+            epoch_interruption: true,
+            ..Default::default()
+        };
+        HostPrecompiler::new(&config)
+            .with_cache(Some(cache.clone()))
+            .compile(EMPTY_MODULE)
+            .unwrap();
+        assert_eq!(cached_files(), 3);
+
+        // Artifacts that are no longer compatible are compiled again
+        let path = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| std::fs::read(path).unwrap() == artifact)
+            .unwrap();
+        std::fs::write(&path, b"not an artifact").unwrap();
+        assert_eq!(cache.compile(EMPTY_MODULE).unwrap(), artifact);
+        assert_eq!(std::fs::read(&path).unwrap(), artifact);
+        assert_eq!(cached_files(), 3);
+
+        assert!(cache.compile(b"not wasm").is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
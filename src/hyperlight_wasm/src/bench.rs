@@ -0,0 +1,292 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! A harness that measures each stage of running a guest function in a
+//! new sandbox: creating the sandbox, loading the Wasm runtime, loading
+//! the module, the first call and subsequent calls.
+//!
+//! The stages are timed in the same way as the crate's criterion
+//! benchmarks, so embedders and CI can track regressions in their own
+//! modules with the same methodology. [`BenchReport::to_json`] produces
+//! output whose format only changes along with
+//! [`BenchReport::FORMAT_VERSION`].
+//!
+//! ```no_run
+//! use hyperlight_wasm::bench::Benchmark;
+//!
+//! let report = Benchmark::new("RunWasm.aot", "Echo")
+//!     .with_iterations(1000)
+//!     .run::<String>(("Hello World!".to_string(),))
+//!     .unwrap();
+//! println!("{}", report.to_json());
+//! ```
+
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use hyperlight_host::func::{ParameterTuple, SupportedReturnType};
+
+use crate::{ProtoWasmSandbox, Result, SandboxBuilder, new_error};
+
+type Setup = Box<dyn Fn(&mut ProtoWasmSandbox) -> Result<()>>;
+
+/// A benchmark of a guest function, see the [module documentation](self)
+pub struct Benchmark {
+    builder: SandboxBuilder,
+    setup: Option<Setup>,
+    module_path: PathBuf,
+    function: String,
+    iterations: usize,
+}
+
+impl Benchmark {
+    /// The number of steady-state calls made by default
+    pub const DEFAULT_ITERATIONS: usize = 100;
+
+    /// Create a benchmark of the function `function` in the module at
+    /// `module_path`, run in sandboxes with the default configuration
+    pub fn new(module_path: impl Into<PathBuf>, function: impl Into<String>) -> Self {
+        Self {
+            builder: SandboxBuilder::new(),
+            setup: None,
+            module_path: module_path.into(),
+            function: function.into(),
+            iterations: Self::DEFAULT_ITERATIONS,
+        }
+    }
+
+    /// Create the sandbox with `builder` rather than the default
+    /// configuration
+    pub fn with_sandbox_builder(mut self, builder: SandboxBuilder) -> Self {
+        self.builder = builder;
+        self
+    }
+
+    /// Call `setup` on the sandbox before loading the runtime, for example
+    /// to register the host functions the module imports. The time taken
+    /// by `setup` is not included in any stage.
+    pub fn with_setup(
+        mut self,
+        setup: impl Fn(&mut ProtoWasmSandbox) -> Result<()> + 'static,
+    ) -> Self {
+        self.setup = Some(Box::new(setup));
+        self
+    }
+
+    /// Set the number of steady-state calls made after the first call.
+    /// This must be at least 1.
+    pub fn with_iterations(mut self, iterations: usize) -> Self {
+        self.iterations = iterations;
+        self
+    }
+
+    /// Run the benchmark, calling the function with `params` each time
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the number of iterations is 0, or if any stage
+    /// fails.
+    pub fn run<Output: SupportedReturnType>(
+        &self,
+        params: impl ParameterTuple,
+    ) -> Result<BenchReport> {
+        if self.iterations == 0 {
+            return Err(new_error!("a benchmark needs at least one iteration"));
+        }
+
+        let start = Instant::now();
+        let mut proto_wasm_sandbox = self.builder.clone().build()?;
+        let sandbox_creation = start.elapsed();
+
+        if let Some(setup) = &self.setup {
+            setup(&mut proto_wasm_sandbox)?;
+        }
+
+        let start = Instant::now();
+        let wasm_sandbox = proto_wasm_sandbox.load_runtime()?;
+        let runtime_load = start.elapsed();
+
+        let start = Instant::now();
+        let mut loaded_wasm_sandbox = wasm_sandbox.load_module(&self.module_path)?;
+        let module_load = start.elapsed();
+
+        let start = Instant::now();
+        loaded_wasm_sandbox.call_guest_function::<Output>(&self.function, params.clone())?;
+        let first_call = start.elapsed();
+
+        let mut samples = Vec::with_capacity(self.iterations);
+        for _ in 0..self.iterations {
+            let start = Instant::now();
+            loaded_wasm_sandbox.call_guest_function::<Output>(&self.function, params.clone())?;
+            samples.push(start.elapsed());
+        }
+
+        Ok(BenchReport {
+            function: self.function.clone(),
+            sandbox_creation,
+            runtime_load,
+            module_load,
+            first_call,
+            steady_state_call: CallLatency::from_samples(samples),
+        })
+    }
+}
+
+/// The time taken by each stage of a [`Benchmark`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BenchReport {
+    /// The name of the function that was called
+    pub function: String,
+    /// The time taken to build the `ProtoWasmSandbox`
+    pub sandbox_creation: Duration,
+    /// The time taken to load the Wasm runtime into the sandbox
+    pub runtime_load: Duration,
+    /// The time taken to load the module into the sandbox
+    pub module_load: Duration,
+    /// The time taken by the first call to the function
+    pub first_call: Duration,
+    /// The latency of the calls made after the first
+    pub steady_state_call: CallLatency,
+}
+
+impl BenchReport {
+    /// The version of the format produced by [`to_json`](Self::to_json)
+    pub const FORMAT_VERSION: u32 = 1;
+
+    /// Encode the report as a single line of JSON, with every duration in
+    /// nanoseconds:
+    ///
+    /// ```text
+    /// {"format_version":1,"function":"Echo","sandbox_creation_ns":..,
+    ///  "runtime_load_ns":..,"module_load_ns":..,"first_call_ns":..,
+    ///  "steady_state_call":{"iterations":..,"min_ns":..,"mean_ns":..,
+    ///  "median_ns":..,"p99_ns":..,"max_ns":..}}
+    /// ```
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        let latency = &self.steady_state_call;
+        // Writing to a String cannot fail
+        let _ = write!(
+            json,
+            "{{\"format_version\":{},\"function\":\"{}\",\"sandbox_creation_ns\":{},\"runtime_load_ns\":{},\"module_load_ns\":{},\"first_call_ns\":{},\"steady_state_call\":{{\"iterations\":{},\"min_ns\":{},\"mean_ns\":{},\"median_ns\":{},\"p99_ns\":{},\"max_ns\":{}}}}}",
+            Self::FORMAT_VERSION,
+            escape_json(&self.function),
+            self.sandbox_creation.as_nanos(),
+            self.runtime_load.as_nanos(),
+            self.module_load.as_nanos(),
+            self.first_call.as_nanos(),
+            latency.iterations,
+            latency.min.as_nanos(),
+            latency.mean.as_nanos(),
+            latency.median.as_nanos(),
+            latency.p99.as_nanos(),
+            latency.max.as_nanos(),
+        );
+        json
+    }
+}
+
+/// Statistics about the latency of repeated calls to a guest function
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CallLatency {
+    /// The number of calls made
+    pub iterations: usize,
+    /// The fastest call
+    pub min: Duration,
+    /// The mean latency
+    pub mean: Duration,
+    /// The median latency
+    pub median: Duration,
+    /// The latency that 99% of calls were at least as fast as
+    pub p99: Duration,
+    /// The slowest call
+    pub max: Duration,
+}
+
+impl CallLatency {
+    fn from_samples(mut samples: Vec<Duration>) -> Self {
+        samples.sort_unstable();
+        let iterations = samples.len();
+        let total: Duration = samples.iter().sum();
+        let percentile = |p: usize| samples[(iterations * p).div_ceil(100).max(1) - 1];
+        Self {
+            iterations,
+            min: samples[0],
+            mean: total / iterations as u32,
+            median: percentile(50),
+            p99: percentile(99),
+            max: samples[iterations - 1],
+        }
+    }
+}
+
+fn escape_json(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{BenchReport, CallLatency};
+
+    #[test]
+    fn test_call_latency() {
+        let samples = (1..=200).rev().map(Duration::from_micros).collect();
+        let latency = CallLatency::from_samples(samples);
+        assert_eq!(latency.iterations, 200);
+        assert_eq!(latency.min, Duration::from_micros(1));
+        assert_eq!(latency.max, Duration::from_micros(200));
+        assert_eq!(latency.mean, Duration::from_nanos(100_500));
+        assert_eq!(latency.median, Duration::from_micros(100));
+        assert_eq!(latency.p99, Duration::from_micros(198));
+
+        let latency = CallLatency::from_samples(vec![Duration::from_micros(7)]);
+        assert_eq!(latency.median, Duration::from_micros(7));
+        assert_eq!(latency.p99, Duration::from_micros(7));
+    }
+
+    #[test]
+    fn test_bench_report_json() {
+        let report = BenchReport {
+            function: "Say \"hi\"".to_string(),
+            sandbox_creation: Duration::from_nanos(1),
+            runtime_load: Duration::from_nanos(2),
+            module_load: Duration::from_nanos(3),
+            first_call: Duration::from_nanos(4),
+            steady_state_call: CallLatency::from_samples(vec![
+                Duration::from_nanos(5),
+                Duration::from_nanos(7),
+            ]),
+        };
+        assert_eq!(
+            report.to_json(),
+            "{\"format_version\":1,\"function\":\"Say \\\"hi\\\"\",\"sandbox_creation_ns\":1,\"runtime_load_ns\":2,\"module_load_ns\":3,\"first_call_ns\":4,\"steady_state_call\":{\"iterations\":2,\"min_ns\":5,\"mean_ns\":6,\"median_ns\":5,\"p99_ns\":7,\"max_ns\":7}}"
+        );
+    }
+}
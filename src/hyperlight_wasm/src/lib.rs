@@ -17,16 +17,87 @@ limitations under the License.
 #![deny(dead_code, missing_docs, unused_mut)]
 //! This crate provides a Hyperlight implementation for WebAssembly (Wasm) guest code.
 
+/// Precompiling modules and components for the embedded Wasm runtime
+#[cfg(feature = "aot")]
+pub mod aot;
+/// A harness for benchmarking guest functions
+#[cfg(feature = "bench")]
+pub mod bench;
 /// provides details about the build
 pub mod build_info;
 mod sandbox;
+/// Checking modules against a policy before they are loaded
+pub mod validate;
 
 use build_info::BuildInfo;
+#[cfg(feature = "async")]
+pub use sandbox::async_host::AsyncHost;
+pub use sandbox::call_budget::CallBudgetExceeded;
+pub use sandbox::call_hooks::CallRecord;
+pub use sandbox::call_outcome::CallOutcome;
+pub use sandbox::call_report::CallReport;
+pub use sandbox::call_timeout::GuestCallTimeout;
+pub use sandbox::callbacks::Callback;
+pub use sandbox::cancellation::{CancelOnDrop, CancellationToken, GuestCallCancelled};
+pub use sandbox::epoch_deadline::EpochDeadlineExceeded;
+pub use sandbox::executor::{ExecutorStats, PendingCall, SandboxExecutor};
+pub use sandbox::exit_status::ExitStatus;
+pub use sandbox::guest_abi::GuestAbi;
+pub use sandbox::guest_aborted::GuestAborted;
+pub use sandbox::guest_callbacks::GuestCallbacks;
+pub use sandbox::guest_resource::GuestResource;
+pub use sandbox::guest_trap::{GuestTrap, TrapCode, TrapFrame};
+pub use sandbox::host_error::{HostError, OrRaise};
+pub use sandbox::host_function_cache::HostFunctionCache;
+pub use sandbox::host_function_failed::HostFunctionFailed;
+pub use sandbox::host_function_manifest::{HostFunctionManifest, ManifestFunction};
 pub use sandbox::loaded_wasm_sandbox::LoadedWasmSandbox;
+pub use sandbox::mapped_buffers::GuestAddr;
+pub use sandbox::memory_stats::MemoryStats;
+#[cfg(target_os = "linux")]
+pub use sandbox::module_handle::ModuleHandle;
+pub use sandbox::module_resolver::{DirectoryResolver, ModuleResolver, ModuleSource};
+pub use sandbox::module_usage::{ModuleUsage, all_module_usage, module_usage};
+pub use sandbox::multi_value::MultiValue;
+pub use sandbox::panic_policy::{CatchPanics, OnHostCall, OnHostError, OnPanic, PanicPolicy};
+pub use sandbox::preopened_dirs::DirAccess;
 pub use sandbox::proto_wasm_sandbox::ProtoWasmSandbox;
+pub use sandbox::provenance::Provenance;
+pub use sandbox::required_exports::RequiredExport;
+pub use sandbox::runtime_kind::RuntimeKind;
 pub use sandbox::sandbox_builder::SandboxBuilder;
+pub use sandbox::sandbox_pool::SandboxPool;
+pub use sandbox::stack_exhausted::StackExhausted;
+pub use sandbox::state_cells::{StateCellValue, StateCells};
+pub use sandbox::streaming::StreamedCall;
+pub use sandbox::virtual_clock::VirtualClock;
+pub use sandbox::wasm_limits::WasmLimitExceeded;
 pub use sandbox::wasm_sandbox::WasmSandbox;
 
+/// Where random numbers requested by guests come from, see
+/// [`SandboxBuilder::with_entropy_policy`]
+pub use hyperlight_wasm_runtime::runtime_config::EntropyPolicy;
+
+/// The WebAssembly proposals guests may use, see
+/// [`SandboxBuilder::with_wasm_features`]
+pub use hyperlight_wasm_runtime::runtime_config::WasmFeatures;
+
+/// How much guests precompiled on the host are optimized, see
+/// [`SandboxBuilder::with_opt_level`]
+pub use hyperlight_wasm_runtime::runtime_config::OptLevel;
+
+/// A function exported by a loaded guest, see
+/// [`LoadedWasmSandbox::guest_functions`]
+pub use hyperlight_wasm_runtime::guest_functions::GuestFunction;
+
+/// A value passed to or returned from a function of a component loaded
+/// without bindings, see [`LoadedWasmSandbox::call_component_function`]
+pub use hyperlight_wasm_runtime::component_value::ComponentValue;
+
+/// A value returned by a module function that returns more than one
+/// value, see [`MultiValue`]
+pub use hyperlight_wasm_runtime::multi_value::WasmValue;
+
 // Re-export types from hyperlight-host so consumers don't need to depend on it directly
 
 /// The container to store the value of a single parameter to a guest
@@ -34,6 +105,8 @@ pub use sandbox::wasm_sandbox::WasmSandbox;
 pub type ParameterValue = hyperlight_host::func::ParameterValue;
 /// The container to store the return value from a guest function call.
 pub type ReturnValue = hyperlight_host::func::ReturnValue;
+/// The type of a parameter of a host or guest function.
+pub type ParameterType = hyperlight_host::func::ParameterType;
 /// The type of the return value from a guest function call.
 pub type ReturnType = hyperlight_host::func::ReturnType;
 /// The Result of a function call
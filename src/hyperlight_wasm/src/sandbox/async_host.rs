@@ -0,0 +1,109 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::future::Future;
+
+use hyperlight_host::{Result, new_error};
+use tokio::runtime::Handle;
+
+/// Runs host functions implemented by async Rust functions on a tokio
+/// runtime, so that component imports, whose traits generated by
+/// `host_bindgen!` are synchronous, can be implemented by async code
+/// such as outbound HTTP calls.
+///
+/// A host function is called on the thread running the guest call, and
+/// [`block_on`](Self::block_on) suspends the guest call there until the
+/// future resolves, while the future itself runs on the runtime. Make
+/// guest calls with
+/// [`LoadedWasmSandbox::call_guest_function_async`](crate::LoadedWasmSandbox::call_guest_function_async),
+/// which runs them on tokio's blocking thread pool, or from a thread
+/// outside the runtime: waiting on a runtime's worker thread panics.
+///
+/// ```ignore
+/// struct Host {
+///     async_host: AsyncHost,
+/// }
+///
+/// impl bindings::my::world::Http for Host {
+///     fn get(&mut self, url: String) -> String {
+///         self.async_host.block_on(fetch(url))
+///     }
+/// }
+/// ```
+///
+/// Requires the `async` feature.
+#[derive(Clone, Debug)]
+pub struct AsyncHost {
+    handle: Handle,
+}
+
+impl AsyncHost {
+    /// Create an `AsyncHost` that runs futures on the runtime of `handle`
+    pub fn new(handle: Handle) -> Self {
+        Self { handle }
+    }
+
+    /// Create an `AsyncHost` that runs futures on the tokio runtime the
+    /// caller is running in
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if it is not called from within a tokio runtime.
+    pub fn current() -> Result<Self> {
+        Handle::try_current()
+            .map(Self::new)
+            .map_err(|e| new_error!("AsyncHost must be created in a tokio runtime: {}", e))
+    }
+
+    /// Run `future` to completion on the runtime, suspending the guest
+    /// call that called the host function until it resolves, and return
+    /// its output to the guest.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called on one of the runtime's worker threads, that is
+    /// from a guest call made with
+    /// [`LoadedWasmSandbox::call_guest_function`](crate::LoadedWasmSandbox::call_guest_function)
+    /// directly in an async task.
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.handle.block_on(future)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AsyncHost;
+
+    #[test]
+    fn test_async_host_block_on() {
+        assert!(AsyncHost::current().is_err());
+
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let async_host = runtime.block_on(async { AsyncHost::current() }).unwrap();
+
+        // A host function called by a guest call running on the blocking
+        // thread pool waits for a future that runs on the runtime
+        let result = runtime
+            .block_on(runtime.spawn_blocking(move || {
+                async_host.block_on(async { tokio::spawn(async { 40 + 2 }).await.unwrap() })
+            }))
+            .unwrap();
+        assert_eq!(result, 42);
+    }
+}
@@ -0,0 +1,285 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use hyperlight_host::{HyperlightError, Result, new_error};
+
+use super::module_usage::CpuTimer;
+use super::panic_policy::OnHostCall;
+
+/// The error returned by a guest call that exceeded one of its time
+/// budgets, see
+/// [`SandboxBuilder::with_guest_time_budget`](crate::SandboxBuilder::with_guest_time_budget)
+/// and
+/// [`SandboxBuilder::with_host_time_budget`](crate::SandboxBuilder::with_host_time_budget).
+///
+/// It is returned as a [`HyperlightError::AnyhowError`], from which
+/// [`from_error`](Self::from_error) extracts it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CallBudgetExceeded {
+    /// The guest ran for longer than its budget, not counting the time
+    /// spent in host functions. The call was interrupted, which poisons
+    /// the sandbox.
+    GuestTime {
+        /// The name of the guest function that was called
+        function_name: String,
+        /// How long the guest was allowed to run for
+        budget: Duration,
+    },
+    /// The host functions called by the guest ran for longer than their
+    /// budget in total. The host function that exceeded it returned an
+    /// error to the guest in place of its result.
+    HostTime {
+        /// The name of the guest function that was called
+        function_name: String,
+        /// How long host functions were allowed to run for
+        budget: Duration,
+    },
+}
+
+impl CallBudgetExceeded {
+    /// The budget exceeded by the call that failed with `error`, or
+    /// `None` if the call failed for another reason
+    pub fn from_error(error: &HyperlightError) -> Option<&Self> {
+        match error {
+            HyperlightError::AnyhowError(e) => e.downcast_ref(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CallBudgetExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::GuestTime {
+                function_name,
+                budget,
+            } => write!(
+                f,
+                "guest function {} ran for longer than its budget of {:?}",
+                function_name, budget
+            ),
+            Self::HostTime {
+                function_name,
+                budget,
+            } => write!(
+                f,
+                "host functions called by guest function {} ran for longer than their budget of {:?}",
+                function_name, budget
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CallBudgetExceeded {}
+
+impl From<CallBudgetExceeded> for HyperlightError {
+    fn from(exceeded: CallBudgetExceeded) -> Self {
+        HyperlightError::AnyhowError(anyhow::Error::new(exceeded))
+    }
+}
+
+#[derive(Default)]
+struct HostClockState {
+    // The time spent in host functions that have returned since the
+    // clock was reset
+    spent: Duration,
+    // When the running host function was called, if one is running
+    entered: Option<Instant>,
+    // The CPU time the thread used in host functions that have returned
+    // since the clock was reset
+    cpu_spent: Duration,
+    // Measures the CPU time the running host function uses, if one is
+    // running
+    entered_cpu: Option<CpuTimer>,
+    // Whether a host function has returned after the budget was spent
+    exceeded: bool,
+    // The number of host functions called since the clock was reset
+    calls: u64,
+}
+
+/// Measures the time the host functions of a sandbox spend running
+/// during each guest call. Clones share the measurement.
+#[derive(Clone, Default)]
+pub(crate) struct HostClock {
+    budget: Option<Duration>,
+    state: Arc<Mutex<HostClockState>>,
+}
+
+impl HostClock {
+    pub(crate) fn new(budget: Option<Duration>) -> Self {
+        Self {
+            budget,
+            state: Arc::default(),
+        }
+    }
+
+    /// The budget host functions have for each guest call, if any
+    pub(crate) fn budget(&self) -> Option<Duration> {
+        self.budget
+    }
+
+    /// Start measuring a new guest call
+    pub(crate) fn reset(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            *state = HostClockState::default();
+        }
+    }
+
+    /// The time spent in host functions since the clock was reset,
+    /// including the time the running host function has spent so far
+    pub(crate) fn spent(&self) -> Duration {
+        self.state.lock().map_or(Duration::ZERO, |state| {
+            state.spent
+                + state
+                    .entered
+                    .map_or(Duration::ZERO, |entered| entered.elapsed())
+        })
+    }
+
+    /// The CPU time used by host functions that have returned since the
+    /// clock was reset
+    pub(crate) fn cpu_spent(&self) -> Duration {
+        self.state
+            .lock()
+            .map_or(Duration::ZERO, |state| state.cpu_spent)
+    }
+
+    /// The number of host functions called since the clock was reset
+    pub(crate) fn calls(&self) -> u64 {
+        self.state.lock().map_or(0, |state| state.calls)
+    }
+
+    /// Whether a host function exceeded the budget since the clock was
+    /// reset
+    pub(crate) fn exceeded(&self) -> bool {
+        self.state.lock().is_ok_and(|state| state.exceeded)
+    }
+
+    /// Call `f`, a host function, measuring the time it takes. If the
+    /// budget has been spent once it returns, its result is replaced
+    /// with an error, which traps the guest call.
+    pub(crate) fn time<T>(&self, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        self.enter();
+        let result = f();
+        self.exit()?;
+        result
+    }
+
+    /// A function timing the calls to a host function, for
+    /// [`CatchPanics::wrap_calls`](super::panic_policy::CatchPanics::wrap_calls)
+    pub(crate) fn on_call(&self) -> OnHostCall {
+        let clock = self.clone();
+        Arc::new(move |call| {
+            clock.time(|| {
+                call();
+                Ok(())
+            })
+        })
+    }
+
+    fn enter(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.entered = Some(Instant::now());
+            state.entered_cpu = Some(CpuTimer::start());
+            state.calls += 1;
+        }
+    }
+
+    fn exit(&self) -> Result<()> {
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|e| new_error!("Error locking host clock: {:?}", e))?;
+        if let Some(entered) = state.entered.take() {
+            state.spent += entered.elapsed();
+        }
+        if let Some(entered_cpu) = state.entered_cpu.take() {
+            state.cpu_spent += entered_cpu.elapsed();
+        }
+        match self.budget {
+            Some(budget) if state.spent > budget => {
+                state.exceeded = true;
+                Err(new_error!(
+                    "host functions ran for longer than their budget of {:?}",
+                    budget
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+
+    use hyperlight_host::HyperlightError;
+
+    use super::{CallBudgetExceeded, HostClock};
+
+    #[test]
+    fn test_host_clock() {
+        let clock = HostClock::new(Some(Duration::from_millis(50)));
+        assert_eq!(clock.time(|| Ok(1)).unwrap(), 1);
+        assert!(!clock.exceeded());
+
+        let result = clock.time(|| {
+            thread::sleep(Duration::from_millis(60));
+            Ok(2)
+        });
+        assert!(result.is_err());
+        assert!(clock.exceeded());
+        assert!(clock.spent() >= Duration::from_millis(60));
+        // Sleeping does not use the thread's CPU time
+        #[cfg(target_os = "linux")]
+        assert!(clock.cpu_spent() < Duration::from_millis(60));
+        assert_eq!(clock.calls(), 2);
+
+        clock.reset();
+        assert!(!clock.exceeded());
+        assert_eq!(clock.spent(), Duration::ZERO);
+        assert_eq!(clock.cpu_spent(), Duration::ZERO);
+        assert_eq!(clock.calls(), 0);
+
+        // Without a budget, host functions are only measured
+        let clock = HostClock::default();
+        let result = clock.time(|| {
+            thread::sleep(Duration::from_millis(10));
+            Ok(())
+        });
+        assert!(result.is_ok());
+        assert!(clock.spent() >= Duration::from_millis(10));
+    }
+
+    #[test]
+    fn test_call_budget_exceeded_from_error() {
+        let exceeded = CallBudgetExceeded::HostTime {
+            function_name: "CallHostFunction".to_string(),
+            budget: Duration::from_millis(10),
+        };
+        let error = HyperlightError::from(exceeded.clone());
+        assert_eq!(CallBudgetExceeded::from_error(&error), Some(&exceeded));
+        assert_eq!(
+            CallBudgetExceeded::from_error(&HyperlightError::ExecutionCanceledByHost()),
+            None
+        );
+    }
+}
@@ -0,0 +1,182 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hyperlight_host::{HyperlightError, Result};
+
+use super::panic_policy::OnHostCall;
+
+type CallStartHook = Arc<dyn Fn(&str) -> Result<()> + Send + Sync>;
+type CallEndHook = Arc<dyn Fn(&CallRecord<'_>) + Send + Sync>;
+type HostCallHook = Arc<dyn Fn(&str) -> Result<()> + Send + Sync>;
+
+/// A guest call that has returned, passed to the hooks set with
+/// [`LoadedWasmSandbox::on_call_end`](crate::LoadedWasmSandbox::on_call_end)
+#[derive(Debug)]
+pub struct CallRecord<'a> {
+    /// The name of the guest function that was called
+    pub function_name: &'a str,
+    /// The wall-clock time the call took
+    pub duration: Duration,
+    /// The error the call returned, or `None` if it succeeded
+    pub error: Option<&'a HyperlightError>,
+}
+
+impl CallRecord<'_> {
+    /// Whether the call succeeded
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+#[derive(Default)]
+struct Hooks {
+    call_start: Vec<CallStartHook>,
+    call_end: Vec<CallEndHook>,
+    host_call: Vec<HostCallHook>,
+}
+
+/// The hooks run around the guest calls of a sandbox and the host
+/// functions they call. Clones share the hooks, so that host functions
+/// registered before the hooks are set run them.
+#[derive(Clone, Default)]
+pub(crate) struct CallHooks {
+    hooks: Arc<Mutex<Hooks>>,
+}
+
+impl CallHooks {
+    pub(crate) fn add_call_start(&self, hook: CallStartHook) {
+        if let Ok(mut hooks) = self.hooks.lock() {
+            hooks.call_start.push(hook);
+        }
+    }
+
+    pub(crate) fn add_call_end(&self, hook: CallEndHook) {
+        if let Ok(mut hooks) = self.hooks.lock() {
+            hooks.call_end.push(hook);
+        }
+    }
+
+    pub(crate) fn add_host_call(&self, hook: HostCallHook) {
+        if let Ok(mut hooks) = self.hooks.lock() {
+            hooks.host_call.push(hook);
+        }
+    }
+
+    /// Remove all the hooks
+    pub(crate) fn clear(&self) {
+        if let Ok(mut hooks) = self.hooks.lock() {
+            *hooks = Hooks::default();
+        }
+    }
+
+    /// Run the hooks for the start of a call to `function_name`, in the
+    /// order they were added, stopping at the first that fails
+    pub(crate) fn call_start(&self, function_name: &str) -> Result<()> {
+        // Copied out so that the hooks can add hooks
+        let hooks = self
+            .hooks
+            .lock()
+            .map(|hooks| hooks.call_start.clone())
+            .unwrap_or_default();
+        hooks.iter().try_for_each(|hook| hook(function_name))
+    }
+
+    /// Run the hooks for the end of a call
+    pub(crate) fn call_end(&self, record: &CallRecord<'_>) {
+        let hooks = self
+            .hooks
+            .lock()
+            .map(|hooks| hooks.call_end.clone())
+            .unwrap_or_default();
+        hooks.iter().for_each(|hook| hook(record));
+    }
+
+    /// A function running the host call hooks before each call to the
+    /// host function `name`, which is then made through `on_call`. If a
+    /// hook fails, the host function is not called and the guest receives
+    /// the hook's error.
+    pub(crate) fn on_host_call(&self, name: &str, on_call: OnHostCall) -> OnHostCall {
+        let call_hooks = self.clone();
+        let name = name.to_string();
+        Arc::new(move |call| {
+            call_hooks.host_call(&name)?;
+            on_call(call)
+        })
+    }
+
+    /// Run the hooks for a call to the host function `name`
+    pub(crate) fn host_call(&self, name: &str) -> Result<()> {
+        let hooks = self
+            .hooks
+            .lock()
+            .map(|hooks| hooks.host_call.clone())
+            .unwrap_or_default();
+        hooks.iter().try_for_each(|hook| hook(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use hyperlight_host::new_error;
+
+    use super::*;
+
+    #[test]
+    fn test_call_hooks() {
+        let hooks = CallHooks::default();
+        let starts = Arc::new(AtomicUsize::new(0));
+        let counted = starts.clone();
+        hooks.add_call_start(Arc::new(move |_| {
+            counted.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }));
+        hooks.add_call_start(Arc::new(|name| match name {
+            "denied" => Err(new_error!("{} is denied", name)),
+            _ => Ok(()),
+        }));
+        assert!(hooks.call_start("allowed").is_ok());
+        assert!(hooks.call_start("denied").is_err());
+        assert_eq!(starts.load(Ordering::SeqCst), 2);
+
+        let host_calls = Arc::new(AtomicUsize::new(0));
+        let counted = host_calls.clone();
+        hooks.add_host_call(Arc::new(move |_| {
+            counted.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }));
+        let mut called = false;
+        hooks.on_host_call(
+            "HostFunc",
+            Arc::new(|call| {
+                call();
+                Ok(())
+            }),
+        )(&mut || called = true)
+        .unwrap();
+        assert!(called);
+        assert_eq!(host_calls.load(Ordering::SeqCst), 1);
+
+        hooks.clear();
+        assert!(hooks.call_start("denied").is_ok());
+        assert!(hooks.host_call("HostFunc").is_ok());
+        assert_eq!(host_calls.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,41 @@
+/*
+Copyright 2025 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::time::Duration;
+
+/// The result of a guest function call made with
+/// [`LoadedWasmSandbox::call_guest_function_detailed`](crate::LoadedWasmSandbox::call_guest_function_detailed),
+/// together with telemetry about the call.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CallOutcome<T> {
+    /// The value returned by the guest function
+    pub value: T,
+    /// The wall-clock time the call took, as in
+    /// [`CallReport::duration`](crate::CallReport::duration)
+    pub duration: Duration,
+    /// The time spent in host functions during the call, which is
+    /// included in `duration`. Only host functions registered with
+    /// [`ProtoWasmSandbox::register`](crate::ProtoWasmSandbox::register)
+    /// and the methods built on it are measured.
+    pub host_time: Duration,
+    /// The number of measured host functions the guest called during the
+    /// call
+    pub host_calls: u64,
+    /// The change in the size of the module's linear memory, in bytes.
+    /// This is `None` for components, and for modules that do not export
+    /// their memory as `memory`.
+    pub guest_mem_delta: Option<i64>,
+}
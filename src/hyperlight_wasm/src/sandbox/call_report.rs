@@ -0,0 +1,44 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::time::Duration;
+
+/// How the time of the most recent guest call of a sandbox was spent,
+/// see [`LoadedWasmSandbox::last_call_stats`](crate::LoadedWasmSandbox::last_call_stats).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CallReport {
+    /// The name of the guest function that was called
+    pub function_name: String,
+    /// The wall-clock time the call took
+    pub duration: Duration,
+    /// The CPU time the guest used, which is the CPU time the calling
+    /// thread used during the call, including running the vCPU, less the
+    /// CPU time it used in host functions. Unlike `duration`, this does
+    /// not include the time the thread waited to be scheduled, so it
+    /// suits enforcing quotas fairly. It is the wall-clock time spent
+    /// outside host functions on platforms where the CPU time of a thread
+    /// is not available.
+    pub guest_cpu_time: Duration,
+    /// The wall-clock time spent in host functions during the call. Only
+    /// host functions registered with
+    /// [`ProtoWasmSandbox::register`](crate::ProtoWasmSandbox::register)
+    /// and the methods built on it are measured.
+    pub host_time: Duration,
+    /// The number of measured host functions the guest called
+    pub host_calls: u64,
+    /// Whether the call succeeded
+    pub succeeded: bool,
+}
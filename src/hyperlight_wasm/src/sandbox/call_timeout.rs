@@ -0,0 +1,163 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::fmt;
+use std::sync::Arc;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use hyperlight_host::HyperlightError;
+use hyperlight_host::hypervisor::InterruptHandle;
+
+use super::call_budget::HostClock;
+
+/// The error returned by a guest call that was interrupted because it
+/// ran for longer than its timeout, see
+/// [`SandboxBuilder::with_guest_call_timeout`](crate::SandboxBuilder::with_guest_call_timeout)
+/// and
+/// [`LoadedWasmSandbox::call_guest_function_with_timeout`](crate::LoadedWasmSandbox::call_guest_function_with_timeout).
+///
+/// It is returned as a [`HyperlightError::AnyhowError`], from which
+/// [`from_error`](Self::from_error) extracts it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GuestCallTimeout {
+    /// The name of the guest function that was called
+    pub function_name: String,
+    /// How long the call was allowed to run for
+    pub timeout: Duration,
+}
+
+impl GuestCallTimeout {
+    /// The timeout that interrupted the call that failed with `error`,
+    /// or `None` if the call failed for another reason
+    pub fn from_error(error: &HyperlightError) -> Option<&Self> {
+        match error {
+            HyperlightError::AnyhowError(e) => e.downcast_ref(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for GuestCallTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "guest function {} did not return within {:?}",
+            self.function_name, self.timeout
+        )
+    }
+}
+
+impl std::error::Error for GuestCallTimeout {}
+
+impl From<GuestCallTimeout> for HyperlightError {
+    fn from(timeout: GuestCallTimeout) -> Self {
+        HyperlightError::AnyhowError(anyhow::Error::new(timeout))
+    }
+}
+
+/// Why a [`Watchdog`] interrupted a guest call
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Expiry {
+    /// The call ran for longer than its timeout
+    Timeout(Duration),
+    /// The guest ran for longer than its budget, not counting the time
+    /// spent in host functions
+    GuestTime(Duration),
+}
+
+/// Interrupts a guest call that is still running when its timeout
+/// expires, or once the guest has spent its time budget
+pub(crate) struct Watchdog {
+    cancel: mpsc::Sender<()>,
+    thread: JoinHandle<Option<Expiry>>,
+}
+
+impl Watchdog {
+    /// Start a watchdog for a call limited by `timeout` and by
+    /// `guest_budget`, whose host functions are measured by `host_clock`,
+    /// or return `None` if the call is not limited
+    pub(crate) fn start(
+        handle: Arc<dyn InterruptHandle>,
+        timeout: Option<Duration>,
+        guest_budget: Option<Duration>,
+        host_clock: &HostClock,
+    ) -> Option<Self> {
+        if timeout.is_none() && guest_budget.is_none() {
+            return None;
+        }
+        let host_clock = host_clock.clone();
+        let (cancel, cancelled) = mpsc::channel();
+        let start = Instant::now();
+        let thread = thread::spawn(move || {
+            loop {
+                // Time spent in host functions does not count against the
+                // guest's budget, so its deadline moves while they run
+                let timeout_at = timeout.map(|timeout| start + timeout);
+                let budget_at = guest_budget.map(|budget| start + budget + host_clock.spent());
+                let now = Instant::now();
+                let expiry = match (timeout_at, budget_at) {
+                    (Some(at), _) if now >= at => timeout.map(Expiry::Timeout),
+                    (_, Some(at)) if now >= at => guest_budget.map(Expiry::GuestTime),
+                    _ => None,
+                };
+                if expiry.is_some() {
+                    handle.kill();
+                    return expiry;
+                }
+                let deadline = timeout_at.into_iter().chain(budget_at).min()?;
+                match cancelled.recv_timeout(deadline - now) {
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    // Stopped, or dropped because a panic unwound through the call
+                    _ => return None,
+                }
+            }
+        });
+        Some(Self { cancel, thread })
+    }
+
+    /// Stop the watchdog once the call has returned, returning why it
+    /// interrupted the call, if it did
+    pub(crate) fn stop(self) -> Option<Expiry> {
+        let _ = self.cancel.send(());
+        self.thread.join().ok().flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use hyperlight_host::{HyperlightError, new_error};
+
+    use super::GuestCallTimeout;
+
+    #[test]
+    fn test_guest_call_timeout_from_error() {
+        let timeout = GuestCallTimeout {
+            function_name: "KeepCPUBusy".to_string(),
+            timeout: Duration::from_millis(100),
+        };
+        let error = HyperlightError::from(timeout.clone());
+        assert_eq!(GuestCallTimeout::from_error(&error), Some(&timeout));
+        assert_eq!(
+            GuestCallTimeout::from_error(&HyperlightError::ExecutionCanceledByHost()),
+            None
+        );
+        assert_eq!(GuestCallTimeout::from_error(&new_error!("other")), None);
+    }
+}
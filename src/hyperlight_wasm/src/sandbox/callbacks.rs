@@ -0,0 +1,205 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use hyperlight_host::func::{
+    HostFunction, ParameterTuple, ParameterType, Registerable, ReturnType, SupportedReturnType,
+};
+use hyperlight_host::{MultiUseSandbox, Result, new_error};
+
+use super::panic_policy::CatchPanics;
+use super::sandbox_context::SandboxContext;
+
+type RegisterFn = Arc<dyn Fn(&SandboxContext, &mut MultiUseSandbox) -> Result<()> + Send + Sync>;
+type ReregisterFn = Arc<dyn Fn(&mut MultiUseSandbox) -> Result<()> + Send + Sync>;
+
+/// A host function that replaces a registered host function of the
+/// same name for a single guest call, see
+/// [`LoadedWasmSandbox::call_guest_function_with_callbacks`](crate::LoadedWasmSandbox::call_guest_function_with_callbacks).
+///
+/// Panics in the callback are handled as for
+/// [`ProtoWasmSandbox::register`](crate::ProtoWasmSandbox::register).
+#[derive(Clone)]
+pub struct Callback {
+    name: String,
+    parameter_types: &'static [ParameterType],
+    return_type: ReturnType,
+    register: RegisterFn,
+}
+
+impl Callback {
+    /// A callback replacing the host function `name` with `host_func`
+    pub fn new<Args: CatchPanics<Output>, Output: SupportedReturnType>(
+        name: impl AsRef<str>,
+        host_func: impl Into<HostFunction<Output, Args>>,
+    ) -> Self {
+        let name = name.as_ref().to_string();
+        let host_func = host_func.into();
+        let register_name = name.clone();
+        Self {
+            name,
+            parameter_types: Args::TYPE,
+            return_type: Output::TYPE,
+            register: Arc::new(move |context, inner| {
+                inner.register_host_function(
+                    &register_name,
+                    context.wrap(&register_name, host_func.clone()),
+                )
+            }),
+        }
+    }
+
+    /// The name of the host function the callback replaces
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl std::fmt::Debug for Callback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Callback")
+            .field("name", &self.name)
+            .field("parameter_types", &self.parameter_types)
+            .field("return_type", &self.return_type)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Clone)]
+struct RegisteredHostFunction {
+    parameter_types: &'static [ParameterType],
+    return_type: ReturnType,
+    register: ReregisterFn,
+}
+
+/// The host functions registered with a sandbox, kept so that those
+/// replaced by [`Callback`]s can be registered again after the call
+#[derive(Clone, Default)]
+pub(crate) struct RegisteredHostFunctions(HashMap<String, RegisteredHostFunction>);
+
+impl RegisteredHostFunctions {
+    /// Record that `host_func` was registered under `name`
+    pub(crate) fn insert<Args: ParameterTuple, Output: SupportedReturnType>(
+        &mut self,
+        name: &str,
+        host_func: HostFunction<Output, Args>,
+    ) {
+        let register_name = name.to_string();
+        self.0.insert(
+            name.to_string(),
+            RegisteredHostFunction {
+                parameter_types: Args::TYPE,
+                return_type: Output::TYPE,
+                register: Arc::new(move |inner| {
+                    inner.register_host_function(&register_name, host_func.clone())
+                }),
+            },
+        );
+    }
+
+    /// Check that each of `callbacks` replaces a registered host function
+    /// with the same signature
+    pub(crate) fn check(&self, callbacks: &[Callback]) -> Result<()> {
+        for callback in callbacks {
+            let registered = self.0.get(&callback.name).ok_or_else(|| {
+                new_error!(
+                    "callback {} does not replace a registered host function",
+                    callback.name
+                )
+            })?;
+            if registered.parameter_types != callback.parameter_types
+                || registered.return_type != callback.return_type
+            {
+                return Err(new_error!(
+                    "callback {} takes {:?} and returns {:?}, but the host function takes {:?} and returns {:?}",
+                    callback.name,
+                    callback.parameter_types,
+                    callback.return_type,
+                    registered.parameter_types,
+                    registered.return_type
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Register the host functions `callbacks` replaced again
+    pub(crate) fn restore(
+        &self,
+        inner: &mut MultiUseSandbox,
+        callbacks: &[Callback],
+    ) -> Result<()> {
+        for callback in callbacks {
+            if let Some(registered) = self.0.get(&callback.name) {
+                (registered.register)(inner)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Register `callbacks` on `inner` in place of the host functions they
+/// replace
+pub(crate) fn register(
+    context: &SandboxContext,
+    inner: &mut MultiUseSandbox,
+    callbacks: &[Callback],
+) -> Result<()> {
+    for callback in callbacks {
+        (callback.register)(context, inner)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use hyperlight_host::func::HostFunction;
+
+    use super::{Callback, RegisteredHostFunctions};
+
+    #[test]
+    fn test_check_callbacks() {
+        let mut registered = RegisteredHostFunctions::default();
+        registered.insert(
+            "Lookup",
+            HostFunction::from(|key: String| Ok(key.len() as i32)),
+        );
+
+        let callback = Callback::new("Lookup", |_key: String| Ok(0i32));
+        assert_eq!(callback.name(), "Lookup");
+        assert!(registered.check(&[callback]).is_ok());
+        assert!(registered.check(&[]).is_ok());
+
+        // Callbacks must replace a registered function with the same
+        // signature
+        let err = registered
+            .check(&[Callback::new("Missing", |_key: String| Ok(0i32))])
+            .unwrap_err();
+        assert!(err.to_string().contains("does not replace"));
+        assert!(
+            registered
+                .check(&[Callback::new("Lookup", |_key: String| Ok(0i64))])
+                .is_err()
+        );
+        assert!(
+            registered
+                .check(&[Callback::new("Lookup", |_key: i32| Ok(0i32))])
+                .is_err()
+        );
+    }
+}
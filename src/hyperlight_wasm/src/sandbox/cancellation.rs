@@ -0,0 +1,283 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use hyperlight_host::HyperlightError;
+use hyperlight_host::hypervisor::InterruptHandle;
+
+/// How often a cancelled call is killed again until it returns, since a
+/// kill that lands just before the vCPU starts running is discarded
+const KILL_RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Cancels the guest calls it is passed to, see
+/// [`LoadedWasmSandbox::call_guest_function_cancellable`](crate::LoadedWasmSandbox::call_guest_function_cancellable).
+///
+/// Clones share their state, so a clone kept by another thread or task
+/// can cancel a call running on this one. Once cancelled, a token stays
+/// cancelled: calls passed the token afterwards are not started. Use
+/// [`drop_guard`](Self::drop_guard) to cancel the token when a scope or
+/// future is dropped.
+///
+/// ```no_run
+/// # use hyperlight_wasm::{CancellationToken, GuestCallCancelled, LoadedWasmSandbox};
+/// # fn example(mut sandbox: LoadedWasmSandbox) {
+/// let token = CancellationToken::new();
+/// let canceller = token.clone();
+/// std::thread::spawn(move || {
+///     std::thread::sleep(std::time::Duration::from_millis(100));
+///     canceller.cancel();
+/// });
+/// let err = sandbox
+///     .call_guest_function_cancellable::<i32>("KeepCPUBusy", 10000i32, &token)
+///     .unwrap_err();
+/// assert!(GuestCallCancelled::from_error(&err).is_some());
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    state: Arc<TokenState>,
+}
+
+#[derive(Debug, Default)]
+struct TokenState {
+    cancelled: AtomicBool,
+    // The watchers of the calls running with the token, by id
+    watchers: Mutex<Vec<(u64, mpsc::Sender<Signal>)>>,
+}
+
+// The id of the next watcher registered with a token
+static NEXT_WATCHER_ID: AtomicU64 = AtomicU64::new(0);
+
+impl CancellationToken {
+    /// A token that is not cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the calls running with this token, and the calls passed it
+    /// later. This returns without waiting for the calls to stop.
+    pub fn cancel(&self) {
+        if self.state.cancelled.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        for (_, watcher) in self.state.watchers.lock().unwrap().iter() {
+            let _ = watcher.send(Signal::Cancel);
+        }
+    }
+
+    /// Whether [`cancel`](Self::cancel) has been called on this token or
+    /// one of its clones
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::Acquire)
+    }
+
+    /// A guard that cancels this token when it is dropped, unless it is
+    /// disarmed first. Keeping the guard in a future cancels its call
+    /// when the future is dropped, as `tokio::time::timeout` and
+    /// `tokio::select!` do with the futures that lose.
+    pub fn drop_guard(self) -> CancelOnDrop {
+        CancelOnDrop { token: Some(self) }
+    }
+}
+
+/// Cancels its [`CancellationToken`] when dropped, see
+/// [`CancellationToken::drop_guard`]
+#[derive(Debug)]
+#[must_use = "the token is cancelled as soon as the guard is dropped"]
+pub struct CancelOnDrop {
+    token: Option<CancellationToken>,
+}
+
+impl CancelOnDrop {
+    /// Return the token without cancelling it
+    pub fn disarm(mut self) -> CancellationToken {
+        self.token.take().unwrap_or_default()
+    }
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if let Some(token) = self.token.take() {
+            token.cancel();
+        }
+    }
+}
+
+/// The error returned by a guest call that was cancelled through its
+/// [`CancellationToken`], see
+/// [`LoadedWasmSandbox::call_guest_function_cancellable`](crate::LoadedWasmSandbox::call_guest_function_cancellable).
+///
+/// It is returned as a [`HyperlightError::AnyhowError`], from which
+/// [`from_error`](Self::from_error) extracts it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GuestCallCancelled {
+    /// The name of the guest function that was called
+    pub function_name: String,
+    /// Whether the call had started when it was cancelled. If it had, it
+    /// was killed and the sandbox is poisoned until it is restored; if
+    /// not, the guest did not run and the sandbox is unchanged.
+    pub started: bool,
+}
+
+impl GuestCallCancelled {
+    /// The cancellation that stopped the call that failed with `error`,
+    /// or `None` if the call failed for another reason
+    pub fn from_error(error: &HyperlightError) -> Option<&Self> {
+        match error {
+            HyperlightError::AnyhowError(e) => e.downcast_ref(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for GuestCallCancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.started {
+            write!(f, "guest function {} was cancelled", self.function_name)
+        } else {
+            write!(
+                f,
+                "guest function {} was cancelled before it started",
+                self.function_name
+            )
+        }
+    }
+}
+
+impl std::error::Error for GuestCallCancelled {}
+
+impl From<GuestCallCancelled> for HyperlightError {
+    fn from(cancelled: GuestCallCancelled) -> Self {
+        HyperlightError::AnyhowError(anyhow::Error::new(cancelled))
+    }
+}
+
+/// What a [`CancelWatcher`] is told
+#[derive(Debug)]
+enum Signal {
+    /// The token was cancelled
+    Cancel,
+    /// The call returned
+    Stop,
+}
+
+/// Kills a guest call once its token is cancelled
+pub(crate) struct CancelWatcher {
+    token: CancellationToken,
+    id: u64,
+    stop: mpsc::Sender<Signal>,
+    thread: JoinHandle<bool>,
+}
+
+impl CancelWatcher {
+    /// Start watching `token` for a call on the sandbox that `handle`
+    /// interrupts
+    pub(crate) fn start(token: &CancellationToken, handle: Arc<dyn InterruptHandle>) -> Self {
+        let (stop, signals) = mpsc::channel();
+        let id = NEXT_WATCHER_ID.fetch_add(1, Ordering::Relaxed);
+        token
+            .state
+            .watchers
+            .lock()
+            .unwrap()
+            .push((id, stop.clone()));
+        // The token may have been cancelled before the watcher was
+        // registered, in which case it was not told
+        if token.is_cancelled() {
+            let _ = stop.send(Signal::Cancel);
+        }
+        let thread = thread::spawn(move || {
+            match signals.recv() {
+                Ok(Signal::Cancel) => {}
+                Ok(Signal::Stop) | Err(_) => return false,
+            }
+            loop {
+                handle.kill();
+                match signals.recv_timeout(KILL_RETRY_INTERVAL) {
+                    Ok(Signal::Cancel) | Err(RecvTimeoutError::Timeout) => continue,
+                    Ok(Signal::Stop) | Err(RecvTimeoutError::Disconnected) => return true,
+                }
+            }
+        });
+        Self {
+            token: token.clone(),
+            id,
+            stop,
+            thread,
+        }
+    }
+
+    /// Stop watching once the call has returned, returning whether the
+    /// call was killed because the token was cancelled
+    pub(crate) fn stop(self) -> bool {
+        self.token
+            .state
+            .watchers
+            .lock()
+            .unwrap()
+            .retain(|(id, _)| *id != self.id);
+        let _ = self.stop.send(Signal::Stop);
+        self.thread.join().unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyperlight_host::{HyperlightError, new_error};
+
+    use super::{CancellationToken, GuestCallCancelled};
+
+    #[test]
+    fn test_cancellation_token() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(!token.is_cancelled());
+        clone.cancel();
+        assert!(token.is_cancelled());
+        // Cancelling again does nothing
+        token.cancel();
+        assert!(clone.is_cancelled());
+
+        let token = CancellationToken::new();
+        drop(token.clone().drop_guard());
+        assert!(token.is_cancelled());
+
+        let token = CancellationToken::new();
+        let token = token.drop_guard().disarm();
+        assert!(!token.is_cancelled());
+    }
+
+    #[test]
+    fn test_guest_call_cancelled_from_error() {
+        let cancelled = GuestCallCancelled {
+            function_name: "KeepCPUBusy".to_string(),
+            started: true,
+        };
+        let error = HyperlightError::from(cancelled.clone());
+        assert_eq!(GuestCallCancelled::from_error(&error), Some(&cancelled));
+        assert_eq!(
+            GuestCallCancelled::from_error(&HyperlightError::ExecutionCanceledByHost()),
+            None
+        );
+        assert_eq!(GuestCallCancelled::from_error(&new_error!("other")), None);
+    }
+}
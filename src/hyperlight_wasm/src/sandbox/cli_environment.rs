@@ -0,0 +1,102 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use hyperlight_host::{Result, new_error};
+
+/// The arguments and environment variables set with
+/// [`SandboxBuilder::with_args`](crate::SandboxBuilder::with_args) and
+/// [`SandboxBuilder::with_env`](crate::SandboxBuilder::with_env), which
+/// the guest reads when the runtime is loaded
+#[derive(Clone, Debug, Default)]
+pub(crate) struct CliEnvironment {
+    args: Vec<String>,
+    vars: Vec<(String, String)>,
+}
+
+impl CliEnvironment {
+    /// Replace the arguments with `args`
+    pub(crate) fn set_args(&mut self, args: Vec<String>) {
+        self.args = args;
+    }
+
+    /// Set the variable `key` to `value`, replacing any value it had
+    pub(crate) fn set_var(&mut self, key: &str, value: &str) {
+        match self.vars.iter_mut().find(|(k, _)| k == key) {
+            Some((_, v)) => *v = value.to_string(),
+            None => self.vars.push((key.to_string(), value.to_string())),
+        }
+    }
+
+    /// The arguments, each followed by a NUL byte
+    pub(crate) fn encode_args(&self) -> Result<Vec<u8>> {
+        encode(self.args.iter().map(|arg| {
+            if arg.contains('\0') {
+                return Err(new_error!("argument {:?} contains a NUL byte", arg));
+            }
+            Ok(arg.clone())
+        }))
+    }
+
+    /// The variables as `KEY=VALUE`, each followed by a NUL byte
+    pub(crate) fn encode_vars(&self) -> Result<Vec<u8>> {
+        encode(self.vars.iter().map(|(key, value)| {
+            if key.is_empty() || key.contains(['=', '\0']) || value.contains('\0') {
+                return Err(new_error!("invalid environment variable {:?}", key));
+            }
+            Ok(format!("{}={}", key, value))
+        }))
+    }
+}
+
+fn encode(strings: impl Iterator<Item = Result<String>>) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    for string in strings {
+        bytes.extend_from_slice(string?.as_bytes());
+        bytes.push(0);
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CliEnvironment;
+
+    #[test]
+    fn test_cli_environment() {
+        let mut environment = CliEnvironment::default();
+        assert_eq!(environment.encode_args().unwrap(), b"");
+        assert_eq!(environment.encode_vars().unwrap(), b"");
+
+        environment.set_args(vec!["app.wasm".to_string(), "--verbose".to_string()]);
+        environment.set_var("HOME", "/");
+        environment.set_var("EMPTY", "");
+        environment.set_var("HOME", "/home/guest");
+        assert_eq!(environment.encode_args().unwrap(), b"app.wasm\0--verbose\0");
+        assert_eq!(
+            environment.encode_vars().unwrap(),
+            b"HOME=/home/guest\0EMPTY=\0"
+        );
+
+        for (key, value) in [("", "x"), ("A=B", "x"), ("A", "x\0y")] {
+            let mut environment = CliEnvironment::default();
+            environment.set_var(key, value);
+            assert!(environment.encode_vars().is_err());
+        }
+        let mut environment = CliEnvironment::default();
+        environment.set_args(vec!["a\0b".to_string()]);
+        assert!(environment.encode_args().is_err());
+    }
+}
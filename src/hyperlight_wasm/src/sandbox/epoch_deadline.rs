@@ -0,0 +1,123 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::fmt;
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use hyperlight_host::HyperlightError;
+
+/// The error returned by a guest call that was stopped at its epoch
+/// deadline, see
+/// [`LoadedWasmSandbox::set_epoch_deadline`](crate::LoadedWasmSandbox::set_epoch_deadline).
+///
+/// Unlike a call that was interrupted, the call trapped inside the
+/// guest, so the sandbox is not poisoned.
+///
+/// It is returned as a [`HyperlightError::AnyhowError`], from which
+/// [`from_error`](Self::from_error) extracts it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EpochDeadlineExceeded {
+    /// The name of the guest function that was called
+    pub function_name: String,
+    /// How long the call was allowed to run for
+    pub deadline: Duration,
+}
+
+impl EpochDeadlineExceeded {
+    /// The deadline that stopped the call that failed with `error`, or
+    /// `None` if the call failed for another reason
+    pub fn from_error(error: &HyperlightError) -> Option<&Self> {
+        match error {
+            HyperlightError::AnyhowError(e) => e.downcast_ref(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for EpochDeadlineExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "guest function {} was stopped at its epoch deadline of {:?}",
+            self.function_name, self.deadline
+        )
+    }
+}
+
+impl std::error::Error for EpochDeadlineExceeded {}
+
+impl From<EpochDeadlineExceeded> for HyperlightError {
+    fn from(exceeded: EpochDeadlineExceeded) -> Self {
+        HyperlightError::AnyhowError(anyhow::Error::new(exceeded))
+    }
+}
+
+fn read_tsc() -> u64 {
+    // SAFETY: rdtsc has no side effects
+    unsafe { std::arch::x86_64::_rdtsc() }
+}
+
+/// Convert `duration` to ticks of the time stamp counter, which the
+/// guest reads to check its deadline. The counter's rate is measured
+/// once per process.
+pub(crate) fn tsc_ticks(duration: Duration) -> u64 {
+    static TICKS_PER_SECOND: OnceLock<u64> = OnceLock::new();
+    let ticks_per_second = *TICKS_PER_SECOND.get_or_init(|| {
+        let start = Instant::now();
+        let start_tsc = read_tsc();
+        thread::sleep(Duration::from_millis(10));
+        let ticks = read_tsc().wrapping_sub(start_tsc) as u128;
+        (ticks * 1_000_000_000 / start.elapsed().as_nanos().max(1)) as u64
+    });
+    let ticks = duration.as_nanos() * ticks_per_second as u128 / 1_000_000_000;
+    // 0 means no deadline to the guest
+    u64::try_from(ticks).unwrap_or(u64::MAX).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use hyperlight_host::HyperlightError;
+
+    use super::{EpochDeadlineExceeded, tsc_ticks};
+
+    #[test]
+    fn test_tsc_ticks() {
+        let millisecond = tsc_ticks(Duration::from_millis(1));
+        let second = tsc_ticks(Duration::from_secs(1));
+        assert!(millisecond > 1);
+        assert!(second > millisecond * 500);
+        assert_eq!(tsc_ticks(Duration::ZERO), 1);
+        assert_eq!(tsc_ticks(Duration::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn test_epoch_deadline_exceeded_from_error() {
+        let exceeded = EpochDeadlineExceeded {
+            function_name: "KeepCPUBusy".to_string(),
+            deadline: Duration::from_millis(10),
+        };
+        let error = HyperlightError::from(exceeded.clone());
+        assert_eq!(EpochDeadlineExceeded::from_error(&error), Some(&exceeded));
+        assert_eq!(
+            EpochDeadlineExceeded::from_error(&HyperlightError::ExecutionCanceledByHost()),
+            None
+        );
+    }
+}
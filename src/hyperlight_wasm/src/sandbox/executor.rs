@@ -0,0 +1,483 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, mpsc};
+use std::thread::{self, JoinHandle};
+
+use hyperlight_host::func::{ParameterTuple, SupportedReturnType};
+use hyperlight_host::sandbox::snapshot::Snapshot;
+use hyperlight_host::{Result, new_error};
+
+use super::loaded_wasm_sandbox::LoadedWasmSandbox;
+use super::metrics::{
+    METRIC_EXECUTOR_BUSY_WORKERS, METRIC_EXECUTOR_CALLS, METRIC_EXECUTOR_QUEUED_CALLS,
+    METRIC_EXECUTOR_RECYCLED_SANDBOXES, METRIC_EXECUTOR_STEALS,
+};
+
+type SandboxFactory = dyn Fn() -> Result<LoadedWasmSandbox> + Send + Sync;
+// Run with the sandbox taken for the call, or the error if there is none
+type Job = Box<dyn FnOnce(Result<&mut LoadedWasmSandbox>) + Send>;
+
+/// Runs guest calls on a set of sandboxes from a set of worker threads,
+/// so that many concurrent calls share a bounded number of sandboxes
+/// and threads.
+///
+/// The executor is built with a function that builds each sandbox with
+/// its module loaded, and starts `workers` threads sharing `sandboxes`
+/// sandboxes. Calls are queued with [`call`](Self::call) or
+/// [`execute`](Self::execute), spread across the workers' queues, and
+/// run by the first worker to take them, with whichever sandbox is idle.
+/// Idle workers steal calls from the queues of busy ones.
+///
+/// Each sandbox is snapshotted when it is built. A sandbox left poisoned
+/// by a call, for example because it timed out, is restored to that
+/// snapshot before it is used again, or replaced by a new sandbox if it
+/// cannot be restored. Other sandboxes keep their state between calls.
+/// If every sandbox has been lost, a new one is built for each call
+/// until one is built, and calls fail while none can be.
+///
+/// Dropping the executor waits for the queued calls to run. Queued
+/// calls, busy workers, calls run, steals and recycled sandboxes are
+/// recorded as metrics.
+pub struct SandboxExecutor {
+    shared: Arc<Shared>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+/// A call queued on a [`SandboxExecutor`], whose result is returned by
+/// [`wait`](Self::wait)
+#[derive(Debug)]
+pub struct PendingCall<T> {
+    result: mpsc::Receiver<Result<T>>,
+}
+
+impl<T> PendingCall<T> {
+    /// Block until the call has run, and return its result
+    ///
+    /// # Errors
+    ///
+    /// Returns the call's error, an error if no sandbox was available to
+    /// run it, or an error if the call panicked.
+    pub fn wait(self) -> Result<T> {
+        self.result
+            .recv()
+            .unwrap_or_else(|_| Err(new_error!("The queued call panicked")))
+    }
+}
+
+/// How busy a [`SandboxExecutor`] is, see [`SandboxExecutor::stats`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ExecutorStats {
+    /// The number of worker threads
+    pub workers: usize,
+    /// The number of workers running a call
+    pub busy_workers: usize,
+    /// The number of sandboxes, which is lower than the number the
+    /// executor was built with if poisoned sandboxes could not be
+    /// replaced
+    pub sandboxes: usize,
+    /// The number of sandboxes not running a call
+    pub idle_sandboxes: usize,
+    /// The number of calls waiting for a worker
+    pub queued_calls: usize,
+    /// The number of calls run
+    pub completed_calls: u64,
+    /// The number of calls a worker took from another worker's queue
+    pub stolen_calls: u64,
+    /// The number of poisoned sandboxes restored or replaced
+    pub recycled_sandboxes: u64,
+}
+
+impl ExecutorStats {
+    /// The fraction of workers running a call, from 0 to 1
+    pub fn utilization(&self) -> f64 {
+        if self.workers == 0 {
+            return 0.0;
+        }
+        self.busy_workers as f64 / self.workers as f64
+    }
+}
+
+// A sandbox and the snapshot taken when it was built
+struct Slot {
+    sandbox: LoadedWasmSandbox,
+    snapshot: Arc<Snapshot>,
+}
+
+impl Slot {
+    fn build(factory: &SandboxFactory) -> Result<Self> {
+        let mut sandbox = factory()?;
+        let snapshot = sandbox.snapshot()?;
+        Ok(Slot { sandbox, snapshot })
+    }
+}
+
+struct Shared {
+    // The queue of each worker
+    queues: Vec<Mutex<VecDeque<Job>>>,
+    // The queue the next call is pushed to
+    next_queue: AtomicUsize,
+    // The calls in all queues
+    queued: AtomicUsize,
+    // Held by workers checking for calls before they sleep, so that
+    // calls queued meanwhile wake them up
+    sleep: Mutex<()>,
+    work: Condvar,
+    shutdown: AtomicBool,
+    idle: Mutex<Vec<Slot>>,
+    sandbox_returned: Condvar,
+    sandboxes: AtomicUsize,
+    busy: AtomicUsize,
+    completed: AtomicU64,
+    stolen: AtomicU64,
+    recycled: AtomicU64,
+    factory: Box<SandboxFactory>,
+}
+
+impl SandboxExecutor {
+    /// Start `workers` threads running calls on `sandboxes` sandboxes,
+    /// each built by `factory` with the module calls are made to loaded.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `workers` or `sandboxes` is zero, the first
+    /// error returned by `factory`, or an error if a sandbox cannot be
+    /// snapshotted.
+    pub fn new(
+        workers: usize,
+        sandboxes: usize,
+        factory: impl Fn() -> Result<LoadedWasmSandbox> + Send + Sync + 'static,
+    ) -> Result<Self> {
+        if workers == 0 || sandboxes == 0 {
+            return Err(new_error!(
+                "An executor needs at least one worker and one sandbox"
+            ));
+        }
+        let factory: Box<SandboxFactory> = Box::new(factory);
+        let idle = (0..sandboxes)
+            .map(|_| Slot::build(&*factory))
+            .collect::<Result<Vec<_>>>()?;
+        let shared = Arc::new(Shared::new(workers, idle, factory));
+        let workers = (0..workers)
+            .map(|index| {
+                let shared = shared.clone();
+                thread::Builder::new()
+                    .name(format!("hyperlight-wasm-executor-{}", index))
+                    .spawn(move || shared.run_worker(index))
+                    .map_err(|e| new_error!("Failed to start an executor worker: {}", e))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(SandboxExecutor { shared, workers })
+    }
+
+    /// Queue a call to the guest function `fn_name` with `params`, as
+    /// [`LoadedWasmSandbox::call_guest_function`] makes it
+    pub fn call<Output>(
+        &self,
+        fn_name: impl Into<String>,
+        params: impl ParameterTuple + 'static,
+    ) -> PendingCall<Output>
+    where
+        Output: SupportedReturnType + Send + 'static,
+    {
+        let fn_name = fn_name.into();
+        self.execute(move |sandbox| sandbox.call_guest_function(&fn_name, params))
+    }
+
+    /// Queue `f` to run with an idle sandbox, for calls that need more
+    /// than [`call`](Self::call) offers, such as a timeout or several
+    /// guest calls in a row
+    pub fn execute<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut LoadedWasmSandbox) -> Result<T> + Send + 'static,
+    ) -> PendingCall<T> {
+        self.shared.execute(f)
+    }
+
+    /// How busy the executor is
+    pub fn stats(&self) -> ExecutorStats {
+        let shared = &self.shared;
+        ExecutorStats {
+            workers: self.workers.len(),
+            busy_workers: shared.busy.load(Ordering::Relaxed),
+            sandboxes: shared.sandboxes.load(Ordering::Relaxed),
+            idle_sandboxes: shared.lock_idle().len(),
+            queued_calls: shared.queued.load(Ordering::Relaxed),
+            completed_calls: shared.completed.load(Ordering::Relaxed),
+            stolen_calls: shared.stolen.load(Ordering::Relaxed),
+            recycled_sandboxes: shared.recycled.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Shared {
+    fn new(workers: usize, idle: Vec<Slot>, factory: Box<SandboxFactory>) -> Self {
+        Shared {
+            queues: (0..workers).map(|_| Mutex::default()).collect(),
+            next_queue: AtomicUsize::new(0),
+            queued: AtomicUsize::new(0),
+            sleep: Mutex::new(()),
+            work: Condvar::new(),
+            shutdown: AtomicBool::new(false),
+            sandboxes: AtomicUsize::new(idle.len()),
+            idle: Mutex::new(idle),
+            sandbox_returned: Condvar::new(),
+            busy: AtomicUsize::new(0),
+            completed: AtomicU64::new(0),
+            stolen: AtomicU64::new(0),
+            recycled: AtomicU64::new(0),
+            factory,
+        }
+    }
+
+    fn execute<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut LoadedWasmSandbox) -> Result<T> + Send + 'static,
+    ) -> PendingCall<T> {
+        let (sender, result) = mpsc::sync_channel(1);
+        self.push(Box::new(move |sandbox| {
+            // The caller may have stopped waiting
+            let _ = sender.send(sandbox.and_then(f));
+        }));
+        PendingCall { result }
+    }
+
+    fn push(&self, job: Job) {
+        // Counted before it is pushed, so that the worker that takes it
+        // never sees fewer calls queued than it has taken
+        self.queued.fetch_add(1, Ordering::SeqCst);
+        metrics::gauge!(METRIC_EXECUTOR_QUEUED_CALLS).increment(1);
+        let index = self.next_queue.fetch_add(1, Ordering::Relaxed) % self.queues.len();
+        lock(&self.queues[index]).push_back(job);
+        let _sleep = lock(&self.sleep);
+        self.work.notify_one();
+    }
+
+    // Take the oldest call from the worker's own queue, or else the
+    // newest from another worker's queue
+    fn pop(&self, index: usize) -> Option<Job> {
+        if let Some(job) = lock(&self.queues[index]).pop_front() {
+            return Some(job);
+        }
+        let others = (1..self.queues.len()).map(|i| (index + i) % self.queues.len());
+        for other in others {
+            if let Some(job) = lock(&self.queues[other]).pop_back() {
+                self.stolen.fetch_add(1, Ordering::Relaxed);
+                metrics::counter!(METRIC_EXECUTOR_STEALS).increment(1);
+                return Some(job);
+            }
+        }
+        None
+    }
+
+    fn run_worker(&self, index: usize) {
+        loop {
+            let Some(job) = self.pop(index) else {
+                let sleep = lock(&self.sleep);
+                if self.queued.load(Ordering::SeqCst) > 0 {
+                    // A call is being pushed
+                    continue;
+                }
+                if self.shutdown.load(Ordering::SeqCst) {
+                    return;
+                }
+                drop(self.work.wait(sleep));
+                continue;
+            };
+            self.queued.fetch_sub(1, Ordering::SeqCst);
+            metrics::gauge!(METRIC_EXECUTOR_QUEUED_CALLS).decrement(1);
+
+            let mut slot = match self.take_sandbox() {
+                Ok(slot) => slot,
+                Err(e) => {
+                    job(Err(e));
+                    continue;
+                }
+            };
+            self.busy.fetch_add(1, Ordering::Relaxed);
+            metrics::gauge!(METRIC_EXECUTOR_BUSY_WORKERS).increment(1);
+            let completed =
+                panic::catch_unwind(AssertUnwindSafe(|| job(Ok(&mut slot.sandbox)))).is_ok();
+            self.busy.fetch_sub(1, Ordering::Relaxed);
+            metrics::gauge!(METRIC_EXECUTOR_BUSY_WORKERS).decrement(1);
+            self.completed.fetch_add(1, Ordering::Relaxed);
+            metrics::counter!(METRIC_EXECUTOR_CALLS).increment(1);
+            // A call that panicked may have left the sandbox in any state
+            let recycle = !completed || slot.sandbox.is_poisoned().unwrap_or(true);
+            let slot = if recycle {
+                self.recycle(slot)
+            } else {
+                Some(slot)
+            };
+            self.return_sandbox(slot);
+        }
+    }
+
+    // Restore a poisoned sandbox, or replace it if it cannot be restored
+    fn recycle(&self, mut slot: Slot) -> Option<Slot> {
+        self.recycled.fetch_add(1, Ordering::Relaxed);
+        metrics::counter!(METRIC_EXECUTOR_RECYCLED_SANDBOXES).increment(1);
+        if slot.sandbox.restore(slot.snapshot.clone()).is_ok() {
+            return Some(slot);
+        }
+        drop(slot);
+        Slot::build(&*self.factory)
+            .inspect_err(|e| tracing::error!("Failed to replace a poisoned sandbox: {e}"))
+            .ok()
+    }
+
+    // Take an idle sandbox, waiting for one to be returned, or build one
+    // if every sandbox was lost
+    fn take_sandbox(&self) -> Result<Slot> {
+        let mut idle = self.lock_idle();
+        loop {
+            if let Some(slot) = idle.pop() {
+                return Ok(slot);
+            }
+            if self.sandboxes.load(Ordering::SeqCst) == 0 {
+                // Counted before it is built, so that other workers wait
+                // for it rather than build their own
+                self.sandboxes.fetch_add(1, Ordering::SeqCst);
+                drop(idle);
+                return Slot::build(&*self.factory).map_err(|e| {
+                    self.return_sandbox(None);
+                    new_error!("No sandbox is available to run the call: {}", e)
+                });
+            }
+            idle = self
+                .sandbox_returned
+                .wait(idle)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    fn return_sandbox(&self, slot: Option<Slot>) {
+        let mut idle = self.lock_idle();
+        match slot {
+            Some(slot) => idle.push(slot),
+            None => {
+                self.sandboxes.fetch_sub(1, Ordering::SeqCst);
+            }
+        }
+        // Wake every waiting worker if the last sandbox was lost, so
+        // that one of them builds another
+        self.sandbox_returned.notify_all();
+    }
+
+    fn lock_idle(&self) -> MutexGuard<'_, Vec<Slot>> {
+        lock(&self.idle)
+    }
+}
+
+// The executor's locks are not held while calls run, so a poisoned lock
+// holds consistent data
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl std::fmt::Debug for SandboxExecutor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SandboxExecutor")
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+impl Drop for SandboxExecutor {
+    fn drop(&mut self) {
+        self.shared.shutdown.store(true, Ordering::SeqCst);
+        {
+            let _sleep = lock(&self.shared.sleep);
+            self.shared.work.notify_all();
+        }
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+
+    use examples_common::get_wasm_module_path;
+
+    use hyperlight_host::new_error;
+
+    use super::{SandboxExecutor, Shared};
+    use crate::SandboxBuilder;
+
+    #[test]
+    fn test_sandbox_executor() {
+        let executor = SandboxExecutor::new(2, 2, || {
+            let mut proto_wasm_sandbox = SandboxBuilder::new().build()?;
+            proto_wasm_sandbox.register("TestHostFunc", |a: i32| Ok(a))?;
+            proto_wasm_sandbox
+                .load_runtime()?
+                .load_module(get_wasm_module_path("rust_wasm_samples.aot")?)
+        })
+        .unwrap();
+
+        let pending: Vec<_> = (0..16)
+            .map(|i: i32| (i, executor.call::<i32>("call_host_function", i)))
+            .collect();
+        for (i, pending) in pending {
+            assert_eq!(pending.wait().unwrap(), i);
+        }
+
+        // A call that times out poisons its sandbox, which is restored
+        let err = executor
+            .execute(|sandbox| {
+                sandbox.call_guest_function_with_timeout::<u64>(
+                    "spin",
+                    u64::MAX,
+                    Duration::from_millis(100),
+                )
+            })
+            .wait();
+        assert!(err.is_err());
+        let result = executor.call::<u64>("spin", 1000u64).wait().unwrap();
+        assert_eq!(result, 1000);
+
+        let stats = executor.stats();
+        assert_eq!(stats.workers, 2);
+        assert_eq!(stats.sandboxes, 2);
+        assert_eq!(stats.queued_calls, 0);
+        assert_eq!(stats.completed_calls, 18);
+        assert_eq!(stats.recycled_sandboxes, 1);
+
+        assert!(SandboxExecutor::new(0, 1, || unreachable!()).is_err());
+    }
+
+    #[test]
+    fn test_no_sandbox_available() {
+        // An executor whose sandboxes have all been lost, and whose
+        // factory fails
+        let shared = Shared::new(1, Vec::new(), Box::new(|| Err(new_error!("no hypervisor"))));
+        let pending = shared.execute(|_| Ok(()));
+        shared.shutdown.store(true, Ordering::SeqCst);
+        shared.run_worker(0);
+
+        let err = pending.wait().unwrap_err().to_string();
+        assert!(err.contains("No sandbox is available"), "{err}");
+        assert!(err.contains("no hypervisor"), "{err}");
+        assert_eq!(shared.sandboxes.load(Ordering::SeqCst), 0);
+    }
+}
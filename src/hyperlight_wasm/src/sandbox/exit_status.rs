@@ -0,0 +1,64 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::fmt;
+
+/// How a command module run with
+/// [`LoadedWasmSandbox::run_start`](crate::LoadedWasmSandbox::run_start)
+/// exited: with the code it passed to the WASI `proc_exit` function, or
+/// with 0 if its `_start` function returned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ExitStatus {
+    code: i32,
+}
+
+impl ExitStatus {
+    pub(crate) fn new(code: i32) -> Self {
+        Self { code }
+    }
+
+    /// The exit code
+    pub fn code(&self) -> i32 {
+        self.code
+    }
+
+    /// Whether the module exited successfully, with code 0
+    pub fn success(&self) -> bool {
+        self.code == 0
+    }
+}
+
+impl fmt::Display for ExitStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "exit code {}", self.code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ExitStatus;
+
+    #[test]
+    fn test_exit_status() {
+        let success = ExitStatus::new(0);
+        assert!(success.success());
+        assert_eq!(success.code(), 0);
+        let failure = ExitStatus::new(3);
+        assert!(!failure.success());
+        assert_eq!(failure.code(), 3);
+        assert_eq!(failure.to_string(), "exit code 3");
+    }
+}
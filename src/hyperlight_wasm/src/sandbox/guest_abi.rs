@@ -0,0 +1,104 @@
+/*
+Copyright 2025 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use hyperlight_wasm_runtime::guest_abi;
+
+/// The convention a Wasm module uses to exchange strings and byte
+/// buffers with the host.
+///
+/// Classic (non-component) modules receive and return strings and
+/// buffers as pointers into their linear memory. How that memory is
+/// allocated, and how strings are encoded, depends on the toolchain the
+/// module was built with, so the ABI must be selected to match the
+/// module when it is loaded, see
+/// [`WasmSandbox::with_guest_abi`](crate::WasmSandbox::with_guest_abi).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GuestAbi {
+    /// Modules built with C or Rust toolchains. The module exports
+    /// `malloc` and `free`, and strings are NUL-terminated UTF-8.
+    #[default]
+    C,
+    /// Modules built with AssemblyScript. The module exports the
+    /// runtime's `__new`, `__pin` and `__unpin` functions (build with
+    /// `--exportRuntime`), strings are UTF-16 `String` objects and byte
+    /// buffers are `ArrayBuffer` objects.
+    AssemblyScript,
+    /// Modules built with TinyGo. The module exports `malloc` and `free`,
+    /// strings are passed to guest and host functions as a pointer and a
+    /// length, and strings and byte buffers returned from guest functions
+    /// are packed into an `i64` with the pointer in the lower 32 bits and
+    /// the length in the upper 32 bits.
+    TinyGo,
+}
+
+impl GuestAbi {
+    /// The value identifying this ABI to the wasm runtime
+    pub(crate) fn as_i32(self) -> i32 {
+        match self {
+            GuestAbi::C => guest_abi::C,
+            GuestAbi::AssemblyScript => guest_abi::ASSEMBLY_SCRIPT,
+            GuestAbi::TinyGo => guest_abi::TINYGO,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyperlight_wasm_runtime::guest_abi::{
+        decode_utf16, encode_utf16, pack_ptr_len, unpack_ptr_len,
+    };
+
+    #[test]
+    fn test_utf16_round_trip() {
+        for s in ["", "Hyperlight", "héllo wörld", "🦀 and 𝄞", "\0"] {
+            let bytes = encode_utf16(s);
+            assert_eq!(bytes.len(), s.encode_utf16().count() * 2);
+            assert_eq!(decode_utf16(&bytes).as_deref(), Some(s));
+        }
+        // Code units are little endian, and characters outside the BMP
+        // are surrogate pairs
+        assert_eq!(encode_utf16("A"), [0x41, 0]);
+        assert_eq!(encode_utf16("🦀"), [0x3e, 0xd8, 0x80, 0xdd]);
+    }
+
+    #[test]
+    fn test_decode_utf16_invalid() {
+        // Odd lengths cannot be UTF-16
+        assert_eq!(decode_utf16(&[0x41, 0, 0x42]), None);
+        // Unpaired high and low surrogates
+        assert_eq!(decode_utf16(&[0x3e, 0xd8]), None);
+        assert_eq!(decode_utf16(&[0x80, 0xdd, 0x41, 0]), None);
+        assert_eq!(decode_utf16(&[0x3e, 0xd8, 0x41, 0]), None);
+    }
+
+    #[test]
+    fn test_ptr_len_round_trip() {
+        for (addr, len) in [
+            (0, 0),
+            (1024, 13),
+            (i32::MAX, u32::MAX),
+            // wasm32 addresses above 2 GiB are negative as i32
+            (i32::MIN, 1),
+            (-1, 7),
+        ] {
+            assert_eq!(unpack_ptr_len(pack_ptr_len(addr, len)), (addr, len));
+        }
+        // The pointer is in the lower half and the length in the upper
+        assert_eq!(pack_ptr_len(0x1234, 5), 0x0000_0005_0000_1234);
+        assert_eq!(pack_ptr_len(-1, 0), 0x0000_0000_ffff_ffff);
+        assert_eq!(unpack_ptr_len(-1), (-1, u32::MAX));
+    }
+}
@@ -0,0 +1,135 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::fmt;
+
+use hyperlight_host::HyperlightError;
+use hyperlight_wasm_runtime::runtime_config::GUEST_ABORTED;
+
+/// The error returned by a guest call that ended because the guest
+/// aborted, by calling the WASI `proc_exit` or `wasi:cli/exit`
+/// functions, or by trapping after writing to stderr, as Rust guests do
+/// when they panic.
+///
+/// The guest's stderr output is kept whether or not the host captures
+/// it, see
+/// [`SandboxBuilder::with_stderr`](crate::SandboxBuilder::with_stderr),
+/// so the message of a panic is available here. The sandbox is not
+/// poisoned.
+///
+/// It is returned as a [`HyperlightError::AnyhowError`], from which
+/// [`from_error`](Self::from_error) extracts it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GuestAborted {
+    /// The name of the guest function that was called
+    pub function_name: String,
+    /// The end of what the guest wrote to stderr during the call, such as
+    /// its panic message
+    pub message: String,
+    /// The exit code the guest exited with, or `None` if it trapped
+    pub exit_code: Option<i32>,
+}
+
+impl GuestAborted {
+    /// The abort that ended the call that failed with `error`, or `None`
+    /// if the call failed for another reason
+    pub fn from_error(error: &HyperlightError) -> Option<&Self> {
+        match error {
+            HyperlightError::AnyhowError(e) => e.downcast_ref(),
+            _ => None,
+        }
+    }
+
+    /// The abort that ended the call to `function_name` that failed with
+    /// the guest error `message`, or `None` if it failed for another
+    /// reason
+    pub(crate) fn from_guest_error(message: &str, function_name: &str) -> Option<Self> {
+        let (exit_code, output) = message.strip_prefix(GUEST_ABORTED)?.split_once('\n')?;
+        let exit_code = match exit_code {
+            "-" => None,
+            code => Some(code.parse().ok()?),
+        };
+        Some(Self {
+            function_name: function_name.to_string(),
+            message: output.to_string(),
+            exit_code,
+        })
+    }
+}
+
+impl fmt::Display for GuestAborted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.exit_code {
+            Some(code) => write!(
+                f,
+                "guest function {} exited with code {}",
+                self.function_name, code
+            )?,
+            None => write!(f, "guest function {} aborted", self.function_name)?,
+        }
+        if !self.message.is_empty() {
+            write!(f, ": {}", self.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for GuestAborted {}
+
+impl From<GuestAborted> for HyperlightError {
+    fn from(aborted: GuestAborted) -> Self {
+        HyperlightError::AnyhowError(anyhow::Error::new(aborted))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyperlight_host::HyperlightError;
+    use hyperlight_wasm_runtime::runtime_config::GUEST_ABORTED;
+
+    use super::GuestAborted;
+
+    #[test]
+    fn test_guest_aborted_from_error() {
+        let message = format!("{}-\npanicked at src/lib.rs:3:5:\nboom", GUEST_ABORTED);
+        let aborted = GuestAborted::from_guest_error(&message, "Run").unwrap();
+        assert_eq!(
+            aborted,
+            GuestAborted {
+                function_name: "Run".to_string(),
+                message: "panicked at src/lib.rs:3:5:\nboom".to_string(),
+                exit_code: None,
+            }
+        );
+        assert_eq!(
+            aborted.to_string(),
+            "guest function Run aborted: panicked at src/lib.rs:3:5:\nboom"
+        );
+
+        let message = format!("{}3\n", GUEST_ABORTED);
+        let exited = GuestAborted::from_guest_error(&message, "Run").unwrap();
+        assert_eq!(exited.exit_code, Some(3));
+        assert_eq!(exited.to_string(), "guest function Run exited with code 3");
+        assert_eq!(GuestAborted::from_guest_error("unreachable", "Run"), None);
+
+        let error = HyperlightError::from(aborted.clone());
+        assert_eq!(GuestAborted::from_error(&error), Some(&aborted));
+        assert_eq!(
+            GuestAborted::from_error(&HyperlightError::ExecutionCanceledByHost()),
+            None
+        );
+    }
+}
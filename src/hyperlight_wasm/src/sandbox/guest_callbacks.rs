@@ -0,0 +1,216 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, mpsc};
+use std::thread;
+
+use hyperlight_host::func::SupportedReturnType;
+use hyperlight_host::func::{ParameterTuple, ParameterValue, ReturnType, ReturnValue};
+use hyperlight_host::{Result, new_error};
+use hyperlight_wasm_runtime::host_reentry::{GuestMessage, HostMessage};
+
+/// How deeply re-entrant host functions may nest by default, see
+/// [`SandboxBuilder::with_max_reentrancy_depth`](crate::SandboxBuilder::with_max_reentrancy_depth)
+pub(crate) const DEFAULT_MAX_REENTRANCY_DEPTH: usize = 8;
+
+pub(crate) type ReentrantFunction =
+    Arc<dyn Fn(&mut GuestCallbacks, Vec<ParameterValue>) -> Result<ReturnValue> + Send + Sync>;
+
+/// Calls back into the module that called a re-entrant host function,
+/// registered with
+/// [`ProtoWasmSandbox::register_reentrant`](crate::ProtoWasmSandbox::register_reentrant).
+///
+/// The host function runs on its own thread while the guest waits for
+/// it, and [`call`](Self::call) has the guest call one of the module's
+/// exports and waits for its result.
+#[derive(Debug)]
+pub struct GuestCallbacks {
+    requests: mpsc::Sender<Request>,
+    replies: mpsc::Receiver<Result<ReturnValue>>,
+    depth: usize,
+}
+
+impl GuestCallbacks {
+    /// Call the function `fn_name` exported by the module that called the
+    /// host function, passing parameters `params`, and return its result.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the module does not export `fn_name`, the call
+    /// fails, or its result is not an `Output`.
+    pub fn call<Output: SupportedReturnType>(
+        &mut self,
+        fn_name: &str,
+        params: impl ParameterTuple,
+    ) -> Result<Output> {
+        self.requests
+            .send(Request::CallExport {
+                name: fn_name.to_string(),
+                params: params.into_value(),
+                return_type: Output::TYPE,
+            })
+            .map_err(|_| new_error!("The guest call that called the host function has ended"))?;
+        let value = self
+            .replies
+            .recv()
+            .map_err(|_| new_error!("The guest call that called the host function has ended"))??;
+        Output::from_value(value).map_err(|e| new_error!("{}", e))
+    }
+
+    /// How many re-entrant host function calls are running, including
+    /// this one: 1 if the host function was called from a guest call,
+    /// 2 if it was called from an export called back by another
+    /// re-entrant host function, and so on
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+}
+
+enum Request {
+    CallExport {
+        name: String,
+        params: Vec<ParameterValue>,
+        return_type: ReturnType,
+    },
+    Return(Result<ReturnValue>),
+}
+
+// A running re-entrant host function
+struct Frame {
+    requests: mpsc::Receiver<Request>,
+    replies: mpsc::Sender<Result<ReturnValue>>,
+}
+
+struct CallStack {
+    frames: Vec<Frame>,
+    max_depth: usize,
+}
+
+/// The re-entrant host functions running in a sandbox, innermost last.
+/// Clones share the calls.
+#[derive(Clone)]
+pub(crate) struct ReentrantCalls(Arc<Mutex<CallStack>>);
+
+impl ReentrantCalls {
+    pub(crate) fn new(max_depth: usize) -> Self {
+        Self(Arc::new(Mutex::new(CallStack {
+            frames: Vec::new(),
+            max_depth,
+        })))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, CallStack> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Forget the host functions left running by a guest call that was
+    /// interrupted, whose calls back into the guest then fail
+    pub(crate) fn clear(&self) {
+        self.lock().frames.clear();
+    }
+
+    /// Handle a message from the guest, encoded as described in
+    /// [`hyperlight_wasm_runtime::host_reentry`], starting or resuming
+    /// one of `functions`, and return the encoded reply
+    pub(crate) fn handle(
+        &self,
+        functions: &HashMap<String, ReentrantFunction>,
+        message: &[u8],
+    ) -> Result<Vec<u8>> {
+        let message = GuestMessage::from_bytes(message)
+            .ok_or_else(|| new_error!("malformed message to a re-entrant host function"))?;
+        let mut stack = self.lock();
+        match message {
+            GuestMessage::Call { name, params } => {
+                let function = functions
+                    .get(&name)
+                    .ok_or_else(|| new_error!("host function {} is not re-entrant", name))?
+                    .clone();
+                if stack.frames.len() >= stack.max_depth {
+                    return Err(new_error!(
+                        "host function {} would nest re-entrant host functions more than {} deep",
+                        name,
+                        stack.max_depth
+                    ));
+                }
+                let (request_sender, requests) = mpsc::channel();
+                let (replies, reply_receiver) = mpsc::channel();
+                let mut callbacks = GuestCallbacks {
+                    requests: request_sender.clone(),
+                    replies: reply_receiver,
+                    depth: stack.frames.len() + 1,
+                };
+                thread::Builder::new()
+                    .name(format!("hyperlight-wasm-reentrant-{}", name))
+                    .spawn(move || {
+                        let result = function(&mut callbacks, params);
+                        // The guest call may have been interrupted
+                        let _ = request_sender.send(Request::Return(result));
+                    })?;
+                stack.frames.push(Frame { requests, replies });
+            }
+            GuestMessage::Returned(value) => stack.reply(Ok(value))?,
+            GuestMessage::Failed(message) => stack.reply(Err(new_error!("{}", message)))?,
+        }
+        let request = stack
+            .frames
+            .last()
+            .ok_or_else(|| new_error!("no re-entrant host function is running"))?
+            .requests
+            .recv();
+        match request {
+            Ok(Request::CallExport {
+                name,
+                params,
+                return_type,
+            }) => Ok(HostMessage::CallExport {
+                name,
+                params,
+                return_type,
+            }
+            .to_bytes()),
+            Ok(Request::Return(result)) => {
+                stack.frames.pop();
+                result.map(|value| HostMessage::Return(value).to_bytes())
+            }
+            Err(_) => {
+                stack.frames.pop();
+                Err(new_error!(
+                    "re-entrant host function ended without returning"
+                ))
+            }
+        }
+    }
+}
+
+impl Default for ReentrantCalls {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_REENTRANCY_DEPTH)
+    }
+}
+
+impl CallStack {
+    // Pass the result of an export to the innermost host function
+    fn reply(&self, result: Result<ReturnValue>) -> Result<()> {
+        self.frames
+            .last()
+            .ok_or_else(|| new_error!("no re-entrant host function is running"))?
+            .replies
+            .send(result)
+            .map_err(|_| new_error!("re-entrant host function ended without returning"))
+    }
+}
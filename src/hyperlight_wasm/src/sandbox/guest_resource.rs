@@ -0,0 +1,140 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use hyperlight_host::{Result, new_error};
+
+use super::loaded_wasm_sandbox::LoadedWasmSandbox;
+
+/// A resource exported by the loaded component that the component has
+/// given to the host, such as the result of its constructor.
+///
+/// The guest keeps the resource and the host refers to it by a handle.
+/// The functions of the resource are called by their names in the
+/// guest, which are built from the path of the resource, for example
+/// `r#my::r#world::parser` for the resource `parser` exported by the
+/// world `my:world`, and passed and return the resource as its
+/// [`handle`](Self::handle):
+///
+/// ```ignore
+/// let result: Vec<u8> = sandbox.call_guest_function(
+///     &GuestResource::constructor_name("r#my::r#world::parser"),
+///     (prefix,),
+/// )?;
+/// let parser = GuestResource::from_result("r#my::r#world::parser", &result)?;
+/// let parsed: Vec<u8> =
+///     sandbox.call_guest_function(&parser.method_name("parse"), (parser.handle(), input))?;
+/// parser.drop_in(&mut sandbox)?;
+/// ```
+///
+/// The other parameters and results are marshalled as for the functions
+/// called by the bindings generated by `host_bindgen!`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GuestResource {
+    resource: String,
+    handle: u32,
+}
+
+impl GuestResource {
+    /// The resource of type `resource` returned by a guest function in
+    /// `result`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `result` is not a resource handle.
+    pub fn from_result(resource: &str, result: &[u8]) -> Result<Self> {
+        let handle: [u8; 4] = result.try_into().map_err(|_| {
+            new_error!(
+                "malformed handle of {} bytes for {}",
+                result.len(),
+                resource
+            )
+        })?;
+        Ok(Self {
+            resource: resource.to_string(),
+            handle: u32::from_ne_bytes(handle),
+        })
+    }
+
+    /// The resource's handle, to pass to the guest functions that borrow
+    /// or take ownership of it
+    pub fn handle(&self) -> Vec<u8> {
+        self.handle.to_ne_bytes().to_vec()
+    }
+
+    /// The name of the guest function that constructs a resource of type
+    /// `resource`
+    pub fn constructor_name(resource: &str) -> String {
+        resource_function_name(resource, "constructor", "")
+    }
+
+    /// The name of the guest function that is the static function
+    /// `function` of the resource type `resource`
+    pub fn static_function_name(resource: &str, function: &str) -> String {
+        resource_function_name(resource, "static", &format!(".{}", function))
+    }
+
+    /// The name of the guest function that is the method `method` of the
+    /// resource, whose first parameter is its handle
+    pub fn method_name(&self, method: &str) -> String {
+        resource_function_name(&self.resource, "method", &format!(".{}", method))
+    }
+
+    /// Drop the resource in the guest of `sandbox`, which must be the
+    /// sandbox that returned it, running its destructor
+    pub fn drop_in(self, sandbox: &mut LoadedWasmSandbox) -> Result<()> {
+        let name = resource_function_name(&self.resource, "resource-drop", "");
+        sandbox.call_guest_function::<Vec<u8>>(&name, (self.handle(),))?;
+        Ok(())
+    }
+}
+
+// The guest function of the resource `resource` of the given kind, named
+// as `wasm_guest_bindgen!` registers it, for example
+// `r#my::r#world::[method]parser.parse`
+fn resource_function_name(resource: &str, kind: &str, suffix: &str) -> String {
+    let (path, name) = match resource.rsplit_once("::") {
+        Some((path, name)) => (format!("{}::", path), name),
+        None => (String::new(), resource),
+    };
+    format!("{}[{}]{}{}", path, kind, name, suffix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GuestResource;
+
+    #[test]
+    fn test_guest_resource_names_and_handle() {
+        const PARSER: &str = "r#test::r#res::parser";
+        assert_eq!(
+            GuestResource::constructor_name(PARSER),
+            "r#test::r#res::[constructor]parser"
+        );
+        assert_eq!(
+            GuestResource::static_function_name(PARSER, "merge"),
+            "r#test::r#res::[static]parser.merge"
+        );
+
+        let parser = GuestResource::from_result(PARSER, &7u32.to_ne_bytes()).unwrap();
+        assert_eq!(parser.handle(), 7u32.to_ne_bytes().to_vec());
+        assert_eq!(
+            parser.method_name("parse"),
+            "r#test::r#res::[method]parser.parse"
+        );
+
+        assert!(GuestResource::from_result(PARSER, &[0; 3]).is_err());
+    }
+}
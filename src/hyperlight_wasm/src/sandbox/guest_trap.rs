@@ -0,0 +1,231 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::fmt;
+
+use hyperlight_host::HyperlightError;
+use hyperlight_wasm_runtime::runtime_config::WASM_TRAP;
+
+/// The error returned by a guest call that trapped, for example on an
+/// out of bounds memory access or an `unreachable` instruction such as
+/// the one a Rust guest aborts with when it panics. Calls that exhaust
+/// the wasm stack return a [`StackExhausted`](crate::StackExhausted)
+/// error instead.
+///
+/// The trap happened inside the guest, so the sandbox is not poisoned.
+///
+/// It is returned as a [`HyperlightError::AnyhowError`], from which
+/// [`from_error`](Self::from_error) extracts it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GuestTrap {
+    /// The name of the guest function that was called
+    pub function_name: String,
+    /// What caused the trap
+    pub code: TrapCode,
+    /// The wasm backtrace of the trap, innermost frame first
+    pub frames: Vec<TrapFrame>,
+}
+
+/// What caused a [`GuestTrap`], following wasmtime's trap codes
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TrapCode {
+    /// The wasm stack was exhausted, which calls report as
+    /// [`StackExhausted`](crate::StackExhausted) rather than as a
+    /// [`GuestTrap`]
+    StackOverflow,
+    /// A linear memory was accessed out of bounds
+    MemoryOutOfBounds,
+    /// A misaligned atomic access was made
+    HeapMisaligned,
+    /// A table was accessed out of bounds
+    TableOutOfBounds,
+    /// An indirect call went through a null table entry
+    IndirectCallToNull,
+    /// An indirect call's signature did not match the callee's
+    BadSignature,
+    /// An integer operation overflowed
+    IntegerOverflow,
+    /// An integer was divided by zero
+    IntegerDivisionByZero,
+    /// A float could not be converted to an integer
+    BadConversionToInteger,
+    /// An `unreachable` instruction was executed
+    UnreachableCodeReached,
+    /// A null reference was dereferenced
+    NullReference,
+    /// Another trap code, by its wasmtime name
+    Other(String),
+}
+
+impl TrapCode {
+    /// The wasmtime name of the trap code
+    pub(crate) fn name(&self) -> String {
+        match self {
+            Self::Other(name) => name.clone(),
+            code => format!("{:?}", code),
+        }
+    }
+
+    fn from_name(name: &str) -> Self {
+        match name {
+            "StackOverflow" => Self::StackOverflow,
+            "MemoryOutOfBounds" => Self::MemoryOutOfBounds,
+            "HeapMisaligned" => Self::HeapMisaligned,
+            "TableOutOfBounds" => Self::TableOutOfBounds,
+            "IndirectCallToNull" => Self::IndirectCallToNull,
+            "BadSignature" => Self::BadSignature,
+            "IntegerOverflow" => Self::IntegerOverflow,
+            "IntegerDivisionByZero" => Self::IntegerDivisionByZero,
+            "BadConversionToInteger" => Self::BadConversionToInteger,
+            "UnreachableCodeReached" => Self::UnreachableCodeReached,
+            "NullReference" => Self::NullReference,
+            other => Self::Other(other.to_string()),
+        }
+    }
+}
+
+/// A frame of the wasm backtrace of a [`GuestTrap`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrapFrame {
+    /// The index of the function in its module
+    pub func_index: u32,
+    /// The function's offset in its module, if known
+    pub module_offset: Option<usize>,
+    /// The function's name from the module's name section, if any
+    pub func_name: Option<String>,
+}
+
+impl TrapFrame {
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(3, ' ');
+        let func_index = fields.next()?.parse().ok()?;
+        let module_offset = match fields.next()? {
+            "-" => None,
+            offset => Some(offset.parse().ok()?),
+        };
+        let func_name = fields.next().filter(|name| !name.is_empty());
+        Some(Self {
+            func_index,
+            module_offset,
+            func_name: func_name.map(str::to_string),
+        })
+    }
+}
+
+impl GuestTrap {
+    /// The trap of the call that failed with `error`, or `None` if the
+    /// call failed for another reason
+    pub fn from_error(error: &HyperlightError) -> Option<&Self> {
+        match error {
+            HyperlightError::AnyhowError(e) => e.downcast_ref(),
+            _ => None,
+        }
+    }
+
+    /// The trap of the call to `function_name` that failed with the guest
+    /// error `message`, or `None` if it failed for another reason
+    pub(crate) fn from_guest_error(message: &str, function_name: &str) -> Option<Self> {
+        let mut lines = message.strip_prefix(WASM_TRAP)?.lines();
+        let code = TrapCode::from_name(lines.next()?);
+        let frames = lines.map(TrapFrame::parse).collect::<Option<_>>()?;
+        Some(Self {
+            function_name: function_name.to_string(),
+            code,
+            frames,
+        })
+    }
+}
+
+impl fmt::Display for GuestTrap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "guest function {} trapped: {}",
+            self.function_name,
+            self.code.name()
+        )?;
+        for frame in &self.frames {
+            write!(f, "\n    at ")?;
+            match &frame.func_name {
+                Some(name) => write!(f, "{}", name)?,
+                None => write!(f, "<wasm function {}>", frame.func_index)?,
+            }
+            if let Some(offset) = frame.module_offset {
+                write!(f, " (offset {:#x})", offset)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for GuestTrap {}
+
+impl From<GuestTrap> for HyperlightError {
+    fn from(trap: GuestTrap) -> Self {
+        HyperlightError::AnyhowError(anyhow::Error::new(trap))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyperlight_host::HyperlightError;
+    use hyperlight_wasm_runtime::runtime_config::WASM_TRAP;
+
+    use super::{GuestTrap, TrapCode, TrapFrame};
+
+    #[test]
+    fn test_guest_trap_from_error() {
+        let message = format!("{}MemoryOutOfBounds\n3 120 read_byte\n7 - ", WASM_TRAP);
+        let trap = GuestTrap::from_guest_error(&message, "ReadByte").unwrap();
+        assert_eq!(
+            trap,
+            GuestTrap {
+                function_name: "ReadByte".to_string(),
+                code: TrapCode::MemoryOutOfBounds,
+                frames: vec![
+                    TrapFrame {
+                        func_index: 3,
+                        module_offset: Some(120),
+                        func_name: Some("read_byte".to_string()),
+                    },
+                    TrapFrame {
+                        func_index: 7,
+                        module_offset: None,
+                        func_name: None,
+                    },
+                ],
+            }
+        );
+        assert_eq!(
+            trap.to_string(),
+            "guest function ReadByte trapped: MemoryOutOfBounds\n    at read_byte (offset 0x78)\n    at <wasm function 7>"
+        );
+
+        let message = format!("{}AllocationTooLarge", WASM_TRAP);
+        assert_eq!(
+            GuestTrap::from_guest_error(&message, "Grow").unwrap().code,
+            TrapCode::Other("AllocationTooLarge".to_string())
+        );
+        assert_eq!(GuestTrap::from_guest_error("unreachable", "Grow"), None);
+
+        let error = HyperlightError::from(trap.clone());
+        assert_eq!(GuestTrap::from_error(&error), Some(&trap));
+        assert_eq!(
+            GuestTrap::from_error(&HyperlightError::ExecutionCanceledByHost()),
+            None
+        );
+    }
+}
@@ -0,0 +1,175 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::fmt::{self, Display};
+
+use hyperlight_common::for_each_tuple;
+use hyperlight_host::func::{HostFunction, ParameterTuple, SupportedReturnType};
+use hyperlight_host::{HyperlightError, new_error};
+
+use super::panic_policy::{CatchPanics, OnHostError};
+
+thread_local! {
+    // The error raised by the host function being called on this thread
+    static RAISED: RefCell<Option<HostError>> = const { RefCell::new(None) };
+}
+
+/// An error failing a call to a host function whose signature cannot
+/// return one, such as the methods of the host traits generated by
+/// `host_bindgen!`, see [`HostError::raise`]
+#[derive(Debug)]
+pub struct HostError(HyperlightError);
+
+impl HostError {
+    /// An error with `message`
+    pub fn new(message: impl Display) -> Self {
+        Self(new_error!("{}", message))
+    }
+
+    /// Fail the call to the host function this is called from with this
+    /// error, and return a default value for the host function to return
+    /// in the meantime, which the guest never sees.
+    ///
+    /// The error traps the guest call without poisoning the sandbox, as
+    /// an error returned by a host function registered with
+    /// [`ProtoWasmSandbox::register`](crate::ProtoWasmSandbox::register)
+    /// does, and the guest call fails with a
+    /// [`HostFunctionFailed`](crate::HostFunctionFailed) error holding
+    /// it. This only has an effect in the host functions registered by the
+    /// bindings generated by `host_bindgen!`.
+    pub fn raise<T: Default>(self) -> T {
+        RAISED.with_borrow_mut(|raised| *raised = Some(self));
+        T::default()
+    }
+}
+
+impl Display for HostError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for HostError {}
+
+impl From<HyperlightError> for HostError {
+    fn from(error: HyperlightError) -> Self {
+        Self(error)
+    }
+}
+
+/// Fail the call to the host function this is called from with an
+/// error, see [`HostError::raise`]
+pub trait OrRaise<T> {
+    /// Return the value, or fail the host function call with the error
+    fn or_raise(self) -> T;
+}
+
+impl<T: Default, E: Display> OrRaise<T> for std::result::Result<T, E> {
+    fn or_raise(self) -> T {
+        self.unwrap_or_else(|error| HostError::new(error).raise())
+    }
+}
+
+// The type of each parameter of the host functions registered by the
+// bindings generated by `host_bindgen!`
+macro_rules! bytes {
+    ($P:ident) => {
+        Vec<u8>
+    };
+}
+
+/// Wrap `host_func`, registered through `Registerable` directly, so that
+/// a [`HostError`] it raises is passed to `on_error` and returned to the
+/// guest instead of its result. Only functions taking and returning
+/// buffers, as those registered by the bindings generated by
+/// `host_bindgen!` do, are wrapped.
+pub(crate) fn catch_host_errors<Output: SupportedReturnType, Args: ParameterTuple>(
+    host_func: HostFunction<Output, Args>,
+    on_error: OnHostError,
+) -> HostFunction<Output, Args> {
+    let mut host_func: Box<dyn Any> = Box::new(host_func);
+    macro_rules! try_wrap {
+        ([$N:expr] ($($p:ident: $P:ident),*)) => {
+            host_func = match host_func.downcast::<HostFunction<Vec<u8>, ($(bytes!($P),)*)>>() {
+                Ok(host_func) => {
+                    let raising = HostFunction::from(move |$($p: bytes!($P)),*| {
+                        RAISED.with_borrow_mut(|raised| *raised = None);
+                        let result = host_func.call(($($p,)*));
+                        match RAISED.with_borrow_mut(Option::take) {
+                            Some(error) => Err(error.0),
+                            None => result,
+                        }
+                    });
+                    let wrapped: Box<dyn Any> =
+                        Box::new(<($(bytes!($P),)*)>::keep_errors(raising, on_error));
+                    return match wrapped.downcast() {
+                        Ok(wrapped) => *wrapped,
+                        Err(_) => unreachable!("the wrapped function has the same type"),
+                    };
+                }
+                Err(host_func) => host_func,
+            };
+        };
+    }
+    for_each_tuple!(try_wrap);
+    match host_func.downcast() {
+        Ok(host_func) => *host_func,
+        Err(_) => unreachable!("the function was not converted"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[test]
+    fn test_catch_host_errors() {
+        let kept = Arc::new(Mutex::new(None));
+        let on_error: OnHostError = {
+            let kept = kept.clone();
+            Arc::new(move |error: HyperlightError| {
+                let message = error.to_string();
+                *kept.lock().unwrap() = Some(message.clone());
+                new_error!("{}", message)
+            })
+        };
+
+        let host_func = HostFunction::from(|a: Vec<u8>, b: Vec<u8>| -> Vec<u8> {
+            if a.is_empty() {
+                return HostError::new("a is empty").raise();
+            }
+            let n: Result<u8, HyperlightError> =
+                b.first().copied().ok_or_else(|| new_error!("b is empty"));
+            vec![n.or_raise()]
+        });
+        let host_func = catch_host_errors(host_func, on_error.clone());
+        assert_eq!(host_func.call((vec![1], vec![2])).unwrap(), [2]);
+        let error = host_func.call((vec![], vec![2])).unwrap_err();
+        assert_eq!(error.to_string(), "a is empty");
+        assert_eq!(kept.lock().unwrap().as_deref(), Some("a is empty"));
+        let error = host_func.call((vec![1], vec![])).unwrap_err();
+        assert_eq!(error.to_string(), "b is empty");
+        assert_eq!(host_func.call((vec![1], vec![3])).unwrap(), [3]);
+
+        // Functions of other types are left as they are
+        let host_func = catch_host_errors(HostFunction::from(|a: i32| -> i32 { a + 1 }), on_error);
+        assert_eq!(host_func.call((1,)).unwrap(), 2);
+    }
+}
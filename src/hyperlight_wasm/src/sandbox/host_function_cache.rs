@@ -0,0 +1,76 @@
+/*
+Copyright 2025 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::time::{Duration, Instant};
+
+/// How the results of a host function registered with
+/// [`ProtoWasmSandbox::register_cached`](crate::ProtoWasmSandbox::register_cached)
+/// are cached in the guest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HostFunctionCache {
+    /// How long a result may be used for. Results are discarded at the
+    /// start of the first guest call made after this much time has passed
+    /// since the results were last discarded, so a result may be used
+    /// for longer during a long-running guest call.
+    pub ttl: Duration,
+    /// The maximum number of results cached for different parameters.
+    /// When a new result does not fit, the oldest result is discarded.
+    /// The cache is searched linearly, so this should be small.
+    pub max_entries: usize,
+}
+
+/// Tracks when the results cached in the guest were last discarded, so
+/// the host can discard them again when they expire
+#[derive(Clone, Debug, Default)]
+pub(crate) struct HostFunctionCacheExpiry {
+    // The name and ttl of each cached host function, and when its results
+    // were last discarded, or None if the guest may hold results of
+    // unknown age
+    functions: Vec<(String, Duration, Option<Instant>)>,
+}
+
+impl HostFunctionCacheExpiry {
+    pub(crate) fn new(functions: impl IntoIterator<Item = (String, Duration)>) -> Self {
+        Self {
+            functions: functions
+                .into_iter()
+                .map(|(name, ttl)| (name, ttl, None))
+                .collect(),
+        }
+    }
+
+    /// Forget when results were last discarded, for example because the
+    /// sandbox was restored to a snapshot holding older results
+    pub(crate) fn reset(&mut self) {
+        for (_, _, flushed) in &mut self.functions {
+            *flushed = None;
+        }
+    }
+
+    /// Return the names of the functions whose results have expired, and
+    /// record that they are discarded now
+    pub(crate) fn take_expired(&mut self) -> Vec<&str> {
+        let now = Instant::now();
+        let mut expired = Vec::new();
+        for (name, ttl, flushed) in &mut self.functions {
+            if flushed.is_none_or(|flushed| now.duration_since(flushed) >= *ttl) {
+                *flushed = Some(now);
+                expired.push(name.as_str());
+            }
+        }
+        expired
+    }
+}
@@ -0,0 +1,202 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use hyperlight_host::{HyperlightError, new_error};
+use hyperlight_wasm_runtime::runtime_config::HOST_FUNCTION_FAILED;
+
+use super::panic_policy::OnHostError;
+
+/// The error returned by a guest call that failed because a host function
+/// the guest called returned an error.
+///
+/// The guest cannot handle the error: it traps the guest call, and the
+/// sandbox is not poisoned. The error the host function returned is kept
+/// for host functions registered with
+/// [`ProtoWasmSandbox::register`](crate::ProtoWasmSandbox::register),
+/// [`register_cached`](crate::ProtoWasmSandbox::register_cached),
+/// [`register_manifest`](crate::ProtoWasmSandbox::register_manifest) or
+/// [`LoadedWasmSandbox::register`](crate::LoadedWasmSandbox::register),
+/// see [`host_error`](Self::host_error).
+///
+/// It is returned as a [`HyperlightError::AnyhowError`], from which
+/// [`from_error`](Self::from_error) extracts it.
+#[derive(Debug)]
+pub struct HostFunctionFailed {
+    /// The name of the guest function that was called
+    pub function_name: String,
+    /// The name of the host function that returned the error
+    pub host_function: String,
+    /// The message of the error, as the guest received it
+    pub message: String,
+    host_error: Option<HyperlightError>,
+}
+
+impl HostFunctionFailed {
+    /// The failure of a host function that ended the call that failed
+    /// with `error`, or `None` if the call failed for another reason
+    pub fn from_error(error: &HyperlightError) -> Option<&Self> {
+        match error {
+            HyperlightError::AnyhowError(e) => e.downcast_ref(),
+            _ => None,
+        }
+    }
+
+    /// The error the host function returned, or `None` if it was not
+    /// kept. This is also the [`source`](std::error::Error::source) of
+    /// `self`.
+    pub fn host_error(&self) -> Option<&HyperlightError> {
+        self.host_error.as_ref()
+    }
+
+    /// The failure of a host function that ended the call to
+    /// `function_name` that failed with the guest error `message`, or
+    /// `None` if it failed for another reason. The error the host
+    /// function returned is taken from `host_errors`.
+    pub(crate) fn from_guest_error(
+        message: &str,
+        function_name: &str,
+        host_errors: &HostErrors,
+    ) -> Option<Self> {
+        let (host_function, message) = message
+            .strip_prefix(HOST_FUNCTION_FAILED)?
+            .split_once('\n')?;
+        Some(Self {
+            function_name: function_name.to_string(),
+            host_function: host_function.to_string(),
+            message: message.to_string(),
+            host_error: host_errors.take(host_function),
+        })
+    }
+}
+
+impl fmt::Display for HostFunctionFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "host function {} called by guest function {} failed: {}",
+            self.host_function, self.function_name, self.message
+        )
+    }
+}
+
+impl std::error::Error for HostFunctionFailed {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.host_error
+            .as_ref()
+            .map(|e| e as &(dyn std::error::Error + 'static))
+    }
+}
+
+impl From<HostFunctionFailed> for HyperlightError {
+    fn from(failed: HostFunctionFailed) -> Self {
+        HyperlightError::AnyhowError(anyhow::Error::new(failed))
+    }
+}
+
+/// Keeps the last error returned to the guest by a host function of a
+/// sandbox, so that it can be attached to the [`HostFunctionFailed`]
+/// error of the guest call. Clones share the error.
+#[derive(Clone, Default)]
+pub(crate) struct HostErrors(Arc<Mutex<Option<(String, HyperlightError)>>>);
+
+impl HostErrors {
+    /// A function keeping the errors returned by the host function
+    /// `name`, for [`CatchPanics::keep_errors`](super::panic_policy::CatchPanics::keep_errors)
+    pub(crate) fn on_error(&self, name: &str) -> OnHostError {
+        let errors = self.clone();
+        let name = name.to_string();
+        Arc::new(move |error| errors.keep(&name, error))
+    }
+
+    /// Keep `error`, returned by the host function `name`, returning the
+    /// error to pass to the guest in its place
+    pub(crate) fn keep(&self, name: &str, error: HyperlightError) -> HyperlightError {
+        let message = error.to_string();
+        if let Ok(mut kept) = self.0.lock() {
+            *kept = Some((name.to_string(), error));
+        }
+        new_error!("{}", message)
+    }
+
+    /// Forget the error kept during the previous guest call
+    pub(crate) fn clear(&self) {
+        if let Ok(mut kept) = self.0.lock() {
+            *kept = None;
+        }
+    }
+
+    /// Take the error kept for the host function `name`
+    fn take(&self, name: &str) -> Option<HyperlightError> {
+        let mut kept = self.0.lock().ok()?;
+        match kept.take() {
+            Some((kept_name, error)) if kept_name == name => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use hyperlight_host::{HyperlightError, new_error};
+    use hyperlight_wasm_runtime::runtime_config::HOST_FUNCTION_FAILED;
+
+    use super::{HostErrors, HostFunctionFailed};
+
+    #[test]
+    fn test_host_function_failed_from_error() {
+        let host_errors = HostErrors::default();
+        let passed = host_errors.keep("Lookup", new_error!("key not found"));
+        assert_eq!(passed.to_string(), "key not found");
+
+        let message = format!("{}Lookup\nkey not found", HOST_FUNCTION_FAILED);
+        let failed = HostFunctionFailed::from_guest_error(&message, "Run", &host_errors).unwrap();
+        assert_eq!(failed.function_name, "Run");
+        assert_eq!(failed.host_function, "Lookup");
+        assert_eq!(failed.message, "key not found");
+        assert_eq!(
+            failed.to_string(),
+            "host function Lookup called by guest function Run failed: key not found"
+        );
+        assert!(matches!(
+            failed.host_error(),
+            Some(HyperlightError::Error(message)) if message == "key not found"
+        ));
+        assert!(failed.source().is_some());
+
+        // Errors are kept for one call, and only for the function that
+        // returned them
+        let failed = HostFunctionFailed::from_guest_error(&message, "Run", &host_errors).unwrap();
+        assert!(failed.host_error().is_none());
+        host_errors.keep("Other", new_error!("other"));
+        let failed = HostFunctionFailed::from_guest_error(&message, "Run", &host_errors).unwrap();
+        assert!(failed.host_error().is_none());
+        assert!(HostFunctionFailed::from_guest_error("unreachable", "Run", &host_errors).is_none());
+
+        let error = HyperlightError::from(failed);
+        assert_eq!(
+            HostFunctionFailed::from_error(&error).map(|f| f.host_function.as_str()),
+            Some("Lookup")
+        );
+        assert!(
+            HostFunctionFailed::from_error(&HyperlightError::ExecutionCanceledByHost()).is_none()
+        );
+    }
+}
@@ -0,0 +1,379 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use hyperlight_host::func::{ParameterType, ParameterValue, ReturnType, ReturnValue};
+use hyperlight_host::{Result, new_error};
+
+/// A host function described by a [`HostFunctionManifest`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ManifestFunction {
+    /// The name modules import the function as
+    pub name: String,
+    /// The types of the function's parameters. For modules, a
+    /// `VecBytes` parameter must be followed by an `Int` holding its
+    /// length.
+    pub parameter_types: Vec<ParameterType>,
+    /// The type of the function's result
+    pub return_type: ReturnType,
+    /// Tags describing what the function gives guests access to, such as
+    /// `"fs:read"`, see [`HostFunctionManifest::restricted_to`]
+    pub capabilities: Vec<String>,
+}
+
+impl ManifestFunction {
+    /// Describe a function called `name` with no capability tags
+    pub fn new(
+        name: impl Into<String>,
+        parameter_types: impl IntoIterator<Item = ParameterType>,
+        return_type: ReturnType,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            parameter_types: parameter_types.into_iter().collect(),
+            return_type,
+            capabilities: Vec::new(),
+        }
+    }
+
+    /// Tag the function with `capability`
+    pub fn with_capability(mut self, capability: impl Into<String>) -> Self {
+        self.capabilities.push(capability.into());
+        self
+    }
+
+    pub(super) fn check_params(&self, params: &[ParameterValue]) -> Result<()> {
+        let types = params.iter().map(ParameterType::from);
+        if !types.eq(self.parameter_types.iter().cloned()) {
+            return Err(new_error!(
+                "host function {} expects parameters {:?}, not {:?}",
+                self.name,
+                self.parameter_types,
+                params
+            ));
+        }
+        Ok(())
+    }
+
+    pub(super) fn check_return_value(&self, value: &ReturnValue) -> Result<()> {
+        let matches = matches!(
+            (self.return_type, value),
+            (ReturnType::Int, ReturnValue::Int(_))
+                | (ReturnType::UInt, ReturnValue::UInt(_))
+                | (ReturnType::Long, ReturnValue::Long(_))
+                | (ReturnType::ULong, ReturnValue::ULong(_))
+                | (ReturnType::Float, ReturnValue::Float(_))
+                | (ReturnType::Double, ReturnValue::Double(_))
+                | (ReturnType::String, ReturnValue::String(_))
+                | (ReturnType::Bool, ReturnValue::Bool(_))
+                | (ReturnType::VecBytes, ReturnValue::VecBytes(_))
+                | (ReturnType::Void, ReturnValue::Void(()))
+        );
+        if !matches {
+            return Err(new_error!(
+                "host function {} returns {:?}, not {:?}",
+                self.name,
+                self.return_type,
+                value
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A set of host functions that are not known until run time, for
+/// example because they are defined by a scripting layer, registered
+/// together with
+/// [`ProtoWasmSandbox::register_manifest`](crate::ProtoWasmSandbox::register_manifest).
+///
+/// A manifest can be stored or sent elsewhere with
+/// [`to_bytes`](Self::to_bytes) and read back with
+/// [`from_bytes`](Self::from_bytes).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HostFunctionManifest {
+    functions: Vec<ManifestFunction>,
+}
+
+// Version of the encoding produced by to_bytes
+const MANIFEST_FORMAT_VERSION: u8 = 1;
+
+fn parameter_type_code(ty: &ParameterType) -> u8 {
+    match ty {
+        ParameterType::Int => 0,
+        ParameterType::UInt => 1,
+        ParameterType::Long => 2,
+        ParameterType::ULong => 3,
+        ParameterType::Float => 4,
+        ParameterType::Double => 5,
+        ParameterType::String => 6,
+        ParameterType::Bool => 7,
+        ParameterType::VecBytes => 8,
+    }
+}
+
+fn parameter_type_from_code(code: u8) -> Option<ParameterType> {
+    Some(match code {
+        0 => ParameterType::Int,
+        1 => ParameterType::UInt,
+        2 => ParameterType::Long,
+        3 => ParameterType::ULong,
+        4 => ParameterType::Float,
+        5 => ParameterType::Double,
+        6 => ParameterType::String,
+        7 => ParameterType::Bool,
+        8 => ParameterType::VecBytes,
+        _ => return None,
+    })
+}
+
+fn return_type_code(ty: ReturnType) -> u8 {
+    match ty {
+        ReturnType::Int => 0,
+        ReturnType::UInt => 1,
+        ReturnType::Long => 2,
+        ReturnType::ULong => 3,
+        ReturnType::Float => 4,
+        ReturnType::Double => 5,
+        ReturnType::String => 6,
+        ReturnType::Bool => 7,
+        ReturnType::VecBytes => 8,
+        ReturnType::Void => 9,
+    }
+}
+
+fn return_type_from_code(code: u8) -> Option<ReturnType> {
+    Some(match code {
+        0 => ReturnType::Int,
+        1 => ReturnType::UInt,
+        2 => ReturnType::Long,
+        3 => ReturnType::ULong,
+        4 => ReturnType::Float,
+        5 => ReturnType::Double,
+        6 => ReturnType::String,
+        7 => ReturnType::Bool,
+        8 => ReturnType::VecBytes,
+        9 => ReturnType::Void,
+        _ => return None,
+    })
+}
+
+pub(super) fn push_str(bytes: &mut Vec<u8>, s: &str) {
+    bytes.extend_from_slice(&(s.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(s.as_bytes());
+}
+
+// Reads the encoding produced by to_bytes, returning None when it runs
+// out or is malformed
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn u8(&mut self) -> Option<u8> {
+        let (&b, rest) = self.0.split_first()?;
+        self.0 = rest;
+        Some(b)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let (b, rest) = self.0.split_first_chunk()?;
+        self.0 = rest;
+        Some(u32::from_le_bytes(*b))
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = self.u32()? as usize;
+        if self.0.len() < len {
+            return None;
+        }
+        let (s, rest) = self.0.split_at(len);
+        self.0 = rest;
+        String::from_utf8(s.to_vec()).ok()
+    }
+}
+
+impl HostFunctionManifest {
+    /// Create an empty manifest
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `function` to the manifest
+    pub fn with_function(mut self, function: ManifestFunction) -> Self {
+        self.functions.push(function);
+        self
+    }
+
+    /// The functions in the manifest
+    pub fn functions(&self) -> &[ManifestFunction] {
+        &self.functions
+    }
+
+    /// A copy of the manifest holding only the functions whose
+    /// capability tags are all in `granted`, so that a host can expose a
+    /// different subset of its capabilities to each sandbox
+    pub fn restricted_to(&self, granted: &[&str]) -> Self {
+        Self {
+            functions: self
+                .functions
+                .iter()
+                .filter(|f| f.capabilities.iter().all(|c| granted.contains(&c.as_str())))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Encode the manifest
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![MANIFEST_FORMAT_VERSION];
+        bytes.extend_from_slice(&(self.functions.len() as u32).to_le_bytes());
+        for function in &self.functions {
+            push_str(&mut bytes, &function.name);
+            bytes.extend_from_slice(&(function.parameter_types.len() as u32).to_le_bytes());
+            bytes.extend(function.parameter_types.iter().map(parameter_type_code));
+            bytes.push(return_type_code(function.return_type));
+            bytes.extend_from_slice(&(function.capabilities.len() as u32).to_le_bytes());
+            for capability in &function.capabilities {
+                push_str(&mut bytes, capability);
+            }
+        }
+        bytes
+    }
+
+    /// Describe the manifest as JSON, in the form read by
+    /// `hyperlight-wasm-aot preflight --host-manifest`:
+    ///
+    /// ```json
+    /// {"functions": [{"name": "ReadConfig", "parameter_types": ["String"],
+    ///   "return_type": "String", "capabilities": ["config:read"]}]}
+    /// ```
+    pub fn to_json(&self) -> String {
+        let functions: Vec<serde_json::Value> = self
+            .functions
+            .iter()
+            .map(|function| {
+                serde_json::json!({
+                    "name": function.name,
+                    "parameter_types": function
+                        .parameter_types
+                        .iter()
+                        .map(|ty| format!("{:?}", ty))
+                        .collect::<Vec<_>>(),
+                    "return_type": format!("{:?}", function.return_type),
+                    "capabilities": function.capabilities,
+                })
+            })
+            .collect();
+        serde_json::json!({ "functions": functions }).to_string()
+    }
+
+    /// Decode a manifest encoded with [`to_bytes`](Self::to_bytes)
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `bytes` is not a manifest encoded by this
+    /// version of hyperlight-wasm.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        Self::decode(bytes).ok_or_else(|| new_error!("malformed host function manifest"))
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        let mut reader = Reader(bytes);
+        if reader.u8()? != MANIFEST_FORMAT_VERSION {
+            return None;
+        }
+        let mut functions = Vec::new();
+        for _ in 0..reader.u32()? {
+            let name = reader.string()?;
+            let parameter_types = (0..reader.u32()?)
+                .map(|_| parameter_type_from_code(reader.u8()?))
+                .collect::<Option<_>>()?;
+            let return_type = return_type_from_code(reader.u8()?)?;
+            let capabilities = (0..reader.u32()?)
+                .map(|_| reader.string())
+                .collect::<Option<_>>()?;
+            functions.push(ManifestFunction {
+                name,
+                parameter_types,
+                return_type,
+                capabilities,
+            });
+        }
+        reader.0.is_empty().then_some(Self { functions })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyperlight_host::func::{ParameterType, ParameterValue, ReturnType, ReturnValue};
+
+    use super::{HostFunctionManifest, ManifestFunction};
+
+    #[test]
+    fn test_host_function_manifest() {
+        let manifest = HostFunctionManifest::new()
+            .with_function(
+                ManifestFunction::new("ReadConfig", [ParameterType::String], ReturnType::String)
+                    .with_capability("config:read"),
+            )
+            .with_function(
+                ManifestFunction::new(
+                    "WriteFile",
+                    [ParameterType::VecBytes, ParameterType::Int],
+                    ReturnType::Void,
+                )
+                .with_capability("fs:write")
+                .with_capability("config:read"),
+            )
+            .with_function(ManifestFunction::new("Now", [], ReturnType::ULong));
+
+        let bytes = manifest.to_bytes();
+        assert_eq!(HostFunctionManifest::from_bytes(&bytes).unwrap(), manifest);
+        assert!(HostFunctionManifest::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(HostFunctionManifest::from_bytes(&[]).is_err());
+
+        let json: serde_json::Value = serde_json::from_str(&manifest.to_json()).unwrap();
+        assert_eq!(
+            json["functions"][1],
+            serde_json::json!({
+                "name": "WriteFile",
+                "parameter_types": ["VecBytes", "Int"],
+                "return_type": "Void",
+                "capabilities": ["fs:write", "config:read"],
+            })
+        );
+
+        let restricted = manifest.restricted_to(&["config:read"]);
+        let names: Vec<_> = restricted.functions().iter().map(|f| &f.name).collect();
+        assert_eq!(names, ["ReadConfig", "Now"]);
+
+        let read_config = &manifest.functions()[0];
+        assert!(
+            read_config
+                .check_params(&[ParameterValue::String("key".to_string())])
+                .is_ok()
+        );
+        assert!(read_config.check_params(&[ParameterValue::Int(1)]).is_err());
+        assert!(read_config.check_params(&[]).is_err());
+        assert!(
+            read_config
+                .check_return_value(&ReturnValue::String("value".to_string()))
+                .is_ok()
+        );
+        assert!(
+            read_config
+                .check_return_value(&ReturnValue::Void(()))
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,164 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use hyperlight_host::{Result, new_error};
+use hyperlight_wasm_runtime::instance_state::{CHUNK_SIZE, InstanceLayout};
+
+/// The bytes serialized instance states start with
+const MAGIC: &[u8; 4] = b"HLWS";
+
+/// The version of the serialized instance state encoding
+const VERSION: u32 = 1;
+
+/// The state of a module instance, see
+/// [`LoadedWasmSandbox::serialize_state`](crate::LoadedWasmSandbox::serialize_state).
+///
+/// Only the chunks of memory that are not all zeroes are kept, as most
+/// of a module's memory is usually untouched.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct InstanceState {
+    /// The hash of the module the state was taken from, if it was known
+    pub(crate) module_hash: Option<String>,
+    /// The instance's memory size and globals
+    pub(crate) layout: InstanceLayout,
+    /// The offsets and contents of the non-zero chunks of memory, each
+    /// at most [`CHUNK_SIZE`] bytes long
+    pub(crate) chunks: Vec<(u64, Vec<u8>)>,
+}
+
+fn take<const N: usize>(bytes: &mut &[u8]) -> Result<[u8; N]> {
+    let (value, rest) = bytes
+        .split_first_chunk::<N>()
+        .ok_or_else(|| new_error!("Serialized instance state is truncated"))?;
+    *bytes = rest;
+    Ok(*value)
+}
+
+fn take_bytes<'a>(bytes: &mut &'a [u8]) -> Result<&'a [u8]> {
+    let len = u32::from_le_bytes(take(bytes)?) as usize;
+    if bytes.len() < len {
+        return Err(new_error!("Serialized instance state is truncated"));
+    }
+    let (value, rest) = bytes.split_at(len);
+    *bytes = rest;
+    Ok(value)
+}
+
+fn push_bytes(bytes: &mut Vec<u8>, value: &[u8]) {
+    bytes.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(value);
+}
+
+impl InstanceState {
+    /// Keep `chunk`, read from `offset`, if it is not all zeroes
+    pub(crate) fn push_chunk(&mut self, offset: u64, chunk: Vec<u8>) {
+        if chunk.iter().any(|b| *b != 0) {
+            self.chunks.push((offset, chunk));
+        }
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        push_bytes(
+            &mut bytes,
+            self.module_hash.as_deref().unwrap_or_default().as_bytes(),
+        );
+        push_bytes(&mut bytes, &self.layout.to_bytes());
+        bytes.extend_from_slice(&(self.chunks.len() as u32).to_le_bytes());
+        for (offset, chunk) in &self.chunks {
+            bytes.extend_from_slice(&offset.to_le_bytes());
+            push_bytes(&mut bytes, chunk);
+        }
+        bytes
+    }
+
+    pub(crate) fn from_bytes(mut bytes: &[u8]) -> Result<Self> {
+        if take::<4>(&mut bytes)? != *MAGIC {
+            return Err(new_error!("Not a serialized instance state"));
+        }
+        let version = u32::from_le_bytes(take(&mut bytes)?);
+        if version != VERSION {
+            return Err(new_error!(
+                "Unsupported serialized instance state version {}",
+                version
+            ));
+        }
+        let module_hash = String::from_utf8(take_bytes(&mut bytes)?.to_vec())
+            .map_err(|_| new_error!("Serialized instance state has an invalid module hash"))?;
+        let layout = InstanceLayout::from_bytes(take_bytes(&mut bytes)?)
+            .ok_or_else(|| new_error!("Serialized instance state has an invalid layout"))?;
+        let count = u32::from_le_bytes(take(&mut bytes)?);
+        let chunks = (0..count)
+            .map(|_| {
+                let offset = u64::from_le_bytes(take(&mut bytes)?);
+                let chunk = take_bytes(&mut bytes)?;
+                let in_bounds = offset
+                    .checked_add(chunk.len() as u64)
+                    .is_some_and(|end| end <= layout.memory_size);
+                if chunk.len() > CHUNK_SIZE || !in_bounds {
+                    return Err(new_error!(
+                        "Serialized instance state has an invalid chunk at {}",
+                        offset
+                    ));
+                }
+                Ok((offset, chunk.to_vec()))
+            })
+            .collect::<Result<_>>()?;
+        if !bytes.is_empty() {
+            return Err(new_error!("Serialized instance state has trailing bytes"));
+        }
+        Ok(Self {
+            module_hash: (!module_hash.is_empty()).then_some(module_hash),
+            layout,
+            chunks,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyperlight_wasm_runtime::instance_state::{GlobalValue, InstanceLayout};
+
+    use super::InstanceState;
+
+    #[test]
+    fn test_instance_state_round_trip() {
+        let mut state = InstanceState {
+            module_hash: Some("abc".to_string()),
+            layout: InstanceLayout {
+                memory_size: 65536,
+                globals: vec![
+                    ("counter".to_string(), GlobalValue::I32(-3)),
+                    ("scale".to_string(), GlobalValue::F64(1.5f64.to_bits())),
+                ],
+            },
+            chunks: Vec::new(),
+        };
+        state.push_chunk(0, vec![0; 16]);
+        state.push_chunk(16, vec![0, 1, 0]);
+        assert_eq!(state.chunks, vec![(16, vec![0, 1, 0])]);
+
+        let bytes = state.to_bytes();
+        assert_eq!(InstanceState::from_bytes(&bytes).unwrap(), state);
+        assert!(InstanceState::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(InstanceState::from_bytes(b"HLWX").is_err());
+
+        // Chunks must lie within the memory
+        state.chunks = vec![(65535, vec![1, 2])];
+        assert!(InstanceState::from_bytes(&state.to_bytes()).is_err());
+    }
+}
@@ -15,17 +15,104 @@ limitations under the License.
 */
 
 use std::fmt::Debug;
+use std::path::Path;
+#[cfg(feature = "crashdump")]
+use std::path::PathBuf;
 use std::sync::Arc;
+#[cfg(feature = "crashdump")]
+use std::time::SystemTime;
+use std::time::{Duration, Instant};
 
-use hyperlight_host::func::{ParameterTuple, SupportedReturnType};
+use hyperlight_common::flatbuffer_wrappers::function_call::{FunctionCall, FunctionCallType};
+use hyperlight_host::func::{HostFunction, ParameterTuple, ReturnValue, SupportedReturnType};
 use hyperlight_host::hypervisor::InterruptHandle;
+#[cfg(target_os = "linux")]
+use hyperlight_host::mem::memory_region::{MemoryRegion, MemoryRegionFlags, MemoryRegionType};
 use hyperlight_host::sandbox::Callable;
 use hyperlight_host::sandbox::snapshot::Snapshot;
-use hyperlight_host::{MultiUseSandbox, Result, log_then_return, new_error};
+use hyperlight_host::{HyperlightError, MultiUseSandbox, Result, log_then_return, new_error};
 
+use hyperlight_wasm_runtime::batch::{self, CALL_BATCH_FUNCTION};
+use hyperlight_wasm_runtime::call_stats::CallStats;
+use hyperlight_wasm_runtime::component_value::{self, CALL_COMPONENT_FUNCTION, ComponentValue};
+use hyperlight_wasm_runtime::guest_functions::{self, GuestFunction};
+use hyperlight_wasm_runtime::guest_log::GuestLogs;
+use hyperlight_wasm_runtime::instance_state::{
+    CHUNK_SIZE, GET_INSTANCE_LAYOUT_FUNCTION, InstanceLayout, READ_INSTANCE_MEMORY_FUNCTION,
+    RESET_INSTANCE_FUNCTION, WRITE_INSTANCE_MEMORY_FUNCTION,
+};
+use hyperlight_wasm_runtime::payload_cipher::{
+    CLEAR_PAYLOAD_KEY_FUNCTION, SET_PAYLOAD_KEY_FUNCTION,
+};
+use hyperlight_wasm_runtime::runtime_config::EPOCH_DEADLINE_EXCEEDED;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use tracing::instrument;
+
+use super::call_budget::CallBudgetExceeded;
+use super::call_hooks::CallRecord;
+use super::call_outcome::CallOutcome;
+use super::call_report::CallReport;
+use super::call_timeout::{Expiry, GuestCallTimeout, Watchdog};
+use super::callbacks::{self, Callback};
+use super::cancellation::{CancelWatcher, CancellationToken, GuestCallCancelled};
+use super::epoch_deadline::{self, EpochDeadlineExceeded};
+use super::exit_status::ExitStatus;
+use super::guest_abi::GuestAbi;
+use super::guest_aborted::GuestAborted;
+use super::guest_trap::GuestTrap;
+use super::host_function_cache::HostFunctionCacheExpiry;
+use super::host_function_failed::HostFunctionFailed;
+use super::instance_state::InstanceState;
+use super::mapped_buffers::{BufferAddrs, GuestAddr};
+use super::memory_stats::MemoryStats;
 use super::metrics::METRIC_TOTAL_LOADED_WASM_SANDBOXES;
-use super::wasm_sandbox::WasmSandbox;
-use crate::sandbox::metrics::{METRIC_ACTIVE_LOADED_WASM_SANDBOXES, METRIC_SANDBOX_UNLOADS};
+use super::module_usage::{self, CpuTimer};
+use super::panic_policy::CatchPanics;
+use super::provenance::Provenance;
+use super::required_exports;
+use super::sandbox_context::SandboxContext;
+use super::stack_exhausted::StackExhausted;
+use super::staged_params;
+use super::state_cells::{StateCellValue, StateCells};
+use super::streaming::StreamedCall;
+#[cfg(feature = "crashdump")]
+use super::wasm_crashdump::WasmCrashContext;
+use super::wasm_limits::WasmLimitExceeded;
+use super::wasm_sandbox::{self, WasmSandbox};
+#[cfg(feature = "aot")]
+use crate::aot::AotCache;
+use crate::sandbox::metrics::{
+    METRIC_ACTIVE_LOADED_WASM_SANDBOXES, METRIC_FORCED_TERMINATIONS,
+    METRIC_GUEST_FUNCTION_CALL_DURATION, METRIC_GUEST_FUNCTION_CALL_ERRORS,
+    METRIC_GUEST_FUNCTION_CALLS, METRIC_GUEST_FUNCTION_HOST_CALLS,
+    METRIC_GUEST_FUNCTION_LABEL_NAME, METRIC_MODULE_UPGRADE_ROLLBACKS, METRIC_MODULE_UPGRADES,
+    METRIC_SANDBOX_UNLOADS,
+};
+
+/// The target of the records written by guests
+const GUEST_LOG_TARGET: &str = "hyperlight_wasm::guest";
+
+/// How long dropping a sandbox in the middle of a guest call waits for
+/// the call to be interrupted
+const FORCED_TERMINATION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The function modules export their state from when they are upgraded,
+/// see [`LoadedWasmSandbox::upgrade_module_from_buffer`]
+const EXPORT_STATE_FUNCTION: &str = "export_state";
+
+/// The function upgraded modules import their predecessor's state with
+const IMPORT_STATE_FUNCTION: &str = "import_state";
+
+/// The guest function that sets the epoch deadline of guest calls, in
+/// time stamp counter ticks
+const SET_EPOCH_DEADLINE_FUNCTION: &str = "SetEpochDeadline";
+
+/// The guest functions that let modules access buffers mapped into the
+/// guest, and stop them
+#[cfg(target_os = "linux")]
+const MAP_BUFFER_FUNCTION: &str = "MapBuffer";
+const UNMAP_BUFFER_FUNCTION: &str = "UnmapBuffer";
 
 /// A sandbox that has both a Wasm engine and an arbitrary Wasm module
 /// loaded into memory.
@@ -36,6 +123,13 @@ use crate::sandbox::metrics::{METRIC_ACTIVE_LOADED_WASM_SANDBOXES, METRIC_SANDBO
 /// memory context. If you want to "reset" the memory context, create
 /// a new `LoadedWasmSandbox` -- either from another `WasmSandbox` or by
 /// calling `my_loaded_wasm_sandbox.devolve()?.evolve()?`
+///
+/// Dropping a `LoadedWasmSandbox` in the middle of a guest call, for
+/// example because a host function panicked and the panic unwound
+/// through the call, interrupts the call and waits for the vCPU to stop
+/// before the sandbox's resources are released. These forced
+/// terminations are counted in the
+/// `wasm_sandbox_forced_terminations_total` metric.
 pub struct LoadedWasmSandbox {
     // inner is an Option<MultiUseSandbox> as we need to take ownership of it
     // We implement drop on the LoadedWasmSandbox to decrement the count of Sandboxes when it is dropped
@@ -43,6 +137,38 @@ pub struct LoadedWasmSandbox {
     inner: Option<MultiUseSandbox>,
     // The state the sandbox was in before loading a wasm module. Used for transitioning back to a `WasmSandbox` (unloading the wasm module).
     runtime_snapshot: Option<Arc<Snapshot>>,
+    // Settings and state kept for when the module is unloaded
+    context: SandboxContext,
+    // The WASI functions imported by the module that were stubbed
+    stubbed_wasi_imports: Vec<String>,
+    // The hash of the module, if its usage or provenance is recorded
+    module_hash: Option<String>,
+    // The provenance of the module, if it is recorded
+    provenance: Option<Provenance>,
+    // The ABI the module was loaded with, which upgrades are loaded with
+    guest_abi: GuestAbi,
+    // The names and bytes of the library modules linked before the
+    // module, which upgrades are linked with
+    linked_modules: Vec<(String, Vec<u8>)>,
+    // The only host functions the module may import, if they are
+    // restricted, which upgrades are restricted to
+    allowed_imports: Option<Vec<String>>,
+    // The deadline of each guest call, set again in the guest whenever
+    // its memory is restored
+    epoch_deadline: Option<Duration>,
+    // How the time of the most recent guest call was spent
+    last_call: Option<CallReport>,
+    // The change in the size of the module's memory during the most
+    // recent guest call, if it was fetched
+    last_mem_delta: Option<i64>,
+    // Whether a guest call is running. This is still set when the
+    // sandbox is dropped if a panic unwound through the call.
+    call_in_progress: bool,
+    // The guest addresses of the buffers mapped with map_buffer
+    buffer_addrs: BufferAddrs,
+    // The wasm context written next to core dumps of the guest
+    #[cfg(feature = "crashdump")]
+    crash_context: WasmCrashContext,
 }
 
 impl LoadedWasmSandbox {
@@ -65,15 +191,835 @@ impl LoadedWasmSandbox {
     /// from guest panics, memory violations, etc.), and the sandbox is marked
     /// as poisoned. This method then returns `PoisonedSandbox` on subsequent
     /// calls until the sandbox is recovered.
+    ///
+    /// This is the single entry point for guest calls: bindings generated
+    /// for components call it through [`Callable`], so calls to module
+    /// and component exports are traced the same way, and counted in the
+    /// same way in the `wasm_guest_function_calls_total` and
+    /// `wasm_guest_function_call_errors_total` metrics emitted with the
+    /// `function_call_metrics` feature.
+    ///
+    /// Log records buffered by the guest during the call are emitted
+    /// once it returns, see
+    /// [`SandboxBuilder::with_guest_log_level`](crate::SandboxBuilder::with_guest_log_level).
+    ///
+    /// If a host function panics during the call and the sandbox's
+    /// [`PanicPolicy`](crate::PanicPolicy) is `PoisonSandbox`, the call
+    /// returns an error holding the panic message and the sandbox is
+    /// poisoned.
+    ///
+    /// If the sandbox was built with
+    /// [`SandboxBuilder::with_guest_call_timeout`](crate::SandboxBuilder::with_guest_call_timeout),
+    /// a call that runs for longer than the timeout is interrupted, see
+    /// [`call_guest_function_with_timeout()`](Self::call_guest_function_with_timeout).
+    #[instrument(skip(self, params), level = "Trace")]
     pub fn call_guest_function<Output: SupportedReturnType>(
         &mut self,
         fn_name: &str,
         params: impl ParameterTuple,
     ) -> Result<Output> {
-        match &mut self.inner {
-            Some(inner) => inner.call(fn_name, params),
-            None => log_then_return!("No inner MultiUseSandbox to call"),
+        self.call_with_timeout(fn_name, params, self.context.call_timeout, None, false)
+    }
+
+    /// Call the function in the guest with the name `fn_name`, passing
+    /// parameters `params`, killing the call once `token` is cancelled.
+    ///
+    /// The token can be cancelled from any thread, through a clone of it
+    /// or a [`CancelOnDrop`](crate::CancelOnDrop) guard. If it is already
+    /// cancelled, the call is not started. Any timeout set with
+    /// [`SandboxBuilder::with_guest_call_timeout`](crate::SandboxBuilder::with_guest_call_timeout)
+    /// still applies.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`GuestCallCancelled`] error, wrapped in
+    /// [`HyperlightError::AnyhowError`], if the call was cancelled. If
+    /// the call had started, the sandbox is then poisoned: use
+    /// [`restore()`](Self::restore) or reload the module to recover it.
+    /// A call that completes before the kill lands returns its result,
+    /// and the sandbox is left as it would be otherwise. Otherwise,
+    /// returns the same errors as
+    /// [`call_guest_function()`](Self::call_guest_function).
+    #[instrument(skip(self, params, token), level = "Trace")]
+    pub fn call_guest_function_cancellable<Output: SupportedReturnType>(
+        &mut self,
+        fn_name: &str,
+        params: impl ParameterTuple,
+        token: &CancellationToken,
+    ) -> Result<Output> {
+        self.call_with_timeout(
+            fn_name,
+            params,
+            self.context.call_timeout,
+            Some(token),
+            false,
+        )
+    }
+
+    /// Call the function in the guest with the name `fn_name`, passing
+    /// parameters `params`, interrupting the call if it runs for longer
+    /// than `timeout`. This overrides any timeout set with
+    /// [`SandboxBuilder::with_guest_call_timeout`](crate::SandboxBuilder::with_guest_call_timeout).
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`GuestCallTimeout`] error, wrapped in
+    /// [`HyperlightError::AnyhowError`], if the call timed out. The
+    /// sandbox is then poisoned: use [`restore()`](Self::restore) or
+    /// reload the module to recover it. Otherwise, returns the same
+    /// errors as [`call_guest_function()`](Self::call_guest_function).
+    #[instrument(skip(self, params), level = "Trace")]
+    pub fn call_guest_function_with_timeout<Output: SupportedReturnType>(
+        &mut self,
+        fn_name: &str,
+        params: impl ParameterTuple,
+        timeout: Duration,
+    ) -> Result<Output> {
+        self.call_with_timeout(fn_name, params, Some(timeout), None, false)
+    }
+
+    fn call_with_timeout<Output: SupportedReturnType>(
+        &mut self,
+        fn_name: &str,
+        params: impl ParameterTuple,
+        timeout: Option<Duration>,
+        cancel: Option<&CancellationToken>,
+        fetch_mem_delta: bool,
+    ) -> Result<Output> {
+        let start = Instant::now();
+        let result = self.context.call_hooks.call_start(fn_name).and_then(|()| {
+            self.call_without_hooks(fn_name, params, timeout, cancel, fetch_mem_delta)
+        });
+        self.context.call_hooks.call_end(&CallRecord {
+            function_name: fn_name,
+            duration: start.elapsed(),
+            error: result.as_ref().err(),
+        });
+        result
+    }
+
+    fn call_without_hooks<Output: SupportedReturnType>(
+        &mut self,
+        fn_name: &str,
+        params: impl ParameterTuple,
+        timeout: Option<Duration>,
+        cancel: Option<&CancellationToken>,
+        fetch_mem_delta: bool,
+    ) -> Result<Output> {
+        self.last_mem_delta = None;
+        if cfg!(feature = "function_call_metrics") {
+            metrics::counter!(METRIC_GUEST_FUNCTION_CALLS, METRIC_GUEST_FUNCTION_LABEL_NAME => fn_name.to_string()).increment(1);
+        }
+        let result = match &mut self.inner {
+            Some(_) if self.context.panic_handler.poison().is_some() => {
+                Err(HyperlightError::PoisonedSandbox)
+            }
+            Some(_) if cancel.is_some_and(CancellationToken::is_cancelled) => {
+                Err(GuestCallCancelled {
+                    function_name: fn_name.to_string(),
+                    started: false,
+                }
+                .into())
+            }
+            Some(inner) => {
+                let timer = CpuTimer::start();
+                let start = Instant::now();
+                #[cfg(feature = "crashdump")]
+                let crash_since = SystemTime::now();
+                self.call_in_progress = true;
+                let result = flush_expired_host_function_results(
+                    inner,
+                    &mut self.context.host_function_cache,
+                )
+                .and_then(|()| {
+                    let host_clock = &self.context.host_clock;
+                    host_clock.reset();
+                    self.context.host_errors.clear();
+                    self.context.reentrant_calls.clear();
+                    let watchdog = Watchdog::start(
+                        inner.interrupt_handle(),
+                        timeout,
+                        self.context.guest_time_budget,
+                        host_clock,
+                    );
+                    let watcher =
+                        cancel.map(|token| CancelWatcher::start(token, inner.interrupt_handle()));
+                    // The spans the runtime emits with the trace_guest
+                    // feature are children of the span current when the
+                    // guest is entered
+                    let span = tracing::info_span!(
+                        "guest_call",
+                        function_name = fn_name,
+                        sandbox_id = self.context.sandbox_id
+                    );
+                    let result = span.in_scope(|| {
+                        let result = match &self.context.payload_key {
+                            Some(key) => key.call(inner, fn_name, params),
+                            None => inner.call(fn_name, params),
+                        };
+                        // Fetched before the watchdog stops, so that it
+                        // still bounds the extra VM entry
+                        if fetch_mem_delta && result.is_ok() {
+                            let stats =
+                                inner
+                                    .call::<Vec<u8>>("GetCallStats", ())
+                                    .and_then(|bytes| {
+                                        CallStats::from_bytes(&bytes).ok_or_else(|| {
+                                            new_error!("guest call stats are malformed")
+                                        })
+                                    })?;
+                            self.last_mem_delta = stats.memory_delta;
+                        }
+                        result
+                    });
+                    let expiry = watchdog.and_then(Watchdog::stop);
+                    let cancelled = watcher.is_some_and(CancelWatcher::stop);
+                    let function_name = fn_name.to_string();
+                    match (result, expiry) {
+                        (Err(HyperlightError::ExecutionCanceledByHost()), _) if cancelled => {
+                            Err(GuestCallCancelled {
+                                function_name,
+                                started: true,
+                            }
+                            .into())
+                        }
+                        (
+                            Err(HyperlightError::ExecutionCanceledByHost()),
+                            Some(Expiry::Timeout(timeout)),
+                        ) => Err(GuestCallTimeout {
+                            function_name,
+                            timeout,
+                        }
+                        .into()),
+                        (
+                            Err(HyperlightError::ExecutionCanceledByHost()),
+                            Some(Expiry::GuestTime(budget)),
+                        ) => Err(CallBudgetExceeded::GuestTime {
+                            function_name,
+                            budget,
+                        }
+                        .into()),
+                        (Err(HyperlightError::GuestError(_, message)), _)
+                            if message == EPOCH_DEADLINE_EXCEEDED =>
+                        {
+                            Err(EpochDeadlineExceeded {
+                                function_name,
+                                deadline: self.epoch_deadline.unwrap_or_default(),
+                            }
+                            .into())
+                        }
+                        // Checked whatever the result, since the guest may
+                        // have handled the error the host function returned,
+                        // and before the host function failures it causes
+                        (_, _) if host_clock.exceeded() => Err(CallBudgetExceeded::HostTime {
+                            function_name,
+                            budget: host_clock.budget().unwrap_or_default(),
+                        }
+                        .into()),
+                        (Err(HyperlightError::GuestError(code, message)), _) => {
+                            if let Some(exceeded) = WasmLimitExceeded::from_guest_error(
+                                &message,
+                                fn_name,
+                                &self.context,
+                            ) {
+                                Err(exceeded.into())
+                            } else if let Some(failed) = HostFunctionFailed::from_guest_error(
+                                &message,
+                                fn_name,
+                                &self.context.host_errors,
+                            ) {
+                                Err(failed.into())
+                            } else if let Some(exhausted) =
+                                StackExhausted::from_guest_error(&message, fn_name, &self.context)
+                            {
+                                Err(exhausted.into())
+                            } else if let Some(aborted) =
+                                GuestAborted::from_guest_error(&message, fn_name)
+                            {
+                                Err(aborted.into())
+                            } else if let Some(trap) =
+                                GuestTrap::from_guest_error(&message, fn_name)
+                            {
+                                Err(trap.into())
+                            } else {
+                                Err(HyperlightError::GuestError(code, message))
+                            }
+                        }
+                        (result, _) => result,
+                    }
+                });
+                self.call_in_progress = false;
+                #[cfg(feature = "crashdump")]
+                if self.context.crashdump
+                    && let Err(error) = &result
+                {
+                    self.crash_context.record_failed_call(
+                        inner,
+                        self.module_hash.as_deref(),
+                        fn_name,
+                        error,
+                        crash_since,
+                    );
+                }
+                let duration = start.elapsed();
+                let cpu_time = timer.elapsed();
+                if cfg!(feature = "function_call_metrics") {
+                    metrics::histogram!(METRIC_GUEST_FUNCTION_CALL_DURATION, METRIC_GUEST_FUNCTION_LABEL_NAME => fn_name.to_string())
+                        .record(duration.as_secs_f64());
+                    metrics::counter!(METRIC_GUEST_FUNCTION_HOST_CALLS, METRIC_GUEST_FUNCTION_LABEL_NAME => fn_name.to_string())
+                        .increment(self.context.host_clock.calls());
+                }
+                // The guest may have handled the error returned by the
+                // host function, so report the panic whatever the result
+                let result = match self.context.panic_handler.poison() {
+                    Some(error) => Err(new_error!("{}", error)),
+                    None => result,
+                };
+                let host_clock = &self.context.host_clock;
+                self.last_call = Some(CallReport {
+                    function_name: fn_name.to_string(),
+                    duration,
+                    guest_cpu_time: cpu_time.saturating_sub(host_clock.cpu_spent()),
+                    host_time: host_clock.spent(),
+                    host_calls: host_clock.calls(),
+                    succeeded: result.is_ok(),
+                });
+                if self.context.usage_accounting
+                    && let Some(module_hash) = &self.module_hash
+                {
+                    record_usage(inner, module_hash, cpu_time);
+                }
+                // A poisoned sandbox cannot be called until it is restored,
+                // so the memory it used is not known
+                if self.context.memory.metrics_label.is_some() && !inner.poisoned() {
+                    let _ = self.context.memory.fetch(inner);
+                }
+                // A poisoned sandbox cannot be called until it is restored, so
+                // its logs are lost
+                if self.context.drain_guest_logs && !inner.poisoned() {
+                    drain_guest_logs(inner, self.context.sandbox_id);
+                }
+                result
+            }
+            None => Err(new_error!("No inner MultiUseSandbox to call")),
+        };
+        if let Err(e) = &result {
+            if cfg!(feature = "function_call_metrics") {
+                metrics::counter!(METRIC_GUEST_FUNCTION_CALL_ERRORS, METRIC_GUEST_FUNCTION_LABEL_NAME => fn_name.to_string()).increment(1);
+            }
+            tracing::debug!("guest function {fn_name} failed: {e:?}");
+        }
+        result
+    }
+
+    /// Call the function in the guest with the name `fn_name`, passing
+    /// parameters `params`, without blocking the async executor.
+    ///
+    /// A vCPU runs on the thread that enters it, so the call runs on
+    /// tokio's blocking thread pool while the returned future waits for
+    /// it. Otherwise it behaves as
+    /// [`call_guest_function()`](Self::call_guest_function). This must be
+    /// called from within a tokio runtime.
+    ///
+    /// Dropping the future before the call returns, for example because
+    /// it was wrapped in `tokio::time::timeout`, kills the call through
+    /// the sandbox's [`InterruptHandle`]. The sandbox then stays on the
+    /// blocking thread and is dropped there, so further calls to `self`
+    /// return an error.
+    ///
+    /// Requires the `async` feature.
+    #[cfg(feature = "async")]
+    pub async fn call_guest_function_async<Output>(
+        &mut self,
+        fn_name: &str,
+        params: impl ParameterTuple + 'static,
+    ) -> Result<Output>
+    where
+        Output: SupportedReturnType + Send + 'static,
+    {
+        let kill_on_drop = KillOnDrop(Some(self.interrupt_handle()?));
+        let fn_name = fn_name.to_string();
+        let result = self
+            .call_blocking(move |sandbox| sandbox.call_guest_function(&fn_name, params))
+            .await;
+        kill_on_drop.disarm();
+        result
+    }
+
+    /// Call the function in the guest with the name `fn_name`, passing
+    /// parameters `params`, without blocking the async executor, killing
+    /// the call once `token` is cancelled.
+    ///
+    /// This combines
+    /// [`call_guest_function_async()`](Self::call_guest_function_async)
+    /// and
+    /// [`call_guest_function_cancellable()`](Self::call_guest_function_cancellable):
+    /// dropping the future before the call returns cancels `token`, so
+    /// that other calls sharing it are cancelled too, and the call
+    /// returns a [`GuestCallCancelled`] error on the blocking thread.
+    ///
+    /// Requires the `async` feature.
+    #[cfg(feature = "async")]
+    pub async fn call_guest_function_cancellable_async<Output>(
+        &mut self,
+        fn_name: &str,
+        params: impl ParameterTuple + 'static,
+        token: &CancellationToken,
+    ) -> Result<Output>
+    where
+        Output: SupportedReturnType + Send + 'static,
+    {
+        let cancel_on_drop = token.clone().drop_guard();
+        let fn_name = fn_name.to_string();
+        let token = token.clone();
+        let result = self
+            .call_blocking(move |sandbox| {
+                sandbox.call_guest_function_cancellable(&fn_name, params, &token)
+            })
+            .await;
+        cancel_on_drop.disarm();
+        result
+    }
+
+    // Run `call` with the sandbox on tokio's blocking thread pool. If
+    // the returned future is dropped, the sandbox stays on the blocking
+    // thread and `self` is left detached.
+    #[cfg(feature = "async")]
+    async fn call_blocking<Output, F>(&mut self, call: F) -> Result<Output>
+    where
+        Output: Send + 'static,
+        F: FnOnce(&mut Self) -> Result<Output> + Send + 'static,
+    {
+        let mut sandbox = std::mem::replace(self, Self::detached());
+        let joined = tokio::task::spawn_blocking(move || {
+            let result = call(&mut sandbox);
+            (sandbox, result)
+        })
+        .await;
+        match joined {
+            Ok((sandbox, result)) => {
+                *self = sandbox;
+                result
+            }
+            Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+            Err(e) => Err(new_error!("guest call task failed: {}", e)),
+        }
+    }
+
+    // An empty sandbox standing in for one whose call is running on
+    // another thread
+    #[cfg(feature = "async")]
+    fn detached() -> Self {
+        // Balances the decrement when it is dropped
+        metrics::gauge!(METRIC_ACTIVE_LOADED_WASM_SANDBOXES).increment(1);
+        LoadedWasmSandbox {
+            inner: None,
+            runtime_snapshot: None,
+            context: SandboxContext::default(),
+            stubbed_wasi_imports: Vec::new(),
+            module_hash: None,
+            provenance: None,
+            guest_abi: GuestAbi::default(),
+            linked_modules: Vec::new(),
+            allowed_imports: None,
+            epoch_deadline: None,
+            last_call: None,
+            last_mem_delta: None,
+            call_in_progress: false,
+            buffer_addrs: BufferAddrs::default(),
+            #[cfg(feature = "crashdump")]
+            crash_context: WasmCrashContext::default(),
+        }
+    }
+
+    /// Call the function in the guest with the name `fn_name`, passing
+    /// `params` and returning its result as JSON. The function must be
+    /// exported with `#[hyperlight_export(json)]` from the guest SDK.
+    ///
+    /// `params` must serialize to a JSON array holding the function's
+    /// parameters in order, so it is usually a tuple, `()` for a function
+    /// without parameters or `(value,)` for a function with one. The
+    /// encoded parameters must fit in the sandbox's input buffer, and the
+    /// encoded result in its output buffer. Otherwise it behaves as
+    /// [`call_guest_function()`](Self::call_guest_function).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `params` cannot be encoded or the result cannot
+    /// be decoded as `Output`, as well as the errors returned by
+    /// [`call_guest_function()`](Self::call_guest_function). The guest
+    /// traps if it cannot decode the parameters.
+    pub fn call_guest_function_json<Output: DeserializeOwned>(
+        &mut self,
+        fn_name: &str,
+        params: &impl Serialize,
+    ) -> Result<Output> {
+        let params = serde_json::to_vec(params)
+            .map_err(|e| new_error!("parameters of {} cannot be encoded: {}", fn_name, e))?;
+        let len = params.len() as i32;
+        let result: Vec<u8> = self.call_guest_function(fn_name, (params, len))?;
+        serde_json::from_slice(&result)
+            .map_err(|e| new_error!("result of {} cannot be decoded: {}", fn_name, e))
+    }
+
+    /// Call the function in the guest with the name `fn_name`, passing
+    /// parameters `params`, and return its result together with telemetry
+    /// about the call, such as how long it took and how many host
+    /// functions it called, see [`CallOutcome`].
+    ///
+    /// The timings and host call count are those of
+    /// [`last_call_stats()`](Self::last_call_stats). This costs one more
+    /// VM entry than [`call_guest_function()`](Self::call_guest_function),
+    /// to fetch the change in the size of the module's memory from the
+    /// guest, which is bounded by the same timeout as the call.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from the call if it failed, or an error if the
+    /// change in the size of the module's memory could not be fetched.
+    pub fn call_guest_function_detailed<Output: SupportedReturnType>(
+        &mut self,
+        fn_name: &str,
+        params: impl ParameterTuple,
+    ) -> Result<CallOutcome<Output>> {
+        let value =
+            self.call_with_timeout(fn_name, params, self.context.call_timeout, None, true)?;
+        let report = match &self.last_call {
+            Some(report) => report,
+            None => {
+                log_then_return!("No report of the call to {}", fn_name);
+            }
+        };
+        Ok(CallOutcome {
+            value,
+            duration: report.duration,
+            host_time: report.host_time,
+            host_calls: report.host_calls,
+            guest_mem_delta: self.last_mem_delta,
+        })
+    }
+
+    /// How the time of the most recent guest call was spent, including
+    /// the CPU time the guest used, or `None` if no guest function has
+    /// been called since the module was loaded. Calls refused by an
+    /// [`on_call_start`](Self::on_call_start) hook, or made while the
+    /// sandbox was poisoned, are not reported.
+    pub fn last_call_stats(&self) -> Option<&CallReport> {
+        self.last_call.as_ref()
+    }
+
+    /// Call the function in the guest with the name `fn_name`, passing
+    /// parameters `params`, and return the chunks of output it emitted
+    /// together with the value it returned, see [`StreamedCall`].
+    ///
+    /// This lets guest functions produce outputs larger than the
+    /// sandbox's output buffer. Modules emit each chunk by calling the
+    /// `EmitChunk` host function, which takes a buffer and its length and
+    /// is provided unless the host registers its own; the guest SDK wraps
+    /// it in `hyperlight_wasm_guest_sdk::emit_chunk`. Chunks are kept on
+    /// the host until the call returns. Chunks emitted during other calls
+    /// are discarded.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from the call if it failed, in which case the
+    /// chunks emitted are discarded.
+    pub fn call_guest_function_streaming<Output: SupportedReturnType>(
+        &mut self,
+        fn_name: &str,
+        params: impl ParameterTuple,
+    ) -> Result<StreamedCall<Output>> {
+        self.context.chunks.start()?;
+        let result = self.call_guest_function(fn_name, params);
+        let chunks = self.context.chunks.finish();
+        Ok(StreamedCall::new(result?, chunks))
+    }
+
+    /// Call the function in the guest with the name `fn_name`, passing
+    /// parameters `params`, copying `Vec<u8>` parameters larger than the
+    /// sandbox's input buffer into the guest in chunks before the call.
+    ///
+    /// This lets hosts pass multi-megabyte buffers without building the
+    /// sandbox with a large input buffer. Each chunk is a separate guest
+    /// call, and the guest holds the whole buffer on its heap before
+    /// copying it into the module's memory, so the sandbox's heap must be
+    /// large enough to hold the buffers, see
+    /// [`SandboxBuilder::with_guest_heap_size`](crate::SandboxBuilder::with_guest_heap_size).
+    /// Otherwise it behaves as
+    /// [`call_guest_function()`](Self::call_guest_function).
+    ///
+    /// Components are not supported.
+    pub fn call_guest_function_chunked<Output: SupportedReturnType, P: ParameterTuple>(
+        &mut self,
+        fn_name: &str,
+        params: P,
+    ) -> Result<Output> {
+        let Some(inner) = &mut self.inner else {
+            log_then_return!("No inner MultiUseSandbox to call");
+        };
+        // Staged buffers are not encrypted by the call, so encrypt them
+        // before they are split into chunks
+        let values = match &self.context.payload_key {
+            Some(key) => key.encrypt_params(params)?.into_value(),
+            None => params.into_value(),
+        };
+        let params = staged_params::stage::<P>(inner, values)?;
+        self.call_guest_function(fn_name, params)
+    }
+
+    /// Call the functions in the guest named in `calls`, each with its
+    /// parameters, one after another in a single guest call, and return
+    /// their results in order.
+    ///
+    /// Each call to [`call_guest_function()`](Self::call_guest_function)
+    /// enters and exits the VM, which dominates the cost of small calls
+    /// such as per-record transforms; a batch pays that cost once. The
+    /// encoded calls must fit in the sandbox's input buffer and their
+    /// results in its output buffer. The batch is a single guest call
+    /// for timeouts, deadlines and metrics, in which it is named
+    /// `CallBatch`. Components are not supported.
+    ///
+    /// # Errors
+    ///
+    /// If a call fails, the calls after it are not run, the changes made
+    /// by the calls before it are kept and its error is returned, naming
+    /// `CallBatch` as the function that failed. Otherwise, returns the
+    /// same errors as [`call_guest_function()`](Self::call_guest_function).
+    pub fn call_batch<Output: SupportedReturnType, P: ParameterTuple + Clone>(
+        &mut self,
+        calls: &[(&str, P)],
+    ) -> Result<Vec<Output>> {
+        let calls = calls
+            .iter()
+            .map(|(fn_name, params)| {
+                let values = match &self.context.payload_key {
+                    Some(key) => key.encrypt_params(params.clone())?.into_value(),
+                    None => params.clone().into_value(),
+                };
+                Ok(FunctionCall::new(
+                    fn_name.to_string(),
+                    Some(values),
+                    FunctionCallType::Guest,
+                    Output::TYPE,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let bytes: Vec<u8> =
+            self.call_guest_function(CALL_BATCH_FUNCTION, batch::calls_to_bytes(&calls))?;
+        let results = batch::results_from_bytes(&bytes)
+            .filter(|results| results.len() == calls.len())
+            .ok_or_else(|| new_error!("guest batch results are malformed"))?;
+        results
+            .into_iter()
+            .map(|result| {
+                // Decrypted before it is converted, as in PayloadKey::call
+                let result = match (&self.context.payload_key, result) {
+                    (Some(key), ReturnValue::VecBytes(sealed)) => {
+                        ReturnValue::VecBytes(key.decrypt_result(sealed)?)
+                    }
+                    (_, result) => result,
+                };
+                Output::from_value(result)
+                    .map_err(|e| new_error!("Failed to convert batch result: {}", e))
+            })
+            .collect()
+    }
+
+    /// Call the function named `function`, in the interface named
+    /// `interface` or, if it is `None`, at the root of the component's
+    /// world, exported by a component loaded with
+    /// [`WasmSandbox::load_component`], passing it `args` and returning
+    /// its results.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the component does not export the function,
+    /// if `args` do not match its parameters, or if the call fails.
+    pub fn call_component_function(
+        &mut self,
+        interface: Option<&str>,
+        function: &str,
+        args: &[ComponentValue],
+    ) -> Result<Vec<ComponentValue>> {
+        let name = match interface {
+            Some(interface) => format!("{interface}#{function}"),
+            None => function.to_string(),
+        };
+        let bytes: Vec<u8> = self.call_guest_function(
+            CALL_COMPONENT_FUNCTION,
+            (name, component_value::to_bytes(args)),
+        )?;
+        component_value::from_bytes(&bytes)
+            .ok_or_else(|| new_error!("component function results are malformed"))
+    }
+
+    /// Run a module built as a WASI command by calling its `_start`
+    /// function, returning the status it exited with.
+    ///
+    /// The module exits with the code it passes to the WASI `proc_exit`
+    /// function, which wasi-libc calls when `main` returns a non-zero
+    /// code, or with 0 if `_start` returns. Set the command's arguments
+    /// and environment variables with
+    /// [`SandboxBuilder::with_args`](crate::SandboxBuilder::with_args) and
+    /// [`SandboxBuilder::with_env`](crate::SandboxBuilder::with_env).
+    ///
+    /// Commands built with wasi-libc expect `_start` to be called once, so
+    /// take a [`snapshot()`](Self::snapshot) before running one and
+    /// [`restore()`](Self::restore) it to run it again. Components are not
+    /// supported.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the module does not export `_start`, or if
+    /// `_start` failed without exiting, for example with a [`GuestAborted`]
+    /// error without an exit code if it panicked. Otherwise, returns the
+    /// same errors as [`call_guest_function()`](Self::call_guest_function).
+    pub fn run_start(&mut self) -> Result<ExitStatus> {
+        match self.call_guest_function::<()>("_start", ()) {
+            Ok(()) => Ok(ExitStatus::new(0)),
+            Err(e) => match GuestAborted::from_error(&e).and_then(|aborted| aborted.exit_code) {
+                Some(code) => Ok(ExitStatus::new(code)),
+                None => Err(e),
+            },
+        }
+    }
+
+    /// Call the function in the guest with the name `fn_name`, passing
+    /// parameters `params`, and then discard any changes the call made to
+    /// the sandbox's state.
+    ///
+    /// This guarantees that calls which should be pure queries, such as
+    /// health probes or speculative calls against stateful guests, cannot
+    /// affect later calls. The sandbox is snapshotted before the call and
+    /// restored afterwards, so this is more expensive than
+    /// [`call_guest_function()`](Self::call_guest_function). Because the
+    /// sandbox is restored even if the call fails, a readonly call never
+    /// leaves the sandbox poisoned.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from the call if it failed, or the error from
+    /// restoring the sandbox if that failed.
+    pub fn call_guest_function_readonly<Output: SupportedReturnType>(
+        &mut self,
+        fn_name: &str,
+        params: impl ParameterTuple,
+    ) -> Result<Output> {
+        let snapshot = self.snapshot()?;
+        let result = self.call_guest_function(fn_name, params);
+        self.restore(snapshot)?;
+        result
+    }
+
+    /// Call the function in the guest with the name `fn_name`, passing
+    /// parameters `params`, and discard any changes the call made to the
+    /// sandbox's state if it fails.
+    ///
+    /// This gives calls transactional semantics: a call that traps, is
+    /// interrupted or times out leaves the sandbox as it was before the
+    /// call, and not poisoned, while a call that succeeds keeps its
+    /// changes. The sandbox is snapshotted before each call, so this is
+    /// more expensive than
+    /// [`call_guest_function()`](Self::call_guest_function).
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from the call if it failed, once the sandbox has
+    /// been restored, or the error from restoring the sandbox if that
+    /// failed.
+    pub fn call_guest_function_transactional<Output: SupportedReturnType>(
+        &mut self,
+        fn_name: &str,
+        params: impl ParameterTuple,
+    ) -> Result<Output> {
+        let snapshot = self.snapshot()?;
+        let result = self.call_guest_function(fn_name, params);
+        if result.is_err() {
+            self.restore(snapshot)?;
+        }
+        result
+    }
+
+    /// Call the function in the guest with the name `fn_name`, passing
+    /// parameters `params`, with each of `callbacks` replacing the host
+    /// function of the same name for the duration of the call, see
+    /// [`call_guest_function()`](Self::call_guest_function).
+    ///
+    /// This passes per-call state, such as a progress reporter, to the
+    /// host functions the guest calls. The replaced host functions are
+    /// registered again once the call returns, whether it succeeded or
+    /// not.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, without calling the guest, if a callback does
+    /// not replace a host function registered with
+    /// [`ProtoWasmSandbox::register`](crate::ProtoWasmSandbox::register)
+    /// or [`register`](Self::register) with the same parameter and return
+    /// types. Otherwise, returns the same errors as
+    /// [`call_guest_function()`](Self::call_guest_function).
+    pub fn call_guest_function_with_callbacks<Output: SupportedReturnType>(
+        &mut self,
+        fn_name: &str,
+        params: impl ParameterTuple,
+        callbacks: &[Callback],
+    ) -> Result<Output> {
+        self.context.host_functions.check(callbacks)?;
+        let inner = self
+            .inner
+            .as_mut()
+            .ok_or_else(|| new_error!("No inner MultiUseSandbox to register callbacks with"))?;
+        let result = callbacks::register(&self.context, inner, callbacks)
+            .and_then(|()| self.call_guest_function(fn_name, params));
+        let inner = self
+            .inner
+            .as_mut()
+            .ok_or_else(|| new_error!("No inner MultiUseSandbox to restore host functions of"))?;
+        let restored = self.context.host_functions.restore(inner, callbacks);
+        result.and_then(|output| restored.map(|()| output))
+    }
+
+    /// Ask the guest to run a garbage collection, for example between
+    /// requests, returning whether it did.
+    ///
+    /// Managed-language guests opt in by exporting a function taking no
+    /// parameters and returning nothing, named `__hlwasm_gc` for modules or
+    /// `hlwasm-gc` for components. AssemblyScript modules built with
+    /// `--exportRuntime` are collected with `__collect` if they do not
+    /// export `__hlwasm_gc`. This does nothing for guests that do not
+    /// support it.
+    pub fn hint_gc(&mut self) -> Result<bool> {
+        self.check_host_function_panic()?;
+        let inner = match &mut self.inner {
+            Some(inner) => inner,
+            None => log_then_return!("No inner MultiUseSandbox to hint gc"),
+        };
+        let collected: i32 = inner.call("HintGc", ())?;
+        Ok(collected != 0)
+    }
+
+    /// Prepare the exported functions named in `fn_names` to be called, so
+    /// that their first real call does not pay one-time costs such as
+    /// lazily initialising the function and faulting in code pages.
+    ///
+    /// The functions are not called. For modules, an error is returned if
+    /// any of them is not exported. For components, the names are not
+    /// checked and only the component's code is faulted in.
+    ///
+    /// Warming up a sandbox before taking a [`snapshot()`](Self::snapshot)
+    /// means sandboxes restored from that snapshot are also warm.
+    pub fn warm_up(&mut self, fn_names: &[&str]) -> Result<()> {
+        self.check_host_function_panic()?;
+        let inner = match &mut self.inner {
+            Some(inner) => inner,
+            None => log_then_return!("No inner MultiUseSandbox to warm up"),
+        };
+        let names = fn_names.join("\0").into_bytes();
+        let res: i32 = inner.call("WarmUp", names)?;
+        if res != 0 {
+            return Err(new_error!("WarmUp Failed with error code {:?}", res));
         }
+        Ok(())
     }
 
     /// Take a snapshot of the current state of the sandbox.
@@ -81,16 +1027,34 @@ impl LoadedWasmSandbox {
     /// The snapshot can later be used with [`restore()`](Self::restore) to
     /// return the sandbox to this state.
     ///
+    /// If the sandbox was built with
+    /// [`SandboxBuilder::with_payload_key`](crate::SandboxBuilder::with_payload_key),
+    /// the key is removed from the guest while the snapshot is taken.
+    ///
     /// # Errors
     ///
     /// Returns `Err(HyperlightError::PoisonedSandbox)` if the sandbox is in a
     /// poisoned state. Use [`restore()`](Self::restore) with a previously
     /// taken snapshot to recover before taking a new snapshot.
     pub fn snapshot(&mut self) -> Result<Arc<Snapshot>> {
-        match &mut self.inner {
-            Some(inner) => inner.snapshot(),
+        self.check_host_function_panic()?;
+        let snapshot = match &mut self.inner {
+            // Snapshots must not hold the payload key
+            Some(inner) if self.context.payload_key.is_some() => {
+                inner.call::<()>(CLEAR_PAYLOAD_KEY_FUNCTION, ())?;
+                let snapshot = inner.snapshot();
+                self.apply_payload_key()?;
+                snapshot?
+            }
+            Some(inner) => inner.snapshot()?,
             None => log_then_return!("No inner MultiUseSandbox to snapshot"),
+        };
+        // Sandboxes loaded from the snapshot record usage under the same
+        // module hash
+        if let Some(module_hash) = &self.module_hash {
+            module_usage::remember_snapshot(&snapshot, module_hash);
         }
+        Ok(snapshot)
     }
 
     /// Restore the state of the sandbox to the state captured in the given snapshot.
@@ -106,10 +1070,186 @@ impl LoadedWasmSandbox {
     /// 2. Reset memory to the snapshot state
     /// 3. Allow subsequent [`call_guest_function()`](Self::call_guest_function) calls to succeed
     pub fn restore(&mut self, snapshot: Arc<Snapshot>) -> Result<()> {
+        // The snapshot may hold cached host function results of any age
+        self.context.host_function_cache.reset();
         match &mut self.inner {
-            Some(inner) => inner.restore(snapshot),
+            Some(inner) => inner.restore(snapshot)?,
             None => log_then_return!("No inner MultiUseSandbox to restore"),
         }
+        self.context.panic_handler.clear_poison();
+        self.apply_epoch_deadline()?;
+        self.apply_payload_key()
+    }
+
+    /// Map the `len` bytes of host memory at `base` into the sandbox,
+    /// returning the guest address the loaded module accesses them at
+    /// through the `hlwasm:buffers` imports. Large datasets can be passed
+    /// this way without copying them into a guest call's parameters.
+    ///
+    /// The buffer is mapped read-only, since Hyperlight does not yet
+    /// support writable mappings, so the module's writes to it trap.
+    ///
+    /// The buffer stays mapped until the sandbox is restored to a
+    /// snapshot taken before it was mapped, or the module is unloaded.
+    /// [`unmap_buffer`](Self::unmap_buffer) only stops the module
+    /// accessing it. Depending on the host platform, there are likely
+    /// alignment requirements of at least one page for `base` and `len`.
+    ///
+    /// # Safety
+    /// It is the caller's responsibility to ensure that the host memory
+    /// remains valid, and is not written to, for as long as it is
+    /// mapped.
+    #[cfg(target_os = "linux")]
+    pub unsafe fn map_buffer(
+        &mut self,
+        base: *const libc::c_void,
+        len: usize,
+    ) -> Result<GuestAddr> {
+        let inner = self
+            .inner
+            .as_mut()
+            .ok_or_else(|| new_error!("No inner MultiUseSandbox to map a buffer into"))?;
+        let addr = self.buffer_addrs.allocate(len);
+        let guest_base = addr.as_u64() as usize;
+        let rgn = MemoryRegion {
+            host_region: base as usize..base.wrapping_add(len) as usize,
+            guest_region: guest_base..guest_base + len,
+            flags: MemoryRegionFlags::READ,
+            region_type: MemoryRegionType::Heap,
+        };
+        unsafe { inner.map_region(&rgn) }?;
+        // The last parameter tells the runtime whether the buffer is
+        // writable
+        inner.call::<()>(MAP_BUFFER_FUNCTION, (addr.as_u64(), len as u64, false))?;
+        Ok(addr)
+    }
+
+    /// Stop the loaded module accessing the buffer mapped at `addr` with
+    /// [`map_buffer`](Self::map_buffer).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no buffer is mapped at `addr`, including if
+    /// the sandbox was restored to a snapshot taken before it was mapped.
+    pub fn unmap_buffer(&mut self, addr: GuestAddr) -> Result<()> {
+        match &mut self.inner {
+            Some(inner) => inner.call(UNMAP_BUFFER_FUNCTION, addr.as_u64()),
+            None => log_then_return!("No inner MultiUseSandbox to unmap a buffer from"),
+        }
+    }
+
+    /// Stop each guest call that runs for longer than `deadline` at the
+    /// next function call or loop iteration in the guest, where wasm
+    /// checks for epoch deadlines. The call returns an
+    /// [`EpochDeadlineExceeded`] error, but unlike a call interrupted
+    /// with [`InterruptHandle::kill`] or a timeout, the sandbox is not
+    /// poisoned and can be called again without being restored.
+    ///
+    /// A call blocked in a host function is only stopped once the host
+    /// function returns. The deadline is measured with the time stamp
+    /// counter, and calls run slower while they have a deadline.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sandbox was not built with
+    /// [`SandboxBuilder::with_epoch_interruption`](crate::SandboxBuilder::with_epoch_interruption).
+    pub fn set_epoch_deadline(&mut self, deadline: Duration) -> Result<()> {
+        self.epoch_deadline = Some(deadline);
+        self.apply_epoch_deadline()
+    }
+
+    /// Remove the deadline set with
+    /// [`set_epoch_deadline`](Self::set_epoch_deadline)
+    pub fn clear_epoch_deadline(&mut self) -> Result<()> {
+        if self.epoch_deadline.take().is_none() {
+            return Ok(());
+        }
+        match &mut self.inner {
+            Some(inner) => inner.call(SET_EPOCH_DEADLINE_FUNCTION, 0u64),
+            None => log_then_return!("No inner MultiUseSandbox to clear the epoch deadline of"),
+        }
+    }
+
+    // Set the epoch deadline in the guest, whose copy of it is lost when
+    // its memory is restored
+    fn apply_epoch_deadline(&mut self) -> Result<()> {
+        let Some(deadline) = self.epoch_deadline else {
+            return Ok(());
+        };
+        match &mut self.inner {
+            Some(inner) => inner.call(
+                SET_EPOCH_DEADLINE_FUNCTION,
+                epoch_deadline::tsc_ticks(deadline),
+            ),
+            None => log_then_return!("No inner MultiUseSandbox to set the epoch deadline of"),
+        }
+    }
+
+    // Give the guest the payload key, which is never part of its
+    // snapshots
+    fn apply_payload_key(&mut self) -> Result<()> {
+        let Some(key) = &self.context.payload_key else {
+            return Ok(());
+        };
+        match &mut self.inner {
+            Some(inner) => inner.call(SET_PAYLOAD_KEY_FUNCTION, key.to_vec()),
+            None => log_then_return!("No inner MultiUseSandbox to set the payload key of"),
+        }
+    }
+
+    /// Register the given host function `host_func` with `self` under
+    /// the given `name`, see [`WasmSandbox::register`].
+    ///
+    /// The loaded module was linked when it was loaded, so it cannot
+    /// import a function it did not already import, but modules loaded
+    /// after it is unloaded can.
+    pub fn register<Args: CatchPanics<Output>, Output: SupportedReturnType>(
+        &mut self,
+        name: impl AsRef<str>,
+        host_func: impl Into<HostFunction<Output, Args>>,
+    ) -> Result<()> {
+        let inner = self
+            .inner
+            .as_mut()
+            .ok_or_else(|| new_error!("No inner MultiUseSandbox to register with"))?;
+        self.context
+            .register(inner, name.as_ref(), host_func.into())
+    }
+
+    /// Run `hook` with the name of the guest function before each guest
+    /// call, including the calls made by the bindings generated by
+    /// `host_bindgen!`. Hooks run in the order they were set. If a hook
+    /// returns an error, for example because a quota was spent, the
+    /// guest function is not called and the call returns that error.
+    ///
+    /// Hooks are kept when the module is unloaded, until
+    /// [`clear_call_hooks`](Self::clear_call_hooks) is called.
+    pub fn on_call_start(&mut self, hook: impl Fn(&str) -> Result<()> + Send + Sync + 'static) {
+        self.context.call_hooks.add_call_start(Arc::new(hook));
+    }
+
+    /// Run `hook` once each guest call returns, with the name of the
+    /// guest function, the time the call took and the error it returned,
+    /// if any. This includes calls that an
+    /// [`on_call_start`](Self::on_call_start) hook refused.
+    pub fn on_call_end(&mut self, hook: impl Fn(&CallRecord<'_>) + Send + Sync + 'static) {
+        self.context.call_hooks.add_call_end(Arc::new(hook));
+    }
+
+    /// Run `hook` with the name of the host function before each call the
+    /// guest makes to a host function registered with
+    /// [`ProtoWasmSandbox::register`](crate::ProtoWasmSandbox::register)
+    /// or the methods built on it. If `hook` returns an error, the host
+    /// function is not called and the guest receives that error.
+    pub fn on_host_call(&mut self, hook: impl Fn(&str) -> Result<()> + Send + Sync + 'static) {
+        self.context.call_hooks.add_host_call(Arc::new(hook));
+    }
+
+    /// Remove the hooks set with [`on_call_start`](Self::on_call_start),
+    /// [`on_call_end`](Self::on_call_end) and
+    /// [`on_host_call`](Self::on_host_call).
+    pub fn clear_call_hooks(&mut self) {
+        self.context.call_hooks.clear();
     }
 
     /// Unload the wasm module and return a `WasmSandbox` that can be
@@ -117,7 +1257,9 @@ impl LoadedWasmSandbox {
     ///
     /// This method defers calling [`restore()`](Self::restore) to
     /// reset the sandbox to its pre-module state until a new module
-    /// is loaded. However, the sandbox will always be restored when a
+    /// is loaded, unless the sandbox was built with
+    /// [`SandboxBuilder::with_zero_memory_on_unload`](crate::SandboxBuilder::with_zero_memory_on_unload).
+    /// However, the sandbox will always be restored when a
     /// new module is loaded, so a poisoned sandbox can be recovered
     /// by unloading and reloading a module.
     pub fn unload_module(mut self) -> Result<WasmSandbox> {
@@ -131,21 +1273,482 @@ impl LoadedWasmSandbox {
             .take()
             .ok_or_else(|| new_error!("No snapshot of the WasmSandbox to unload"))?;
 
-        WasmSandbox::new_from_loaded(sandbox, snapshot).inspect(|_| {
-            metrics::counter!(METRIC_SANDBOX_UNLOADS).increment(1);
-        })
+        WasmSandbox::new_from_loaded(sandbox, snapshot, std::mem::take(&mut self.context)).inspect(
+            |_| {
+                metrics::counter!(METRIC_SANDBOX_UNLOADS).increment(1);
+            },
+        )
     }
 
-    pub(super) fn new(
-        inner: MultiUseSandbox,
-        runtime_snapshot: Arc<Snapshot>,
-    ) -> Result<LoadedWasmSandbox> {
-        metrics::gauge!(METRIC_ACTIVE_LOADED_WASM_SANDBOXES).increment(1);
-        metrics::counter!(METRIC_TOTAL_LOADED_WASM_SANDBOXES).increment(1);
-        Ok(LoadedWasmSandbox {
+    /// Replace the loaded module with the module in the file at `file`,
+    /// see [`reload_module_from_buffer()`](Self::reload_module_from_buffer).
+    pub fn reload_module(&mut self, file: impl AsRef<Path>) -> Result<()> {
+        self.reload_module_from_buffer(&std::fs::read(file)?)
+    }
+
+    /// Replace the loaded module with the module in `buffer`, for example
+    /// a new build of it, without unloading the sandbox: the sandbox is
+    /// restored to its state before the current module was loaded and
+    /// the new module is loaded with the same host functions, linked
+    /// modules and [`GuestAbi`]. Unlike
+    /// [`upgrade_module_from_buffer()`](Self::upgrade_module_from_buffer),
+    /// the current module's state is discarded.
+    ///
+    /// If the new module fails to load, the sandbox is restored to the
+    /// current module, with the state it had before the reload, and the
+    /// error is returned. Reloads and their rollbacks are counted in the
+    /// same metrics as upgrades.
+    pub fn reload_module_from_buffer(&mut self, buffer: &[u8]) -> Result<()> {
+        self.replace_module(buffer, None)
+    }
+
+    /// Replace the loaded module with a new version of it, read from the
+    /// file at `file`, carrying its state across, see
+    /// [`upgrade_module_from_buffer()`](Self::upgrade_module_from_buffer).
+    pub fn upgrade_module(&mut self, file: impl AsRef<Path>) -> Result<()> {
+        self.upgrade_module_from_buffer(&std::fs::read(file)?)
+    }
+
+    /// Replace the loaded module with the new version of it in `buffer`,
+    /// carrying its state across, so that long-lived stateful modules can
+    /// be upgraded without losing their state.
+    ///
+    /// The state is migrated through two functions that both versions
+    /// export: the current module's `export_state`, which takes no
+    /// parameters and returns its state as a buffer, and the new
+    /// module's `import_state`, which takes that buffer and its length
+    /// and returns `0` on success. The new module is loaded with the
+    /// same [`GuestAbi`] and must provide the sandbox's
+    /// [required exports](crate::SandboxBuilder::with_required_exports).
+    ///
+    /// Calls are served by the current module until the upgrade
+    /// succeeds and by the new module after. If loading the new module
+    /// or importing the state fails, the sandbox is restored to the
+    /// current module, with the state it had before the upgrade, and the
+    /// error is returned. Upgrades and rollbacks are counted in the
+    /// `wasm_module_upgrades_total` and
+    /// `wasm_module_upgrade_rollbacks_total` metrics.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `export_state` fails, in which case the
+    /// sandbox is unchanged, or if the upgrade is rolled back.
+    pub fn upgrade_module_from_buffer(&mut self, buffer: &[u8]) -> Result<()> {
+        let state: Vec<u8> = self.call_guest_function(EXPORT_STATE_FUNCTION, ())?;
+        self.replace_module(buffer, Some(state))
+    }
+
+    // Load the module in `buffer` in place of the current one, importing
+    // `state` into it if it is given, and roll back to the current one if
+    // that fails
+    fn replace_module(&mut self, buffer: &[u8], state: Option<Vec<u8>>) -> Result<()> {
+        let rollback = self.snapshot()?;
+        let stubbed_wasi_imports = self.stubbed_wasi_imports.clone();
+        let module_hash = self.module_hash.clone();
+        let provenance = self.provenance.clone();
+
+        match self.load_replacement(buffer, state) {
+            Ok(()) => {
+                metrics::counter!(METRIC_MODULE_UPGRADES).increment(1);
+                Ok(())
+            }
+            Err(e) => {
+                tracing::warn!("module replacement failed, rolling back: {}", e);
+                self.restore(rollback)?;
+                self.stubbed_wasi_imports = stubbed_wasi_imports;
+                self.module_hash = module_hash;
+                self.provenance = provenance;
+                metrics::counter!(METRIC_MODULE_UPGRADE_ROLLBACKS).increment(1);
+                Err(e)
+            }
+        }
+    }
+
+    // Load the module in `buffer` in place of the current one, importing
+    // `state` into it if it is given
+    fn load_replacement(&mut self, buffer: &[u8], state: Option<Vec<u8>>) -> Result<()> {
+        let runtime_snapshot = self
+            .runtime_snapshot
+            .clone()
+            .ok_or_else(|| new_error!("No snapshot of the WasmSandbox to replace the module in"))?;
+        let inner = self
+            .inner
+            .as_mut()
+            .ok_or_else(|| new_error!("No inner MultiUseSandbox to replace the module in"))?;
+        inner.restore(runtime_snapshot)?;
+        wasm_sandbox::set_guest_abi(inner, self.guest_abi)?;
+        self.context.add_host_functions(inner)?;
+        wasm_sandbox::restrict_host_functions(inner, self.allowed_imports.as_deref())?;
+        wasm_sandbox::link_wasm_modules(inner, &self.linked_modules)?;
+        let artifact = self.context.prepare(buffer)?;
+        wasm_sandbox::load_wasm_module_from_bytes(
+            inner,
+            artifact.unwrap_or_else(|| buffer.to_vec()),
+        )?;
+        required_exports::check(inner, &self.context.required_exports)?;
+        self.stubbed_wasi_imports = wasm_sandbox::stubbed_wasi_imports(inner)?;
+        self.context.host_function_cache.reset();
+        self.module_hash = self
+            .context
+            .hash_modules()
+            .then(|| module_usage::linked_module_hash(&self.linked_modules, buffer));
+        self.provenance = self
+            .context
+            .provenance
+            .as_ref()
+            .map(|provenance| provenance.record(self.module_hash.clone()));
+
+        self.apply_epoch_deadline()?;
+        self.apply_payload_key()?;
+
+        let Some(state) = state else {
+            return Ok(());
+        };
+        let len = state.len() as i32;
+        let res: i32 = self.call_guest_function(IMPORT_STATE_FUNCTION, (state, len))?;
+        if res != 0 {
+            return Err(new_error!(
+                "{} failed with error code {:?}",
+                IMPORT_STATE_FUNCTION,
+                res
+            ));
+        }
+        Ok(())
+    }
+
+    pub(super) fn new(
+        inner: MultiUseSandbox,
+        runtime_snapshot: Arc<Snapshot>,
+        mut context: SandboxContext,
+        stubbed_wasi_imports: Vec<String>,
+        module_hash: Option<String>,
+        guest_abi: GuestAbi,
+    ) -> Result<LoadedWasmSandbox> {
+        metrics::gauge!(METRIC_ACTIVE_LOADED_WASM_SANDBOXES).increment(1);
+        metrics::counter!(METRIC_TOTAL_LOADED_WASM_SANDBOXES).increment(1);
+        // The sandbox may have been loaded from a snapshot holding cached
+        // host function results of any age
+        context.host_function_cache.reset();
+        // Loading the module restored the sandbox
+        context.panic_handler.clear_poison();
+        let provenance = context
+            .provenance
+            .as_ref()
+            .map(|provenance| provenance.record(module_hash.clone()));
+        let mut loaded = LoadedWasmSandbox {
             inner: Some(inner),
             runtime_snapshot: Some(runtime_snapshot),
-        })
+            context,
+            stubbed_wasi_imports,
+            module_hash,
+            provenance,
+            guest_abi,
+            linked_modules: Vec::new(),
+            allowed_imports: None,
+            epoch_deadline: None,
+            last_call: None,
+            last_mem_delta: None,
+            call_in_progress: false,
+            buffer_addrs: BufferAddrs::default(),
+            #[cfg(feature = "crashdump")]
+            crash_context: WasmCrashContext::default(),
+        };
+        #[cfg(feature = "crashdump")]
+        if loaded.context.crashdump
+            && let Some(inner) = &mut loaded.inner
+        {
+            loaded.crash_context = WasmCrashContext::fetch(inner).unwrap_or_else(|e| {
+                tracing::warn!("Failed to fetch the wasm context for core dumps: {e}");
+                WasmCrashContext::default()
+            });
+        }
+        loaded.apply_payload_key()?;
+        Ok(loaded)
+    }
+
+    pub(super) fn with_linked_modules(mut self, linked_modules: Vec<(String, Vec<u8>)>) -> Self {
+        self.linked_modules = linked_modules;
+        self
+    }
+
+    pub(super) fn with_allowed_imports(mut self, allowed_imports: Vec<String>) -> Self {
+        self.allowed_imports = Some(allowed_imports);
+        self
+    }
+
+    /// Restore the sandbox's own AOT cache, which
+    /// `WasmSandbox::load_module_cached` replaced while loading
+    #[cfg(feature = "aot")]
+    pub(super) fn with_aot_cache(mut self, cache: Option<AotCache>) -> Self {
+        if let Some(precompiler) = &mut self.context.precompiler {
+            precompiler.cache = cache;
+        }
+        self
+    }
+
+    /// Read the value of the state cell `name`, see [`StateCells`]
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no cell named `name` has been declared.
+    pub fn get_cell(&self, name: &str) -> Result<StateCellValue> {
+        self.context.state_cells.get(name)
+    }
+
+    /// Set the value of the state cell `name`, see [`StateCells`]
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no cell named `name` has been declared, or if
+    /// `value` is not of the type the cell was declared with.
+    pub fn set_cell(&self, name: &str, value: impl Into<StateCellValue>) -> Result<()> {
+        self.context.state_cells.set(name, value)
+    }
+
+    /// A handle to this sandbox's state cells, which can be used from
+    /// other threads while a guest call is running.
+    pub fn state_cells(&self) -> StateCells {
+        self.context.state_cells.clone()
+    }
+
+    /// The functions exported by the loaded module or component, with
+    /// their parameter and result types, so that hosts can find out what
+    /// a guest provides without calling it. Module functions have core
+    /// wasm types such as `i32`, which do not say whether a pointer is a
+    /// string or a buffer; component functions have WIT types such as
+    /// `list<u8>`.
+    pub fn guest_functions(&mut self) -> Result<Vec<GuestFunction>> {
+        let bytes: Vec<u8> = match &mut self.inner {
+            Some(inner) => inner.call("GetGuestFunctions", ())?,
+            None => log_then_return!("No inner MultiUseSandbox to list the functions of"),
+        };
+        guest_functions::from_bytes(&bytes)
+            .ok_or_else(|| new_error!("Failed to decode the guest's functions"))
+    }
+
+    /// Serialize the state of the loaded module's instance, its `memory`
+    /// export and exported mutable globals, so that it can be written to
+    /// disk or sent to another process and restored there with
+    /// [`restore_state`](Self::restore_state). A module that has been
+    /// warmed up once can then be started in that state without running
+    /// its initialisation again.
+    ///
+    /// Unlike [`snapshot`](Self::snapshot), which captures the whole
+    /// sandbox but can only be restored into the sandbox that took it,
+    /// the serialized state only covers the module instance. Host-side
+    /// state such as [state cells](Self::state_cells), and state the
+    /// module does not export, such as tables or unexported globals, is
+    /// not included. Components are not supported.
+    pub fn serialize_state(&mut self) -> Result<Vec<u8>> {
+        let Some(inner) = &mut self.inner else {
+            log_then_return!("No inner MultiUseSandbox to serialize the state of");
+        };
+        let layout: Vec<u8> = inner.call(GET_INSTANCE_LAYOUT_FUNCTION, ())?;
+        let mut state = InstanceState {
+            module_hash: self.module_hash.clone(),
+            layout: InstanceLayout::from_bytes(&layout)
+                .ok_or_else(|| new_error!("Failed to decode the instance layout"))?,
+            chunks: Vec::new(),
+        };
+        let mut offset = 0;
+        while offset < state.layout.memory_size {
+            let len = (state.layout.memory_size - offset).min(CHUNK_SIZE as u64);
+            let chunk: Vec<u8> = inner.call(READ_INSTANCE_MEMORY_FUNCTION, (offset, len as i32))?;
+            state.push_chunk(offset, chunk);
+            offset += len;
+        }
+        Ok(state.to_bytes())
+    }
+
+    /// Restore the state of the loaded module's instance from `state`,
+    /// serialized with [`serialize_state`](Self::serialize_state) by a
+    /// sandbox in this or another process that loaded the same module.
+    ///
+    /// The instance's memory is grown to the size it had when the state
+    /// was serialized, so it must not have grown beyond that size since
+    /// the module was loaded.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `state` is malformed, or if both sandboxes
+    /// know the hash of their module and the hashes differ, see
+    /// [`module_hash`](Self::module_hash). If restoring fails part way
+    /// through, the instance is left in an unspecified state and should
+    /// be reset with [`restore`](Self::restore).
+    pub fn restore_state(&mut self, state: &[u8]) -> Result<()> {
+        let state = InstanceState::from_bytes(state)?;
+        if let (Some(expected), Some(actual)) = (&state.module_hash, &self.module_hash)
+            && expected != actual
+        {
+            log_then_return!(
+                "Serialized state is of module {}, but module {} is loaded",
+                expected,
+                actual
+            );
+        }
+        let Some(inner) = &mut self.inner else {
+            log_then_return!("No inner MultiUseSandbox to restore the state of");
+        };
+        inner.call::<()>(RESET_INSTANCE_FUNCTION, state.layout.to_bytes())?;
+        for (offset, chunk) in state.chunks {
+            inner.call::<()>(WRITE_INSTANCE_MEMORY_FUNCTION, (offset, chunk))?;
+        }
+        Ok(())
+    }
+
+    /// The WASI functions imported by the module that the runtime does not
+    /// implement, which were linked to stubs, see
+    /// [`SandboxBuilder::with_unsupported_wasi_errno`](crate::SandboxBuilder::with_unsupported_wasi_errno).
+    /// These are also logged as a warning when the module is loaded.
+    pub fn stubbed_wasi_imports(&self) -> &[String] {
+        &self.stubbed_wasi_imports
+    }
+
+    /// The id of the sandbox, unique within the process, which is
+    /// recorded as the `sandbox_id` field of the `guest_log` spans that
+    /// the guest's log records are emitted in. It stays the same as
+    /// modules are unloaded and loaded.
+    pub fn sandbox_id(&self) -> u64 {
+        self.context.sandbox_id
+    }
+
+    /// The hex-encoded BLAKE3 hash of the file or buffer the module was
+    /// loaded from, under which its usage is recorded, or `None` if the
+    /// sandbox records neither usage nor provenance, see
+    /// [`SandboxBuilder::with_usage_accounting`](crate::SandboxBuilder::with_usage_accounting).
+    ///
+    /// Sandboxes loaded from snapshots only know the hash if the snapshot
+    /// was taken by a sandbox that hashes its module.
+    pub fn module_hash(&self) -> Option<&str> {
+        self.module_hash.as_deref()
+    }
+
+    /// The memory used by the sandbox's guest: the guest memory wasmtime
+    /// has used for the loaded guest, the peak of that since the sandbox
+    /// was built, and the sizes of the loaded module's linear memories.
+    ///
+    /// The peak only covers the memory seen when it was read, by this
+    /// method or, if the sandbox was built with
+    /// [`SandboxBuilder::with_memory_metrics`](crate::SandboxBuilder::with_memory_metrics),
+    /// after each guest call. Reading the statistics enters the VM, so
+    /// they cannot be read while the sandbox is poisoned.
+    pub fn memory_stats(&mut self) -> Result<MemoryStats> {
+        match &mut self.inner {
+            Some(inner) => self.context.memory.fetch(inner),
+            None => log_then_return!("No inner MultiUseSandbox to read memory statistics from"),
+        }
+    }
+
+    /// Have the guest release the memory it no longer needs, for example
+    /// between requests in a long-lived sandbox, and return the memory it
+    /// uses afterwards, as [`memory_stats()`](Self::memory_stats) does.
+    ///
+    /// This runs the guest's collector, as [`hint_gc()`](Self::hint_gc)
+    /// does, frees the last call's return values in a module's memory
+    /// and, with the `gc` feature, collects wasmtime's GC heap. The
+    /// runtime's heap is a fixed region of guest memory and pages mapped
+    /// for wasmtime stay mapped, so their memory is not given back to
+    /// the host; restore a [`snapshot()`](Self::snapshot) to return the
+    /// guest to the memory it used then.
+    pub fn trim_guest_memory(&mut self) -> Result<MemoryStats> {
+        self.check_host_function_panic()?;
+        match &mut self.inner {
+            Some(inner) => self.context.memory.trim(inner),
+            None => log_then_return!("No inner MultiUseSandbox to trim the memory of"),
+        }
+    }
+
+    /// Read `len` bytes of the guest's memory at the guest virtual
+    /// address `addr`, such as a log buffer the module keeps, to inspect
+    /// the guest after a failed call without generating a crash dump.
+    ///
+    /// The memory is read by the runtime, so it cannot be read while
+    /// the sandbox is poisoned, but can after a module traps. Use
+    /// [`read_wasm_memory`](Self::read_wasm_memory) to read the module's
+    /// linear memory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any page of the range is not mapped in the
+    /// guest.
+    #[cfg(feature = "memory_inspection")]
+    pub fn read_guest_memory(&mut self, addr: u64, len: usize) -> Result<Vec<u8>> {
+        self.read_memory("ReadGuestMemory", addr, len)
+    }
+
+    /// Read `len` bytes of the loaded module's `memory` export at
+    /// `offset`, as the module sees it, to inspect the module after a
+    /// failed call. As with [`read_guest_memory`](Self::read_guest_memory),
+    /// this cannot be done while the sandbox is poisoned. Components are
+    /// not supported.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the module does not export its memory, or if
+    /// the range is out of its bounds.
+    #[cfg(feature = "memory_inspection")]
+    pub fn read_wasm_memory(&mut self, offset: u64, len: usize) -> Result<Vec<u8>> {
+        self.read_memory(READ_INSTANCE_MEMORY_FUNCTION, offset, len)
+    }
+
+    // Read `len` bytes from `start` in chunks with the guest function
+    // `function_name`, which fit in the sandbox's output buffer
+    #[cfg(feature = "memory_inspection")]
+    fn read_memory(&mut self, function_name: &str, start: u64, len: usize) -> Result<Vec<u8>> {
+        let Some(inner) = &mut self.inner else {
+            log_then_return!("No inner MultiUseSandbox to read memory from");
+        };
+        let mut bytes = Vec::with_capacity(len);
+        while bytes.len() < len {
+            let offset = start.checked_add(bytes.len() as u64).ok_or_else(|| {
+                new_error!("Memory read at {:#x} of {} bytes overflows", start, len)
+            })?;
+            let chunk_len = (len - bytes.len()).min(CHUNK_SIZE);
+            let chunk: Vec<u8> = inner.call(function_name, (offset, chunk_len as i32))?;
+            if chunk.len() != chunk_len {
+                log_then_return!(
+                    "Guest returned {} bytes of memory, expected {}",
+                    chunk.len(),
+                    chunk_len
+                );
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        Ok(bytes)
+    }
+
+    /// Generate a core dump of the sandbox's VM in `dir`, and write the
+    /// loaded module's wasm context next to it, returning the path of the
+    /// context file. This is the dump written when a guest call crashes,
+    /// see
+    /// [`SandboxBuilder::with_crashdump_enabled`](crate::SandboxBuilder::with_crashdump_enabled).
+    ///
+    /// The context is a JSON object holding the format `version`, the
+    /// file name of the `core_dump` it is for, the `module_hash` and the
+    /// names of the functions the module `exports`, and the `name`,
+    /// `guest_addr` and `size` of each of its `linear_memories`, as they
+    /// are found in the core dump. The context of a crashed guest call
+    /// also holds its `function_name` and `error`, and the `code` and
+    /// `frames` of its `trap`, innermost first, if it trapped. Fields
+    /// that are not known are `null`.
+    ///
+    /// As with hyperlight-host's core dumps, if `dir` does not exist the
+    /// dump is written to the temporary directory.
+    #[cfg(feature = "crashdump")]
+    pub fn generate_crashdump_to_dir(&mut self, dir: impl Into<String>) -> Result<PathBuf> {
+        let Some(inner) = &mut self.inner else {
+            log_then_return!("No inner MultiUseSandbox to generate a core dump of");
+        };
+        self.crash_context
+            .dump(inner, self.module_hash.as_deref(), Some(dir.into()))
+    }
+
+    /// The signed record of the runtime, module, host functions and
+    /// configuration of this sandbox, or `None` if the sandbox does not
+    /// record provenance, see
+    /// [`SandboxBuilder::with_provenance_key`](crate::SandboxBuilder::with_provenance_key).
+    pub fn provenance(&self) -> Option<&Provenance> {
+        self.provenance.as_ref()
     }
 
     /// Get a handle to the interrupt handler for this sandbox,
@@ -168,6 +1771,8 @@ impl LoadedWasmSandbox {
     /// - Guest panic or abort
     /// - Memory violation
     /// - Stack or heap exhaustion
+    /// - A panic in a host function, if the sandbox's
+    ///   [`PanicPolicy`](crate::PanicPolicy) is `PoisonSandbox`
     ///
     /// Note: The call that causes poisoning returns the original error (e.g.,
     /// `ExecutionCanceledByHost`), not `PoisonedSandbox`. The sandbox is marked
@@ -185,12 +1790,105 @@ impl LoadedWasmSandbox {
     /// - `Err` if the sandbox is in an invalid state
     pub fn is_poisoned(&self) -> Result<bool> {
         match &self.inner {
-            Some(inner) => Ok(inner.poisoned()),
+            Some(inner) => Ok(inner.poisoned() || self.context.panic_handler.poison().is_some()),
             None => log_then_return!("No inner MultiUseSandbox to check poisoned state"),
         }
     }
+
+    /// Return `PoisonedSandbox` if a panic in a host function has
+    /// poisoned the sandbox
+    fn check_host_function_panic(&self) -> Result<()> {
+        if self.context.panic_handler.poison().is_some() {
+            return Err(HyperlightError::PoisonedSandbox);
+        }
+        Ok(())
+    }
+}
+
+/// Record a call to the module whose hash is `module_hash`, which took
+/// `cpu_time`
+fn record_usage(inner: &mut MultiUseSandbox, module_hash: &str, cpu_time: Duration) {
+    // A poisoned sandbox cannot be called until it is restored, so the
+    // size of its memory is not known
+    let memory_size = if inner.poisoned() {
+        None
+    } else {
+        inner
+            .call::<Vec<u8>>("GetCallStats", ())
+            .ok()
+            .and_then(|bytes| CallStats::from_bytes(&bytes))
+            .and_then(|stats| stats.memory_size)
+    };
+    module_usage::record_call(module_hash, cpu_time, memory_size);
+}
+
+/// Discard the host function results cached in the guest that have expired
+fn flush_expired_host_function_results(
+    inner: &mut MultiUseSandbox,
+    host_function_cache: &mut HostFunctionCacheExpiry,
+) -> Result<()> {
+    let expired = host_function_cache.take_expired();
+    if expired.is_empty() {
+        return Ok(());
+    }
+    let names = expired.join("\0").into_bytes();
+    let res: i32 = inner.call("FlushHostFunctionCache", names)?;
+    if res != 0 {
+        return Err(new_error!(
+            "FlushHostFunctionCache Failed with error code {:?}",
+            res
+        ));
+    }
+    Ok(())
+}
+
+/// Emit the log records buffered by the guest since the last call, in
+/// a `guest_log` span recording the id of the sandbox
+fn drain_guest_logs(inner: &mut MultiUseSandbox, sandbox_id: u64) {
+    let bytes: Vec<u8> = match inner.call("DrainLogBuffer", ()) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::warn!("failed to drain guest log buffer: {e:?}");
+            return;
+        }
+    };
+    let Some(logs) = GuestLogs::from_bytes(&bytes) else {
+        tracing::warn!("guest log buffer is malformed");
+        return;
+    };
+    let span = tracing::info_span!("guest_log", sandbox_id);
+    let _entered = span.enter();
+    if logs.dropped > 0 {
+        tracing::warn!(target: GUEST_LOG_TARGET, "{} guest log records were dropped because the buffer was full", logs.dropped);
+    }
+    // tracing needs the level of an event to be a constant
+    macro_rules! emit {
+        ($record:expr, $($fields:tt)*) => {
+            match $record.level {
+                1 => tracing::error!(target: GUEST_LOG_TARGET, $($fields)*),
+                2 => tracing::warn!(target: GUEST_LOG_TARGET, $($fields)*),
+                3 => tracing::info!(target: GUEST_LOG_TARGET, $($fields)*),
+                4 => tracing::debug!(target: GUEST_LOG_TARGET, $($fields)*),
+                _ => tracing::trace!(target: GUEST_LOG_TARGET, $($fields)*),
+            }
+        };
+    }
+    for record in logs.records {
+        if record.target.is_empty() && record.fields.is_empty() {
+            emit!(record, "{}", record.message);
+            continue;
+        }
+        let fields = record
+            .fields
+            .iter()
+            .map(|(key, value)| format!("{}={:?}", key, value))
+            .collect::<Vec<_>>()
+            .join(" ");
+        emit!(record, guest_target = %record.target, fields = %fields, "{}", record.message);
+    }
 }
 
+// Used by the bindings generated by `hyperlight_component_macro::host_bindgen!`
 impl Callable for LoadedWasmSandbox {
     fn call<Output: SupportedReturnType>(
         &mut self,
@@ -201,8 +1899,61 @@ impl Callable for LoadedWasmSandbox {
     }
 }
 
+/// Interrupt the guest call `inner` was making when its sandbox was
+/// dropped, and release the sandbox once the vCPU has stopped
+fn terminate_call(inner: MultiUseSandbox) {
+    metrics::counter!(METRIC_FORCED_TERMINATIONS).increment(1);
+    tracing::warn!("LoadedWasmSandbox dropped during a guest call, interrupting the call");
+    // kill blocks until the vCPU stops, so wait for it on another thread
+    // to bound how long the drop takes
+    let handle = inner.interrupt_handle();
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(handle.kill());
+    });
+    match rx.recv_timeout(FORCED_TERMINATION_TIMEOUT) {
+        Ok(_) => drop(inner),
+        Err(_) => {
+            // Releasing the memory of a vCPU that may still be running is
+            // unsound, so leak the sandbox instead
+            tracing::error!(
+                "guest call did not stop within {:?}, leaking its sandbox",
+                FORCED_TERMINATION_TIMEOUT
+            );
+            std::mem::forget(inner);
+        }
+    }
+}
+
+/// Kills the guest call of an async call whose future was dropped
+#[cfg(feature = "async")]
+struct KillOnDrop(Option<Arc<dyn InterruptHandle>>);
+
+#[cfg(feature = "async")]
+impl KillOnDrop {
+    fn disarm(mut self) {
+        self.0 = None;
+    }
+}
+
+#[cfg(feature = "async")]
+impl Drop for KillOnDrop {
+    fn drop(&mut self) {
+        if let Some(handle) = self.0.take() {
+            // kill blocks until the vCPU stops, which must not happen on
+            // an executor thread
+            std::thread::spawn(move || handle.kill());
+        }
+    }
+}
+
 impl Drop for LoadedWasmSandbox {
     fn drop(&mut self) {
+        if self.call_in_progress
+            && let Some(inner) = self.inner.take()
+        {
+            terminate_call(inner);
+        }
         metrics::gauge!(METRIC_ACTIVE_LOADED_WASM_SANDBOXES).decrement(1);
     }
 }
@@ -218,16 +1969,28 @@ impl Debug for LoadedWasmSandbox {
 #[cfg(test)]
 mod tests {
     use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
     use std::thread;
+    use std::time::Duration;
 
     use crossbeam_queue::ArrayQueue;
     use examples_common::get_wasm_module_path;
     use hyperlight_host::{HyperlightError, new_error};
 
+    #[cfg(feature = "memory_inspection")]
+    use super::CHUNK_SIZE;
     use super::{LoadedWasmSandbox, WasmSandbox};
-    use crate::Result;
     use crate::sandbox::proto_wasm_sandbox::ProtoWasmSandbox;
     use crate::sandbox::sandbox_builder::SandboxBuilder;
+    use crate::{
+        CallBudgetExceeded, Callback, CancellationToken, DirectoryResolver, EntropyPolicy,
+        EpochDeadlineExceeded, GuestAbi, GuestCallCancelled, GuestCallTimeout, GuestCallbacks,
+        HostFunctionCache, HostFunctionFailed, HostFunctionManifest, ManifestFunction, MultiValue,
+        PanicPolicy, ParameterType, ParameterValue, Registerable, RequiredExport, Result,
+        ReturnType, ReturnValue, StateCellValue, WasmValue,
+    };
+    #[cfg(feature = "aot")]
+    use crate::{DirAccess, GuestAborted, GuestTrap, StackExhausted, TrapCode, WasmLimitExceeded};
 
     fn get_time_since_boot_microsecond() -> Result<i64> {
         let res = std::time::SystemTime::now()
@@ -259,196 +2022,2744 @@ mod tests {
         call_funcs(loaded_wasm_sandbox, 500);
     }
 
+    #[cfg(feature = "async")]
     #[test]
-    fn test_sandbox_use_on_different_threads() {
-        let wasm_sandbox_queue = Arc::new(ArrayQueue::<WasmSandbox>::new(10));
-        let loaded_wasm_sandbox_queue = Arc::new(ArrayQueue::<LoadedWasmSandbox>::new(10));
-
-        // Create a queue of WasmSandbox instances
-        for i in 0..10 {
-            println!("Creating WasmSandbox instance {}", i);
-            let mut sandbox = ProtoWasmSandbox::default();
+    fn test_call_guest_function_async() {
+        let mut sandbox = ProtoWasmSandbox::default();
+        sandbox
+            .register(
+                "GetTimeSinceBootMicrosecond",
+                get_time_since_boot_microsecond,
+            )
+            .unwrap();
+        let wasm_sandbox = sandbox.load_runtime().unwrap();
+        let mod_path = get_wasm_module_path("RunWasm.aot").unwrap();
+        let mut loaded_wasm_sandbox = wasm_sandbox.load_module(mod_path).unwrap();
 
-            sandbox
-                .register(
-                    "GetTimeSinceBootMicrosecond",
-                    get_time_since_boot_microsecond,
-                )
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let result: i32 = loaded_wasm_sandbox
+                .call_guest_function_async("CalcFib", 4i32)
+                .await
                 .unwrap();
+            assert_eq!(result, 3);
 
-            let wasm_sandbox = sandbox.load_runtime().unwrap();
-            wasm_sandbox_queue.push(wasm_sandbox).unwrap();
-            println!("Pushed WasmSandbox instance {}", i);
-        }
-
-        // Get the WasmSandbox instances from the queue and load the module on a new thread
-        // then call a function and push the LoadedWasmSandbox instance to the loaded_wasm_sandbox_queue
-        let thread_handles: Vec<_> = (0..10)
-            .map(|i| {
-                let wq = wasm_sandbox_queue.clone();
-                let lwq = loaded_wasm_sandbox_queue.clone();
-
-                thread::spawn(move || {
-                    println!("Loading module on thread {}", i);
-                    let wasm_sandbox = wq.pop().unwrap();
-                    let loaded_wasm_sandbox: LoadedWasmSandbox = {
-                        let mod_path = get_wasm_module_path("RunWasm.aot").unwrap();
-                        wasm_sandbox.load_module(mod_path)
-                    }
-                    .unwrap();
-                    println!("Calling function on thread {}", i);
-                    let lws = call_funcs(loaded_wasm_sandbox, 1);
-                    lwq.push(lws).unwrap();
-                    println!("Pushed LoadedWasmSandbox instance to queue on thread {}", i)
-                })
-            })
-            .collect::<Vec<_>>();
+            // Timing out kills the call, and the sandbox cannot be used again
+            let timed_out = tokio::time::timeout(
+                Duration::from_millis(100),
+                loaded_wasm_sandbox.call_guest_function_async::<i32>("KeepCPUBusy", 10000i32),
+            )
+            .await;
+            assert!(timed_out.is_err());
+        });
+        assert!(
+            loaded_wasm_sandbox
+                .call_guest_function::<i32>("CalcFib", 4i32)
+                .is_err()
+        );
+    }
 
-        for handle in thread_handles {
-            handle.join().unwrap();
-        }
+    #[test]
+    fn test_guest_call_timeout() {
+        let mut sandbox = SandboxBuilder::new()
+            .with_guest_call_timeout(Duration::from_millis(100))
+            .build()
+            .unwrap();
+        sandbox
+            .register(
+                "GetTimeSinceBootMicrosecond",
+                get_time_since_boot_microsecond,
+            )
+            .unwrap();
+        let wasm_sandbox = sandbox.load_runtime().unwrap();
+        let mod_path = get_wasm_module_path("RunWasm.aot").unwrap();
+        let mut loaded_wasm_sandbox = wasm_sandbox.load_module(mod_path).unwrap();
+        let snapshot = loaded_wasm_sandbox.snapshot().unwrap();
 
-        // Get the LoadedWasmSandbox instances from the queue and call a function on a new thread, then unload the module and
-        // push the WasmSandbox instance back to the wasm_sandbox_queue
+        let err = loaded_wasm_sandbox
+            .call_guest_function::<i32>("KeepCPUBusy", 10000i32)
+            .unwrap_err();
+        let timeout = GuestCallTimeout::from_error(&err).expect("call should time out");
+        assert_eq!(timeout.function_name, "KeepCPUBusy");
+        assert_eq!(timeout.timeout, Duration::from_millis(100));
+        assert!(loaded_wasm_sandbox.is_poisoned().unwrap());
 
-        let thread_handles: Vec<_> = (0..10)
-            .map(|i| {
-                let wq = wasm_sandbox_queue.clone();
-                let lwq = loaded_wasm_sandbox_queue.clone();
+        loaded_wasm_sandbox.restore(snapshot).unwrap();
+        let result: i32 = loaded_wasm_sandbox
+            .call_guest_function_with_timeout("CalcFib", 10i32, Duration::from_secs(10))
+            .unwrap();
+        assert_eq!(result, 55);
+    }
 
-                thread::spawn(move || {
-                    println!("Popping sandbox on thread {}", i);
-                    let loaded_wasm_sandbox = lwq.pop().unwrap();
-                    println!("Calling funcs on thread {}", i);
-                    let lws = call_funcs(loaded_wasm_sandbox, 1);
-                    println!("Unloading module on thread {}", i);
-                    let ws = lws.unload_module().unwrap();
-                    println!("Pusing WasmSandbox on thread {}", i);
-                    wq.push(ws).unwrap();
-                })
-            })
-            .collect::<Vec<_>>();
+    #[test]
+    fn test_guest_call_cancellable() {
+        let mut sandbox = SandboxBuilder::new().build().unwrap();
+        sandbox
+            .register(
+                "GetTimeSinceBootMicrosecond",
+                get_time_since_boot_microsecond,
+            )
+            .unwrap();
+        let wasm_sandbox = sandbox.load_runtime().unwrap();
+        let mod_path = get_wasm_module_path("RunWasm.aot").unwrap();
+        let mut loaded_wasm_sandbox = wasm_sandbox.load_module(mod_path).unwrap();
+        let snapshot = loaded_wasm_sandbox.snapshot().unwrap();
 
-        for handle in thread_handles {
-            handle.join().unwrap();
-        }
+        // A call that is not cancelled completes
+        let token = CancellationToken::new();
+        let result: i32 = loaded_wasm_sandbox
+            .call_guest_function_cancellable("CalcFib", 10i32, &token)
+            .unwrap();
+        assert_eq!(result, 55);
 
-        // Now get the sandbox back from the queue and load the module and call a function
-        // this time we will load the .wasm version of the module rather than the .aot version
+        let canceller = token.clone();
+        let cancel_thread = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            canceller.cancel();
+        });
+        let err = loaded_wasm_sandbox
+            .call_guest_function_cancellable::<i32>("KeepCPUBusy", 10000i32, &token)
+            .unwrap_err();
+        cancel_thread.join().unwrap();
+        let cancelled = GuestCallCancelled::from_error(&err).expect("call should be cancelled");
+        assert_eq!(cancelled.function_name, "KeepCPUBusy");
+        assert!(cancelled.started);
+        assert!(loaded_wasm_sandbox.is_poisoned().unwrap());
 
-        let thread_handles: Vec<_> = (0..10)
-            .map(|i| {
-                let wq = wasm_sandbox_queue.clone();
+        // A call passed a cancelled token does not start
+        loaded_wasm_sandbox.restore(snapshot).unwrap();
+        let err = loaded_wasm_sandbox
+            .call_guest_function_cancellable::<i32>("CalcFib", 10i32, &token)
+            .unwrap_err();
+        assert!(!GuestCallCancelled::from_error(&err).unwrap().started);
+        assert!(!loaded_wasm_sandbox.is_poisoned().unwrap());
+    }
+
+    #[test]
+    fn test_call_time_budgets() {
+        // The guest is interrupted once it has spent its own budget
+        let mut sandbox = SandboxBuilder::new()
+            .with_guest_time_budget(Duration::from_millis(100))
+            .build()
+            .unwrap();
+        sandbox
+            .register(
+                "GetTimeSinceBootMicrosecond",
+                get_time_since_boot_microsecond,
+            )
+            .unwrap();
+        let wasm_sandbox = sandbox.load_runtime().unwrap();
+        let mod_path = get_wasm_module_path("RunWasm.aot").unwrap();
+        let mut loaded_wasm_sandbox = wasm_sandbox.load_module(mod_path).unwrap();
+        let err = loaded_wasm_sandbox
+            .call_guest_function::<i32>("KeepCPUBusy", 10000i32)
+            .unwrap_err();
+        assert_eq!(
+            CallBudgetExceeded::from_error(&err),
+            Some(&CallBudgetExceeded::GuestTime {
+                function_name: "KeepCPUBusy".to_string(),
+                budget: Duration::from_millis(100),
+            })
+        );
+
+        // Time spent in host functions counts against their budget, not
+        // the guest's
+        let mut sandbox = SandboxBuilder::new()
+            .with_guest_time_budget(Duration::from_millis(100))
+            .with_host_time_budget(Duration::from_millis(150))
+            .build()
+            .unwrap();
+        sandbox
+            .register("TestHostFunc", |ms: i32| {
+                thread::sleep(Duration::from_millis(ms as u64));
+                Ok(ms)
+            })
+            .unwrap();
+        let wasm_sandbox = sandbox.load_runtime().unwrap();
+        let mod_path = get_wasm_module_path("rust_wasm_samples.aot").unwrap();
+        let mut loaded_wasm_sandbox = wasm_sandbox.load_module(mod_path).unwrap();
+        let outcome = loaded_wasm_sandbox
+            .call_guest_function_detailed::<i32>("call_host_function", 120i32)
+            .unwrap();
+        assert_eq!(outcome.value, 120);
+        assert!(outcome.host_time >= Duration::from_millis(120));
+
+        let err = loaded_wasm_sandbox
+            .call_guest_function::<i32>("call_host_function", 200i32)
+            .unwrap_err();
+        assert_eq!(
+            CallBudgetExceeded::from_error(&err),
+            Some(&CallBudgetExceeded::HostTime {
+                function_name: "call_host_function".to_string(),
+                budget: Duration::from_millis(150),
+            })
+        );
+        // The budget applies to each call
+        let result: i32 = loaded_wasm_sandbox
+            .call_guest_function("call_host_function", 1i32)
+            .unwrap();
+        assert_eq!(result, 1);
+    }
+
+    #[test]
+    #[cfg(feature = "aot")]
+    fn test_max_wasm_memory() {
+        // A module with one page of memory exporting `grow(pages) -> i32`,
+        // which returns the result of `memory.grow`, and
+        // `grow_or_trap(pages) -> i32`, which traps if it fails
+        const GROW_MODULE: &[u8] = &[
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+            0x01, 0x06, 0x01, 0x60, 0x01, 0x7f, 0x01, 0x7f, // types
+            0x03, 0x03, 0x02, 0x00, 0x00, // functions
+            0x05, 0x03, 0x01, 0x00, 0x01, // memory
+            0x07, 0x17, 0x02, 0x04, b'g', b'r', b'o', b'w', 0x00, 0x00, 0x0c, b'g', b'r', b'o',
+            b'w', b'_', b'o', b'r', b'_', b't', b'r', b'a', b'p', 0x00, 0x01, // exports
+            0x0a, 0x1a, 0x02, 0x06, 0x00, 0x20, 0x00, 0x40, 0x00, 0x0b, 0x11, 0x00, 0x20, 0x00,
+            0x40, 0x00, 0x22, 0x00, 0x41, 0x7f, 0x46, 0x04, 0x40, 0x00, 0x0b, 0x20, 0x00,
+            0x0b, // code
+        ];
+        const PAGE: usize = 65536;
+
+        let mut loaded_wasm_sandbox = SandboxBuilder::new()
+            .with_max_wasm_memory(2 * PAGE)
+            .build()
+            .unwrap()
+            .load_runtime()
+            .unwrap()
+            .load_module_from_buffer(GROW_MODULE)
+            .unwrap();
+
+        // Growth beyond the limit fails in the guest, which can handle it
+        let result: i32 = loaded_wasm_sandbox
+            .call_guest_function("grow", 1i32)
+            .unwrap();
+        assert_eq!(result, 1);
+        let result: i32 = loaded_wasm_sandbox
+            .call_guest_function("grow", 1i32)
+            .unwrap();
+        assert_eq!(result, -1);
+
+        let err = loaded_wasm_sandbox
+            .call_guest_function::<i32>("grow_or_trap", 1i32)
+            .unwrap_err();
+        assert_eq!(
+            WasmLimitExceeded::from_error(&err),
+            Some(&WasmLimitExceeded::Memory {
+                function_name: "grow_or_trap".to_string(),
+                max_bytes: 2 * PAGE as u64,
+            })
+        );
+        assert!(!loaded_wasm_sandbox.is_poisoned().unwrap());
+
+        // The memory keeps the size it had before growth was refused
+        let result: i32 = loaded_wasm_sandbox
+            .call_guest_function("grow", 0i32)
+            .unwrap();
+        assert_eq!(result, 2);
+    }
+
+    #[test]
+    #[cfg(feature = "aot")]
+    fn test_guest_aborted() {
+        // A module exporting `quit(code)`, which calls the WASI
+        // `proc_exit` function with `code`
+        const EXIT_MODULE: &[u8] = &[
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+            0x01, 0x05, 0x01, 0x60, 0x01, 0x7f, 0x00, // types
+            0x02, 0x24, 0x01, 0x16, b'w', b'a', b's', b'i', b'_', b's', b'n', b'a', b'p', b's',
+            b'h', b'o', b't', b'_', b'p', b'r', b'e', b'v', b'i', b'e', b'w', b'1', 0x09, b'p',
+            b'r', b'o', b'c', b'_', b'e', b'x', b'i', b't', 0x00, 0x00, // imports
+            0x03, 0x02, 0x01, 0x00, // functions
+            0x07, 0x08, 0x01, 0x04, b'q', b'u', b'i', b't', 0x00, 0x01, // exports
+            0x0a, 0x08, 0x01, 0x06, 0x00, 0x20, 0x00, 0x10, 0x00, 0x0b, // code
+        ];
+
+        let mut loaded_wasm_sandbox = SandboxBuilder::new()
+            .build()
+            .unwrap()
+            .load_runtime()
+            .unwrap()
+            .load_module_from_buffer(EXIT_MODULE)
+            .unwrap();
+        assert!(loaded_wasm_sandbox.stubbed_wasi_imports().is_empty());
+
+        let err = loaded_wasm_sandbox
+            .call_guest_function::<()>("quit", 3i32)
+            .unwrap_err();
+        assert_eq!(
+            GuestAborted::from_error(&err),
+            Some(&GuestAborted {
+                function_name: "quit".to_string(),
+                message: String::new(),
+                exit_code: Some(3),
+            })
+        );
+        assert!(!loaded_wasm_sandbox.is_poisoned().unwrap());
+    }
+
+    // A module exporting `open(oflags) -> errno`, which opens
+    // `hello.txt` in the directory preopened at fd 3 with the WASI
+    // `path_open` function and stores its fd at address 0, and
+    // `read4() -> i32`, which reads the first 4 bytes of that file
+    // with `fd_read`, returning them or the negated errno
+    #[cfg(feature = "aot")]
+    const FS_MODULE: &[u8] = &[
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+        0x01, 0x1f, 0x04, 0x60, 0x09, 0x7f, 0x7f, 0x7f, 0x7f, 0x7f, 0x7e, 0x7e, 0x7f, 0x7f, 0x01,
+        0x7f, 0x60, 0x04, 0x7f, 0x7f, 0x7f, 0x7f, 0x01, 0x7f, 0x60, 0x01, 0x7f, 0x01, 0x7f, 0x60,
+        0x00, 0x01, 0x7f, // types
+        0x02, 0x45, 0x02, 0x16, b'w', b'a', b's', b'i', b'_', b's', b'n', b'a', b'p', b's', b'h',
+        b'o', b't', b'_', b'p', b'r', b'e', b'v', b'i', b'e', b'w', b'1', 0x09, b'p', b'a', b't',
+        b'h', b'_', b'o', b'p', b'e', b'n', 0x00, 0x00, 0x16, b'w', b'a', b's', b'i', b'_', b's',
+        b'n', b'a', b'p', b's', b'h', b'o', b't', b'_', b'p', b'r', b'e', b'v', b'i', b'e', b'w',
+        b'1', 0x07, b'f', b'd', b'_', b'r', b'e', b'a', b'd', 0x00, 0x01, // imports
+        0x03, 0x03, 0x02, 0x02, 0x03, // functions
+        0x05, 0x03, 0x01, 0x00, 0x01, // memory
+        0x07, 0x19, 0x03, 0x04, b'o', b'p', b'e', b'n', 0x00, 0x02, 0x05, b'r', b'e', b'a', b'd',
+        b'4', 0x00, 0x03, 0x06, b'm', b'e', b'm', b'o', b'r', b'y', 0x02, 0x00, // exports
+        0x0a, 0x4a, 0x02, 0x16, 0x00, 0x41, 0x03, 0x41, 0x00, 0x41, 0x10, 0x41, 0x09, 0x20, 0x00,
+        0x42, 0x02, 0x42, 0x00, 0x41, 0x00, 0x41, 0x00, 0x10, 0x00, 0x0b, 0x31, 0x01, 0x01, 0x7f,
+        0x41, 0x20, 0x41, 0xc0, 0x00, 0x36, 0x02, 0x00, 0x41, 0x24, 0x41, 0x04, 0x36, 0x02, 0x00,
+        0x41, 0x00, 0x28, 0x02, 0x00, 0x41, 0x20, 0x41, 0x01, 0x41, 0x28, 0x10, 0x01, 0x22, 0x00,
+        0x04, 0x40, 0x41, 0x00, 0x20, 0x00, 0x6b, 0x0f, 0x0b, 0x41, 0xc0, 0x00, 0x28, 0x02, 0x00,
+        0x0b, // code
+        0x0b, 0x0f, 0x01, 0x00, 0x41, 0x10, 0x0b, 0x09, b'h', b'e', b'l', b'l', b'o', b'.', b't',
+        b'x', b't', // data
+    ];
+
+    #[test]
+    #[cfg(feature = "aot")]
+    fn test_preopened_dir() {
+        const ERRNO_ROFS: i32 = 69;
+        const OFLAGS_CREAT: i32 = 1;
+
+        let dir = std::env::temp_dir().join(format!("hlwasm-preopened-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("hello.txt"), b"wasm file").unwrap();
+
+        let mut loaded_wasm_sandbox = SandboxBuilder::new()
+            .with_preopened_dir(&dir, "/data", DirAccess::ReadOnly)
+            .build()
+            .unwrap()
+            .load_runtime()
+            .unwrap()
+            .load_module_from_buffer(FS_MODULE)
+            .unwrap();
+        assert!(loaded_wasm_sandbox.stubbed_wasi_imports().is_empty());
+
+        let errno: i32 = loaded_wasm_sandbox
+            .call_guest_function("open", 0i32)
+            .unwrap();
+        assert_eq!(errno, 0);
+        let bytes: i32 = loaded_wasm_sandbox
+            .call_guest_function("read4", ())
+            .unwrap();
+        assert_eq!(bytes.to_le_bytes(), *b"wasm");
+
+        // Files cannot be created in a read-only directory
+        let errno: i32 = loaded_wasm_sandbox
+            .call_guest_function("open", OFLAGS_CREAT)
+            .unwrap();
+        assert_eq!(errno, ERRNO_ROFS);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "aot")]
+    fn test_vfs() {
+        const ERRNO_NOENT: i32 = 44;
+
+        let mut loaded_wasm_sandbox = SandboxBuilder::new()
+            .with_vfs(vec![("/hello.txt".to_string(), b"in memory".to_vec())])
+            .build()
+            .unwrap()
+            .load_runtime()
+            .unwrap()
+            .load_module_from_buffer(FS_MODULE)
+            .unwrap();
+
+        let errno: i32 = loaded_wasm_sandbox
+            .call_guest_function("open", 0i32)
+            .unwrap();
+        assert_eq!(errno, 0);
+        let bytes: i32 = loaded_wasm_sandbox
+            .call_guest_function("read4", ())
+            .unwrap();
+        assert_eq!(bytes.to_le_bytes(), *b"in m");
+
+        // Other paths are not looked up on the host
+        let mut loaded_wasm_sandbox = SandboxBuilder::new()
+            .with_vfs(vec![("/other.txt".to_string(), Vec::new())])
+            .build()
+            .unwrap()
+            .load_runtime()
+            .unwrap()
+            .load_module_from_buffer(FS_MODULE)
+            .unwrap();
+        let errno: i32 = loaded_wasm_sandbox
+            .call_guest_function("open", 0i32)
+            .unwrap();
+        assert_eq!(errno, ERRNO_NOENT);
+    }
+
+    #[test]
+    #[cfg(feature = "aot")]
+    fn test_cli_environment() {
+        // A module exporting `argc() -> i32` and `environc() -> i32`,
+        // which return the counts from the WASI `args_sizes_get` and
+        // `environ_sizes_get` functions, and `arg(i) -> i32` and
+        // `env(i) -> i32`, which return the first 4 bytes of the `i`th
+        // string from `args_get` and `environ_get`
+        const ENV_MODULE: &[u8] = &[
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+            0x01, 0x10, 0x03, 0x60, 0x02, 0x7f, 0x7f, 0x01, 0x7f, 0x60, 0x00, 0x01, 0x7f, 0x60,
+            0x01, 0x7f, 0x01, 0x7f, // types
+            0x02, 0x9b, 0x01, 0x04, 0x16, b'w', b'a', b's', b'i', b'_', b's', b'n', b'a', b'p',
+            b's', b'h', b'o', b't', b'_', b'p', b'r', b'e', b'v', b'i', b'e', b'w', b'1', 0x0e,
+            b'a', b'r', b'g', b's', b'_', b's', b'i', b'z', b'e', b's', b'_', b'g', b'e', b't',
+            0x00, 0x00, 0x16, b'w', b'a', b's', b'i', b'_', b's', b'n', b'a', b'p', b's', b'h',
+            b'o', b't', b'_', b'p', b'r', b'e', b'v', b'i', b'e', b'w', b'1', 0x08, b'a', b'r',
+            b'g', b's', b'_', b'g', b'e', b't', 0x00, 0x00, 0x16, b'w', b'a', b's', b'i', b'_',
+            b's', b'n', b'a', b'p', b's', b'h', b'o', b't', b'_', b'p', b'r', b'e', b'v', b'i',
+            b'e', b'w', b'1', 0x11, b'e', b'n', b'v', b'i', b'r', b'o', b'n', b'_', b's', b'i',
+            b'z', b'e', b's', b'_', b'g', b'e', b't', 0x00, 0x00, 0x16, b'w', b'a', b's', b'i',
+            b'_', b's', b'n', b'a', b'p', b's', b'h', b'o', b't', b'_', b'p', b'r', b'e', b'v',
+            b'i', b'e', b'w', b'1', 0x0b, b'e', b'n', b'v', b'i', b'r', b'o', b'n', b'_', b'g',
+            b'e', b't', 0x00, 0x00, // imports
+            0x03, 0x05, 0x04, 0x01, 0x01, 0x02, 0x02, // functions
+            0x05, 0x03, 0x01, 0x00, 0x01, // memory
+            0x07, 0x28, 0x05, 0x04, b'a', b'r', b'g', b'c', 0x00, 0x04, 0x08, b'e', b'n', b'v',
+            b'i', b'r', b'o', b'n', b'c', 0x00, 0x05, 0x03, b'a', b'r', b'g', 0x00, 0x06, 0x03,
+            b'e', b'n', b'v', 0x00, 0x07, 0x06, b'm', b'e', b'm', b'o', b'r', b'y', 0x02,
+            0x00, // exports
+            0x0a, 0x4f, 0x04, 0x0e, 0x00, 0x41, 0x00, 0x41, 0x04, 0x10, 0x00, 0x1a, 0x41, 0x00,
+            0x28, 0x02, 0x00, 0x0b, 0x0e, 0x00, 0x41, 0x00, 0x41, 0x04, 0x10, 0x02, 0x1a, 0x41,
+            0x00, 0x28, 0x02, 0x00, 0x0b, 0x17, 0x00, 0x41, 0x80, 0x08, 0x41, 0x80, 0x10, 0x10,
+            0x01, 0x1a, 0x20, 0x00, 0x41, 0x04, 0x6c, 0x28, 0x02, 0x80, 0x08, 0x28, 0x02, 0x00,
+            0x0b, 0x17, 0x00, 0x41, 0x80, 0x08, 0x41, 0x80, 0x10, 0x10, 0x03, 0x1a, 0x20, 0x00,
+            0x41, 0x04, 0x6c, 0x28, 0x02, 0x80, 0x08, 0x28, 0x02, 0x00, 0x0b, // code
+        ];
+
+        let mut loaded_wasm_sandbox = SandboxBuilder::new()
+            .with_args(vec!["prog".to_string(), "--verbose".to_string()])
+            .with_env("HOME", "/home/guest")
+            .with_env("LANG", "C")
+            .build()
+            .unwrap()
+            .load_runtime()
+            .unwrap()
+            .load_module_from_buffer(ENV_MODULE)
+            .unwrap();
+        let argc: i32 = loaded_wasm_sandbox.call_guest_function("argc", ()).unwrap();
+        assert_eq!(argc, 2);
+        let arg: i32 = loaded_wasm_sandbox
+            .call_guest_function("arg", 1i32)
+            .unwrap();
+        assert_eq!(arg.to_le_bytes(), *b"--ve");
+        let environc: i32 = loaded_wasm_sandbox
+            .call_guest_function("environc", ())
+            .unwrap();
+        assert_eq!(environc, 2);
+        let env: i32 = loaded_wasm_sandbox
+            .call_guest_function("env", 1i32)
+            .unwrap();
+        assert_eq!(env.to_le_bytes(), *b"LANG");
+
+        // Nothing is set by default
+        let mut loaded_wasm_sandbox = SandboxBuilder::new()
+            .build()
+            .unwrap()
+            .load_runtime()
+            .unwrap()
+            .load_module_from_buffer(ENV_MODULE)
+            .unwrap();
+        let argc: i32 = loaded_wasm_sandbox.call_guest_function("argc", ()).unwrap();
+        assert_eq!(argc, 0);
+        let environc: i32 = loaded_wasm_sandbox
+            .call_guest_function("environc", ())
+            .unwrap();
+        assert_eq!(environc, 0);
+
+        assert!(SandboxBuilder::new().with_env("A=B", "C").build().is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "aot")]
+    fn test_run_start() {
+        // A command module whose `_start` calls `proc_exit` with the code
+        // stored at address 0 by `set_exit_code(code)`, unless it is 0
+        const COMMAND_MODULE: &[u8] = &[
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+            0x01, 0x08, 0x02, 0x60, 0x01, 0x7f, 0x00, 0x60, 0x00, 0x00, // types
+            0x02, 0x24, 0x01, 0x16, b'w', b'a', b's', b'i', b'_', b's', b'n', b'a', b'p', b's',
+            b'h', b'o', b't', b'_', b'p', b'r', b'e', b'v', b'i', b'e', b'w', b'1', 0x09, b'p',
+            b'r', b'o', b'c', b'_', b'e', b'x', b'i', b't', 0x00, 0x00, // imports
+            0x03, 0x03, 0x02, 0x01, 0x00, // functions
+            0x05, 0x03, 0x01, 0x00, 0x01, // memory
+            0x07, 0x23, 0x03, 0x06, b'_', b's', b't', b'a', b'r', b't', 0x00, 0x01, 0x0d, b's',
+            b'e', b't', b'_', b'e', b'x', b'i', b't', b'_', b'c', b'o', b'd', b'e', 0x00, 0x02,
+            0x06, b'm', b'e', b'm', b'o', b'r', b'y', 0x02, 0x00, // exports
+            0x0a, 0x1d, 0x02, 0x11, 0x00, 0x41, 0x00, 0x28, 0x02, 0x00, 0x04, 0x40, 0x41, 0x00,
+            0x28, 0x02, 0x00, 0x10, 0x00, 0x0b, 0x0b, 0x09, 0x00, 0x41, 0x00, 0x20, 0x00, 0x36,
+            0x02, 0x00, 0x0b, // code
+        ];
+
+        let mut loaded_wasm_sandbox = SandboxBuilder::new()
+            .build()
+            .unwrap()
+            .load_runtime()
+            .unwrap()
+            .load_module_from_buffer(COMMAND_MODULE)
+            .unwrap();
+        let status = loaded_wasm_sandbox.run_start().unwrap();
+        assert!(status.success());
+
+        let _: () = loaded_wasm_sandbox
+            .call_guest_function("set_exit_code", 3i32)
+            .unwrap();
+        let status = loaded_wasm_sandbox.run_start().unwrap();
+        assert_eq!(status.code(), 3);
+        assert!(!loaded_wasm_sandbox.is_poisoned().unwrap());
+
+        // Modules that are not commands cannot be run
+        let mut loaded_wasm_sandbox = SandboxBuilder::new()
+            .build()
+            .unwrap()
+            .load_runtime()
+            .unwrap()
+            .load_module_from_buffer(FS_MODULE)
+            .unwrap();
+        assert!(loaded_wasm_sandbox.run_start().is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "aot")]
+    fn test_tail_calls() {
+        // A module exporting `count(n) -> i32`, which counts down from `n`
+        // in a function that calls itself with `return_call`, returning
+        // the number of calls
+        const TAIL_CALL_MODULE: &[u8] = &[
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+            0x01, 0x0c, 0x02, 0x60, 0x02, 0x7f, 0x7f, 0x01, 0x7f, 0x60, 0x01, 0x7f, 0x01,
+            0x7f, // types
+            0x03, 0x03, 0x02, 0x00, 0x01, // functions
+            0x05, 0x03, 0x01, 0x00, 0x01, // memory
+            0x07, 0x12, 0x02, 0x05, b'c', b'o', b'u', b'n', b't', 0x00, 0x01, 0x06, b'm', b'e',
+            b'm', b'o', b'r', b'y', 0x02, 0x00, // exports
+            0x0a, 0x22, 0x02, 0x17, 0x00, 0x20, 0x00, 0x45, 0x04, 0x7f, 0x20, 0x01, 0x05, 0x20,
+            0x00, 0x41, 0x01, 0x6b, 0x20, 0x01, 0x41, 0x01, 0x6a, 0x12, 0x00, 0x0b, 0x0b, 0x08,
+            0x00, 0x20, 0x00, 0x41, 0x00, 0x10, 0x00, 0x0b, // code
+        ];
+
+        let mut loaded_wasm_sandbox = SandboxBuilder::new()
+            .build()
+            .unwrap()
+            .load_runtime()
+            .unwrap()
+            .load_module_from_buffer(TAIL_CALL_MODULE)
+            .unwrap();
+        // Far deeper than the wasm stack allows for ordinary calls
+        let calls: i32 = loaded_wasm_sandbox
+            .call_guest_function("count", 1_000_000i32)
+            .unwrap();
+        assert_eq!(calls, 1_000_000);
+    }
+
+    #[test]
+    #[cfg(all(feature = "aot", feature = "gc"))]
+    fn test_gc() {
+        // A module exporting `point() -> i32`, which allocates a struct
+        // with a field of 7 and returns the field
+        const GC_MODULE: &[u8] = &[
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+            0x01, 0x09, 0x02, 0x5f, 0x01, 0x7f, 0x00, 0x60, 0x00, 0x01, 0x7f, // types
+            0x03, 0x02, 0x01, 0x01, // functions
+            0x07, 0x09, 0x01, 0x05, b'p', b'o', b'i', b'n', b't', 0x00, 0x00, // exports
+            0x0a, 0x0d, 0x01, 0x0b, 0x00, 0x41, 0x07, 0xfb, 0x00, 0x00, 0xfb, 0x02, 0x00, 0x00,
+            0x0b, // code
+        ];
+
+        let mut loaded_wasm_sandbox = SandboxBuilder::new()
+            .build()
+            .unwrap()
+            .load_runtime()
+            .unwrap()
+            .load_module_from_buffer(GC_MODULE)
+            .unwrap();
+        let field: i32 = loaded_wasm_sandbox
+            .call_guest_function("point", ())
+            .unwrap();
+        assert_eq!(field, 7);
+    }
+
+    #[test]
+    #[cfg(feature = "aot")]
+    fn test_stack_exhausted() {
+        // A module exporting `recurse(n) -> i32`, which calls itself forever
+        const RECURSIVE_MODULE: &[u8] = &[
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+            0x01, 0x06, 0x01, 0x60, 0x01, 0x7f, 0x01, 0x7f, // types
+            0x03, 0x02, 0x01, 0x00, // functions
+            0x07, 0x0b, 0x01, 0x07, b'r', b'e', b'c', b'u', b'r', b's', b'e', 0x00,
+            0x00, // exports
+            0x0a, 0x08, 0x01, 0x06, 0x00, 0x20, 0x00, 0x10, 0x00, 0x0b, // code
+        ];
+
+        let mut loaded_wasm_sandbox = SandboxBuilder::new()
+            .with_max_wasm_stack(64 * 1024)
+            .build()
+            .unwrap()
+            .load_runtime()
+            .unwrap()
+            .load_module_from_buffer(RECURSIVE_MODULE)
+            .unwrap();
+
+        let err = loaded_wasm_sandbox
+            .call_guest_function::<i32>("recurse", 0i32)
+            .unwrap_err();
+        let exhausted = StackExhausted::from_error(&err).unwrap();
+        assert_eq!(exhausted.function_name, "recurse");
+        assert_eq!(exhausted.max_wasm_stack, 64 * 1024);
+        assert!(exhausted.frames.iter().all(|frame| frame.func_index == 0));
+        // Stack exhaustion is not reported as another trap
+        assert!(GuestTrap::from_error(&err).is_none());
+        assert!(!loaded_wasm_sandbox.is_poisoned().unwrap());
+    }
+
+    #[test]
+    #[cfg(feature = "aot")]
+    fn test_guest_trap() {
+        // A module with one page of memory exporting `load(addr) -> i32`,
+        // which loads from `addr`, and `abort()`, which is unreachable
+        const TRAP_MODULE: &[u8] = &[
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+            0x01, 0x09, 0x02, 0x60, 0x01, 0x7f, 0x01, 0x7f, 0x60, 0x00, 0x00, // types
+            0x03, 0x03, 0x02, 0x00, 0x01, // functions
+            0x05, 0x03, 0x01, 0x00, 0x01, // memory
+            0x07, 0x10, 0x02, 0x04, b'l', b'o', b'a', b'd', 0x00, 0x00, 0x05, b'a', b'b', b'o',
+            b'r', b't', 0x00, 0x01, // exports
+            0x0a, 0x0d, 0x02, 0x07, 0x00, 0x20, 0x00, 0x28, 0x02, 0x00, 0x0b, 0x03, 0x00, 0x00,
+            0x0b, // code
+        ];
+
+        let mut loaded_wasm_sandbox = SandboxBuilder::new()
+            .build()
+            .unwrap()
+            .load_runtime()
+            .unwrap()
+            .load_module_from_buffer(TRAP_MODULE)
+            .unwrap();
+
+        let err = loaded_wasm_sandbox
+            .call_guest_function::<i32>("load", 65536i32)
+            .unwrap_err();
+        let trap = GuestTrap::from_error(&err).unwrap();
+        assert_eq!(trap.function_name, "load");
+        assert_eq!(trap.code, TrapCode::MemoryOutOfBounds);
+        assert_eq!(trap.frames[0].func_index, 0);
+        assert!(!loaded_wasm_sandbox.is_poisoned().unwrap());
+
+        let err = loaded_wasm_sandbox
+            .call_guest_function::<()>("abort", ())
+            .unwrap_err();
+        let trap = GuestTrap::from_error(&err).unwrap();
+        assert_eq!(trap.code, TrapCode::UnreachableCodeReached);
+        assert_eq!(trap.frames[0].func_index, 1);
+
+        // Calls that do not trap are unaffected
+        let result: i32 = loaded_wasm_sandbox
+            .call_guest_function("load", 0i32)
+            .unwrap();
+        assert_eq!(result, 0);
+    }
+
+    #[test]
+    #[cfg(all(feature = "aot", target_os = "linux"))]
+    fn test_map_buffer() {
+        // A module importing the hlwasm:buffers `len` and `read`
+        // functions, exporting `size(addr) -> i64`, which returns the
+        // buffer's length, and `read_at(addr, offset) -> i32`, which
+        // returns the byte of the buffer at `offset`
+        const BUFFER_MODULE: &[u8] = &[
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+            0x01, 0x14, 0x03, 0x60, 0x01, 0x7e, 0x01, 0x7e, 0x60, 0x04, 0x7e, 0x7e, 0x7f, 0x7f,
+            0x01, 0x7f, 0x60, 0x02, 0x7e, 0x7e, 0x01, 0x7f, // types
+            0x02, 0x2c, 0x02, 0x0e, b'h', b'l', b'w', b'a', b's', b'm', 0x3a, b'b', b'u', b'f',
+            b'f', b'e', b'r', b's', 0x03, b'l', b'e', b'n', 0x00, 0x00, 0x0e, b'h', b'l', b'w',
+            b'a', b's', b'm', 0x3a, b'b', b'u', b'f', b'f', b'e', b'r', b's', 0x04, b'r', b'e',
+            b'a', b'd', 0x00, 0x01, // imports
+            0x03, 0x03, 0x02, 0x00, 0x02, // functions
+            0x05, 0x03, 0x01, 0x00, 0x01, // memory
+            0x07, 0x1b, 0x03, 0x04, b's', b'i', b'z', b'e', 0x00, 0x02, 0x07, b'r', b'e', b'a',
+            b'd', b'_', b'a', b't', 0x00, 0x03, 0x06, b'm', b'e', b'm', b'o', b'r', b'y', 0x02,
+            0x00, // exports
+            0x0a, 0x22, 0x02, 0x06, 0x00, 0x20, 0x00, 0x10, 0x00, 0x0b, 0x19, 0x00, 0x41, 0x00,
+            0x41, 0x00, 0x3a, 0x00, 0x00, 0x20, 0x00, 0x20, 0x01, 0x41, 0x00, 0x41, 0x01, 0x10,
+            0x01, 0x1a, 0x41, 0x00, 0x2d, 0x00, 0x00, 0x0b, // code
+        ];
+        const LEN: usize = 0x2000;
+
+        // Mapped buffers must be page aligned
+        let base = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                LEN,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        assert_ne!(base, libc::MAP_FAILED);
+        let bytes = unsafe { std::slice::from_raw_parts_mut(base as *mut u8, LEN) };
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = (i % 251) as u8;
+        }
+
+        let mut loaded_wasm_sandbox = SandboxBuilder::new()
+            .build()
+            .unwrap()
+            .load_runtime()
+            .unwrap()
+            .load_module_from_buffer(BUFFER_MODULE)
+            .unwrap();
+
+        let addr = unsafe { loaded_wasm_sandbox.map_buffer(base, LEN) }.unwrap();
+        let size: i64 = loaded_wasm_sandbox
+            .call_guest_function("size", addr.as_u64())
+            .unwrap();
+        assert_eq!(size, LEN as i64);
+        for offset in [0u64, 1, 300, LEN as u64 - 1] {
+            let byte: i32 = loaded_wasm_sandbox
+                .call_guest_function("read_at", (addr.as_u64(), offset))
+                .unwrap();
+            assert_eq!(byte, (offset % 251) as i32);
+        }
+
+        // Unmapped buffers, and addresses where nothing is mapped, cannot
+        // be accessed
+        loaded_wasm_sandbox.unmap_buffer(addr).unwrap();
+        let size: i64 = loaded_wasm_sandbox
+            .call_guest_function("size", addr.as_u64())
+            .unwrap();
+        assert_eq!(size, -1);
+        assert!(
+            loaded_wasm_sandbox
+                .call_guest_function::<i32>("read_at", (addr.as_u64(), 0u64))
+                .is_err()
+        );
+        assert!(loaded_wasm_sandbox.unmap_buffer(addr).is_err());
+
+        drop(loaded_wasm_sandbox);
+        unsafe { libc::munmap(base, LEN) };
+    }
+
+    #[test]
+    fn test_host_function_failed() {
+        let mut sandbox = SandboxBuilder::new().build().unwrap();
+        sandbox
+            .register("TestHostFunc", |a: i32| {
+                if a < 0 {
+                    Err(new_error!("negative value {}", a))
+                } else {
+                    Ok(a)
+                }
+            })
+            .unwrap();
+        let wasm_sandbox = sandbox.load_runtime().unwrap();
+        let mod_path = get_wasm_module_path("rust_wasm_samples.aot").unwrap();
+        let mut loaded_wasm_sandbox = wasm_sandbox.load_module(mod_path).unwrap();
+
+        let err = loaded_wasm_sandbox
+            .call_guest_function::<i32>("call_host_function", -1i32)
+            .unwrap_err();
+        let failed = HostFunctionFailed::from_error(&err).unwrap();
+        assert_eq!(failed.function_name, "call_host_function");
+        assert_eq!(failed.host_function, "TestHostFunc");
+        assert_eq!(failed.message, "negative value -1");
+        assert!(matches!(
+            failed.host_error(),
+            Some(HyperlightError::Error(message)) if message == "negative value -1"
+        ));
+        assert!(!loaded_wasm_sandbox.is_poisoned().unwrap());
+
+        // Calls whose host functions succeed are unaffected
+        let result: i32 = loaded_wasm_sandbox
+            .call_guest_function("call_host_function", 2i32)
+            .unwrap();
+        assert_eq!(result, 2);
+    }
+
+    #[test]
+    fn test_epoch_deadline() {
+        let mut sandbox = SandboxBuilder::new()
+            .with_epoch_interruption(true)
+            .build()
+            .unwrap();
+        sandbox.register("TestHostFunc", |a: i32| Ok(a)).unwrap();
+        let wasm_sandbox = sandbox.load_runtime().unwrap();
+        let mod_path = get_wasm_module_path("rust_wasm_samples_epoch.aot").unwrap();
+        let mut loaded_wasm_sandbox = wasm_sandbox.load_module(mod_path).unwrap();
+
+        loaded_wasm_sandbox
+            .set_epoch_deadline(Duration::from_millis(50))
+            .unwrap();
+        let err = loaded_wasm_sandbox
+            .call_guest_function::<u64>("spin", u64::MAX)
+            .unwrap_err();
+        assert_eq!(
+            EpochDeadlineExceeded::from_error(&err),
+            Some(&EpochDeadlineExceeded {
+                function_name: "spin".to_string(),
+                deadline: Duration::from_millis(50),
+            })
+        );
+
+        // The sandbox is not poisoned, and calls within the deadline
+        // still succeed
+        assert!(!loaded_wasm_sandbox.is_poisoned().unwrap());
+        let result: u64 = loaded_wasm_sandbox
+            .call_guest_function("spin", 1000u64)
+            .unwrap();
+        assert_eq!(result, 1000);
+
+        // The deadline survives restoring a snapshot
+        let snapshot = loaded_wasm_sandbox.snapshot().unwrap();
+        loaded_wasm_sandbox.restore(snapshot).unwrap();
+        assert!(
+            loaded_wasm_sandbox
+                .call_guest_function::<u64>("spin", u64::MAX)
+                .is_err()
+        );
+
+        loaded_wasm_sandbox.clear_epoch_deadline().unwrap();
+        let result: u64 = loaded_wasm_sandbox
+            .call_guest_function("spin", 1000u64)
+            .unwrap();
+        assert_eq!(result, 1000);
+
+        // Deadlines need guests compiled with epoch interruption
+        let mut sandbox = SandboxBuilder::new().build().unwrap();
+        sandbox.register("TestHostFunc", |a: i32| Ok(a)).unwrap();
+        let wasm_sandbox = sandbox.load_runtime().unwrap();
+        let mod_path = get_wasm_module_path("rust_wasm_samples.aot").unwrap();
+        let mut loaded_wasm_sandbox = wasm_sandbox.load_module(mod_path).unwrap();
+        assert!(
+            loaded_wasm_sandbox
+                .set_epoch_deadline(Duration::from_millis(50))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_sandbox_use_on_different_threads() {
+        let wasm_sandbox_queue = Arc::new(ArrayQueue::<WasmSandbox>::new(10));
+        let loaded_wasm_sandbox_queue = Arc::new(ArrayQueue::<LoadedWasmSandbox>::new(10));
+
+        // Create a queue of WasmSandbox instances
+        for i in 0..10 {
+            println!("Creating WasmSandbox instance {}", i);
+            let mut sandbox = ProtoWasmSandbox::default();
+
+            sandbox
+                .register(
+                    "GetTimeSinceBootMicrosecond",
+                    get_time_since_boot_microsecond,
+                )
+                .unwrap();
+
+            let wasm_sandbox = sandbox.load_runtime().unwrap();
+            wasm_sandbox_queue.push(wasm_sandbox).unwrap();
+            println!("Pushed WasmSandbox instance {}", i);
+        }
+
+        // Get the WasmSandbox instances from the queue and load the module on a new thread
+        // then call a function and push the LoadedWasmSandbox instance to the loaded_wasm_sandbox_queue
+        let thread_handles: Vec<_> = (0..10)
+            .map(|i| {
+                let wq = wasm_sandbox_queue.clone();
+                let lwq = loaded_wasm_sandbox_queue.clone();
 
                 thread::spawn(move || {
-                    println!("Popping WasmSandbox on thread {}", i);
-                    let wasm_sandbox = wq.pop().unwrap();
                     println!("Loading module on thread {}", i);
+                    let wasm_sandbox = wq.pop().unwrap();
                     let loaded_wasm_sandbox: LoadedWasmSandbox = {
                         let mod_path = get_wasm_module_path("RunWasm.aot").unwrap();
                         wasm_sandbox.load_module(mod_path)
                     }
                     .unwrap();
-                    println!("Calling function on thread {}", i);
-                    call_funcs(loaded_wasm_sandbox, 1);
-                })
-            })
-            .collect::<Vec<_>>();
+                    println!("Calling function on thread {}", i);
+                    let lws = call_funcs(loaded_wasm_sandbox, 1);
+                    lwq.push(lws).unwrap();
+                    println!("Pushed LoadedWasmSandbox instance to queue on thread {}", i)
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for handle in thread_handles {
+            handle.join().unwrap();
+        }
+
+        // Get the LoadedWasmSandbox instances from the queue and call a function on a new thread, then unload the module and
+        // push the WasmSandbox instance back to the wasm_sandbox_queue
+
+        let thread_handles: Vec<_> = (0..10)
+            .map(|i| {
+                let wq = wasm_sandbox_queue.clone();
+                let lwq = loaded_wasm_sandbox_queue.clone();
+
+                thread::spawn(move || {
+                    println!("Popping sandbox on thread {}", i);
+                    let loaded_wasm_sandbox = lwq.pop().unwrap();
+                    println!("Calling funcs on thread {}", i);
+                    let lws = call_funcs(loaded_wasm_sandbox, 1);
+                    println!("Unloading module on thread {}", i);
+                    let ws = lws.unload_module().unwrap();
+                    println!("Pusing WasmSandbox on thread {}", i);
+                    wq.push(ws).unwrap();
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for handle in thread_handles {
+            handle.join().unwrap();
+        }
+
+        // Now get the sandbox back from the queue and load the module and call a function
+        // this time we will load the .wasm version of the module rather than the .aot version
+
+        let thread_handles: Vec<_> = (0..10)
+            .map(|i| {
+                let wq = wasm_sandbox_queue.clone();
+
+                thread::spawn(move || {
+                    println!("Popping WasmSandbox on thread {}", i);
+                    let wasm_sandbox = wq.pop().unwrap();
+                    println!("Loading module on thread {}", i);
+                    let loaded_wasm_sandbox: LoadedWasmSandbox = {
+                        let mod_path = get_wasm_module_path("RunWasm.aot").unwrap();
+                        wasm_sandbox.load_module(mod_path)
+                    }
+                    .unwrap();
+                    println!("Calling function on thread {}", i);
+                    call_funcs(loaded_wasm_sandbox, 1);
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for handle in thread_handles {
+            handle.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_call_guest_functions_with_custom_config_multiple_times() {
+        let mut sandbox = SandboxBuilder::new()
+            .with_guest_scratch_size(32 * 1024)
+            .with_guest_heap_size(128 * 1024)
+            .build()
+            .unwrap();
+
+        sandbox
+            .register(
+                "GetTimeSinceBootMicrosecond",
+                get_time_since_boot_microsecond,
+            )
+            .unwrap();
+
+        let wasm_sandbox = sandbox.load_runtime().unwrap();
+
+        let loaded_wasm_sandbox: LoadedWasmSandbox = {
+            let mod_path = get_wasm_module_path("RunWasm.aot").unwrap();
+            wasm_sandbox.load_module(mod_path)
+        }
+        .unwrap();
+
+        call_funcs(loaded_wasm_sandbox, 1000);
+    }
+
+    #[test]
+    fn test_call_host_func_with_vecbytes() {
+        let host_func = |b: Vec<u8>, l: i32| {
+            // get the C String from the vec of bytes
+
+            let s = std::str::from_utf8(&b).unwrap();
+            println!("Host function received buffer: {}", s);
+
+            // check that s is the expected value if not return an error
+            if s != "Hello World!" {
+                return Err(new_error!("Unexpected value in buffer {}", s));
+            }
+
+            if l != 12 {
+                return Err(new_error!("Unexpected length of buffer {}", l));
+            }
+            Ok(0i32)
+        };
+
+        let mut proto_wasm_sandbox = SandboxBuilder::new().build().unwrap();
+
+        proto_wasm_sandbox
+            .register("HostFuncWithBufferAndLength", host_func)
+            .unwrap();
+
+        let wasm_sandbox = proto_wasm_sandbox.load_runtime().unwrap();
+
+        let mut loaded_wasm_sandbox: LoadedWasmSandbox = {
+            let mod_path = get_wasm_module_path("HostFunction.aot").unwrap();
+            wasm_sandbox.load_module(mod_path)
+        }
+        .unwrap();
+
+        // Call a guest function that calls a host function that takes a buffer and a length
+
+        let r: i32 = loaded_wasm_sandbox
+            .call_guest_function("PassBufferAndLengthToHost", ())
+            .unwrap();
+
+        assert_eq!(r, 0);
+    }
+
+    #[test]
+    fn test_load_module_fails_with_missing_host_function() {
+        // HostFunction.aot imports "HostFuncWithBufferAndLength" from "env".
+        // Loading it without registering that host function should fail
+        // at instantiation time (linker.instantiate) because the import
+        // cannot be satisfied.
+        let proto_wasm_sandbox = SandboxBuilder::new().build().unwrap();
+
+        let wasm_sandbox = proto_wasm_sandbox.load_runtime().unwrap();
+
+        let result: std::result::Result<LoadedWasmSandbox, _> = {
+            let mod_path = get_wasm_module_path("HostFunction.aot").unwrap();
+            wasm_sandbox.load_module(mod_path)
+        };
+
+        let err = result.unwrap_err();
+        let err_msg = format!("{:?}", err);
+        assert!(
+            err_msg.contains("HostFuncWithBufferAndLength"),
+            "Error should mention the missing host function, got: {err_msg}"
+        );
+    }
+
+    #[test]
+    fn test_load_module_checks_required_guest_abi() {
+        let load = |builder: SandboxBuilder, module: &str| {
+            let mut proto_wasm_sandbox = builder.build().unwrap();
+            proto_wasm_sandbox
+                .register("TestHostFunc", |a: i32| Ok(a))
+                .unwrap();
+            let wasm_sandbox = proto_wasm_sandbox.load_runtime().unwrap();
+            let mod_path = get_wasm_module_path(module).unwrap();
+            wasm_sandbox.load_module(mod_path)
+        };
+
+        // rust_wasm_samples declares version 1
+        load(
+            SandboxBuilder::new().with_required_guest_abi(1..=1),
+            "rust_wasm_samples.aot",
+        )
+        .unwrap();
+
+        let err = load(
+            SandboxBuilder::new().with_required_guest_abi(2..),
+            "rust_wasm_samples.aot",
+        )
+        .unwrap_err();
+        let err_msg = format!("{err:?}");
+        assert!(
+            err_msg.contains("guest ABI version mismatch") && err_msg.contains("version 1"),
+            "Error should describe the version mismatch, got: {err_msg}"
+        );
+
+        // RunWasm does not declare a version
+        let err = load(
+            SandboxBuilder::new().with_required_guest_abi(1..),
+            "RunWasm.aot",
+        )
+        .unwrap_err();
+        let err_msg = format!("{err:?}");
+        assert!(
+            err_msg.contains("hlwasm_abi_version"),
+            "Error should mention the missing export, got: {err_msg}"
+        );
+    }
+
+    #[test]
+    fn test_entropy_policy() {
+        let random_u32 = |builder: SandboxBuilder| -> i64 {
+            let mut proto_wasm_sandbox = builder.build().unwrap();
+            proto_wasm_sandbox
+                .register("TestHostFunc", |a: i32| Ok(a))
+                .unwrap();
+            let wasm_sandbox = proto_wasm_sandbox.load_runtime().unwrap();
+            let mod_path = get_wasm_module_path("rust_wasm_samples.aot").unwrap();
+            let mut loaded_wasm_sandbox = wasm_sandbox.load_module(mod_path).unwrap();
+            loaded_wasm_sandbox
+                .call_guest_function("random_u32", ())
+                .unwrap()
+        };
+
+        // Random numbers are denied by default
+        assert_eq!(random_u32(SandboxBuilder::new()), -1);
+
+        let seeded = random_u32(SandboxBuilder::new().with_rng_seed(42));
+        assert!(seeded >= 0);
+        assert_eq!(random_u32(SandboxBuilder::new().with_rng_seed(42)), seeded);
+        assert_ne!(random_u32(SandboxBuilder::new().with_rng_seed(43)), seeded);
+
+        let host =
+            random_u32(SandboxBuilder::new().with_entropy_policy(EntropyPolicy::HostEntropy));
+        assert!(host >= 0);
+    }
+
+    #[test]
+    fn test_register_cached() {
+        let host_calls = Arc::new(AtomicUsize::new(0));
+        let mut proto_wasm_sandbox = SandboxBuilder::new().build().unwrap();
+        let counter = host_calls.clone();
+        proto_wasm_sandbox
+            .register_cached(
+                "TestHostFunc",
+                move |a: i32| {
+                    counter.fetch_add(1, Ordering::Relaxed);
+                    Ok(a * 2)
+                },
+                HostFunctionCache {
+                    ttl: Duration::from_secs(3600),
+                    max_entries: 1,
+                },
+            )
+            .unwrap();
+        let wasm_sandbox = proto_wasm_sandbox.load_runtime().unwrap();
+
+        let mut loaded_wasm_sandbox: LoadedWasmSandbox = {
+            let mod_path = get_wasm_module_path("rust_wasm_samples.aot").unwrap();
+            wasm_sandbox.load_module(mod_path)
+        }
+        .unwrap();
+
+        let mut call = |a: i32| -> i32 {
+            loaded_wasm_sandbox
+                .call_guest_function("call_host_function", a)
+                .unwrap()
+        };
+        assert_eq!(call(1), 2);
+        assert_eq!(call(1), 2);
+        assert_eq!(host_calls.load(Ordering::Relaxed), 1);
+
+        // Only one result is kept
+        assert_eq!(call(2), 4);
+        assert_eq!(call(1), 2);
+        assert_eq!(host_calls.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn test_call_guest_function_with_callbacks() {
+        let mut proto_wasm_sandbox = SandboxBuilder::new().build().unwrap();
+        proto_wasm_sandbox
+            .register("TestHostFunc", |a: i32| Ok(a))
+            .unwrap();
+        let wasm_sandbox = proto_wasm_sandbox.load_runtime().unwrap();
+        let mod_path = get_wasm_module_path("rust_wasm_samples.aot").unwrap();
+        let mut loaded_wasm_sandbox = wasm_sandbox.load_module(mod_path).unwrap();
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let callback = Callback::new("TestHostFunc", move |a: i32| {
+            counter.fetch_add(1, Ordering::Relaxed);
+            Ok(a * 10)
+        });
+        let result: i32 = loaded_wasm_sandbox
+            .call_guest_function_with_callbacks("call_host_function", 2i32, &[callback])
+            .unwrap();
+        assert_eq!(result, 20);
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+
+        // The registered host function is back for later calls
+        let result: i32 = loaded_wasm_sandbox
+            .call_guest_function("call_host_function", 2i32)
+            .unwrap();
+        assert_eq!(result, 2);
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+
+        // Callbacks must replace a registered host function
+        let callback = Callback::new("Unregistered", |a: i32| Ok(a));
+        assert!(
+            loaded_wasm_sandbox
+                .call_guest_function_with_callbacks::<i32>("call_host_function", 2i32, &[callback])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_register_after_load_runtime() {
+        let proto_wasm_sandbox = SandboxBuilder::new().build().unwrap();
+        let mut wasm_sandbox = proto_wasm_sandbox.load_runtime().unwrap();
+        wasm_sandbox
+            .register("TestHostFunc", |a: i32| Ok(a * 3))
+            .unwrap();
+
+        let mod_path = get_wasm_module_path("rust_wasm_samples.aot").unwrap();
+        let mut loaded_wasm_sandbox = wasm_sandbox.load_module(&mod_path).unwrap();
+        let result: i32 = loaded_wasm_sandbox
+            .call_guest_function("call_host_function", 2)
+            .unwrap();
+        assert_eq!(result, 6);
+
+        // Host functions can also be replaced while a module is loaded
+        loaded_wasm_sandbox
+            .register("TestHostFunc", |a: i32| Ok(a * 5))
+            .unwrap();
+        let wasm_sandbox = loaded_wasm_sandbox.unload_module().unwrap();
+        let mut loaded_wasm_sandbox = wasm_sandbox.load_module(&mod_path).unwrap();
+        let result: i32 = loaded_wasm_sandbox
+            .call_guest_function("call_host_function", 2)
+            .unwrap();
+        assert_eq!(result, 10);
+    }
+
+    #[test]
+    fn test_required_exports() {
+        let load = |builder: SandboxBuilder| {
+            let mut proto_wasm_sandbox = builder.build().unwrap();
+            proto_wasm_sandbox
+                .register("TestHostFunc", |a: i32| Ok(a))
+                .unwrap();
+            let wasm_sandbox = proto_wasm_sandbox.load_runtime().unwrap();
+            let mod_path = get_wasm_module_path("rust_wasm_samples.aot").unwrap();
+            wasm_sandbox.load_module(mod_path)
+        };
+
+        load(
+            SandboxBuilder::new()
+                .with_required_exports([RequiredExport::new("call_host_function")
+                    .with_signature([ParameterType::Int], ReturnType::Int)]),
+        )
+        .unwrap();
+
+        let err = load(
+            SandboxBuilder::new().with_required_exports([
+                RequiredExport::from("init"),
+                RequiredExport::new("call_host_function")
+                    .with_signature([ParameterType::Long], ReturnType::Int),
+            ]),
+        )
+        .unwrap_err();
+        let err_msg = format!("{err:?}");
+        assert!(
+            err_msg.contains("init is not exported")
+                && err_msg.contains("call_host_function has type"),
+            "Error should describe every problem export, got: {err_msg}"
+        );
+    }
+
+    #[test]
+    fn test_guest_functions() {
+        let mut proto_wasm_sandbox = SandboxBuilder::new().build().unwrap();
+        proto_wasm_sandbox
+            .register("TestHostFunc", |a: i32| Ok(a))
+            .unwrap();
+        let wasm_sandbox = proto_wasm_sandbox.load_runtime().unwrap();
+        let mut loaded_wasm_sandbox = wasm_sandbox
+            .load_module(get_wasm_module_path("rust_wasm_samples.aot").unwrap())
+            .unwrap();
+
+        let functions = loaded_wasm_sandbox.guest_functions().unwrap();
+        let call_host_function = functions
+            .iter()
+            .find(|function| function.name == "call_host_function")
+            .unwrap();
+        assert_eq!(call_host_function.params, ["i32"]);
+        assert_eq!(call_host_function.results, ["i32"]);
+        let spin = functions
+            .iter()
+            .find(|function| function.name == "spin")
+            .unwrap();
+        assert_eq!(spin.params, ["i64"]);
+        assert_eq!(spin.results, ["i64"]);
+    }
+
+    #[test]
+    fn test_serialize_state() {
+        let load = || {
+            let mut proto_wasm_sandbox = SandboxBuilder::new().build().unwrap();
+            proto_wasm_sandbox
+                .register("TestHostFunc", |a: i32| Ok(a))
+                .unwrap();
+            let wasm_sandbox = proto_wasm_sandbox.load_runtime().unwrap();
+            wasm_sandbox
+                .load_module(get_wasm_module_path("rust_wasm_samples.aot").unwrap())
+                .unwrap()
+        };
+
+        let mut loaded_wasm_sandbox = load();
+        for _ in 0..3 {
+            let _: i32 = loaded_wasm_sandbox
+                .call_guest_function("increment_counter", ())
+                .unwrap();
+        }
+        let state = loaded_wasm_sandbox.serialize_state().unwrap();
+
+        // A separate sandbox picks up where the first one left off
+        let mut restored = load();
+        restored.restore_state(&state).unwrap();
+        let count: i32 = restored
+            .call_guest_function("increment_counter", ())
+            .unwrap();
+        assert_eq!(count, 4);
+
+        assert!(restored.restore_state(&state[..state.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_call_guest_function_chunked() {
+        let mut proto_wasm_sandbox = SandboxBuilder::new()
+            .with_guest_heap_size(16 * 1024 * 1024)
+            .build()
+            .unwrap();
+        proto_wasm_sandbox
+            .register("TestHostFunc", |a: i32| Ok(a))
+            .unwrap();
+        let wasm_sandbox = proto_wasm_sandbox.load_runtime().unwrap();
+        let mut loaded_wasm_sandbox = wasm_sandbox
+            .load_module(get_wasm_module_path("rust_wasm_samples.aot").unwrap())
+            .unwrap();
+
+        // Far larger than the default input buffer
+        let data: Vec<u8> = (0..2 * 1024 * 1024).map(|i| i as u8).collect();
+        let expected: u64 = data.iter().map(|b| *b as u64).sum();
+        let len = data.len() as i32;
+        let sum: u64 = loaded_wasm_sandbox
+            .call_guest_function_chunked("checksum", (data, len))
+            .unwrap();
+        assert_eq!(sum, expected);
+
+        // Small buffers are passed directly
+        let sum: u64 = loaded_wasm_sandbox
+            .call_guest_function_chunked("checksum", (vec![1u8, 2, 3], 3i32))
+            .unwrap();
+        assert_eq!(sum, 6);
+    }
+
+    #[test]
+    fn test_multi_value_results() {
+        let wasm_sandbox = SandboxBuilder::new()
+            .build()
+            .unwrap()
+            .load_runtime()
+            .unwrap();
+        let mut loaded_wasm_sandbox = wasm_sandbox
+            .load_module(get_wasm_module_path("multi_value.aot").unwrap())
+            .unwrap();
+
+        let values: MultiValue = loaded_wasm_sandbox
+            .call_guest_function("divmod", (7i32, 2i32))
+            .unwrap();
+        assert_eq!(values.0, [WasmValue::I32(3), WasmValue::I32(1)]);
+        let values: MultiValue = loaded_wasm_sandbox
+            .call_guest_function("mixed", ())
+            .unwrap();
+        assert_eq!(
+            values.0,
+            [
+                WasmValue::I64(1 << 40),
+                WasmValue::F32(1.5),
+                WasmValue::F64(-2.25)
+            ]
+        );
+
+        // Multiple values cannot be returned as a single value
+        assert!(
+            loaded_wasm_sandbox
+                .call_guest_function::<i32>("divmod", (7i32, 2i32))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_tinygo_guest_abi() {
+        let mut proto_wasm_sandbox = SandboxBuilder::new().build().unwrap();
+        proto_wasm_sandbox
+            .register("HostLog", |msg: String| Ok(msg.len() as i32))
+            .unwrap();
+        proto_wasm_sandbox
+            .register("HostBytes", |n: i32| Ok((1..=n as u8).collect::<Vec<u8>>()))
+            .unwrap();
+        let mut loaded_wasm_sandbox = proto_wasm_sandbox
+            .load_runtime()
+            .unwrap()
+            .with_guest_abi(GuestAbi::TinyGo)
+            .load_module(get_wasm_module_path("tinygo_abi.aot").unwrap())
+            .unwrap();
+
+        // Strings are passed as a pointer and a length, and returned
+        // packed into an i64
+        let greeting: String = loaded_wasm_sandbox
+            .call_guest_function("Greet", "Hyperlight".to_string())
+            .unwrap();
+        assert_eq!(greeting, "Hello, Hyperlight!");
+        let logged: i32 = loaded_wasm_sandbox
+            .call_guest_function("LogMessage", "héllo".to_string())
+            .unwrap();
+        assert_eq!(logged, 6);
+        let len: i32 = loaded_wasm_sandbox
+            .call_guest_function("ByteLength", (vec![0u8; 16], 16i32))
+            .unwrap();
+        assert_eq!(len, 16);
+
+        // Buffers returned by host functions are packed into an i64
+        let sum: i32 = loaded_wasm_sandbox
+            .call_guest_function("SumHostBytes", 10i32)
+            .unwrap();
+        assert_eq!(sum, 55);
+    }
+
+    #[test]
+    fn test_assemblyscript_guest_abi() {
+        let mut proto_wasm_sandbox = SandboxBuilder::new().build().unwrap();
+        proto_wasm_sandbox
+            .register("HostLog", |msg: String| Ok(msg.chars().count() as i32))
+            .unwrap();
+        let mut loaded_wasm_sandbox = proto_wasm_sandbox
+            .load_runtime()
+            .unwrap()
+            .with_guest_abi(GuestAbi::AssemblyScript)
+            .load_module(get_wasm_module_path("assemblyscript_abi.aot").unwrap())
+            .unwrap();
+
+        // Strings are passed and returned as UTF-16 managed objects
+        let greeting: String = loaded_wasm_sandbox
+            .call_guest_function("Greet", "Hyperlight 🦀".to_string())
+            .unwrap();
+        assert_eq!(greeting, "Hello, Hyperlight 🦀!");
+        let logged: i32 = loaded_wasm_sandbox
+            .call_guest_function("LogMessage", "héllo".to_string())
+            .unwrap();
+        assert_eq!(logged, 5);
+
+        // Buffers are passed as ArrayBuffer objects, which know their
+        // length
+        let len: i32 = loaded_wasm_sandbox
+            .call_guest_function("ByteLength", (vec![0u8; 16], 16i32))
+            .unwrap();
+        assert_eq!(len, 16);
+        let len: i32 = loaded_wasm_sandbox
+            .call_guest_function("ByteLength", (vec![0u8; 16], 15i32))
+            .unwrap();
+        assert_eq!(len, -1);
+    }
+
+    #[test]
+    fn test_capture_stdout() {
+        #[derive(Clone, Default)]
+        struct SharedBuffer(Arc<std::sync::Mutex<Vec<u8>>>);
+
+        impl std::io::Write for SharedBuffer {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let stdout = SharedBuffer::default();
+        let stderr = SharedBuffer::default();
+        let mut proto_wasm_sandbox = SandboxBuilder::new()
+            .with_stdout(stdout.clone())
+            .with_stderr(stderr.clone())
+            .build()
+            .unwrap();
+        proto_wasm_sandbox
+            .register("TestHostFunc", |a: i32| Ok(a))
+            .unwrap();
+        let wasm_sandbox = proto_wasm_sandbox.load_runtime().unwrap();
+        let mut loaded_wasm_sandbox = wasm_sandbox
+            .load_module(get_wasm_module_path("rust_wasm_samples.aot").unwrap())
+            .unwrap();
+
+        let result: i32 = loaded_wasm_sandbox
+            .call_guest_function("hello_world", ())
+            .unwrap();
+        assert_eq!(result, 0);
+        let written: i32 = loaded_wasm_sandbox
+            .call_guest_function("hello_stderr", ())
+            .unwrap();
+        assert_eq!(written, 31);
+        assert_eq!(
+            *stdout.0.lock().unwrap(),
+            b"Hello from Wasm in Hyperlight!\n"
+        );
+        assert_eq!(
+            *stderr.0.lock().unwrap(),
+            b"Error from Wasm in Hyperlight!\n"
+        );
+
+        // Without a writer, the guest cannot write to stderr
+        let mut proto_wasm_sandbox = SandboxBuilder::new().build().unwrap();
+        proto_wasm_sandbox
+            .register("TestHostFunc", |a: i32| Ok(a))
+            .unwrap();
+        let mut loaded_wasm_sandbox = proto_wasm_sandbox
+            .load_runtime()
+            .unwrap()
+            .load_module(get_wasm_module_path("rust_wasm_samples.aot").unwrap())
+            .unwrap();
+        let written: i32 = loaded_wasm_sandbox
+            .call_guest_function("hello_stderr", ())
+            .unwrap();
+        assert_eq!(written, 0);
+    }
+
+    #[test]
+    fn test_memory_stats() {
+        let mut proto_wasm_sandbox = SandboxBuilder::new()
+            .with_memory_metrics("test_memory_stats")
+            .build()
+            .unwrap();
+        proto_wasm_sandbox
+            .register("TestHostFunc", |a: i32| Ok(a))
+            .unwrap();
+        let mut loaded_wasm_sandbox = proto_wasm_sandbox
+            .load_runtime()
+            .unwrap()
+            .load_module(get_wasm_module_path("rust_wasm_samples.aot").unwrap())
+            .unwrap();
+        let snapshot = loaded_wasm_sandbox.snapshot().unwrap();
+
+        let stats = loaded_wasm_sandbox.memory_stats().unwrap();
+        assert!(stats.used_bytes > 0);
+        assert_eq!(stats.peak_used_bytes, stats.used_bytes);
+        assert_eq!(stats.linear_memories.len(), 1);
+        assert_eq!(stats.linear_memories[0].0, "memory");
+        assert!(stats.linear_memory_bytes() > 0);
+
+        // Touching more of the module's memory uses more guest memory
+        let data = vec![1u8; 1024 * 1024];
+        let _: u64 = loaded_wasm_sandbox
+            .call_guest_function_chunked("checksum", (data, 1024 * 1024i32))
+            .unwrap();
+        let grown = loaded_wasm_sandbox.memory_stats().unwrap();
+        assert!(grown.used_bytes > stats.used_bytes);
+        assert!(grown.linear_memory_bytes() > stats.linear_memory_bytes());
+
+        // Restoring the snapshot frees that memory, but not the peak
+        loaded_wasm_sandbox.restore(snapshot).unwrap();
+        let restored = loaded_wasm_sandbox.memory_stats().unwrap();
+        assert!(restored.used_bytes < grown.used_bytes);
+        assert!(restored.peak_used_bytes >= grown.used_bytes);
+    }
+
+    #[test]
+    #[cfg(feature = "crashdump")]
+    fn test_generate_crashdump_to_dir() {
+        let dir = std::env::temp_dir().join(format!("hl_wasm_dump_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut proto_wasm_sandbox = SandboxBuilder::new().build().unwrap();
+        proto_wasm_sandbox
+            .register("TestHostFunc", |a: i32| Ok(a))
+            .unwrap();
+        let mut loaded_wasm_sandbox = proto_wasm_sandbox
+            .load_runtime()
+            .unwrap()
+            .load_module(get_wasm_module_path("rust_wasm_samples.aot").unwrap())
+            .unwrap();
+
+        let path = loaded_wasm_sandbox
+            .generate_crashdump_to_dir(dir.to_string_lossy())
+            .unwrap();
+        let context: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        let core_dump = context["core_dump"].as_str().unwrap();
+        assert!(dir.join(core_dump).exists());
+        assert!(
+            context["exports"]
+                .as_array()
+                .unwrap()
+                .contains(&"increment_counter".into())
+        );
+        assert_eq!(context["linear_memories"][0]["name"], "memory");
+        assert!(
+            context["linear_memories"][0]["guest_addr"]
+                .as_u64()
+                .unwrap()
+                > 0
+        );
+        assert!(context["trap"].is_null());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "memory_inspection")]
+    fn test_read_memory() {
+        let mut proto_wasm_sandbox = SandboxBuilder::new().build().unwrap();
+        proto_wasm_sandbox
+            .register("TestHostFunc", |a: i32| Ok(a))
+            .unwrap();
+        let mut loaded_wasm_sandbox = proto_wasm_sandbox
+            .load_runtime()
+            .unwrap()
+            .load_module(get_wasm_module_path("rust_wasm_samples.aot").unwrap())
+            .unwrap();
+        let size = loaded_wasm_sandbox
+            .memory_stats()
+            .unwrap()
+            .linear_memory_bytes();
+
+        // Reads span several chunks, up to the end of the linear memory
+        let len = 3 * CHUNK_SIZE + 1;
+        let memory = loaded_wasm_sandbox
+            .read_wasm_memory(size - len as u64, len)
+            .unwrap();
+        assert_eq!(memory.len(), len);
+        assert!(loaded_wasm_sandbox.read_wasm_memory(size - 1, 2).is_err());
+
+        // The first page of the guest is never mapped, and failing to
+        // read it leaves the sandbox usable
+        assert!(loaded_wasm_sandbox.read_guest_memory(0, 1).is_err());
+        let count: i32 = loaded_wasm_sandbox
+            .call_guest_function("increment_counter", ())
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn test_guest_reads_sandbox_limits() {
+        let heap_size = 4 * 1024 * 1024;
+        let mut proto_wasm_sandbox = SandboxBuilder::new()
+            .with_guest_heap_size(heap_size)
+            .build()
+            .unwrap();
+        proto_wasm_sandbox
+            .register("TestHostFunc", |a: i32| Ok(a))
+            .unwrap();
+        let wasm_sandbox = proto_wasm_sandbox.load_runtime().unwrap();
+
+        let mut loaded_wasm_sandbox: LoadedWasmSandbox = {
+            let mod_path = get_wasm_module_path("rust_wasm_samples.aot").unwrap();
+            wasm_sandbox.load_module(mod_path)
+        }
+        .unwrap();
+
+        let result: u64 = loaded_wasm_sandbox
+            .call_guest_function("heap_size", ())
+            .unwrap();
+        assert_eq!(result, heap_size);
+    }
+
+    #[test]
+    fn test_load_module_by_name() {
+        let mod_path = get_wasm_module_path("rust_wasm_samples.aot").unwrap();
+        let mod_dir = std::path::Path::new(&mod_path)
+            .parent()
+            .unwrap()
+            .to_path_buf();
+        let mut proto_wasm_sandbox = SandboxBuilder::new()
+            .with_module_resolver(DirectoryResolver::new([mod_dir]))
+            .build()
+            .unwrap();
+        proto_wasm_sandbox
+            .register("TestHostFunc", |a: i32| Ok(a))
+            .unwrap();
+        let wasm_sandbox = proto_wasm_sandbox.load_runtime().unwrap();
+
+        let mut loaded_wasm_sandbox = wasm_sandbox
+            .load_module_by_name("rust_wasm_samples")
+            .unwrap();
+        let result: u32 = loaded_wasm_sandbox
+            .call_guest_function("add", (1u32, 2u32))
+            .unwrap();
+        assert_eq!(result, 3);
+
+        // The resolver is kept when the module is unloaded
+        let wasm_sandbox = loaded_wasm_sandbox.unload_module().unwrap();
+        assert!(wasm_sandbox.load_module_by_name("missing").is_err());
+    }
+
+    #[test]
+    fn test_register_manifest() {
+        let manifest = HostFunctionManifest::new().with_function(
+            ManifestFunction::new("TestHostFunc", [ParameterType::Int], ReturnType::Int)
+                .with_capability("test"),
+        );
+        let mut proto_wasm_sandbox = SandboxBuilder::new().build().unwrap();
+        proto_wasm_sandbox
+            .register_manifest(&manifest, |name, params| match (name, params.as_slice()) {
+                ("TestHostFunc", [ParameterValue::Int(a)]) => Ok(ReturnValue::Int(a * 2)),
+                _ => Err(new_error!("unexpected call to {}", name)),
+            })
+            .unwrap();
+        // Names can only be registered once
+        assert!(
+            proto_wasm_sandbox
+                .register_manifest(&manifest, |_, _| Ok(ReturnValue::Void(())))
+                .is_err()
+        );
+        let wasm_sandbox = proto_wasm_sandbox.load_runtime().unwrap();
+
+        let mut loaded_wasm_sandbox: LoadedWasmSandbox = {
+            let mod_path = get_wasm_module_path("rust_wasm_samples.aot").unwrap();
+            wasm_sandbox.load_module(mod_path)
+        }
+        .unwrap();
+
+        let result: i32 = loaded_wasm_sandbox
+            .call_guest_function("call_host_function", 21i32)
+            .unwrap();
+        assert_eq!(result, 42);
+    }
+
+    #[test]
+    fn test_register_reentrant() {
+        let mut proto_wasm_sandbox = SandboxBuilder::new()
+            .with_max_reentrancy_depth(4)
+            .build()
+            .unwrap();
+        // Each call calls back into the module, which calls the host
+        // function again, until the countdown reaches 0
+        proto_wasm_sandbox
+            .register_reentrant(
+                "TestHostFunc",
+                |guest: &mut GuestCallbacks, (countdown,): (i32,)| {
+                    if countdown == 0 {
+                        let sum: u32 = guest.call("add", (40u32, 2u32))?;
+                        return Ok(sum as i32 + guest.depth() as i32);
+                    }
+                    guest.call::<i32>("call_host_function", (countdown - 1,))
+                },
+            )
+            .unwrap();
+        // Names can only be registered once
+        assert!(
+            proto_wasm_sandbox
+                .register_reentrant("TestHostFunc", |_: &mut GuestCallbacks, (): ()| Ok(()))
+                .is_err()
+        );
+        let wasm_sandbox = proto_wasm_sandbox.load_runtime().unwrap();
+        let mod_path = get_wasm_module_path("rust_wasm_samples.aot").unwrap();
+        let mut loaded_wasm_sandbox = wasm_sandbox.load_module(mod_path).unwrap();
+
+        let result: i32 = loaded_wasm_sandbox
+            .call_guest_function("call_host_function", 0i32)
+            .unwrap();
+        assert_eq!(result, 43);
+        let result: i32 = loaded_wasm_sandbox
+            .call_guest_function("call_host_function", 3i32)
+            .unwrap();
+        assert_eq!(result, 46);
+
+        // Nesting deeper than the limit fails the call, but not the next
+        let err = loaded_wasm_sandbox
+            .call_guest_function::<i32>("call_host_function", 4i32)
+            .unwrap_err();
+        assert!(err.to_string().contains("more than 4 deep"), "{err}");
+        assert!(!loaded_wasm_sandbox.is_poisoned().unwrap());
+        let result: i32 = loaded_wasm_sandbox
+            .call_guest_function("call_host_function", 1i32)
+            .unwrap();
+        assert_eq!(result, 44);
+    }
+
+    #[test]
+    fn test_usage_accounting() {
+        let mod_path = get_wasm_module_path("rust_wasm_samples.aot").unwrap();
+        let module_hash = blake3::hash(&std::fs::read(&mod_path).unwrap()).to_string();
+        let calls_before = crate::module_usage(&module_hash).map_or(0, |usage| usage.calls);
+
+        let mut proto_wasm_sandbox = SandboxBuilder::new()
+            .with_usage_accounting(true)
+            .build()
+            .unwrap();
+        proto_wasm_sandbox
+            .register("TestHostFunc", |a: i32| Ok(a))
+            .unwrap();
+        let wasm_sandbox = proto_wasm_sandbox.load_runtime().unwrap();
+        let mut loaded_wasm_sandbox = wasm_sandbox.load_module(&mod_path).unwrap();
+        assert_eq!(
+            loaded_wasm_sandbox.module_hash(),
+            Some(module_hash.as_str())
+        );
+
+        for _ in 0..3 {
+            let _: u32 = loaded_wasm_sandbox
+                .call_guest_function("add", (1u32, 2u32))
+                .unwrap();
+        }
+        let usage = crate::module_usage(&module_hash).unwrap();
+        assert!(usage.calls >= calls_before + 3);
+        assert!(usage.peak_memory.unwrap() > 0);
+
+        // Sandboxes loaded from a snapshot record usage under the same hash
+        let snapshot = loaded_wasm_sandbox.snapshot().unwrap();
+        let wasm_sandbox = loaded_wasm_sandbox.unload_module().unwrap();
+        let loaded_wasm_sandbox = wasm_sandbox.load_from_snapshot(snapshot).unwrap();
+        assert_eq!(
+            loaded_wasm_sandbox.module_hash(),
+            Some(module_hash.as_str())
+        );
+    }
+
+    #[test]
+    fn test_provenance() {
+        let key = [42u8; 32];
+        let mod_path = get_wasm_module_path("rust_wasm_samples.aot").unwrap();
+        let module_hash = blake3::hash(&std::fs::read(&mod_path).unwrap()).to_string();
+
+        let mut proto_wasm_sandbox = SandboxBuilder::new()
+            .with_provenance_key(key)
+            .build()
+            .unwrap();
+        proto_wasm_sandbox
+            .register("TestHostFunc", |a: i32| Ok(a))
+            .unwrap();
+        let wasm_sandbox = proto_wasm_sandbox.load_runtime().unwrap();
+        let loaded_wasm_sandbox = wasm_sandbox.load_module(&mod_path).unwrap();
+
+        let provenance = loaded_wasm_sandbox.provenance().unwrap();
+        assert_eq!(
+            provenance.module_hash.as_deref(),
+            Some(module_hash.as_str())
+        );
+        assert!(
+            provenance
+                .host_functions
+                .functions()
+                .iter()
+                .any(|f| f.name == "TestHostFunc")
+        );
+        assert!(provenance.verify(&key));
+    }
+
+    #[test]
+    fn test_drop_during_call() {
+        let mut proto_wasm_sandbox = ProtoWasmSandbox::default();
+        // Registered through Registerable so that the panic is not caught
+        Registerable::register_host_function(
+            &mut proto_wasm_sandbox,
+            "TestHostFunc",
+            |_: i32| -> Result<i32> { panic!("host function panicked") },
+        )
+        .unwrap();
+        let wasm_sandbox = proto_wasm_sandbox.load_runtime().unwrap();
+
+        let mut loaded_wasm_sandbox: LoadedWasmSandbox = {
+            let mod_path = get_wasm_module_path("rust_wasm_samples.aot").unwrap();
+            wasm_sandbox.load_module(mod_path)
+        }
+        .unwrap();
+        let interrupt_handle = loaded_wasm_sandbox.interrupt_handle().unwrap();
+
+        // The panic unwinds through the call, dropping the sandbox while
+        // the call is in progress
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(move || {
+            let _: i32 = loaded_wasm_sandbox
+                .call_guest_function("call_host_function", 1i32)
+                .unwrap();
+        }));
+        assert!(result.is_err());
+        assert!(interrupt_handle.dropped());
+    }
+
+    #[test]
+    fn test_panic_policy() {
+        for policy in [PanicPolicy::PoisonSandbox, PanicPolicy::ReturnError] {
+            let mut proto_wasm_sandbox = SandboxBuilder::new()
+                .with_panic_policy(policy)
+                .build()
+                .unwrap();
+            proto_wasm_sandbox
+                .register("TestHostFunc", |a: i32| -> Result<i32> {
+                    if a < 0 {
+                        panic!("negative parameter {a}");
+                    }
+                    Ok(a)
+                })
+                .unwrap();
+            let wasm_sandbox = proto_wasm_sandbox.load_runtime().unwrap();
+            let mut loaded_wasm_sandbox: LoadedWasmSandbox = {
+                let mod_path = get_wasm_module_path("rust_wasm_samples.aot").unwrap();
+                wasm_sandbox.load_module(mod_path)
+            }
+            .unwrap();
+            let snapshot = loaded_wasm_sandbox.snapshot().unwrap();
+
+            let err = loaded_wasm_sandbox
+                .call_guest_function::<i32>("call_host_function", -1i32)
+                .unwrap_err();
+            assert!(
+                err.to_string()
+                    .contains("host function TestHostFunc panicked: negative parameter -1"),
+                "{err:?}"
+            );
+
+            let poisoned = policy == PanicPolicy::PoisonSandbox;
+            assert_eq!(loaded_wasm_sandbox.is_poisoned().unwrap(), poisoned);
+            let result = loaded_wasm_sandbox.call_guest_function::<i32>("call_host_function", 1i32);
+            if poisoned {
+                assert!(matches!(result, Err(HyperlightError::PoisonedSandbox)));
+                loaded_wasm_sandbox.restore(snapshot).unwrap();
+                assert!(!loaded_wasm_sandbox.is_poisoned().unwrap());
+            } else {
+                assert_eq!(result.unwrap(), 1);
+            }
+            let result: i32 = loaded_wasm_sandbox
+                .call_guest_function("call_host_function", 2i32)
+                .unwrap();
+            assert_eq!(result, 2);
+        }
+    }
+
+    #[test]
+    fn test_unsupported_wasi_imports_are_stubbed() {
+        let mut proto_wasm_sandbox = SandboxBuilder::new()
+            .with_unsupported_wasi_errno(58)
+            .build()
+            .unwrap();
+        proto_wasm_sandbox
+            .register("TestHostFunc", |a: i32| Ok(a))
+            .unwrap();
+        let wasm_sandbox = proto_wasm_sandbox.load_runtime().unwrap();
+
+        let mut loaded_wasm_sandbox: LoadedWasmSandbox = {
+            let mod_path = get_wasm_module_path("rust_wasm_samples.aot").unwrap();
+            wasm_sandbox.load_module(mod_path)
+        }
+        .unwrap();
+
+        assert_eq!(loaded_wasm_sandbox.stubbed_wasi_imports(), ["fd_sync"]);
+        let errno: i32 = loaded_wasm_sandbox
+            .call_guest_function("sync_stdout", ())
+            .unwrap();
+        assert_eq!(errno, 58);
+    }
+
+    #[test]
+    fn test_on_progress() {
+        let mut proto_wasm_sandbox = SandboxBuilder::new().build().unwrap();
+        proto_wasm_sandbox
+            .register("TestHostFunc", |a: i32| Ok(a))
+            .unwrap();
+        let reports = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let captured = reports.clone();
+        proto_wasm_sandbox
+            .on_progress(move |progress, partial| {
+                captured.lock().unwrap().push((progress, partial.to_vec()));
+            })
+            .unwrap();
+        let wasm_sandbox = proto_wasm_sandbox.load_runtime().unwrap();
+
+        let mut loaded_wasm_sandbox: LoadedWasmSandbox = {
+            let mod_path = get_wasm_module_path("rust_wasm_samples.aot").unwrap();
+            wasm_sandbox.load_module(mod_path)
+        }
+        .unwrap();
+
+        let result: u32 = loaded_wasm_sandbox
+            .call_guest_function("count_with_progress", 3u32)
+            .unwrap();
+        assert_eq!(result, 3);
+        let expected: Vec<(u32, Vec<u8>)> =
+            (1..=3u32).map(|i| (i, i.to_le_bytes().to_vec())).collect();
+        assert_eq!(*reports.lock().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_call_guest_function_streaming() {
+        let mut proto_wasm_sandbox = SandboxBuilder::new().build().unwrap();
+        proto_wasm_sandbox
+            .register("TestHostFunc", |a: i32| Ok(a))
+            .unwrap();
+        let wasm_sandbox = proto_wasm_sandbox.load_runtime().unwrap();
+
+        let mut loaded_wasm_sandbox: LoadedWasmSandbox = {
+            let mod_path = get_wasm_module_path("rust_wasm_samples.aot").unwrap();
+            wasm_sandbox.load_module(mod_path)
+        }
+        .unwrap();
+
+        let streamed = loaded_wasm_sandbox
+            .call_guest_function_streaming::<u32>("stream_numbers", 3u32)
+            .unwrap();
+        assert_eq!(*streamed.value(), 3);
+        let chunks: Vec<Vec<u8>> = streamed.collect();
+        let expected: Vec<Vec<u8>> = (1..=3u32).map(|i| i.to_le_bytes().to_vec()).collect();
+        assert_eq!(chunks, expected);
+
+        // Chunks emitted outside streaming calls are discarded
+        let result: u32 = loaded_wasm_sandbox
+            .call_guest_function("stream_numbers", 2u32)
+            .unwrap();
+        assert_eq!(result, 2);
+        let streamed = loaded_wasm_sandbox
+            .call_guest_function_streaming::<u32>("stream_numbers", 1u32)
+            .unwrap();
+        assert_eq!(streamed.count(), 1);
+    }
+
+    #[test]
+    fn test_state_cells() {
+        let mut proto_wasm_sandbox = SandboxBuilder::new().build().unwrap();
+        proto_wasm_sandbox
+            .register("TestHostFunc", |a: i32| Ok(a))
+            .unwrap();
+        proto_wasm_sandbox
+            .declare_state_cell("counter", 0u64)
+            .unwrap();
+        proto_wasm_sandbox
+            .declare_state_cell("message", b"hello".as_slice())
+            .unwrap();
+        let wasm_sandbox = proto_wasm_sandbox.load_runtime().unwrap();
+
+        let mut loaded_wasm_sandbox: LoadedWasmSandbox = {
+            let mod_path = get_wasm_module_path("rust_wasm_samples.aot").unwrap();
+            wasm_sandbox.load_module(mod_path)
+        }
+        .unwrap();
+
+        let result: u64 = loaded_wasm_sandbox
+            .call_guest_function("increment_cell", "counter".to_string())
+            .unwrap();
+        assert_eq!(result, 1);
+        assert_eq!(
+            loaded_wasm_sandbox.get_cell("counter").unwrap(),
+            StateCellValue::U64(1)
+        );
+
+        loaded_wasm_sandbox.set_cell("counter", 41u64).unwrap();
+        let result: u64 = loaded_wasm_sandbox
+            .call_guest_function("increment_cell", "counter".to_string())
+            .unwrap();
+        assert_eq!(result, 42);
+
+        // The cell holds bytes, so the guest traps
+        let result: Result<u64> =
+            loaded_wasm_sandbox.call_guest_function("increment_cell", "message".to_string());
+        assert!(result.is_err());
+        assert!(loaded_wasm_sandbox.set_cell("message", 1u64).is_err());
+        assert!(loaded_wasm_sandbox.get_cell("missing").is_err());
+    }
+
+    #[test]
+    fn test_call_guest_function_readonly() {
+        let mut proto_wasm_sandbox = SandboxBuilder::new().build().unwrap();
+        proto_wasm_sandbox
+            .register("TestHostFunc", |a: i32| Ok(a))
+            .unwrap();
+        let wasm_sandbox = proto_wasm_sandbox.load_runtime().unwrap();
+
+        let mut loaded_wasm_sandbox: LoadedWasmSandbox = {
+            let mod_path = get_wasm_module_path("rust_wasm_samples.aot").unwrap();
+            wasm_sandbox.load_module(mod_path)
+        }
+        .unwrap();
+
+        // Readonly calls see the current state but do not change it
+        for _ in 0..2 {
+            let count: i32 = loaded_wasm_sandbox
+                .call_guest_function_readonly("increment_counter", ())
+                .unwrap();
+            assert_eq!(count, 1);
+        }
+
+        let count: i32 = loaded_wasm_sandbox
+            .call_guest_function("increment_counter", ())
+            .unwrap();
+        assert_eq!(count, 1);
+        let count: i32 = loaded_wasm_sandbox
+            .call_guest_function_readonly("increment_counter", ())
+            .unwrap();
+        assert_eq!(count, 2);
+        let count: i32 = loaded_wasm_sandbox
+            .call_guest_function("increment_counter", ())
+            .unwrap();
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn test_batch_encoding() {
+        use hyperlight_common::flatbuffer_wrappers::function_call::{
+            FunctionCall, FunctionCallType,
+        };
+        use hyperlight_host::func::{ParameterValue, ReturnType, ReturnValue};
+        use hyperlight_wasm_runtime::batch;
+
+        let calls = [
+            FunctionCall::new(
+                "add".to_string(),
+                Some(vec![ParameterValue::UInt(1), ParameterValue::UInt(2)]),
+                FunctionCallType::Guest,
+                ReturnType::UInt,
+            ),
+            FunctionCall::new(
+                "checksum".to_string(),
+                Some(vec![ParameterValue::VecBytes(vec![1, 2, 3])]),
+                FunctionCallType::Guest,
+                ReturnType::Void,
+            ),
+        ];
+        let decoded = batch::calls_from_bytes(&batch::calls_to_bytes(&calls)).unwrap();
+        assert_eq!(decoded.len(), 2);
+        for (call, decoded) in calls.iter().zip(&decoded) {
+            assert_eq!(call.function_name, decoded.function_name);
+            assert_eq!(call.parameters, decoded.parameters);
+            assert_eq!(call.expected_return_type, decoded.expected_return_type);
+        }
+
+        let results = [ReturnValue::UInt(3), ReturnValue::Void(())];
+        let bytes = batch::results_to_bytes(&results);
+        assert_eq!(batch::results_from_bytes(&bytes).unwrap(), results);
+        assert!(batch::results_from_bytes(&bytes[..bytes.len() - 1]).is_none());
+    }
+
+    #[test]
+    fn test_guest_log_encoding() {
+        use hyperlight_wasm_runtime::guest_log::{GuestLogRecord, GuestLogs};
+
+        let mut logs = GuestLogs::new();
+        logs.push(GuestLogRecord {
+            level: 3,
+            target: String::new(),
+            message: "plain".to_string(),
+            fields: vec![],
+        });
+        logs.push(GuestLogRecord {
+            level: 1,
+            target: "db".to_string(),
+            message: "query failed".to_string(),
+            fields: vec![
+                ("table".to_string(), "users".to_string()),
+                ("rows".to_string(), "0".to_string()),
+            ],
+        });
+        logs.dropped = 2;
+        let bytes = logs.to_bytes();
+        assert_eq!(GuestLogs::from_bytes(&bytes).unwrap(), logs);
+        assert!(GuestLogs::from_bytes(&bytes[..bytes.len() - 1]).is_none());
+    }
+
+    #[test]
+    fn test_component_value_encoding() {
+        use hyperlight_wasm_runtime::component_value;
+
+        use crate::ComponentValue;
+
+        let values = [
+            ComponentValue::Bool(true),
+            ComponentValue::S8(-1),
+            ComponentValue::U64(1 << 40),
+            ComponentValue::Float64(-2.25),
+            ComponentValue::Char('λ'),
+            ComponentValue::String("hello".to_string()),
+            ComponentValue::List(vec![ComponentValue::U32(1), ComponentValue::U32(2)]),
+            ComponentValue::Record(vec![
+                ("left".to_string(), ComponentValue::U32(1)),
+                ("right".to_string(), ComponentValue::Tuple(vec![])),
+            ]),
+            ComponentValue::Variant(
+                "some-case".to_string(),
+                Some(Box::new(ComponentValue::S16(7))),
+            ),
+            ComponentValue::Enum("red".to_string()),
+            ComponentValue::Option(None),
+            ComponentValue::Result(Err(Some(Box::new(ComponentValue::String(
+                "failed".to_string(),
+            ))))),
+            ComponentValue::Flags(vec!["read".to_string(), "write".to_string()]),
+        ];
+        let bytes = component_value::to_bytes(&values);
+        assert_eq!(component_value::from_bytes(&bytes).unwrap(), values);
+        assert!(component_value::from_bytes(&bytes[..bytes.len() - 1]).is_none());
+        assert!(component_value::from_bytes(&[0xff]).is_none());
+        assert!(component_value::from_bytes(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_fresh_instance_per_call() {
+        use crate::{ComponentValue, RuntimeKind};
+
+        // The component counts its calls, unless each call gets a fresh
+        // instance
+        for (fresh_instance_per_call, counts) in [(false, [1, 2, 3]), (true, [1, 1, 1])] {
+            // Components are loaded without bindings by the module runtime
+            let mut loaded_wasm_sandbox = SandboxBuilder::new()
+                .with_runtime(RuntimeKind::Module)
+                .with_fresh_instance_per_call(fresh_instance_per_call)
+                .build()
+                .unwrap()
+                .load_runtime()
+                .unwrap()
+                .load_component(get_wasm_module_path("counter_component.aot").unwrap())
+                .unwrap();
+            for count in counts {
+                let results = loaded_wasm_sandbox
+                    .call_component_function(None, "increment", &[])
+                    .unwrap();
+                assert_eq!(results, [ComponentValue::U32(count)]);
+            }
+        }
+    }
 
-        for handle in thread_handles {
-            handle.join().unwrap();
+    #[test]
+    fn test_load_component_with_required_exports() {
+        let sandbox = SandboxBuilder::new()
+            .with_required_exports([RequiredExport::from("add")])
+            .build()
+            .unwrap()
+            .load_runtime()
+            .unwrap();
+        let err = sandbox.load_component_from_buffer(&[]).unwrap_err();
+        assert!(err.to_string().contains("required exports"), "{err}");
+    }
+
+    #[test]
+    fn test_call_batch() {
+        let mut proto_wasm_sandbox = SandboxBuilder::new().build().unwrap();
+        proto_wasm_sandbox
+            .register("TestHostFunc", |a: i32| Ok(a))
+            .unwrap();
+        let mut loaded_wasm_sandbox = {
+            let mod_path = get_wasm_module_path("rust_wasm_samples.aot").unwrap();
+            proto_wasm_sandbox
+                .load_runtime()
+                .unwrap()
+                .load_module(mod_path)
         }
+        .unwrap();
+
+        let sums: Vec<u32> = loaded_wasm_sandbox
+            .call_batch(&[("add", (1u32, 2u32)), ("add", (3u32, 4u32))])
+            .unwrap();
+        assert_eq!(sums, [3, 7]);
+        let none: Vec<u32> = loaded_wasm_sandbox.call_batch::<_, ()>(&[]).unwrap();
+        assert!(none.is_empty());
+
+        // Calls run in order, and stop at the first that fails
+        let counts: Vec<i32> = loaded_wasm_sandbox
+            .call_batch(&[("increment_counter", ()); 3])
+            .unwrap();
+        assert_eq!(counts, [1, 2, 3]);
+        assert!(
+            loaded_wasm_sandbox
+                .call_batch::<i32, ()>(&[
+                    ("increment_counter", ()),
+                    ("no_such_function", ()),
+                    ("increment_counter", ()),
+                ])
+                .is_err()
+        );
+        let count: i32 = loaded_wasm_sandbox
+            .call_guest_function("increment_counter", ())
+            .unwrap();
+        assert_eq!(count, 5);
     }
 
     #[test]
-    fn test_call_guest_functions_with_custom_config_multiple_times() {
-        let mut sandbox = SandboxBuilder::new()
-            .with_guest_scratch_size(32 * 1024)
-            .with_guest_heap_size(128 * 1024)
+    #[cfg(feature = "aot")]
+    fn test_call_guest_function_transactional() {
+        // A module with a counter exporting `bump() -> i32`, which
+        // increments the counter and returns it, and `fail()`, which
+        // increments the counter and traps
+        const COUNTER_MODULE: &[u8] = &[
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+            0x01, 0x08, 0x02, 0x60, 0x00, 0x01, 0x7f, 0x60, 0x00, 0x00, // types
+            0x03, 0x03, 0x02, 0x00, 0x01, // functions
+            0x06, 0x06, 0x01, 0x7f, 0x01, 0x41, 0x00, 0x0b, // globals
+            0x07, 0x0f, 0x02, 0x04, b'b', b'u', b'm', b'p', 0x00, 0x00, 0x04, b'f', b'a', b'i',
+            b'l', 0x00, 0x01, // exports
+            0x0a, 0x18, 0x02, 0x0b, 0x00, 0x23, 0x00, 0x41, 0x01, 0x6a, 0x24, 0x00, 0x23, 0x00,
+            0x0b, 0x0a, 0x00, 0x23, 0x00, 0x41, 0x01, 0x6a, 0x24, 0x00, 0x00, 0x0b, // code
+        ];
+
+        let mut loaded_wasm_sandbox = SandboxBuilder::new()
             .build()
+            .unwrap()
+            .load_runtime()
+            .unwrap()
+            .load_module_from_buffer(COUNTER_MODULE)
             .unwrap();
 
-        sandbox
-            .register(
-                "GetTimeSinceBootMicrosecond",
-                get_time_since_boot_microsecond,
-            )
+        // Successful calls keep their changes
+        let count: i32 = loaded_wasm_sandbox
+            .call_guest_function_transactional("bump", ())
             .unwrap();
+        assert_eq!(count, 1);
 
-        let wasm_sandbox = sandbox.load_runtime().unwrap();
+        // Failed calls are rolled back and leave the sandbox usable
+        let err = loaded_wasm_sandbox
+            .call_guest_function_transactional::<()>("fail", ())
+            .unwrap_err();
+        assert!(GuestTrap::from_error(&err).is_some());
+        assert!(!loaded_wasm_sandbox.is_poisoned().unwrap());
+        let count: i32 = loaded_wasm_sandbox
+            .call_guest_function_transactional("bump", ())
+            .unwrap();
+        assert_eq!(count, 2);
+    }
 
-        let loaded_wasm_sandbox: LoadedWasmSandbox = {
-            let mod_path = get_wasm_module_path("RunWasm.aot").unwrap();
-            wasm_sandbox.load_module(mod_path)
+    #[test]
+    fn test_upgrade_module() {
+        let mut proto_wasm_sandbox = SandboxBuilder::new().build().unwrap();
+        proto_wasm_sandbox
+            .register("TestHostFunc", |a: i32| Ok(a))
+            .unwrap();
+        let wasm_sandbox = proto_wasm_sandbox.load_runtime().unwrap();
+
+        let mod_path = get_wasm_module_path("rust_wasm_samples.aot").unwrap();
+        let mut loaded_wasm_sandbox = wasm_sandbox.load_module(&mod_path).unwrap();
+        for _ in 0..2 {
+            let _: i32 = loaded_wasm_sandbox
+                .call_guest_function("increment_counter", ())
+                .unwrap();
         }
-        .unwrap();
 
-        call_funcs(loaded_wasm_sandbox, 1000);
+        // The counter is carried across to the new version
+        loaded_wasm_sandbox.upgrade_module(&mod_path).unwrap();
+        let count: i32 = loaded_wasm_sandbox
+            .call_guest_function("increment_counter", ())
+            .unwrap();
+        assert_eq!(count, 3);
+
+        // A failed upgrade leaves the current module serving calls, with
+        // its state
+        assert!(
+            loaded_wasm_sandbox
+                .upgrade_module_from_buffer(b"not a module")
+                .is_err()
+        );
+        let count: i32 = loaded_wasm_sandbox
+            .call_guest_function("increment_counter", ())
+            .unwrap();
+        assert_eq!(count, 4);
+
+        // Modules that do not export their state cannot be upgraded
+        let wasm_sandbox = loaded_wasm_sandbox.unload_module().unwrap();
+        let mut loaded_wasm_sandbox = wasm_sandbox
+            .load_module(get_wasm_module_path("RunWasm.aot").unwrap())
+            .unwrap();
+        assert!(loaded_wasm_sandbox.upgrade_module(&mod_path).is_err());
     }
 
     #[test]
-    fn test_call_host_func_with_vecbytes() {
-        let host_func = |b: Vec<u8>, l: i32| {
-            // get the C String from the vec of bytes
+    fn test_reload_module() {
+        let mut proto_wasm_sandbox = SandboxBuilder::new().build().unwrap();
+        proto_wasm_sandbox
+            .register("TestHostFunc", |a: i32| Ok(a))
+            .unwrap();
+        let wasm_sandbox = proto_wasm_sandbox.load_runtime().unwrap();
 
-            let s = std::str::from_utf8(&b).unwrap();
-            println!("Host function received buffer: {}", s);
+        let mod_path = get_wasm_module_path("rust_wasm_samples.aot").unwrap();
+        let mut loaded_wasm_sandbox = wasm_sandbox.load_module(&mod_path).unwrap();
+        for _ in 0..2 {
+            let _: i32 = loaded_wasm_sandbox
+                .call_guest_function("increment_counter", ())
+                .unwrap();
+        }
 
-            // check that s is the expected value if not return an error
-            if s != "Hello World!" {
-                return Err(new_error!("Unexpected value in buffer {}", s));
-            }
+        // A failed reload leaves the current module serving calls, with
+        // its state
+        assert!(
+            loaded_wasm_sandbox
+                .reload_module_from_buffer(b"not a module")
+                .is_err()
+        );
+        let count: i32 = loaded_wasm_sandbox
+            .call_guest_function("increment_counter", ())
+            .unwrap();
+        assert_eq!(count, 3);
 
-            if l != 12 {
-                return Err(new_error!("Unexpected length of buffer {}", l));
+        // The reloaded module starts afresh, with the registered host
+        // functions still available to it
+        loaded_wasm_sandbox.reload_module(&mod_path).unwrap();
+        let count: i32 = loaded_wasm_sandbox
+            .call_guest_function("increment_counter", ())
+            .unwrap();
+        assert_eq!(count, 1);
+        let res: i32 = loaded_wasm_sandbox
+            .call_guest_function("call_host_function", 42i32)
+            .unwrap();
+        assert_eq!(res, 42);
+
+        // Modules that do not export their state can be reloaded too
+        loaded_wasm_sandbox
+            .reload_module(get_wasm_module_path("RunWasm.aot").unwrap())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_payload_key() {
+        let mut proto_wasm_sandbox = SandboxBuilder::new()
+            .with_payload_key([7; 32])
+            .build()
+            .unwrap();
+        proto_wasm_sandbox
+            .register("TestHostFunc", |a: i32| Ok(a))
+            .unwrap();
+        let wasm_sandbox = proto_wasm_sandbox.load_runtime().unwrap();
+        let mut loaded_wasm_sandbox = wasm_sandbox
+            .load_module(get_wasm_module_path("rust_wasm_samples.aot").unwrap())
+            .unwrap();
+
+        // Buffers are decrypted for the module and encrypted for the host
+        let state = 5i32.to_le_bytes().to_vec();
+        let res: i32 = loaded_wasm_sandbox
+            .call_guest_function("import_state", (state.clone(), 4i32))
+            .unwrap();
+        assert_eq!(res, 0);
+        let exported: Vec<u8> = loaded_wasm_sandbox
+            .call_guest_function("export_state", ())
+            .unwrap();
+        assert_eq!(exported, state);
+
+        // The key is given back to the guest after snapshots and restores
+        let snapshot = loaded_wasm_sandbox.snapshot().unwrap();
+        let count: i32 = loaded_wasm_sandbox
+            .call_guest_function("increment_counter", ())
+            .unwrap();
+        assert_eq!(count, 6);
+        loaded_wasm_sandbox.restore(snapshot).unwrap();
+        let exported: Vec<u8> = loaded_wasm_sandbox
+            .call_guest_function("export_state", ())
+            .unwrap();
+        assert_eq!(exported, state);
+    }
+
+    #[test]
+    #[cfg(feature = "crashdump")]
+    fn test_payload_key_not_in_snapshot() {
+        let key = *b"hyperlight-wasm-payload-key-test";
+        let dir = std::env::temp_dir().join(format!("hl_wasm_key_dump_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut proto_wasm_sandbox = SandboxBuilder::new().with_payload_key(key).build().unwrap();
+        proto_wasm_sandbox
+            .register("TestHostFunc", |a: i32| Ok(a))
+            .unwrap();
+        let mut loaded_wasm_sandbox = proto_wasm_sandbox
+            .load_runtime()
+            .unwrap()
+            .load_module(get_wasm_module_path("rust_wasm_samples.aot").unwrap())
+            .unwrap();
+        loaded_wasm_sandbox.snapshot().unwrap();
+
+        // Dump the guest's memory as it was snapshotted, with the key
+        // removed as snapshot() removes it, and look for the key in it
+        loaded_wasm_sandbox
+            .inner
+            .as_mut()
+            .unwrap()
+            .call::<()>(super::CLEAR_PAYLOAD_KEY_FUNCTION, ())
+            .unwrap();
+        let path = loaded_wasm_sandbox
+            .generate_crashdump_to_dir(dir.to_string_lossy())
+            .unwrap();
+        let context: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        let core_dump = std::fs::read(dir.join(context["core_dump"].as_str().unwrap())).unwrap();
+        assert!(!core_dump.windows(key.len()).any(|bytes| bytes == key));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_call_hooks() {
+        let mut proto_wasm_sandbox = SandboxBuilder::new().build().unwrap();
+        proto_wasm_sandbox
+            .register("TestHostFunc", |a: i32| Ok(a))
+            .unwrap();
+        let mut loaded_wasm_sandbox = {
+            let mod_path = get_wasm_module_path("rust_wasm_samples.aot").unwrap();
+            proto_wasm_sandbox
+                .load_runtime()
+                .unwrap()
+                .load_module(mod_path)
+        }
+        .unwrap();
+
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        loaded_wasm_sandbox.on_call_start(move |name| {
+            recorded.lock().unwrap().push(format!("start {name}"));
+            match name {
+                "increment_counter" => Err(new_error!("quota spent")),
+                _ => Ok(()),
             }
-            Ok(0i32)
-        };
+        });
+        let recorded = events.clone();
+        loaded_wasm_sandbox.on_host_call(move |name| {
+            recorded.lock().unwrap().push(format!("host {name}"));
+            Ok(())
+        });
+        let recorded = events.clone();
+        loaded_wasm_sandbox.on_call_end(move |record| {
+            recorded.lock().unwrap().push(format!(
+                "end {} {}",
+                record.function_name,
+                record.succeeded()
+            ));
+        });
 
+        let result: i32 = loaded_wasm_sandbox
+            .call_guest_function("call_host_function", 2i32)
+            .unwrap();
+        assert_eq!(result, 2);
+        // Refused calls do not reach the guest
+        assert!(
+            loaded_wasm_sandbox
+                .call_guest_function::<i32>("increment_counter", ())
+                .is_err()
+        );
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                "start call_host_function",
+                "host TestHostFunc",
+                "end call_host_function true",
+                "start increment_counter",
+                "end increment_counter false",
+            ]
+        );
+
+        loaded_wasm_sandbox.clear_call_hooks();
+        let count: i32 = loaded_wasm_sandbox
+            .call_guest_function("increment_counter", ())
+            .unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(events.lock().unwrap().len(), 5);
+    }
+
+    #[test]
+    fn test_last_call_stats() {
         let mut proto_wasm_sandbox = SandboxBuilder::new().build().unwrap();
+        proto_wasm_sandbox
+            .register("TestHostFunc", |a: i32| {
+                std::thread::sleep(Duration::from_millis(20));
+                Ok(a)
+            })
+            .unwrap();
+        let mut loaded_wasm_sandbox = {
+            let mod_path = get_wasm_module_path("rust_wasm_samples.aot").unwrap();
+            proto_wasm_sandbox
+                .load_runtime()
+                .unwrap()
+                .load_module(mod_path)
+        }
+        .unwrap();
+        assert!(loaded_wasm_sandbox.last_call_stats().is_none());
+
+        let result: i32 = loaded_wasm_sandbox
+            .call_guest_function("call_host_function", 3i32)
+            .unwrap();
+        assert_eq!(result, 3);
+        let report = loaded_wasm_sandbox.last_call_stats().unwrap();
+        assert_eq!(report.function_name, "call_host_function");
+        assert_eq!(report.host_calls, 1);
+        assert!(report.succeeded);
+        assert!(report.host_time >= Duration::from_millis(20));
+        // The host function slept, which is not guest CPU time
+        assert!(report.guest_cpu_time < report.duration);
+        assert!(report.duration >= report.host_time);
+
+        assert!(
+            loaded_wasm_sandbox
+                .call_guest_function::<i32>("no_such_function", ())
+                .is_err()
+        );
+        let report = loaded_wasm_sandbox.last_call_stats().unwrap();
+        assert_eq!(report.function_name, "no_such_function");
+        assert_eq!(report.host_calls, 0);
+        assert!(!report.succeeded);
+    }
+
+    #[test]
+    fn test_call_guest_function_json() {
+        #[derive(serde::Deserialize, Debug, PartialEq)]
+        struct Invoice {
+            customer: String,
+            lines: Vec<(String, u64)>,
+            total: u64,
+        }
+
+        let mut loaded_wasm_sandbox = {
+            let mod_path = get_wasm_module_path("rust_wasm_samples.aot").unwrap();
+            SandboxBuilder::new()
+                .build()
+                .unwrap()
+                .load_runtime()
+                .unwrap()
+                .load_module(mod_path)
+        }
+        .unwrap();
+
+        let orders = serde_json::json!([
+            { "item": "apple", "quantity": 3, "unit_price": 40 },
+            { "item": "pear", "quantity": 2, "unit_price": 55 },
+        ]);
+        let invoice: Invoice = loaded_wasm_sandbox
+            .call_guest_function_json("make_invoice", &("alice", orders))
+            .unwrap();
+        assert_eq!(
+            invoice,
+            Invoice {
+                customer: "alice".to_string(),
+                lines: vec![("apple".to_string(), 120), ("pear".to_string(), 110)],
+                total: 230,
+            }
+        );
 
+        // A result of the wrong shape is an error rather than a panic
+        assert!(
+            loaded_wasm_sandbox
+                .call_guest_function_json::<u64>("make_invoice", &("bob", [(); 0]))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_call_guest_function_detailed() {
+        let mut proto_wasm_sandbox = SandboxBuilder::new().build().unwrap();
         proto_wasm_sandbox
-            .register("HostFuncWithBufferAndLength", host_func)
+            .register("TestHostFunc", |a: i32| Ok(a))
+            .unwrap();
+        let wasm_sandbox = proto_wasm_sandbox.load_runtime().unwrap();
+
+        let mut loaded_wasm_sandbox: LoadedWasmSandbox = {
+            let mod_path = get_wasm_module_path("rust_wasm_samples.aot").unwrap();
+            wasm_sandbox.load_module(mod_path)
+        }
+        .unwrap();
+
+        let outcome = loaded_wasm_sandbox
+            .call_guest_function_detailed::<i32>("call_host_function", 5i32)
             .unwrap();
+        assert_eq!(outcome.value, 5);
+        assert_eq!(outcome.host_calls, 1);
+        assert!(outcome.guest_mem_delta.is_some());
 
+        let outcome = loaded_wasm_sandbox
+            .call_guest_function_detailed::<i32>("increment_counter", ())
+            .unwrap();
+        assert_eq!(outcome.value, 1);
+        assert_eq!(outcome.host_calls, 0);
+    }
+
+    #[test]
+    fn test_max_return_value_size() {
+        let proto_wasm_sandbox = SandboxBuilder::new()
+            .with_max_return_value_size(8)
+            .build()
+            .unwrap();
         let wasm_sandbox = proto_wasm_sandbox.load_runtime().unwrap();
 
         let mut loaded_wasm_sandbox: LoadedWasmSandbox = {
-            let mod_path = get_wasm_module_path("HostFunction.aot").unwrap();
+            let mod_path = get_wasm_module_path("RunWasm.aot").unwrap();
             wasm_sandbox.load_module(mod_path)
         }
         .unwrap();
 
-        // Call a guest function that calls a host function that takes a buffer and a length
+        let result: String = loaded_wasm_sandbox
+            .call_guest_function("Echo", "Hello".to_string())
+            .unwrap();
+        assert_eq!(result, "Hello");
 
-        let r: i32 = loaded_wasm_sandbox
-            .call_guest_function("PassBufferAndLengthToHost", ())
+        let err = loaded_wasm_sandbox
+            .call_guest_function::<String>("Echo", "Hello World!".to_string())
+            .unwrap_err();
+        assert!(
+            format!("{:?}", err).contains("maximum return value size"),
+            "Error should mention the maximum return value size, got: {err:?}"
+        );
+
+        // The sandbox is still usable after an oversized return value
+        let result: String = loaded_wasm_sandbox
+            .call_guest_function("Echo", "Hello".to_string())
             .unwrap();
+        assert_eq!(result, "Hello");
+    }
 
-        assert_eq!(r, 0);
+    #[test]
+    fn test_hint_gc_without_gc_export() {
+        let proto_wasm_sandbox = SandboxBuilder::new().build().unwrap();
+        let wasm_sandbox = proto_wasm_sandbox.load_runtime().unwrap();
+
+        let mut loaded_wasm_sandbox: LoadedWasmSandbox = {
+            let mod_path = get_wasm_module_path("RunWasm.aot").unwrap();
+            wasm_sandbox.load_module(mod_path)
+        }
+        .unwrap();
+
+        // RunWasm does not export a gc function, so this is a no-op
+        assert!(!loaded_wasm_sandbox.hint_gc().unwrap());
+
+        let result: i32 = loaded_wasm_sandbox
+            .call_guest_function("CalcFib", 4i32)
+            .unwrap();
+        assert_eq!(result, 3);
     }
 
     #[test]
-    fn test_load_module_fails_with_missing_host_function() {
-        // HostFunction.aot imports "HostFuncWithBufferAndLength" from "env".
-        // Loading it without registering that host function should fail
-        // at instantiation time (linker.instantiate) because the import
-        // cannot be satisfied.
+    fn test_trim_guest_memory() {
         let proto_wasm_sandbox = SandboxBuilder::new().build().unwrap();
+        let wasm_sandbox = proto_wasm_sandbox.load_runtime().unwrap();
+
+        let mut loaded_wasm_sandbox: LoadedWasmSandbox = {
+            let mod_path = get_wasm_module_path("RunWasm.aot").unwrap();
+            wasm_sandbox.load_module(mod_path)
+        }
+        .unwrap();
+
+        let result: i32 = loaded_wasm_sandbox
+            .call_guest_function("CalcFib", 4i32)
+            .unwrap();
+        assert_eq!(result, 3);
+
+        let stats = loaded_wasm_sandbox.trim_guest_memory().unwrap();
+        assert!(stats.used_bytes > 0);
+        assert!(stats.heap_bytes > 0);
+        assert_eq!(
+            stats.heap_bytes,
+            loaded_wasm_sandbox.memory_stats().unwrap().heap_bytes
+        );
+
+        // The module can still be called after it has been trimmed
+        let result: i32 = loaded_wasm_sandbox
+            .call_guest_function("CalcFib", 5i32)
+            .unwrap();
+        assert_eq!(result, 5);
+    }
 
+    #[test]
+    fn test_warm_up() {
+        let proto_wasm_sandbox = SandboxBuilder::new().build().unwrap();
         let wasm_sandbox = proto_wasm_sandbox.load_runtime().unwrap();
 
-        let result: std::result::Result<LoadedWasmSandbox, _> = {
-            let mod_path = get_wasm_module_path("HostFunction.aot").unwrap();
+        let mut loaded_wasm_sandbox: LoadedWasmSandbox = {
+            let mod_path = get_wasm_module_path("RunWasm.aot").unwrap();
             wasm_sandbox.load_module(mod_path)
-        };
+        }
+        .unwrap();
 
-        let err = result.unwrap_err();
-        let err_msg = format!("{:?}", err);
+        loaded_wasm_sandbox.warm_up(&["CalcFib"]).unwrap();
+
+        let err = loaded_wasm_sandbox
+            .warm_up(&["CalcFib", "NoSuchFunction"])
+            .unwrap_err();
         assert!(
-            err_msg.contains("HostFuncWithBufferAndLength"),
-            "Error should mention the missing host function, got: {err_msg}"
+            format!("{:?}", err).contains("NoSuchFunction"),
+            "Error should mention the missing function, got: {err:?}"
         );
+
+        // Warming up does not prevent functions being called
+        let result: i32 = loaded_wasm_sandbox
+            .call_guest_function("CalcFib", 4i32)
+            .unwrap();
+        assert_eq!(result, 3);
     }
 
     fn call_funcs(
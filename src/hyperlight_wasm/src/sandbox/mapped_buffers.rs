@@ -0,0 +1,94 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::fmt;
+
+/// Where buffers are mapped in the guest, well above the module mapped
+/// by `WasmSandbox::load_module_by_mapping`
+const MAPPED_BUFFERS_VA: u64 = 0x2_0000_0000;
+const PAGE_SIZE: u64 = 0x1000;
+
+/// The guest address a host buffer was mapped at by
+/// [`LoadedWasmSandbox::map_buffer`](crate::LoadedWasmSandbox::map_buffer),
+/// which identifies the buffer to modules.
+///
+/// Pass it to the module, as a parameter or from a host function, for
+/// the module to access the buffer through the `hlwasm:buffers` imports,
+/// as `hyperlight_wasm_guest_sdk::buffers` does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct GuestAddr(u64);
+
+impl GuestAddr {
+    /// The address as a `u64`, as modules take it
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+}
+
+impl From<GuestAddr> for u64 {
+    fn from(addr: GuestAddr) -> Self {
+        addr.0
+    }
+}
+
+impl fmt::Display for GuestAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#x}", self.0)
+    }
+}
+
+/// Hands out the guest addresses of the buffers mapped into a loaded
+/// sandbox. Addresses are never reused, since a buffer stays mapped in
+/// the VM until the sandbox is restored to before it was mapped.
+#[derive(Debug)]
+pub(crate) struct BufferAddrs {
+    next: u64,
+}
+
+impl Default for BufferAddrs {
+    fn default() -> Self {
+        Self {
+            next: MAPPED_BUFFERS_VA,
+        }
+    }
+}
+
+impl BufferAddrs {
+    /// The address to map a buffer of `len` bytes at
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    pub(crate) fn allocate(&mut self, len: usize) -> GuestAddr {
+        let addr = self.next;
+        self.next += (len as u64).div_ceil(PAGE_SIZE).max(1) * PAGE_SIZE;
+        GuestAddr(addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BufferAddrs, GuestAddr, MAPPED_BUFFERS_VA};
+
+    #[test]
+    fn test_buffer_addrs() {
+        let mut addrs = BufferAddrs::default();
+        let first = addrs.allocate(0x1800);
+        assert_eq!(first.as_u64(), MAPPED_BUFFERS_VA);
+        let second = addrs.allocate(1);
+        assert_eq!(u64::from(second), MAPPED_BUFFERS_VA + 0x2000);
+        let third = addrs.allocate(0);
+        assert_eq!(third.as_u64(), MAPPED_BUFFERS_VA + 0x3000);
+        assert_eq!(GuestAddr(0x2000).to_string(), "0x2000");
+    }
+}
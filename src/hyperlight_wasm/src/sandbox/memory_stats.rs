@@ -0,0 +1,153 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use hyperlight_host::{MultiUseSandbox, Result, new_error};
+use hyperlight_wasm_runtime::memory_stats::{
+    GET_MEMORY_STATS_FUNCTION, GuestMemoryStats, TRIM_MEMORY_FUNCTION,
+};
+
+use super::metrics::{
+    METRIC_SANDBOX_LABEL_NAME, METRIC_SANDBOX_LINEAR_MEMORY_BYTES,
+    METRIC_SANDBOX_MEMORY_USED_BYTES, METRIC_SANDBOX_PEAK_MEMORY_USED_BYTES,
+};
+
+/// The memory used by the guest of a sandbox, see
+/// [`LoadedWasmSandbox::memory_stats`](crate::LoadedWasmSandbox::memory_stats)
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoryStats {
+    /// The bytes of guest memory the runtime has given wasmtime for the
+    /// loaded guest's linear memories, tables and code. Memory is
+    /// counted as it is first touched, so this is what the guest has
+    /// actually used rather than what it has reserved.
+    pub used_bytes: u64,
+    /// The largest [`used_bytes`](Self::used_bytes) seen by the sandbox
+    /// since it was built, including before it was restored from a
+    /// snapshot or had its module reloaded
+    pub peak_used_bytes: u64,
+    /// The size in bytes of the heap the runtime allocates from, a fixed
+    /// region of guest memory set by the sandbox's heap size. Its usage
+    /// is not reported.
+    pub heap_bytes: u64,
+    /// The names and sizes in bytes of the linear memories exported by
+    /// the loaded module. Components do not report their memories.
+    pub linear_memories: Vec<(String, u64)>,
+}
+
+impl MemoryStats {
+    /// The total size in bytes of the linear memories in
+    /// [`linear_memories`](Self::linear_memories)
+    pub fn linear_memory_bytes(&self) -> u64 {
+        self.linear_memories.iter().map(|(_, size)| size).sum()
+    }
+}
+
+/// Tracks the peak memory used by a sandbox's guests, and publishes the
+/// memory they use as metrics if the sandbox was built with
+/// [`SandboxBuilder::with_memory_metrics`](crate::SandboxBuilder::with_memory_metrics)
+#[derive(Clone, Debug, Default)]
+pub(crate) struct MemoryTracker {
+    peak_used_bytes: u64,
+    // The value of the sandbox label of the metrics, if they are recorded
+    pub(crate) metrics_label: Option<String>,
+}
+
+impl MemoryTracker {
+    pub(crate) fn new(metrics_label: Option<String>) -> Self {
+        Self {
+            peak_used_bytes: 0,
+            metrics_label,
+        }
+    }
+
+    /// Fetch the memory used by the guest of `inner`, recording it in the
+    /// peak and the metrics
+    pub(crate) fn fetch(&mut self, inner: &mut MultiUseSandbox) -> Result<MemoryStats> {
+        self.call(inner, GET_MEMORY_STATS_FUNCTION)
+    }
+
+    /// Have the guest of `inner` release the memory it no longer needs,
+    /// then fetch the memory it uses as [`fetch`](Self::fetch) does
+    pub(crate) fn trim(&mut self, inner: &mut MultiUseSandbox) -> Result<MemoryStats> {
+        self.call(inner, TRIM_MEMORY_FUNCTION)
+    }
+
+    fn call(&mut self, inner: &mut MultiUseSandbox, function: &str) -> Result<MemoryStats> {
+        let bytes: Vec<u8> = inner.call(function, ())?;
+        let guest_stats = GuestMemoryStats::from_bytes(&bytes)
+            .ok_or_else(|| new_error!("malformed memory statistics returned by the guest"))?;
+        Ok(self.record(guest_stats))
+    }
+
+    fn record(&mut self, guest_stats: GuestMemoryStats) -> MemoryStats {
+        self.peak_used_bytes = self.peak_used_bytes.max(guest_stats.mapped_bytes);
+        let stats = MemoryStats {
+            used_bytes: guest_stats.mapped_bytes,
+            peak_used_bytes: self.peak_used_bytes,
+            heap_bytes: guest_stats.heap_bytes,
+            linear_memories: guest_stats.linear_memories,
+        };
+        if let Some(label) = &self.metrics_label {
+            metrics::gauge!(METRIC_SANDBOX_MEMORY_USED_BYTES, METRIC_SANDBOX_LABEL_NAME => label.clone())
+                .set(stats.used_bytes as f64);
+            metrics::gauge!(METRIC_SANDBOX_PEAK_MEMORY_USED_BYTES, METRIC_SANDBOX_LABEL_NAME => label.clone())
+                .set(stats.peak_used_bytes as f64);
+            metrics::gauge!(METRIC_SANDBOX_LINEAR_MEMORY_BYTES, METRIC_SANDBOX_LABEL_NAME => label.clone())
+                .set(stats.linear_memory_bytes() as f64);
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyperlight_wasm_runtime::memory_stats::GuestMemoryStats;
+
+    use super::MemoryTracker;
+
+    #[test]
+    fn test_memory_tracker_peak() {
+        let mut tracker = MemoryTracker::default();
+        let stats = tracker.record(GuestMemoryStats {
+            mapped_bytes: 8192,
+            heap_bytes: 65536,
+            linear_memories: vec![("memory".to_string(), 65536), ("other".to_string(), 131072)],
+        });
+        assert_eq!(stats.used_bytes, 8192);
+        assert_eq!(stats.peak_used_bytes, 8192);
+        assert_eq!(stats.heap_bytes, 65536);
+        assert_eq!(stats.linear_memory_bytes(), 196608);
+
+        // The peak is kept after the guest's memory is reset, for example
+        // by restoring a snapshot
+        let stats = tracker.record(GuestMemoryStats {
+            mapped_bytes: 4096,
+            heap_bytes: 65536,
+            linear_memories: Vec::new(),
+        });
+        assert_eq!(stats.used_bytes, 4096);
+        assert_eq!(stats.peak_used_bytes, 8192);
+        assert_eq!(stats.linear_memory_bytes(), 0);
+
+        let bytes = GuestMemoryStats {
+            mapped_bytes: 1,
+            heap_bytes: 3,
+            linear_memories: vec![("memory".to_string(), 2)],
+        }
+        .to_bytes();
+        assert!(GuestMemoryStats::from_bytes(&bytes).is_some());
+        assert!(GuestMemoryStats::from_bytes(&bytes[..bytes.len() - 1]).is_none());
+    }
+}
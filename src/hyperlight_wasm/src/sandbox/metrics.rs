@@ -32,11 +32,61 @@ pub(crate) static METRIC_TOTAL_LOADED_WASM_SANDBOXES: &str = "loaded_wasm_sandbo
 pub(crate) static METRIC_SANDBOX_LOADS: &str = "sandbox_loads_total";
 pub(crate) static METRIC_SANDBOX_UNLOADS: &str = "sandbox_unloads_total";
 
+// Counters, modules upgraded in place, and upgrades that failed and were rolled back
+pub(crate) static METRIC_MODULE_UPGRADES: &str = "wasm_module_upgrades_total";
+pub(crate) static METRIC_MODULE_UPGRADE_ROLLBACKS: &str = "wasm_module_upgrade_rollbacks_total";
+
+// Counters, modules loaded from a sandbox's module cache, and loads that did not find the module cached
+pub(crate) static METRIC_MODULE_CACHE_HITS: &str = "wasm_module_cache_hits_total";
+pub(crate) static METRIC_MODULE_CACHE_MISSES: &str = "wasm_module_cache_misses_total";
+
+// Counters, guest function calls made on loaded sandboxes, whether directly or through component bindings
+pub(crate) static METRIC_GUEST_FUNCTION_CALLS: &str = "wasm_guest_function_calls_total";
+pub(crate) static METRIC_GUEST_FUNCTION_CALL_ERRORS: &str = "wasm_guest_function_call_errors_total";
+pub(crate) static METRIC_GUEST_FUNCTION_LABEL_NAME: &str = "function_name";
+
+// Per guest function call timing, recorded if the function_call_metrics feature is enabled
+pub(crate) static METRIC_GUEST_FUNCTION_CALL_DURATION: &str =
+    "wasm_guest_function_call_duration_seconds";
+pub(crate) static METRIC_GUEST_FUNCTION_HOST_CALLS: &str = "wasm_guest_function_host_calls_total";
+
+// Counter, loaded sandboxes dropped in the middle of a guest call, whose call was interrupted
+pub(crate) static METRIC_FORCED_TERMINATIONS: &str = "wasm_sandbox_forced_terminations_total";
+
+// Usage of each module across all sandboxes that record it, see module_usage
+pub(crate) static METRIC_MODULE_CALLS: &str = "wasm_module_calls_total";
+pub(crate) static METRIC_MODULE_CPU_MICROSECONDS: &str = "wasm_module_cpu_microseconds_total";
+pub(crate) static METRIC_MODULE_PEAK_MEMORY_BYTES: &str = "wasm_module_peak_memory_bytes";
+pub(crate) static METRIC_MODULE_HASH_LABEL_NAME: &str = "module_hash";
+
+// Sandbox pools, see sandbox_pool
+pub(crate) static METRIC_POOL_IDLE_SANDBOXES: &str = "wasm_sandbox_pool_idle_sandboxes";
+pub(crate) static METRIC_POOL_CHECKOUTS: &str = "wasm_sandbox_pool_checkouts_total";
+pub(crate) static METRIC_POOL_MISSES: &str = "wasm_sandbox_pool_misses_total";
+pub(crate) static METRIC_POOL_CHECKINS: &str = "wasm_sandbox_pool_checkins_total";
+
+// Sandbox executors, see executor
+pub(crate) static METRIC_EXECUTOR_QUEUED_CALLS: &str = "wasm_executor_queued_calls";
+pub(crate) static METRIC_EXECUTOR_BUSY_WORKERS: &str = "wasm_executor_busy_workers";
+pub(crate) static METRIC_EXECUTOR_CALLS: &str = "wasm_executor_calls_total";
+pub(crate) static METRIC_EXECUTOR_STEALS: &str = "wasm_executor_steals_total";
+pub(crate) static METRIC_EXECUTOR_RECYCLED_SANDBOXES: &str =
+    "wasm_executor_recycled_sandboxes_total";
+
+// Gauges, memory used by sandboxes that record it, see memory_stats
+pub(crate) static METRIC_SANDBOX_MEMORY_USED_BYTES: &str = "wasm_sandbox_memory_used_bytes";
+pub(crate) static METRIC_SANDBOX_PEAK_MEMORY_USED_BYTES: &str =
+    "wasm_sandbox_peak_memory_used_bytes";
+pub(crate) static METRIC_SANDBOX_LINEAR_MEMORY_BYTES: &str = "wasm_sandbox_linear_memory_bytes";
+pub(crate) static METRIC_SANDBOX_LABEL_NAME: &str = "sandbox";
+
 #[cfg(test)]
 mod tests {
     use examples_common::get_wasm_module_path;
     use hyperlight_host::HyperlightError;
+    use metrics_util::debugging::DebugValue;
 
+    use super::*;
     use crate::{LoadedWasmSandbox, ProtoWasmSandbox, Result};
 
     fn get_time_since_boot_microsecond() -> Result<i64> {
@@ -76,5 +126,89 @@ mod tests {
         } else {
             assert_eq!(snapshot.len(), 8);
         }
+
+        // With the function_call_metrics feature, guest function calls are
+        // counted per function, including failed ones
+        let snapshot = {
+            let mut sandbox = ProtoWasmSandbox::default();
+            sandbox
+                .register(
+                    "GetTimeSinceBootMicrosecond",
+                    get_time_since_boot_microsecond,
+                )
+                .unwrap();
+
+            let wasm_sandbox = sandbox.load_runtime().unwrap();
+            let mut loaded_wasm_sandbox: LoadedWasmSandbox = {
+                let mod_path = get_wasm_module_path("RunWasm.aot").unwrap();
+                wasm_sandbox.load_module(mod_path).unwrap()
+            };
+            let _: i32 = loaded_wasm_sandbox
+                .call_guest_function("CalcFib", 10i32)
+                .unwrap();
+            let _: i32 = loaded_wasm_sandbox
+                .call_guest_function("CalcFib", 10i32)
+                .unwrap();
+            assert!(
+                loaded_wasm_sandbox
+                    .call_guest_function::<i32>("NoSuchFunction", ())
+                    .is_err()
+            );
+            snapshotter.snapshot()
+        };
+        let snapshot = snapshot.into_vec();
+        let counter = |name: &str, function_name: &str| {
+            snapshot.iter().find_map(|(key, _, _, value)| {
+                let key = key.key();
+                let matches = key.name() == name
+                    && key.labels().any(|l| {
+                        l.key() == METRIC_GUEST_FUNCTION_LABEL_NAME && l.value() == function_name
+                    });
+                match value {
+                    DebugValue::Counter(c) if matches => Some(*c),
+                    _ => None,
+                }
+            })
+        };
+        if cfg!(feature = "function_call_metrics") {
+            assert_eq!(counter(METRIC_GUEST_FUNCTION_CALLS, "CalcFib"), Some(2));
+            assert_eq!(counter(METRIC_GUEST_FUNCTION_CALL_ERRORS, "CalcFib"), None);
+            assert_eq!(
+                counter(METRIC_GUEST_FUNCTION_CALLS, "NoSuchFunction"),
+                Some(1)
+            );
+            assert_eq!(
+                counter(METRIC_GUEST_FUNCTION_CALL_ERRORS, "NoSuchFunction"),
+                Some(1)
+            );
+        } else {
+            assert_eq!(counter(METRIC_GUEST_FUNCTION_CALLS, "CalcFib"), None);
+            assert_eq!(
+                counter(METRIC_GUEST_FUNCTION_CALL_ERRORS, "NoSuchFunction"),
+                None
+            );
+        }
+
+        // Calls are timed, and the host functions they call counted, per
+        // function
+        let durations = snapshot.iter().find_map(|(key, _, _, value)| match value {
+            DebugValue::Histogram(values)
+                if key.key().name() == METRIC_GUEST_FUNCTION_CALL_DURATION
+                    && key.key().labels().any(|l| l.value() == "CalcFib") =>
+            {
+                Some(values.len())
+            }
+            _ => None,
+        });
+        if cfg!(feature = "function_call_metrics") {
+            assert_eq!(durations, Some(2));
+            assert_eq!(
+                counter(METRIC_GUEST_FUNCTION_HOST_CALLS, "CalcFib"),
+                Some(0)
+            );
+        } else {
+            assert_eq!(durations, None);
+            assert_eq!(counter(METRIC_GUEST_FUNCTION_HOST_CALLS, "CalcFib"), None);
+        }
     }
 }
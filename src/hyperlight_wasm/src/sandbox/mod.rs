@@ -14,12 +14,107 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+/// Host functions implemented by async Rust functions.
+#[cfg(feature = "async")]
+pub(crate) mod async_host;
+/// Time budgets for guest calls and the host functions they call.
+pub(crate) mod call_budget;
+/// Hooks run around guest calls and the host functions they call.
+pub(crate) mod call_hooks;
+/// The result of a guest call together with telemetry about it.
+pub(crate) mod call_outcome;
+/// How the time of guest calls was spent.
+pub(crate) mod call_report;
+/// Timeouts for guest calls
+pub(crate) mod call_timeout;
+/// Host functions replaced for a single guest call.
+pub(crate) mod callbacks;
+/// Cancellation of guest calls through tokens.
+pub(crate) mod cancellation;
+/// Arguments and environment variables set for guests.
+pub(crate) mod cli_environment;
+/// Cooperative deadlines for guest calls.
+pub(crate) mod epoch_deadline;
+/// Guest calls run on sandboxes shared by worker threads.
+pub(crate) mod executor;
+/// The exit status of command modules.
+pub(crate) mod exit_status;
+/// The string and buffer conventions of Wasm modules.
+pub(crate) mod guest_abi;
+/// Errors of guest calls that ended because the guest aborted.
+pub(crate) mod guest_aborted;
+/// Host functions that call back into the module calling them.
+pub(crate) mod guest_callbacks;
+/// Resources exported by components and given to the host.
+pub(crate) mod guest_resource;
+/// Errors of guest calls that trapped.
+pub(crate) mod guest_trap;
+/// Errors raised by host functions that cannot return one.
+pub(crate) mod host_error;
+/// Caching of host function results in the guest.
+pub(crate) mod host_function_cache;
+/// Errors of guest calls that failed because a host function did.
+pub(crate) mod host_function_failed;
+/// Host functions described by a manifest.
+pub(crate) mod host_function_manifest;
+/// Serialized state of module instances.
+pub(crate) mod instance_state;
 /// A Wasm Sandbox loaded with a module.
 pub(crate) mod loaded_wasm_sandbox;
+/// Host buffers mapped into loaded sandboxes.
+pub(crate) mod mapped_buffers;
+/// The memory used by the guests of a sandbox.
+pub(crate) mod memory_stats;
 /// Metric definitions for Sandbox module.
 pub(crate) mod metrics;
+/// Snapshots of sandboxes taken just after modules were loaded.
+pub(crate) mod module_cache;
+/// Module files mapped once and loaded into many sandboxes.
+#[cfg(target_os = "linux")]
+pub(crate) mod module_handle;
+/// Finding modules to load by name.
+pub(crate) mod module_resolver;
+/// Usage of modules across all sandboxes.
+pub(crate) mod module_usage;
+/// Results of functions that return more than one value.
+pub(crate) mod multi_value;
+/// Capture of the output guests write to stdout and stderr.
+pub(crate) mod output_capture;
+/// Handling of panics in host functions.
+pub(crate) mod panic_policy;
+/// Encryption of buffers passed to and returned from guest calls.
+pub(crate) mod payload_cipher;
+/// Host directories guests read as WASI filesystems.
+pub(crate) mod preopened_dirs;
+/// Signed records of what was loaded into a sandbox.
+pub(crate) mod provenance;
+/// Functions guests must export to be loaded
+pub(crate) mod required_exports;
+/// Choosing the runtime a sandbox runs.
+pub(crate) mod runtime_kind;
 /// A builder for a WasmSandbox.
 pub(crate) mod sandbox_builder;
+/// The state a sandbox keeps across module loads.
+pub(crate) mod sandbox_context;
+/// A pool of sandboxes with the runtime loaded.
+pub(crate) mod sandbox_pool;
+/// Errors of guest calls that exhausted the wasm stack.
+pub(crate) mod stack_exhausted;
+/// Large buffers passed to guest calls in chunks.
+pub(crate) mod staged_params;
+/// Values shared between the host and the guests of a sandbox.
+pub(crate) mod state_cells;
+/// Guest function results streamed in chunks.
+pub(crate) mod streaming;
+/// In-memory filesystems provided to guests.
+pub(crate) mod vfs;
+/// The clock seen by the guests of a sandbox.
+pub(crate) mod virtual_clock;
+/// The wasm context written next to the core dumps of guest crashes.
+#[cfg(feature = "crashdump")]
+pub(crate) mod wasm_crashdump;
+/// Limits on the linear memories and tables of guests.
+pub(crate) mod wasm_limits;
 /// A Wasm Sandbox that can load a module.
 pub(crate) mod wasm_sandbox;
 
@@ -27,6 +122,8 @@ pub(crate) mod proto_wasm_sandbox;
 
 // This include! macro is replaced by the build.rs script.
 // The build.rs script reads the hyperlight-wasm-runtime binary into a static byte array named WASM_RUNTIME
-// contained in the wasm_runtime_resource.rs file.
+// contained in the wasm_runtime_resource.rs file, along with the kind of runtime it is and, in
+// builds for components, the runtime for modules in WASM_MODULE_RUNTIME.
 
+use runtime_kind::RuntimeKind;
 include!(concat!(env!("OUT_DIR"), "/wasm_runtime_resource.rs"));
@@ -0,0 +1,95 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use hyperlight_host::sandbox::snapshot::Snapshot;
+
+use super::guest_abi::GuestAbi;
+use super::metrics::{METRIC_MODULE_CACHE_HITS, METRIC_MODULE_CACHE_MISSES};
+
+/// Snapshots of a sandbox taken just after modules were loaded into it,
+/// keyed by the hash of each module's bytes and the ABI it was loaded
+/// with, see
+/// [`SandboxBuilder::with_module_cache`](crate::SandboxBuilder::with_module_cache).
+///
+/// Restoring a snapshot loads the module without precompiling,
+/// deserializing or instantiating it again. Snapshots can only be
+/// restored into the sandbox that took them, so each sandbox has its own
+/// cache.
+#[derive(Default)]
+pub(crate) struct ModuleCache {
+    capacity: usize,
+    // The least recently used snapshot first
+    entries: VecDeque<(String, GuestAbi, Arc<Snapshot>)>,
+}
+
+impl ModuleCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Whether modules are cached
+    pub(crate) fn enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// The snapshot taken after the module with hash `module_hash` was
+    /// loaded with `guest_abi`, if it is cached
+    pub(crate) fn get(&mut self, module_hash: &str, guest_abi: GuestAbi) -> Option<Arc<Snapshot>> {
+        let Some(index) = self
+            .entries
+            .iter()
+            .position(|(hash, abi, _)| hash == module_hash && *abi == guest_abi)
+        else {
+            metrics::counter!(METRIC_MODULE_CACHE_MISSES).increment(1);
+            return None;
+        };
+        metrics::counter!(METRIC_MODULE_CACHE_HITS).increment(1);
+        let entry = self.entries.remove(index)?;
+        let snapshot = entry.2.clone();
+        self.entries.push_back(entry);
+        Some(snapshot)
+    }
+
+    /// Cache `snapshot`, taken after the module with hash `module_hash`
+    /// was loaded with `guest_abi`, evicting the least recently used
+    /// snapshot if the cache is full
+    pub(crate) fn insert(
+        &mut self,
+        module_hash: String,
+        guest_abi: GuestAbi,
+        snapshot: Arc<Snapshot>,
+    ) {
+        if !self.enabled() {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((module_hash, guest_abi, snapshot));
+    }
+
+    /// Forget the cached snapshots, which are stale once host functions
+    /// are registered with the sandbox
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
@@ -0,0 +1,225 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use hyperlight_host::{Result, new_error};
+
+use super::module_usage;
+
+/// A module file read into host memory and hashed once, so that it can
+/// be loaded into many sandboxes with
+/// [`WasmSandbox::load_module_handle`](crate::WasmSandbox::load_module_handle)
+/// without reading or copying it again.
+///
+/// Each sandbox the module is loaded into maps the same read-only host
+/// pages, so this is intended for precompiled `.aot` modules loaded into
+/// a fleet of sandboxes. Handles are cheap to clone, and the module stays
+/// in memory until the last clone, and the last sandbox it was loaded
+/// into, is dropped. The file is copied when it is opened, so it may be
+/// removed, replaced or written to in the meantime without affecting
+/// the handle.
+#[derive(Clone)]
+pub struct ModuleHandle {
+    mapping: Arc<Mapping>,
+}
+
+struct Mapping {
+    path: PathBuf,
+    base: *mut libc::c_void,
+    len: usize,
+    mapped_len: usize,
+    hash: String,
+}
+
+// Safety: the mapping is anonymous and read-only, and is only unmapped
+// once no handle refers to it
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl Mapping {
+    fn bytes(&self) -> &[u8] {
+        // Safety: the mapping holds len readable bytes until it is
+        // dropped
+        unsafe { std::slice::from_raw_parts(self.base as *const u8, self.len) }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        // Safety: base and mapped_len describe the mapping made by
+        // ModuleHandle::open, which nothing refers to any more
+        unsafe { libc::munmap(self.base, self.mapped_len) };
+    }
+}
+
+impl ModuleHandle {
+    /// Read the module file at `file` into memory, and hash its contents
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or memory cannot be
+    /// mapped for it, or it is empty.
+    pub fn open(file: impl AsRef<Path>) -> Result<Self> {
+        let path = file.as_ref();
+        let mut file = File::open(path)?;
+        let len = usize::try_from(file.metadata()?.len())
+            .map_err(|_| new_error!("module {} is too large to map", path.display()))?;
+        if len == 0 {
+            return Err(new_error!("module {} is empty", path.display()));
+        }
+        // The file is copied into anonymous memory rather than mapped, so
+        // that other processes truncating or writing to it cannot change
+        // the module once it has been hashed
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let mapped_len = len.div_ceil(page_size) * page_size;
+        // Safety: a new private, anonymous mapping
+        let base = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                mapped_len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if base == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        let mut mapping = Mapping {
+            path: path.to_path_buf(),
+            base,
+            len,
+            mapped_len,
+            hash: String::new(),
+        };
+        // Safety: the mapping is writable and holds len bytes, and
+        // nothing else refers to it yet
+        let bytes = unsafe { std::slice::from_raw_parts_mut(base as *mut u8, len) };
+        file.read_exact(bytes)
+            .map_err(|e| new_error!("failed to read module {}: {}", path.display(), e))?;
+        // Safety: base and mapped_len describe the mapping made above
+        if unsafe { libc::mprotect(base, mapped_len, libc::PROT_READ) } != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        mapping.hash = module_usage::module_hash(mapping.bytes());
+        Ok(Self {
+            mapping: Arc::new(mapping),
+        })
+    }
+
+    /// Open the module file at `file` as [`open`](Self::open) does,
+    /// checking that its contents have the hash `expected_hash`, as
+    /// returned by [`hash`](Self::hash)
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be opened, or its contents do
+    /// not have the hash `expected_hash`.
+    pub fn open_verified(file: impl AsRef<Path>, expected_hash: &str) -> Result<Self> {
+        let handle = Self::open(file)?;
+        if handle.hash() != expected_hash {
+            return Err(new_error!(
+                "module {} has hash {}, expected {}",
+                handle.mapping.path.display(),
+                handle.hash(),
+                expected_hash
+            ));
+        }
+        Ok(handle)
+    }
+
+    /// The hash of the module's contents, as recorded by
+    /// [`module_usage`](crate::module_usage) and provenance
+    pub fn hash(&self) -> &str {
+        &self.mapping.hash
+    }
+
+    /// The length of the module in bytes
+    pub fn len(&self) -> usize {
+        self.mapping.len
+    }
+
+    /// Whether the module is empty, which it never is
+    pub fn is_empty(&self) -> bool {
+        self.mapping.len == 0
+    }
+
+    /// The start of the mapping, and its length in whole pages
+    pub(crate) fn mapping(&self) -> (*mut libc::c_void, usize) {
+        (self.mapping.base, self.mapping.mapped_len)
+    }
+
+    /// Whether `self` and `other` are handles to the same mapping
+    pub(crate) fn same_mapping(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.mapping, &other.mapping)
+    }
+}
+
+impl fmt::Debug for ModuleHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ModuleHandle")
+            .field("path", &self.mapping.path)
+            .field("len", &self.mapping.len)
+            .field("hash", &self.mapping.hash)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ModuleHandle;
+    use crate::sandbox::module_usage;
+
+    #[test]
+    fn test_module_handle() {
+        let path = std::env::temp_dir().join(format!("hlwasm-handle-{}", std::process::id()));
+        let contents: Vec<u8> = (0..5000u32).map(|i| i as u8).collect();
+        std::fs::write(&path, &contents).unwrap();
+
+        let handle = ModuleHandle::open(&path).unwrap();
+        assert_eq!(handle.mapping.bytes(), contents.as_slice());
+        assert_eq!(handle.len(), contents.len());
+        assert_eq!(handle.hash(), module_usage::module_hash(&contents));
+        let (_, mapped_len) = handle.mapping();
+        assert!(mapped_len >= contents.len());
+
+        let clone = handle.clone();
+        assert!(clone.same_mapping(&handle));
+        let verified = ModuleHandle::open_verified(&path, handle.hash()).unwrap();
+        assert!(!verified.same_mapping(&handle));
+        assert!(ModuleHandle::open_verified(&path, "0").is_err());
+
+        // The handle holds a copy of the file, which may change or be
+        // removed
+        std::fs::write(&path, b"changed").unwrap();
+        assert_eq!(handle.mapping.bytes(), contents.as_slice());
+        assert!(ModuleHandle::open_verified(&path, handle.hash()).is_err());
+        std::fs::remove_file(&path).unwrap();
+        assert!(ModuleHandle::open(&path).is_err());
+        assert_eq!(handle.mapping.bytes(), contents.as_slice());
+
+        let empty = path.with_extension("empty");
+        std::fs::write(&empty, b"").unwrap();
+        assert!(ModuleHandle::open(&empty).is_err());
+        std::fs::remove_file(&empty).unwrap();
+    }
+}
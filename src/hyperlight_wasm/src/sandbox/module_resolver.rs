@@ -0,0 +1,129 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::path::PathBuf;
+
+use hyperlight_host::{Result, new_error};
+
+/// Where the AOT compiled module found by a [`ModuleResolver`] is
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ModuleSource {
+    /// A file, loaded with [`WasmSandbox::load_module`](crate::WasmSandbox::load_module)
+    Path(PathBuf),
+    /// A buffer, loaded with
+    /// [`WasmSandbox::load_module_from_buffer`](crate::WasmSandbox::load_module_from_buffer)
+    Bytes(Vec<u8>),
+}
+
+/// Finds modules by name for
+/// [`WasmSandbox::load_module_by_name`](crate::WasmSandbox::load_module_by_name),
+/// so that embedders can keep the lookup, caching and verification of
+/// modules in one place. Set a sandbox's resolver with
+/// [`SandboxBuilder::with_module_resolver`](crate::SandboxBuilder::with_module_resolver).
+///
+/// [`DirectoryResolver`] finds modules in directories on the filesystem.
+/// Any `Fn(&str) -> Result<ModuleSource>` is also a resolver, which can
+/// be used to fetch modules from other sources, such as OCI registries or
+/// URLs.
+pub trait ModuleResolver: Send + Sync {
+    /// Find the module called `name`
+    fn resolve(&self, name: &str) -> Result<ModuleSource>;
+}
+
+impl<F> ModuleResolver for F
+where
+    F: Fn(&str) -> Result<ModuleSource> + Send + Sync,
+{
+    fn resolve(&self, name: &str) -> Result<ModuleSource> {
+        self(name)
+    }
+}
+
+/// A [`ModuleResolver`] that looks for the module `name` as the file
+/// `name.aot` in each of a list of directories in turn
+#[derive(Clone, Debug)]
+pub struct DirectoryResolver {
+    directories: Vec<PathBuf>,
+    extension: String,
+}
+
+impl DirectoryResolver {
+    /// Create a resolver that searches `directories` in order
+    pub fn new(directories: impl IntoIterator<Item = impl Into<PathBuf>>) -> Self {
+        Self {
+            directories: directories.into_iter().map(Into::into).collect(),
+            extension: "aot".to_string(),
+        }
+    }
+
+    /// Look for files with `extension` rather than `aot`
+    pub fn with_extension(mut self, extension: impl Into<String>) -> Self {
+        self.extension = extension.into();
+        self
+    }
+}
+
+impl ModuleResolver for DirectoryResolver {
+    fn resolve(&self, name: &str) -> Result<ModuleSource> {
+        // Names must not be able to reach outside the directories
+        if name.is_empty()
+            || name.starts_with('.')
+            || name.contains(['/', '\\'])
+            || name.contains(std::path::MAIN_SEPARATOR)
+        {
+            return Err(new_error!("invalid module name {:?}", name));
+        }
+        let file_name = format!("{}.{}", name, self.extension);
+        self.directories
+            .iter()
+            .map(|dir| dir.join(&file_name))
+            .find(|path| path.is_file())
+            .map(ModuleSource::Path)
+            .ok_or_else(|| new_error!("module {} not found in {:?}", name, self.directories))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DirectoryResolver, ModuleResolver, ModuleSource};
+
+    #[test]
+    fn test_directory_resolver() {
+        let first = std::env::temp_dir().join(format!("hlwasm-resolver-{}-a", std::process::id()));
+        let second = std::env::temp_dir().join(format!("hlwasm-resolver-{}-b", std::process::id()));
+        std::fs::create_dir_all(&first).unwrap();
+        std::fs::create_dir_all(&second).unwrap();
+        std::fs::write(second.join("plugin-x.aot"), b"").unwrap();
+        std::fs::write(first.join("plugin-y.aot"), b"").unwrap();
+        std::fs::write(second.join("plugin-y.aot"), b"").unwrap();
+
+        let resolver = DirectoryResolver::new([&first, &second]);
+        assert_eq!(
+            resolver.resolve("plugin-x").unwrap(),
+            ModuleSource::Path(second.join("plugin-x.aot"))
+        );
+        assert_eq!(
+            resolver.resolve("plugin-y").unwrap(),
+            ModuleSource::Path(first.join("plugin-y.aot"))
+        );
+        assert!(resolver.resolve("plugin-z").is_err());
+        assert!(resolver.resolve("../plugin-x").is_err());
+        assert!(resolver.resolve("").is_err());
+
+        std::fs::remove_dir_all(&first).unwrap();
+        std::fs::remove_dir_all(&second).unwrap();
+    }
+}
@@ -0,0 +1,191 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+use hyperlight_host::sandbox::snapshot::Snapshot;
+
+use super::metrics::{
+    METRIC_MODULE_CALLS, METRIC_MODULE_CPU_MICROSECONDS, METRIC_MODULE_HASH_LABEL_NAME,
+    METRIC_MODULE_PEAK_MEMORY_BYTES,
+};
+
+/// The usage of a module, accumulated across all the sandboxes in the
+/// process that were built with
+/// [`SandboxBuilder::with_usage_accounting`](crate::SandboxBuilder::with_usage_accounting),
+/// see [`module_usage`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ModuleUsage {
+    /// The number of guest calls made to the module
+    pub calls: u64,
+    /// The CPU time used by the threads running the calls, including the
+    /// host functions they called. On platforms other than Linux this is
+    /// the wall-clock time the calls took.
+    pub cpu_time: Duration,
+    /// The largest size of the module's linear memory after a call, in
+    /// bytes. This is `None` for components, and for modules that do not
+    /// export their memory as `memory`.
+    pub peak_memory: Option<u64>,
+}
+
+// Usage by module hash
+static USAGE: Mutex<BTreeMap<String, ModuleUsage>> = Mutex::new(BTreeMap::new());
+
+// The hashes of the modules loaded in snapshots taken by sandboxes that
+// record usage, so that sandboxes loaded from the snapshots can record
+// usage under the same hash
+static SNAPSHOT_MODULES: Mutex<Vec<(Weak<Snapshot>, String)>> = Mutex::new(Vec::new());
+
+/// The usage of the module whose hash is `module_hash`, or `None` if no
+/// calls to it have been recorded.
+///
+/// A module's hash is the hex-encoded BLAKE3 hash of the file or buffer
+/// it was loaded from, as returned by
+/// [`LoadedWasmSandbox::module_hash`](crate::LoadedWasmSandbox::module_hash).
+/// Usage is also exported through the
+/// `wasm_module_calls_total`, `wasm_module_cpu_microseconds_total` and
+/// `wasm_module_peak_memory_bytes` metrics, labelled with `module_hash`.
+pub fn module_usage(module_hash: &str) -> Option<ModuleUsage> {
+    USAGE.lock().ok()?.get(module_hash).copied()
+}
+
+/// The usage of every module whose calls have been recorded, by module
+/// hash, see [`module_usage`]
+pub fn all_module_usage() -> BTreeMap<String, ModuleUsage> {
+    USAGE.lock().map(|usage| usage.clone()).unwrap_or_default()
+}
+
+/// Hash a module's bytes
+pub(crate) fn module_hash(bytes: &[u8]) -> String {
+    blake3::hash(bytes).to_string()
+}
+
+/// Hash a module's bytes together with those of the library modules
+/// linked before it
+pub(crate) fn linked_module_hash(linked_modules: &[(String, Vec<u8>)], bytes: &[u8]) -> String {
+    let mut hasher = blake3::Hasher::new();
+    for (_, library) in linked_modules {
+        hasher.update(library);
+    }
+    hasher.update(bytes);
+    hasher.finalize().to_string()
+}
+
+/// Record that `snapshot` holds the module whose hash is `module_hash`
+pub(crate) fn remember_snapshot(snapshot: &Arc<Snapshot>, module_hash: &str) {
+    if let Ok(mut snapshots) = SNAPSHOT_MODULES.lock() {
+        snapshots.retain(|(snapshot, _)| snapshot.strong_count() > 0);
+        snapshots.push((Arc::downgrade(snapshot), module_hash.to_string()));
+    }
+}
+
+/// The hash of the module held by `snapshot`, if it was taken by a
+/// sandbox that records usage
+pub(crate) fn snapshot_module_hash(snapshot: &Arc<Snapshot>) -> Option<String> {
+    let snapshots = SNAPSHOT_MODULES.lock().ok()?;
+    snapshots
+        .iter()
+        .find(|(s, _)| std::ptr::eq(s.as_ptr(), Arc::as_ptr(snapshot)))
+        .map(|(_, hash)| hash.clone())
+}
+
+/// Record a call to the module whose hash is `module_hash`
+pub(crate) fn record_call(module_hash: &str, cpu_time: Duration, memory_size: Option<u64>) {
+    let Ok(mut usage) = USAGE.lock() else {
+        return;
+    };
+    let usage = usage.entry(module_hash.to_string()).or_default();
+    usage.calls += 1;
+    usage.cpu_time += cpu_time;
+    if let Some(size) = memory_size {
+        usage.peak_memory = Some(usage.peak_memory.unwrap_or(0).max(size));
+    }
+
+    let label = module_hash.to_string();
+    metrics::counter!(METRIC_MODULE_CALLS, METRIC_MODULE_HASH_LABEL_NAME => label.clone())
+        .increment(1);
+    metrics::counter!(METRIC_MODULE_CPU_MICROSECONDS, METRIC_MODULE_HASH_LABEL_NAME => label.clone())
+        .increment(cpu_time.as_micros() as u64);
+    if let Some(peak) = usage.peak_memory {
+        metrics::gauge!(METRIC_MODULE_PEAK_MEMORY_BYTES, METRIC_MODULE_HASH_LABEL_NAME => label)
+            .set(peak as f64);
+    }
+}
+
+/// Measures the CPU time used by the current thread
+pub(crate) struct CpuTimer {
+    wall: Instant,
+    cpu: Option<Duration>,
+}
+
+impl CpuTimer {
+    pub(crate) fn start() -> Self {
+        Self {
+            wall: Instant::now(),
+            cpu: thread_cpu_time(),
+        }
+    }
+
+    pub(crate) fn elapsed(&self) -> Duration {
+        match (self.cpu, thread_cpu_time()) {
+            (Some(start), Some(now)) => now.saturating_sub(start),
+            _ => self.wall.elapsed(),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn thread_cpu_time() -> Option<Duration> {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: ts is a valid timespec for clock_gettime to write to
+    if unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut ts) } != 0 {
+        return None;
+    }
+    Some(Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn thread_cpu_time() -> Option<Duration> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{all_module_usage, module_hash, module_usage, record_call};
+
+    #[test]
+    fn test_record_call() {
+        let hash = module_hash(b"test_record_call");
+        assert_eq!(module_usage(&hash), None);
+
+        record_call(&hash, Duration::from_millis(2), None);
+        record_call(&hash, Duration::from_millis(3), Some(65536));
+        record_call(&hash, Duration::from_millis(1), Some(4096));
+
+        let usage = module_usage(&hash).unwrap();
+        assert_eq!(usage.calls, 3);
+        assert_eq!(usage.cpu_time, Duration::from_millis(6));
+        assert_eq!(usage.peak_memory, Some(65536));
+        assert_eq!(all_module_usage().get(&hash), Some(&usage));
+    }
+}
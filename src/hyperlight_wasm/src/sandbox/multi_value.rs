@@ -0,0 +1,76 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use hyperlight_host::func::{ReturnType, ReturnValue, SupportedReturnType};
+use hyperlight_wasm_runtime::multi_value::{self, WasmValue};
+
+/// The values returned by a module function that returns more than one
+/// value, for example one compiled with the multi-value proposal
+/// enabled. Such functions are called with this as their return type,
+/// and the module's results are returned in order.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MultiValue(pub Vec<WasmValue>);
+
+impl SupportedReturnType for MultiValue {
+    const TYPE: ReturnType = ReturnType::VecBytes;
+
+    fn into_value(self) -> ReturnValue {
+        ReturnValue::VecBytes(multi_value::to_bytes(&self.0))
+    }
+
+    fn from_value(value: ReturnValue) -> Result<Self, hyperlight_common::func::Error> {
+        match value {
+            ReturnValue::VecBytes(bytes) => match multi_value::from_bytes(&bytes) {
+                Some(values) => Ok(MultiValue(values)),
+                None => Err(
+                    hyperlight_common::func::Error::ReturnValueConversionFailure(
+                        ReturnValue::VecBytes(bytes),
+                        "MultiValue",
+                    ),
+                ),
+            },
+            other => Err(hyperlight_common::func::Error::UnexpectedReturnValueType(
+                other,
+                "VecBytes".to_string(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyperlight_host::func::SupportedReturnType;
+    use hyperlight_wasm_runtime::multi_value::WasmValue;
+
+    use super::MultiValue;
+
+    #[test]
+    fn test_multi_value_round_trip() {
+        let values = MultiValue(vec![
+            WasmValue::I32(-1),
+            WasmValue::I64(1 << 40),
+            WasmValue::F32(1.5),
+            WasmValue::F64(-2.25),
+        ]);
+        let value = values.clone().into_value();
+        assert_eq!(MultiValue::from_value(value).unwrap(), values);
+        assert!(
+            MultiValue::from_value(hyperlight_host::func::ReturnValue::VecBytes(vec![0, 1]))
+                .is_err()
+        );
+        assert!(MultiValue::from_value(hyperlight_host::func::ReturnValue::Int(1)).is_err());
+    }
+}
@@ -0,0 +1,71 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use hyperlight_host::{Result, new_error};
+
+/// A writer guest output is captured into, shared by every sandbox
+/// built from the same `SandboxBuilder`
+pub(crate) type OutputSink = Arc<Mutex<dyn Write + Send>>;
+
+/// The writers guest writes to stdout and stderr are captured into
+#[derive(Clone, Default)]
+pub(crate) struct OutputCapture {
+    pub(crate) stdout: Option<OutputSink>,
+    pub(crate) stderr: Option<OutputSink>,
+}
+
+impl OutputCapture {
+    /// Write `bytes` written by the guest to `fd` to its sink, returning
+    /// the number of bytes written
+    pub(crate) fn write(&self, fd: i32, bytes: &[u8]) -> Result<i32> {
+        let sink = match fd {
+            1 => self.stdout.as_ref(),
+            2 => self.stderr.as_ref(),
+            _ => None,
+        }
+        .ok_or_else(|| new_error!("output to file descriptor {} is not captured", fd))?;
+        let mut sink = sink
+            .lock()
+            .map_err(|e| new_error!("Error locking output sink: {:?}", e))?;
+        sink.write_all(bytes)
+            .map_err(|e| new_error!("failed to write guest output: {}", e))?;
+        Ok(bytes.len() as i32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::OutputCapture;
+
+    #[test]
+    fn test_output_capture_write() {
+        let stdout = Arc::new(Mutex::new(Vec::new()));
+        let capture = OutputCapture {
+            stdout: Some(stdout.clone()),
+            stderr: None,
+        };
+        assert_eq!(capture.write(1, b"hello").unwrap(), 5);
+        assert_eq!(capture.write(1, b" world").unwrap(), 6);
+        assert_eq!(*stdout.lock().unwrap(), b"hello world");
+        assert!(capture.write(2, b"error").is_err());
+        assert!(capture.write(3, b"other").is_err());
+    }
+}
@@ -0,0 +1,203 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+
+use hyperlight_common::for_each_tuple;
+use hyperlight_host::func::{HostFunction, ParameterTuple, SupportedReturnType};
+use hyperlight_host::{HyperlightError, Result, new_error};
+
+/// What happens when a host function registered with
+/// [`ProtoWasmSandbox::register`](crate::ProtoWasmSandbox::register),
+/// [`register_cached`](crate::ProtoWasmSandbox::register_cached) or
+/// [`register_manifest`](crate::ProtoWasmSandbox::register_manifest)
+/// panics, see
+/// [`SandboxBuilder::with_panic_policy`](crate::SandboxBuilder::with_panic_policy).
+///
+/// The panic message is kept in the error returned by the guest call
+/// in every case but `Abort`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PanicPolicy {
+    /// Fail the guest call and poison the sandbox, since the host
+    /// function may have left state it shares with the guest
+    /// inconsistent. The sandbox cannot be called again until it is
+    /// restored or its module is reloaded.
+    #[default]
+    PoisonSandbox,
+    /// Return an error to the guest, as if the host function had
+    /// returned one, which traps the guest call. The sandbox can still
+    /// be called.
+    ReturnError,
+    /// Abort the process
+    Abort,
+}
+
+/// Handles the panics caught in the host functions of a sandbox
+/// according to its [`PanicPolicy`]. Clones share the poisoned state.
+#[derive(Clone, Default)]
+pub(crate) struct PanicHandler {
+    policy: PanicPolicy,
+    // The error for the panic that poisoned the sandbox, if any
+    poison: Arc<Mutex<Option<String>>>,
+}
+
+impl PanicHandler {
+    pub(crate) fn new(policy: PanicPolicy) -> Self {
+        Self {
+            policy,
+            poison: Arc::default(),
+        }
+    }
+
+    /// Call `f`, the host function `name`, handling any panic in it
+    pub(crate) fn call<T>(&self, name: &str, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        panic::catch_unwind(AssertUnwindSafe(f))
+            .unwrap_or_else(|payload| Err(self.handle_panic(name, payload)))
+    }
+
+    /// A function handling the panics of the host function `name`, for
+    /// [`CatchPanics::catch_panics`]
+    pub(crate) fn on_panic(&self, name: &str) -> OnPanic {
+        let handler = self.clone();
+        let name = name.to_string();
+        Arc::new(move |payload| handler.handle_panic(&name, payload))
+    }
+
+    /// The error for the panic that poisoned the sandbox, if one has
+    /// since the poison was last cleared
+    pub(crate) fn poison(&self) -> Option<String> {
+        self.poison.lock().ok()?.clone()
+    }
+
+    /// Clear the poison, once the sandbox has been restored
+    pub(crate) fn clear_poison(&self) {
+        if let Ok(mut poison) = self.poison.lock() {
+            *poison = None;
+        }
+    }
+
+    fn handle_panic(&self, name: &str, payload: Box<dyn Any + Send>) -> HyperlightError {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        let error = format!("host function {name} panicked: {message}");
+        match self.policy {
+            PanicPolicy::PoisonSandbox => {
+                tracing::error!("{error}, poisoning the sandbox");
+                if let Ok(mut poison) = self.poison.lock() {
+                    poison.get_or_insert_with(|| error.clone());
+                }
+            }
+            PanicPolicy::ReturnError => tracing::warn!("{error}"),
+            PanicPolicy::Abort => {
+                tracing::error!("{error}, aborting");
+                std::process::abort();
+            }
+        }
+        new_error!("{}", error)
+    }
+}
+
+/// Converts the payload of a panic caught in a host function into the
+/// error returned to the guest
+pub type OnPanic = Arc<dyn Fn(Box<dyn Any + Send>) -> HyperlightError + Send + Sync>;
+
+/// Makes a call to a host function, passed as the closure it is called
+/// with, so that the host can measure or limit the call. An error it
+/// returns is returned to the guest in place of the function's result.
+pub type OnHostCall = Arc<dyn Fn(&mut dyn FnMut()) -> Result<()> + Send + Sync>;
+
+/// Keeps an error returned by a host function, so that it can be
+/// attached to the [`HostFunctionFailed`](crate::HostFunctionFailed)
+/// error of the guest call, returning the error passed to the guest in
+/// its place.
+pub type OnHostError = Arc<dyn Fn(HyperlightError) -> HyperlightError + Send + Sync>;
+
+/// Implemented for the parameters of every host function that can be
+/// registered, so that panics in host functions can be caught and
+/// handled according to the sandbox's [`PanicPolicy`], the time they
+/// take measured and the errors they return kept.
+pub trait CatchPanics<Output: SupportedReturnType>: ParameterTuple {
+    /// Wrap `host_func` so that a panic in it is passed to `on_panic`,
+    /// and the error `on_panic` returns is returned to the guest
+    fn catch_panics(
+        host_func: HostFunction<Output, Self>,
+        on_panic: OnPanic,
+    ) -> HostFunction<Output, Self>;
+
+    /// Wrap `host_func` so that each call to it is made through
+    /// `on_call`, see [`OnHostCall`]
+    fn wrap_calls(
+        host_func: HostFunction<Output, Self>,
+        on_call: OnHostCall,
+    ) -> HostFunction<Output, Self>;
+
+    /// Wrap `host_func` so that each error it returns is passed to
+    /// `on_error`, and the error `on_error` returns is returned to the
+    /// guest
+    fn keep_errors(
+        host_func: HostFunction<Output, Self>,
+        on_error: OnHostError,
+    ) -> HostFunction<Output, Self>;
+}
+
+macro_rules! impl_catch_panics {
+    ([$N:expr] ($($p:ident: $P:ident),*)) => {
+        impl<Output, $($P),*> CatchPanics<Output> for ($($P,)*)
+        where
+            ($($P,)*): ParameterTuple,
+            Output: SupportedReturnType + 'static,
+            $($P: 'static,)*
+        {
+            fn catch_panics(
+                host_func: HostFunction<Output, Self>,
+                on_panic: OnPanic,
+            ) -> HostFunction<Output, Self> {
+                HostFunction::from(move |$($p: $P),*| -> Result<Output> {
+                    panic::catch_unwind(AssertUnwindSafe(|| host_func.call(($($p,)*))))
+                        .unwrap_or_else(|payload| Err(on_panic(payload)))
+                })
+            }
+
+            fn wrap_calls(
+                host_func: HostFunction<Output, Self>,
+                on_call: OnHostCall,
+            ) -> HostFunction<Output, Self> {
+                HostFunction::from(move |$($p: $P),*| -> Result<Output> {
+                    let mut args = Some(($($p,)*));
+                    let mut result = None;
+                    on_call(&mut || result = args.take().map(|args| host_func.call(args)))?;
+                    result.unwrap_or_else(|| Err(new_error!("host function was not called")))
+                })
+            }
+
+            fn keep_errors(
+                host_func: HostFunction<Output, Self>,
+                on_error: OnHostError,
+            ) -> HostFunction<Output, Self> {
+                HostFunction::from(move |$($p: $P),*| -> Result<Output> {
+                    host_func.call(($($p,)*)).map_err(|e| on_error(e))
+                })
+            }
+        }
+    };
+}
+
+for_each_tuple!(impl_catch_panics);
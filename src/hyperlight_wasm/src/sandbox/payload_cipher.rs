@@ -0,0 +1,222 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::marker::PhantomData;
+
+use hyperlight_host::func::{
+    ParameterTuple, ParameterType, ParameterValue, ReturnType, ReturnValue, SupportedReturnType,
+};
+use hyperlight_host::{MultiUseSandbox, Result, new_error};
+use hyperlight_wasm_runtime::payload_cipher::{self, KEY_SIZE, NONCE_SIZE};
+
+/// The key `VecBytes` parameters and results of guest calls are
+/// encrypted with, see
+/// [`SandboxBuilder::with_payload_key`](crate::SandboxBuilder::with_payload_key)
+#[derive(Clone)]
+pub(crate) struct PayloadKey([u8; KEY_SIZE]);
+
+impl PayloadKey {
+    pub(crate) fn new(key: [u8; KEY_SIZE]) -> Self {
+        Self(key)
+    }
+
+    /// The key, to be passed to the guest
+    pub(crate) fn to_vec(&self) -> Vec<u8> {
+        self.0.to_vec()
+    }
+
+    /// Encrypt the `VecBytes` values in `params`, each under a random
+    /// nonce
+    pub(crate) fn encrypt_params<P: ParameterTuple>(
+        &self,
+        params: P,
+    ) -> Result<EncryptedParams<P>> {
+        let values = params
+            .into_value()
+            .into_iter()
+            .map(|value| match value {
+                ParameterValue::VecBytes(bytes) => {
+                    let sealed = payload_cipher::seal(&self.0, random_nonce()?, &bytes)
+                        .ok_or_else(|| new_error!("Parameter is too long to encrypt"))?;
+                    Ok(ParameterValue::VecBytes(sealed))
+                }
+                value => Ok(value),
+            })
+            .collect::<Result<_>>()?;
+        Ok(EncryptedParams {
+            values,
+            params: PhantomData,
+        })
+    }
+
+    /// Call the guest function `fn_name` in `inner`, encrypting the
+    /// `VecBytes` values in `params` and decrypting the result if it is a
+    /// `VecBytes` value. The result is decrypted before it is converted
+    /// to `Output`, since types such as [`MultiValue`](crate::MultiValue)
+    /// cannot be converted from encrypted bytes.
+    pub(crate) fn call<Output: SupportedReturnType>(
+        &self,
+        inner: &mut MultiUseSandbox,
+        fn_name: &str,
+        params: impl ParameterTuple,
+    ) -> Result<Output> {
+        let params = self.encrypt_params(params)?;
+        if Output::TYPE != ReturnType::VecBytes {
+            return inner.call(fn_name, params);
+        }
+        let bytes = self.decrypt_result(inner.call::<Vec<u8>>(fn_name, params)?)?;
+        Output::from_value(ReturnValue::VecBytes(bytes))
+            .map_err(|e| new_error!("Failed to convert decrypted result: {}", e))
+    }
+
+    /// Decrypt `output` if it is a `VecBytes` value
+    pub(crate) fn decrypt_result<Output: SupportedReturnType>(
+        &self,
+        output: Output,
+    ) -> Result<Output> {
+        if Output::TYPE != ReturnType::VecBytes {
+            return Ok(output);
+        }
+        let ReturnValue::VecBytes(sealed) = output.into_value() else {
+            return Err(new_error!("VecBytes result has a value of another type"));
+        };
+        let bytes = payload_cipher::open(&self.0, &sealed)
+            .ok_or_else(|| new_error!("Encrypted result failed authentication"))?;
+        Output::from_value(ReturnValue::VecBytes(bytes))
+            .map_err(|e| new_error!("Failed to convert decrypted result: {}", e))
+    }
+}
+
+/// A random nonce for a buffer encrypted with the payload key
+fn random_nonce() -> Result<[u8; NONCE_SIZE]> {
+    let mut nonce = [0u8; NONCE_SIZE];
+    getrandom::fill(&mut nonce)
+        .map_err(|e| new_error!("Failed to generate a payload nonce: {}", e))?;
+    Ok(nonce)
+}
+
+/// The host function the runtime calls for the nonce of each result it
+/// encrypts. Nonces are drawn by the host since the guest's memory, and
+/// any counter in it, is rolled back when a snapshot is restored.
+pub(crate) fn result_nonce() -> Result<Vec<u8>> {
+    random_nonce().map(|nonce| nonce.to_vec())
+}
+
+/// The parameters of a guest call, with their `VecBytes` values
+/// encrypted
+#[derive(Clone)]
+pub(crate) struct EncryptedParams<P> {
+    values: Vec<ParameterValue>,
+    params: PhantomData<fn() -> P>,
+}
+
+impl<P: ParameterTuple> ParameterTuple for EncryptedParams<P> {
+    const SIZE: usize = P::SIZE;
+
+    const TYPE: &[ParameterType] = P::TYPE;
+
+    fn into_value(self) -> Vec<ParameterValue> {
+        self.values
+    }
+
+    fn from_value(
+        values: Vec<ParameterValue>,
+    ) -> std::result::Result<Self, hyperlight_common::func::Error> {
+        Ok(Self {
+            values,
+            params: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyperlight_host::func::{ParameterTuple, ParameterValue};
+    use hyperlight_wasm_runtime::payload_cipher::{self, NONCE_SIZE, TAG_SIZE};
+
+    use super::{PayloadKey, result_nonce};
+
+    #[test]
+    fn test_payload_cipher() {
+        let key = [3; 32];
+        let nonce: [u8; NONCE_SIZE] = std::array::from_fn(|i| i as u8);
+        let plaintext = b"Ladies and Gentlemen of the class of '99";
+        let sealed = payload_cipher::seal(&key, nonce, plaintext).unwrap();
+        assert_eq!(sealed.len(), NONCE_SIZE + plaintext.len() + TAG_SIZE);
+        assert_eq!(sealed[..NONCE_SIZE], nonce);
+        assert_ne!(
+            sealed[NONCE_SIZE..NONCE_SIZE + plaintext.len()],
+            plaintext[..]
+        );
+        assert_eq!(
+            payload_cipher::open(&key, &sealed).as_deref(),
+            Some(&plaintext[..])
+        );
+
+        // Changing any byte of the nonce, ciphertext or tag fails
+        // authentication
+        for i in [0, NONCE_SIZE, sealed.len() - 1] {
+            let mut tampered = sealed.clone();
+            tampered[i] ^= 1;
+            assert_eq!(payload_cipher::open(&key, &tampered), None);
+        }
+        assert_eq!(payload_cipher::open(&[4; 32], &sealed), None);
+        assert_eq!(
+            payload_cipher::open(&key, &sealed[..sealed.len() - 1]),
+            None
+        );
+        assert_eq!(
+            payload_cipher::open(&key, &[0; NONCE_SIZE + TAG_SIZE - 1]),
+            None
+        );
+
+        // Empty buffers are authenticated too
+        let sealed = payload_cipher::seal(&key, nonce, &[]).unwrap();
+        assert_eq!(payload_cipher::open(&key, &sealed), Some(vec![]));
+        assert_eq!(
+            payload_cipher::open(&key, &[0; NONCE_SIZE + TAG_SIZE]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_encrypt_params() {
+        let key = PayloadKey::new([7; 32]);
+        let values = key
+            .encrypt_params((vec![1u8, 2, 3], 3i32))
+            .unwrap()
+            .into_value();
+        let [ParameterValue::VecBytes(sealed), ParameterValue::Int(3)] = values.as_slice() else {
+            panic!("unexpected parameters {values:?}");
+        };
+        assert_ne!(sealed[NONCE_SIZE..NONCE_SIZE + 3], [1, 2, 3]);
+        assert_eq!(payload_cipher::open(&[7; 32], sealed), Some(vec![1, 2, 3]));
+
+        let sealed = payload_cipher::seal(&[7; 32], [1; NONCE_SIZE], &[4, 5, 6]).unwrap();
+        assert_eq!(key.decrypt_result(sealed.clone()).unwrap(), vec![4, 5, 6]);
+        let mut tampered = sealed;
+        tampered[NONCE_SIZE] ^= 1;
+        assert!(key.decrypt_result(tampered).is_err());
+        assert_eq!(key.decrypt_result(42i32).unwrap(), 42);
+    }
+
+    #[test]
+    fn test_result_nonce() {
+        let nonce = result_nonce().unwrap();
+        assert_eq!(nonce.len(), NONCE_SIZE);
+        assert_ne!(nonce, result_nonce().unwrap());
+    }
+}
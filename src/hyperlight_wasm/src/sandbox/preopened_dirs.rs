@@ -0,0 +1,221 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use hyperlight_host::{Result, new_error};
+use hyperlight_wasm_runtime::wasi_fs::{
+    self, ERRNO_ACCES, ERRNO_INVAL, ERRNO_IO, ERRNO_NOENT, ERRNO_NOTCAPABLE, FILETYPE_DIRECTORY,
+    FILETYPE_REGULAR_FILE, Stat,
+};
+
+/// How guests may access a directory preopened with
+/// [`SandboxBuilder::with_preopened_dir`](crate::SandboxBuilder::with_preopened_dir)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DirAccess {
+    /// Guests may open and read the files in the directory and its
+    /// subdirectories, but not create, modify or remove them
+    ReadOnly,
+}
+
+#[derive(Clone, Debug)]
+struct PreopenedDir {
+    host_path: PathBuf,
+    guest_path: String,
+}
+
+/// The host directories preopened for the modules loaded in a sandbox.
+/// The paths the guest passes are resolved against them and must stay
+/// inside them, including after following symbolic links.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct PreopenedDirs {
+    dirs: Vec<PreopenedDir>,
+}
+
+impl PreopenedDirs {
+    /// Preopen `host_path` as `guest_path` in the guest. Only
+    /// [`DirAccess::ReadOnly`] exists, so `access` is not recorded.
+    pub(crate) fn add(&mut self, host_path: &Path, guest_path: &str, _access: DirAccess) {
+        self.dirs.push(PreopenedDir {
+            host_path: host_path.to_path_buf(),
+            guest_path: guest_path.to_string(),
+        });
+    }
+
+    /// Check that the preopened directories exist, and resolve their paths
+    /// on the host and normalize their paths in the guest
+    pub(crate) fn open(&self) -> Result<Self> {
+        let dirs = self
+            .dirs
+            .iter()
+            .map(|dir| {
+                let host_path = dir.host_path.canonicalize().map_err(|e| {
+                    new_error!(
+                        "failed to preopen directory {}: {}",
+                        dir.host_path.display(),
+                        e
+                    )
+                })?;
+                if !host_path.is_dir() {
+                    return Err(new_error!(
+                        "failed to preopen {}: not a directory",
+                        dir.host_path.display()
+                    ));
+                }
+                let guest_path = wasi_fs::normalize_path(&dir.guest_path)
+                    .filter(|path| !path.contains('\n'))
+                    .ok_or_else(|| {
+                        new_error!(
+                            "invalid guest path {:?} for a preopened directory",
+                            dir.guest_path
+                        )
+                    })?;
+                Ok(PreopenedDir {
+                    host_path,
+                    guest_path,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { dirs })
+    }
+
+    /// The paths of the directories in the guest, separated by newlines
+    pub(crate) fn guest_paths(&self) -> String {
+        self.dirs
+            .iter()
+            .map(|dir| dir.guest_path.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// The file or directory at `path` in the guest
+    pub(crate) fn stat(&self, path: &str) -> std::result::Result<Stat, u16> {
+        let metadata = self.resolve(path)?.metadata().map_err(errno)?;
+        let filetype = if metadata.is_dir() {
+            FILETYPE_DIRECTORY
+        } else if metadata.is_file() {
+            FILETYPE_REGULAR_FILE
+        } else {
+            return Err(ERRNO_INVAL);
+        };
+        Ok(Stat {
+            filetype,
+            size: metadata.len(),
+        })
+    }
+
+    /// Read at most `len` bytes from `offset` in the file at `path` in the
+    /// guest
+    pub(crate) fn read(&self, path: &str, offset: u64, len: i32) -> Result<Vec<u8>> {
+        let host_path = self
+            .resolve(path)
+            .map_err(|errno| new_error!("failed to resolve {}: errno {}", path, errno))?;
+        let mut file = File::open(&host_path)
+            .map_err(|e| new_error!("failed to open {}: {}", host_path.display(), e))?;
+        let mut bytes = Vec::new();
+        file.seek(SeekFrom::Start(offset))
+            .and_then(|_| {
+                file.take(u64::try_from(len).unwrap_or(0))
+                    .read_to_end(&mut bytes)
+            })
+            .map_err(|e| new_error!("failed to read {}: {}", host_path.display(), e))?;
+        Ok(bytes)
+    }
+
+    // The path on the host of `path` in the guest, which must be in one of
+    // the preopened directories
+    fn resolve(&self, path: &str) -> std::result::Result<PathBuf, u16> {
+        // The guest normalizes paths, but is not trusted to
+        let path = wasi_fs::normalize_path(path).ok_or(ERRNO_NOTCAPABLE)?;
+        let (dir, relative) = self
+            .dirs
+            .iter()
+            .filter_map(|dir| Some((dir, wasi_fs::relative_path(&path, &dir.guest_path)?)))
+            .max_by_key(|(dir, _)| dir.guest_path.len())
+            .ok_or(ERRNO_NOTCAPABLE)?;
+        let host_path = dir.host_path.join(relative).canonicalize().map_err(errno)?;
+        if !host_path.starts_with(&dir.host_path) {
+            return Err(ERRNO_NOTCAPABLE);
+        }
+        Ok(host_path)
+    }
+}
+
+fn errno(error: io::Error) -> u16 {
+    match error.kind() {
+        io::ErrorKind::NotFound => ERRNO_NOENT,
+        io::ErrorKind::PermissionDenied => ERRNO_ACCES,
+        _ => ERRNO_IO,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyperlight_wasm_runtime::wasi_fs::{
+        ERRNO_NOENT, ERRNO_NOTCAPABLE, FILETYPE_DIRECTORY, FILETYPE_REGULAR_FILE, Stat,
+    };
+
+    use super::{DirAccess, PreopenedDirs};
+
+    #[test]
+    fn test_preopened_dirs() {
+        let root = std::env::temp_dir().join(format!("hlwasm-preopen-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("data/nested")).unwrap();
+        std::fs::write(root.join("data/nested/file.txt"), b"hello world").unwrap();
+        std::fs::write(root.join("secret.txt"), b"secret").unwrap();
+
+        let mut dirs = PreopenedDirs::default();
+        dirs.add(&root.join("data"), "/data/", DirAccess::ReadOnly);
+        let dirs = dirs.open().unwrap();
+        assert_eq!(dirs.guest_paths(), "/data");
+
+        assert_eq!(
+            dirs.stat("/data/nested/file.txt"),
+            Ok(Stat {
+                filetype: FILETYPE_REGULAR_FILE,
+                size: 11
+            })
+        );
+        assert_eq!(
+            dirs.stat("/data/nested").map(|stat| stat.filetype),
+            Ok(FILETYPE_DIRECTORY)
+        );
+        assert_eq!(dirs.stat("/data/missing.txt"), Err(ERRNO_NOENT));
+        assert_eq!(dirs.stat("/data/../secret.txt"), Err(ERRNO_NOTCAPABLE));
+        assert_eq!(dirs.stat("/secret.txt"), Err(ERRNO_NOTCAPABLE));
+        assert_eq!(
+            dirs.read("/data/nested/file.txt", 6, 100).unwrap(),
+            b"world"
+        );
+        assert!(dirs.read("/data/../secret.txt", 0, 100).is_err());
+
+        // Symbolic links may not leave the directory either
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(root.join("secret.txt"), root.join("data/link.txt"))
+                .unwrap();
+            assert_eq!(dirs.stat("/data/link.txt"), Err(ERRNO_NOTCAPABLE));
+        }
+
+        let mut missing = PreopenedDirs::default();
+        missing.add(&root.join("missing"), "/missing", DirAccess::ReadOnly);
+        assert!(missing.open().is_err());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
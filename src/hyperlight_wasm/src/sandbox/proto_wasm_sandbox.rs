@@ -15,17 +15,78 @@ limitations under the License.
 */
 
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use hyperlight_common::flatbuffer_wrappers::host_function_definition::HostFunctionDefinition;
 use hyperlight_common::flatbuffer_wrappers::host_function_details::HostFunctionDetails;
-use hyperlight_host::func::{HostFunction, ParameterTuple, Registerable, SupportedReturnType};
+use hyperlight_host::func::{
+    HostFunction, ParameterTuple, ParameterType, ParameterValue, Registerable, ReturnType,
+    ReturnValue, SupportedReturnType,
+};
 use hyperlight_host::sandbox::config::SandboxConfiguration;
 use hyperlight_host::{GuestBinary, Result, UninitializedSandbox, new_error};
+use hyperlight_wasm_runtime::component_value::{self, ComponentValue};
+use hyperlight_wasm_runtime::host_dispatch::{self, HOST_DISPATCH_FUNCTION};
+use hyperlight_wasm_runtime::host_function_cache::{self, CachedHostFunction};
+use hyperlight_wasm_runtime::host_reentry::HOST_REENTER_FUNCTION;
+use hyperlight_wasm_runtime::payload_cipher::HOST_PAYLOAD_NONCE_FUNCTION;
+use hyperlight_wasm_runtime::runtime_config::{
+    EntropyPolicy, HOST_ARGS_FUNCTION, HOST_CLOCK_FUNCTION, HOST_ENVIRONMENT_FUNCTION,
+    HOST_RANDOM_FUNCTION, HOST_STATE_CELL_GET_BYTES_FUNCTION, HOST_STATE_CELL_GET_U64_FUNCTION,
+    HOST_STATE_CELL_SET_BYTES_FUNCTION, HOST_STATE_CELL_SET_U64_FUNCTION, HOST_WRITE_FUNCTION,
+    RuntimeConfig,
+};
+use hyperlight_wasm_runtime::wasi_fs::{
+    self, HOST_FS_PREOPENS_FUNCTION, HOST_FS_READ_FUNCTION, HOST_FS_STAT_FUNCTION,
+    HOST_VFS_FILES_FUNCTION, HOST_VFS_READ_FUNCTION,
+};
 
+use super::call_budget::HostClock;
+use super::call_hooks::CallHooks;
+use super::callbacks::RegisteredHostFunctions;
+use super::cli_environment::CliEnvironment;
+use super::guest_callbacks::{GuestCallbacks, ReentrantCalls, ReentrantFunction};
+use super::host_error;
+use super::host_function_cache::{HostFunctionCache, HostFunctionCacheExpiry};
+use super::host_function_failed::HostErrors;
+use super::host_function_manifest::{HostFunctionManifest, ManifestFunction};
+use super::memory_stats::MemoryTracker;
 use super::metrics::{METRIC_ACTIVE_PROTO_WASM_SANDBOXES, METRIC_TOTAL_PROTO_WASM_SANDBOXES};
+use super::module_cache::ModuleCache;
+use super::module_resolver::ModuleResolver;
+use super::output_capture::OutputCapture;
+use super::panic_policy::{CatchPanics, PanicHandler};
+use super::payload_cipher::{self, PayloadKey};
+use super::preopened_dirs::PreopenedDirs;
+use super::provenance::ProvenanceRecorder;
+use super::required_exports::RequiredExport;
 use super::sandbox_builder::SandboxBuilder;
+use super::sandbox_context::{self, SandboxContext};
+use super::state_cells::{StateCellValue, StateCells};
+use super::streaming::ChunkSink;
+use super::vfs::Vfs;
+use super::virtual_clock::VirtualClock;
 use super::wasm_sandbox::WasmSandbox;
+#[cfg(feature = "aot")]
+use crate::aot::HostPrecompiler;
 use crate::build_info::BuildInfo;
+use crate::validate::Policy;
+
+/// The host function conventionally imported by modules to read the time
+const TIME_SINCE_BOOT_FUNCTION: &str = "GetTimeSinceBootMicrosecond";
+
+/// The host function modules call to report progress, see
+/// [`ProtoWasmSandbox::on_progress`]
+const PROGRESS_FUNCTION: &str = "ReportProgress";
+
+/// The host function modules call to emit a chunk of output, see
+/// [`LoadedWasmSandbox::call_guest_function_streaming`](crate::LoadedWasmSandbox::call_guest_function_streaming)
+const CHUNK_FUNCTION: &str = "EmitChunk";
+
+type ProgressCallback = Box<dyn FnMut(u32, &[u8]) + Send>;
+
+type HostDispatcher = Arc<dyn Fn(&str, Vec<ParameterValue>) -> Result<ReturnValue> + Send + Sync>;
 
 /// A Hyperlight Sandbox with no Wasm run time loaded and no guest module code loaded.
 /// This is used to register new host functions that can be called by guest code.
@@ -36,6 +97,67 @@ use crate::build_info::BuildInfo;
 pub struct ProtoWasmSandbox {
     pub(super) inner: Option<UninitializedSandbox>,
     host_function_definitions: HashMap<String, HostFunctionDefinition>,
+    // Settings for the wasmtime engine, passed to the guest during InitWasmRuntime
+    runtime_config: RuntimeConfig,
+    // The clock read by the built-in time shims
+    clock: VirtualClock,
+    // Host functions whose results are cached in the guest
+    cached_host_functions: Vec<(String, HostFunctionCache)>,
+    // Host functions registered from manifests, called through the
+    // dispatch host function
+    dispatched_host_functions: HashMap<String, (ManifestFunction, HostDispatcher)>,
+    // Host functions that call back into the module, called through the
+    // re-entry host function
+    reentrant_host_functions: HashMap<String, ReentrantFunction>,
+    // The re-entrant host functions running
+    pub(super) reentrant_calls: ReentrantCalls,
+    // Values shared with the guest
+    state_cells: StateCells,
+    // Called when the guest reports progress
+    progress_callback: Arc<Mutex<Option<ProgressCallback>>>,
+    // Collects the chunks emitted during streaming calls
+    chunks: ChunkSink,
+    // Finds modules loaded by name
+    pub(super) module_resolver: Option<Arc<dyn ModuleResolver>>,
+    // Whether the usage of loaded modules is recorded
+    pub(super) usage_accounting: bool,
+    // Handles panics in the host functions registered with `register`
+    pub(super) panic_handler: PanicHandler,
+    // Records the provenance of loaded modules
+    pub(super) provenance: Option<ProvenanceRecorder>,
+    // Whether memory is reset as soon as a module is unloaded
+    pub(super) zero_memory_on_unload: bool,
+    // How many loaded modules' snapshots each sandbox keeps
+    pub(super) module_cache_capacity: usize,
+    // The functions loaded guests must export
+    pub(super) required_exports: Vec<RequiredExport>,
+    // The policy loaded modules are checked against
+    pub(super) validation_policy: Option<Policy>,
+    // How long guest calls may run for
+    pub(super) call_timeout: Option<Duration>,
+    // How long the guest may run for in each call, not counting time in
+    // host functions
+    pub(super) guest_time_budget: Option<Duration>,
+    // Measures, and limits, the time spent in host functions during each
+    // call
+    pub(super) host_clock: HostClock,
+    // The hooks run around guest calls and host function calls
+    call_hooks: CallHooks,
+    // The host functions registered with `register_host_function`
+    host_functions: RegisteredHostFunctions,
+    // Keeps the errors host functions return to the guest
+    host_errors: HostErrors,
+    // The key VecBytes parameters and results are encrypted with
+    pub(super) payload_key: Option<PayloadKey>,
+    // Tracks the memory used by the sandbox's guests
+    pub(super) memory: MemoryTracker,
+    // Whether the wasm context of guest crashes is written next to their
+    // core dumps
+    #[cfg(feature = "crashdump")]
+    pub(super) crashdump: bool,
+    // Precompiles guests that are not yet precompiled as they are loaded
+    #[cfg(feature = "aot")]
+    pub(super) precompiler: Option<HostPrecompiler>,
 }
 
 impl Registerable for ProtoWasmSandbox {
@@ -43,11 +165,27 @@ impl Registerable for ProtoWasmSandbox {
         &mut self,
         name: &str,
         hf: impl Into<HostFunction<Output, Args>>,
+    ) -> Result<()> {
+        // The bindings generated by `host_bindgen!` register through here,
+        // and their functions can only fail by raising a `HostError`
+        let hf = host_error::catch_host_errors(hf.into(), self.host_errors.on_error(name));
+        self.insert_host_function(name, hf)
+    }
+}
+
+impl ProtoWasmSandbox {
+    fn insert_host_function<Args: ParameterTuple, Output: SupportedReturnType>(
+        &mut self,
+        name: &str,
+        hf: HostFunction<Output, Args>,
     ) -> Result<()> {
         self.inner
             .as_mut()
             .ok_or(new_error!("inner sandbox was none"))
-            .and_then(|sb| sb.register(name, hf))?;
+            .and_then(|sb| sb.register(name, hf.clone()))?;
+        self.dispatched_host_functions.remove(name);
+        self.reentrant_host_functions.remove(name);
+        self.host_functions.insert(name, hf);
 
         // Track the host function definition for pushing to guest at load time.
         // matching hyperlight-core's FunctionRegistry behavior.
@@ -74,19 +212,125 @@ impl ProtoWasmSandbox {
     /// `LoadedWasmSandbox` returned from the `load_module` method on the `WasmSandbox`, you can
     /// use the `max_execution_time` and `max_wait_for_cancellation`
     /// fields in the `SandboxConfiguration` struct.
+    #[allow(clippy::too_many_arguments)]
     pub(super) fn new(
         cfg: Option<SandboxConfiguration>,
         guest_binary: GuestBinary,
+        runtime_config: RuntimeConfig,
+        clock: VirtualClock,
+        output_capture: OutputCapture,
+        preopened_dirs: PreopenedDirs,
+        vfs: Vfs,
+        cli_environment: CliEnvironment,
     ) -> Result<Self> {
         BuildInfo::log();
-        let inner = UninitializedSandbox::new(guest_binary, cfg)?;
+        let mut inner = UninitializedSandbox::new(guest_binary, cfg)?;
+        // Only the runtime calls these, so they are not added to the host
+        // function definitions passed to the guest
+        inner.register(HOST_CLOCK_FUNCTION, move |clock_id: i32| {
+            Ok(clock.wasi_clock_nanos(clock_id).unwrap_or(-1))
+        })?;
+        if runtime_config.entropy_policy == EntropyPolicy::HostEntropy {
+            inner.register(HOST_RANDOM_FUNCTION, |len: i32| {
+                let mut bytes = vec![0u8; usize::try_from(len).unwrap_or(0)];
+                getrandom::fill(&mut bytes)
+                    .map_err(|e| new_error!("failed to read host entropy: {}", e))?;
+                Ok(bytes)
+            })?;
+        }
+        // The runtime asks for the nonce of each result it encrypts with
+        // the payload key, see SandboxBuilder::with_payload_key
+        inner.register(HOST_PAYLOAD_NONCE_FUNCTION, payload_cipher::result_nonce)?;
+        if runtime_config.capture_stdout || runtime_config.capture_stderr {
+            inner.register(HOST_WRITE_FUNCTION, move |fd: i32, bytes: Vec<u8>| {
+                output_capture.write(fd, &bytes)
+            })?;
+        }
+        if runtime_config.preopened_dirs {
+            let dirs = Arc::new(preopened_dirs.open()?);
+            let guest_paths = dirs.guest_paths();
+            inner.register(HOST_FS_PREOPENS_FUNCTION, move || Ok(guest_paths.clone()))?;
+            let fs = dirs.clone();
+            inner.register(HOST_FS_STAT_FUNCTION, move |path: String| {
+                Ok(wasi_fs::encode_stat(fs.stat(&path)))
+            })?;
+            inner.register(
+                HOST_FS_READ_FUNCTION,
+                move |path: String, offset: u64, len: i32| dirs.read(&path, offset, len),
+            )?;
+        }
+        if runtime_config.vfs {
+            let vfs = vfs.open()?;
+            let listing = vfs.listing();
+            inner.register(HOST_VFS_FILES_FUNCTION, move || Ok(listing.clone()))?;
+            inner.register(
+                HOST_VFS_READ_FUNCTION,
+                move |index: i32, offset: u64, len: i32| vfs.read(index, offset, len),
+            )?;
+        }
+        if runtime_config.cli_environment {
+            let args = cli_environment.encode_args()?;
+            inner.register(HOST_ARGS_FUNCTION, move || Ok(args.clone()))?;
+            let vars = cli_environment.encode_vars()?;
+            inner.register(HOST_ENVIRONMENT_FUNCTION, move || Ok(vars.clone()))?;
+        }
+        let state_cells = StateCells::default();
+        let cells = state_cells.clone();
+        inner.register(HOST_STATE_CELL_GET_U64_FUNCTION, move |name: String| {
+            cells.get_u64(&name)
+        })?;
+        let cells = state_cells.clone();
+        inner.register(
+            HOST_STATE_CELL_SET_U64_FUNCTION,
+            move |name: String, value: u64| cells.set(&name, value),
+        )?;
+        let cells = state_cells.clone();
+        inner.register(HOST_STATE_CELL_GET_BYTES_FUNCTION, move |name: String| {
+            cells.get_bytes(&name)
+        })?;
+        let cells = state_cells.clone();
+        inner.register(
+            HOST_STATE_CELL_SET_BYTES_FUNCTION,
+            move |name: String, value: Vec<u8>| cells.set(&name, value),
+        )?;
         metrics::gauge!(METRIC_ACTIVE_PROTO_WASM_SANDBOXES).increment(1);
         metrics::counter!(METRIC_TOTAL_PROTO_WASM_SANDBOXES).increment(1);
 
         let host_function_definitions = HashMap::new();
+        #[cfg(feature = "aot")]
+        let precompiler = Some(HostPrecompiler::new(&runtime_config));
         Ok(Self {
             inner: Some(inner),
             host_function_definitions,
+            runtime_config,
+            clock,
+            cached_host_functions: Vec::new(),
+            dispatched_host_functions: HashMap::new(),
+            reentrant_host_functions: HashMap::new(),
+            reentrant_calls: ReentrantCalls::default(),
+            state_cells,
+            progress_callback: Arc::new(Mutex::new(None)),
+            chunks: ChunkSink::default(),
+            module_resolver: None,
+            usage_accounting: false,
+            panic_handler: PanicHandler::default(),
+            provenance: None,
+            zero_memory_on_unload: false,
+            module_cache_capacity: 0,
+            required_exports: Vec::new(),
+            validation_policy: None,
+            call_timeout: None,
+            guest_time_budget: None,
+            host_clock: HostClock::default(),
+            call_hooks: CallHooks::default(),
+            host_functions: RegisteredHostFunctions::default(),
+            host_errors: HostErrors::default(),
+            payload_key: None,
+            memory: MemoryTracker::default(),
+            #[cfg(feature = "crashdump")]
+            crashdump: true,
+            #[cfg(feature = "aot")]
+            precompiler,
         })
     }
 
@@ -97,6 +341,100 @@ impl ProtoWasmSandbox {
     /// The returned `WasmSandbox` can be then be cached and used to load a different Wasm module.
     ///
     pub fn load_runtime(mut self) -> Result<WasmSandbox> {
+        // Provide the conventional time function, in virtual time, unless
+        // the host registered its own
+        if !self
+            .host_function_definitions
+            .contains_key(TIME_SINCE_BOOT_FUNCTION)
+        {
+            let clock = self.clock;
+            self.register(TIME_SINCE_BOOT_FUNCTION, move || Ok(clock.now_micros()))?;
+        }
+        // Likewise provide the progress function, unless the host
+        // registered its own
+        if !self
+            .host_function_definitions
+            .contains_key(PROGRESS_FUNCTION)
+        {
+            let progress_callback = self.progress_callback.clone();
+            self.register(
+                PROGRESS_FUNCTION,
+                move |progress: u32, partial: Vec<u8>, _len: i32| {
+                    let mut callback = progress_callback
+                        .lock()
+                        .map_err(|e| new_error!("Error locking progress callback: {:?}", e))?;
+                    if let Some(callback) = callback.as_mut() {
+                        callback(progress, &partial);
+                    }
+                    Ok(())
+                },
+            )?;
+        }
+        // And the function modules emit chunks of streamed output with
+        if !self.host_function_definitions.contains_key(CHUNK_FUNCTION) {
+            let chunks = self.chunks.clone();
+            self.register(CHUNK_FUNCTION, move |chunk: Vec<u8>, _len: i32| {
+                chunks.push(chunk)
+            })?;
+        }
+
+        if self.provenance.is_some() {
+            let host_functions = self.host_function_manifest();
+            if let Some(provenance) = &mut self.provenance {
+                provenance.set_host_functions(host_functions);
+            }
+        }
+
+        // Modules call host functions registered from manifests through
+        // a single host function, which checks the calls against the
+        // manifests
+        let dispatched_host_functions = std::mem::take(&mut self.dispatched_host_functions);
+        let dispatched_names: Vec<String> = dispatched_host_functions.keys().cloned().collect();
+        if !dispatched_host_functions.is_empty() {
+            let panic_handler = self.panic_handler.clone();
+            let host_clock = self.host_clock.clone();
+            let call_hooks = self.call_hooks.clone();
+            let host_errors = self.host_errors.clone();
+            self.inner
+                .as_mut()
+                .ok_or(new_error!("inner sandbox was none"))?
+                .register(
+                    HOST_DISPATCH_FUNCTION,
+                    move |name: String, params: Vec<u8>| {
+                        let (function, dispatcher) =
+                            dispatched_host_functions.get(&name).ok_or_else(|| {
+                                new_error!("host function {} is not dispatched", name)
+                            })?;
+                        let params =
+                            host_dispatch::params_from_bytes(&params).ok_or_else(|| {
+                                new_error!("malformed parameters for host function {}", name)
+                            })?;
+                        function.check_params(&params)?;
+                        call_hooks.host_call(&name)?;
+                        let result = host_clock
+                            .time(|| panic_handler.call(&name, || dispatcher(&name, params)))
+                            .map_err(|e| host_errors.keep(&name, e))?;
+                        function.check_return_value(&result)?;
+                        Ok(host_dispatch::return_value_to_bytes(&result))
+                    },
+                )?;
+        }
+
+        // Modules call re-entrant host functions through a single host
+        // function, which runs them and passes their calls back into the
+        // module
+        let reentrant_host_functions = std::mem::take(&mut self.reentrant_host_functions);
+        let reentrant_names: Vec<String> = reentrant_host_functions.keys().cloned().collect();
+        if !reentrant_host_functions.is_empty() {
+            let reentrant_calls = self.reentrant_calls.clone();
+            self.inner
+                .as_mut()
+                .ok_or(new_error!("inner sandbox was none"))?
+                .register(HOST_REENTER_FUNCTION, move |message: Vec<u8>| {
+                    reentrant_calls.handle(&reentrant_host_functions, &message)
+                })?;
+        }
+
         // Serialize host function definitions to push to the guest during InitWasmRuntime
         let host_function_definitions = HostFunctionDetails {
             host_functions: Some(
@@ -115,8 +453,14 @@ impl ProtoWasmSandbox {
             None => return Err(new_error!("No inner sandbox found.")),
         };
 
-        // Pass host function definitions to the guest as a parameter
-        let res: i32 = sandbox.call("InitWasmRuntime", (host_function_definitions_bytes,))?;
+        // Pass host function definitions and engine settings to the guest as parameters
+        let res: i32 = sandbox.call(
+            "InitWasmRuntime",
+            (
+                host_function_definitions_bytes,
+                self.runtime_config.to_bytes(),
+            ),
+        )?;
         if res != 0 {
             return Err(new_error!(
                 "InitWasmRuntime Failed  with error code {:?}",
@@ -124,18 +468,380 @@ impl ProtoWasmSandbox {
             ));
         }
 
-        WasmSandbox::new(sandbox)
+        if !dispatched_names.is_empty() {
+            let res: i32 = sandbox.call("ConfigureHostDispatch", dispatched_names.join("\0"))?;
+            if res != 0 {
+                return Err(new_error!(
+                    "ConfigureHostDispatch Failed with error code {:?}",
+                    res
+                ));
+            }
+        }
+
+        if !reentrant_names.is_empty() {
+            let res: i32 = sandbox.call(
+                "ConfigureReentrantHostFunctions",
+                reentrant_names.join("\0"),
+            )?;
+            if res != 0 {
+                return Err(new_error!(
+                    "ConfigureReentrantHostFunctions Failed with error code {:?}",
+                    res
+                ));
+            }
+        }
+
+        if !self.cached_host_functions.is_empty() {
+            let functions: Vec<CachedHostFunction> = self
+                .cached_host_functions
+                .iter()
+                .map(|(name, cache)| CachedHostFunction {
+                    name: name.clone(),
+                    max_entries: cache.max_entries as u64,
+                })
+                .collect();
+            let res: i32 = sandbox.call(
+                "ConfigureHostFunctionCache",
+                host_function_cache::to_bytes(&functions),
+            )?;
+            if res != 0 {
+                return Err(new_error!(
+                    "ConfigureHostFunctionCache Failed with error code {:?}",
+                    res
+                ));
+            }
+        }
+        let host_function_cache = HostFunctionCacheExpiry::new(
+            self.cached_host_functions
+                .drain(..)
+                .map(|(name, cache)| (name, cache.ttl)),
+        );
+
+        // Only drain guest logs after each call if the guest keeps any
+        let drain_guest_logs = self.runtime_config.guest_log_level.unwrap_or(0) > 0;
+        WasmSandbox::new(
+            sandbox,
+            SandboxContext {
+                sandbox_id: sandbox_context::next_sandbox_id(),
+                drain_guest_logs,
+                host_function_cache,
+                state_cells: self.state_cells.clone(),
+                module_resolver: self.module_resolver.take(),
+                usage_accounting: self.usage_accounting,
+                panic_handler: self.panic_handler.clone(),
+                provenance: self.provenance.take(),
+                zero_memory_on_unload: self.zero_memory_on_unload,
+                chunks: self.chunks.clone(),
+                host_functions: std::mem::take(&mut self.host_functions),
+                added_host_functions: Vec::new(),
+                required_exports: std::mem::take(&mut self.required_exports),
+                call_timeout: self.call_timeout,
+                guest_time_budget: self.guest_time_budget,
+                host_clock: self.host_clock.clone(),
+                call_hooks: self.call_hooks.clone(),
+                host_errors: self.host_errors.clone(),
+                reentrant_calls: self.reentrant_calls.clone(),
+                payload_key: self.payload_key.clone(),
+                memory: std::mem::take(&mut self.memory),
+                max_wasm_memory: self.runtime_config.max_wasm_memory,
+                max_table_elements: self.runtime_config.max_table_elements,
+                max_wasm_stack: self.runtime_config.max_wasm_stack,
+                module_cache: ModuleCache::new(self.module_cache_capacity),
+                validation_policy: self.validation_policy.clone(),
+                #[cfg(target_os = "linux")]
+                module_handles: Vec::new(),
+                #[cfg(feature = "crashdump")]
+                crashdump: self.crashdump,
+                #[cfg(feature = "aot")]
+                precompiler: self.precompiler.clone(),
+            },
+        )
+    }
+
+    /// Describe the host functions that modules loaded into this sandbox
+    /// can import, including the `GetTimeSinceBootMicrosecond`,
+    /// `ReportProgress` and `EmitChunk` functions that
+    /// [`load_runtime`](Self::load_runtime) provides unless the host
+    /// registers its own, sorted by name.
+    ///
+    /// The manifest can be written out with
+    /// [`HostFunctionManifest::to_json`] and passed to
+    /// `hyperlight-wasm-aot preflight` to check, before deployment, that
+    /// a module only imports functions the host provides.
+    pub fn host_function_manifest(&self) -> HostFunctionManifest {
+        let defaults = [
+            ManifestFunction::new(TIME_SINCE_BOOT_FUNCTION, [], ReturnType::Long),
+            ManifestFunction::new(
+                PROGRESS_FUNCTION,
+                [
+                    ParameterType::UInt,
+                    ParameterType::VecBytes,
+                    ParameterType::Int,
+                ],
+                ReturnType::Void,
+            ),
+            ManifestFunction::new(
+                CHUNK_FUNCTION,
+                [ParameterType::VecBytes, ParameterType::Int],
+                ReturnType::Void,
+            ),
+        ];
+        let mut functions: Vec<ManifestFunction> = self
+            .host_function_definitions
+            .values()
+            .map(|definition| {
+                match self
+                    .dispatched_host_functions
+                    .get(&definition.function_name)
+                {
+                    Some((function, _)) => function.clone(),
+                    None => ManifestFunction::new(
+                        definition.function_name.clone(),
+                        definition.parameter_types.clone().unwrap_or_default(),
+                        definition.return_type,
+                    ),
+                }
+            })
+            .chain(
+                defaults
+                    .into_iter()
+                    .filter(|f| !self.host_function_definitions.contains_key(&f.name)),
+            )
+            .collect();
+        functions.sort_by(|a, b| a.name.cmp(&b.name));
+        functions.into_iter().fold(
+            HostFunctionManifest::new(),
+            HostFunctionManifest::with_function,
+        )
     }
 
     /// Register the given host function `host_func` with `self` under
     /// the given `name`. Return `Ok` if the registration succeeded, and a
     /// descriptive `Err` otherwise.
-    pub fn register<Args: ParameterTuple, Output: SupportedReturnType>(
+    ///
+    /// Panics in `host_func` are caught and handled according to the
+    /// sandbox's [`PanicPolicy`](crate::PanicPolicy). Host functions
+    /// registered through [`Registerable`] directly, as the bindings
+    /// generated by `host_bindgen!` do, are not wrapped, so a panic in one
+    /// unwinds through the guest call. Those can fail the call instead
+    /// with [`HostError::raise`](crate::HostError::raise).
+    pub fn register<Args: CatchPanics<Output>, Output: SupportedReturnType>(
         &mut self,
         name: impl AsRef<str>,
         host_func: impl Into<HostFunction<Output, Args>>,
     ) -> Result<()> {
-        self.register_host_function(name.as_ref(), host_func)
+        let name = name.as_ref();
+        let host_func = Args::wrap_calls(
+            Args::keep_errors(
+                Args::catch_panics(host_func.into(), self.panic_handler.on_panic(name)),
+                self.host_errors.on_error(name),
+            ),
+            self.call_hooks
+                .on_host_call(name, self.host_clock.on_call()),
+        );
+        self.insert_host_function(name, host_func)
+    }
+
+    /// Register the given host function `host_func` with `self` under
+    /// the given `name`, and cache its results in the guest as described
+    /// by `cache`, so that calls from modules with the same parameters as
+    /// a cached call return the cached result without leaving the VM.
+    ///
+    /// This is intended for idempotent host functions that guests call
+    /// often, such as configuration lookups. Results are only cached for
+    /// modules, not components.
+    pub fn register_cached<Args: CatchPanics<Output>, Output: SupportedReturnType>(
+        &mut self,
+        name: impl AsRef<str>,
+        host_func: impl Into<HostFunction<Output, Args>>,
+        cache: HostFunctionCache,
+    ) -> Result<()> {
+        let name = name.as_ref();
+        self.register(name, host_func)?;
+        self.cached_host_functions.retain(|(n, _)| n != name);
+        self.cached_host_functions.push((name.to_string(), cache));
+        Ok(())
+    }
+
+    /// Register each host function described by `manifest`, so that
+    /// calls to any of them from modules are passed to `dispatcher` with
+    /// the function's name and parameters. This lets hosts expose
+    /// functions that are only defined at run time, for example by a
+    /// scripting layer, without a call to [`register`](Self::register)
+    /// for each one.
+    ///
+    /// The parameters are checked against the manifest before
+    /// `dispatcher` is called, and an error is returned to the guest if
+    /// `dispatcher` returns a value of the wrong type. Registering a
+    /// function with the same name later replaces the manifest's
+    /// function. Manifest functions can only be called from modules, not
+    /// components.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a function in `manifest` has the same name as
+    /// a host function that is already registered, or as another
+    /// function in `manifest`.
+    pub fn register_manifest(
+        &mut self,
+        manifest: &HostFunctionManifest,
+        dispatcher: impl Fn(&str, Vec<ParameterValue>) -> Result<ReturnValue> + Send + Sync + 'static,
+    ) -> Result<()> {
+        let mut names = std::collections::HashSet::new();
+        for function in manifest.functions() {
+            if self.host_function_definitions.contains_key(&function.name)
+                || !names.insert(function.name.as_str())
+            {
+                return Err(new_error!(
+                    "host function {} is already registered",
+                    function.name
+                ));
+            }
+        }
+        let dispatcher: HostDispatcher = Arc::new(dispatcher);
+        for function in manifest.functions() {
+            self.host_function_definitions.insert(
+                function.name.clone(),
+                HostFunctionDefinition {
+                    function_name: function.name.clone(),
+                    parameter_types: Some(function.parameter_types.clone()),
+                    return_type: function.return_type,
+                },
+            );
+            self.dispatched_host_functions.insert(
+                function.name.clone(),
+                (function.clone(), dispatcher.clone()),
+            );
+        }
+        Ok(())
+    }
+
+    /// Register the given host function `host_func` with `self` under
+    /// the given `name`, so that it can call back into the module that
+    /// called it, for example to call a comparator the module exports.
+    ///
+    /// `host_func` is passed a [`GuestCallbacks`] along with its
+    /// parameters, whose [`call`](GuestCallbacks::call) calls one of the
+    /// module's exports. Those exports may call host functions in turn,
+    /// including re-entrant ones, up to the depth set with
+    /// [`SandboxBuilder::with_max_reentrancy_depth`](crate::SandboxBuilder::with_max_reentrancy_depth):
+    /// a call that would nest deeper fails, so that a module and a host
+    /// function calling each other forever fail the guest call rather
+    /// than exhaust the guest's stack.
+    ///
+    /// Each call runs `host_func` on a new thread while the guest waits
+    /// for it, so it must be `Sync`. Panics in `host_func` are handled as
+    /// for [`register`](Self::register), and its results are never
+    /// cached. Re-entrant host functions can only be called from
+    /// modules, not components.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a host function named `name` is already
+    /// registered.
+    pub fn register_reentrant<Args: ParameterTuple, Output: SupportedReturnType>(
+        &mut self,
+        name: impl AsRef<str>,
+        host_func: impl Fn(&mut GuestCallbacks, Args) -> Result<Output> + Send + Sync + 'static,
+    ) -> Result<()> {
+        let name = name.as_ref();
+        if self.host_function_definitions.contains_key(name) {
+            return Err(new_error!("host function {} is already registered", name));
+        }
+        let function_name = name.to_string();
+        let panic_handler = self.panic_handler.clone();
+        let call_hooks = self.call_hooks.clone();
+        let host_errors = self.host_errors.clone();
+        let function: ReentrantFunction = Arc::new(move |callbacks, params| {
+            call_hooks.host_call(&function_name)?;
+            let args = Args::from_value(params).map_err(|e| new_error!("{}", e))?;
+            panic_handler
+                .call(&function_name, || host_func(callbacks, args))
+                .map(Output::into_value)
+                .map_err(|e| host_errors.keep(&function_name, e))
+        });
+        self.host_function_definitions.insert(
+            name.to_string(),
+            HostFunctionDefinition {
+                function_name: name.to_string(),
+                parameter_types: Some(Args::TYPE.to_vec()),
+                return_type: Output::TYPE,
+            },
+        );
+        self.reentrant_host_functions
+            .insert(name.to_string(), function);
+        Ok(())
+    }
+
+    /// Register `host_func` as the function named `function`, in the
+    /// interface named `interface` or, if it is `None`, at the root of
+    /// the world, imported by components loaded with
+    /// [`WasmSandbox::load_component`](crate::WasmSandbox::load_component).
+    /// It is called with the parameters the component passes and returns
+    /// the function's results.
+    ///
+    /// The host function is registered as `interface#function`, or
+    /// `function` at the root.
+    pub fn register_component_function(
+        &mut self,
+        interface: Option<&str>,
+        function: &str,
+        host_func: impl Fn(Vec<ComponentValue>) -> Result<Vec<ComponentValue>> + Send + Sync + 'static,
+    ) -> Result<()> {
+        let name = match interface {
+            Some(interface) => format!("{interface}#{function}"),
+            None => function.to_string(),
+        };
+        self.register(&name, move |params: Vec<u8>| -> Result<Vec<u8>> {
+            let params = component_value::from_bytes(&params)
+                .ok_or_else(|| new_error!("component function parameters are malformed"))?;
+            Ok(component_value::to_bytes(&host_func(params)?))
+        })
+    }
+
+    /// Set the function called when a module reports progress during a
+    /// guest call, with the progress value and any partial result it
+    /// passed. This lets the host follow long-running calls, which can
+    /// still be interrupted as usual.
+    ///
+    /// Modules report progress by calling the `ReportProgress` host
+    /// function, which takes a `u32`, a buffer and the buffer's length
+    /// and is provided unless the host registers its own. Reports made
+    /// before a callback is set are discarded.
+    pub fn on_progress(&mut self, callback: impl FnMut(u32, &[u8]) + Send + 'static) -> Result<()> {
+        *self
+            .progress_callback
+            .lock()
+            .map_err(|e| new_error!("Error locking progress callback: {:?}", e))? =
+            Some(Box::new(callback));
+        Ok(())
+    }
+
+    /// Declare a state cell named `name` holding `initial`, which guests
+    /// and the host can read and write between calls, see
+    /// [`StateCells`]. The cell keeps the type of `initial`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a cell named `name` has already been declared.
+    pub fn declare_state_cell(
+        &mut self,
+        name: impl AsRef<str>,
+        initial: impl Into<StateCellValue>,
+    ) -> Result<()> {
+        self.state_cells.declare(name.as_ref(), initial.into())
+    }
+
+    /// A handle to this sandbox's state cells, which remains valid once
+    /// the runtime and a module are loaded.
+    pub fn state_cells(&self) -> StateCells {
+        self.state_cells.clone()
+    }
+
+    /// The clock seen by this sandbox's guests, see
+    /// [`SandboxBuilder::with_virtual_time`].
+    pub fn clock(&self) -> VirtualClock {
+        self.clock
     }
 
     /// Register the given host printing function `print_func` with `self`.
@@ -0,0 +1,158 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use super::host_function_manifest::{HostFunctionManifest, ManifestFunction, push_str};
+use crate::build_info::BuildInfo;
+
+/// The version of the encoding produced by [`Provenance::to_bytes`]
+const PROVENANCE_FORMAT_VERSION: u8 = 1;
+
+/// A signed record of what was loaded into a sandbox, so that audits can
+/// reconstruct exactly what ran, see
+/// [`SandboxBuilder::with_provenance_key`](crate::SandboxBuilder::with_provenance_key)
+/// and [`LoadedWasmSandbox::provenance`](crate::LoadedWasmSandbox::provenance).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Provenance {
+    /// The version of hyperlight-wasm
+    pub package_version: String,
+    /// The blake3 hash of the hyperlight-wasm-runtime binary
+    pub wasm_runtime_hash: String,
+    /// The version of wasmtime in the runtime
+    pub wasmtime_version: String,
+    /// The hex-encoded blake3 hash of the loaded module, or `None` if the
+    /// module was loaded from a snapshot taken by a sandbox that did not
+    /// hash its module
+    pub module_hash: Option<String>,
+    /// The host functions the module could import
+    pub host_functions: HostFunctionManifest,
+    /// The configuration of the sandbox and of the runtime
+    pub configuration: String,
+    /// The hex-encoded keyed blake3 hash of [`to_bytes`](Self::to_bytes),
+    /// keyed with the sandbox's provenance key
+    pub signature: String,
+}
+
+impl Provenance {
+    /// Encode the record, without its signature. These are the bytes
+    /// the signature covers.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![PROVENANCE_FORMAT_VERSION];
+        push_str(&mut bytes, &self.package_version);
+        push_str(&mut bytes, &self.wasm_runtime_hash);
+        push_str(&mut bytes, &self.wasmtime_version);
+        match &self.module_hash {
+            Some(module_hash) => {
+                bytes.push(1);
+                push_str(&mut bytes, module_hash);
+            }
+            None => bytes.push(0),
+        }
+        let host_functions = self.host_functions.to_bytes();
+        bytes.extend_from_slice(&(host_functions.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&host_functions);
+        push_str(&mut bytes, &self.configuration);
+        bytes
+    }
+
+    /// Whether the record was signed with `key` and has not been
+    /// changed since
+    pub fn verify(&self, key: &[u8; 32]) -> bool {
+        blake3::Hash::from_hex(&self.signature)
+            .is_ok_and(|signature| signature == blake3::keyed_hash(key, &self.to_bytes()))
+    }
+}
+
+/// Records the provenance of the modules loaded into a sandbox
+pub(crate) struct ProvenanceRecorder {
+    key: [u8; 32],
+    configuration: String,
+    host_functions: HostFunctionManifest,
+}
+
+impl ProvenanceRecorder {
+    pub(crate) fn new(key: [u8; 32], configuration: String) -> Self {
+        Self {
+            key,
+            configuration,
+            host_functions: HostFunctionManifest::default(),
+        }
+    }
+
+    /// Set the host functions registered with the sandbox
+    pub(crate) fn set_host_functions(&mut self, host_functions: HostFunctionManifest) {
+        self.host_functions = host_functions;
+    }
+
+    /// Add a host function registered after the runtime was loaded,
+    /// replacing any with the same name
+    pub(crate) fn add_host_function(&mut self, function: ManifestFunction) {
+        let mut functions: Vec<ManifestFunction> = self
+            .host_functions
+            .functions()
+            .iter()
+            .filter(|f| f.name != function.name)
+            .cloned()
+            .collect();
+        functions.push(function);
+        functions.sort_by(|a, b| a.name.cmp(&b.name));
+        self.host_functions = functions.into_iter().fold(
+            HostFunctionManifest::new(),
+            HostFunctionManifest::with_function,
+        );
+    }
+
+    /// The signed provenance of the module whose hash is `module_hash`
+    pub(crate) fn record(&self, module_hash: Option<String>) -> Provenance {
+        let build_info = BuildInfo::get();
+        let mut provenance = Provenance {
+            package_version: build_info.package_version.to_string(),
+            wasm_runtime_hash: build_info.wasm_runtime_blake3_hash.to_string(),
+            wasmtime_version: build_info.wasm_runtime_wasmtime_version.to_string(),
+            module_hash,
+            host_functions: self.host_functions.clone(),
+            configuration: self.configuration.clone(),
+            signature: String::new(),
+        };
+        provenance.signature = blake3::keyed_hash(&self.key, &provenance.to_bytes()).to_string();
+        provenance
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyperlight_host::func::{ParameterType, ReturnType};
+
+    use super::ProvenanceRecorder;
+    use crate::{HostFunctionManifest, ManifestFunction};
+
+    #[test]
+    fn test_provenance() {
+        let key = [7u8; 32];
+        let mut recorder = ProvenanceRecorder::new(key, "heap_size: 1048576".to_string());
+        recorder.set_host_functions(HostFunctionManifest::new().with_function(
+            ManifestFunction::new("TestHostFunc", [ParameterType::Int], ReturnType::Int),
+        ));
+        let provenance = recorder.record(Some("abc".to_string()));
+        assert_eq!(provenance.module_hash.as_deref(), Some("abc"));
+        assert_eq!(provenance.host_functions.functions().len(), 1);
+        assert!(provenance.verify(&key));
+        assert!(!provenance.verify(&[8u8; 32]));
+
+        let mut tampered = provenance.clone();
+        tampered.module_hash = Some("abd".to_string());
+        assert!(!tampered.verify(&key));
+    }
+}
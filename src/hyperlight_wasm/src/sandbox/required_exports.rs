@@ -0,0 +1,104 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use hyperlight_common::flatbuffer_wrappers::host_function_definition::HostFunctionDefinition;
+use hyperlight_common::flatbuffer_wrappers::host_function_details::HostFunctionDetails;
+use hyperlight_host::func::{ParameterType, ReturnType};
+use hyperlight_host::{MultiUseSandbox, Result, new_error};
+
+/// A function that guests must export to be loaded, see
+/// [`SandboxBuilder::with_required_exports`](crate::SandboxBuilder::with_required_exports)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequiredExport {
+    /// The name of the function. Functions that components export from
+    /// an interface are named `interface#function`.
+    pub name: String,
+    /// The parameter and return types the function must be callable
+    /// with, or `None` to accept any function. Only the types of module
+    /// exports are checked, since the types of component exports are
+    /// fixed by their world.
+    pub signature: Option<(Vec<ParameterType>, ReturnType)>,
+}
+
+impl RequiredExport {
+    /// Require a function called `name`, with any type
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            signature: None,
+        }
+    }
+
+    /// Require the function to take `parameter_types` and return
+    /// `return_type`
+    pub fn with_signature(
+        mut self,
+        parameter_types: impl IntoIterator<Item = ParameterType>,
+        return_type: ReturnType,
+    ) -> Self {
+        self.signature = Some((parameter_types.into_iter().collect(), return_type));
+        self
+    }
+}
+
+impl From<&str> for RequiredExport {
+    fn from(name: &str) -> Self {
+        Self::new(name)
+    }
+}
+
+impl From<String> for RequiredExport {
+    fn from(name: String) -> Self {
+        Self::new(name)
+    }
+}
+
+/// Check that the guest loaded into `inner` provides `exports`,
+/// returning a single error describing every export that is missing or
+/// has the wrong type
+pub(crate) fn check(inner: &mut MultiUseSandbox, exports: &[RequiredExport]) -> Result<()> {
+    if exports.is_empty() {
+        return Ok(());
+    }
+    // The guest reads each export as a function definition, whose
+    // parameter types are only set if its type is checked
+    let details = HostFunctionDetails {
+        host_functions: Some(
+            exports
+                .iter()
+                .map(|export| HostFunctionDefinition {
+                    function_name: export.name.clone(),
+                    parameter_types: export.signature.as_ref().map(|(params, _)| params.clone()),
+                    return_type: export
+                        .signature
+                        .as_ref()
+                        .map_or(ReturnType::Void, |(_, ret)| *ret),
+                })
+                .collect(),
+        ),
+    };
+    let bytes: Vec<u8> = (&details)
+        .try_into()
+        .map_err(|e| new_error!("Failed to serialize required exports: {:?}", e))?;
+    let problems: String = inner.call("CheckExports", bytes)?;
+    if !problems.is_empty() {
+        return Err(new_error!(
+            "guest does not provide its required exports: {}",
+            problems
+        ));
+    }
+    Ok(())
+}
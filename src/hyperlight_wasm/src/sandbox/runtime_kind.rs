@@ -0,0 +1,87 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use hyperlight_host::{Result, new_error};
+
+use super::{WASM_MODULE_RUNTIME, WASM_RUNTIME, WASM_RUNTIME_KIND};
+
+/// Which of the runtimes embedded in this build of hyperlight-wasm a
+/// sandbox runs, see
+/// [`SandboxBuilder::with_runtime`](crate::SandboxBuilder::with_runtime).
+///
+/// The runtime for components supports the WIT world in the `WIT_WORLD`
+/// environment variable when hyperlight-wasm was built, so a build
+/// without `WIT_WORLD` embeds only the runtime for modules. A build with
+/// `WIT_WORLD` embeds both, unless the runtime was prebuilt.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RuntimeKind {
+    /// The runtime for core WebAssembly modules
+    Module,
+    /// The runtime for components of the world in `WIT_WORLD`
+    Component,
+}
+
+impl RuntimeKind {
+    /// The runtime sandboxes run unless they choose another: the runtime
+    /// for components in builds with `WIT_WORLD`, and the runtime for
+    /// modules otherwise
+    pub fn embedded_default() -> Self {
+        WASM_RUNTIME_KIND
+    }
+
+    /// Whether this build of hyperlight-wasm embeds the runtime
+    pub fn is_embedded(self) -> bool {
+        self == WASM_RUNTIME_KIND || (self == RuntimeKind::Module && WASM_MODULE_RUNTIME.is_some())
+    }
+
+    /// The runtime's guest binary
+    pub(crate) fn guest_binary(self) -> Result<&'static [u8]> {
+        if self == WASM_RUNTIME_KIND {
+            return Ok(&WASM_RUNTIME);
+        }
+        match self {
+            RuntimeKind::Module => WASM_MODULE_RUNTIME.ok_or_else(|| {
+                new_error!("this build of hyperlight-wasm only embeds the runtime for components")
+            }),
+            RuntimeKind::Component => Err(new_error!(
+                "this build of hyperlight-wasm does not embed the runtime for components: set WIT_WORLD when building it"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RuntimeKind;
+
+    #[test]
+    fn test_runtime_kind() {
+        let default = RuntimeKind::embedded_default();
+        assert!(default.is_embedded());
+        assert!(default.guest_binary().is_ok());
+
+        // Builds for components embed the runtime for modules too, unless
+        // the runtime is prebuilt
+        let other = match default {
+            RuntimeKind::Module => RuntimeKind::Component,
+            RuntimeKind::Component => RuntimeKind::Module,
+        };
+        assert_eq!(other.is_embedded(), other.guest_binary().is_ok());
+        if default == RuntimeKind::Module {
+            assert!(other.guest_binary().is_err());
+        }
+    }
+}
@@ -14,11 +14,40 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
+use std::io::Write;
+use std::ops::{Bound, RangeBounds};
+use std::path::Path;
+#[cfg(feature = "aot")]
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
 use hyperlight_host::func::HostFunction;
 use hyperlight_host::sandbox::SandboxConfiguration;
-use hyperlight_host::{GuestBinary, HyperlightError, Result, is_hypervisor_present};
+use hyperlight_host::{GuestBinary, HyperlightError, Result, is_hypervisor_present, new_error};
+
+use hyperlight_wasm_runtime::runtime_config::{
+    EntropyPolicy, OptLevel, RuntimeConfig, WasmFeatures,
+};
 
+use super::call_budget::HostClock;
+use super::cli_environment::CliEnvironment;
+use super::guest_callbacks::{DEFAULT_MAX_REENTRANCY_DEPTH, ReentrantCalls};
+use super::memory_stats::MemoryTracker;
+use super::module_resolver::ModuleResolver;
+use super::output_capture::OutputCapture;
+use super::panic_policy::{PanicHandler, PanicPolicy};
+use super::payload_cipher::PayloadKey;
+use super::preopened_dirs::{DirAccess, PreopenedDirs};
 use super::proto_wasm_sandbox::ProtoWasmSandbox;
+use super::provenance::ProvenanceRecorder;
+use super::required_exports::RequiredExport;
+use super::runtime_kind::RuntimeKind;
+use super::vfs::Vfs;
+use super::virtual_clock::VirtualClock;
+#[cfg(feature = "aot")]
+use crate::aot::AotCache;
+use crate::validate::Policy;
 
 // use large minimum scratch/heap/input data sizes
 // to deal with the size of wasmtime/wasi-libc aot artifacts
@@ -30,7 +59,36 @@ pub const MIN_HEAP_SIZE: u64 = 1024 * 1024;
 #[derive(Clone)]
 pub struct SandboxBuilder {
     config: SandboxConfiguration,
+    runtime_config: RuntimeConfig,
     host_print_fn: Option<HostFunction<i32, (String,)>>,
+    output_capture: OutputCapture,
+    preopened_dirs: PreopenedDirs,
+    vfs: Vfs,
+    cli_environment: CliEnvironment,
+    time_offset: Duration,
+    time_scale: f64,
+    frozen_time: Option<SystemTime>,
+    module_resolver: Option<Arc<dyn ModuleResolver>>,
+    usage_accounting: bool,
+    panic_policy: PanicPolicy,
+    provenance_key: Option<[u8; 32]>,
+    zero_memory_on_unload: bool,
+    module_cache_capacity: usize,
+    max_reentrancy_depth: usize,
+    required_exports: Vec<RequiredExport>,
+    validation_policy: Option<Policy>,
+    call_timeout: Option<Duration>,
+    guest_time_budget: Option<Duration>,
+    host_time_budget: Option<Duration>,
+    payload_key: Option<[u8; 32]>,
+    memory_metrics_label: Option<String>,
+    runtime_kind: RuntimeKind,
+    #[cfg(feature = "crashdump")]
+    crashdump: bool,
+    #[cfg(feature = "aot")]
+    host_precompilation: bool,
+    #[cfg(feature = "aot")]
+    aot_cache: Option<AotCache>,
 }
 
 impl SandboxBuilder {
@@ -41,9 +99,46 @@ impl SandboxBuilder {
         config.set_heap_size(MIN_HEAP_SIZE);
         config.set_scratch_size(MIN_SCRATCH_SIZE);
 
+        // Reported to guests through the hlwasm:limits interface
+        let runtime_config = RuntimeConfig {
+            heap_size: Some(MIN_HEAP_SIZE),
+            input_buffer_size: Some(MIN_INPUT_DATA_SIZE as u64),
+            output_buffer_size: Some(SandboxConfiguration::DEFAULT_OUTPUT_SIZE as u64),
+            ..Default::default()
+        };
+
         Self {
             config,
+            runtime_config,
             host_print_fn: None,
+            output_capture: OutputCapture::default(),
+            preopened_dirs: PreopenedDirs::default(),
+            vfs: Vfs::default(),
+            cli_environment: CliEnvironment::default(),
+            time_offset: Duration::ZERO,
+            time_scale: 1.0,
+            frozen_time: None,
+            module_resolver: None,
+            usage_accounting: false,
+            panic_policy: PanicPolicy::default(),
+            provenance_key: None,
+            zero_memory_on_unload: false,
+            module_cache_capacity: 0,
+            max_reentrancy_depth: DEFAULT_MAX_REENTRANCY_DEPTH,
+            required_exports: Vec::new(),
+            validation_policy: None,
+            call_timeout: None,
+            guest_time_budget: None,
+            host_time_budget: None,
+            payload_key: None,
+            memory_metrics_label: None,
+            runtime_kind: RuntimeKind::embedded_default(),
+            #[cfg(feature = "crashdump")]
+            crashdump: true,
+            #[cfg(feature = "aot")]
+            host_precompilation: true,
+            #[cfg(feature = "aot")]
+            aot_cache: None,
         }
     }
 
@@ -78,9 +173,109 @@ impl SandboxBuilder {
         self
     }
 
+    /// Capture what guests write to stdout into `writer`, rather than
+    /// printing it with the host print function. Modules write to stdout
+    /// with the WASI `fd_write` function, and components with
+    /// `wasi:cli/stdout` if [`with_wasi_p2`](Self::with_wasi_p2) is
+    /// enabled.
+    ///
+    /// Guest output is written to `writer` as the guest writes it, while
+    /// the guest call runs. A failed write fails the guest's write. Every
+    /// sandbox built from this builder, or from clones of it, writes to
+    /// the same `writer`.
+    pub fn with_stdout(mut self, writer: impl Write + Send + 'static) -> Self {
+        self.output_capture.stdout = Some(Arc::new(Mutex::new(writer)));
+        self.runtime_config.capture_stdout = true;
+        self
+    }
+
+    /// Capture what guests write to stderr into `writer`, as
+    /// [`with_stdout`](Self::with_stdout) does for stdout. Without it,
+    /// module writes to stderr fail and components print what they write
+    /// to stderr with the host print function.
+    pub fn with_stderr(mut self, writer: impl Write + Send + 'static) -> Self {
+        self.output_capture.stderr = Some(Arc::new(Mutex::new(writer)));
+        self.runtime_config.capture_stderr = true;
+        self
+    }
+
+    /// Preopen the host directory `host_path` for modules as the
+    /// directory `guest_path`, such as `/data`, so that they can open and
+    /// read its files with the WASI filesystem functions, for example
+    /// with `std::fs` in Rust guests built for `wasm32-wasip1`. Files are
+    /// read on the host as the guest reads them. Paths that leave the
+    /// directory, including through symbolic links, fail with
+    /// `ERRNO_NOTCAPABLE`.
+    ///
+    /// Only [`DirAccess::ReadOnly`] is supported: opening a file to
+    /// create, truncate or write it fails with `ERRNO_ROFS`. Components
+    /// cannot read preopened directories, they still import
+    /// `wasi:filesystem` from the host functions of their world.
+    ///
+    /// Building the sandbox fails if `host_path` is not a directory.
+    pub fn with_preopened_dir(
+        mut self,
+        host_path: impl AsRef<Path>,
+        guest_path: &str,
+        access: DirAccess,
+    ) -> Self {
+        self.preopened_dirs
+            .add(host_path.as_ref(), guest_path, access);
+        self.runtime_config.preopened_dirs = true;
+        self
+    }
+
+    /// Provide modules with an in-memory filesystem holding `files`, each
+    /// an absolute path in the guest, such as `/data/config.json`, and
+    /// its contents. Modules open and read the files with the WASI
+    /// filesystem functions, as for
+    /// [`with_preopened_dir`](Self::with_preopened_dir), but the files
+    /// are copied into the sandbox when the runtime is loaded, so guest
+    /// calls read them without calling the host. The directories in the
+    /// paths exist too, and the filesystem is preopened as `/`, after any
+    /// preopened host directories. Its files shadow host files at the
+    /// same paths.
+    ///
+    /// The filesystem is read-only, and its files take up space on the
+    /// guest heap, see [`with_guest_heap_size`](Self::with_guest_heap_size).
+    /// Calling this again adds more files. Building the sandbox fails if
+    /// a path is relative or conflicts with another.
+    pub fn with_vfs(mut self, files: Vec<(String, Vec<u8>)>) -> Self {
+        self.vfs.add(files);
+        self.runtime_config.vfs = true;
+        self
+    }
+
+    /// Set the environment variable `key` to `value` for guests, replacing
+    /// any value set before. Modules read the variables with the wasip1
+    /// `environ_get` function, and components with
+    /// `wasi:cli/environment`. The host's own environment is not passed
+    /// to guests.
+    ///
+    /// Building the sandbox fails if `key` is empty or contains `=`, or if
+    /// either contains a NUL byte.
+    pub fn with_env(mut self, key: &str, value: &str) -> Self {
+        self.cli_environment.set_var(key, value);
+        self.runtime_config.cli_environment = true;
+        self
+    }
+
+    /// Set the arguments guests read with the wasip1 `args_get` function
+    /// or `wasi:cli/environment`, replacing any set before. They are passed
+    /// as they are, so the first is conventionally the program name.
+    ///
+    /// Building the sandbox fails if an argument contains a NUL byte.
+    pub fn with_args(mut self, args: Vec<String>) -> Self {
+        self.cli_environment.set_args(args);
+        self.runtime_config.cli_environment = true;
+        self
+    }
+
     /// Set the guest output buffer size
     pub fn with_guest_output_buffer_size(mut self, guest_output_buffer_size: usize) -> Self {
         self.config.set_output_data_size(guest_output_buffer_size);
+        self.runtime_config.output_buffer_size =
+            Some(guest_output_buffer_size.max(SandboxConfiguration::MIN_OUTPUT_SIZE) as u64);
         self
     }
 
@@ -92,6 +287,7 @@ impl SandboxBuilder {
     pub fn with_guest_input_buffer_size(mut self, guest_input_buffer_size: usize) -> Self {
         if guest_input_buffer_size > MIN_INPUT_DATA_SIZE {
             self.config.set_input_data_size(guest_input_buffer_size);
+            self.runtime_config.input_buffer_size = Some(guest_input_buffer_size as u64);
         }
         self
     }
@@ -115,31 +311,640 @@ impl SandboxBuilder {
     pub fn with_guest_heap_size(mut self, guest_heap_size: u64) -> Self {
         if guest_heap_size > MIN_HEAP_SIZE {
             self.config.set_heap_size(guest_heap_size);
+            self.runtime_config.heap_size = Some(guest_heap_size);
         }
         self
     }
 
+    /// Set the maximum amount of stack space, in bytes, that wasm code
+    /// may use before a stack overflow trap is raised. If this is not
+    /// set, wasmtime's default (512 KiB) is used. Calls that exhaust the
+    /// stack fail with a [`StackExhausted`](crate::StackExhausted) error.
+    ///
+    /// Raise this to accommodate deeply recursive guests, or lower it to
+    /// constrain them. Wasm code runs on the guest's native stack, so
+    /// raising this may also require increasing the scratch size with
+    /// [`with_guest_scratch_size`](Self::with_guest_scratch_size).
+    pub fn with_max_wasm_stack(mut self, max_wasm_stack: usize) -> Self {
+        self.runtime_config.max_wasm_stack = Some(max_wasm_stack as u64);
+        self
+    }
+
+    /// Limit each linear memory of loaded guests to `max_wasm_memory`
+    /// bytes, so that `memory.grow` fails, returning -1 to the guest, when
+    /// it would grow a memory beyond the limit, rather than the guest's
+    /// allocator failing when the sandbox's heap runs out. A guest call
+    /// that fails after a memory was refused growth returns a
+    /// [`WasmLimitExceeded`](crate::WasmLimitExceeded) error. Guests
+    /// whose initial memory is larger than the limit fail to load.
+    ///
+    /// Memories are only limited by the sandbox's heap by default.
+    pub fn with_max_wasm_memory(mut self, max_wasm_memory: usize) -> Self {
+        self.runtime_config.max_wasm_memory = Some(max_wasm_memory as u64);
+        self
+    }
+
+    /// Limit each table of loaded guests to `max_table_elements`
+    /// elements, so that `table.grow` fails, returning -1 to the guest,
+    /// when it would grow a table beyond the limit. A guest call that
+    /// fails after a table was refused growth returns a
+    /// [`WasmLimitExceeded`](crate::WasmLimitExceeded) error.
+    ///
+    /// Tables are only limited by the sandbox's heap by default.
+    pub fn with_max_table_elements(mut self, max_table_elements: usize) -> Self {
+        self.runtime_config.max_table_elements = Some(max_table_elements as u64);
+        self
+    }
+
+    /// Require wasm modules to have been precompiled with NaN
+    /// canonicalization enabled, so that floating-point results are
+    /// reproducible across hosts.
+    ///
+    /// Modules must be compiled with `hyperlight-wasm-aot compile
+    /// --canonicalize-nans`. Loading a module whose NaN canonicalization
+    /// setting does not match the sandbox's fails.
+    pub fn with_nan_canonicalization(mut self, enabled: bool) -> Self {
+        self.runtime_config.canonicalize_nans = enabled;
+        self
+    }
+
+    /// Require wasm modules and components to have been precompiled with
+    /// deterministic relaxed SIMD enabled, so that relaxed SIMD
+    /// instructions give the same results on every host.
+    ///
+    /// Guests must be compiled with `hyperlight-wasm-aot compile
+    /// --deterministic-relaxed-simd`. Loading a guest whose setting does
+    /// not match the sandbox's fails.
+    pub fn with_deterministic_relaxed_simd(mut self, enabled: bool) -> Self {
+        self.runtime_config.deterministic_relaxed_simd = enabled;
+        self
+    }
+
+    /// Choose the WebAssembly proposals guests may use, such as SIMD or
+    /// tail calls. All proposals wasmtime supports are enabled by default,
+    /// except threads, which the runtime does not support.
+    ///
+    /// Guests must be precompiled with the same proposals, with the
+    /// `--disable-*` options of `hyperlight-wasm-aot compile`, and loading
+    /// a guest precompiled with different proposals fails. Guests
+    /// precompiled on the host use the sandbox's proposals, see
+    /// [`aot::compile_for_sandbox`](crate::aot::compile_for_sandbox).
+    /// Building the sandbox fails if `features.threads` is set.
+    pub fn with_wasm_features(mut self, features: WasmFeatures) -> Self {
+        self.runtime_config.wasm_features = features;
+        self
+    }
+
+    /// Set how much the compiler optimizes guests precompiled on the
+    /// host, see
+    /// [`with_host_precompilation`](Self::with_host_precompilation) and
+    /// [`aot::compile_for_sandbox`](crate::aot::compile_for_sandbox).
+    /// There is no compiler in the guest, so this does not affect guests
+    /// precompiled elsewhere, which use the `--opt-level` option of
+    /// `hyperlight-wasm-aot compile`. Defaults to [`OptLevel::Speed`].
+    pub fn with_opt_level(mut self, opt_level: OptLevel) -> Self {
+        self.runtime_config.opt_level = opt_level;
+        self
+    }
+
+    /// Make runs of the sandbox's guests bit-reproducible, for example
+    /// for consensus: this enables
+    /// [`with_nan_canonicalization`](Self::with_nan_canonicalization) and
+    /// [`with_deterministic_relaxed_simd`](Self::with_deterministic_relaxed_simd),
+    /// sets the entropy policy to [`EntropyPolicy::Deterministic`] and,
+    /// unless [`with_frozen_time`](Self::with_frozen_time) was called,
+    /// freezes time at the Unix epoch.
+    ///
+    /// Guests must be compiled with `hyperlight-wasm-aot compile
+    /// --canonicalize-nans --deterministic-relaxed-simd`. Host functions
+    /// registered by the host, including time functions replacing
+    /// `GetTimeSinceBootMicrosecond`, must be deterministic themselves.
+    pub fn with_deterministic_execution(mut self) -> Self {
+        self.runtime_config.canonicalize_nans = true;
+        self.runtime_config.deterministic_relaxed_simd = true;
+        self.runtime_config.entropy_policy = EntropyPolicy::Deterministic;
+        self.frozen_time.get_or_insert(SystemTime::UNIX_EPOCH);
+        self
+    }
+
+    /// Require wasm modules and components to have been precompiled with
+    /// epoch interruption enabled, so that guest calls can be given a
+    /// deadline with
+    /// [`LoadedWasmSandbox::set_epoch_deadline`](crate::LoadedWasmSandbox::set_epoch_deadline).
+    ///
+    /// Guests must be compiled with `hyperlight-wasm-aot compile
+    /// --epoch-interruption`. Loading a guest whose epoch interruption
+    /// setting does not match the sandbox's fails.
+    pub fn with_epoch_interruption(mut self, enabled: bool) -> Self {
+        self.runtime_config.epoch_interruption = enabled;
+        self
+    }
+
+    /// Set the most verbose level of log records written by wasm modules
+    /// that are kept. Defaults to [`log::max_level()`] at the time the
+    /// sandbox is built.
+    ///
+    /// Modules write log records with the `log` and `log_record` functions
+    /// imported from the `hyperlight` module (see
+    /// `hyperlight_wasm_guest_sdk::log`), and components with the `log`
+    /// function of the `hlwasm:log/log` interface. Records are buffered in
+    /// the guest and emitted as `tracing` events, with the target
+    /// `hyperlight_wasm::guest`, in a `guest_log` span, after each guest
+    /// call returns. Records more verbose than `level` are discarded in the
+    /// guest.
+    pub fn with_guest_log_level(mut self, level: log::LevelFilter) -> Self {
+        self.runtime_config.guest_log_level = Some(level as u64);
+        self
+    }
+
+    /// Set the maximum size, in bytes, of a String or VecBytes returned
+    /// from a guest function. Calls whose return value is larger fail
+    /// with a `HyperlightError::GuestError` whose message says the return
+    /// value exceeds the maximum return value size. The size is checked
+    /// in the guest before the value is copied out, so oversized values
+    /// never reach the output buffer or host memory.
+    ///
+    /// Return values are not limited by default, other than by the size
+    /// of the output buffer.
+    pub fn with_max_return_value_size(mut self, max_return_value_size: usize) -> Self {
+        self.runtime_config.max_return_value_size = Some(max_return_value_size as u64);
+        self
+    }
+
+    /// Set where random numbers requested by guests, with the WASI
+    /// `random_get` function, come from. See [`EntropyPolicy`].
+    ///
+    /// Defaults to [`EntropyPolicy::Denied`], so that guests get an error
+    /// when they ask for random numbers.
+    pub fn with_entropy_policy(mut self, policy: EntropyPolicy) -> Self {
+        self.runtime_config.entropy_policy = policy;
+        self
+    }
+
+    /// Seed the guest's pseudo-random number generator with `seed`, and
+    /// use it for random numbers requested by guests. This sets the
+    /// entropy policy to [`EntropyPolicy::Deterministic`], so that
+    /// sandboxes built with the same seed see the same random numbers,
+    /// which is useful for reproducible simulations and tests.
+    pub fn with_rng_seed(mut self, seed: u64) -> Self {
+        self.runtime_config.rng_seed = Some(seed);
+        self.runtime_config.entropy_policy = EntropyPolicy::Deterministic;
+        self
+    }
+
+    /// Set the WASI errno returned by WASI functions that a module imports
+    /// but the runtime does not implement. Such imports are linked to
+    /// stubs, so that modules that rarely use them can still be loaded;
+    /// stubs for functions that do not return an errno trap when called.
+    /// The stubbed functions are listed by
+    /// [`LoadedWasmSandbox::stubbed_wasi_imports`](crate::LoadedWasmSandbox::stubbed_wasi_imports).
+    ///
+    /// Defaults to `ERRNO_NOSYS` (52).
+    pub fn with_unsupported_wasi_errno(mut self, errno: u16) -> Self {
+        self.runtime_config.unsupported_wasi_errno = Some(errno as u64);
+        self
+    }
+
+    /// Fault in the whole linear memory of each module when it is
+    /// loaded, rather than page by page as the module first uses it.
+    /// This makes the first calls into a module faster and more
+    /// predictable, at the cost of slower loads and of backing memory
+    /// the module may never use. It has no effect on components.
+    ///
+    /// Defaults to `false`.
+    pub fn with_prefault_memory(mut self, enabled: bool) -> Self {
+        self.runtime_config.prefault_memory = enabled;
+        self
+    }
+
+    /// Provide the WASI preview 2 interfaces a component imports from
+    /// the runtime, rather than from the host functions of its world:
+    /// clocks read the sandbox's clock, random numbers follow its
+    /// entropy policy, and stdout and stderr are written with the host
+    /// print function, unless they are captured with
+    /// [`with_stdout`](Self::with_stdout) and
+    /// [`with_stderr`](Self::with_stderr). Other WASI interfaces are
+    /// still linked from the world. It has no effect on modules.
+    ///
+    /// Defaults to `false`.
+    pub fn with_wasi_p2(mut self, enabled: bool) -> Self {
+        self.runtime_config.wasi_p2 = enabled;
+        self
+    }
+
+    /// Instantiate a loaded component afresh before each call of one of
+    /// its exports, so that calls see none of the state left by earlier
+    /// calls, as if the component had just been loaded. The component
+    /// is deserialized and its imports resolved once, when it is loaded,
+    /// so each call only pays for creating the instance. Resources the
+    /// component returned to the host are dropped with the instance that
+    /// created them, so their handles cannot be passed to later calls.
+    /// It has no effect on modules.
+    ///
+    /// Defaults to `false`.
+    pub fn with_fresh_instance_per_call(mut self, enabled: bool) -> Self {
+        self.runtime_config.fresh_instance_per_call = enabled;
+        self
+    }
+
+    /// Precompile guests that are not yet precompiled on the host as
+    /// they are loaded, rather than passing them to the guest, which
+    /// cannot compile them. Modules and components are compiled with
+    /// the sandbox's settings each time they are loaded, so hosts that
+    /// load the same guest repeatedly should precompile it once with
+    /// [`aot::compile_for_sandbox`](crate::aot::compile_for_sandbox).
+    ///
+    /// This requires the `aot` feature. Defaults to `true`.
+    #[cfg(feature = "aot")]
+    pub fn with_host_precompilation(mut self, enabled: bool) -> Self {
+        self.host_precompilation = enabled;
+        self
+    }
+
+    /// Keep the artifacts of guests precompiled on the host in `dir`, so
+    /// that a guest loaded again, by this or another sandbox with the
+    /// same settings, is read from the cache rather than compiled again.
+    /// See [`AotCache`] and
+    /// [`with_host_precompilation`](Self::with_host_precompilation).
+    ///
+    /// This requires the `aot` feature. By default artifacts are not
+    /// cached.
+    #[cfg(feature = "aot")]
+    pub fn with_aot_cache(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.aot_cache = Some(AotCache::new(dir));
+        self
+    }
+
+    /// Reset the sandbox's memory to its state before the module was
+    /// loaded as soon as the module is unloaded with
+    /// [`LoadedWasmSandbox::unload_module`](crate::LoadedWasmSandbox::unload_module),
+    /// rather than when the next module is loaded. This discards the
+    /// module's data, zeroing the memory it used, so that it is not kept
+    /// while the `WasmSandbox` is cached, at the cost of slower unloads.
+    ///
+    /// Defaults to `false`.
+    pub fn with_zero_memory_on_unload(mut self, enabled: bool) -> Self {
+        self.zero_memory_on_unload = enabled;
+        self
+    }
+
+    /// Keep snapshots of the sandbox taken just after each of the last
+    /// `capacity` distinct modules was loaded into it, so that loading
+    /// one of them again, for example each time the sandbox is checked
+    /// out of a [`SandboxPool`](crate::SandboxPool) to run the same
+    /// module, restores its snapshot rather than precompiling,
+    /// deserializing and instantiating the module again.
+    ///
+    /// Modules are identified by the hash of their bytes, so files are
+    /// read in full when they are loaded. Only modules loaded with
+    /// [`WasmSandbox::load_module`](crate::WasmSandbox::load_module) and
+    /// [`WasmSandbox::load_module_from_buffer`](crate::WasmSandbox::load_module_from_buffer)
+    /// are cached, and the cache is cleared when a host function is
+    /// registered with
+    /// [`WasmSandbox::register`](crate::WasmSandbox::register). Each
+    /// snapshot keeps a copy of the sandbox's memory.
+    ///
+    /// Defaults to 0, which caches nothing.
+    pub fn with_module_cache(mut self, capacity: usize) -> Self {
+        self.module_cache_capacity = capacity;
+        self
+    }
+
+    /// Set how deeply host functions registered with
+    /// [`ProtoWasmSandbox::register_reentrant`](crate::ProtoWasmSandbox::register_reentrant)
+    /// may nest: a re-entrant host function called from an export that
+    /// another one called back into is one level deeper than it. A call
+    /// to a re-entrant host function that would nest deeper fails.
+    ///
+    /// Defaults to 8.
+    pub fn with_max_reentrancy_depth(mut self, depth: usize) -> Self {
+        self.max_reentrancy_depth = depth;
+        self
+    }
+
+    /// Run the sandbox's guests in virtual time, which starts `offset`
+    /// ahead of the host's clock when the sandbox is built and advances
+    /// `scale` times as fast as it, so that simulations can run guests at
+    /// accelerated time. See [`VirtualClock`].
+    ///
+    /// Virtual time is used by the WASI `clock_time_get` function and by
+    /// the default `GetTimeSinceBootMicrosecond` host function, which is
+    /// registered unless the host registers its own. `scale` must be
+    /// finite and positive, or [`build`](Self::build) fails.
+    ///
+    /// By default guests see the host's clock. This replaces
+    /// [`with_frozen_time`](Self::with_frozen_time).
+    pub fn with_virtual_time(mut self, offset: Duration, scale: f64) -> Self {
+        self.time_offset = offset;
+        self.time_scale = scale;
+        self.frozen_time = None;
+        self
+    }
+
+    /// Stop the clock seen by the sandbox's guests at the wall-clock time
+    /// `at`, so that runs of a guest are deterministic and can be
+    /// replayed: every read of the wall clock returns `at` and the
+    /// monotonic clock stays at zero. See [`VirtualClock`].
+    ///
+    /// Frozen time is used wherever virtual time is, see
+    /// [`with_virtual_time`](Self::with_virtual_time), which this
+    /// replaces.
+    pub fn with_frozen_time(mut self, at: SystemTime) -> Self {
+        self.frozen_time = Some(at);
+        self
+    }
+
+    /// Require loaded guests to declare a guest ABI version in `range`, so
+    /// that loading a guest built against an incompatible guest SDK fails
+    /// with a clear error instead of misbehaving at its first call.
+    ///
+    /// Modules declare their version by exporting a function named
+    /// `hlwasm_abi_version`, and components by exporting a function named
+    /// `hlwasm-abi-version`, that takes no parameters and returns the
+    /// version as a `u32` (see `hyperlight_wasm_guest_sdk::abi_version!`).
+    /// If a range is required, loading a guest that does not declare a
+    /// version, or whose version is outside the range, fails with a
+    /// `HyperlightError::GuestError` whose message starts with
+    /// `guest ABI version mismatch`.
+    ///
+    /// Guest ABI versions are not checked by default.
+    pub fn with_required_guest_abi(mut self, range: impl RangeBounds<u32>) -> Self {
+        let min = match range.start_bound() {
+            Bound::Included(&v) => v as u64,
+            Bound::Excluded(&v) => v as u64 + 1,
+            Bound::Unbounded => 0,
+        };
+        let max = match range.end_bound() {
+            Bound::Included(&v) => Some(v as u64 + 1),
+            Bound::Excluded(&v) => Some(v as u64),
+            Bound::Unbounded => None,
+        };
+        self.runtime_config.min_guest_abi_version = Some(min);
+        self.runtime_config.max_guest_abi_version = max;
+        self
+    }
+
     /// Enable or disable crashdump generation for the sandbox
     /// When enabled, core dumps will be generated when the guest crashes
     /// This requires the `crashdump` feature to be enabled
+    ///
+    /// Core dumps are also generated when a module traps or aborts, which
+    /// does not crash the VM, and each is accompanied by a
+    /// `<core dump>.wasm.json` file holding its wasm context: the
+    /// function called, the module's hash, exports and linear memories,
+    /// and the trap's wasm backtrace, see
+    /// [`LoadedWasmSandbox::generate_crashdump_to_dir`](crate::LoadedWasmSandbox::generate_crashdump_to_dir).
+    /// Crashdumps are enabled by default.
     #[cfg(feature = "crashdump")]
     pub fn with_crashdump_enabled(mut self, enabled: bool) -> Self {
         self.config.set_guest_core_dump(enabled);
+        self.crashdump = enabled;
+        self
+    }
+
+    /// Set the [`ModuleResolver`] used to find modules loaded with
+    /// [`WasmSandbox::load_module_by_name`](crate::WasmSandbox::load_module_by_name).
+    pub fn with_module_resolver(mut self, resolver: impl ModuleResolver + 'static) -> Self {
+        self.module_resolver = Some(Arc::new(resolver));
         self
     }
 
+    /// Record the CPU time, number of calls and peak memory of the
+    /// modules loaded into the sandbox, accumulated by module across all
+    /// the sandboxes in the process that record usage, see
+    /// [`module_usage`](crate::module_usage). Defaults to `false`.
+    ///
+    /// This hashes each module as it is loaded, and costs one more VM
+    /// entry per guest call, to read the size of the module's memory.
+    pub fn with_usage_accounting(mut self, enabled: bool) -> Self {
+        self.usage_accounting = enabled;
+        self
+    }
+
+    /// Record the memory used by the sandbox's guests after each guest
+    /// call in the `wasm_sandbox_memory_used_bytes`,
+    /// `wasm_sandbox_peak_memory_used_bytes` and
+    /// `wasm_sandbox_linear_memory_bytes` gauges, labelled with
+    /// `sandbox` set to `label`, so that, for example, tenants can be
+    /// billed for the memory their guests use. See
+    /// [`LoadedWasmSandbox::memory_stats`](crate::LoadedWasmSandbox::memory_stats).
+    ///
+    /// This costs one more VM entry per guest call, to read the memory
+    /// used by the guest.
+    pub fn with_memory_metrics(mut self, label: impl Into<String>) -> Self {
+        self.memory_metrics_label = Some(label.into());
+        self
+    }
+
+    /// Set what happens when a host function panics, see [`PanicPolicy`].
+    /// Defaults to [`PanicPolicy::PoisonSandbox`].
+    pub fn with_panic_policy(mut self, policy: PanicPolicy) -> Self {
+        self.panic_policy = policy;
+        self
+    }
+
+    /// Record a [`Provenance`](crate::Provenance) for each module loaded
+    /// into the sandbox, signed with `key`, see
+    /// [`LoadedWasmSandbox::provenance`](crate::LoadedWasmSandbox::provenance).
+    ///
+    /// Like [`with_usage_accounting`](Self::with_usage_accounting), this
+    /// hashes each module as it is loaded.
+    pub fn with_provenance_key(mut self, key: [u8; 32]) -> Self {
+        self.provenance_key = Some(key);
+        self
+    }
+
+    /// Require guests to export the functions in `exports`, given as
+    /// names or as [`RequiredExport`]s with signatures, for example
+    /// `with_required_exports(["init", "handle"])`.
+    ///
+    /// Loading a guest that does not export one of the functions, or
+    /// exports one with the wrong signature, fails with a single error
+    /// describing every such export, so that plugins missing their entry
+    /// points are rejected when they are loaded rather than when they
+    /// are first called.
+    pub fn with_required_exports<E: Into<RequiredExport>>(
+        mut self,
+        exports: impl IntoIterator<Item = E>,
+    ) -> Self {
+        self.required_exports = exports.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Check each module against `policy` on the host before loading it,
+    /// see [`validate::validate_module`](crate::validate::validate_module).
+    /// Loading a module that breaks the policy fails with a single error
+    /// describing every way it does.
+    ///
+    /// Only modules in WebAssembly form can be checked, so sandboxes
+    /// with a policy refuse precompiled artifacts and components. With
+    /// the `aot` feature, modules are precompiled on the host after they
+    /// are checked. Modules restored from the module cache are not
+    /// checked again, and neither are snapshots loaded with
+    /// [`WasmSandbox::load_from_snapshot`](crate::WasmSandbox::load_from_snapshot).
+    pub fn with_validation_policy(mut self, policy: Policy) -> Self {
+        self.validation_policy = Some(policy);
+        self
+    }
+
+    /// Interrupt guest calls that run for longer than `timeout`, failing
+    /// them with a [`GuestCallTimeout`](crate::GuestCallTimeout) error.
+    /// Calls are not limited by default. The timeout is reported to
+    /// guests through the `hlwasm:limits` interface.
+    ///
+    /// A call that times out poisons the sandbox, which must then be
+    /// restored or have its module reloaded, see
+    /// [`LoadedWasmSandbox::call_guest_function_with_timeout`](crate::LoadedWasmSandbox::call_guest_function_with_timeout).
+    pub fn with_guest_call_timeout(mut self, timeout: Duration) -> Self {
+        self.call_timeout = Some(timeout);
+        self.runtime_config.call_timeout_micros = Some(timeout.as_micros() as u64);
+        self
+    }
+
+    /// Interrupt guest calls once the guest has run for longer than
+    /// `budget`, not counting the time spent in host functions, failing
+    /// them with [`CallBudgetExceeded::GuestTime`](crate::CallBudgetExceeded::GuestTime).
+    /// Together with
+    /// [`with_host_time_budget`](Self::with_host_time_budget), this keeps
+    /// a guest from dodging its budget by pushing work into expensive
+    /// host calls. Guests are not limited by default.
+    ///
+    /// A call that exceeds its budget poisons the sandbox, as a call that
+    /// times out does, see
+    /// [`with_guest_call_timeout`](Self::with_guest_call_timeout).
+    pub fn with_guest_time_budget(mut self, budget: Duration) -> Self {
+        self.guest_time_budget = Some(budget);
+        self
+    }
+
+    /// Limit the total time the host functions called during a guest
+    /// call may run for to `budget`, failing calls that exceed it with
+    /// [`CallBudgetExceeded::HostTime`](crate::CallBudgetExceeded::HostTime).
+    /// Host functions are not limited by default.
+    ///
+    /// A host function cannot be interrupted, so the budget is checked
+    /// as each one returns: the one that exceeds it returns an error to
+    /// the guest in place of its result. The time spent in host
+    /// functions is reported by
+    /// [`LoadedWasmSandbox::call_guest_function_detailed`](crate::LoadedWasmSandbox::call_guest_function_detailed)
+    /// whether or not it is limited, so that hosts can spot their own
+    /// slow callbacks. Only host functions registered with
+    /// [`ProtoWasmSandbox::register`] and the methods built on it are
+    /// measured.
+    pub fn with_host_time_budget(mut self, budget: Duration) -> Self {
+        self.host_time_budget = Some(budget);
+        self
+    }
+
+    /// Encrypt the `VecBytes` parameters and results of guest calls with
+    /// `key` while they cross into and out of the sandbox, so that they
+    /// are only in plaintext in guest memory while the wasm function
+    /// that uses them runs.
+    ///
+    /// The runtime decrypts each parameter just before copying it into
+    /// the module's memory and overwrites each result as soon as it has
+    /// copied it out, encrypting it for the host. Guests should
+    /// overwrite buffer parameters before freeing them, as modules built
+    /// with the guest SDK do. The key is removed from the guest while
+    /// [`LoadedWasmSandbox::snapshot`](crate::LoadedWasmSandbox::snapshot)
+    /// takes a snapshot, so snapshots hold neither the key nor any
+    /// decrypted buffer.
+    ///
+    /// Only modules are supported: loading a component into a sandbox
+    /// with a payload key fails.
+    pub fn with_payload_key(mut self, key: [u8; 32]) -> Self {
+        self.payload_key = Some(key);
+        self
+    }
+
+    /// Run the runtime `kind` rather than
+    /// [`RuntimeKind::embedded_default`], so that one build of
+    /// hyperlight-wasm can run both modules and components.
+    ///
+    /// [`build`](Self::build) fails if this build of hyperlight-wasm does
+    /// not embed the runtime, see [`RuntimeKind`].
+    pub fn with_runtime(mut self, kind: RuntimeKind) -> Self {
+        self.runtime_kind = kind;
+        self
+    }
+
+    /// The runtime config guests are compiled for, see
+    /// [`aot::compile_for_sandbox`](crate::aot::compile_for_sandbox)
+    #[cfg(feature = "aot")]
+    pub(crate) fn runtime_config(&self) -> &RuntimeConfig {
+        &self.runtime_config
+    }
+
     /// Build the ProtoWasmSandbox
-    pub fn build(self) -> Result<ProtoWasmSandbox> {
+    pub fn build(mut self) -> Result<ProtoWasmSandbox> {
         if !is_hypervisor_present() {
             return Err(HyperlightError::NoHypervisorFound());
         }
 
-        let guest_binary = GuestBinary::Buffer(&super::WASM_RUNTIME);
+        if !(self.time_scale.is_finite() && self.time_scale > 0.0) {
+            return Err(new_error!(
+                "virtual time scale must be finite and positive, got {}",
+                self.time_scale
+            ));
+        }
+        if self.runtime_config.wasm_features.threads {
+            return Err(new_error!(
+                "the WebAssembly threads proposal is not supported"
+            ));
+        }
+        let clock = match self.frozen_time {
+            Some(at) => VirtualClock::frozen(at),
+            None => VirtualClock::new(self.time_offset, self.time_scale),
+        };
+
+        let guest_binary = GuestBinary::Buffer(self.runtime_kind.guest_binary()?);
+        self.runtime_config
+            .guest_log_level
+            .get_or_insert(log::max_level() as u64);
 
-        let mut proto_wasm_sandbox = ProtoWasmSandbox::new(Some(self.config), guest_binary)?;
+        let provenance = self.provenance_key.map(|key| {
+            ProvenanceRecorder::new(key, format!("{:?}, {:?}", self.config, self.runtime_config))
+        });
+
+        let mut proto_wasm_sandbox = ProtoWasmSandbox::new(
+            Some(self.config),
+            guest_binary,
+            self.runtime_config,
+            clock,
+            self.output_capture,
+            self.preopened_dirs,
+            self.vfs,
+            self.cli_environment,
+        )?;
         if let Some(host_print_fn) = self.host_print_fn {
             proto_wasm_sandbox.register_print(host_print_fn)?;
         }
+        proto_wasm_sandbox.module_resolver = self.module_resolver;
+        proto_wasm_sandbox.usage_accounting = self.usage_accounting;
+        proto_wasm_sandbox.panic_handler = PanicHandler::new(self.panic_policy);
+        proto_wasm_sandbox.provenance = provenance;
+        proto_wasm_sandbox.zero_memory_on_unload = self.zero_memory_on_unload;
+        proto_wasm_sandbox.module_cache_capacity = self.module_cache_capacity;
+        proto_wasm_sandbox.reentrant_calls = ReentrantCalls::new(self.max_reentrancy_depth);
+        proto_wasm_sandbox.required_exports = self.required_exports;
+        proto_wasm_sandbox.validation_policy = self.validation_policy;
+        proto_wasm_sandbox.call_timeout = self.call_timeout;
+        proto_wasm_sandbox.guest_time_budget = self.guest_time_budget;
+        proto_wasm_sandbox.host_clock = HostClock::new(self.host_time_budget);
+        proto_wasm_sandbox.payload_key = self.payload_key.map(PayloadKey::new);
+        proto_wasm_sandbox.memory = MemoryTracker::new(self.memory_metrics_label);
+        #[cfg(feature = "crashdump")]
+        {
+            proto_wasm_sandbox.crashdump = self.crashdump;
+        }
+        #[cfg(feature = "aot")]
+        if self.host_precompilation {
+            if let Some(precompiler) = &mut proto_wasm_sandbox.precompiler {
+                precompiler.cache = self.aot_cache;
+            }
+        } else {
+            proto_wasm_sandbox.precompiler = None;
+        }
         Ok(proto_wasm_sandbox)
     }
 }
@@ -149,3 +954,31 @@ impl Default for SandboxBuilder {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use hyperlight_wasm_runtime::runtime_config::RuntimeConfig;
+
+    use super::SandboxBuilder;
+
+    #[test]
+    fn test_fresh_instance_per_call_config() {
+        let builder = SandboxBuilder::new().with_fresh_instance_per_call(true);
+        let bytes = builder.runtime_config.to_bytes();
+        let config = RuntimeConfig::from_bytes(&bytes).unwrap();
+        assert!(config.fresh_instance_per_call);
+        assert_eq!(config, builder.runtime_config);
+
+        // The setting is encoded under its own tag, and only when it is
+        // enabled
+        let config = RuntimeConfig {
+            fresh_instance_per_call: true,
+            ..Default::default()
+        };
+        assert_eq!(config.to_bytes(), [27, 1, 0, 0, 0, 0, 0, 0, 0]);
+        assert!(RuntimeConfig::default().to_bytes().is_empty());
+        let builder = builder.with_fresh_instance_per_call(false);
+        let config = RuntimeConfig::from_bytes(&builder.runtime_config.to_bytes()).unwrap();
+        assert!(!config.fresh_instance_per_call);
+    }
+}
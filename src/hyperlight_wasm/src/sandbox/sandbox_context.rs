@@ -0,0 +1,288 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use hyperlight_common::flatbuffer_wrappers::host_function_definition::HostFunctionDefinition;
+use hyperlight_common::flatbuffer_wrappers::host_function_details::HostFunctionDetails;
+use hyperlight_host::func::{HostFunction, Registerable, SupportedReturnType};
+use hyperlight_host::{MultiUseSandbox, Result, new_error};
+
+use super::call_budget::HostClock;
+use super::call_hooks::CallHooks;
+use super::callbacks::RegisteredHostFunctions;
+use super::guest_callbacks::ReentrantCalls;
+use super::host_function_cache::HostFunctionCacheExpiry;
+use super::host_function_failed::HostErrors;
+use super::host_function_manifest::ManifestFunction;
+use super::memory_stats::MemoryTracker;
+use super::module_cache::ModuleCache;
+#[cfg(target_os = "linux")]
+use super::module_handle::ModuleHandle;
+use super::module_resolver::ModuleResolver;
+use super::panic_policy::{CatchPanics, PanicHandler};
+use super::payload_cipher::PayloadKey;
+use super::provenance::ProvenanceRecorder;
+use super::required_exports::RequiredExport;
+use super::state_cells::StateCells;
+use super::streaming::ChunkSink;
+#[cfg(feature = "aot")]
+use crate::aot::HostPrecompiler;
+use crate::validate::{self, Policy};
+
+/// The settings and host-side state of a sandbox, which are passed from
+/// its `WasmSandbox` to each `LoadedWasmSandbox` and back as modules are
+/// loaded and unloaded
+#[derive(Default)]
+pub(crate) struct SandboxContext {
+    // Identifies the sandbox in the spans of its guest log records, see
+    // LoadedWasmSandbox::sandbox_id
+    pub(crate) sandbox_id: u64,
+    // Whether the guest log buffer is drained after each guest call
+    pub(crate) drain_guest_logs: bool,
+    // When the host function results cached in the guest expire
+    pub(crate) host_function_cache: HostFunctionCacheExpiry,
+    // Values shared with the guest
+    pub(crate) state_cells: StateCells,
+    // Finds modules loaded by name
+    pub(crate) module_resolver: Option<Arc<dyn ModuleResolver>>,
+    // Whether the usage of loaded modules is recorded, see
+    // SandboxBuilder::with_usage_accounting
+    pub(crate) usage_accounting: bool,
+    // Handles panics in host functions, and records whether one has
+    // poisoned the sandbox
+    pub(crate) panic_handler: PanicHandler,
+    // Records the provenance of loaded modules, see
+    // SandboxBuilder::with_provenance_key
+    pub(crate) provenance: Option<ProvenanceRecorder>,
+    // Whether memory is reset as soon as a module is unloaded, see
+    // SandboxBuilder::with_zero_memory_on_unload
+    pub(crate) zero_memory_on_unload: bool,
+    // Collects the chunks emitted during streaming calls
+    pub(crate) chunks: ChunkSink,
+    // The host functions registered with the sandbox, which callbacks
+    // can replace for a call
+    pub(crate) host_functions: RegisteredHostFunctions,
+    // The host functions registered after the runtime was loaded, which
+    // are added to the guest's linker before each module is loaded
+    pub(crate) added_host_functions: Vec<HostFunctionDefinition>,
+    // The functions loaded guests must export, see
+    // SandboxBuilder::with_required_exports
+    pub(crate) required_exports: Vec<RequiredExport>,
+    // How long guest calls may run for, see
+    // SandboxBuilder::with_guest_call_timeout
+    pub(crate) call_timeout: Option<Duration>,
+    // How long the guest may run for in each call, not counting time in
+    // host functions, see SandboxBuilder::with_guest_time_budget
+    pub(crate) guest_time_budget: Option<Duration>,
+    // Measures, and limits, the time spent in host functions during each
+    // call
+    pub(crate) host_clock: HostClock,
+    // The hooks run around guest calls and the host functions they call,
+    // see LoadedWasmSandbox::on_call_start
+    pub(crate) call_hooks: CallHooks,
+    // Keeps the errors host functions return to the guest, for the
+    // HostFunctionFailed errors of guest calls
+    pub(crate) host_errors: HostErrors,
+    // The re-entrant host functions running, forgotten when a guest call
+    // starts
+    pub(crate) reentrant_calls: ReentrantCalls,
+    // The key VecBytes parameters and results are encrypted with, see
+    // SandboxBuilder::with_payload_key
+    pub(crate) payload_key: Option<PayloadKey>,
+    // Tracks the peak memory used by the sandbox's guests, and records
+    // metrics of their memory if SandboxBuilder::with_memory_metrics was
+    // used
+    pub(crate) memory: MemoryTracker,
+    // The largest size of each linear memory and the most elements of
+    // each table of the guests, see SandboxBuilder::with_max_wasm_memory
+    // and SandboxBuilder::with_max_table_elements
+    pub(crate) max_wasm_memory: Option<u64>,
+    pub(crate) max_table_elements: Option<u64>,
+    // The most stack space wasm code may use, see
+    // SandboxBuilder::with_max_wasm_stack
+    pub(crate) max_wasm_stack: Option<u64>,
+    // Snapshots taken just after modules were loaded, see
+    // SandboxBuilder::with_module_cache
+    pub(crate) module_cache: ModuleCache,
+    // The policy loaded modules are checked against, see
+    // SandboxBuilder::with_validation_policy
+    pub(crate) validation_policy: Option<Policy>,
+    // The handles of the modules loaded with
+    // WasmSandbox::load_module_handle, kept so that their memory outlives
+    // its mappings into the sandbox and its snapshots
+    #[cfg(target_os = "linux")]
+    pub(crate) module_handles: Vec<ModuleHandle>,
+    // Whether the wasm context of guest crashes is written next to their
+    // core dumps, see SandboxBuilder::with_crashdump_enabled
+    #[cfg(feature = "crashdump")]
+    pub(crate) crashdump: bool,
+    // Precompiles guests that are not yet precompiled as they are loaded,
+    // unless disabled with SandboxBuilder::with_host_precompilation
+    #[cfg(feature = "aot")]
+    pub(crate) precompiler: Option<HostPrecompiler>,
+}
+
+// The id of the next sandbox whose runtime is loaded
+static NEXT_SANDBOX_ID: AtomicU64 = AtomicU64::new(1);
+
+/// A new id, unique within the process, for a sandbox whose runtime is
+/// being loaded
+pub(crate) fn next_sandbox_id() -> u64 {
+    NEXT_SANDBOX_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+impl SandboxContext {
+    /// Whether loaded modules are hashed, for usage accounting or
+    /// provenance
+    pub(crate) fn hash_modules(&self) -> bool {
+        self.usage_accounting || self.provenance.is_some()
+    }
+
+    /// Check `bytes` against the sandbox's validation policy, if it has
+    /// one, then precompile them on the host if they are a module or
+    /// component that is not yet precompiled, returning `None` if they
+    /// do not need precompiling
+    pub(crate) fn prepare(&self, bytes: &[u8]) -> Result<Option<Vec<u8>>> {
+        if let Some(policy) = &self.validation_policy {
+            validate::check(bytes, policy)?;
+        }
+        self.precompile(bytes)
+    }
+
+    /// Check the file at `file` against the sandbox's validation policy,
+    /// if it has one, then precompile it on the host if it is a module or
+    /// component that is not yet precompiled, returning `None` if it does
+    /// not need precompiling
+    pub(crate) fn prepare_file(&self, file: &Path) -> Result<Option<Vec<u8>>> {
+        if let Some(policy) = &self.validation_policy {
+            validate::check(&std::fs::read(file)?, policy)?;
+        }
+        self.precompile_file(file)
+    }
+
+    /// Precompile `bytes` on the host if they are a module or component
+    /// that is not yet precompiled, returning `None` if they do not
+    /// need precompiling
+    #[cfg(feature = "aot")]
+    fn precompile(&self, bytes: &[u8]) -> Result<Option<Vec<u8>>> {
+        match &self.precompiler {
+            Some(precompiler) => precompiler.precompile(bytes),
+            None => Ok(None),
+        }
+    }
+
+    /// Precompile `bytes` on the host if they are a module or component
+    /// that is not yet precompiled, returning `None` if they do not
+    /// need precompiling
+    #[cfg(not(feature = "aot"))]
+    fn precompile(&self, _bytes: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(None)
+    }
+
+    /// Precompile the file at `file` on the host if it is a module or
+    /// component that is not yet precompiled, returning `None` if it
+    /// does not need precompiling
+    #[cfg(feature = "aot")]
+    fn precompile_file(&self, file: &Path) -> Result<Option<Vec<u8>>> {
+        match &self.precompiler {
+            Some(precompiler) => precompiler.precompile_file(file),
+            None => Ok(None),
+        }
+    }
+
+    /// Precompile the file at `file` on the host if it is a module or
+    /// component that is not yet precompiled, returning `None` if it
+    /// does not need precompiling
+    #[cfg(not(feature = "aot"))]
+    fn precompile_file(&self, _file: &Path) -> Result<Option<Vec<u8>>> {
+        Ok(None)
+    }
+
+    /// Wrap `host_func`, registered under `name`, so that its panics are
+    /// handled, its errors kept and the time it takes measured
+    pub(crate) fn wrap<Args: CatchPanics<Output>, Output: SupportedReturnType>(
+        &self,
+        name: &str,
+        host_func: HostFunction<Output, Args>,
+    ) -> HostFunction<Output, Args> {
+        Args::wrap_calls(
+            Args::keep_errors(
+                Args::catch_panics(host_func, self.panic_handler.on_panic(name)),
+                self.host_errors.on_error(name),
+            ),
+            self.call_hooks
+                .on_host_call(name, self.host_clock.on_call()),
+        )
+    }
+
+    /// Register `host_func` under `name` on `inner`, a sandbox whose
+    /// runtime is already loaded, so that modules loaded afterwards can
+    /// import it
+    pub(crate) fn register<Args: CatchPanics<Output>, Output: SupportedReturnType>(
+        &mut self,
+        inner: &mut MultiUseSandbox,
+        name: &str,
+        host_func: HostFunction<Output, Args>,
+    ) -> Result<()> {
+        let host_func = self.wrap(name, host_func);
+        inner.register_host_function(name, host_func.clone())?;
+        self.host_functions.insert(name, host_func);
+        self.module_cache.clear();
+
+        self.added_host_functions
+            .retain(|definition| definition.function_name != name);
+        self.added_host_functions.push(HostFunctionDefinition {
+            function_name: name.to_string(),
+            parameter_types: Some(Args::TYPE.to_vec()),
+            return_type: Output::TYPE,
+        });
+        if let Some(provenance) = &mut self.provenance {
+            provenance.add_host_function(ManifestFunction::new(
+                name,
+                Args::TYPE.to_vec(),
+                Output::TYPE,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Add the host functions registered after the runtime was loaded
+    /// to the guest's linker. The runtime snapshot predates them, so
+    /// this is needed each time a module is loaded.
+    pub(crate) fn add_host_functions(&self, inner: &mut MultiUseSandbox) -> Result<()> {
+        if self.added_host_functions.is_empty() {
+            return Ok(());
+        }
+        let details = HostFunctionDetails {
+            host_functions: Some(self.added_host_functions.clone()),
+        };
+        let bytes: Vec<u8> = (&details)
+            .try_into()
+            .map_err(|e| new_error!("Failed to serialize host function details: {:?}", e))?;
+        let res: i32 = inner.call("AddHostFunctions", bytes)?;
+        if res != 0 {
+            return Err(new_error!(
+                "AddHostFunctions Failed with error code {:?}",
+                res
+            ));
+        }
+        Ok(())
+    }
+}
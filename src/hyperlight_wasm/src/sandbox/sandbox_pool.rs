@@ -0,0 +1,222 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::sync::Mutex;
+
+use hyperlight_host::{Result, new_error};
+
+use super::loaded_wasm_sandbox::LoadedWasmSandbox;
+use super::metrics::{
+    METRIC_POOL_CHECKINS, METRIC_POOL_CHECKOUTS, METRIC_POOL_IDLE_SANDBOXES, METRIC_POOL_MISSES,
+};
+use super::wasm_sandbox::WasmSandbox;
+
+type SandboxFactory = dyn Fn() -> Result<WasmSandbox> + Send + Sync;
+
+/// A pool of [`WasmSandbox`]es with the runtime already loaded, so that
+/// modules can be loaded without waiting for a sandbox to be built.
+///
+/// The pool is built with a function that builds each sandbox, which
+/// registers the host functions modules need, and keeps up to
+/// `capacity` idle sandboxes. Sandboxes are taken from the pool with
+/// [`checkout`](Self::checkout) and given back with
+/// [`checkin`](Self::checkin), which unloads their module. A pool can be
+/// shared between threads, for example in an `Arc`.
+///
+/// The pool records the following metrics:
+/// - `wasm_sandbox_pool_idle_sandboxes`: the number of idle sandboxes
+///   across all pools
+/// - `wasm_sandbox_pool_checkouts_total`: the number of sandboxes checked
+///   out
+/// - `wasm_sandbox_pool_misses_total`: the number of checkouts that found
+///   the pool empty and built a new sandbox
+/// - `wasm_sandbox_pool_checkins_total`: the number of sandboxes checked
+///   back in
+pub struct SandboxPool {
+    idle: Mutex<Vec<WasmSandbox>>,
+    capacity: usize,
+    factory: Box<SandboxFactory>,
+}
+
+impl SandboxPool {
+    /// Create a pool of `capacity` sandboxes, each built by `factory`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error returned by `factory`.
+    pub fn new(
+        capacity: usize,
+        factory: impl Fn() -> Result<WasmSandbox> + Send + Sync + 'static,
+    ) -> Result<Self> {
+        let pool = SandboxPool {
+            idle: Mutex::new(Vec::with_capacity(capacity)),
+            capacity,
+            factory: Box::new(factory),
+        };
+        pool.refill()?;
+        Ok(pool)
+    }
+
+    /// The number of idle sandboxes the pool keeps
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of idle sandboxes in the pool
+    pub fn idle(&self) -> Result<usize> {
+        Ok(self.lock()?.len())
+    }
+
+    /// Take an idle sandbox from the pool, or build a new one if the pool
+    /// is empty.
+    pub fn checkout(&self) -> Result<WasmSandbox> {
+        metrics::counter!(METRIC_POOL_CHECKOUTS).increment(1);
+        if let Some(sandbox) = self.lock()?.pop() {
+            metrics::gauge!(METRIC_POOL_IDLE_SANDBOXES).decrement(1);
+            return Ok(sandbox);
+        }
+        metrics::counter!(METRIC_POOL_MISSES).increment(1);
+        (self.factory)()
+    }
+
+    /// Unload the module from `sandbox` and give it back to the pool. If
+    /// the pool is already full, the sandbox is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the module cannot be unloaded, in which case
+    /// the sandbox is dropped.
+    pub fn checkin(&self, sandbox: LoadedWasmSandbox) -> Result<()> {
+        self.checkin_unloaded(sandbox.unload_module()?)
+    }
+
+    /// Give a sandbox that has no module loaded back to the pool. If the
+    /// pool is already full, the sandbox is dropped.
+    pub fn checkin_unloaded(&self, sandbox: WasmSandbox) -> Result<()> {
+        metrics::counter!(METRIC_POOL_CHECKINS).increment(1);
+        let mut idle = self.lock()?;
+        if idle.len() < self.capacity {
+            idle.push(sandbox);
+            metrics::gauge!(METRIC_POOL_IDLE_SANDBOXES).increment(1);
+        }
+        Ok(())
+    }
+
+    /// Build sandboxes until the pool holds `capacity` idle sandboxes,
+    /// for example after sandboxes that failed to unload were dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error returned by the pool's factory, keeping
+    /// the sandboxes built before it.
+    pub fn refill(&self) -> Result<()> {
+        while self.idle()? < self.capacity {
+            // Build outside the lock so that checkouts are not held up
+            let sandbox = (self.factory)()?;
+            let mut idle = self.lock()?;
+            if idle.len() < self.capacity {
+                idle.push(sandbox);
+                metrics::gauge!(METRIC_POOL_IDLE_SANDBOXES).increment(1);
+            }
+        }
+        Ok(())
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, Vec<WasmSandbox>>> {
+        self.idle
+            .lock()
+            .map_err(|e| new_error!("Error locking sandbox pool: {}", e))
+    }
+}
+
+impl std::fmt::Debug for SandboxPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SandboxPool")
+            .field("capacity", &self.capacity)
+            .finish()
+    }
+}
+
+impl Drop for SandboxPool {
+    fn drop(&mut self) {
+        let idle = match self.idle.get_mut() {
+            Ok(idle) => idle.len(),
+            Err(e) => e.into_inner().len(),
+        };
+        metrics::gauge!(METRIC_POOL_IDLE_SANDBOXES).decrement(idle as f64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    use examples_common::get_wasm_module_path;
+
+    use super::SandboxPool;
+    use crate::SandboxBuilder;
+
+    #[test]
+    fn test_sandbox_pool() {
+        let built = Arc::new(AtomicUsize::new(0));
+        let pool = {
+            let built = built.clone();
+            Arc::new(
+                SandboxPool::new(2, move || {
+                    built.fetch_add(1, Ordering::Relaxed);
+                    let mut proto_wasm_sandbox = SandboxBuilder::new().build()?;
+                    proto_wasm_sandbox.register("TestHostFunc", |a: i32| Ok(a))?;
+                    proto_wasm_sandbox.load_runtime()
+                })
+                .unwrap(),
+            )
+        };
+        assert_eq!(pool.idle().unwrap(), 2);
+        assert_eq!(built.load(Ordering::Relaxed), 2);
+
+        let handles: Vec<_> = (0..3)
+            .map(|i: i32| {
+                let pool = pool.clone();
+                thread::spawn(move || {
+                    let mut loaded_wasm_sandbox = pool
+                        .checkout()
+                        .unwrap()
+                        .load_module(get_wasm_module_path("rust_wasm_samples.aot").unwrap())
+                        .unwrap();
+                    let res: i32 = loaded_wasm_sandbox
+                        .call_guest_function("call_host_function", i)
+                        .unwrap();
+                    assert_eq!(res, i);
+                    loaded_wasm_sandbox
+                })
+            })
+            .collect();
+        let loaded: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+        // The third checkout found the pool empty and built a sandbox
+        assert_eq!(pool.idle().unwrap(), 0);
+        assert_eq!(built.load(Ordering::Relaxed), 3);
+
+        // Only as many sandboxes as the pool's capacity are kept
+        for loaded_wasm_sandbox in loaded {
+            pool.checkin(loaded_wasm_sandbox).unwrap();
+        }
+        assert_eq!(pool.idle().unwrap(), 2);
+        assert_eq!(built.load(Ordering::Relaxed), 3);
+    }
+}
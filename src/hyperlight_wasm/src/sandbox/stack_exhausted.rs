@@ -0,0 +1,137 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::fmt;
+
+use hyperlight_host::HyperlightError;
+
+use super::guest_trap::{GuestTrap, TrapCode, TrapFrame};
+use super::sandbox_context::SandboxContext;
+
+/// wasmtime's default for `max_wasm_stack`, used unless the sandbox was
+/// built with
+/// [`SandboxBuilder::with_max_wasm_stack`](crate::SandboxBuilder::with_max_wasm_stack)
+const DEFAULT_MAX_WASM_STACK: u64 = 512 * 1024;
+
+/// The error returned by a guest call that exhausted the wasm stack, for
+/// example by recursing too deeply, rather than the [`GuestTrap`] other
+/// traps return.
+///
+/// The stack is limited by
+/// [`SandboxBuilder::with_max_wasm_stack`](crate::SandboxBuilder::with_max_wasm_stack).
+/// The trap happened inside the guest, so the sandbox is not poisoned.
+///
+/// It is returned as a [`HyperlightError::AnyhowError`], from which
+/// [`from_error`](Self::from_error) extracts it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StackExhausted {
+    /// The name of the guest function that was called
+    pub function_name: String,
+    /// The most stack space, in bytes, wasm code could use
+    pub max_wasm_stack: u64,
+    /// The wasm backtrace of the trap, innermost frame first
+    pub frames: Vec<TrapFrame>,
+}
+
+impl StackExhausted {
+    /// The stack exhaustion that ended the call that failed with `error`,
+    /// or `None` if the call failed for another reason
+    pub fn from_error(error: &HyperlightError) -> Option<&Self> {
+        match error {
+            HyperlightError::AnyhowError(e) => e.downcast_ref(),
+            _ => None,
+        }
+    }
+
+    /// The stack exhaustion that ended the call to `function_name` that
+    /// failed with the guest error `message`, or `None` if it failed for
+    /// another reason
+    pub(crate) fn from_guest_error(
+        message: &str,
+        function_name: &str,
+        context: &SandboxContext,
+    ) -> Option<Self> {
+        let trap = GuestTrap::from_guest_error(message, function_name)?;
+        (trap.code == TrapCode::StackOverflow).then(|| Self {
+            function_name: trap.function_name,
+            max_wasm_stack: context.max_wasm_stack.unwrap_or(DEFAULT_MAX_WASM_STACK),
+            frames: trap.frames,
+        })
+    }
+}
+
+impl fmt::Display for StackExhausted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "guest function {} exhausted the wasm stack of {} bytes",
+            self.function_name, self.max_wasm_stack
+        )?;
+        if let Some(frame) = self.frames.first() {
+            write!(f, " in ")?;
+            match &frame.func_name {
+                Some(name) => write!(f, "{}", name)?,
+                None => write!(f, "<wasm function {}>", frame.func_index)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for StackExhausted {}
+
+impl From<StackExhausted> for HyperlightError {
+    fn from(exhausted: StackExhausted) -> Self {
+        HyperlightError::AnyhowError(anyhow::Error::new(exhausted))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyperlight_host::HyperlightError;
+    use hyperlight_wasm_runtime::runtime_config::WASM_TRAP;
+
+    use super::StackExhausted;
+    use crate::sandbox::sandbox_context::SandboxContext;
+
+    #[test]
+    fn test_stack_exhausted_from_error() {
+        let context = SandboxContext {
+            max_wasm_stack: Some(64 * 1024),
+            ..Default::default()
+        };
+        let message = format!("{}StackOverflow\n2 40 recurse\n2 40 recurse", WASM_TRAP);
+        let exhausted = StackExhausted::from_guest_error(&message, "Recurse", &context).unwrap();
+        assert_eq!(exhausted.function_name, "Recurse");
+        assert_eq!(exhausted.max_wasm_stack, 64 * 1024);
+        assert_eq!(exhausted.frames.len(), 2);
+        assert_eq!(
+            exhausted.to_string(),
+            "guest function Recurse exhausted the wasm stack of 65536 bytes in recurse"
+        );
+
+        let error = HyperlightError::from(exhausted.clone());
+        assert_eq!(StackExhausted::from_error(&error), Some(&exhausted));
+
+        // Other traps are not stack exhaustion
+        let message = format!("{}MemoryOutOfBounds\n3 120 read_byte", WASM_TRAP);
+        assert!(StackExhausted::from_guest_error(&message, "ReadByte", &context).is_none());
+        let context = SandboxContext::default();
+        let message = format!("{}StackOverflow", WASM_TRAP);
+        let exhausted = StackExhausted::from_guest_error(&message, "Recurse", &context).unwrap();
+        assert_eq!(exhausted.max_wasm_stack, 512 * 1024);
+    }
+}
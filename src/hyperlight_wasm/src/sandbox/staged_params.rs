@@ -0,0 +1,86 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::marker::PhantomData;
+
+use hyperlight_host::func::{ParameterTuple, ParameterType, ParameterValue};
+use hyperlight_host::{MultiUseSandbox, Result};
+
+/// The size of the chunks `VecBytes` parameters are staged in, which
+/// fits in the smallest input buffer a sandbox can be built with
+pub(crate) const CHUNK_SIZE: usize = 64 * 1024;
+
+/// The guest function that appends a chunk to a staged parameter
+const STAGE_PARAMETER_FUNCTION: &str = "StageParameter";
+
+/// The guest function that discards any parameters staged for a call
+/// that was never made
+const CLEAR_STAGED_PARAMETERS_FUNCTION: &str = "ClearStagedParameters";
+
+/// Pass the `VecBytes` values in `values` that are longer than
+/// [`CHUNK_SIZE`] to the guest in chunks, returning the parameters of
+/// the call with those values replaced by empty placeholders, which the
+/// guest replaces with the staged values.
+pub(crate) fn stage<P: ParameterTuple>(
+    inner: &mut MultiUseSandbox,
+    values: Vec<ParameterValue>,
+) -> Result<StagedParams<P>> {
+    inner.call::<()>(CLEAR_STAGED_PARAMETERS_FUNCTION, ())?;
+    let values = values
+        .into_iter()
+        .enumerate()
+        .map(|(index, value)| match value {
+            ParameterValue::VecBytes(bytes) if bytes.len() > CHUNK_SIZE => {
+                for chunk in bytes.chunks(CHUNK_SIZE) {
+                    inner.call::<()>(STAGE_PARAMETER_FUNCTION, (index as i32, chunk.to_vec()))?;
+                }
+                Ok(ParameterValue::VecBytes(Vec::new()))
+            }
+            value => Ok(value),
+        })
+        .collect::<Result<_>>()?;
+    Ok(StagedParams {
+        values,
+        params: PhantomData,
+    })
+}
+
+/// The parameters of a guest call, with their large `VecBytes` values
+/// staged in the guest
+#[derive(Clone)]
+pub(crate) struct StagedParams<P> {
+    values: Vec<ParameterValue>,
+    params: PhantomData<fn() -> P>,
+}
+
+impl<P: ParameterTuple> ParameterTuple for StagedParams<P> {
+    const SIZE: usize = P::SIZE;
+
+    const TYPE: &[ParameterType] = P::TYPE;
+
+    fn into_value(self) -> Vec<ParameterValue> {
+        self.values
+    }
+
+    fn from_value(
+        values: Vec<ParameterValue>,
+    ) -> std::result::Result<Self, hyperlight_common::func::Error> {
+        Ok(Self {
+            values,
+            params: PhantomData,
+        })
+    }
+}
@@ -0,0 +1,178 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use hyperlight_host::{Result, new_error};
+
+/// The value of a state cell, see [`StateCells`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StateCellValue {
+    /// An unsigned 64-bit integer, for flags and counters
+    U64(u64),
+    /// A byte buffer
+    Bytes(Vec<u8>),
+}
+
+impl StateCellValue {
+    fn type_name(&self) -> &'static str {
+        match self {
+            StateCellValue::U64(_) => "u64",
+            StateCellValue::Bytes(_) => "bytes",
+        }
+    }
+}
+
+impl From<u64> for StateCellValue {
+    fn from(value: u64) -> Self {
+        StateCellValue::U64(value)
+    }
+}
+
+impl From<Vec<u8>> for StateCellValue {
+    fn from(value: Vec<u8>) -> Self {
+        StateCellValue::Bytes(value)
+    }
+}
+
+impl From<&[u8]> for StateCellValue {
+    fn from(value: &[u8]) -> Self {
+        StateCellValue::Bytes(value.to_vec())
+    }
+}
+
+/// Named, typed values shared between the host and the guests of a
+/// sandbox, declared with
+/// [`ProtoWasmSandbox::declare_state_cell`](crate::ProtoWasmSandbox::declare_state_cell).
+///
+/// Both sides can read and write a cell between guest calls, and the host
+/// can do so from another thread while a call is running, for example to
+/// set a "shutdown requested" flag that the guest polls or to read a
+/// progress counter that it updates. Modules access cells through the
+/// `hlwasm:state` import module.
+///
+/// The cells are held by the host, so they are not part of the sandbox's
+/// snapshots: restoring a snapshot leaves them unchanged.
+///
+/// Handles are cheap to clone and all refer to the same cells.
+#[derive(Clone, Debug, Default)]
+pub struct StateCells {
+    cells: Arc<Mutex<HashMap<String, StateCellValue>>>,
+}
+
+impl StateCells {
+    pub(super) fn declare(&self, name: &str, initial: StateCellValue) -> Result<()> {
+        let mut cells = self
+            .cells
+            .lock()
+            .map_err(|e| new_error!("Error locking state cells: {:?}", e))?;
+        if cells.contains_key(name) {
+            return Err(new_error!("state cell {} is already declared", name));
+        }
+        cells.insert(name.to_string(), initial);
+        Ok(())
+    }
+
+    /// Read the value of the cell `name`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no cell named `name` has been declared.
+    pub fn get(&self, name: &str) -> Result<StateCellValue> {
+        let cells = self
+            .cells
+            .lock()
+            .map_err(|e| new_error!("Error locking state cells: {:?}", e))?;
+        cells
+            .get(name)
+            .cloned()
+            .ok_or_else(|| new_error!("state cell {} is not declared", name))
+    }
+
+    /// Set the value of the cell `name`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no cell named `name` has been declared, or if
+    /// `value` is not of the type the cell was declared with.
+    pub fn set(&self, name: &str, value: impl Into<StateCellValue>) -> Result<()> {
+        let value = value.into();
+        let mut cells = self
+            .cells
+            .lock()
+            .map_err(|e| new_error!("Error locking state cells: {:?}", e))?;
+        let cell = cells
+            .get_mut(name)
+            .ok_or_else(|| new_error!("state cell {} is not declared", name))?;
+        if std::mem::discriminant(cell) != std::mem::discriminant(&value) {
+            return Err(new_error!(
+                "state cell {} holds {}, not {}",
+                name,
+                cell.type_name(),
+                value.type_name()
+            ));
+        }
+        *cell = value;
+        Ok(())
+    }
+
+    pub(super) fn get_u64(&self, name: &str) -> Result<u64> {
+        match self.get(name)? {
+            StateCellValue::U64(value) => Ok(value),
+            value => Err(new_error!(
+                "state cell {} holds {}, not u64",
+                name,
+                value.type_name()
+            )),
+        }
+    }
+
+    pub(super) fn get_bytes(&self, name: &str) -> Result<Vec<u8>> {
+        match self.get(name)? {
+            StateCellValue::Bytes(value) => Ok(value),
+            value => Err(new_error!(
+                "state cell {} holds {}, not bytes",
+                name,
+                value.type_name()
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{StateCellValue, StateCells};
+
+    #[test]
+    fn test_state_cells() {
+        let cells = StateCells::default();
+        cells.declare("flag", 0u64.into()).unwrap();
+        cells.declare("data", b"abc".as_slice().into()).unwrap();
+        assert!(cells.declare("flag", 1u64.into()).is_err());
+
+        let handle = cells.clone();
+        handle.set("flag", 1u64).unwrap();
+        assert_eq!(cells.get("flag").unwrap(), StateCellValue::U64(1));
+        assert_eq!(cells.get_u64("flag").unwrap(), 1);
+        assert_eq!(cells.get_bytes("data").unwrap(), b"abc");
+
+        assert!(cells.set("flag", vec![1u8]).is_err());
+        assert!(cells.get_u64("data").is_err());
+        assert!(cells.get("missing").is_err());
+        assert!(cells.set("missing", 1u64).is_err());
+    }
+}
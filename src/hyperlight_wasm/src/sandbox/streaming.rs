@@ -0,0 +1,94 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::sync::{Arc, Mutex};
+
+use hyperlight_host::{Result, new_error};
+
+/// The result of a guest call made with
+/// [`LoadedWasmSandbox::call_guest_function_streaming`](crate::LoadedWasmSandbox::call_guest_function_streaming):
+/// an iterator over the chunks of output the guest function emitted, in
+/// the order it emitted them, together with the value it returned.
+#[derive(Debug)]
+pub struct StreamedCall<Output> {
+    value: Output,
+    chunks: std::vec::IntoIter<Vec<u8>>,
+}
+
+impl<Output> StreamedCall<Output> {
+    pub(crate) fn new(value: Output, chunks: Vec<Vec<u8>>) -> Self {
+        Self {
+            value,
+            chunks: chunks.into_iter(),
+        }
+    }
+
+    /// The value returned by the guest function
+    pub fn value(&self) -> &Output {
+        &self.value
+    }
+
+    /// The value returned by the guest function, discarding any chunks
+    /// that have not been read
+    pub fn into_value(self) -> Output {
+        self.value
+    }
+}
+
+impl<Output> Iterator for StreamedCall<Output> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+        self.chunks.next()
+    }
+}
+
+/// Collects the chunks emitted by a guest function during a streaming
+/// call. Chunks emitted during other calls are discarded.
+#[derive(Clone, Default)]
+pub(crate) struct ChunkSink(Arc<Mutex<Option<Vec<Vec<u8>>>>>);
+
+impl ChunkSink {
+    /// Start collecting chunks
+    pub(crate) fn start(&self) -> Result<()> {
+        *self
+            .0
+            .lock()
+            .map_err(|e| new_error!("Error locking chunk sink: {:?}", e))? = Some(Vec::new());
+        Ok(())
+    }
+
+    /// Collect `chunk`, if chunks are being collected
+    pub(crate) fn push(&self, chunk: Vec<u8>) -> Result<()> {
+        let mut chunks = self
+            .0
+            .lock()
+            .map_err(|e| new_error!("Error locking chunk sink: {:?}", e))?;
+        if let Some(chunks) = chunks.as_mut() {
+            chunks.push(chunk);
+        }
+        Ok(())
+    }
+
+    /// Stop collecting chunks, returning those collected
+    pub(crate) fn finish(&self) -> Vec<Vec<u8>> {
+        self.0
+            .lock()
+            .ok()
+            .and_then(|mut chunks| chunks.take())
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,119 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use hyperlight_host::{Result, new_error};
+use hyperlight_wasm_runtime::wasi_fs;
+
+/// The files of the in-memory filesystem provided with
+/// [`SandboxBuilder::with_vfs`](crate::SandboxBuilder::with_vfs), which
+/// the guest copies when the runtime is loaded
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Vfs {
+    files: Vec<(String, Vec<u8>)>,
+}
+
+impl Vfs {
+    /// Add `files`, each a path in the guest and its contents
+    pub(crate) fn add(&mut self, files: Vec<(String, Vec<u8>)>) {
+        self.files.extend(files);
+    }
+
+    /// Check that the paths are absolute and distinct and that none of
+    /// them is a directory of another, and normalize them
+    pub(crate) fn open(&self) -> Result<Self> {
+        let mut files: Vec<(String, Vec<u8>)> = Vec::with_capacity(self.files.len());
+        for (path, contents) in &self.files {
+            let normalized = wasi_fs::normalize_path(path)
+                .filter(|normalized| normalized.starts_with('/') && normalized != "/")
+                .ok_or_else(|| new_error!("invalid path {:?} in the in-memory filesystem", path))?;
+            if let Some((other, _)) = files.iter().find(|(other, _)| {
+                other == &normalized
+                    || wasi_fs::relative_path(other, &normalized).is_some()
+                    || wasi_fs::relative_path(&normalized, other).is_some()
+            }) {
+                return Err(new_error!(
+                    "paths {:?} and {:?} in the in-memory filesystem conflict",
+                    other,
+                    path
+                ));
+            }
+            files.push((normalized, contents.clone()));
+        }
+        Ok(Self { files })
+    }
+
+    /// The paths and sizes of the files, encoded for the guest
+    pub(crate) fn listing(&self) -> Vec<u8> {
+        wasi_fs::encode_vfs_files(
+            self.files
+                .iter()
+                .map(|(path, contents)| (path.as_str(), contents.len() as u64)),
+        )
+    }
+
+    /// At most `len` bytes of the file at `index` from `offset`
+    pub(crate) fn read(&self, index: i32, offset: u64, len: i32) -> Result<Vec<u8>> {
+        let (_, contents) = usize::try_from(index)
+            .ok()
+            .and_then(|index| self.files.get(index))
+            .ok_or_else(|| new_error!("no file {} in the in-memory filesystem", index))?;
+        let start = usize::try_from(offset)
+            .unwrap_or(usize::MAX)
+            .min(contents.len());
+        let end = start
+            .saturating_add(usize::try_from(len).unwrap_or(0))
+            .min(contents.len());
+        Ok(contents[start..end].to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyperlight_wasm_runtime::wasi_fs;
+
+    use super::Vfs;
+
+    #[test]
+    fn test_vfs() {
+        let mut vfs = Vfs::default();
+        vfs.add(vec![
+            ("/data/config.json".to_string(), b"{}".to_vec()),
+            ("/data/./words.txt".to_string(), b"hello world".to_vec()),
+        ]);
+        let vfs = vfs.open().unwrap();
+        assert_eq!(
+            wasi_fs::decode_vfs_files(&vfs.listing()).unwrap(),
+            [
+                ("/data/config.json".to_string(), 2),
+                ("/data/words.txt".to_string(), 11)
+            ]
+        );
+        assert_eq!(vfs.read(1, 6, 100).unwrap(), b"world");
+        assert_eq!(vfs.read(1, 100, 100).unwrap(), b"");
+        assert!(vfs.read(2, 0, 100).is_err());
+
+        for conflicting in [
+            vec![("relative.txt".to_string(), vec![])],
+            vec![("/".to_string(), vec![])],
+            vec![("/a".to_string(), vec![]), ("/a/".to_string(), vec![])],
+            vec![("/a".to_string(), vec![]), ("/a/b".to_string(), vec![])],
+        ] {
+            let mut vfs = Vfs::default();
+            vfs.add(conflicting);
+            assert!(vfs.open().is_err());
+        }
+    }
+}
@@ -0,0 +1,126 @@
+/*
+Copyright 2025 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// The clock seen by the guests of a sandbox, see
+/// [`SandboxBuilder::with_virtual_time`](crate::SandboxBuilder::with_virtual_time)
+/// and [`SandboxBuilder::with_frozen_time`](crate::SandboxBuilder::with_frozen_time).
+///
+/// Virtual time starts `offset` ahead of the host's clock when the
+/// sandbox is built, and then advances `scale` times as fast as the
+/// host's clock. Frozen time always reads the same wall-clock time, and
+/// its monotonic clock stays at zero. The built-in time shims (the WASI `clock_time_get`
+/// function and the default `GetTimeSinceBootMicrosecond` host function)
+/// read this clock. Hosts implementing other time functions, such as
+/// `wasi:clocks` for components, can read it with
+/// [`ProtoWasmSandbox::clock`](crate::ProtoWasmSandbox::clock).
+#[derive(Clone, Copy, Debug)]
+pub struct VirtualClock {
+    origin: Instant,
+    origin_wall: SystemTime,
+    offset: Duration,
+    scale: f64,
+}
+
+impl VirtualClock {
+    pub(super) fn new(offset: Duration, scale: f64) -> Self {
+        Self {
+            origin: Instant::now(),
+            origin_wall: SystemTime::now(),
+            offset,
+            scale,
+        }
+    }
+
+    /// A clock that is stopped at the wall-clock time `at`
+    pub(super) fn frozen(at: SystemTime) -> Self {
+        Self {
+            origin: Instant::now(),
+            origin_wall: at,
+            offset: Duration::ZERO,
+            scale: 0.0,
+        }
+    }
+
+    /// The virtual time that has passed since the sandbox was built,
+    /// plus the offset. This is the time used for monotonic clocks.
+    pub fn elapsed(&self) -> Duration {
+        self.offset + self.origin.elapsed().mul_f64(self.scale)
+    }
+
+    /// The current virtual wall-clock time
+    pub fn now(&self) -> SystemTime {
+        self.origin_wall + self.elapsed()
+    }
+
+    /// The current virtual wall-clock time, in microseconds since the
+    /// Unix epoch, as returned by the default `GetTimeSinceBootMicrosecond`
+    /// host function
+    pub(super) fn now_micros(&self) -> i64 {
+        let since_epoch = self.now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        i64::try_from(since_epoch.as_micros()).unwrap_or(i64::MAX)
+    }
+
+    /// The value of WASI clock `clock_id` in nanoseconds, or `None` if
+    /// the clock is not supported. CPU time clocks are treated as
+    /// monotonic clocks.
+    pub(super) fn wasi_clock_nanos(&self, clock_id: i32) -> Option<i64> {
+        let time = match clock_id {
+            // realtime
+            0 => self.now().duration_since(UNIX_EPOCH).unwrap_or_default(),
+            // monotonic, process_cputime_id and thread_cputime_id
+            1..=3 => self.elapsed(),
+            _ => return None,
+        };
+        Some(i64::try_from(time.as_nanos()).unwrap_or(i64::MAX))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::VirtualClock;
+
+    #[test]
+    fn test_virtual_clock_offset_and_scale() {
+        let offset = Duration::from_secs(3600);
+        let clock = VirtualClock::new(offset, 1000.0);
+        let before = SystemTime::now();
+        std::thread::sleep(Duration::from_millis(10));
+
+        // 10ms of host time is at least 10s of virtual time
+        assert!(clock.elapsed() >= offset + Duration::from_secs(10));
+        assert!(clock.now() >= before + offset + Duration::from_secs(10));
+        let monotonic = clock.wasi_clock_nanos(1).unwrap();
+        assert!(monotonic >= (offset + Duration::from_secs(10)).as_nanos() as i64);
+        assert_eq!(clock.wasi_clock_nanos(4), None);
+    }
+
+    #[test]
+    fn test_virtual_clock_frozen() {
+        let at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let clock = VirtualClock::frozen(at);
+        std::thread::sleep(Duration::from_millis(10));
+
+        assert_eq!(clock.now(), at);
+        assert_eq!(clock.elapsed(), Duration::ZERO);
+        assert_eq!(clock.now_micros(), 1_700_000_000_000_000);
+        assert_eq!(clock.wasi_clock_nanos(0), Some(1_700_000_000_000_000_000));
+        assert_eq!(clock.wasi_clock_nanos(1), Some(0));
+    }
+}
@@ -0,0 +1,238 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use hyperlight_host::{HyperlightError, MultiUseSandbox, Result, new_error};
+use hyperlight_wasm_runtime::guest_functions;
+use hyperlight_wasm_runtime::linear_memories::{self, GET_LINEAR_MEMORIES_FUNCTION, LinearMemory};
+
+use super::guest_aborted::GuestAborted;
+use super::guest_trap::{GuestTrap, TrapCode, TrapFrame};
+use super::stack_exhausted::StackExhausted;
+
+/// The version of the format of the wasm context files, increased when
+/// fields are removed or change meaning
+const FORMAT_VERSION: u32 = 1;
+
+/// The wasm context of a loaded module, written next to the core dumps of
+/// its sandbox so that tooling can make sense of them: the module's
+/// exports, and where its linear memories are in the dumped guest memory.
+///
+/// The context is fetched when the module is loaded, since the guest
+/// cannot be called once the VM has crashed, and the linear memories are
+/// fetched again whenever the guest can still be called.
+#[derive(Debug, Default)]
+pub(crate) struct WasmCrashContext {
+    // The names of the functions the module exports
+    exports: Vec<String>,
+    // Where the module's linear memories are in the guest
+    linear_memories: Vec<LinearMemory>,
+}
+
+impl WasmCrashContext {
+    /// Fetch the context of the module loaded in `inner`
+    pub(crate) fn fetch(inner: &mut MultiUseSandbox) -> Result<Self> {
+        let exports: Vec<u8> = inner.call("GetGuestFunctions", ())?;
+        let exports = guest_functions::from_bytes(&exports)
+            .ok_or_else(|| new_error!("Failed to decode the guest's functions"))?;
+        let mut context = Self {
+            exports: exports.into_iter().map(|function| function.name).collect(),
+            linear_memories: Vec::new(),
+        };
+        context.fetch_linear_memories(inner)?;
+        Ok(context)
+    }
+
+    fn fetch_linear_memories(&mut self, inner: &mut MultiUseSandbox) -> Result<()> {
+        let memories: Vec<u8> = inner.call(GET_LINEAR_MEMORIES_FUNCTION, ())?;
+        self.linear_memories = linear_memories::from_bytes(&memories)
+            .ok_or_else(|| new_error!("Failed to decode the guest's linear memories"))?;
+        Ok(())
+    }
+
+    /// Write the context of the guest call to `function_name` that
+    /// failed with `error` next to the core dump of the crash, if it was
+    /// a crash. A VM crash is dumped by hyperlight-host as it happens,
+    /// while traps, which the runtime survives, are dumped here.
+    pub(crate) fn record_failed_call(
+        &mut self,
+        inner: &mut MultiUseSandbox,
+        module_hash: Option<&str>,
+        function_name: &str,
+        error: &HyperlightError,
+        since: SystemTime,
+    ) {
+        let trapped = trap_of(error).is_some()
+            || GuestAborted::from_error(error).is_some_and(|aborted| aborted.exit_code.is_none());
+        if !inner.poisoned() {
+            if !trapped {
+                return;
+            }
+            if let Err(e) = self.generate(inner, None) {
+                tracing::error!("Failed to generate a core dump of the trap: {e}");
+                return;
+            }
+        }
+        // A poisoning failure that is not dumped, such as the call being
+        // cancelled, has no context to go with it
+        let Some(core_dump) = newest_core_dump(&dump_dir(None), since) else {
+            return;
+        };
+        let call = Some((function_name, error));
+        if let Err(e) = self.write(&core_dump, module_hash, call) {
+            tracing::error!("Failed to write the wasm context of {core_dump:?}: {e}");
+        }
+    }
+
+    /// Generate a core dump of `inner` in `dir`, or where hyperlight-host
+    /// puts core dumps if `None`, and write the context next to it
+    pub(crate) fn dump(
+        &mut self,
+        inner: &mut MultiUseSandbox,
+        module_hash: Option<&str>,
+        dir: Option<String>,
+    ) -> Result<PathBuf> {
+        let since = SystemTime::now();
+        let dir = self.generate(inner, dir)?;
+        let core_dump = newest_core_dump(&dir, since)
+            .ok_or_else(|| new_error!("No core dump was generated in {:?}", dir))?;
+        self.write(&core_dump, module_hash, None)
+    }
+
+    // Generate a core dump of `inner`, returning the directory it is in
+    fn generate(&mut self, inner: &mut MultiUseSandbox, dir: Option<String>) -> Result<PathBuf> {
+        if let Err(e) = self.fetch_linear_memories(inner) {
+            tracing::warn!("Failed to fetch the guest's linear memories: {e}");
+        }
+        let dir = dump_dir(dir);
+        inner.generate_crashdump_to_dir(dir.to_string_lossy())?;
+        Ok(dir)
+    }
+
+    // Write the context next to `core_dump`, as `<core dump>.wasm.json`
+    fn write(
+        &self,
+        core_dump: &Path,
+        module_hash: Option<&str>,
+        call: Option<(&str, &HyperlightError)>,
+    ) -> Result<PathBuf> {
+        let trap = call.and_then(|(_, error)| trap_of(error));
+        let json = serde_json::json!({
+            "version": FORMAT_VERSION,
+            "core_dump": core_dump.file_name().map(|name| name.to_string_lossy()),
+            "module_hash": module_hash,
+            "exports": self.exports,
+            "linear_memories": self.linear_memories.iter().map(|memory| serde_json::json!({
+                "name": memory.name,
+                "guest_addr": memory.guest_addr,
+                "size": memory.size,
+            })).collect::<Vec<_>>(),
+            "function_name": call.map(|(function_name, _)| function_name),
+            "error": call.map(|(_, error)| error.to_string()),
+            "trap": trap.map(|(code, frames)| serde_json::json!({
+                "code": code.name(),
+                "frames": frames.iter().map(|frame| serde_json::json!({
+                    "func_index": frame.func_index,
+                    "module_offset": frame.module_offset,
+                    "func_name": frame.func_name,
+                })).collect::<Vec<_>>(),
+            })),
+        });
+        let path = core_dump.with_extension("wasm.json");
+        fs::write(&path, serde_json::to_vec_pretty(&json)?)?;
+        Ok(path)
+    }
+}
+
+/// The trap code and wasm backtrace of a call that failed with `error`,
+/// if it trapped
+fn trap_of(error: &HyperlightError) -> Option<(TrapCode, &[TrapFrame])> {
+    if let Some(exhausted) = StackExhausted::from_error(error) {
+        return Some((TrapCode::StackOverflow, &exhausted.frames));
+    }
+    GuestTrap::from_error(error).map(|trap| (trap.code.clone(), trap.frames.as_slice()))
+}
+
+/// The directory hyperlight-host writes core dumps to: `dir`, or the
+/// `HYPERLIGHT_CORE_DUMP_DIR` environment variable, if the directory
+/// exists, or the temporary directory otherwise
+fn dump_dir(dir: Option<String>) -> PathBuf {
+    dir.or_else(|| std::env::var("HYPERLIGHT_CORE_DUMP_DIR").ok())
+        .map(PathBuf::from)
+        .filter(|dir| dir.exists())
+        .unwrap_or_else(std::env::temp_dir)
+}
+
+/// The most recent core dump written to `dir` since `since`
+fn newest_core_dump(dir: &Path, since: SystemTime) -> Option<PathBuf> {
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let name = entry.file_name();
+            let name = name.to_str()?;
+            if !name.starts_with("hl_core_") || !name.ends_with(".elf") {
+                return None;
+            }
+            let modified = entry.metadata().ok()?.modified().ok()?;
+            (modified >= since).then(|| (modified, entry.path()))
+        })
+        .max()
+        .map(|(_, path)| path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_newest_core_dump() {
+        let dir = std::env::temp_dir().join(format!("hl_wasm_crashdump_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let since = SystemTime::now() - Duration::from_secs(1);
+        assert_eq!(newest_core_dump(&dir, since), None);
+
+        fs::write(dir.join("hl_core_1.elf"), b"").unwrap();
+        fs::write(dir.join("hl_core_1.wasm.json"), b"").unwrap();
+        fs::write(dir.join("other.elf"), b"").unwrap();
+        assert_eq!(
+            newest_core_dump(&dir, since),
+            Some(dir.join("hl_core_1.elf"))
+        );
+        // Dumps written before the crash are not its dump
+        assert_eq!(
+            newest_core_dump(&dir, SystemTime::now() + Duration::from_secs(60)),
+            None
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_dump_dir() {
+        let dir = std::env::temp_dir();
+        assert_eq!(dump_dir(Some(dir.to_string_lossy().to_string())), dir);
+        assert_eq!(
+            dump_dir(Some("/does/not/exist".to_string())),
+            std::env::temp_dir()
+        );
+    }
+}
@@ -0,0 +1,156 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::fmt;
+
+use hyperlight_host::HyperlightError;
+use hyperlight_wasm_runtime::runtime_config::{
+    WASM_MEMORY_LIMIT_EXCEEDED, WASM_TABLE_LIMIT_EXCEEDED,
+};
+
+use super::sandbox_context::SandboxContext;
+
+/// The error returned by a guest call that failed after the guest tried
+/// to grow a linear memory or table beyond the sandbox's limit, see
+/// [`SandboxBuilder::with_max_wasm_memory`](crate::SandboxBuilder::with_max_wasm_memory)
+/// and
+/// [`SandboxBuilder::with_max_table_elements`](crate::SandboxBuilder::with_max_table_elements).
+///
+/// The growth failed inside the guest, as `memory.grow` or `table.grow`
+/// does when it cannot grow, so the sandbox is not poisoned. Guests that
+/// handle the failure themselves return normally.
+///
+/// It is returned as a [`HyperlightError::AnyhowError`], from which
+/// [`from_error`](Self::from_error) extracts it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WasmLimitExceeded {
+    /// A linear memory was refused growth
+    Memory {
+        /// The name of the guest function that was called
+        function_name: String,
+        /// The largest size, in bytes, each linear memory may grow to
+        max_bytes: u64,
+    },
+    /// A table was refused growth
+    TableElements {
+        /// The name of the guest function that was called
+        function_name: String,
+        /// The most elements each table may grow to
+        max_elements: u64,
+    },
+}
+
+impl WasmLimitExceeded {
+    /// The limit exceeded by the call that failed with `error`, or `None`
+    /// if the call failed for another reason
+    pub fn from_error(error: &HyperlightError) -> Option<&Self> {
+        match error {
+            HyperlightError::AnyhowError(e) => e.downcast_ref(),
+            _ => None,
+        }
+    }
+
+    /// The limit exceeded by the call to `function_name` that failed with
+    /// the guest error `message`, or `None` if it failed for another
+    /// reason
+    pub(crate) fn from_guest_error(
+        message: &str,
+        function_name: &str,
+        context: &SandboxContext,
+    ) -> Option<Self> {
+        let function_name = function_name.to_string();
+        match message {
+            WASM_MEMORY_LIMIT_EXCEEDED => Some(Self::Memory {
+                function_name,
+                max_bytes: context.max_wasm_memory.unwrap_or(u64::MAX),
+            }),
+            WASM_TABLE_LIMIT_EXCEEDED => Some(Self::TableElements {
+                function_name,
+                max_elements: context.max_table_elements.unwrap_or(u64::MAX),
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for WasmLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Memory {
+                function_name,
+                max_bytes,
+            } => write!(
+                f,
+                "guest function {} tried to grow a linear memory beyond its limit of {} bytes",
+                function_name, max_bytes
+            ),
+            Self::TableElements {
+                function_name,
+                max_elements,
+            } => write!(
+                f,
+                "guest function {} tried to grow a table beyond its limit of {} elements",
+                function_name, max_elements
+            ),
+        }
+    }
+}
+
+impl std::error::Error for WasmLimitExceeded {}
+
+impl From<WasmLimitExceeded> for HyperlightError {
+    fn from(exceeded: WasmLimitExceeded) -> Self {
+        HyperlightError::AnyhowError(anyhow::Error::new(exceeded))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyperlight_host::HyperlightError;
+    use hyperlight_wasm_runtime::runtime_config::WASM_MEMORY_LIMIT_EXCEEDED;
+
+    use super::WasmLimitExceeded;
+    use crate::sandbox::sandbox_context::SandboxContext;
+
+    #[test]
+    fn test_wasm_limit_exceeded_from_error() {
+        let context = SandboxContext {
+            max_wasm_memory: Some(1 << 20),
+            ..Default::default()
+        };
+        let exceeded =
+            WasmLimitExceeded::from_guest_error(WASM_MEMORY_LIMIT_EXCEEDED, "Allocate", &context)
+                .unwrap();
+        assert_eq!(
+            exceeded,
+            WasmLimitExceeded::Memory {
+                function_name: "Allocate".to_string(),
+                max_bytes: 1 << 20,
+            }
+        );
+        assert_eq!(
+            WasmLimitExceeded::from_guest_error("unreachable", "Allocate", &context),
+            None
+        );
+
+        let error = HyperlightError::from(exceeded.clone());
+        assert_eq!(WasmLimitExceeded::from_error(&error), Some(&exceeded));
+        assert_eq!(
+            WasmLimitExceeded::from_error(&HyperlightError::ExecutionCanceledByHost()),
+            None
+        );
+    }
+}
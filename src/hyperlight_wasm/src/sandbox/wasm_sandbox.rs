@@ -17,12 +17,24 @@ limitations under the License.
 use std::path::Path;
 use std::sync::Arc;
 
+use hyperlight_host::func::{HostFunction, SupportedReturnType};
 #[cfg(target_os = "linux")]
 use hyperlight_host::mem::memory_region::{MemoryRegion, MemoryRegionFlags, MemoryRegionType};
 use hyperlight_host::sandbox::snapshot::Snapshot;
 use hyperlight_host::{MultiUseSandbox, Result, new_error};
+use hyperlight_wasm_runtime::component_value::LOAD_COMPONENT_FUNCTION;
 
+use super::guest_abi::GuestAbi;
 use super::loaded_wasm_sandbox::LoadedWasmSandbox;
+#[cfg(target_os = "linux")]
+use super::module_handle::ModuleHandle;
+use super::module_resolver::ModuleSource;
+use super::module_usage;
+use super::panic_policy::CatchPanics;
+use super::required_exports;
+use super::sandbox_context::SandboxContext;
+#[cfg(feature = "aot")]
+use crate::aot::AotCache;
 use crate::sandbox::metrics::{
     METRIC_ACTIVE_WASM_SANDBOXES, METRIC_SANDBOX_LOADS, METRIC_TOTAL_WASM_SANDBOXES,
 };
@@ -103,6 +115,16 @@ mod backing_sandbox {
             };
             Ok(())
         }
+        pub(super) fn get_mut(&mut self) -> Result<&mut MultiUseSandbox> {
+            match self {
+                BackingSandbox::Clean(x) | BackingSandbox::Loaded(x) | BackingSandbox::Dirty(x) => {
+                    Ok(x)
+                }
+                BackingSandbox::Missing => Err(new_error!(
+                    "internal invariant violation: encountered missing backing sandbox"
+                )),
+            }
+        }
         pub(super) fn get_loaded(&mut self) -> Result<MultiUseSandbox> {
             match std::mem::replace(self, BackingSandbox::Missing) {
                 BackingSandbox::Loaded(x) => Ok(x),
@@ -131,6 +153,23 @@ mod backing_sandbox {
             Ok(())
         }
 
+        #[test]
+        fn test_zero_memory_on_unload_cleans() -> Result<()> {
+            let mut sb = SandboxBuilder::new()
+                .with_zero_memory_on_unload(true)
+                .with_prefault_memory(true)
+                .build()?;
+            sb.register(
+                "GetTimeSinceBootMicrosecond",
+                get_time_since_boot_microsecond,
+            )?;
+            let sb = sb.load_runtime()?;
+            let lb = sb.load_module(get_test_file_path("RunWasm.aot")?)?;
+            let sb = lb.unload_module()?;
+            assert!(matches!(sb.inner, super::BackingSandbox::Clean(_)));
+            Ok(())
+        }
+
         #[test]
         fn test_dirty_backing_sandbox_cannot_be_loaded_via_fn() -> Result<()> {
             let mut sb = SandboxBuilder::new().build()?;
@@ -176,6 +215,10 @@ pub struct WasmSandbox {
     // Snapshot of state of an initial WasmSandbox (runtime loaded, but no guest module code loaded).
     // Used for LoadedWasmSandbox to be able restore state back to WasmSandbox
     snapshot: Option<Arc<Snapshot>>,
+    // The ABI of the next module to be loaded
+    guest_abi: GuestAbi,
+    // Settings and state passed on to the loaded sandbox
+    context: SandboxContext,
 }
 
 const MAPPED_BINARY_VA: u64 = 0x1_0000_0000u64;
@@ -184,13 +227,15 @@ impl WasmSandbox {
     /// This function should be used to create a new `WasmSandbox` from a ProtoWasmSandbox.
     /// The difference between this function and creating  a `WasmSandbox` directly is that
     /// this function will increment the metrics for the number of `WasmSandbox`es in the system.
-    pub(super) fn new(mut inner: MultiUseSandbox) -> Result<Self> {
+    pub(super) fn new(mut inner: MultiUseSandbox, context: SandboxContext) -> Result<Self> {
         let snapshot = inner.snapshot()?;
         metrics::gauge!(METRIC_ACTIVE_WASM_SANDBOXES).increment(1);
         metrics::counter!(METRIC_TOTAL_WASM_SANDBOXES).increment(1);
         Ok(WasmSandbox {
             inner: BackingSandbox::Clean(inner),
             snapshot: Some(snapshot),
+            guest_abi: GuestAbi::default(),
+            context,
         })
     }
 
@@ -201,13 +246,20 @@ impl WasmSandbox {
     pub(super) fn new_from_loaded(
         loaded: MultiUseSandbox,
         snapshot: Arc<Snapshot>,
+        context: SandboxContext,
     ) -> Result<Self> {
         metrics::gauge!(METRIC_ACTIVE_WASM_SANDBOXES).increment(1);
         metrics::counter!(METRIC_TOTAL_WASM_SANDBOXES).increment(1);
-        Ok(WasmSandbox {
+        let mut sandbox = WasmSandbox {
             inner: BackingSandbox::Dirty(loaded),
             snapshot: Some(snapshot),
-        })
+            guest_abi: GuestAbi::default(),
+            context,
+        };
+        if sandbox.context.zero_memory_on_unload {
+            sandbox.clean_inner()?;
+        }
+        Ok(sandbox)
     }
 
     fn clean_inner(&mut self) -> Result<()> {
@@ -217,33 +269,235 @@ impl WasmSandbox {
         self.inner.clean(snapshot.clone())
     }
 
+    /// Set the [`GuestAbi`] used to exchange strings and buffers with
+    /// modules subsequently loaded into this sandbox. Defaults to
+    /// [`GuestAbi::C`].
+    ///
+    /// This has no effect on [`load_from_snapshot`](Self::load_from_snapshot),
+    /// since the snapshot already records the ABI of the module it was
+    /// taken from.
+    pub fn with_guest_abi(mut self, guest_abi: GuestAbi) -> Self {
+        self.guest_abi = guest_abi;
+        self
+    }
+
+    /// Register the given host function `host_func` with `self` under
+    /// the given `name`, after the runtime has been loaded, so that a
+    /// host can add host functions depending on which module it loads.
+    ///
+    /// Modules subsequently loaded with [`load_module`](Self::load_module),
+    /// [`load_module_from_buffer`](Self::load_module_from_buffer) or
+    /// [`load_module_by_name`](Self::load_module_by_name) can import the
+    /// function, as can modules loaded after this sandbox's module is
+    /// unloaded. Panics in `host_func` are handled as for
+    /// [`ProtoWasmSandbox::register`](crate::ProtoWasmSandbox::register).
+    ///
+    /// Components cannot import host functions registered this way,
+    /// since their imports are fixed by their WIT world.
+    pub fn register<Args: CatchPanics<Output>, Output: SupportedReturnType>(
+        &mut self,
+        name: impl AsRef<str>,
+        host_func: impl Into<HostFunction<Output, Args>>,
+    ) -> Result<()> {
+        let inner = self.inner.get_mut()?;
+        self.context
+            .register(inner, name.as_ref(), host_func.into())
+    }
+
     /// Load a Wasm module at the given path into the sandbox and return a `LoadedWasmSandbox`
     /// able to execute code in the loaded Wasm Module.
     ///
     /// Before you can call guest functions in the sandbox, you must call
     /// this function and use the returned value to call guest functions.
     pub fn load_module(mut self, file: impl AsRef<Path>) -> Result<LoadedWasmSandbox> {
+        let module_hash = if self.context.hash_modules() || self.context.module_cache.enabled() {
+            Some(module_usage::module_hash(&std::fs::read(file.as_ref())?))
+        } else {
+            None
+        };
+
+        if !self.load_cached(module_hash.as_deref())? {
+            self.clean_inner()?;
+            let guest_abi = self.guest_abi;
+            let context = &self.context;
+            self.inner.load_via_fn(|inner| {
+                set_guest_abi(inner, guest_abi)?;
+                context.add_host_functions(inner)?;
+                load_wasm_module_from_file(inner, context, file.as_ref())
+            })?;
+            self.cache_loaded(module_hash.as_deref())?;
+        }
+
+        let module_hash = module_hash.filter(|_| self.context.hash_modules());
+        self.finalize_module_load(module_hash)
+    }
+
+    /// Load the module or component at `file` as
+    /// [`load_module`](Self::load_module) does, keeping the artifact
+    /// precompiled from it in `cache_dir`. The artifact is reused by
+    /// later loads with the same sandbox settings, from this or any other
+    /// process, as long as it is compatible with the embedded runtime,
+    /// and compiled and stored again if not. See [`AotCache`].
+    ///
+    /// This requires the `aot` feature. Files that are already
+    /// precompiled are loaded as they are.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sandbox was built without
+    /// [host precompilation](crate::SandboxBuilder::with_host_precompilation),
+    /// or if the module cannot be compiled or loaded.
+    #[cfg(feature = "aot")]
+    pub fn load_module_cached(
+        mut self,
+        file: impl AsRef<Path>,
+        cache_dir: impl AsRef<Path>,
+    ) -> Result<LoadedWasmSandbox> {
+        let Some(precompiler) = &mut self.context.precompiler else {
+            return Err(new_error!(
+                "load_module_cached needs host precompilation, which this sandbox was built without"
+            ));
+        };
+        let cache = precompiler.cache.replace(AotCache::new(cache_dir.as_ref()));
+        self.load_module(file)
+            .map(|loaded| loaded.with_aot_cache(cache))
+    }
+
+    /// Load the Wasm modules at the given paths into the sandbox, in
+    /// order, and return a `LoadedWasmSandbox` able to execute code in
+    /// the last of them.
+    ///
+    /// Each module but the last is a library whose exports the modules
+    /// loaded after it can import from the module named by its name,
+    /// for example `load_modules(&[("math", "math.aot"), ("app",
+    /// "app.aot")])` lets `app.aot` import `math.add` from `math.aot`.
+    /// The last module's name is not used, since no module is loaded
+    /// after it. All the modules share one store, so they keep their
+    /// state across guest calls just as a single module does.
+    ///
+    /// Usage and provenance are recorded under the hash of all the
+    /// modules together, and upgrading the loaded module with
+    /// [`LoadedWasmSandbox::upgrade_module`] links the new version with
+    /// the same libraries.
+    ///
+    /// Components cannot be linked this way.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `modules` is empty, or if a module cannot be
+    /// loaded or imports a function no earlier module exports.
+    pub fn load_modules<P: AsRef<Path>>(
+        mut self,
+        modules: &[(&str, P)],
+    ) -> Result<LoadedWasmSandbox> {
+        let Some(((_, file), libraries)) = modules.split_last() else {
+            return Err(new_error!("no modules to load"));
+        };
         self.clean_inner()?;
 
+        let libraries = libraries
+            .iter()
+            .map(|(name, path)| {
+                let bytes = std::fs::read(path)?;
+                let bytes = self.context.prepare(&bytes)?.unwrap_or(bytes);
+                Ok((name.to_string(), bytes))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let module_hash = if self.context.hash_modules() {
+            let bytes = std::fs::read(file.as_ref())?;
+            Some(module_usage::linked_module_hash(&libraries, &bytes))
+        } else {
+            None
+        };
+
+        let guest_abi = self.guest_abi;
+        let context = &self.context;
         self.inner.load_via_fn(|inner| {
-            if let Ok(len) = inner.map_file_cow(file.as_ref(), MAPPED_BINARY_VA, None) {
-                inner.call::<()>("LoadWasmModulePhys", (MAPPED_BINARY_VA, len))?;
-            } else {
-                let wasm_bytes = std::fs::read(file)?;
-                load_wasm_module_from_bytes(inner, wasm_bytes)?;
-            }
-            Ok(())
+            set_guest_abi(inner, guest_abi)?;
+            context.add_host_functions(inner)?;
+            link_wasm_modules(inner, &libraries)?;
+            load_wasm_module_from_file(inner, context, file.as_ref())
+        })?;
+
+        Ok(self
+            .finalize_module_load(module_hash)?
+            .with_linked_modules(libraries))
+    }
+
+    /// Load the Wasm module at `file` into the sandbox, as
+    /// [`load_module`](Self::load_module) does, but only let it import
+    /// the registered host functions named in `allowed`. This keeps the
+    /// functions an untrusted module can call to the ones it needs. The
+    /// functions the runtime provides itself, such as the WASI shims, are
+    /// not affected.
+    ///
+    /// Upgrading the loaded module with
+    /// [`LoadedWasmSandbox::upgrade_module`] keeps the same restriction.
+    /// The module cache is not used, so that a module cached without the
+    /// restriction is never loaded with it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a name in `allowed` is not a registered host
+    /// function, or if the module cannot be loaded, for example because
+    /// it imports a host function that is not allowed.
+    pub fn load_module_with_imports(
+        mut self,
+        file: impl AsRef<Path>,
+        allowed: &[&str],
+    ) -> Result<LoadedWasmSandbox> {
+        let module_hash = if self.context.hash_modules() {
+            Some(module_usage::module_hash(&std::fs::read(file.as_ref())?))
+        } else {
+            None
+        };
+        let allowed: Vec<String> = allowed.iter().map(|name| name.to_string()).collect();
+
+        self.clean_inner()?;
+        let guest_abi = self.guest_abi;
+        let context = &self.context;
+        self.inner.load_via_fn(|inner| {
+            set_guest_abi(inner, guest_abi)?;
+            context.add_host_functions(inner)?;
+            restrict_host_functions(inner, Some(&allowed))?;
+            load_wasm_module_from_file(inner, context, file.as_ref())
         })?;
 
-        self.finalize_module_load()
+        Ok(self
+            .finalize_module_load(module_hash)?
+            .with_allowed_imports(allowed))
+    }
+
+    /// Load the module called `name`, found by the sandbox's
+    /// [`ModuleResolver`], see
+    /// [`SandboxBuilder::with_module_resolver`](crate::SandboxBuilder::with_module_resolver).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sandbox has no resolver, if the resolver
+    /// fails, or if the module cannot be loaded.
+    pub fn load_module_by_name(self, name: &str) -> Result<LoadedWasmSandbox> {
+        let source = match &self.context.module_resolver {
+            Some(resolver) => resolver.resolve(name)?,
+            None => return Err(new_error!("no module resolver to find module {}", name)),
+        };
+        match source {
+            ModuleSource::Path(path) => self.load_module(path),
+            ModuleSource::Bytes(bytes) => self.load_module_from_buffer(&bytes),
+        }
     }
 
     /// Load a Wasm module by restoring a Hyperlight snapshot taken
     /// from a `LoadedWasmSandbox`.
     pub fn load_from_snapshot(mut self, snapshot: Arc<Snapshot>) -> Result<LoadedWasmSandbox> {
+        let module_hash = if self.context.hash_modules() {
+            module_usage::snapshot_module_hash(&snapshot)
+        } else {
+            None
+        };
         self.inner.load_via_restore(snapshot)?;
 
-        self.finalize_module_load()
+        self.finalize_module_load(module_hash)
     }
 
     /// Load a Wasm module that is currently present in a buffer in
@@ -265,25 +519,66 @@ impl WasmSandbox {
     ) -> Result<LoadedWasmSandbox> {
         self.clean_inner()?;
 
+        let module_hash = if self.context.hash_modules() {
+            let bytes = unsafe { std::slice::from_raw_parts(base as *const u8, len) };
+            Some(module_usage::module_hash(bytes))
+        } else {
+            None
+        };
+
+        let guest_abi = self.guest_abi;
+        let context = &self.context;
         self.inner.load_via_fn(|inner| {
-            let guest_base: usize = MAPPED_BINARY_VA as usize;
-            let rgn = MemoryRegion {
-                host_region: base as usize..base.wrapping_add(len) as usize,
-                guest_region: guest_base..guest_base + len,
-                flags: MemoryRegionFlags::READ | MemoryRegionFlags::EXECUTE,
-                region_type: MemoryRegionType::Heap,
-            };
-            if let Ok(()) = unsafe { inner.map_region(&rgn) } {
-                inner.call::<()>("LoadWasmModulePhys", (MAPPED_BINARY_VA, len as u64))?;
-            } else {
-                let wasm_bytes =
-                    unsafe { std::slice::from_raw_parts(base as *const u8, len).to_vec() };
-                load_wasm_module_from_bytes(inner, wasm_bytes)?;
-            }
-            Ok(())
+            set_guest_abi(inner, guest_abi)?;
+            context.add_host_functions(inner)?;
+            unsafe { load_wasm_module_from_mapping(inner, context, base, len, len) }
         })?;
 
-        self.finalize_module_load()
+        self.finalize_module_load(module_hash)
+    }
+
+    /// Load the module opened as `handle` into the sandbox, mapping the
+    /// host pages `handle` holds rather than reading or copying the
+    /// module, and return a `LoadedWasmSandbox` able to execute code in
+    /// it.
+    ///
+    /// Loading one handle into many sandboxes maps the same host memory
+    /// into each of them, which saves memory and load time. The module's
+    /// hash is the one computed when `handle` was opened, so it is not
+    /// hashed again for usage accounting, provenance or the module cache.
+    /// The sandbox keeps `handle` alive for as long as the module may be
+    /// mapped into it.
+    #[cfg(target_os = "linux")]
+    pub fn load_module_handle(mut self, handle: &ModuleHandle) -> Result<LoadedWasmSandbox> {
+        let module_hash = Some(handle.hash().to_string());
+
+        if !self.load_cached(module_hash.as_deref())? {
+            self.clean_inner()?;
+            let guest_abi = self.guest_abi;
+            let context = &self.context;
+            let (base, mapped_len) = handle.mapping();
+            self.inner.load_via_fn(|inner| {
+                set_guest_abi(inner, guest_abi)?;
+                context.add_host_functions(inner)?;
+                // Safety: the sandbox keeps handle, and so its mapping,
+                // alive
+                unsafe {
+                    load_wasm_module_from_mapping(inner, context, base, handle.len(), mapped_len)
+                }
+            })?;
+            self.cache_loaded(module_hash.as_deref())?;
+        }
+        if !self
+            .context
+            .module_handles
+            .iter()
+            .any(|kept| kept.same_mapping(handle))
+        {
+            self.context.module_handles.push(handle.clone());
+        }
+
+        let module_hash = module_hash.filter(|_| self.context.hash_modules());
+        self.finalize_module_load(module_hash)
     }
 
     /// Load a Wasm module from a buffer of bytes into the sandbox and return a `LoadedWasmSandbox`
@@ -292,30 +587,243 @@ impl WasmSandbox {
     /// Before you can call guest functions in the sandbox, you must call
     /// this function and use the returned value to call guest functions.
     pub fn load_module_from_buffer(mut self, buffer: &[u8]) -> Result<LoadedWasmSandbox> {
-        self.clean_inner()?;
+        let module_hash = (self.context.hash_modules() || self.context.module_cache.enabled())
+            .then(|| module_usage::module_hash(buffer));
+
+        if !self.load_cached(module_hash.as_deref())? {
+            self.clean_inner()?;
+            let artifact = self.context.prepare(buffer)?;
+            let guest_abi = self.guest_abi;
+            let context = &self.context;
+            // TODO: get rid of this clone
+            self.inner.load_via_fn(|inner| {
+                set_guest_abi(inner, guest_abi)?;
+                context.add_host_functions(inner)?;
+                load_wasm_module_from_bytes(inner, artifact.unwrap_or_else(|| buffer.to_vec()))
+            })?;
+            self.cache_loaded(module_hash.as_deref())?;
+        }
+
+        let module_hash = module_hash.filter(|_| self.context.hash_modules());
+        self.finalize_module_load(module_hash)
+    }
+
+    /// Load the component in the file at `file` into the sandbox without
+    /// bindings generated for its world, and return a `LoadedWasmSandbox`
+    /// whose exported functions can be called with
+    /// [`LoadedWasmSandbox::call_component_function`].
+    ///
+    /// This needs the module runtime, see [`RuntimeKind::Module`](crate::RuntimeKind::Module),
+    /// and lets hosts run components whose worlds are only known at run
+    /// time. Each function the component imports is linked to the host
+    /// function registered for it with
+    /// [`ProtoWasmSandbox::register_component_function`](crate::ProtoWasmSandbox::register_component_function).
+    pub fn load_component(self, file: impl AsRef<Path>) -> Result<LoadedWasmSandbox> {
+        let buffer = std::fs::read(file.as_ref())?;
+        self.load_component_from_buffer(&buffer)
+    }
+
+    /// Load a component from a buffer of bytes into the sandbox, see
+    /// [`load_component`](Self::load_component).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sandbox requires exports, which are only
+    /// checked for modules, or if the component cannot be loaded or
+    /// imports a function the host has not registered.
+    pub fn load_component_from_buffer(mut self, buffer: &[u8]) -> Result<LoadedWasmSandbox> {
+        if !self.context.required_exports.is_empty() {
+            return Err(new_error!(
+                "required exports are not supported for components loaded without bindings"
+            ));
+        }
+        let module_hash = (self.context.hash_modules() || self.context.module_cache.enabled())
+            .then(|| module_usage::module_hash(buffer));
+
+        if !self.load_cached(module_hash.as_deref())? {
+            self.clean_inner()?;
+            let artifact = self.context.prepare(buffer)?;
+            let guest_abi = self.guest_abi;
+            let context = &self.context;
+            self.inner.load_via_fn(|inner| {
+                set_guest_abi(inner, guest_abi)?;
+                context.add_host_functions(inner)?;
+                inner.call::<()>(
+                    LOAD_COMPONENT_FUNCTION,
+                    artifact.unwrap_or_else(|| buffer.to_vec()),
+                )
+            })?;
+            self.cache_loaded(module_hash.as_deref())?;
+        }
 
-        // TODO: get rid of this clone
-        self.inner
-            .load_via_fn(|inner| load_wasm_module_from_bytes(inner, buffer.to_vec()))?;
+        let module_hash = module_hash.filter(|_| self.context.hash_modules());
+        self.finalize_module_load(module_hash)
+    }
+
+    /// Load the module with hash `module_hash` by restoring the snapshot
+    /// taken when it was last loaded, returning `false` if it is not in
+    /// the module cache
+    fn load_cached(&mut self, module_hash: Option<&str>) -> Result<bool> {
+        let Some(module_hash) = module_hash.filter(|_| self.context.module_cache.enabled()) else {
+            return Ok(false);
+        };
+        match self.context.module_cache.get(module_hash, self.guest_abi) {
+            Some(snapshot) => {
+                self.inner.load_via_restore(snapshot)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
 
-        self.finalize_module_load()
+    /// Add a snapshot of the sandbox, into which the module with hash
+    /// `module_hash` was just loaded, to the module cache
+    fn cache_loaded(&mut self, module_hash: Option<&str>) -> Result<()> {
+        let Some(module_hash) = module_hash.filter(|_| self.context.module_cache.enabled()) else {
+            return Ok(());
+        };
+        let snapshot = self.inner.get_mut()?.snapshot()?;
+        self.context
+            .module_cache
+            .insert(module_hash.to_string(), self.guest_abi, snapshot);
+        Ok(())
     }
 
     /// Helper function to finalize module loading and create LoadedWasmSandbox
-    fn finalize_module_load(mut self) -> Result<LoadedWasmSandbox> {
+    fn finalize_module_load(mut self, module_hash: Option<String>) -> Result<LoadedWasmSandbox> {
         metrics::counter!(METRIC_SANDBOX_LOADS).increment(1);
 
-        let sandbox = self.inner.get_loaded()?;
+        let mut sandbox = self.inner.get_loaded()?;
+        required_exports::check(&mut sandbox, &self.context.required_exports)?;
 
         let snapshot = self.snapshot.take().ok_or(new_error!(
             "internal invariant violation: Snapshot is missing"
         ))?;
 
-        LoadedWasmSandbox::new(sandbox, snapshot)
+        let stubbed_wasi_imports = stubbed_wasi_imports(&mut sandbox)?;
+
+        LoadedWasmSandbox::new(
+            sandbox,
+            snapshot,
+            std::mem::take(&mut self.context),
+            stubbed_wasi_imports,
+            module_hash,
+            self.guest_abi,
+        )
+    }
+}
+
+/// The unsupported WASI functions imported by the module loaded into
+/// `inner`, which were stubbed
+pub(super) fn stubbed_wasi_imports(inner: &mut MultiUseSandbox) -> Result<Vec<String>> {
+    let stubbed: String = inner.call("GetStubbedWasiImports", ())?;
+    let stubbed_wasi_imports: Vec<String> = stubbed
+        .split(',')
+        .filter(|name| !name.is_empty())
+        .map(String::from)
+        .collect();
+    if !stubbed_wasi_imports.is_empty() {
+        tracing::warn!(
+            "module imports unsupported WASI functions, which were stubbed: {}",
+            stubbed
+        );
+    }
+    Ok(stubbed_wasi_imports)
+}
+
+pub(super) fn set_guest_abi(inner: &mut MultiUseSandbox, guest_abi: GuestAbi) -> Result<()> {
+    // The runtime snapshot always uses the C ABI, so there is nothing to do
+    if guest_abi == GuestAbi::C {
+        return Ok(());
+    }
+    let res: i32 = inner.call("SetGuestAbi", guest_abi.as_i32())?;
+    if res != 0 {
+        return Err(new_error!("SetGuestAbi Failed with error code {:?}", res));
+    }
+    Ok(())
+}
+
+/// Only let modules loaded into `inner` after this import the host
+/// functions named in `allowed`, if it is given
+pub(super) fn restrict_host_functions(
+    inner: &mut MultiUseSandbox,
+    allowed: Option<&[String]>,
+) -> Result<()> {
+    let Some(allowed) = allowed else {
+        return Ok(());
+    };
+    if let Some(name) = allowed.iter().find(|name| name.contains(',')) {
+        return Err(new_error!(
+            "host function {} cannot be allowed by name",
+            name
+        ));
     }
+    inner.call::<()>("RestrictHostFunctions", allowed.join(","))
 }
 
-fn load_wasm_module_from_bytes(inner: &mut MultiUseSandbox, wasm_bytes: Vec<u8>) -> Result<()> {
+/// Instantiate each of `modules`, a module's name and its bytes, so
+/// that modules loaded after them can import their exports
+pub(super) fn link_wasm_modules(
+    inner: &mut MultiUseSandbox,
+    modules: &[(String, Vec<u8>)],
+) -> Result<()> {
+    for (name, wasm_bytes) in modules {
+        inner.call::<()>("LinkWasmModule", (name.clone(), wasm_bytes.clone()))?;
+    }
+    Ok(())
+}
+
+/// Load the `len` byte module at `base` on the host, mapping the
+/// `mapped_len` bytes there into the guest if possible
+///
+/// # Safety
+/// The host memory must remain intact, and not be written to, for as
+/// long as it is mapped into the guest.
+#[cfg(target_os = "linux")]
+unsafe fn load_wasm_module_from_mapping(
+    inner: &mut MultiUseSandbox,
+    context: &SandboxContext,
+    base: *mut libc::c_void,
+    len: usize,
+    mapped_len: usize,
+) -> Result<()> {
+    let bytes = unsafe { std::slice::from_raw_parts(base as *const u8, len) };
+    if let Some(artifact) = context.prepare(bytes)? {
+        return load_wasm_module_from_bytes(inner, artifact);
+    }
+    let guest_base: usize = MAPPED_BINARY_VA as usize;
+    let rgn = MemoryRegion {
+        host_region: base as usize..base.wrapping_add(mapped_len) as usize,
+        guest_region: guest_base..guest_base + mapped_len,
+        flags: MemoryRegionFlags::READ | MemoryRegionFlags::EXECUTE,
+        region_type: MemoryRegionType::Heap,
+    };
+    if let Ok(()) = unsafe { inner.map_region(&rgn) } {
+        inner.call::<()>("LoadWasmModulePhys", (MAPPED_BINARY_VA, len as u64))
+    } else {
+        load_wasm_module_from_bytes(inner, bytes.to_vec())
+    }
+}
+
+fn load_wasm_module_from_file(
+    inner: &mut MultiUseSandbox,
+    context: &SandboxContext,
+    file: &Path,
+) -> Result<()> {
+    if let Some(artifact) = context.prepare_file(file)? {
+        return load_wasm_module_from_bytes(inner, artifact);
+    }
+    if let Ok(len) = inner.map_file_cow(file, MAPPED_BINARY_VA, None) {
+        inner.call::<()>("LoadWasmModulePhys", (MAPPED_BINARY_VA, len))
+    } else {
+        load_wasm_module_from_bytes(inner, std::fs::read(file)?)
+    }
+}
+
+pub(super) fn load_wasm_module_from_bytes(
+    inner: &mut MultiUseSandbox,
+    wasm_bytes: Vec<u8>,
+) -> Result<()> {
     let res: i32 = inner.call(
         "LoadWasmModule",
         (wasm_bytes.clone(), wasm_bytes.len() as i32),
@@ -349,6 +857,7 @@ mod tests {
     use hyperlight_host::{HyperlightError, is_hypervisor_present};
 
     use super::*;
+    use crate::sandbox::runtime_kind::RuntimeKind;
     pub(super) use crate::sandbox::sandbox_builder::SandboxBuilder;
 
     #[test]
@@ -663,6 +1172,115 @@ mod tests {
         assert_eq!(result, 0);
     }
 
+    #[test]
+    fn test_module_cache() {
+        let mut sandbox = SandboxBuilder::new().with_module_cache(1).build().unwrap();
+        sandbox
+            .register(
+                "GetTimeSinceBootMicrosecond",
+                get_time_since_boot_microsecond,
+            )
+            .unwrap();
+        let mut sb = sandbox.load_runtime().unwrap();
+
+        let helloworld_wasm = get_test_file_path("HelloWorld.aot").unwrap();
+        let runwasm_wasm = get_test_file_path("RunWasm.aot").unwrap();
+        let runwasm_buffer = std::fs::read(&runwasm_wasm).unwrap();
+
+        // The first load of each module is cached and evicts the other,
+        // the second is restored from the cache, whether it is loaded
+        // from the file or from a buffer of the same bytes
+        for _ in 0..2 {
+            let mut lb = sb.load_module(&helloworld_wasm).unwrap();
+            let result: i32 = lb
+                .call_guest_function("HelloWorld", "Message from Rust Test".to_string())
+                .unwrap();
+            assert_eq!(result, 0);
+            sb = lb.unload_module().unwrap();
+
+            let mut lb = sb.load_module(&runwasm_wasm).unwrap();
+            let result: i32 = lb.call_guest_function("CalcFib", 10i32).unwrap();
+            assert_eq!(result, 55);
+            sb = lb.unload_module().unwrap();
+
+            let mut lb = sb.load_module_from_buffer(&runwasm_buffer).unwrap();
+            let result: i32 = lb.call_guest_function("CalcFib", 10i32).unwrap();
+            assert_eq!(result, 55);
+            sb = lb.unload_module().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_load_modules() {
+        let sb = SandboxBuilder::new()
+            .build()
+            .unwrap()
+            .load_runtime()
+            .unwrap();
+        let library = get_test_file_path("MathLibrary.aot").unwrap();
+        let app = get_test_file_path("MathApp.aot").unwrap();
+
+        let mut loaded = sb
+            .load_modules(&[("math", &library), ("app", &app)])
+            .unwrap();
+        let result: i32 = loaded
+            .call_guest_function("MultiplyAdd", (6i32, 7i32, 3i32))
+            .unwrap();
+        assert_eq!(result, 45);
+
+        // The application cannot be loaded without its library
+        let sb = loaded.unload_module().unwrap();
+        assert!(sb.load_modules(&[("app", &app)]).is_err());
+    }
+
+    #[test]
+    fn test_load_module_with_imports() {
+        let mut proto = SandboxBuilder::new().build().unwrap();
+        proto
+            .register(
+                "GetTimeSinceBootMicrosecond",
+                get_time_since_boot_microsecond,
+            )
+            .unwrap();
+        let sb = proto.load_runtime().unwrap();
+        let run_wasm = get_test_file_path("RunWasm.aot").unwrap();
+
+        // The module imports a host function that is not allowed
+        let err = sb.load_module_with_imports(&run_wasm, &[]).unwrap_err();
+        assert!(
+            err.to_string().contains("GetTimeSinceBootMicrosecond"),
+            "{err}"
+        );
+
+        let mut proto = SandboxBuilder::new().build().unwrap();
+        proto
+            .register(
+                "GetTimeSinceBootMicrosecond",
+                get_time_since_boot_microsecond,
+            )
+            .unwrap();
+        let sb = proto.load_runtime().unwrap();
+        assert!(
+            sb.load_module_with_imports(&run_wasm, &["NoSuchFunction"])
+                .is_err()
+        );
+
+        let mut proto = SandboxBuilder::new().build().unwrap();
+        proto
+            .register(
+                "GetTimeSinceBootMicrosecond",
+                get_time_since_boot_microsecond,
+            )
+            .unwrap();
+        let mut loaded = proto
+            .load_runtime()
+            .unwrap()
+            .load_module_with_imports(&run_wasm, &["GetTimeSinceBootMicrosecond"])
+            .unwrap();
+        let result: i32 = loaded.call_guest_function("CalcFib", 10i32).unwrap();
+        assert_eq!(result, 55);
+    }
+
     #[test]
     fn test_load_module_buffer() {
         let sandboxes = get_test_wasm_sandboxes().unwrap();
@@ -686,6 +1304,156 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_with_runtime() {
+        // Modules run in the runtime for modules, whichever runtime the
+        // build embeds by default
+        if RuntimeKind::Module.is_embedded() {
+            let mut loaded_wasm_sandbox = SandboxBuilder::new()
+                .with_runtime(RuntimeKind::Module)
+                .build()
+                .unwrap()
+                .load_runtime()
+                .unwrap()
+                .load_module(get_test_file_path("HelloWorld.aot").unwrap())
+                .unwrap();
+            let result: i32 = loaded_wasm_sandbox
+                .call_guest_function("HelloWorld", "Message from Rust Test".to_string())
+                .unwrap();
+            assert_eq!(result, 0);
+        }
+        if !RuntimeKind::Component.is_embedded() {
+            let err = SandboxBuilder::new()
+                .with_runtime(RuntimeKind::Component)
+                .build()
+                .unwrap_err();
+            assert!(err.to_string().contains("WIT_WORLD"));
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_load_module_handle() {
+        let handle = ModuleHandle::open(get_test_file_path("HelloWorld.aot").unwrap()).unwrap();
+
+        // The same handle is loaded into several sandboxes, and again
+        // after a module is unloaded
+        let mut loaded: Vec<LoadedWasmSandbox> = (0..3)
+            .map(|_| {
+                SandboxBuilder::new()
+                    .build()
+                    .unwrap()
+                    .load_runtime()
+                    .unwrap()
+                    .load_module_handle(&handle)
+                    .unwrap()
+            })
+            .collect();
+        for loaded_wasm_sandbox in &mut loaded {
+            let result: i32 = loaded_wasm_sandbox
+                .call_guest_function("HelloWorld", "Message from Rust Test".to_string())
+                .unwrap();
+            assert_eq!(result, 0);
+        }
+        let wasm_sandbox = loaded.pop().unwrap().unload_module().unwrap();
+        let mut loaded_wasm_sandbox = wasm_sandbox.load_module_handle(&handle).unwrap();
+        let result: i32 = loaded_wasm_sandbox
+            .call_guest_function("HelloWorld", "Message from Rust Test".to_string())
+            .unwrap();
+        assert_eq!(result, 0);
+
+        // The sandboxes keep the module mapped once the handle is dropped
+        drop(handle);
+        let result: i32 = loaded[0]
+            .call_guest_function("HelloWorld", "Message from Rust Test".to_string())
+            .unwrap();
+        assert_eq!(result, 0);
+    }
+
+    // A module exporting `add(i32, i32) -> i32`, which is not
+    // precompiled
+    #[cfg(feature = "aot")]
+    const ADD_MODULE: &[u8] = &[
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+        0x01, 0x07, 0x01, 0x60, 0x02, 0x7f, 0x7f, 0x01, 0x7f, // types
+        0x03, 0x02, 0x01, 0x00, // functions
+        0x07, 0x07, 0x01, 0x03, b'a', b'd', b'd', 0x00, 0x00, // exports
+        0x0a, 0x09, 0x01, 0x07, 0x00, 0x20, 0x00, 0x20, 0x01, 0x6a, 0x0b, // code
+    ];
+
+    #[test]
+    #[cfg(feature = "aot")]
+    fn test_load_module_precompiled_on_host() {
+        let mut loaded = SandboxBuilder::new()
+            .build()
+            .unwrap()
+            .load_runtime()
+            .unwrap()
+            .load_module_from_buffer(ADD_MODULE)
+            .unwrap();
+        let result: i32 = loaded.call_guest_function("add", (2i32, 3i32)).unwrap();
+        assert_eq!(result, 5);
+
+        // Without host precompilation the guest cannot load the module
+        let wasm_sandbox = SandboxBuilder::new()
+            .with_host_precompilation(false)
+            .build()
+            .unwrap()
+            .load_runtime()
+            .unwrap();
+        assert!(wasm_sandbox.load_module_from_buffer(ADD_MODULE).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "aot")]
+    fn test_load_module_cached() {
+        let dir =
+            std::env::temp_dir().join(format!("hlwasm-load-module-cached-{}", std::process::id()));
+        let cache_dir = dir.join("cache");
+        std::fs::create_dir_all(&dir).unwrap();
+        let module = dir.join("add.wasm");
+        std::fs::write(&module, ADD_MODULE).unwrap();
+
+        for _ in 0..2 {
+            let mut loaded = SandboxBuilder::new()
+                .build()
+                .unwrap()
+                .load_runtime()
+                .unwrap()
+                .load_module_cached(&module, &cache_dir)
+                .unwrap();
+            let result: i32 = loaded.call_guest_function("add", (2i32, 3i32)).unwrap();
+            assert_eq!(result, 5);
+            // The artifact is compiled once, then reused
+            assert_eq!(std::fs::read_dir(&cache_dir).unwrap().count(), 1);
+        }
+
+        // Sandboxes with different settings get their own artifact
+        SandboxBuilder::new()
+            .with_epoch_interruption(true)
+            .with_aot_cache(&cache_dir)
+            .build()
+            .unwrap()
+            .load_runtime()
+            .unwrap()
+            .load_module(&module)
+            .unwrap();
+        assert_eq!(std::fs::read_dir(&cache_dir).unwrap().count(), 2);
+
+        let wasm_sandbox = SandboxBuilder::new()
+            .with_host_precompilation(false)
+            .build()
+            .unwrap()
+            .load_runtime()
+            .unwrap();
+        assert!(
+            wasm_sandbox
+                .load_module_cached(&module, &cache_dir)
+                .is_err()
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
     pub(super) fn get_test_file_path(filename: &str) -> Result<String> {
         #[cfg(debug_assertions)]
         let config = "debug";
@@ -0,0 +1,450 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Check WebAssembly modules against a [`Policy`] on the host, before
+//! they are loaded into a sandbox.
+//!
+//! [`validate_module`] parses a module and describes what it imports and
+//! declares, along with the ways it breaks the policy. Sandboxes built
+//! with [`SandboxBuilder::with_validation_policy`](crate::SandboxBuilder::with_validation_policy)
+//! check every module they load this way, and refuse the ones that break
+//! their policy.
+//!
+//! Only modules in WebAssembly form can be checked. Precompiled
+//! artifacts do not keep the information needed, so check the module an
+//! artifact was compiled from before deploying it. Components are not
+//! supported.
+//!
+//! ```no_run
+//! use hyperlight_wasm::validate::{AllowedImport, Policy, validate_module};
+//!
+//! let policy = Policy {
+//!     allowed_imports: Some(vec![
+//!         AllowedImport::module("wasi_snapshot_preview1"),
+//!         AllowedImport::item("env", "HostPrint"),
+//!     ]),
+//!     max_size: Some(4 * 1024 * 1024),
+//!     max_memories: Some(1),
+//!     ..Default::default()
+//! };
+//! let report = validate_module(&std::fs::read("module.wasm").unwrap(), &policy).unwrap();
+//! for violation in &report.violations {
+//!     println!("{}", violation);
+//! }
+//! ```
+
+use std::fmt;
+
+use hyperlight_host::{Result, new_error};
+use wasmparser::{Encoding, ExternalKind, Parser, Payload, TypeRef};
+
+/// The limits a module must keep to, see [`validate_module`]. Each
+/// limit that is `None` is not checked.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Policy {
+    /// The imports the module may have. Modules may have any imports if
+    /// this is `None`, and none if it is empty. The imports a module
+    /// needs from the runtime, such as `wasi_snapshot_preview1`, must be
+    /// allowed too.
+    pub allowed_imports: Option<Vec<AllowedImport>>,
+    /// The largest size of the module, in bytes
+    pub max_size: Option<usize>,
+    /// The most linear memories the module may import and define
+    pub max_memories: Option<u32>,
+    /// The most tables the module may import and define
+    pub max_tables: Option<u32>,
+    /// The most functions the module may import and define
+    pub max_functions: Option<u32>,
+}
+
+/// An import allowed by a [`Policy`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AllowedImport {
+    /// The module the import is from
+    pub module: String,
+    /// The name of the imported item, or `None` to allow every item of
+    /// `module`
+    pub name: Option<String>,
+}
+
+impl AllowedImport {
+    /// Allow every import from `module`
+    pub fn module(module: impl Into<String>) -> Self {
+        Self {
+            module: module.into(),
+            name: None,
+        }
+    }
+
+    /// Allow the import of `name` from `module`, for example
+    /// `AllowedImport::item("env", "HostPrint")`
+    pub fn item(module: impl Into<String>, name: impl Into<String>) -> Self {
+        Self {
+            module: module.into(),
+            name: Some(name.into()),
+        }
+    }
+
+    fn allows(&self, module: &str, name: &str) -> bool {
+        self.module == module && self.name.as_deref().is_none_or(|n| n == name)
+    }
+}
+
+/// The kind of an item a module imports
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImportKind {
+    /// A function
+    Function,
+    /// A table
+    Table,
+    /// A linear memory
+    Memory,
+    /// A global
+    Global,
+    /// An exception tag
+    Tag,
+}
+
+/// An item a module imports
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ModuleImport {
+    /// The module the item is imported from
+    pub module: String,
+    /// The name of the item
+    pub name: String,
+    /// What kind of item it is
+    pub kind: ImportKind,
+}
+
+/// A way in which a module breaks a [`Policy`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PolicyViolation {
+    /// The module imports an item the policy does not allow
+    ImportNotAllowed {
+        /// The module the item is imported from
+        module: String,
+        /// The name of the item
+        name: String,
+    },
+    /// The module is larger than the policy allows
+    TooLarge {
+        /// The size of the module, in bytes
+        size: usize,
+        /// The largest size allowed
+        max: usize,
+    },
+    /// The module has more linear memories than the policy allows
+    TooManyMemories {
+        /// The number of memories the module imports and defines
+        count: u32,
+        /// The most allowed
+        max: u32,
+    },
+    /// The module has more tables than the policy allows
+    TooManyTables {
+        /// The number of tables the module imports and defines
+        count: u32,
+        /// The most allowed
+        max: u32,
+    },
+    /// The module has more functions than the policy allows
+    TooManyFunctions {
+        /// The number of functions the module imports and defines
+        count: u32,
+        /// The most allowed
+        max: u32,
+    },
+}
+
+impl fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyViolation::ImportNotAllowed { module, name } => {
+                write!(f, "import {}::{} is not allowed", module, name)
+            }
+            PolicyViolation::TooLarge { size, max } => {
+                write!(f, "module is {} bytes, more than {}", size, max)
+            }
+            PolicyViolation::TooManyMemories { count, max } => {
+                write!(f, "module has {} memories, more than {}", count, max)
+            }
+            PolicyViolation::TooManyTables { count, max } => {
+                write!(f, "module has {} tables, more than {}", count, max)
+            }
+            PolicyViolation::TooManyFunctions { count, max } => {
+                write!(f, "module has {} functions, more than {}", count, max)
+            }
+        }
+    }
+}
+
+/// What [`validate_module`] found in a module
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ModuleReport {
+    /// The size of the module, in bytes
+    pub size: usize,
+    /// Every import of the module, in the order the module declares them
+    pub imports: Vec<ModuleImport>,
+    /// The names of the functions the module exports
+    pub exported_functions: Vec<String>,
+    /// The number of linear memories the module imports and defines
+    pub memories: u32,
+    /// The number of tables the module imports and defines
+    pub tables: u32,
+    /// The number of functions the module imports and defines
+    pub functions: u32,
+    /// The ways in which the module breaks the policy, in the order
+    /// they were found
+    pub violations: Vec<PolicyViolation>,
+}
+
+impl ModuleReport {
+    /// Whether the module keeps to the policy
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+/// Parse the WebAssembly module in `bytes` and check it against
+/// `policy`, returning a report of what it contains and of every way it
+/// breaks the policy.
+///
+/// # Errors
+///
+/// Returns an error if `bytes` are not a valid module, including if they
+/// are a precompiled artifact or a component. Breaking the policy is not
+/// an error, see [`ModuleReport::violations`].
+pub fn validate_module(bytes: &[u8], policy: &Policy) -> Result<ModuleReport> {
+    if !bytes.starts_with(b"\0asm") {
+        return Err(new_error!(
+            "only WebAssembly modules can be validated, not precompiled artifacts"
+        ));
+    }
+    let mut report = ModuleReport {
+        size: bytes.len(),
+        ..Default::default()
+    };
+    for payload in Parser::new(0).parse_all(bytes) {
+        let payload = payload.map_err(|e| new_error!("invalid WebAssembly module: {}", e))?;
+        match payload {
+            Payload::Version {
+                encoding: Encoding::Component,
+                ..
+            } => return Err(new_error!("components cannot be validated")),
+            Payload::ImportSection(reader) => {
+                for import in reader.into_imports() {
+                    let import =
+                        import.map_err(|e| new_error!("invalid WebAssembly module: {}", e))?;
+                    let kind = match import.ty {
+                        TypeRef::Func(_) | TypeRef::FuncExact(_) => ImportKind::Function,
+                        TypeRef::Table(_) => ImportKind::Table,
+                        TypeRef::Memory(_) => ImportKind::Memory,
+                        TypeRef::Global(_) => ImportKind::Global,
+                        TypeRef::Tag(_) => ImportKind::Tag,
+                    };
+                    match kind {
+                        ImportKind::Function => report.functions += 1,
+                        ImportKind::Table => report.tables += 1,
+                        ImportKind::Memory => report.memories += 1,
+                        _ => {}
+                    }
+                    report.imports.push(ModuleImport {
+                        module: import.module.to_string(),
+                        name: import.name.to_string(),
+                        kind,
+                    });
+                }
+            }
+            Payload::FunctionSection(reader) => report.functions += reader.count(),
+            Payload::TableSection(reader) => report.tables += reader.count(),
+            Payload::MemorySection(reader) => report.memories += reader.count(),
+            Payload::ExportSection(reader) => {
+                for export in reader {
+                    let export =
+                        export.map_err(|e| new_error!("invalid WebAssembly module: {}", e))?;
+                    if matches!(export.kind, ExternalKind::Func | ExternalKind::FuncExact) {
+                        report.exported_functions.push(export.name.to_string());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    report.violations = violations(&report, policy);
+    Ok(report)
+}
+
+/// The ways in which the module described by `report` breaks `policy`
+fn violations(report: &ModuleReport, policy: &Policy) -> Vec<PolicyViolation> {
+    let mut violations = Vec::new();
+    if let Some(allowed) = &policy.allowed_imports {
+        for import in &report.imports {
+            if !allowed
+                .iter()
+                .any(|a| a.allows(&import.module, &import.name))
+            {
+                violations.push(PolicyViolation::ImportNotAllowed {
+                    module: import.module.clone(),
+                    name: import.name.clone(),
+                });
+            }
+        }
+    }
+    if let Some(max) = policy.max_size.filter(|max| report.size > *max) {
+        violations.push(PolicyViolation::TooLarge {
+            size: report.size,
+            max,
+        });
+    }
+    if let Some(max) = policy.max_memories.filter(|max| report.memories > *max) {
+        violations.push(PolicyViolation::TooManyMemories {
+            count: report.memories,
+            max,
+        });
+    }
+    if let Some(max) = policy.max_tables.filter(|max| report.tables > *max) {
+        violations.push(PolicyViolation::TooManyTables {
+            count: report.tables,
+            max,
+        });
+    }
+    if let Some(max) = policy.max_functions.filter(|max| report.functions > *max) {
+        violations.push(PolicyViolation::TooManyFunctions {
+            count: report.functions,
+            max,
+        });
+    }
+    violations
+}
+
+/// Check the module in `bytes` against `policy` before it is loaded,
+/// returning a single error describing every way it breaks the policy
+pub(crate) fn check(bytes: &[u8], policy: &Policy) -> Result<()> {
+    let report = validate_module(bytes, policy).map_err(|e| {
+        new_error!(
+            "module cannot be checked against the validation policy: {}",
+            e
+        )
+    })?;
+    if !report.is_ok() {
+        let violations = report
+            .violations
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        return Err(new_error!(
+            "module breaks the validation policy: {}",
+            violations.join("; ")
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AllowedImport, ImportKind, Policy, PolicyViolation, check, validate_module};
+
+    // A module importing env.HostPrint and a memory, defining one
+    // function and one table, and exporting the function as "run":
+    //
+    // (module
+    //   (import "env" "HostPrint" (func (param i32) (result i32)))
+    //   (import "env" "memory" (memory 1))
+    //   (table 1 funcref)
+    //   (func (export "run")))
+    const MODULE: &[u8] = &[
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+        0x01, 0x09, 0x02, 0x60, 0x01, 0x7f, 0x01, 0x7f, 0x60, 0x00, 0x00, // types
+        0x02, 0x1f, 0x02, // imports
+        0x03, b'e', b'n', b'v', 0x09, b'H', b'o', b's', b't', b'P', b'r', b'i', b'n', b't', 0x00,
+        0x00, // env.HostPrint
+        0x03, b'e', b'n', b'v', 0x06, b'm', b'e', b'm', b'o', b'r', b'y', 0x02, 0x00,
+        0x01, // env.memory
+        0x03, 0x02, 0x01, 0x01, // functions
+        0x04, 0x04, 0x01, 0x70, 0x00, 0x01, // tables
+        0x07, 0x07, 0x01, 0x03, b'r', b'u', b'n', 0x00, 0x01, // exports
+        0x0a, 0x04, 0x01, 0x02, 0x00, 0x0b, // code
+    ];
+
+    #[test]
+    fn test_validate_module() {
+        let report = validate_module(MODULE, &Policy::default()).unwrap();
+        assert!(report.is_ok());
+        assert_eq!(report.size, MODULE.len());
+        assert_eq!(
+            report
+                .imports
+                .iter()
+                .map(|i| (i.module.as_str(), i.name.as_str(), i.kind))
+                .collect::<Vec<_>>(),
+            [
+                ("env", "HostPrint", ImportKind::Function),
+                ("env", "memory", ImportKind::Memory)
+            ]
+        );
+        assert_eq!(report.exported_functions, ["run"]);
+        assert_eq!(
+            (report.memories, report.tables, report.functions),
+            (1, 1, 2)
+        );
+
+        let policy = Policy {
+            allowed_imports: Some(vec![AllowedImport::item("env", "memory")]),
+            max_size: Some(16),
+            max_memories: Some(1),
+            max_tables: Some(0),
+            max_functions: Some(1),
+        };
+        let report = validate_module(MODULE, &policy).unwrap();
+        assert_eq!(
+            report.violations,
+            [
+                PolicyViolation::ImportNotAllowed {
+                    module: "env".to_string(),
+                    name: "HostPrint".to_string()
+                },
+                PolicyViolation::TooLarge {
+                    size: MODULE.len(),
+                    max: 16
+                },
+                PolicyViolation::TooManyTables { count: 1, max: 0 },
+                PolicyViolation::TooManyFunctions { count: 2, max: 1 },
+            ]
+        );
+        let err = check(MODULE, &policy).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("import env::HostPrint is not allowed; module is"),
+            "{}",
+            err
+        );
+
+        let policy = Policy {
+            allowed_imports: Some(vec![AllowedImport::module("env")]),
+            ..Default::default()
+        };
+        check(MODULE, &policy).unwrap();
+    }
+
+    #[test]
+    fn test_validate_module_rejects_other_inputs() {
+        // A precompiled artifact is an ELF file
+        assert!(validate_module(b"\x7fELF\x02\x01\x01\0", &Policy::default()).is_err());
+        // An empty component
+        assert!(validate_module(b"\0asm\x0d\0\x01\0", &Policy::default()).is_err());
+        assert!(validate_module(&MODULE[..20], &Policy::default()).is_err());
+    }
+}
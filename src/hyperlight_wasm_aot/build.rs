@@ -0,0 +1,67 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+// build.rs
+
+// Records the versions of the two wasmtime dependencies, which are needed to
+// build the version string recorded in artifacts compiled with NaN
+// canonicalization.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use cargo_metadata::MetadataCommand;
+
+fn main() {
+    let metadata = MetadataCommand::new().exec().unwrap();
+    let aot_package = metadata
+        .packages
+        .iter()
+        .find(|p| p.name.as_str() == env!("CARGO_PKG_NAME"))
+        .expect("hyperlight-wasm-aot package not found in cargo metadata");
+    let resolve = metadata
+        .resolve
+        .as_ref()
+        .expect("cargo metadata did not include dependency resolution");
+    let aot_node = resolve
+        .nodes
+        .iter()
+        .find(|n| n.id == aot_package.id)
+        .expect("hyperlight-wasm-aot dependency node not found in cargo metadata");
+
+    let version_of = |dep_name: &str| {
+        let id = &aot_node
+            .deps
+            .iter()
+            .find(|d| d.name == dep_name)
+            .unwrap_or_else(|| panic!("{dep_name} dependency not found in cargo metadata"))
+            .pkg;
+        metadata[id].version.to_string()
+    };
+
+    let out_dir = env::var_os("OUT_DIR").unwrap();
+    let dest_path = Path::new(&out_dir).join("wasmtime_versions.rs");
+    let file_contents = format!(
+        "const WASMTIME_VERSION: &str = {:?};\nconst WASMTIME_LTS_VERSION: &str = {:?};\n",
+        version_of("wasmtime"),
+        version_of("wasmtime_lts"),
+    );
+    fs::write(dest_path, file_contents).unwrap();
+
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=Cargo.toml");
+}
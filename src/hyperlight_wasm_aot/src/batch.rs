@@ -0,0 +1,250 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Precompiling every module and component in a directory tree, in
+//! parallel, with a manifest of the content hashes of the inputs and
+//! artifacts so that build systems can tell which artifacts are stale.
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{CompileOptions, WasmtimeVersion, is_component, precompile};
+
+/// The name of the manifest [`compile_dir`] writes to the output
+/// directory
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// The artifacts compiled from a directory, written to
+/// [`MANIFEST_FILE`] in the output directory
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// The version of wasmtime the artifacts were compiled with
+    pub wasmtime_version: String,
+    /// The target the artifacts were compiled for
+    pub target: String,
+    /// The artifacts, sorted by input path
+    pub artifacts: Vec<ManifestEntry>,
+}
+
+/// An artifact compiled from a directory
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// The path of the input relative to the input directory, with `/`
+    /// separators
+    pub input: String,
+    /// The path of the artifact relative to the output directory, with
+    /// `/` separators
+    pub output: String,
+    /// Whether the input is a component rather than a module
+    pub component: bool,
+    /// The hex-encoded BLAKE3 hash of the input
+    pub input_hash: String,
+    /// The hex-encoded BLAKE3 hash of the artifact, which is what
+    /// `LoadedWasmSandbox::module_hash` reports once it is loaded
+    pub output_hash: String,
+}
+
+/// The result of [`compile_dir`]
+#[derive(Debug, Default)]
+pub struct BatchReport {
+    /// The artifacts that were compiled, as written to the manifest
+    pub manifest: Manifest,
+    /// The inputs that could not be compiled, relative to the input
+    /// directory, and why
+    pub failures: Vec<(PathBuf, String)>,
+}
+
+impl BatchReport {
+    /// Whether every input was compiled
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Find the `.wasm` files under `dir`, returning their paths relative to
+/// `dir` in sorted order
+pub fn find_wasm_files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    fn walk(dir: &Path, relative: &Path, files: &mut Vec<PathBuf>) -> Result<(), String> {
+        let entries = std::fs::read_dir(dir)
+            .map_err(|e| format!("failed to read {}: {}", dir.display(), e))?;
+        for entry in entries {
+            let entry = entry.map_err(|e| format!("failed to read {}: {}", dir.display(), e))?;
+            let path = entry.path();
+            let relative = relative.join(entry.file_name());
+            if path.is_dir() {
+                walk(&path, &relative, files)?;
+            } else if path.is_file() && path.extension().is_some_and(|ext| ext == "wasm") {
+                files.push(relative);
+            }
+        }
+        Ok(())
+    }
+    let mut files = Vec::new();
+    walk(dir, Path::new(""), &mut files)?;
+    files.sort();
+    Ok(files)
+}
+
+/// Precompile every `.wasm` file under `in_dir` into `out_dir`, on up to
+/// `jobs` threads, and write a [`Manifest`] of the artifacts to
+/// [`MANIFEST_FILE`] in `out_dir`.
+///
+/// Each artifact is written to the input's path relative to `in_dir`,
+/// under `out_dir` and with an `.aot` extension. Components are detected
+/// from their contents, so `options.component` is ignored. Inputs that
+/// fail to compile are reported rather than stopping the others, and are
+/// left out of the manifest. An error is only returned if `in_dir`
+/// cannot be read or the manifest cannot be written.
+pub fn compile_dir(
+    in_dir: &Path,
+    out_dir: &Path,
+    options: &CompileOptions,
+    jobs: usize,
+) -> Result<BatchReport, String> {
+    let inputs = find_wasm_files(in_dir)?;
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(inputs.len()));
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, inputs.len().max(1)) {
+            scope.spawn(|| {
+                while let Some(input) = inputs.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let result = compile_file(in_dir, out_dir, input, options);
+                    results.lock().unwrap().push((input, result));
+                }
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut report = BatchReport {
+        manifest: Manifest {
+            wasmtime_version: match options.wasmtime_version {
+                WasmtimeVersion::Latest => crate::WASMTIME_VERSION,
+                WasmtimeVersion::Lts => crate::WASMTIME_LTS_VERSION,
+            }
+            .to_string(),
+            target: options.target().to_string(),
+            artifacts: Vec::new(),
+        },
+        failures: Vec::new(),
+    };
+    for (input, result) in results {
+        match result {
+            Ok(entry) => report.manifest.artifacts.push(entry),
+            Err(e) => report.failures.push((input.clone(), e)),
+        }
+    }
+    std::fs::create_dir_all(out_dir)
+        .map_err(|e| format!("failed to create {}: {}", out_dir.display(), e))?;
+    let manifest_path = out_dir.join(MANIFEST_FILE);
+    let json = serde_json::to_string_pretty(&report.manifest).map_err(|e| e.to_string())?;
+    std::fs::write(&manifest_path, json)
+        .map_err(|e| format!("failed to write {}: {}", manifest_path.display(), e))?;
+    Ok(report)
+}
+
+/// Precompile `input`, relative to `in_dir`, to its place under `out_dir`
+fn compile_file(
+    in_dir: &Path,
+    out_dir: &Path,
+    input: &Path,
+    options: &CompileOptions,
+) -> Result<ManifestEntry, String> {
+    let bytes = std::fs::read(in_dir.join(input)).map_err(|e| e.to_string())?;
+    let component = is_component(&bytes);
+    let options = CompileOptions {
+        component,
+        ..options.clone()
+    };
+    let artifact = precompile(&bytes, &options)?;
+    let output = input.with_extension("aot");
+    let output_path = out_dir.join(&output);
+    if let Some(parent) = output_path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("failed to create {}: {}", parent.display(), e))?;
+    }
+    std::fs::write(&output_path, &artifact)
+        .map_err(|e| format!("failed to write {}: {}", output_path.display(), e))?;
+    Ok(ManifestEntry {
+        input: manifest_path(input),
+        output: manifest_path(&output),
+        component,
+        input_hash: blake3::hash(&bytes).to_hex().to_string(),
+        output_hash: blake3::hash(&artifact).to_hex().to_string(),
+    })
+}
+
+/// `path` with `/` separators whatever the host's separator is
+fn manifest_path(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MANIFEST_FILE, Manifest, compile_dir};
+    use crate::CompileOptions;
+
+    #[test]
+    fn test_compile_dir() {
+        let root = std::env::temp_dir().join(format!("hl_wasm_aot_batch_{}", std::process::id()));
+        let in_dir = root.join("in");
+        let out_dir = root.join("out");
+        std::fs::create_dir_all(in_dir.join("nested")).unwrap();
+
+        // (memory 1 1)
+        let memory = [b"\0asm\x01\0\0\0".as_slice(), &[5, 4, 1, 1, 1, 1]].concat();
+        std::fs::write(in_dir.join("a.wasm"), &memory).unwrap();
+        std::fs::write(in_dir.join("nested").join("b.wasm"), &memory).unwrap();
+        std::fs::write(in_dir.join("nested").join("broken.wasm"), b"not wasm").unwrap();
+        std::fs::write(in_dir.join("notes.txt"), b"ignored").unwrap();
+
+        let report = compile_dir(&in_dir, &out_dir, &CompileOptions::default(), 4).unwrap();
+        assert!(!report.is_ok());
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(
+            report.failures[0].0,
+            std::path::Path::new("nested/broken.wasm")
+        );
+
+        let artifacts = &report.manifest.artifacts;
+        assert_eq!(artifacts.len(), 2);
+        assert_eq!(artifacts[0].input, "a.wasm");
+        assert_eq!(artifacts[1].input, "nested/b.wasm");
+        assert_eq!(artifacts[1].output, "nested/b.aot");
+        assert!(!artifacts[1].component);
+        // The same input gives the same artifact
+        assert_eq!(artifacts[0].input_hash, artifacts[1].input_hash);
+        assert_eq!(artifacts[0].output_hash, artifacts[1].output_hash);
+        let artifact = std::fs::read(out_dir.join("nested").join("b.aot")).unwrap();
+        assert_eq!(
+            blake3::hash(&artifact).to_hex().to_string(),
+            artifacts[1].output_hash
+        );
+
+        let json = std::fs::read_to_string(out_dir.join(MANIFEST_FILE)).unwrap();
+        let manifest: Manifest = serde_json::from_str(&json).unwrap();
+        assert_eq!(manifest, report.manifest);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,247 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Reads the world a component targets, so that build systems can pick
+//! the `WIT_WORLD` to build hyperlight-wasm with and notice when a
+//! component no longer matches the bindings generated for it.
+//!
+//! The world can be written as WIT text, or as the binary WIT package
+//! that `WIT_WORLD` points to, the same as `wasm-tools component wit -w`
+//! writes.
+
+use std::fmt;
+
+use serde::Serialize;
+use wit_parser::{PackageId, Resolve, WorldId, WorldItem as WitWorldItem, WorldKey};
+
+/// What an import or export of a world is
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WorldItemKind {
+    /// An interface, a namespace of functions and types
+    Interface,
+    /// A function imported or exported by the world itself
+    Function,
+    /// A type used by the world's own functions
+    Type,
+}
+
+/// An import or export of a world
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct WorldItem {
+    /// The name of the item, such as `wasi:cli/environment@0.2.0` for an
+    /// interface
+    pub name: String,
+    /// What the item is
+    pub kind: WorldItemKind,
+    /// The names of the functions in the item, if it is an interface
+    pub functions: Vec<String>,
+}
+
+/// The world a component targets
+#[derive(Debug, Serialize)]
+pub struct ComponentWorld {
+    /// The name of the package holding the world
+    pub package: String,
+    /// The name of the world, which is what `WIT_WORLD_NAME` selects
+    pub world: String,
+    /// The world's imports, in the order the component declares them
+    pub imports: Vec<WorldItem>,
+    /// The world's exports, in the order the component declares them
+    pub exports: Vec<WorldItem>,
+    #[serde(skip)]
+    resolve: Resolve,
+    #[serde(skip)]
+    package_id: PackageId,
+}
+
+impl ComponentWorld {
+    /// The world as WIT text, including the packages it uses
+    pub fn to_wit(&self) -> Result<String, String> {
+        let nested = self
+            .resolve
+            .packages
+            .iter()
+            .map(|(id, _)| id)
+            .filter(|id| *id != self.package_id)
+            .collect::<Vec<_>>();
+        let mut printer = wit_component::WitPrinter::default();
+        printer
+            .print(&self.resolve, self.package_id, &nested)
+            .map_err(|e| e.to_string())?;
+        Ok(printer.output.to_string())
+    }
+
+    /// The world as a binary WIT package, which hyperlight-wasm can be
+    /// built with by pointing `WIT_WORLD` to it
+    pub fn to_wasm(&self) -> Result<Vec<u8>, String> {
+        wit_component::encode(&self.resolve, self.package_id).map_err(|e| e.to_string())
+    }
+}
+
+impl fmt::Display for ComponentWorld {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "world {}/{}", self.package, self.world)?;
+        for (heading, items) in [("imports", &self.imports), ("exports", &self.exports)] {
+            write!(f, "\n{}:", heading)?;
+            if items.is_empty() {
+                write!(f, " none")?;
+            }
+            for item in items {
+                let kind = match item.kind {
+                    WorldItemKind::Interface => "interface",
+                    WorldItemKind::Function => "function",
+                    WorldItemKind::Type => "type",
+                };
+                write!(f, "\n  {} {}", kind, item.name)?;
+                for function in &item.functions {
+                    write!(f, "\n    {}", function)?;
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Read the world the component in `wasm` targets. Core modules and
+/// precompiled artifacts are not supported.
+pub fn inspect(wasm: &[u8]) -> Result<ComponentWorld, String> {
+    if !crate::is_component(wasm) {
+        return Err("only WebAssembly components can be inspected".to_string());
+    }
+    let (resolve, world_id) = match wit_component::decode(wasm).map_err(|e| e.to_string())? {
+        wit_component::DecodedWasm::Component(resolve, world_id) => (resolve, world_id),
+        wit_component::DecodedWasm::WitPackage(..) => {
+            return Err("the input is a WIT package rather than a component".to_string());
+        }
+    };
+    let world = &resolve.worlds[world_id];
+    let package_id = world
+        .package
+        .ok_or_else(|| "the component's world is not in a package".to_string())?;
+    Ok(ComponentWorld {
+        package: resolve.packages[package_id].name.to_string(),
+        world: world.name.clone(),
+        imports: items(&resolve, world_id, true),
+        exports: items(&resolve, world_id, false),
+        package_id,
+        resolve,
+    })
+}
+
+/// The imports or exports of a world
+fn items(resolve: &Resolve, world_id: WorldId, imports: bool) -> Vec<WorldItem> {
+    let world = &resolve.worlds[world_id];
+    let items = if imports {
+        &world.imports
+    } else {
+        &world.exports
+    };
+    items
+        .iter()
+        .map(|(key, item): (&WorldKey, &WitWorldItem)| {
+            let name = resolve.name_world_key(key);
+            match item {
+                WitWorldItem::Interface { id, .. } => WorldItem {
+                    name,
+                    kind: WorldItemKind::Interface,
+                    functions: resolve.interfaces[*id].functions.keys().cloned().collect(),
+                },
+                WitWorldItem::Function(_) => WorldItem {
+                    name,
+                    kind: WorldItemKind::Function,
+                    functions: Vec::new(),
+                },
+                WitWorldItem::Type(_) => WorldItem {
+                    name,
+                    kind: WorldItemKind::Type,
+                    functions: Vec::new(),
+                },
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use wit_parser::Resolve;
+
+    use super::{WorldItemKind, inspect};
+
+    #[test]
+    fn test_inspect() {
+        let mut resolve = Resolve::default();
+        let package = resolve
+            .push_str(
+                "demo.wit",
+                "package example:demo;
+                 interface host { log: func(); }
+                 world demo { import host; export run: func(); }",
+            )
+            .unwrap();
+        let world = resolve.select_world(&[package], Some("demo")).unwrap();
+
+        // (import "example:demo/host" "log" (func))
+        // (func (export "run"))
+        let mut module = [
+            b"\0asm\x01\0\0\0".as_slice(),
+            &[1, 4, 1, 0x60, 0, 0],
+            &[2, 25, 1, 17],
+            b"example:demo/host",
+            &[3],
+            b"log",
+            &[0, 0],
+            &[3, 2, 1, 0],
+            &[7, 7, 1, 3],
+            b"run",
+            &[0, 1],
+            &[10, 4, 1, 2, 0, 0x0b],
+        ]
+        .concat();
+        wit_component::embed_component_metadata(
+            &mut module,
+            &resolve,
+            world,
+            wit_component::StringEncoding::UTF8,
+        )
+        .unwrap();
+        let component = wit_component::ComponentEncoder::default()
+            .module(&module)
+            .unwrap()
+            .validate(true)
+            .encode()
+            .unwrap();
+
+        let world = inspect(&component).unwrap();
+        assert_eq!(world.imports.len(), 1);
+        assert_eq!(world.imports[0].name, "example:demo/host");
+        assert_eq!(world.imports[0].kind, WorldItemKind::Interface);
+        assert_eq!(world.imports[0].functions, ["log"]);
+        assert_eq!(world.exports.len(), 1);
+        assert_eq!(world.exports[0].name, "run");
+        assert_eq!(world.exports[0].kind, WorldItemKind::Function);
+        assert!(world.to_wit().unwrap().contains("log: func();"));
+        match wit_component::decode(&world.to_wasm().unwrap()).unwrap() {
+            wit_component::DecodedWasm::WitPackage(resolve, package) => {
+                assert_eq!(resolve.packages[package].name.to_string(), world.package);
+            }
+            wit_component::DecodedWasm::Component(..) => panic!("expected a WIT package"),
+        }
+
+        assert!(inspect(&module).is_err());
+    }
+}
@@ -0,0 +1,463 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Precompile WebAssembly modules and components for hyperlight-wasm,
+//! and check modules against the host functions a sandbox provides
+//! before they are deployed.
+
+use std::fmt::Display;
+
+use clap::ValueEnum;
+use wasmtime::{Config, Engine, ModuleVersionStrategy};
+
+pub mod batch;
+pub mod inspect;
+pub mod preflight;
+pub mod validate;
+
+include!(concat!(env!("OUT_DIR"), "/wasmtime_versions.rs"));
+
+/// Appended to the wasmtime version recorded in artifacts compiled with NaN
+/// canonicalization. This must match the suffix used by hyperlight-wasm-runtime,
+/// which refuses to load artifacts whose setting differs from the sandbox's.
+const NAN_CANONICALIZATION_VERSION_SUFFIX: &str = "+nan-canonicalization";
+
+/// A target that artifacts can be compiled for
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SupportedTarget {
+    /// Native code for hyperlight-wasm-runtime on x86_64
+    X86_64UnknownNone,
+    /// Bytecode for wasmtime's pulley interpreter
+    WasmtimePulley64,
+}
+
+impl Display for SupportedTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SupportedTarget::X86_64UnknownNone => write!(f, "x86_64-unknown-none"),
+            SupportedTarget::WasmtimePulley64 => write!(f, "pulley64"),
+        }
+    }
+}
+
+/// The version of wasmtime to compile with, which must match the version
+/// in the hyperlight-wasm-runtime that loads the artifact
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum WasmtimeVersion {
+    /// The LTS release of wasmtime
+    #[default]
+    Lts,
+    /// The latest release of wasmtime
+    Latest,
+}
+
+/// The WebAssembly proposals an artifact is compiled with. Sandboxes must
+/// be built with the same proposals enabled to load the artifact.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct WasmFeatures {
+    /// The SIMD proposal. Disabling it disables relaxed SIMD too.
+    pub simd: bool,
+    /// The relaxed SIMD proposal
+    pub relaxed_simd: bool,
+    /// The bulk memory proposal
+    pub bulk_memory: bool,
+    /// The tail call proposal
+    pub tail_call: bool,
+    /// The multi-memory proposal
+    pub multi_memory: bool,
+}
+
+impl Default for WasmFeatures {
+    fn default() -> Self {
+        Self {
+            simd: true,
+            relaxed_simd: true,
+            bulk_memory: true,
+            tail_call: true,
+            multi_memory: true,
+        }
+    }
+}
+
+/// How much the compiler optimizes the artifact's code
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum OptLevel {
+    /// No optimizations
+    None,
+    /// Optimize for speed
+    #[default]
+    Speed,
+    /// Optimize for speed and size
+    SpeedAndSize,
+}
+
+/// How to compile a module or component, see [`precompile`]
+#[derive(Clone, Debug, Default)]
+pub struct CompileOptions {
+    /// Compile a component rather than a module
+    pub component: bool,
+    /// Compile with debug info and optimizations disabled
+    pub debug: bool,
+    /// Omit the address map and native unwind info for smaller artifacts
+    pub minimal: bool,
+    /// Compile for the pulley64 target
+    pub pulley: bool,
+    /// Canonicalize NaNs, so that floating-point results are
+    /// reproducible across hosts. Sandboxes must be built with NaN
+    /// canonicalization enabled to load the artifact.
+    pub canonicalize_nans: bool,
+    /// Check for epoch deadlines in function calls and loops, so that
+    /// guest calls can be stopped at a deadline. Sandboxes must be built
+    /// with epoch interruption enabled to load the artifact.
+    pub epoch_interruption: bool,
+    /// Make relaxed SIMD instructions behave the same on every host.
+    /// Sandboxes must be built with deterministic relaxed SIMD enabled to
+    /// load the artifact.
+    pub deterministic_relaxed_simd: bool,
+    /// The WebAssembly proposals to enable
+    pub wasm_features: WasmFeatures,
+    /// The optimization level, which `debug` overrides
+    pub opt_level: OptLevel,
+    /// The version of wasmtime to compile with
+    pub wasmtime_version: WasmtimeVersion,
+}
+
+impl CompileOptions {
+    /// The target the artifact is compiled for
+    pub fn target(&self) -> SupportedTarget {
+        if self.pulley {
+            SupportedTarget::WasmtimePulley64
+        } else {
+            SupportedTarget::X86_64UnknownNone
+        }
+    }
+}
+
+/// Whether `bytes` is a WebAssembly component, as opposed to a core
+/// module or something that is not WebAssembly at all
+pub fn is_component(bytes: &[u8]) -> bool {
+    wasmparser::Parser::is_component(bytes)
+}
+
+/// Whether `bytes` is a WebAssembly module or component that has not
+/// been precompiled
+pub fn is_wasm(bytes: &[u8]) -> bool {
+    wasmparser::Parser::is_core_wasm(bytes) || wasmparser::Parser::is_component(bytes)
+}
+
+/// Check that `bytes` does not use shared memory or import the
+/// wasi-threads `thread-spawn` function. hyperlight-wasm-runtime runs
+/// guests on a single thread and its wasmtime is built without support
+/// for shared memory, so modules built with `-pthread` or for
+/// `wasm32-wasip1-threads` are rejected with an error saying so rather
+/// than failing validation.
+fn check_no_threads(bytes: &[u8]) -> Result<(), String> {
+    let unsupported = |what: &str| {
+        Err(format!(
+            "{} is not supported: modules built with -pthread or for \
+             wasm32-wasip1-threads cannot run in hyperlight-wasm, rebuild them \
+             without threads",
+            what
+        ))
+    };
+    for payload in wasmparser::Parser::new(0).parse_all(bytes) {
+        match payload.map_err(|e| e.to_string())? {
+            wasmparser::Payload::MemorySection(reader) => {
+                for memory in reader {
+                    if memory.map_err(|e| e.to_string())?.shared {
+                        return unsupported("shared memory");
+                    }
+                }
+            }
+            wasmparser::Payload::ImportSection(reader) => {
+                for import in reader.into_imports() {
+                    let import = import.map_err(|e| e.to_string())?;
+                    match import.ty {
+                        wasmparser::TypeRef::Memory(memory) if memory.shared => {
+                            return unsupported("shared memory");
+                        }
+                        wasmparser::TypeRef::Func(_)
+                            if import.module == "wasi" && import.name == "thread-spawn" =>
+                        {
+                            return unsupported("wasi-threads");
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Precompile the WebAssembly module or component in `bytes`, returning
+/// the artifact to load into a sandbox
+pub fn precompile(bytes: &[u8], options: &CompileOptions) -> Result<Vec<u8>, String> {
+    check_no_threads(bytes)?;
+    match options.wasmtime_version {
+        WasmtimeVersion::Latest => {
+            let engine = Engine::new(&latest_config(options)?).map_err(|e| e.to_string())?;
+            if options.component {
+                engine.precompile_component(bytes)
+            } else {
+                engine.precompile_module(bytes)
+            }
+            .map_err(|e| e.to_string())
+        }
+        WasmtimeVersion::Lts => precompile_bytes_lts(bytes, options),
+    }
+}
+
+/// The config of the latest wasmtime version for `options`
+fn latest_config(options: &CompileOptions) -> Result<Config, String> {
+    let mut config = get_config(options.debug, options.minimal, &options.target());
+    let features = options.wasm_features;
+    config.wasm_simd(features.simd);
+    config.wasm_relaxed_simd(features.simd && features.relaxed_simd);
+    config.wasm_bulk_memory(features.bulk_memory);
+    config.wasm_tail_call(features.tail_call);
+    config.wasm_multi_memory(features.multi_memory);
+    #[cfg(feature = "gc")]
+    config.wasm_function_references(true).wasm_gc(true);
+    if !options.debug {
+        config.cranelift_opt_level(match options.opt_level {
+            OptLevel::None => wasmtime::OptLevel::None,
+            OptLevel::Speed => wasmtime::OptLevel::Speed,
+            OptLevel::SpeedAndSize => wasmtime::OptLevel::SpeedAndSize,
+        });
+    }
+    config.epoch_interruption(options.epoch_interruption);
+    config.relaxed_simd_deterministic(options.deterministic_relaxed_simd);
+    if options.canonicalize_nans {
+        config.cranelift_nan_canonicalization(true);
+        config
+            .module_version(ModuleVersionStrategy::Custom(format!(
+                "{}{}",
+                WASMTIME_VERSION, NAN_CANONICALIZATION_VERSION_SUFFIX
+            )))
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(config)
+}
+
+/// Precompile bytes using the LTS wasmtime version
+fn precompile_bytes_lts(bytes: &[u8], options: &CompileOptions) -> Result<Vec<u8>, String> {
+    let engine = wasmtime_lts::Engine::new(&lts_config(options)?).map_err(|e| e.to_string())?;
+    if options.component {
+        engine.precompile_component(bytes)
+    } else {
+        engine.precompile_module(bytes)
+    }
+    .map_err(|e| e.to_string())
+}
+
+/// The config of the LTS wasmtime version for `options`
+fn lts_config(options: &CompileOptions) -> Result<wasmtime_lts::Config, String> {
+    let mut config = wasmtime_lts::Config::new();
+    config
+        .target(&options.target().to_string())
+        .map_err(|e| e.to_string())?;
+    if options.debug {
+        config.debug_info(true);
+        config.cranelift_opt_level(wasmtime_lts::OptLevel::None);
+    } else {
+        config.cranelift_opt_level(match options.opt_level {
+            OptLevel::None => wasmtime_lts::OptLevel::None,
+            OptLevel::Speed => wasmtime_lts::OptLevel::Speed,
+            OptLevel::SpeedAndSize => wasmtime_lts::OptLevel::SpeedAndSize,
+        });
+    }
+    let features = options.wasm_features;
+    config.wasm_simd(features.simd);
+    config.wasm_relaxed_simd(features.simd && features.relaxed_simd);
+    config.wasm_bulk_memory(features.bulk_memory);
+    config.wasm_tail_call(features.tail_call);
+    config.wasm_multi_memory(features.multi_memory);
+    #[cfg(feature = "gc")]
+    config.wasm_function_references(true).wasm_gc(true);
+    if options.minimal {
+        config.generate_address_map(false);
+        config.native_unwind_info(false);
+    }
+    config.epoch_interruption(options.epoch_interruption);
+    config.relaxed_simd_deterministic(options.deterministic_relaxed_simd);
+    if options.canonicalize_nans {
+        config.cranelift_nan_canonicalization(true);
+        config
+            .module_version(wasmtime_lts::ModuleVersionStrategy::Custom(format!(
+                "{}{}",
+                WASMTIME_LTS_VERSION, NAN_CANONICALIZATION_VERSION_SUFFIX
+            )))
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(config)
+}
+
+/// Returns a new `Config` for the Wasmtime engine with additional settings for AOT compilation.
+pub fn get_config(debug: bool, minimal: bool, target: &SupportedTarget) -> Config {
+    let mut config = Config::new();
+
+    // Compile for the pulley64 target if specified
+    match target {
+        SupportedTarget::X86_64UnknownNone => {
+            config.target("x86_64-unknown-none").unwrap();
+            // Enable x86_float_abi_ok only for the latest Wasmtime version.
+            // Safety:
+            // We are using hyperlight cargo to build the guest which
+            // sets the Rust target to be compiled with the hard-float ABI manually via
+            // `-Zbuild-std` and a custom target JSON configuration
+            // See https://github.com/bytecodealliance/wasmtime/pull/11553
+            unsafe { config.x86_float_abi_ok(true) };
+        }
+        SupportedTarget::WasmtimePulley64 => {
+            config.target("pulley64").unwrap();
+        }
+    }
+
+    // Enable the default features for the Wasmtime engine.
+    if debug {
+        config.debug_info(true);
+        config.cranelift_opt_level(wasmtime::OptLevel::None);
+    }
+
+    if minimal {
+        config.generate_address_map(false);
+        config.native_unwind_info(false);
+    }
+
+    config
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CompileOptions, WasmFeatures, WasmtimeVersion, precompile};
+
+    #[test]
+    fn test_precompile_rejects_threads() {
+        const HEADER: &[u8] = b"\0asm\x01\0\0\0";
+        let options = CompileOptions::default();
+
+        // (memory 1 1 shared)
+        let shared_memory = [HEADER, &[5, 4, 1, 3, 1, 1]].concat();
+        let err = precompile(&shared_memory, &options).unwrap_err();
+        assert!(err.starts_with("shared memory is not supported"), "{}", err);
+
+        // (import "wasi" "thread-spawn" (func (param i32) (result i32)))
+        let thread_spawn = [
+            HEADER,
+            &[1, 6, 1, 0x60, 1, 0x7f, 1, 0x7f],
+            &[2, 21, 1, 4],
+            b"wasi",
+            &[12],
+            b"thread-spawn",
+            &[0, 0],
+        ]
+        .concat();
+        let err = precompile(&thread_spawn, &options).unwrap_err();
+        assert!(err.starts_with("wasi-threads is not supported"), "{}", err);
+
+        // (memory 1 1)
+        let memory = [HEADER, &[5, 4, 1, 1, 1, 1]].concat();
+        precompile(&memory, &options).unwrap();
+    }
+
+    #[test]
+    fn test_precompile_wasm_features() {
+        // (memory 1) (memory 1)
+        let two_memories = [b"\0asm\x01\0\0\0".as_slice(), &[5, 5, 2, 0, 1, 0, 1]].concat();
+        precompile(&two_memories, &CompileOptions::default()).unwrap();
+
+        let options = CompileOptions {
+            wasm_features: WasmFeatures {
+                multi_memory: false,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(precompile(&two_memories, &options).is_err());
+    }
+
+    #[test]
+    fn test_precompile_tail_calls() {
+        // (func $loop (param i32 i32) (result i32)
+        //   (if (result i32) (i32.eqz (local.get 0))
+        //     (then (local.get 1))
+        //     (else (return_call $loop
+        //       (i32.sub (local.get 0) (i32.const 1))
+        //       (i32.add (local.get 1) (i32.const 1))))))
+        let tail_call = [
+            b"\0asm\x01\0\0\0".as_slice(),
+            &[1, 7, 1, 0x60, 2, 0x7f, 0x7f, 1, 0x7f],
+            &[3, 2, 1, 0],
+            &[10, 25, 1, 23, 0, 0x20, 0, 0x45, 0x04, 0x7f, 0x20, 1, 0x05],
+            &[
+                0x20, 0, 0x41, 1, 0x6b, 0x20, 1, 0x41, 1, 0x6a, 0x12, 0, 0x0b, 0x0b,
+            ],
+        ]
+        .concat();
+        for options in [
+            CompileOptions::default(),
+            CompileOptions {
+                wasmtime_version: WasmtimeVersion::Latest,
+                ..Default::default()
+            },
+            CompileOptions {
+                pulley: true,
+                ..Default::default()
+            },
+        ] {
+            precompile(&tail_call, &options).unwrap();
+        }
+
+        let options = CompileOptions {
+            wasm_features: WasmFeatures {
+                tail_call: false,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(precompile(&tail_call, &options).is_err());
+    }
+
+    #[test]
+    fn test_precompile_gc() {
+        // (type $point (struct (field i32)))
+        // (func (result i32)
+        //   (struct.get $point 0 (struct.new $point (i32.const 7))))
+        let gc = [
+            b"\0asm\x01\0\0\0".as_slice(),
+            &[1, 9, 2, 0x5f, 1, 0x7f, 0, 0x60, 0, 1, 0x7f],
+            &[3, 2, 1, 1],
+            &[10, 13, 1, 11, 0, 0x41, 7, 0xfb, 0, 0, 0xfb, 2, 0, 0, 0x0b],
+        ]
+        .concat();
+        for options in [
+            CompileOptions::default(),
+            CompileOptions {
+                wasmtime_version: WasmtimeVersion::Latest,
+                ..Default::default()
+            },
+        ] {
+            assert_eq!(
+                precompile(&gc, &options).is_ok(),
+                cfg!(feature = "gc"),
+                "{:?}",
+                options.wasmtime_version
+            );
+        }
+    }
+}
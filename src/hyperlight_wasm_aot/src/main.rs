@@ -14,36 +14,16 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use std::fmt::Display;
 use std::path::Path;
 
-use cargo_metadata::{MetadataCommand, Package};
-use cargo_util_schemas::manifest::PackageName;
-use clap::{Parser, Subcommand, ValueEnum};
-use object::read::elf::ElfFile64;
-use object::{Architecture, Endianness, FileFlags, Object};
-use wasmtime::{Config, Engine, Module, OptLevel, Precompiled};
-
-#[derive(Debug)]
-enum SupportedTarget {
-    X86_64UnknownNone,
-    WasmtimePulley64,
-}
-
-impl Display for SupportedTarget {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            SupportedTarget::X86_64UnknownNone => write!(f, "x86_64-unknown-none"),
-            SupportedTarget::WasmtimePulley64 => write!(f, "pulley64"),
-        }
-    }
-}
-
-#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
-enum WasmtimeVersion {
-    Lts,
-    Latest,
-}
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use hyperlight_wasm_aot::batch::{self, MANIFEST_FILE};
+use hyperlight_wasm_aot::inspect;
+use hyperlight_wasm_aot::preflight::{self, GuestAbi, HostManifest};
+use hyperlight_wasm_aot::validate::{self, ArtifactKind, RuntimeExpectation};
+use hyperlight_wasm_aot::{
+    CompileOptions, OptLevel, SupportedTarget, WasmFeatures, WasmtimeVersion, precompile,
+};
 
 #[derive(Parser)]
 #[command(name = "hyperlight-wasm-aot")]
@@ -56,14 +36,31 @@ struct Cli {
 
 #[derive(Subcommand)]
 enum Commands {
-    /// Precompile a WebAssembly module or component for Wasmtime
+    /// Precompile a WebAssembly module or component for Wasmtime, or every
+    /// .wasm file in a directory
     Compile {
         /// The input WebAssembly file
-        input: String,
+        #[arg(required_unless_present = "dir")]
+        input: Option<String>,
 
         /// The output file path (defaults to input with .aot extension)
         output: Option<String>,
 
+        /// Compile every .wasm file under this directory instead of a single
+        /// file. Components are detected from their contents
+        #[arg(long, conflicts_with_all = ["input", "output", "component"], requires = "out_dir")]
+        dir: Option<String>,
+
+        /// The directory to write the artifacts compiled with --dir to, at the
+        /// inputs' relative paths, along with a manifest.json of their hashes
+        #[arg(long, requires = "dir")]
+        out_dir: Option<String>,
+
+        /// The number of files to compile at once with --dir (defaults to the
+        /// number of CPUs)
+        #[arg(long, requires = "dir")]
+        jobs: Option<usize>,
+
         /// Compile a component rather than a module
         #[arg(long)]
         component: bool,
@@ -80,12 +77,140 @@ enum Commands {
         #[arg(long)]
         pulley: bool,
 
+        /// Canonicalize NaNs so floating-point results are reproducible across hosts.
+        /// Sandboxes must be built with NaN canonicalization enabled to load the output
+        #[arg(long)]
+        canonicalize_nans: bool,
+
+        /// Check for epoch deadlines in function calls and loops. Sandboxes must be
+        /// built with epoch interruption enabled to load the output
+        #[arg(long)]
+        epoch_interruption: bool,
+
+        /// Make relaxed SIMD instructions behave the same on every host. Sandboxes
+        /// must be built with deterministic relaxed SIMD enabled to load the output
+        #[arg(long)]
+        deterministic_relaxed_simd: bool,
+
+        #[command(flatten)]
+        features: FeatureArgs,
+
         /// Wasmtime version used for precompilation
         #[arg(long, value_enum, default_value = "lts")]
         wasmtime_version: WasmtimeVersion,
     },
 
-    /// Check which Wasmtime version was used to precompile a module
+    /// Precompile a WebAssembly module, checking that every function it
+    /// imports is provided by the runtime or by the host
+    Preflight {
+        /// The input WebAssembly module
+        input: String,
+
+        /// The output file path (defaults to input with .aot extension)
+        output: Option<String>,
+
+        /// The host functions the sandbox provides, as written by
+        /// HostFunctionManifest::to_json
+        #[arg(long)]
+        host_manifest: String,
+
+        /// The ABI the module was built for
+        #[arg(long, value_enum, default_value = "default")]
+        abi: GuestAbi,
+
+        /// Precompile with debug and disable optimizations
+        #[arg(long)]
+        debug: bool,
+
+        /// Disable address map and native unwind info for smaller binaries
+        #[arg(long)]
+        minimal: bool,
+
+        /// Pre-compile for the pulley64 target
+        #[arg(long)]
+        pulley: bool,
+
+        /// Canonicalize NaNs so floating-point results are reproducible across hosts.
+        /// Sandboxes must be built with NaN canonicalization enabled to load the output
+        #[arg(long)]
+        canonicalize_nans: bool,
+
+        /// Check for epoch deadlines in function calls and loops. Sandboxes must be
+        /// built with epoch interruption enabled to load the output
+        #[arg(long)]
+        epoch_interruption: bool,
+
+        /// Make relaxed SIMD instructions behave the same on every host. Sandboxes
+        /// must be built with deterministic relaxed SIMD enabled to load the output
+        #[arg(long)]
+        deterministic_relaxed_simd: bool,
+
+        #[command(flatten)]
+        features: FeatureArgs,
+
+        /// Wasmtime version used for precompilation
+        #[arg(long, value_enum, default_value = "lts")]
+        wasmtime_version: WasmtimeVersion,
+    },
+
+    /// Check that a precompiled module or component will load into the runtime
+    /// embedded in hyperlight-wasm, printing a JSON report and exiting with an
+    /// error if it will not
+    Validate {
+        /// The precompiled file to check
+        file: String,
+
+        /// The hyperlight-wasm-runtime binary to check against, to use the exact
+        /// wasmtime version it embeds rather than the one selected by
+        /// --wasmtime-version
+        #[arg(long)]
+        runtime: Option<String>,
+
+        /// Sandboxes run a runtime built with the gdb feature
+        #[arg(long)]
+        debug: bool,
+
+        /// Sandboxes run the pulley64 runtime
+        #[arg(long)]
+        pulley: bool,
+
+        /// Sandboxes are built with NaN canonicalization enabled
+        #[arg(long)]
+        canonicalize_nans: bool,
+
+        /// Sandboxes are built with epoch interruption enabled
+        #[arg(long)]
+        epoch_interruption: bool,
+
+        /// Sandboxes are built with deterministic relaxed SIMD enabled
+        #[arg(long)]
+        deterministic_relaxed_simd: bool,
+
+        #[command(flatten)]
+        features: FeatureArgs,
+
+        /// Wasmtime version of the runtime
+        #[arg(long, value_enum, default_value = "lts")]
+        wasmtime_version: WasmtimeVersion,
+    },
+
+    /// Print the imports and exports of the world a component targets
+    Inspect {
+        /// The component to inspect
+        input: String,
+
+        /// How to print the world
+        #[arg(long, value_enum, default_value = "text")]
+        format: InspectFormat,
+
+        /// Write the world as a binary WIT package to this path, for use as
+        /// WIT_WORLD when building hyperlight-wasm
+        #[arg(long)]
+        emit_world: Option<String>,
+    },
+
+    /// Check which Wasmtime version was used to precompile a module or component,
+    /// exiting with an error if it will not load into the selected runtime flavor
     CheckWasmtimeVersion {
         /// The precompiled file to check
         file: String,
@@ -94,70 +219,60 @@ enum Commands {
         #[arg(long)]
         debug: bool,
 
-        /// Wasmtime version used to validate the precompiled module
+        /// Wasmtime version of the runtime to check the precompiled file against
         #[arg(long, value_enum, default_value = "lts")]
         wasmtime_version: WasmtimeVersion,
     },
 }
 
-/// Precompile bytes using the LTS wasmtime version
-fn precompile_bytes_lts(
-    bytes: &[u8],
-    debug: bool,
-    minimal: bool,
-    pulley: bool,
-    is_component: bool,
-) -> Vec<u8> {
-    let mut config = wasmtime_lts::Config::new();
-    if pulley {
-        config.target("pulley64").unwrap();
-    } else {
-        config.target("x86_64-unknown-none").unwrap();
-    }
-    if debug {
-        config.debug_info(true);
-        config.cranelift_opt_level(wasmtime_lts::OptLevel::None);
-    }
-    if minimal {
-        config.generate_address_map(false);
-        config.native_unwind_info(false);
-    }
-    let engine = wasmtime_lts::Engine::new(&config).unwrap();
-    if is_component {
-        engine.precompile_component(bytes).unwrap()
-    } else {
-        engine.precompile_module(bytes).unwrap()
-    }
+/// How `inspect` prints a component's world
+#[derive(Clone, Copy, ValueEnum)]
+enum InspectFormat {
+    /// A summary of the imports and exports
+    Text,
+    /// The imports and exports as JSON
+    Json,
+    /// The world and the packages it uses as WIT
+    Wit,
 }
 
-/// Detect and deserialize using the LTS wasmtime version
-fn detect_and_deserialize_lts(bytes: &[u8], debug: bool, file: &str) {
-    let mut config = wasmtime_lts::Config::new();
-    config.target("x86_64-unknown-none").unwrap();
-    if debug {
-        config.debug_info(true);
-        config.cranelift_opt_level(wasmtime_lts::OptLevel::None);
-    }
-    let engine = wasmtime_lts::Engine::new(&config).unwrap();
-    match wasmtime_lts::Engine::detect_precompiled(bytes) {
-        Some(wasmtime_lts::Precompiled::Module) => {
-            println!("The file is a valid AOT compiled Wasmtime module");
-            match unsafe { wasmtime_lts::Module::deserialize(&engine, bytes) } {
-                Ok(_) => println!(
-                    "File {} was AOT compiled with a compatible wasmtime version (LTS)",
-                    file
-                ),
-                Err(e) => eprintln!("{}", e),
-            }
-        }
-        Some(wasmtime_lts::Precompiled::Component) => {
-            println!("The file is an AOT compiled Wasmtime component")
-        }
-        None => {
-            eprintln!(
-                "Error - {} is not a valid AOT compiled Wasmtime module or component",
-                file
-            );
+/// The WebAssembly proposals and optimization level to compile with
+#[derive(Args)]
+struct FeatureArgs {
+    /// Disable the SIMD proposal, and with it relaxed SIMD. Sandboxes must be
+    /// built with the same WebAssembly features to load the output
+    #[arg(long)]
+    disable_simd: bool,
+
+    /// Disable the relaxed SIMD proposal
+    #[arg(long)]
+    disable_relaxed_simd: bool,
+
+    /// Disable the bulk memory proposal
+    #[arg(long)]
+    disable_bulk_memory: bool,
+
+    /// Disable the tail call proposal
+    #[arg(long)]
+    disable_tail_call: bool,
+
+    /// Disable the multi-memory proposal
+    #[arg(long)]
+    disable_multi_memory: bool,
+
+    /// The optimization level, ignored with --debug
+    #[arg(long, value_enum, default_value = "speed")]
+    opt_level: OptLevel,
+}
+
+impl FeatureArgs {
+    fn wasm_features(&self) -> WasmFeatures {
+        WasmFeatures {
+            simd: !self.disable_simd,
+            relaxed_simd: !self.disable_relaxed_simd,
+            bulk_memory: !self.disable_bulk_memory,
+            tail_call: !self.disable_tail_call,
+            multi_memory: !self.disable_multi_memory,
         }
     }
 }
@@ -169,70 +284,192 @@ fn main() {
         Commands::Compile {
             input,
             output,
+            dir,
+            out_dir,
+            jobs,
             component,
             debug,
             minimal,
             pulley,
+            canonicalize_nans,
+            epoch_interruption,
+            deterministic_relaxed_simd,
+            features,
             wasmtime_version,
         } => {
-            let outfile = match output {
-                Some(s) => s,
-                None => {
-                    let mut path = Path::new(&input).to_path_buf();
-                    path.set_extension("aot");
-                    path.to_str().unwrap().to_string()
-                }
+            let options = CompileOptions {
+                component,
+                debug,
+                minimal,
+                pulley,
+                canonicalize_nans,
+                epoch_interruption,
+                deterministic_relaxed_simd,
+                wasm_features: features.wasm_features(),
+                opt_level: features.opt_level,
+                wasmtime_version,
             };
-
-            match wasmtime_version {
-                WasmtimeVersion::Latest => {
-                    let target = if pulley {
-                        SupportedTarget::WasmtimePulley64
-                    } else {
-                        SupportedTarget::X86_64UnknownNone
-                    };
-                    if debug {
-                        println!(
-                            "Aot Compiling {} to [{}]: {} with debug info and optimizations off (latest wasmtime)",
-                            input, target, outfile
-                        );
-                    } else {
-                        println!(
-                            "Aot Compiling {} to [{}]: {} (latest wasmtime)",
-                            input, target, outfile
-                        );
-                    }
-                    let config = get_config(debug, minimal, &target);
-                    let engine = Engine::new(&config).unwrap();
-                    let bytes = std::fs::read(&input).unwrap();
-                    let serialized = if component {
-                        engine.precompile_component(&bytes).unwrap()
-                    } else {
-                        engine.precompile_module(&bytes).unwrap()
-                    };
-                    std::fs::write(outfile, serialized).unwrap();
+            let version = match wasmtime_version {
+                WasmtimeVersion::Latest => "latest",
+                WasmtimeVersion::Lts => "LTS",
+            };
+            if let (Some(dir), Some(out_dir)) = (dir, out_dir) {
+                compile_dir(&dir, &out_dir, jobs, &options, version);
+                return;
+            }
+            let input = input.unwrap();
+            let outfile = aot_path(&input, output);
+            if debug {
+                println!(
+                    "Aot Compiling {} to [{}]: {} with debug info and optimizations off ({} wasmtime)",
+                    input,
+                    options.target(),
+                    outfile,
+                    version
+                );
+            } else {
+                println!(
+                    "Aot Compiling {} to [{}]: {} ({} wasmtime)",
+                    input,
+                    options.target(),
+                    outfile,
+                    version
+                );
+            }
+            let bytes = std::fs::read(&input).unwrap();
+            let serialized = precompile(&bytes, &options).unwrap_or_else(|e| {
+                eprintln!("Error - failed to compile {}: {}", input, e);
+                std::process::exit(1)
+            });
+            std::fs::write(outfile, serialized).unwrap();
+        }
+        Commands::Preflight {
+            input,
+            output,
+            host_manifest,
+            abi,
+            debug,
+            minimal,
+            pulley,
+            canonicalize_nans,
+            epoch_interruption,
+            deterministic_relaxed_simd,
+            features,
+            wasmtime_version,
+        } => {
+            let outfile = aot_path(&input, output);
+            let options = CompileOptions {
+                component: false,
+                debug,
+                minimal,
+                pulley,
+                canonicalize_nans,
+                epoch_interruption,
+                deterministic_relaxed_simd,
+                wasm_features: features.wasm_features(),
+                opt_level: features.opt_level,
+                wasmtime_version,
+            };
+            let manifest = std::fs::read_to_string(&host_manifest)
+                .map_err(|e| e.to_string())
+                .and_then(|json| HostManifest::from_json(&json))
+                .unwrap_or_else(|e| {
+                    eprintln!("Error - failed to read {}: {}", host_manifest, e);
+                    std::process::exit(1)
+                });
+            let bytes = std::fs::read(&input).unwrap();
+            let preflight =
+                preflight::preflight(&bytes, &manifest, abi, &options).unwrap_or_else(|e| {
+                    eprintln!("Error - preflight check of {} failed: {}", input, e);
+                    std::process::exit(1)
+                });
+            println!("{}", preflight.report);
+            std::fs::write(&outfile, preflight.artifact).unwrap();
+            println!(
+                "Aot Compiled {} to [{}]: {}",
+                input,
+                options.target(),
+                outfile
+            );
+            if !preflight.report.is_ok() {
+                std::process::exit(1)
+            }
+        }
+        Commands::Validate {
+            file,
+            runtime,
+            debug,
+            pulley,
+            canonicalize_nans,
+            epoch_interruption,
+            deterministic_relaxed_simd,
+            features,
+            wasmtime_version,
+        } => {
+            let options = CompileOptions {
+                component: false,
+                debug,
+                minimal: false,
+                pulley,
+                canonicalize_nans,
+                epoch_interruption,
+                deterministic_relaxed_simd,
+                wasm_features: features.wasm_features(),
+                opt_level: features.opt_level,
+                wasmtime_version,
+            };
+            let expectation = match runtime {
+                Some(runtime) => std::fs::read(&runtime)
+                    .map_err(|e| e.to_string())
+                    .and_then(|bytes| RuntimeExpectation::from_runtime_binary(&bytes, options))
+                    .unwrap_or_else(|e| {
+                        eprintln!("Error - failed to read {}: {}", runtime, e);
+                        std::process::exit(1)
+                    }),
+                None => RuntimeExpectation::new(options),
+            };
+            let bytes = std::fs::read(&file).unwrap_or_else(|e| {
+                eprintln!("Error - failed to read {}: {}", file, e);
+                std::process::exit(1)
+            });
+            let report = validate::validate(&bytes, &expectation);
+            println!("{}", serde_json::to_string_pretty(&report).unwrap());
+            if !report.compatible {
+                std::process::exit(1)
+            }
+        }
+        Commands::Inspect {
+            input,
+            format,
+            emit_world,
+        } => {
+            let world = std::fs::read(&input)
+                .map_err(|e| e.to_string())
+                .and_then(|bytes| inspect::inspect(&bytes))
+                .unwrap_or_else(|e| {
+                    eprintln!("Error - failed to inspect {}: {}", input, e);
+                    std::process::exit(1)
+                });
+            match format {
+                InspectFormat::Text => print!("{}", world),
+                InspectFormat::Json => {
+                    println!("{}", serde_json::to_string_pretty(&world).unwrap())
                 }
-                WasmtimeVersion::Lts => {
-                    let target_name = if pulley {
-                        "pulley64"
-                    } else {
-                        "x86_64-unknown-none"
-                    };
-                    if debug {
-                        println!(
-                            "Aot Compiling {} to [{}]: {} with debug info and optimizations off (LTS wasmtime)",
-                            input, target_name, outfile
-                        );
-                    } else {
-                        println!(
-                            "Aot Compiling {} to [{}]: {} (LTS wasmtime)",
-                            input, target_name, outfile
-                        );
+                InspectFormat::Wit => match world.to_wit() {
+                    Ok(wit) => print!("{}", wit),
+                    Err(e) => {
+                        eprintln!("Error - failed to print the world of {}: {}", input, e);
+                        std::process::exit(1)
                     }
-                    let bytes = std::fs::read(&input).unwrap();
-                    let serialized =
-                        precompile_bytes_lts(&bytes, debug, minimal, pulley, component);
-                    std::fs::write(outfile, serialized).unwrap();
+                },
+            }
+            if let Some(path) = emit_world {
+                let result = world
+                    .to_wasm()
+                    .and_then(|wasm| std::fs::write(&path, wasm).map_err(|e| e.to_string()));
+                if let Err(e) = result {
+                    eprintln!("Error - failed to write {}: {}", path, e);
+                    std::process::exit(1)
                 }
             }
         }
@@ -241,182 +478,125 @@ fn main() {
             debug,
             wasmtime_version,
         } => {
-            match wasmtime_version {
-                WasmtimeVersion::Latest => {
-                    // get the wasmtime version used by hyperlight-wasm-aot
-                    let metadata = MetadataCommand::new().exec().unwrap();
-                    let package_name = PackageName::new("wasmtime".to_string()).unwrap();
-                    let wasmtime_package: Option<&Package> = metadata
-                        .packages
-                        .iter()
-                        .filter(|p| p.name == package_name)
-                        .find(|p| p.version.major > 36);
-                    let version_number = match wasmtime_package {
-                        Some(pkg) => pkg.version.clone(),
-                        None => panic!("latest wasmtime dependency not found"),
-                    };
-                    if debug {
-                        println!(
-                            "Checking Wasmtime version used to compile debug info enabled file: {} (latest wasmtime)",
-                            file
-                        );
-                    } else {
-                        println!(
-                            "Checking Wasmtime version used to compile file: {} (latest wasmtime)",
-                            file
-                        );
-                    }
-                    let bytes = std::fs::read(&file).unwrap();
-                    let target = match get_aot_target(&bytes) {
-                        Ok(target) => target,
-                        Err(e) => {
-                            eprintln!(
-                                "Error - {} is not a valid precompiled Wasmtime module: {}",
-                                file, e
-                            );
-                            std::process::exit(1)
-                        }
-                    };
-                    let config = get_config(debug, false, &target);
-                    let engine = Engine::new(&config).unwrap();
-                    match Engine::detect_precompiled(&bytes) {
-                        Some(pre_compiled) => {
-                            match pre_compiled {
-                                Precompiled::Module => {
-                                    println!("The file is a valid AOT compiled Wasmtime module");
-                                    // It doesnt seem like the functions or data needed to extract the version of wasmtime used to compile the module are exposed in the wasmtime crate
-                                    // so we will try and load it and then catch the error and parse the version from the error message :-(
-                                    match unsafe { Module::deserialize(&engine, bytes) } {
-                                        Ok(_) => println!(
-                                            "File {} was AOT compiled to '{}' with wasmtime version: {}",
-                                            file, target, version_number
-                                        ),
-                                        Err(e) => {
-                                            let error_message = e.to_string();
-                                            if !error_message.starts_with(
-                                                "Module was compiled with incompatible Wasmtime version",
-                                            ) {
-                                                eprintln!("{}", error_message);
-                                                return;
-                                            }
-                                            let version = error_message.trim_start_matches("Module was compiled with incompatible Wasmtime version ").trim();
-                                            println!(
-                                                "File {} was AOT compiled to '{}' with wasmtime version: {}",
-                                                file, target, version
-                                            );
-                                        }
-                                    };
-                                }
-                                Precompiled::Component => {
-                                    eprintln!("The file is an AOT compiled Wasmtime component")
-                                }
-                            }
-                        }
-                        None => {
-                            eprintln!(
-                                "Error - {} is not a valid AOT compiled Wasmtime module or component",
-                                file
-                            );
-                        }
-                    };
-                }
-                WasmtimeVersion::Lts => {
-                    if debug {
-                        println!(
-                            "Checking Wasmtime version used to compile with debug info enabled file: {} (LTS wasmtime)",
-                            file
-                        );
-                    } else {
-                        println!(
-                            "Checking Wasmtime version used to compile file: {} (LTS wasmtime)",
-                            file
-                        );
-                    }
-                    let bytes = std::fs::read(&file).unwrap();
-                    detect_and_deserialize_lts(&bytes, debug, &file);
+            let flavor = match wasmtime_version {
+                WasmtimeVersion::Latest => "latest",
+                WasmtimeVersion::Lts => "LTS",
+            };
+            if debug {
+                println!(
+                    "Checking Wasmtime version used to compile debug info enabled file: {} ({} wasmtime)",
+                    file, flavor
+                );
+            } else {
+                println!(
+                    "Checking Wasmtime version used to compile file: {} ({} wasmtime)",
+                    file, flavor
+                );
+            }
+            let bytes = std::fs::read(&file).unwrap_or_else(|e| {
+                eprintln!("Error - failed to read {}: {}", file, e);
+                std::process::exit(1)
+            });
+            let Some(kind) = validate::artifact_kind(&bytes) else {
+                eprintln!(
+                    "Error - {} is not a valid AOT compiled Wasmtime module or component",
+                    file
+                );
+                std::process::exit(1)
+            };
+            println!("The file is a valid AOT compiled Wasmtime {}", kind);
+            let target = validate::artifact_target(&bytes).unwrap_or_else(|e| {
+                eprintln!(
+                    "Error - {} is not a valid precompiled Wasmtime {}: {}",
+                    file, kind, e
+                );
+                std::process::exit(1)
+            });
+            let version = validate::artifact_version(&bytes).unwrap_or_else(|| {
+                eprintln!("Error - {} does not record a wasmtime version", file);
+                std::process::exit(1)
+            });
+            println!(
+                "File {} was AOT compiled to '{}' with wasmtime version: {}",
+                file, target, version
+            );
+            // Check against a runtime built the way the artifact was
+            // compiled, so that only the wasmtime version is in question
+            let options = CompileOptions {
+                component: kind == ArtifactKind::Component,
+                debug,
+                pulley: target == SupportedTarget::WasmtimePulley64,
+                canonicalize_nans: version.ends_with("+nan-canonicalization"),
+                wasmtime_version,
+                ..Default::default()
+            };
+            let report = validate::validate(&bytes, &RuntimeExpectation::new(options));
+            if !report.compatible {
+                for problem in &report.problems {
+                    eprintln!("Error - {}: {}", file, problem);
                 }
+                std::process::exit(1)
             }
+            println!(
+                "File {} is compatible with the {} wasmtime runtime",
+                file, flavor
+            );
         }
     }
 }
 
-/// Returns a new `Config` for the Wasmtime engine with additional settings for AOT compilation.
-fn get_config(debug: bool, minimal: bool, target: &SupportedTarget) -> Config {
-    let mut config = Config::new();
-
-    // Compile for the pulley64 target if specified
-    match target {
-        SupportedTarget::X86_64UnknownNone => {
-            config.target("x86_64-unknown-none").unwrap();
-            // Enable x86_float_abi_ok only for the latest Wasmtime version.
-            // Safety:
-            // We are using hyperlight cargo to build the guest which
-            // sets the Rust target to be compiled with the hard-float ABI manually via
-            // `-Zbuild-std` and a custom target JSON configuration
-            // See https://github.com/bytecodealliance/wasmtime/pull/11553
-            unsafe { config.x86_float_abi_ok(true) };
-        }
-        SupportedTarget::WasmtimePulley64 => {
-            config.target("pulley64").unwrap();
-        }
+/// Compile every .wasm file under `dir` into `out_dir` on `jobs` threads,
+/// exiting with an error if any of them fails to compile
+fn compile_dir(
+    dir: &str,
+    out_dir: &str,
+    jobs: Option<usize>,
+    options: &CompileOptions,
+    version: &str,
+) {
+    let jobs = jobs.unwrap_or_else(|| {
+        std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+    });
+    println!(
+        "Aot Compiling the .wasm files in {} to [{}]: {} on {} threads ({} wasmtime)",
+        dir,
+        options.target(),
+        out_dir,
+        jobs,
+        version
+    );
+    let report = batch::compile_dir(Path::new(dir), Path::new(out_dir), options, jobs)
+        .unwrap_or_else(|e| {
+            eprintln!("Error - failed to compile {}: {}", dir, e);
+            std::process::exit(1)
+        });
+    for artifact in &report.manifest.artifacts {
+        println!("Aot Compiled {} to {}", artifact.input, artifact.output);
     }
-
-    // Enable the default features for the Wasmtime engine.
-    if debug {
-        config.debug_info(true);
-        config.cranelift_opt_level(OptLevel::None);
+    for (input, e) in &report.failures {
+        eprintln!("Error - failed to compile {}: {}", input.display(), e);
     }
-
-    if minimal {
-        config.generate_address_map(false);
-        config.native_unwind_info(false);
+    println!(
+        "Wrote {} with {} artifacts",
+        Path::new(out_dir).join(MANIFEST_FILE).display(),
+        report.manifest.artifacts.len()
+    );
+    if !report.is_ok() {
+        std::process::exit(1)
     }
-
-    config
 }
 
-/// Parses the AOT compiled file as an ELF file and extracts the target triple
-/// NOTE: These flag bits must match Wasmtime's EF_WASMTIME_PULLEY{64,32} values
-/// used when emitting RISC-V ELF object files. If Wasmtime changes these values,
-/// this code must be updated accordingly to correctly detect pulley targets.
-/// Source of definitions:
-/// https://github.com/bytecodealliance/wasmtime/blob/release-42.0.0/crates/environ/src/obj.rs#L26
-/// Source of logic for detecting pulley targets:
-/// https://github.com/bytecodealliance/wasmtime/blob/release-42.0.0/src/commands/objdump.rs#L408
-fn get_aot_target(bytes: &[u8]) -> Result<SupportedTarget, String> {
-    const EF_WASMTIME_PULLEY64: u32 = 1 << 3;
-    const EF_WASMTIME_PULLEY32: u32 = 1 << 2;
-
-    if let Ok(elf) = ElfFile64::<Endianness>::parse(bytes) {
-        match elf.architecture() {
-            Architecture::X86_64 => Ok(SupportedTarget::X86_64UnknownNone),
-            Architecture::Aarch64 => {
-                Err("Unsupported architecture Aarch64 in AOT compiled file".to_string())
-            }
-            Architecture::S390x => {
-                Err("Unsupported architecture S390x in AOT compiled file".to_string())
-            }
-            Architecture::Riscv64 => {
-                let e_flags = match elf.flags() {
-                    FileFlags::Elf { e_flags, .. } => e_flags,
-                    _ => return Err("Unsupported file format in AOT compiled file".to_string()),
-                };
-
-                if e_flags & EF_WASMTIME_PULLEY64 != 0 {
-                    Ok(SupportedTarget::WasmtimePulley64)
-                } else if e_flags & EF_WASMTIME_PULLEY32 != 0 {
-                    Err("Unsupported Riscv64 AOT compiled file: pulley32 artifacts are not supported".to_string())
-                } else {
-                    Err(
-                        "Unsupported Riscv64 AOT compiled file, missing expected e_flags in elf header".to_string()
-                    )
-                }
-            }
-            other => Err(format!(
-                "Unsupported architecture {other:?} in AOT compiled file"
-            )),
+/// The output path for `input`, which defaults to `input` with an .aot
+/// extension
+fn aot_path(input: &str, output: Option<String>) -> String {
+    match output {
+        Some(s) => s,
+        None => {
+            let mut path = Path::new(input).to_path_buf();
+            path.set_extension("aot");
+            path.to_str().unwrap().to_string()
         }
-    } else {
-        Err("Failed to parse AOT compiled file as ELF".to_string())
     }
 }
@@ -0,0 +1,417 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Checks that a module only imports functions that a sandbox provides,
+//! so that integration errors are caught before deployment rather than
+//! when the module is loaded.
+//!
+//! The host functions a sandbox provides are described by the JSON
+//! written by hyperlight-wasm's `HostFunctionManifest::to_json`, usually
+//! for the manifest returned by `ProtoWasmSandbox::host_function_manifest`.
+
+use std::fmt;
+
+use clap::ValueEnum;
+use serde::Deserialize;
+use wasmparser::{Encoding, FuncType, Parser, Payload, TypeRef, ValType};
+
+use crate::{CompileOptions, precompile};
+
+/// The module modules import host functions from
+const HOST_MODULE: &str = "env";
+
+/// The modules whose imports hyperlight-wasm-runtime provides itself
+const RUNTIME_MODULES: [&str; 4] = [
+    "wasi_snapshot_preview1",
+    "hyperlight",
+    "hlwasm:limits",
+    "hlwasm:state",
+];
+
+/// The ABI the module was built for, which determines how host function
+/// parameters and results are passed
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum GuestAbi {
+    /// The ABI of Rust and C modules
+    #[default]
+    Default,
+    /// The ABI of TinyGo modules, which pass strings as a pointer and a
+    /// length
+    TinyGo,
+}
+
+/// The type of a host function parameter
+#[allow(missing_docs)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+pub enum ParameterType {
+    Int,
+    UInt,
+    Long,
+    ULong,
+    Float,
+    Double,
+    String,
+    Bool,
+    VecBytes,
+}
+
+/// The type of a host function result
+#[allow(missing_docs)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+pub enum ReturnType {
+    Int,
+    UInt,
+    Long,
+    ULong,
+    Float,
+    Double,
+    String,
+    Bool,
+    VecBytes,
+    Void,
+}
+
+/// A host function registered with a sandbox
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct HostFunction {
+    /// The name modules import the function as
+    pub name: String,
+    /// The types of the function's parameters
+    pub parameter_types: Vec<ParameterType>,
+    /// The type of the function's result
+    pub return_type: ReturnType,
+    /// The function's capability tags
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+}
+
+impl HostFunction {
+    /// The type modules must import the function with, which must match
+    /// the type hyperlight-wasm-runtime defines it with
+    fn import_type(&self, abi: GuestAbi) -> Result<FuncType, String> {
+        let mut params = Vec::new();
+        let mut last_was_vec = false;
+        for p in &self.parameter_types {
+            if last_was_vec && *p != ParameterType::Int {
+                return Err(format!(
+                    "host function {} has a vector parameter without a length",
+                    self.name
+                ));
+            }
+            last_was_vec = *p == ParameterType::VecBytes;
+            match p {
+                ParameterType::Long | ParameterType::ULong => params.push(ValType::I64),
+                ParameterType::Float => params.push(ValType::F32),
+                ParameterType::Double => params.push(ValType::F64),
+                // TinyGo passes strings as a (ptr, len) pair
+                ParameterType::String if abi == GuestAbi::TinyGo => {
+                    params.extend([ValType::I32, ValType::I32])
+                }
+                _ => params.push(ValType::I32),
+            }
+        }
+        let results = match self.return_type {
+            ReturnType::Void => vec![],
+            ReturnType::Long | ReturnType::ULong => vec![ValType::I64],
+            ReturnType::Float => vec![ValType::F32],
+            ReturnType::Double => vec![ValType::F64],
+            ReturnType::String if abi == GuestAbi::TinyGo => vec![ValType::I64],
+            // Returned as a pointer in the lower half and a length in the
+            // upper half
+            ReturnType::VecBytes => vec![ValType::I64],
+            _ => vec![ValType::I32],
+        };
+        Ok(FuncType::new(params, results))
+    }
+}
+
+/// The host functions registered with a sandbox
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+pub struct HostManifest {
+    /// The functions, in any order
+    pub functions: Vec<HostFunction>,
+}
+
+impl HostManifest {
+    /// Read a manifest written by hyperlight-wasm's
+    /// `HostFunctionManifest::to_json`
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|e| format!("invalid host manifest: {}", e))
+    }
+}
+
+/// Whether an import of a module will be satisfied when it is loaded
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ImportStatus {
+    /// Provided by hyperlight-wasm-runtime
+    ProvidedByRuntime,
+    /// Provided by a host function with the right type
+    ProvidedByHost,
+    /// Not provided by the runtime or the host
+    Missing,
+    /// Provided by a host function whose type differs from the import's
+    WrongType {
+        /// The type the host function is defined with
+        expected: String,
+    },
+}
+
+/// An import of a module, and whether it will be satisfied
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CheckedImport {
+    /// The module the item is imported from
+    pub module: String,
+    /// The name of the imported item
+    pub name: String,
+    /// The type of the imported item
+    pub ty: String,
+    /// Whether the import will be satisfied
+    pub status: ImportStatus,
+}
+
+impl CheckedImport {
+    /// Whether the import will be satisfied
+    pub fn is_ok(&self) -> bool {
+        matches!(
+            self.status,
+            ImportStatus::ProvidedByRuntime | ImportStatus::ProvidedByHost
+        )
+    }
+}
+
+/// The result of checking a module's imports against a [`HostManifest`]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PreflightReport {
+    /// Every import of the module, in the order the module declares them
+    pub imports: Vec<CheckedImport>,
+}
+
+impl PreflightReport {
+    /// Whether every import will be satisfied
+    pub fn is_ok(&self) -> bool {
+        self.imports.iter().all(CheckedImport::is_ok)
+    }
+
+    /// The imports that will not be satisfied
+    pub fn problems(&self) -> impl Iterator<Item = &CheckedImport> {
+        self.imports.iter().filter(|import| !import.is_ok())
+    }
+}
+
+impl fmt::Display for PreflightReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for import in &self.imports {
+            let status = match &import.status {
+                ImportStatus::ProvidedByRuntime => "ok (runtime)".to_string(),
+                ImportStatus::ProvidedByHost => "ok (host)".to_string(),
+                ImportStatus::Missing => "MISSING".to_string(),
+                ImportStatus::WrongType { expected } => {
+                    format!("WRONG TYPE, host has {}", expected)
+                }
+            };
+            writeln!(
+                f,
+                "{}::{} {}: {}",
+                import.module, import.name, import.ty, status
+            )?;
+        }
+        let problems = self.problems().count();
+        if problems == 0 {
+            write!(f, "all {} imports are satisfied", self.imports.len())
+        } else {
+            write!(
+                f,
+                "{} of {} imports are not satisfied",
+                problems,
+                self.imports.len()
+            )
+        }
+    }
+}
+
+/// Check that every import of the module in `wasm` is provided by
+/// hyperlight-wasm-runtime or by a host function in `manifest`, with the
+/// type the runtime defines it with for `abi`.
+///
+/// Imports from the runtime's own modules, such as
+/// `wasi_snapshot_preview1`, are assumed to be provided. Components are
+/// not supported, since their imports are checked against their world
+/// when they are built.
+pub fn check_imports(
+    wasm: &[u8],
+    manifest: &HostManifest,
+    abi: GuestAbi,
+) -> Result<PreflightReport, String> {
+    let mut types = Vec::new();
+    let mut report = PreflightReport::default();
+    for payload in Parser::new(0).parse_all(wasm) {
+        match payload.map_err(|e| e.to_string())? {
+            Payload::Version {
+                encoding: Encoding::Component,
+                ..
+            } => return Err("preflight checks do not support components".to_string()),
+            Payload::TypeSection(reader) => {
+                for ty in reader.into_iter_err_on_gc_types() {
+                    types.push(ty.map_err(|e| e.to_string())?);
+                }
+            }
+            Payload::ImportSection(reader) => {
+                for import in reader.into_imports() {
+                    let import = import.map_err(|e| e.to_string())?;
+                    let func_type = match import.ty {
+                        TypeRef::Func(index) => Some(
+                            types
+                                .get(index as usize)
+                                .ok_or_else(|| format!("type index {} out of bounds", index))?,
+                        ),
+                        _ => None,
+                    };
+                    let host_function = manifest
+                        .functions
+                        .iter()
+                        .find(|f| import.module == HOST_MODULE && f.name == import.name);
+                    let status = match (func_type, host_function) {
+                        _ if RUNTIME_MODULES.contains(&import.module) => {
+                            ImportStatus::ProvidedByRuntime
+                        }
+                        (Some(func_type), Some(host_function)) => {
+                            let expected = host_function.import_type(abi)?;
+                            if *func_type == expected {
+                                ImportStatus::ProvidedByHost
+                            } else {
+                                ImportStatus::WrongType {
+                                    expected: expected.to_string(),
+                                }
+                            }
+                        }
+                        _ => ImportStatus::Missing,
+                    };
+                    report.imports.push(CheckedImport {
+                        module: import.module.to_string(),
+                        name: import.name.to_string(),
+                        ty: func_type.map_or_else(|| format!("{:?}", import.ty), |t| t.to_string()),
+                        status,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(report)
+}
+
+/// A precompiled module, and the result of checking its imports
+#[derive(Debug)]
+pub struct Preflight {
+    /// The artifact to load into a sandbox
+    pub artifact: Vec<u8>,
+    /// Whether the sandbox provides every import of the module
+    pub report: PreflightReport,
+}
+
+/// Check the imports of the module in `wasm` as [`check_imports`] does,
+/// and precompile it with `options`. The artifact is returned even if
+/// some imports are not satisfied.
+pub fn preflight(
+    wasm: &[u8],
+    manifest: &HostManifest,
+    abi: GuestAbi,
+    options: &CompileOptions,
+) -> Result<Preflight, String> {
+    let report = check_imports(wasm, manifest, abi)?;
+    let artifact = precompile(wasm, options)?;
+    Ok(Preflight { artifact, report })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GuestAbi, HostManifest, ImportStatus, check_imports};
+
+    // A module with a single type, (func (param i32) (result i32)), that
+    // imports each of `imports` as a function of that type
+    fn module(imports: &[(&str, &str)]) -> Vec<u8> {
+        let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+        wasm.extend_from_slice(&[1, 6, 1, 0x60, 1, 0x7f, 1, 0x7f]);
+        let mut section = vec![imports.len() as u8];
+        for (module, name) in imports {
+            for s in [module, name] {
+                section.push(s.len() as u8);
+                section.extend_from_slice(s.as_bytes());
+            }
+            section.extend_from_slice(&[0, 0]);
+        }
+        wasm.push(2);
+        wasm.push(section.len() as u8);
+        wasm.extend(section);
+        wasm
+    }
+
+    #[test]
+    fn test_check_imports() {
+        let manifest = HostManifest::from_json(
+            r#"{"functions": [
+                {"name": "TestHostFunc", "parameter_types": ["Int"], "return_type": "Int", "capabilities": []},
+                {"name": "ReadConfig", "parameter_types": ["String"], "return_type": "String", "capabilities": []},
+                {"name": "Now", "parameter_types": [], "return_type": "Long"}
+            ]}"#,
+        )
+        .unwrap();
+        let wasm = module(&[
+            ("env", "TestHostFunc"),
+            ("env", "ReadConfig"),
+            ("env", "Now"),
+            ("env", "Unregistered"),
+            ("wasi_snapshot_preview1", "fd_write"),
+            ("other", "TestHostFunc"),
+        ]);
+
+        let report = check_imports(&wasm, &manifest, GuestAbi::Default).unwrap();
+        let statuses: Vec<_> = report.imports.iter().map(|i| &i.status).collect();
+        assert_eq!(
+            statuses,
+            [
+                &ImportStatus::ProvidedByHost,
+                &ImportStatus::ProvidedByHost,
+                &ImportStatus::WrongType {
+                    expected: "(func (result i64))".to_string()
+                },
+                &ImportStatus::Missing,
+                &ImportStatus::ProvidedByRuntime,
+                &ImportStatus::Missing,
+            ]
+        );
+        assert!(!report.is_ok());
+        assert_eq!(report.problems().count(), 3);
+
+        // TinyGo passes strings as a pointer and a length
+        let report = check_imports(&wasm, &manifest, GuestAbi::TinyGo).unwrap();
+        assert!(matches!(
+            report.imports[1].status,
+            ImportStatus::WrongType { .. }
+        ));
+
+        let report = check_imports(
+            &module(&[("env", "TestHostFunc")]),
+            &manifest,
+            GuestAbi::Default,
+        )
+        .unwrap();
+        assert!(report.is_ok());
+
+        assert!(HostManifest::from_json(r#"{"functions": [{"name": "F"}]}"#).is_err());
+        assert!(check_imports(b"not wasm", &manifest, GuestAbi::Default).is_err());
+    }
+}
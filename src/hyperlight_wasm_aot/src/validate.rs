@@ -0,0 +1,493 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Checks that a precompiled module or component will load into the
+//! runtime embedded in hyperlight-wasm, so that version and setting
+//! mismatches are caught before deployment rather than when the artifact
+//! is loaded.
+//!
+//! The artifact's target and the wasmtime version recorded in it are
+//! compared with the runtime's. If this tool includes the runtime's
+//! wasmtime version, the artifact is also deserialized with an engine
+//! configured the way the runtime configures its own, which checks the
+//! WebAssembly features and the other settings recorded in the artifact.
+
+use object::read::elf::ElfFile64;
+use object::{Architecture, Endianness, FileFlags, Object, ObjectSection};
+use serde::Serialize;
+
+use crate::{
+    CompileOptions, NAN_CANONICALIZATION_VERSION_SUFFIX, SupportedTarget, WASMTIME_LTS_VERSION,
+    WASMTIME_VERSION, WasmtimeVersion, latest_config, lts_config,
+};
+
+/// The section of hyperlight-wasm-runtime binaries that holds the
+/// version of wasmtime they were built with
+const RUNTIME_METADATA_SECTION: &str = ".note_hyperlight_metadata";
+
+/// The section of wasmtime artifacts that starts with the version of
+/// wasmtime they were compiled with
+const ENGINE_SECTION: &str = ".wasmtime.engine";
+
+/// What a precompiled artifact holds
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArtifactKind {
+    /// A core module
+    Module,
+    /// A component
+    Component,
+}
+
+impl std::fmt::Display for ArtifactKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArtifactKind::Module => write!(f, "module"),
+            ArtifactKind::Component => write!(f, "component"),
+        }
+    }
+}
+
+/// The runtime an artifact is checked against
+#[derive(Clone, Debug)]
+pub struct RuntimeExpectation {
+    /// The exact version of wasmtime the runtime embeds
+    pub wasmtime_version: String,
+    /// The settings sandboxes are built with. `component`, `minimal` and
+    /// `opt_level` are ignored, and `wasmtime_version` selects the
+    /// wasmtime this tool checks the artifact's settings with.
+    pub options: CompileOptions,
+}
+
+impl RuntimeExpectation {
+    /// A runtime embedding the wasmtime selected by
+    /// `options.wasmtime_version`, which is the version this tool
+    /// compiles with
+    pub fn new(options: CompileOptions) -> Self {
+        let wasmtime_version = match options.wasmtime_version {
+            WasmtimeVersion::Latest => WASMTIME_VERSION,
+            WasmtimeVersion::Lts => WASMTIME_LTS_VERSION,
+        };
+        Self {
+            wasmtime_version: wasmtime_version.to_string(),
+            options,
+        }
+    }
+
+    /// The runtime in the hyperlight-wasm-runtime binary `runtime`,
+    /// embedding the wasmtime version recorded in the binary.
+    /// `options.wasmtime_version` is replaced by the matching version of
+    /// this tool, if it has one.
+    pub fn from_runtime_binary(runtime: &[u8], options: CompileOptions) -> Result<Self, String> {
+        let elf = ElfFile64::<Endianness>::parse(runtime)
+            .map_err(|e| format!("not a hyperlight-wasm-runtime binary: {}", e))?;
+        let section = elf
+            .section_by_name(RUNTIME_METADATA_SECTION)
+            .and_then(|section| section.data().ok())
+            .ok_or_else(|| {
+                format!(
+                    "not a hyperlight-wasm-runtime binary: {} section not found",
+                    RUNTIME_METADATA_SECTION
+                )
+            })?;
+        let section = match section.iter().position(|&b| b == 0) {
+            Some(end) => &section[..end],
+            None => section,
+        };
+        let wasmtime_version = std::str::from_utf8(section)
+            .map_err(|_| format!("{} does not hold a version", RUNTIME_METADATA_SECTION))?
+            .to_string();
+        let wasmtime_version_option = if wasmtime_version == WASMTIME_VERSION {
+            WasmtimeVersion::Latest
+        } else if wasmtime_version == WASMTIME_LTS_VERSION {
+            WasmtimeVersion::Lts
+        } else {
+            options.wasmtime_version
+        };
+        Ok(Self {
+            wasmtime_version,
+            options: CompileOptions {
+                wasmtime_version: wasmtime_version_option,
+                ..options
+            },
+        })
+    }
+
+    /// Whether this tool includes the runtime's wasmtime, so that it can
+    /// check the artifact's settings. The latest wasmtime only
+    /// deserializes artifacts for the host it runs on, so only pulley
+    /// artifacts can be checked with it.
+    fn can_check_engine(&self) -> bool {
+        match self.options.wasmtime_version {
+            WasmtimeVersion::Latest => {
+                self.wasmtime_version == WASMTIME_VERSION && self.options.pulley
+            }
+            WasmtimeVersion::Lts => self.wasmtime_version == WASMTIME_LTS_VERSION,
+        }
+    }
+}
+
+/// The result of checking an artifact against a [`RuntimeExpectation`],
+/// which serializes to the JSON report printed by `hyperlight-wasm-aot
+/// validate`
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct ValidationReport {
+    /// Whether the artifact will load into the runtime
+    pub compatible: bool,
+    /// What the artifact holds, if it is a wasmtime artifact
+    pub kind: Option<ArtifactKind>,
+    /// The target the artifact was compiled for, if it could be read
+    pub target: Option<String>,
+    /// The target the runtime runs
+    pub expected_target: String,
+    /// The version of wasmtime the artifact was compiled with, if it
+    /// could be read
+    pub wasmtime_version: Option<String>,
+    /// The version of wasmtime the runtime embeds
+    pub expected_wasmtime_version: String,
+    /// Whether the artifact was compiled with NaN canonicalization, if
+    /// its version could be read
+    pub canonicalize_nans: Option<bool>,
+    /// Whether sandboxes are built with NaN canonicalization
+    pub expected_canonicalize_nans: bool,
+    /// Whether the artifact was deserialized with an engine configured as
+    /// the runtime's is. This is only done if the target and version
+    /// match and this tool includes the runtime's wasmtime version, and
+    /// for the latest wasmtime version only for pulley artifacts.
+    pub engine_checked: bool,
+    /// Why the artifact will not load, if it will not
+    pub problems: Vec<String>,
+}
+
+/// The target of a precompiled artifact, read from its ELF header
+///
+/// NOTE: These flag bits must match Wasmtime's EF_WASMTIME_PULLEY{64,32} values
+/// used when emitting RISC-V ELF object files. If Wasmtime changes these values,
+/// this code must be updated accordingly to correctly detect pulley targets.
+/// Source of definitions:
+/// https://github.com/bytecodealliance/wasmtime/blob/release-42.0.0/crates/environ/src/obj.rs#L26
+/// Source of logic for detecting pulley targets:
+/// https://github.com/bytecodealliance/wasmtime/blob/release-42.0.0/src/commands/objdump.rs#L408
+pub fn artifact_target(bytes: &[u8]) -> Result<SupportedTarget, String> {
+    const EF_WASMTIME_PULLEY64: u32 = 1 << 3;
+    const EF_WASMTIME_PULLEY32: u32 = 1 << 2;
+
+    if let Ok(elf) = ElfFile64::<Endianness>::parse(bytes) {
+        match elf.architecture() {
+            Architecture::X86_64 => Ok(SupportedTarget::X86_64UnknownNone),
+            Architecture::Aarch64 => {
+                Err("Unsupported architecture Aarch64 in AOT compiled file".to_string())
+            }
+            Architecture::S390x => {
+                Err("Unsupported architecture S390x in AOT compiled file".to_string())
+            }
+            Architecture::Riscv64 => {
+                let e_flags = match elf.flags() {
+                    FileFlags::Elf { e_flags, .. } => e_flags,
+                    _ => return Err("Unsupported file format in AOT compiled file".to_string()),
+                };
+
+                if e_flags & EF_WASMTIME_PULLEY64 != 0 {
+                    Ok(SupportedTarget::WasmtimePulley64)
+                } else if e_flags & EF_WASMTIME_PULLEY32 != 0 {
+                    Err("Unsupported Riscv64 AOT compiled file: pulley32 artifacts are not supported".to_string())
+                } else {
+                    Err(
+                        "Unsupported Riscv64 AOT compiled file, missing expected e_flags in elf header".to_string()
+                    )
+                }
+            }
+            other => Err(format!(
+                "Unsupported architecture {other:?} in AOT compiled file"
+            )),
+        }
+    } else {
+        Err("Failed to parse AOT compiled file as ELF".to_string())
+    }
+}
+
+/// What the precompiled artifact in `bytes` holds, or `None` if it is
+/// not a wasmtime artifact. Both wasmtime versions use the same header,
+/// so this does not depend on the version it was compiled with.
+pub fn artifact_kind(bytes: &[u8]) -> Option<ArtifactKind> {
+    match wasmtime::Engine::detect_precompiled(bytes) {
+        Some(wasmtime::Precompiled::Module) => Some(ArtifactKind::Module),
+        Some(wasmtime::Precompiled::Component) => Some(ArtifactKind::Component),
+        None => None,
+    }
+}
+
+/// The version string recorded in a precompiled artifact: a format
+/// byte, which is 0, followed by the string's length and the string.
+/// Artifacts compiled with NaN canonicalization have
+/// `+nan-canonicalization` appended to the version.
+pub fn artifact_version(bytes: &[u8]) -> Option<String> {
+    let elf = ElfFile64::<Endianness>::parse(bytes).ok()?;
+    let data = elf.section_by_name(ENGINE_SECTION)?.data().ok()?;
+    let [0, len, rest @ ..] = data else {
+        return None;
+    };
+    let version = rest.get(..*len as usize)?;
+    String::from_utf8(version.to_vec()).ok()
+}
+
+/// Whether an artifact recording `version` loads into a runtime
+/// embedding wasmtime `runtime_version`. Newer wasmtime releases record
+/// only their major version, since artifacts load into any release with
+/// the same major version.
+fn version_matches(version: &str, runtime_version: &str) -> bool {
+    version == runtime_version || runtime_version.split('.').next() == Some(version)
+}
+
+/// Check that the precompiled module or component in `bytes` will load
+/// into `runtime`
+pub fn validate(bytes: &[u8], runtime: &RuntimeExpectation) -> ValidationReport {
+    let options = &runtime.options;
+    let mut report = ValidationReport {
+        compatible: false,
+        kind: None,
+        target: None,
+        expected_target: options.target().to_string(),
+        wasmtime_version: None,
+        expected_wasmtime_version: runtime.wasmtime_version.clone(),
+        canonicalize_nans: None,
+        expected_canonicalize_nans: options.canonicalize_nans,
+        engine_checked: false,
+        problems: Vec::new(),
+    };
+
+    report.kind = artifact_kind(bytes);
+    let Some(kind) = report.kind else {
+        report
+            .problems
+            .push("not a precompiled wasmtime module or component".to_string());
+        return report;
+    };
+
+    match artifact_target(bytes) {
+        Ok(target) => {
+            report.target = Some(target.to_string());
+            if target != options.target() {
+                report.problems.push(format!(
+                    "compiled for {}, but the runtime runs {}",
+                    target,
+                    options.target()
+                ));
+            }
+        }
+        Err(e) => report.problems.push(e),
+    }
+
+    match artifact_version(bytes) {
+        Some(version) => {
+            let (version, canonicalize_nans) =
+                match version.strip_suffix(NAN_CANONICALIZATION_VERSION_SUFFIX) {
+                    Some(version) => (version.to_string(), true),
+                    None => (version, false),
+                };
+            if !version_matches(&version, &runtime.wasmtime_version) {
+                report.problems.push(format!(
+                    "compiled with wasmtime {}, but the runtime embeds wasmtime {}",
+                    version, runtime.wasmtime_version
+                ));
+            }
+            if canonicalize_nans != options.canonicalize_nans {
+                report.problems.push(format!(
+                    "compiled {} NaN canonicalization, but sandboxes are built {} it",
+                    if canonicalize_nans { "with" } else { "without" },
+                    if options.canonicalize_nans {
+                        "with"
+                    } else {
+                        "without"
+                    }
+                ));
+            }
+            report.wasmtime_version = Some(version);
+            report.canonicalize_nans = Some(canonicalize_nans);
+        }
+        None => report
+            .problems
+            .push("the artifact does not record a wasmtime version".to_string()),
+    }
+
+    if report.problems.is_empty() && runtime.can_check_engine() {
+        report.engine_checked = true;
+        if let Err(e) = check_engine(bytes, kind, options) {
+            report
+                .problems
+                .push(format!("the runtime's engine rejects the artifact: {}", e));
+        }
+    }
+    report.compatible = report.problems.is_empty();
+    report
+}
+
+/// Deserialize the artifact with an engine configured as the runtime
+/// configures its own for sandboxes built with `options`
+fn check_engine(bytes: &[u8], kind: ArtifactKind, options: &CompileOptions) -> Result<(), String> {
+    // The runtime does not turn off the address map or unwind info
+    let options = CompileOptions {
+        minimal: false,
+        ..options.clone()
+    };
+    // Safety: as with check-wasmtime-version, the artifact is trusted to
+    // be one wasmtime wrote. It is only deserialized, never run.
+    match options.wasmtime_version {
+        WasmtimeVersion::Latest => {
+            let engine =
+                wasmtime::Engine::new(&latest_config(&options)?).map_err(|e| e.to_string())?;
+            match kind {
+                ArtifactKind::Module => {
+                    unsafe { wasmtime::Module::deserialize(&engine, bytes) }.map(drop)
+                }
+                ArtifactKind::Component => {
+                    unsafe { wasmtime::component::Component::deserialize(&engine, bytes) }.map(drop)
+                }
+            }
+            .map_err(|e| e.to_string())
+        }
+        WasmtimeVersion::Lts => {
+            let engine =
+                wasmtime_lts::Engine::new(&lts_config(&options)?).map_err(|e| e.to_string())?;
+            match kind {
+                ArtifactKind::Module => {
+                    unsafe { wasmtime_lts::Module::deserialize(&engine, bytes) }.map(drop)
+                }
+                ArtifactKind::Component => {
+                    unsafe { wasmtime_lts::component::Component::deserialize(&engine, bytes) }
+                        .map(drop)
+                }
+            }
+            .map_err(|e| e.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ArtifactKind, RuntimeExpectation, validate};
+    use crate::{CompileOptions, WasmtimeVersion, precompile};
+
+    #[test]
+    fn test_validate() {
+        // (memory 1 1)
+        let memory = [b"\0asm\x01\0\0\0".as_slice(), &[5, 4, 1, 1, 1, 1]].concat();
+        let artifact = precompile(&memory, &CompileOptions::default()).unwrap();
+
+        let report = validate(
+            &artifact,
+            &RuntimeExpectation::new(CompileOptions::default()),
+        );
+        assert!(report.compatible, "{:?}", report.problems);
+        assert!(report.engine_checked);
+        assert_eq!(report.kind, Some(ArtifactKind::Module));
+        assert_eq!(report.target.as_deref(), Some("x86_64-unknown-none"));
+        assert_eq!(
+            report.wasmtime_version.as_deref(),
+            Some(report.expected_wasmtime_version.as_str())
+        );
+
+        // Settings recorded in the artifact are checked by the engine
+        let report = validate(
+            &artifact,
+            &RuntimeExpectation::new(CompileOptions {
+                epoch_interruption: true,
+                ..Default::default()
+            }),
+        );
+        assert!(!report.compatible);
+        assert!(report.engine_checked);
+
+        let report = validate(
+            &artifact,
+            &RuntimeExpectation::new(CompileOptions {
+                canonicalize_nans: true,
+                ..Default::default()
+            }),
+        );
+        assert!(!report.compatible);
+        assert_eq!(report.canonicalize_nans, Some(false));
+        assert!(!report.engine_checked);
+
+        let report = validate(
+            &artifact,
+            &RuntimeExpectation::new(CompileOptions {
+                wasmtime_version: WasmtimeVersion::Latest,
+                ..Default::default()
+            }),
+        );
+        assert!(!report.compatible);
+        assert!(report.problems[0].starts_with("compiled with wasmtime"));
+
+        let report = validate(
+            &artifact,
+            &RuntimeExpectation::new(CompileOptions {
+                pulley: true,
+                ..Default::default()
+            }),
+        );
+        assert!(!report.compatible);
+        assert!(report.problems[0].starts_with("compiled for x86_64-unknown-none"));
+
+        let report = validate(&memory, &RuntimeExpectation::new(CompileOptions::default()));
+        assert!(!report.compatible);
+        assert_eq!(report.kind, None);
+
+        assert!(
+            RuntimeExpectation::from_runtime_binary(&memory, CompileOptions::default()).is_err()
+        );
+    }
+
+    #[test]
+    fn test_validate_wasmtime_versions() {
+        // (memory 1 1)
+        let memory = [b"\0asm\x01\0\0\0".as_slice(), &[5, 4, 1, 1, 1, 1]].concat();
+        // (component)
+        let component = b"\0asm\x0d\0\x01\0".as_slice();
+        let versions = [WasmtimeVersion::Lts, WasmtimeVersion::Latest];
+        for (wasm, kind) in [
+            (memory.as_slice(), ArtifactKind::Module),
+            (component, ArtifactKind::Component),
+        ] {
+            for pulley in [false, true] {
+                for compiled_with in versions {
+                    let options = CompileOptions {
+                        component: kind == ArtifactKind::Component,
+                        pulley,
+                        wasmtime_version: compiled_with,
+                        ..Default::default()
+                    };
+                    let artifact = precompile(wasm, &options).unwrap();
+                    for runtime in versions {
+                        let report = validate(
+                            &artifact,
+                            &RuntimeExpectation::new(CompileOptions {
+                                wasmtime_version: runtime,
+                                ..options.clone()
+                            }),
+                        );
+                        assert_eq!(report.kind, Some(kind));
+                        assert_eq!(report.compatible, compiled_with == runtime);
+                        assert_eq!(
+                            report.engine_checked,
+                            compiled_with == runtime && (runtime == WasmtimeVersion::Lts || pulley)
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,155 @@
+/*
+Copyright 2025 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+/*
+ * C API for hyperlight-wasm, implemented by the hyperlight-wasm-capi
+ * crate, whose documentation describes each function in more detail.
+ *
+ * Sandboxes go through the same stages as in Rust: a builder builds a
+ * proto sandbox, on which host functions are registered, which loads
+ * the runtime into a Wasm sandbox, which loads a module into a loaded
+ * sandbox, whose guest functions are called. Functions moving to the
+ * next stage consume their argument, whether or not they succeed.
+ * Objects that are not consumed are released with their _free function.
+ *
+ * Functions that can fail return NULL or false, and hlw_last_error()
+ * then describes the error.
+ */
+
+#ifndef HYPERLIGHT_WASM_H
+#define HYPERLIGHT_WASM_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct hlw_sandbox_builder hlw_sandbox_builder;
+typedef struct hlw_proto_sandbox hlw_proto_sandbox;
+typedef struct hlw_wasm_sandbox hlw_wasm_sandbox;
+typedef struct hlw_loaded_sandbox hlw_loaded_sandbox;
+
+/*
+ * The type of an hlw_value. Tags are passed to and from the library as
+ * uint32_t, and unknown tags are an error.
+ */
+typedef enum hlw_value_tag {
+    HLW_VOID = 0, /* only valid as a return type */
+    HLW_INT,
+    HLW_UINT,
+    HLW_LONG,
+    HLW_ULONG,
+    HLW_FLOAT,
+    HLW_DOUBLE,
+    HLW_BOOL,
+    HLW_STRING, /* UTF-8 */
+    HLW_BYTES,
+} hlw_value_tag;
+
+/*
+ * A string or buffer. Strings are not NUL terminated when passed to the
+ * library, but strings it returns are, with the NUL not counted in len.
+ */
+typedef struct hlw_bytes {
+    const uint8_t *ptr;
+    size_t len;
+} hlw_bytes;
+
+/* A parameter or return value of a guest or host function */
+typedef struct hlw_value {
+    uint32_t tag; /* an hlw_value_tag */
+    union {
+        int32_t i32;
+        uint32_t u32;
+        int64_t i64;
+        uint64_t u64;
+        float f32;
+        double f64;
+        bool b;
+        hlw_bytes bytes;
+    } data;
+} hlw_value;
+
+/*
+ * A host function, called with the user_data it was registered with and
+ * the nargs parameters the guest passed in args, which are only valid
+ * during the call. It returns true and stores its result in *result, or
+ * returns false to fail the call, optionally storing an HLW_STRING
+ * describing the error in *result. Strings and buffers in *result are
+ * copied when the function returns and remain owned by it.
+ *
+ * Host functions may be called from any thread that calls guest
+ * functions.
+ */
+typedef bool (*hlw_host_function)(void *user_data, const hlw_value *args, size_t nargs,
+                                  hlw_value *result);
+
+/*
+ * The message of the error returned by the last function that failed on
+ * the calling thread, or NULL if the last function called succeeded.
+ * Valid until the next call to a function of this library on the thread.
+ */
+const char *hlw_last_error(void);
+
+/* Release the string or buffer held by a value returned by the library */
+void hlw_value_free(hlw_value *value);
+
+bool hlw_is_hypervisor_present(void);
+
+hlw_sandbox_builder *hlw_sandbox_builder_new(void);
+bool hlw_sandbox_builder_set_guest_input_buffer_size(hlw_sandbox_builder *builder, size_t size);
+bool hlw_sandbox_builder_set_guest_output_buffer_size(hlw_sandbox_builder *builder, size_t size);
+bool hlw_sandbox_builder_set_guest_heap_size(hlw_sandbox_builder *builder, uint64_t size);
+bool hlw_sandbox_builder_set_guest_call_timeout(hlw_sandbox_builder *builder, uint64_t timeout_ms);
+bool hlw_sandbox_builder_set_env(hlw_sandbox_builder *builder, const char *key, const char *value);
+/* Consumes builder */
+hlw_proto_sandbox *hlw_sandbox_builder_build(hlw_sandbox_builder *builder);
+void hlw_sandbox_builder_free(hlw_sandbox_builder *builder);
+
+/* user_data must remain valid until the sandbox is released */
+bool hlw_proto_sandbox_register(hlw_proto_sandbox *sandbox, const char *name,
+                                const uint32_t *param_types, size_t nparams,
+                                uint32_t return_type, hlw_host_function function,
+                                void *user_data);
+/* Consumes sandbox */
+hlw_wasm_sandbox *hlw_proto_sandbox_load_runtime(hlw_proto_sandbox *sandbox);
+void hlw_proto_sandbox_free(hlw_proto_sandbox *sandbox);
+
+/* Consume sandbox */
+hlw_loaded_sandbox *hlw_wasm_sandbox_load_module(hlw_wasm_sandbox *sandbox, const char *path);
+hlw_loaded_sandbox *hlw_wasm_sandbox_load_module_from_buffer(hlw_wasm_sandbox *sandbox,
+                                                             const uint8_t *buffer, size_t len);
+void hlw_wasm_sandbox_free(hlw_wasm_sandbox *sandbox);
+
+/*
+ * Call the guest function name, storing its result, of type return_type,
+ * in *result. Strings and buffers in *result must be released with
+ * hlw_value_free().
+ */
+bool hlw_loaded_sandbox_call(hlw_loaded_sandbox *sandbox, const char *name, const hlw_value *args,
+                             size_t nargs, uint32_t return_type, hlw_value *result);
+/* Consumes sandbox */
+hlw_wasm_sandbox *hlw_loaded_sandbox_unload_module(hlw_loaded_sandbox *sandbox);
+void hlw_loaded_sandbox_free(hlw_loaded_sandbox *sandbox);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* HYPERLIGHT_WASM_H */
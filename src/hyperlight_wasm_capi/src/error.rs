@@ -0,0 +1,106 @@
+/*
+Copyright 2025 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::ffi::{CString, c_char};
+use std::fmt::Display;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::ptr;
+
+use hyperlight_wasm::Result;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Record `error` as the last error of the calling thread
+pub(crate) fn set_last_error(error: impl Display) {
+    // Interior NULs would truncate the message in C, so replace them
+    let message = error.to_string().replace('\0', "\u{FFFD}");
+    let message = CString::new(message).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// Return the value of `result`, or record its error as the last error
+/// of the calling thread and return `None`
+pub(crate) fn check<T>(result: Result<T>) -> Option<T> {
+    LAST_ERROR.with(|last| *last.borrow_mut() = None);
+    result.map_err(set_last_error).ok()
+}
+
+/// Run `f`, the body of a function called from C, returning `on_panic`
+/// and recording the panic as the last error of the calling thread if it
+/// panics, since a panic unwinding out of an `extern "C"` function aborts
+/// the process
+pub(crate) fn catch<T>(on_panic: T, f: impl FnOnce() -> T) -> T {
+    catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        set_last_error(format_args!("panicked: {}", panic_message(&*payload)));
+        on_panic
+    })
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
+
+/// The message of the error returned by the last function of this
+/// library that failed on the calling thread, or NULL if the last
+/// function called succeeded. The message is valid until the next call
+/// to a function of this library on the calling thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn hlw_last_error() -> *const c_char {
+    catch(ptr::null(), || {
+        LAST_ERROR.with(|last| {
+            last.borrow()
+                .as_ref()
+                .map_or(ptr::null(), |message| message.as_ptr())
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+
+    use hyperlight_wasm::new_error;
+
+    use super::*;
+
+    #[test]
+    fn test_last_error() {
+        assert_eq!(check::<()>(Err(new_error!("bad\0value"))), None);
+        let message = unsafe { CStr::from_ptr(hlw_last_error()) };
+        assert_eq!(message.to_str().unwrap(), "bad\u{FFFD}value");
+
+        assert_eq!(check(Ok(1)), Some(1));
+        assert!(hlw_last_error().is_null());
+    }
+
+    #[test]
+    fn test_catch() {
+        assert!(catch(false, || true));
+        assert!(!catch(false, || -> bool { panic!("oops") }));
+        let message = unsafe { CStr::from_ptr(hlw_last_error()) };
+        assert_eq!(message.to_str().unwrap(), "panicked: oops");
+    }
+}
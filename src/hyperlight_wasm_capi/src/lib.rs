@@ -0,0 +1,746 @@
+/*
+Copyright 2025 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+#![deny(dead_code, missing_docs, unused_mut)]
+//! A C API for hyperlight-wasm, for embedding it in C and C++ programs.
+//! The declarations are in `include/hyperlight_wasm.h`.
+//!
+//! Sandboxes go through the same stages as in Rust: a
+//! [`SandboxBuilder`] builds a [`ProtoWasmSandbox`], on which host
+//! functions are registered, which loads the runtime into a
+//! [`WasmSandbox`], which loads a module into a [`LoadedWasmSandbox`],
+//! whose guest functions are called. Each stage is an opaque pointer,
+//! and functions moving to the next stage consume it, whether or not
+//! they succeed. Pointers that are not consumed are released with the
+//! `_free` function of their type.
+//!
+//! Functions that can fail return NULL or `false`, and
+//! [`hlw_last_error`] then describes the error.
+
+mod error;
+mod value;
+
+use std::ffi::{CStr, c_char, c_void};
+use std::path::Path;
+use std::time::Duration;
+
+use hyperlight_common::func::Error as FuncError;
+use hyperlight_wasm::{
+    HostFunctionManifest, LoadedWasmSandbox, ManifestFunction, ParameterTuple, ParameterType,
+    ParameterValue, ProtoWasmSandbox, Result, ReturnValue, SandboxBuilder, SupportedReturnType,
+    WasmSandbox, new_error,
+};
+
+pub use crate::error::hlw_last_error;
+use crate::error::{catch, check};
+pub use crate::value::{HlwBytes, HlwValue, HlwValueData, HlwValueTag, hlw_value_free};
+
+/// A host function, called with the `user_data` it was registered with
+/// and the `nargs` parameters the guest passed in `args`, which are only
+/// valid during the call. It returns `true` and stores its result in
+/// `*result`, or returns `false` to fail the call, optionally storing a
+/// `String` describing the error in `*result`. Strings and buffers in
+/// `*result` are copied when the function returns and remain owned by
+/// it.
+///
+/// Host functions may be called from any thread that calls guest
+/// functions.
+pub type HlwHostFunction = unsafe extern "C" fn(
+    user_data: *mut c_void,
+    args: *const HlwValue,
+    nargs: usize,
+    result: *mut HlwValue,
+) -> bool;
+
+struct HostCallback {
+    function: HlwHostFunction,
+    user_data: *mut c_void,
+}
+
+// Safety: host functions must be callable from any thread, as documented
+// on HlwHostFunction
+unsafe impl Send for HostCallback {}
+unsafe impl Sync for HostCallback {}
+
+impl HostCallback {
+    fn call(&self, name: &str, params: Vec<ParameterValue>) -> Result<ReturnValue> {
+        let args = params.iter().map(HlwValue::borrowed).collect::<Vec<_>>();
+        let mut result = HlwValue::void();
+        // Safety: the arguments point into params, which outlives the call
+        let succeeded =
+            unsafe { (self.function)(self.user_data, args.as_ptr(), args.len(), &mut result) };
+        if !succeeded {
+            let message = match result.tag() {
+                // Safety: host functions must return valid values
+                Ok(HlwValueTag::String) => unsafe { result.string()? },
+                _ => "no message".to_string(),
+            };
+            return Err(new_error!("host function {} failed: {}", name, message));
+        }
+        // Safety: host functions must return valid values
+        unsafe { result.to_return() }
+    }
+}
+
+/// The parameters of a guest call, whose types are only known at run
+/// time
+#[derive(Clone)]
+struct DynamicParams(Vec<ParameterValue>);
+
+impl ParameterTuple for DynamicParams {
+    // Only used when registering host functions, which these are not
+    const SIZE: usize = 0;
+
+    const TYPE: &[ParameterType] = &[];
+
+    fn into_value(self) -> Vec<ParameterValue> {
+        self.0
+    }
+
+    fn from_value(values: Vec<ParameterValue>) -> std::result::Result<Self, FuncError> {
+        Ok(Self(values))
+    }
+}
+
+/// Take ownership of the object `ptr` points to
+///
+/// # Safety
+///
+/// `ptr` must be NULL or have been returned by this library as a `T` that
+/// has not been consumed or released
+unsafe fn take<T>(ptr: *mut T, what: &str) -> Result<T> {
+    if ptr.is_null() {
+        return Err(new_error!("{} is NULL", what));
+    }
+    // Safety: guaranteed by the caller
+    Ok(*unsafe { Box::from_raw(ptr) })
+}
+
+/// Borrow the object `ptr` points to
+///
+/// # Safety
+///
+/// As for [`take`]
+unsafe fn borrow<'a, T>(ptr: *mut T, what: &str) -> Result<&'a mut T> {
+    // Safety: guaranteed by the caller
+    unsafe { ptr.as_mut() }.ok_or_else(|| new_error!("{} is NULL", what))
+}
+
+/// Borrow the C string `ptr` points to as UTF-8
+///
+/// # Safety
+///
+/// `ptr` must be NULL or point to a NUL terminated string
+unsafe fn str<'a>(ptr: *const c_char, what: &str) -> Result<&'a str> {
+    if ptr.is_null() {
+        return Err(new_error!("{} is NULL", what));
+    }
+    // Safety: guaranteed by the caller
+    unsafe { CStr::from_ptr(ptr) }
+        .to_str()
+        .map_err(|e| new_error!("{} is not UTF-8: {}", what, e))
+}
+
+/// Borrow `len` items starting at `ptr`
+///
+/// # Safety
+///
+/// `ptr` must point to `len` valid items, or `len` must be 0
+unsafe fn slice<'a, T>(ptr: *const T, len: usize) -> &'a [T] {
+    if len == 0 {
+        &[]
+    } else {
+        // Safety: guaranteed by the caller
+        unsafe { std::slice::from_raw_parts(ptr, len) }
+    }
+}
+
+fn into_raw<T>(value: Option<T>) -> *mut T {
+    value.map_or(std::ptr::null_mut(), |value| Box::into_raw(Box::new(value)))
+}
+
+/// Update the builder `builder` points to with `f`
+///
+/// # Safety
+///
+/// As for [`take`]
+unsafe fn update(
+    builder: *mut SandboxBuilder,
+    f: impl FnOnce(SandboxBuilder) -> SandboxBuilder,
+) -> bool {
+    // Safety: guaranteed by the caller
+    check(unsafe { borrow(builder, "builder") })
+        .map(|builder| {
+            let taken = std::mem::take(builder);
+            *builder = f(taken);
+        })
+        .is_some()
+}
+
+/// Whether a hypervisor is available to run sandboxes
+#[unsafe(no_mangle)]
+pub extern "C" fn hlw_is_hypervisor_present() -> bool {
+    catch(false, hyperlight_wasm::is_hypervisor_present)
+}
+
+/// Create a builder for a sandbox with the default configuration, which
+/// must be consumed by [`hlw_sandbox_builder_build`] or released with
+/// [`hlw_sandbox_builder_free`]
+#[unsafe(no_mangle)]
+pub extern "C" fn hlw_sandbox_builder_new() -> *mut SandboxBuilder {
+    catch(std::ptr::null_mut(), || {
+        into_raw(Some(SandboxBuilder::new()))
+    })
+}
+
+/// Set the size of the buffer parameters are passed to the guest in, see
+/// `SandboxBuilder::with_guest_input_buffer_size`
+///
+/// # Safety
+///
+/// `builder` must be NULL or a builder returned by
+/// [`hlw_sandbox_builder_new`] that has not been consumed or released
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hlw_sandbox_builder_set_guest_input_buffer_size(
+    builder: *mut SandboxBuilder,
+    size: usize,
+) -> bool {
+    catch(false, || {
+        // Safety: guaranteed by the caller
+        unsafe { update(builder, |b| b.with_guest_input_buffer_size(size)) }
+    })
+}
+
+/// Set the size of the buffer results are returned from the guest in,
+/// see `SandboxBuilder::with_guest_output_buffer_size`
+///
+/// # Safety
+///
+/// As for [`hlw_sandbox_builder_set_guest_input_buffer_size`]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hlw_sandbox_builder_set_guest_output_buffer_size(
+    builder: *mut SandboxBuilder,
+    size: usize,
+) -> bool {
+    catch(false, || {
+        // Safety: guaranteed by the caller
+        unsafe { update(builder, |b| b.with_guest_output_buffer_size(size)) }
+    })
+}
+
+/// Set the size of the guest's heap, see
+/// `SandboxBuilder::with_guest_heap_size`
+///
+/// # Safety
+///
+/// As for [`hlw_sandbox_builder_set_guest_input_buffer_size`]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hlw_sandbox_builder_set_guest_heap_size(
+    builder: *mut SandboxBuilder,
+    size: u64,
+) -> bool {
+    catch(false, || {
+        // Safety: guaranteed by the caller
+        unsafe { update(builder, |b| b.with_guest_heap_size(size)) }
+    })
+}
+
+/// Interrupt guest calls that run for longer than `timeout_ms`
+/// milliseconds, see `SandboxBuilder::with_guest_call_timeout`
+///
+/// # Safety
+///
+/// As for [`hlw_sandbox_builder_set_guest_input_buffer_size`]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hlw_sandbox_builder_set_guest_call_timeout(
+    builder: *mut SandboxBuilder,
+    timeout_ms: u64,
+) -> bool {
+    catch(false, || {
+        let timeout = Duration::from_millis(timeout_ms);
+        // Safety: guaranteed by the caller
+        unsafe { update(builder, |b| b.with_guest_call_timeout(timeout)) }
+    })
+}
+
+/// Set the environment variable `key` to `value` for guests, see
+/// `SandboxBuilder::with_env`
+///
+/// # Safety
+///
+/// As for [`hlw_sandbox_builder_set_guest_input_buffer_size`], and `key`
+/// and `value` must be NULL or NUL terminated strings
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hlw_sandbox_builder_set_env(
+    builder: *mut SandboxBuilder,
+    key: *const c_char,
+    value: *const c_char,
+) -> bool {
+    catch(false, || {
+        // Safety: guaranteed by the caller
+        let Some((key, value)) =
+            check(unsafe { str(key, "key").and_then(|k| Ok((k, str(value, "value")?))) })
+        else {
+            return false;
+        };
+        // Safety: guaranteed by the caller
+        unsafe { update(builder, |b| b.with_env(key, value)) }
+    })
+}
+
+/// Build the sandbox, consuming `builder`. Returns NULL on failure.
+///
+/// # Safety
+///
+/// As for [`hlw_sandbox_builder_set_guest_input_buffer_size`]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hlw_sandbox_builder_build(
+    builder: *mut SandboxBuilder,
+) -> *mut ProtoWasmSandbox {
+    catch(std::ptr::null_mut(), || {
+        // Safety: guaranteed by the caller
+        into_raw(check(
+            unsafe { take(builder, "builder") }.and_then(SandboxBuilder::build),
+        ))
+    })
+}
+
+/// Release `builder`, which may be NULL
+///
+/// # Safety
+///
+/// As for [`hlw_sandbox_builder_set_guest_input_buffer_size`]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hlw_sandbox_builder_free(builder: *mut SandboxBuilder) {
+    catch((), || {
+        // Safety: guaranteed by the caller
+        drop(unsafe { take(builder, "builder") });
+    })
+}
+
+/// Register `function` as the host function named `name`, taking
+/// `nparams` parameters of the types in `param_types` and returning a
+/// value of type `return_type`. It is called with `user_data`, which must
+/// remain valid until the sandbox is released. Guests calling it with
+/// parameters of other types get an error, as do calls for which it
+/// returns a value of another type.
+///
+/// # Safety
+///
+/// `sandbox` must be NULL or a sandbox returned by
+/// [`hlw_sandbox_builder_build`] that has not been consumed or released,
+/// `name` must be NULL or a NUL terminated string and `param_types` must
+/// point to `nparams` tags. Unknown tags are an error.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hlw_proto_sandbox_register(
+    sandbox: *mut ProtoWasmSandbox,
+    name: *const c_char,
+    param_types: *const u32,
+    nparams: usize,
+    return_type: u32,
+    function: HlwHostFunction,
+    user_data: *mut c_void,
+) -> bool {
+    catch(false, || {
+        let register = || {
+            // Safety: guaranteed by the caller
+            let (sandbox, name) = unsafe { (borrow(sandbox, "sandbox")?, str(name, "name")?) };
+            // Safety: guaranteed by the caller
+            let param_types = unsafe { slice(param_types, nparams) }
+                .iter()
+                .map(|tag| HlwValueTag::try_from(*tag)?.parameter_type())
+                .collect::<Result<Vec<_>>>()?;
+            let return_type = HlwValueTag::try_from(return_type)?.return_type();
+            let manifest = HostFunctionManifest::new().with_function(ManifestFunction::new(
+                name,
+                param_types,
+                return_type,
+            ));
+            let callback = HostCallback {
+                function,
+                user_data,
+            };
+            sandbox.register_manifest(&manifest, move |name, params| callback.call(name, params))
+        };
+        check(register()).is_some()
+    })
+}
+
+/// Load the Wasm runtime into the sandbox, consuming `sandbox`. Returns
+/// NULL on failure.
+///
+/// # Safety
+///
+/// As for [`hlw_proto_sandbox_register`]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hlw_proto_sandbox_load_runtime(
+    sandbox: *mut ProtoWasmSandbox,
+) -> *mut WasmSandbox {
+    catch(std::ptr::null_mut(), || {
+        // Safety: guaranteed by the caller
+        into_raw(check(
+            unsafe { take(sandbox, "sandbox") }.and_then(ProtoWasmSandbox::load_runtime),
+        ))
+    })
+}
+
+/// Release `sandbox`, which may be NULL
+///
+/// # Safety
+///
+/// As for [`hlw_proto_sandbox_register`]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hlw_proto_sandbox_free(sandbox: *mut ProtoWasmSandbox) {
+    catch((), || {
+        // Safety: guaranteed by the caller
+        drop(unsafe { take(sandbox, "sandbox") });
+    })
+}
+
+/// Load the module in the file at `path`, consuming `sandbox`. Returns
+/// NULL on failure.
+///
+/// # Safety
+///
+/// `sandbox` must be NULL or a sandbox returned by
+/// [`hlw_proto_sandbox_load_runtime`] or
+/// [`hlw_loaded_sandbox_unload_module`] that has not been consumed or
+/// released, and `path` must be NULL or a NUL terminated string
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hlw_wasm_sandbox_load_module(
+    sandbox: *mut WasmSandbox,
+    path: *const c_char,
+) -> *mut LoadedWasmSandbox {
+    catch(std::ptr::null_mut(), || {
+        // Safety: guaranteed by the caller
+        let load = || unsafe {
+            let sandbox = take(sandbox, "sandbox")?;
+            sandbox.load_module(Path::new(str(path, "path")?))
+        };
+        into_raw(check(load()))
+    })
+}
+
+/// Load the module in the `len` bytes at `buffer`, consuming `sandbox`.
+/// Returns NULL on failure.
+///
+/// # Safety
+///
+/// As for [`hlw_wasm_sandbox_load_module`], and `buffer` must point to
+/// `len` bytes
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hlw_wasm_sandbox_load_module_from_buffer(
+    sandbox: *mut WasmSandbox,
+    buffer: *const u8,
+    len: usize,
+) -> *mut LoadedWasmSandbox {
+    catch(std::ptr::null_mut(), || {
+        // Safety: guaranteed by the caller
+        let load = || unsafe {
+            let sandbox = take(sandbox, "sandbox")?;
+            sandbox.load_module_from_buffer(slice(buffer, len))
+        };
+        into_raw(check(load()))
+    })
+}
+
+/// Release `sandbox`, which may be NULL
+///
+/// # Safety
+///
+/// As for [`hlw_wasm_sandbox_load_module`]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hlw_wasm_sandbox_free(sandbox: *mut WasmSandbox) {
+    catch((), || {
+        // Safety: guaranteed by the caller
+        drop(unsafe { take(sandbox, "sandbox") });
+    })
+}
+
+fn call<Output: SupportedReturnType>(
+    sandbox: &mut LoadedWasmSandbox,
+    name: &str,
+    params: DynamicParams,
+) -> Result<ReturnValue> {
+    sandbox
+        .call_guest_function::<Output>(name, params)
+        .map(Output::into_value)
+}
+
+/// Call the guest function named `name` with the `nargs` parameters in
+/// `args`, expecting it to return a value of type `return_type`, which is
+/// stored in `*result`. Strings and buffers in `*result` must be released
+/// with [`hlw_value_free`]. Returns `false` on failure, leaving `*result`
+/// unchanged.
+///
+/// # Safety
+///
+/// `sandbox` must be NULL or a sandbox returned by
+/// [`hlw_wasm_sandbox_load_module`] or
+/// [`hlw_wasm_sandbox_load_module_from_buffer`] that has not been
+/// consumed or released, `name` must be NULL or a NUL terminated string,
+/// `args` must point to `nargs` valid values and `result` must be NULL or
+/// valid for writes. Unknown tags are an error.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hlw_loaded_sandbox_call(
+    sandbox: *mut LoadedWasmSandbox,
+    name: *const c_char,
+    args: *const HlwValue,
+    nargs: usize,
+    return_type: u32,
+    result: *mut HlwValue,
+) -> bool {
+    catch(false, || {
+        let call = || {
+            // Safety: guaranteed by the caller
+            let (sandbox, name, result) = unsafe {
+                (
+                    borrow(sandbox, "sandbox")?,
+                    str(name, "name")?,
+                    borrow(result, "result")?,
+                )
+            };
+            // Safety: guaranteed by the caller
+            let params = unsafe { slice(args, nargs) }
+                .iter()
+                .map(|arg| unsafe { arg.to_parameter() })
+                .collect::<Result<Vec<_>>>()
+                .map(DynamicParams)?;
+            let value = match HlwValueTag::try_from(return_type)? {
+                HlwValueTag::Void => call::<()>(sandbox, name, params),
+                HlwValueTag::Int => call::<i32>(sandbox, name, params),
+                HlwValueTag::UInt => call::<u32>(sandbox, name, params),
+                HlwValueTag::Long => call::<i64>(sandbox, name, params),
+                HlwValueTag::ULong => call::<u64>(sandbox, name, params),
+                HlwValueTag::Float => call::<f32>(sandbox, name, params),
+                HlwValueTag::Double => call::<f64>(sandbox, name, params),
+                HlwValueTag::Bool => call::<bool>(sandbox, name, params),
+                HlwValueTag::String => call::<String>(sandbox, name, params),
+                HlwValueTag::Bytes => call::<Vec<u8>>(sandbox, name, params),
+            }?;
+            *result = HlwValue::owned(value);
+            Ok(())
+        };
+        check(call()).is_some()
+    })
+}
+
+/// Unload the module, consuming `sandbox`, so that another can be loaded.
+/// Returns NULL on failure.
+///
+/// # Safety
+///
+/// As for [`hlw_loaded_sandbox_call`]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hlw_loaded_sandbox_unload_module(
+    sandbox: *mut LoadedWasmSandbox,
+) -> *mut WasmSandbox {
+    catch(std::ptr::null_mut(), || {
+        // Safety: guaranteed by the caller
+        into_raw(check(
+            unsafe { take(sandbox, "sandbox") }.and_then(LoadedWasmSandbox::unload_module),
+        ))
+    })
+}
+
+/// Release `sandbox`, which may be NULL
+///
+/// # Safety
+///
+/// As for [`hlw_loaded_sandbox_call`]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hlw_loaded_sandbox_free(sandbox: *mut LoadedWasmSandbox) {
+    catch((), || {
+        // Safety: guaranteed by the caller
+        drop(unsafe { take(sandbox, "sandbox") });
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{CStr, CString};
+    use std::ptr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use examples_common::get_wasm_module_path;
+
+    use super::*;
+
+    static FAILED: &str = "negative values are not allowed";
+
+    unsafe extern "C" fn test_host_func(
+        user_data: *mut c_void,
+        args: *const HlwValue,
+        nargs: usize,
+        result: *mut HlwValue,
+    ) -> bool {
+        let calls = unsafe { &*(user_data as *const AtomicUsize) };
+        calls.fetch_add(1, Ordering::SeqCst);
+        assert_eq!(nargs, 1);
+        let arg = unsafe { *args };
+        assert_eq!(arg.tag().unwrap(), HlwValueTag::Int);
+        let a = unsafe { arg.data.i32 };
+        if a < 0 {
+            unsafe {
+                *result = HlwValue {
+                    tag: HlwValueTag::String as u32,
+                    data: HlwValueData {
+                        bytes: HlwBytes {
+                            ptr: FAILED.as_ptr(),
+                            len: FAILED.len(),
+                        },
+                    },
+                }
+            };
+            return false;
+        }
+        unsafe {
+            *result = HlwValue {
+                tag: HlwValueTag::Int as u32,
+                data: HlwValueData { i32: a * 2 },
+            }
+        };
+        true
+    }
+
+    fn last_error() -> String {
+        let message = hlw_last_error();
+        assert!(!message.is_null());
+        unsafe { CStr::from_ptr(message) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn test_c_api() {
+        let calls = AtomicUsize::new(0);
+        let builder = hlw_sandbox_builder_new();
+        assert!(unsafe { hlw_sandbox_builder_set_guest_heap_size(builder, 0x400000) });
+        let proto = unsafe { hlw_sandbox_builder_build(builder) };
+        assert!(!proto.is_null(), "{}", last_error());
+
+        let name = CString::new("TestHostFunc").unwrap();
+        let param_types = [HlwValueTag::Int as u32];
+        assert!(unsafe {
+            hlw_proto_sandbox_register(
+                proto,
+                name.as_ptr(),
+                param_types.as_ptr(),
+                param_types.len(),
+                HlwValueTag::Int as u32,
+                test_host_func,
+                &calls as *const AtomicUsize as *mut c_void,
+            )
+        });
+        let wasm_sandbox = unsafe { hlw_proto_sandbox_load_runtime(proto) };
+        assert!(!wasm_sandbox.is_null(), "{}", last_error());
+
+        // The sandbox is consumed even if the module fails to load
+        let missing = CString::new("no_such_module.aot").unwrap();
+        let loaded = unsafe { hlw_wasm_sandbox_load_module(wasm_sandbox, missing.as_ptr()) };
+        assert!(loaded.is_null());
+        assert!(!last_error().is_empty());
+
+        let builder = hlw_sandbox_builder_new();
+        let proto = unsafe { hlw_sandbox_builder_build(builder) };
+        assert!(unsafe {
+            hlw_proto_sandbox_register(
+                proto,
+                name.as_ptr(),
+                param_types.as_ptr(),
+                param_types.len(),
+                HlwValueTag::Int as u32,
+                test_host_func,
+                &calls as *const AtomicUsize as *mut c_void,
+            )
+        });
+        let wasm_sandbox = unsafe { hlw_proto_sandbox_load_runtime(proto) };
+        let path = get_wasm_module_path("rust_wasm_samples.aot").unwrap();
+        let path = CString::new(path).unwrap();
+        let loaded = unsafe { hlw_wasm_sandbox_load_module(wasm_sandbox, path.as_ptr()) };
+        assert!(!loaded.is_null(), "{}", last_error());
+
+        let function = CString::new("call_host_function").unwrap();
+        let mut result = HlwValue::void();
+        let args = [HlwValue {
+            tag: HlwValueTag::Int as u32,
+            data: HlwValueData { i32: 21 },
+        }];
+        assert!(
+            unsafe {
+                hlw_loaded_sandbox_call(
+                    loaded,
+                    function.as_ptr(),
+                    args.as_ptr(),
+                    args.len(),
+                    HlwValueTag::Int as u32,
+                    &mut result,
+                )
+            },
+            "{}",
+            last_error()
+        );
+        assert_eq!(result.tag().unwrap(), HlwValueTag::Int);
+        assert_eq!(unsafe { result.data.i32 }, 42);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Errors from host functions reach the caller
+        let args = [HlwValue {
+            tag: HlwValueTag::Int as u32,
+            data: HlwValueData { i32: -1 },
+        }];
+        assert!(!unsafe {
+            hlw_loaded_sandbox_call(
+                loaded,
+                function.as_ptr(),
+                args.as_ptr(),
+                args.len(),
+                HlwValueTag::Int as u32,
+                &mut result,
+            )
+        });
+        assert!(last_error().contains(FAILED), "{}", last_error());
+
+        assert!(!unsafe {
+            hlw_loaded_sandbox_call(
+                loaded,
+                ptr::null(),
+                ptr::null(),
+                0,
+                HlwValueTag::Int as u32,
+                &mut result,
+            )
+        });
+        assert_eq!(last_error(), "name is NULL");
+
+        // Unknown tags from C are rejected rather than trusted
+        assert!(!unsafe {
+            hlw_loaded_sandbox_call(
+                loaded,
+                function.as_ptr(),
+                args.as_ptr(),
+                args.len(),
+                42,
+                &mut result,
+            )
+        });
+        assert_eq!(last_error(), "unknown value tag 42");
+
+        let wasm_sandbox = unsafe { hlw_loaded_sandbox_unload_module(loaded) };
+        assert!(!wasm_sandbox.is_null(), "{}", last_error());
+        unsafe { hlw_wasm_sandbox_free(wasm_sandbox) };
+    }
+}
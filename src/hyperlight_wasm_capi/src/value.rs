@@ -0,0 +1,391 @@
+/*
+Copyright 2025 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use std::ptr;
+
+use hyperlight_wasm::{
+    HyperlightError, ParameterType, ParameterValue, Result, ReturnType, ReturnValue, new_error,
+};
+
+use crate::error::catch;
+
+/// The type of an [`HlwValue`]. Tags are passed across the C API as
+/// `u32`s, since C callers may pass any integer, and converted with
+/// [`TryFrom`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HlwValueTag {
+    /// No value, only valid as a return type
+    Void = 0,
+    /// An `int32_t`
+    Int,
+    /// A `uint32_t`
+    UInt,
+    /// An `int64_t`
+    Long,
+    /// A `uint64_t`
+    ULong,
+    /// A `float`
+    Float,
+    /// A `double`
+    Double,
+    /// A `bool`
+    Bool,
+    /// A UTF-8 string
+    String,
+    /// A buffer of bytes
+    Bytes,
+}
+
+impl TryFrom<u32> for HlwValueTag {
+    type Error = HyperlightError;
+
+    fn try_from(tag: u32) -> Result<Self> {
+        Ok(match tag {
+            0 => HlwValueTag::Void,
+            1 => HlwValueTag::Int,
+            2 => HlwValueTag::UInt,
+            3 => HlwValueTag::Long,
+            4 => HlwValueTag::ULong,
+            5 => HlwValueTag::Float,
+            6 => HlwValueTag::Double,
+            7 => HlwValueTag::Bool,
+            8 => HlwValueTag::String,
+            9 => HlwValueTag::Bytes,
+            _ => return Err(new_error!("unknown value tag {}", tag)),
+        })
+    }
+}
+
+impl HlwValueTag {
+    pub(crate) fn parameter_type(self) -> Result<ParameterType> {
+        Ok(match self {
+            HlwValueTag::Void => return Err(new_error!("parameters cannot be void")),
+            HlwValueTag::Int => ParameterType::Int,
+            HlwValueTag::UInt => ParameterType::UInt,
+            HlwValueTag::Long => ParameterType::Long,
+            HlwValueTag::ULong => ParameterType::ULong,
+            HlwValueTag::Float => ParameterType::Float,
+            HlwValueTag::Double => ParameterType::Double,
+            HlwValueTag::Bool => ParameterType::Bool,
+            HlwValueTag::String => ParameterType::String,
+            HlwValueTag::Bytes => ParameterType::VecBytes,
+        })
+    }
+
+    pub(crate) fn return_type(self) -> ReturnType {
+        match self {
+            HlwValueTag::Void => ReturnType::Void,
+            HlwValueTag::Int => ReturnType::Int,
+            HlwValueTag::UInt => ReturnType::UInt,
+            HlwValueTag::Long => ReturnType::Long,
+            HlwValueTag::ULong => ReturnType::ULong,
+            HlwValueTag::Float => ReturnType::Float,
+            HlwValueTag::Double => ReturnType::Double,
+            HlwValueTag::Bool => ReturnType::Bool,
+            HlwValueTag::String => ReturnType::String,
+            HlwValueTag::Bytes => ReturnType::VecBytes,
+        }
+    }
+}
+
+/// A string or buffer held by an [`HlwValue`]. Strings are not NUL
+/// terminated when passed to the library, but strings it returns are,
+/// with the NUL not counted in `len`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct HlwBytes {
+    /// The first byte, which may be NULL if `len` is 0
+    pub ptr: *const u8,
+    /// The number of bytes
+    pub len: usize,
+}
+
+/// The value of an [`HlwValue`], selected by its tag
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub union HlwValueData {
+    /// The value of an `Int`
+    pub i32: i32,
+    /// The value of a `UInt`
+    pub u32: u32,
+    /// The value of a `Long`
+    pub i64: i64,
+    /// The value of a `ULong`
+    pub u64: u64,
+    /// The value of a `Float`
+    pub f32: f32,
+    /// The value of a `Double`
+    pub f64: f64,
+    /// The value of a `Bool`
+    pub b: bool,
+    /// The value of a `String` or `Bytes`
+    pub bytes: HlwBytes,
+}
+
+/// A parameter or return value of a guest or host function
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct HlwValue {
+    /// The type of the value, an [`HlwValueTag`]
+    pub tag: u32,
+    /// The value
+    pub data: HlwValueData,
+}
+
+impl HlwValue {
+    pub(crate) fn void() -> Self {
+        HlwValue {
+            tag: HlwValueTag::Void as u32,
+            data: HlwValueData { u64: 0 },
+        }
+    }
+
+    /// The type of the value, or an error if its tag is unknown
+    pub(crate) fn tag(&self) -> Result<HlwValueTag> {
+        HlwValueTag::try_from(self.tag)
+    }
+
+    /// A value pointing into `value`, which must outlive it
+    pub(crate) fn borrowed(value: &ParameterValue) -> Self {
+        let borrow = |bytes: &[u8]| HlwBytes {
+            ptr: bytes.as_ptr(),
+            len: bytes.len(),
+        };
+        let (tag, data) = match value {
+            ParameterValue::Int(v) => (HlwValueTag::Int, HlwValueData { i32: *v }),
+            ParameterValue::UInt(v) => (HlwValueTag::UInt, HlwValueData { u32: *v }),
+            ParameterValue::Long(v) => (HlwValueTag::Long, HlwValueData { i64: *v }),
+            ParameterValue::ULong(v) => (HlwValueTag::ULong, HlwValueData { u64: *v }),
+            ParameterValue::Float(v) => (HlwValueTag::Float, HlwValueData { f32: *v }),
+            ParameterValue::Double(v) => (HlwValueTag::Double, HlwValueData { f64: *v }),
+            ParameterValue::Bool(v) => (HlwValueTag::Bool, HlwValueData { b: *v }),
+            ParameterValue::String(v) => (
+                HlwValueTag::String,
+                HlwValueData {
+                    bytes: borrow(v.as_bytes()),
+                },
+            ),
+            ParameterValue::VecBytes(v) => (HlwValueTag::Bytes, HlwValueData { bytes: borrow(v) }),
+        };
+        HlwValue {
+            tag: tag as u32,
+            data,
+        }
+    }
+
+    /// A value owning its string or buffer, which must be released with
+    /// [`hlw_value_free`]
+    pub(crate) fn owned(value: ReturnValue) -> Self {
+        let leak = |bytes: Vec<u8>| HlwBytes {
+            len: bytes.len(),
+            ptr: Box::into_raw(bytes.into_boxed_slice()) as *const u8,
+        };
+        let (tag, data) = match value {
+            ReturnValue::Void(()) => return Self::void(),
+            ReturnValue::Int(v) => (HlwValueTag::Int, HlwValueData { i32: v }),
+            ReturnValue::UInt(v) => (HlwValueTag::UInt, HlwValueData { u32: v }),
+            ReturnValue::Long(v) => (HlwValueTag::Long, HlwValueData { i64: v }),
+            ReturnValue::ULong(v) => (HlwValueTag::ULong, HlwValueData { u64: v }),
+            ReturnValue::Float(v) => (HlwValueTag::Float, HlwValueData { f32: v }),
+            ReturnValue::Double(v) => (HlwValueTag::Double, HlwValueData { f64: v }),
+            ReturnValue::Bool(v) => (HlwValueTag::Bool, HlwValueData { b: v }),
+            ReturnValue::String(v) => {
+                let mut bytes = v.into_bytes();
+                bytes.push(0);
+                let mut bytes = leak(bytes);
+                bytes.len -= 1;
+                (HlwValueTag::String, HlwValueData { bytes })
+            }
+            ReturnValue::VecBytes(v) => (HlwValueTag::Bytes, HlwValueData { bytes: leak(v) }),
+        };
+        HlwValue {
+            tag: tag as u32,
+            data,
+        }
+    }
+
+    /// # Safety
+    ///
+    /// `self` must be a `String` or `Bytes` whose pointer is valid for
+    /// reads of its length
+    unsafe fn bytes(&self) -> &[u8] {
+        // Safety: guaranteed by the caller
+        unsafe {
+            let bytes = self.data.bytes;
+            if bytes.len == 0 {
+                &[]
+            } else {
+                std::slice::from_raw_parts(bytes.ptr, bytes.len)
+            }
+        }
+    }
+
+    /// # Safety
+    ///
+    /// `self` must be a `String`, with a pointer valid for reads of its
+    /// length
+    pub(crate) unsafe fn string(&self) -> Result<String> {
+        // Safety: guaranteed by the caller
+        let bytes = unsafe { self.bytes() };
+        String::from_utf8(bytes.to_vec()).map_err(|e| new_error!("string is not UTF-8: {}", e))
+    }
+
+    /// Copy the value into a parameter
+    ///
+    /// # Safety
+    ///
+    /// The value must match its tag, and `String` and `Bytes` values must
+    /// point to memory valid for reads of their length. Unknown tags are
+    /// an error.
+    pub(crate) unsafe fn to_parameter(self) -> Result<ParameterValue> {
+        // Safety: guaranteed by the caller
+        unsafe {
+            Ok(match self.tag()? {
+                HlwValueTag::Void => return Err(new_error!("parameters cannot be void")),
+                HlwValueTag::Int => ParameterValue::Int(self.data.i32),
+                HlwValueTag::UInt => ParameterValue::UInt(self.data.u32),
+                HlwValueTag::Long => ParameterValue::Long(self.data.i64),
+                HlwValueTag::ULong => ParameterValue::ULong(self.data.u64),
+                HlwValueTag::Float => ParameterValue::Float(self.data.f32),
+                HlwValueTag::Double => ParameterValue::Double(self.data.f64),
+                HlwValueTag::Bool => ParameterValue::Bool(self.data.b),
+                HlwValueTag::String => ParameterValue::String(self.string()?),
+                HlwValueTag::Bytes => ParameterValue::VecBytes(self.bytes().to_vec()),
+            })
+        }
+    }
+
+    /// Copy the value into a return value
+    ///
+    /// # Safety
+    ///
+    /// As for [`to_parameter`](Self::to_parameter)
+    pub(crate) unsafe fn to_return(self) -> Result<ReturnValue> {
+        if self.tag()? == HlwValueTag::Void {
+            return Ok(ReturnValue::Void(()));
+        }
+        // Safety: guaranteed by the caller
+        Ok(match unsafe { self.to_parameter()? } {
+            ParameterValue::Int(v) => ReturnValue::Int(v),
+            ParameterValue::UInt(v) => ReturnValue::UInt(v),
+            ParameterValue::Long(v) => ReturnValue::Long(v),
+            ParameterValue::ULong(v) => ReturnValue::ULong(v),
+            ParameterValue::Float(v) => ReturnValue::Float(v),
+            ParameterValue::Double(v) => ReturnValue::Double(v),
+            ParameterValue::Bool(v) => ReturnValue::Bool(v),
+            ParameterValue::String(v) => ReturnValue::String(v),
+            ParameterValue::VecBytes(v) => ReturnValue::VecBytes(v),
+        })
+    }
+}
+
+/// Release the string or buffer held by `value`, which must have been
+/// returned by this library, and set it to `Void`. Does nothing if
+/// `value` is NULL or holds no string or buffer, including if its tag is
+/// unknown.
+///
+/// # Safety
+///
+/// `value` must be NULL or point to a valid value returned by this
+/// library that has not been released.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hlw_value_free(value: *mut HlwValue) {
+    catch((), || {
+        // Safety: guaranteed by the caller
+        let Some(value) = (unsafe { value.as_mut() }) else {
+            return;
+        };
+        // Safety: the tag says the value holds bytes
+        let (bytes, len) = match value.tag() {
+            // Strings are allocated with a NUL that is not counted
+            Ok(HlwValueTag::String) => unsafe { (value.data.bytes, value.data.bytes.len + 1) },
+            Ok(HlwValueTag::Bytes) => unsafe { (value.data.bytes, value.data.bytes.len) },
+            _ => return,
+        };
+        // Safety: the buffer was allocated by HlwValue::owned with this
+        // length
+        drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(bytes.ptr as *mut u8, len)) });
+        *value = HlwValue::void();
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_value_conversions() {
+        let params = [
+            ParameterValue::Int(-1),
+            ParameterValue::ULong(u64::MAX),
+            ParameterValue::Double(0.5),
+            ParameterValue::Bool(true),
+            ParameterValue::String("hello".to_string()),
+            ParameterValue::VecBytes(vec![1, 2, 3]),
+            ParameterValue::VecBytes(vec![]),
+        ];
+        for param in &params {
+            let value = HlwValue::borrowed(param);
+            assert_eq!(&unsafe { value.to_parameter() }.unwrap(), param);
+        }
+
+        let mut value = HlwValue::owned(ReturnValue::String("hello".to_string()));
+        assert_eq!(value.tag().unwrap(), HlwValueTag::String);
+        let bytes = unsafe { value.data.bytes };
+        assert_eq!(bytes.len, 5);
+        // Returned strings can be used as C strings
+        assert_eq!(unsafe { *bytes.ptr.add(5) }, 0);
+        assert!(matches!(
+            unsafe { value.to_return() }.unwrap(),
+            ReturnValue::String(s) if s == "hello"
+        ));
+        unsafe { hlw_value_free(&mut value) };
+        assert_eq!(value.tag().unwrap(), HlwValueTag::Void);
+
+        let mut value = HlwValue::owned(ReturnValue::VecBytes(vec![4, 5]));
+        assert!(matches!(
+            unsafe { value.to_return() }.unwrap(),
+            ReturnValue::VecBytes(v) if v == [4, 5]
+        ));
+        unsafe { hlw_value_free(&mut value) };
+
+        let void = HlwValue::void();
+        assert!(unsafe { void.to_parameter() }.is_err());
+        assert!(matches!(
+            unsafe { void.to_return() }.unwrap(),
+            ReturnValue::Void(())
+        ));
+    }
+
+    #[test]
+    fn test_value_tags() {
+        for tag in 0..=9 {
+            assert_eq!(HlwValueTag::try_from(tag).unwrap() as u32, tag);
+        }
+        assert!(HlwValueTag::try_from(10).is_err());
+        assert!(HlwValueTag::try_from(u32::MAX).is_err());
+
+        // Values with unknown tags are rejected, and not freed
+        let mut value = HlwValue {
+            tag: 42,
+            data: HlwValueData { u64: 1 },
+        };
+        assert!(unsafe { value.to_parameter() }.is_err());
+        assert!(unsafe { value.to_return() }.is_err());
+        unsafe { hlw_value_free(&mut value) };
+        assert_eq!(value.tag, 42);
+    }
+}
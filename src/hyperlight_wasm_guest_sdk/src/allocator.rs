@@ -0,0 +1,65 @@
+/*
+Copyright 2025 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The `malloc` and `free` exports hyperlight-wasm uses to allocate
+//! memory in the module.
+
+use core::alloc::Layout;
+
+// Each allocation is preceded by a header holding its total size, since
+// free() is not given one and the Rust allocator needs it.
+const HEADER: usize = 8;
+
+fn layout(size: usize) -> Option<Layout> {
+    Layout::from_size_align(size.checked_add(HEADER)?, HEADER).ok()
+}
+
+/// Allocate `size` bytes, returning null on failure.
+#[unsafe(no_mangle)]
+pub extern "C" fn malloc(size: usize) -> *mut u8 {
+    let Some(layout) = layout(size) else {
+        return core::ptr::null_mut();
+    };
+    // Safety: layout has a non-zero size
+    let base = unsafe { alloc::alloc::alloc(layout) };
+    if base.is_null() {
+        return base;
+    }
+    // Safety: base points to at least HEADER bytes, aligned for usize
+    unsafe {
+        (base as *mut usize).write(size);
+        base.add(HEADER)
+    }
+}
+
+/// Free memory previously returned by [`malloc`]. Null is ignored.
+///
+/// # Safety
+/// `ptr` must be null or have been returned by [`malloc`] and not yet
+/// freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn free(ptr: *mut u8) {
+    if ptr.is_null() {
+        return;
+    }
+    // Safety: the caller guarantees ptr came from malloc, which stored
+    // the size in the header immediately before it
+    unsafe {
+        let base = ptr.sub(HEADER);
+        let size = (base as *const usize).read();
+        alloc::alloc::dealloc(base, layout(size).unwrap());
+    }
+}
@@ -0,0 +1,426 @@
+/*
+Copyright 2025 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+#![no_std]
+#![deny(missing_docs)]
+//! Helpers for writing Rust Wasm modules that run in hyperlight-wasm.
+//!
+//! hyperlight-wasm passes strings and byte buffers to and from Wasm
+//! modules as pointers into the module's linear memory (see
+//! `marshal.rs` in hyperlight-wasm-runtime for the full contract). This
+//! crate implements that contract so guest code can be written with
+//! ordinary Rust types:
+//!
+//! - [`hyperlight_export`] exports a function so that it can be called
+//!   with `LoadedWasmSandbox::call_guest_function`.
+//! - [`host_functions!`] declares functions registered on the host with
+//!   `ProtoWasmSandbox::register` and generates safe wrappers for them.
+//! - With the `json` feature, `#[hyperlight_export(json)]` exports a
+//!   function whose parameters and result are any types implementing
+//!   serde's traits, passed as JSON, so that it can be called with
+//!   `LoadedWasmSandbox::call_guest_function_json`.
+//! - The `malloc` and `free` functions used by the host to allocate
+//!   memory in the module are exported when the `allocator` feature is
+//!   enabled (the default).
+//!
+//! ```ignore
+//! use hyperlight_wasm_guest_sdk::{host_functions, hyperlight_export};
+//!
+//! host_functions! {
+//!     fn HostLog(msg: &str) -> i32;
+//! }
+//!
+//! #[hyperlight_export]
+//! fn Greet(name: String) -> String {
+//!     HostLog("Greet called");
+//!     format!("Hello, {name}!")
+//! }
+//! ```
+
+extern crate alloc;
+
+#[cfg(all(target_arch = "wasm32", feature = "allocator"))]
+mod allocator;
+mod marshal;
+
+pub use hyperlight_wasm_guest_sdk_macro::{host_functions, hyperlight_export};
+pub use marshal::{FromGuestParam, FromHostReturn, IntoGuestReturn, IntoHostParam};
+
+#[doc(hidden)]
+pub mod __private {
+    pub use alloc::format;
+
+    #[cfg(feature = "json")]
+    pub use crate::json::call as json_call;
+    pub use crate::marshal::take_bytes;
+}
+
+#[cfg(feature = "json")]
+mod json {
+    use alloc::vec::Vec;
+
+    use serde::Serialize;
+    use serde::de::DeserializeOwned;
+
+    /// Call `f` with the parameters encoded as a JSON array in `params`,
+    /// returning its result encoded as JSON. The call traps if the
+    /// parameters cannot be decoded.
+    pub fn call<P: DeserializeOwned, R: Serialize>(
+        params: &[u8],
+        f: impl FnOnce(P) -> R,
+    ) -> Vec<u8> {
+        let params = match serde_json::from_slice(params) {
+            Ok(params) => params,
+            Err(e) => panic!("malformed JSON parameters: {e}"),
+        };
+        match serde_json::to_vec(&f(params)) {
+            Ok(result) => result,
+            Err(e) => panic!("result cannot be encoded as JSON: {e}"),
+        }
+    }
+}
+
+#[link(wasm_import_module = "wasi_snapshot_preview1")]
+unsafe extern "C" {
+    fn fd_write(fd: i32, iovs: *const u32, iovs_len: i32, retptr: *mut u32) -> i32;
+    fn random_get(buf: *mut u8, buf_len: u32) -> i32;
+}
+
+fn write(fd: i32, s: &str) -> usize {
+    let iov: [u32; 2] = [s.as_ptr() as u32, s.len() as u32];
+    let mut written: u32 = 0;
+    // Safety: iov describes a single valid buffer, and written is a
+    // valid location for the result
+    unsafe {
+        fd_write(fd, iov.as_ptr(), 1, &mut written);
+    }
+    written as usize
+}
+
+/// Write `s` to the host's standard output, returning the number of
+/// bytes written.
+pub fn print(s: &str) -> usize {
+    write(1, s)
+}
+
+/// Write `s` to the host's standard error, returning the number of
+/// bytes written. Nothing is written unless the host captures standard
+/// error.
+pub fn eprint(s: &str) -> usize {
+    write(2, s)
+}
+
+/// The limits of the sandbox the module runs in, read through the
+/// `hlwasm:limits` interface provided by hyperlight-wasm
+pub mod limits {
+    #[link(wasm_import_module = "hlwasm:limits")]
+    unsafe extern "C" {
+        #[link_name = "heap-size"]
+        fn hl_heap_size() -> u64;
+        #[link_name = "input-buffer-size"]
+        fn hl_input_buffer_size() -> u64;
+        #[link_name = "output-buffer-size"]
+        fn hl_output_buffer_size() -> u64;
+        #[link_name = "call-timeout-us"]
+        fn hl_call_timeout_us() -> u64;
+    }
+
+    /// The size of the sandbox's heap in bytes
+    pub fn heap_size() -> u64 {
+        // Safety: the import takes no parameters
+        unsafe { hl_heap_size() }
+    }
+
+    /// The size in bytes of the buffer used to pass parameters to guest
+    /// functions and host function results to the guest
+    pub fn input_buffer_size() -> u64 {
+        // Safety: the import takes no parameters
+        unsafe { hl_input_buffer_size() }
+    }
+
+    /// The size in bytes of the buffer used to pass guest function results
+    /// and host function parameters to the host
+    pub fn output_buffer_size() -> u64 {
+        // Safety: the import takes no parameters
+        unsafe { hl_output_buffer_size() }
+    }
+
+    /// The longest a guest call may run for, in microseconds, or 0 if
+    /// calls are not limited
+    pub fn call_timeout_us() -> u64 {
+        // Safety: the import takes no parameters
+        unsafe { hl_call_timeout_us() }
+    }
+}
+
+#[link(wasm_import_module = "env")]
+unsafe extern "C" {
+    #[link_name = "ReportProgress"]
+    fn hl_report_progress(progress: u32, partial: *const u8, partial_len: i32);
+    #[link_name = "EmitChunk"]
+    fn hl_emit_chunk(chunk: *const u8, chunk_len: i32);
+}
+
+/// Report progress from a long-running guest function, along with an
+/// optional partial result, to the callback set by the host with
+/// `ProtoWasmSandbox::on_progress`. The meaning of `progress` and
+/// `partial` is up to the module and the host.
+pub fn report_progress(progress: u32, partial: &[u8]) {
+    // Safety: partial is a valid buffer of partial.len() bytes
+    unsafe { hl_report_progress(progress, partial.as_ptr(), partial.len() as i32) }
+}
+
+/// Emit a chunk of the output of a guest function, which the host
+/// receives from `LoadedWasmSandbox::call_guest_function_streaming`.
+/// Large outputs can be emitted in chunks rather than returned, so that
+/// they need not fit in the sandbox's output buffer.
+pub fn emit_chunk(chunk: &[u8]) {
+    // Safety: chunk is a valid buffer of chunk.len() bytes
+    unsafe { hl_emit_chunk(chunk.as_ptr(), chunk.len() as i32) }
+}
+
+/// The state cells declared by the host with
+/// `ProtoWasmSandbox::declare_state_cell`, read and written through the
+/// `hlwasm:state` interface provided by hyperlight-wasm. Accessing a cell
+/// that has not been declared, or that holds a different type, traps.
+pub mod state {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    #[link(wasm_import_module = "hlwasm:state")]
+    unsafe extern "C" {
+        #[link_name = "get-u64"]
+        fn hl_get_u64(name: *const u8, name_len: i32) -> u64;
+        #[link_name = "set-u64"]
+        fn hl_set_u64(name: *const u8, name_len: i32, value: u64);
+        #[link_name = "get-bytes"]
+        fn hl_get_bytes(name: *const u8, name_len: i32, buf: *mut u8, buf_len: i32) -> i32;
+        #[link_name = "set-bytes"]
+        fn hl_set_bytes(name: *const u8, name_len: i32, buf: *const u8, buf_len: i32);
+    }
+
+    /// Read the `u64` cell `name`
+    pub fn get_u64(name: &str) -> u64 {
+        // Safety: name is a valid buffer of name.len() bytes
+        unsafe { hl_get_u64(name.as_ptr(), name.len() as i32) }
+    }
+
+    /// Set the `u64` cell `name`
+    pub fn set_u64(name: &str, value: u64) {
+        // Safety: name is a valid buffer of name.len() bytes
+        unsafe { hl_set_u64(name.as_ptr(), name.len() as i32, value) }
+    }
+
+    /// Read the bytes cell `name`
+    pub fn get_bytes(name: &str) -> Vec<u8> {
+        let mut buf = vec![0u8; 64];
+        loop {
+            // Safety: name and buf are valid buffers of their lengths
+            let len = unsafe {
+                hl_get_bytes(
+                    name.as_ptr(),
+                    name.len() as i32,
+                    buf.as_mut_ptr(),
+                    buf.len() as i32,
+                )
+            } as usize;
+            if len <= buf.len() {
+                buf.truncate(len);
+                return buf;
+            }
+            // The cell may change between calls, so read it again
+            buf.resize(len, 0);
+        }
+    }
+
+    /// Set the bytes cell `name`
+    pub fn set_bytes(name: &str, value: &[u8]) {
+        // Safety: name and value are valid buffers of their lengths
+        unsafe {
+            hl_set_bytes(
+                name.as_ptr(),
+                name.len() as i32,
+                value.as_ptr(),
+                value.len() as i32,
+            )
+        }
+    }
+}
+
+/// Host buffers mapped into the sandbox with
+/// `LoadedWasmSandbox::map_buffer`, read and written through the
+/// `hlwasm:buffers` interface provided by hyperlight-wasm without the data
+/// passing through a host function call. Buffers are identified by the
+/// guest address the host mapped them at, which it passes to the module,
+/// for example as a parameter. Accessing a buffer that is not mapped, or
+/// writing one mapped read-only, traps.
+pub mod buffers {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    #[link(wasm_import_module = "hlwasm:buffers")]
+    unsafe extern "C" {
+        #[link_name = "len"]
+        fn hl_len(addr: u64) -> i64;
+        #[link_name = "read"]
+        fn hl_read(addr: u64, offset: u64, buf: *mut u8, buf_len: i32) -> i32;
+        #[link_name = "write"]
+        fn hl_write(addr: u64, offset: u64, buf: *const u8, buf_len: i32) -> i32;
+    }
+
+    /// The length of the buffer at `addr`, or `None` if no buffer is
+    /// mapped there
+    pub fn len(addr: u64) -> Option<u64> {
+        // Safety: hl_len only reads its parameter
+        u64::try_from(unsafe { hl_len(addr) }).ok()
+    }
+
+    /// Copy the bytes of the buffer at `addr` from `offset` into `buf`,
+    /// returning how many were copied, fewer than `buf.len()` if the
+    /// buffer ends first
+    pub fn read(addr: u64, offset: u64, buf: &mut [u8]) -> usize {
+        // Safety: buf is a valid buffer of buf.len() bytes
+        unsafe { hl_read(addr, offset, buf.as_mut_ptr(), buf.len() as i32) as usize }
+    }
+
+    /// Copy `buf` into the buffer at `addr` from `offset`, returning how
+    /// many bytes were copied, fewer than `buf.len()` if the buffer ends
+    /// first
+    pub fn write(addr: u64, offset: u64, buf: &[u8]) -> usize {
+        // Safety: buf is a valid buffer of buf.len() bytes
+        unsafe { hl_write(addr, offset, buf.as_ptr(), buf.len() as i32) as usize }
+    }
+
+    /// Copy the whole buffer at `addr`, or `None` if no buffer is mapped
+    /// there
+    pub fn read_all(addr: u64) -> Option<Vec<u8>> {
+        let mut buf = vec![0u8; len(addr)? as usize];
+        let len = read(addr, 0, &mut buf);
+        buf.truncate(len);
+        Some(buf)
+    }
+}
+
+/// Fill `buf` with random bytes, returning whether it succeeded. Where the
+/// bytes come from, and whether guests may have them at all, is chosen by
+/// the host with `SandboxBuilder::with_entropy_policy`.
+pub fn fill_random(buf: &mut [u8]) -> bool {
+    // Safety: buf is a valid buffer of buf.len() bytes
+    unsafe { random_get(buf.as_mut_ptr(), buf.len() as u32) == 0 }
+}
+
+#[link(wasm_import_module = "hyperlight")]
+unsafe extern "C" {
+    #[link_name = "log"]
+    fn hl_log(level: i32, ptr: *const u8, len: i32);
+    #[link_name = "log_record"]
+    fn hl_log_record(
+        level: i32,
+        target: *const u8,
+        target_len: i32,
+        message: *const u8,
+        message_len: i32,
+        fields: *const u8,
+        fields_len: i32,
+    );
+}
+
+/// The severity of a log record written with [`log`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(i32)]
+pub enum Level {
+    /// An error
+    Error = 1,
+    /// A warning
+    Warn = 2,
+    /// Useful information
+    Info = 3,
+    /// Lower priority information
+    Debug = 4,
+    /// Very low priority, often extremely verbose, information
+    Trace = 5,
+}
+
+/// Write a log record. Records are buffered in the sandbox and emitted
+/// by the host through the `log` crate after the current guest call
+/// returns, so this is much cheaper than [`print`]. Records more verbose
+/// than the sandbox's guest log level are discarded.
+pub fn log(level: Level, message: &str) {
+    // Safety: message is a valid buffer of message.len() bytes
+    unsafe { hl_log(level as i32, message.as_ptr(), message.len() as i32) }
+}
+
+/// Write a structured log record, as [`log`] does, with the part of the
+/// module it comes from as its `target` and key-value `fields`, for
+/// example `log_record(Level::Info, "cache", "miss", &[("key", "a")])`.
+/// The host emits the target and fields as fields of a `tracing` event.
+/// Keys and values must not contain NUL characters.
+pub fn log_record(level: Level, target: &str, message: &str, fields: &[(&str, &str)]) {
+    let mut encoded = alloc::vec::Vec::new();
+    for (key, value) in fields {
+        encoded.extend_from_slice(key.as_bytes());
+        encoded.push(0);
+        encoded.extend_from_slice(value.as_bytes());
+        encoded.push(0);
+    }
+    // Safety: each pointer is a valid buffer of the given length
+    unsafe {
+        hl_log_record(
+            level as i32,
+            target.as_ptr(),
+            target.len() as i32,
+            message.as_ptr(),
+            message.len() as i32,
+            encoded.as_ptr(),
+            encoded.len() as i32,
+        )
+    }
+}
+
+/// Declare the version of the guest ABI this module targets, by exporting
+/// the `hlwasm_abi_version` function checked by hosts that call
+/// `SandboxBuilder::with_required_guest_abi`.
+///
+/// ```ignore
+/// hyperlight_wasm_guest_sdk::abi_version!(1);
+/// ```
+#[macro_export]
+macro_rules! abi_version {
+    ($version:expr) => {
+        #[unsafe(no_mangle)]
+        pub extern "C" fn hlwasm_abi_version() -> u32 {
+            $version
+        }
+    };
+}
+
+/// Format the arguments and write them to the host's standard output
+/// with [`print`].
+#[macro_export]
+macro_rules! hlprint {
+    ($($arg:tt)*) => {{
+        $crate::print(&$crate::__private::format!($($arg)*))
+    }}
+}
+
+/// Format the arguments and write them to the host's standard error
+/// with [`eprint`].
+#[macro_export]
+macro_rules! hleprint {
+    ($($arg:tt)*) => {{
+        $crate::eprint(&$crate::__private::format!($($arg)*))
+    }}
+}
@@ -0,0 +1,259 @@
+/*
+Copyright 2025 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Conversions between Rust types and the values hyperlight-wasm passes
+//! across the Wasm boundary. These mirror `marshal.rs` in
+//! hyperlight-wasm-runtime.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ffi::{CStr, c_char};
+
+#[cfg(all(target_arch = "wasm32", feature = "allocator"))]
+use crate::allocator::{free, malloc};
+
+#[cfg(not(all(target_arch = "wasm32", feature = "allocator")))]
+unsafe extern "C" {
+    fn malloc(size: usize) -> *mut u8;
+    fn free(ptr: *mut u8);
+}
+
+fn alloc_bytes(bytes: &[u8], prefix: &[u8], suffix: &[u8]) -> *mut u8 {
+    let len = prefix.len() + bytes.len() + suffix.len();
+    // Safety: malloc either fails, which we check, or returns len bytes
+    unsafe {
+        let ptr = malloc(len);
+        assert!(!ptr.is_null(), "malloc failed");
+        ptr.copy_from_nonoverlapping(prefix.as_ptr(), prefix.len());
+        ptr.add(prefix.len())
+            .copy_from_nonoverlapping(bytes.as_ptr(), bytes.len());
+        ptr.add(prefix.len() + bytes.len())
+            .copy_from_nonoverlapping(suffix.as_ptr(), suffix.len());
+        ptr
+    }
+}
+
+/// Take ownership of a NUL-terminated string allocated with `malloc`.
+unsafe fn take_cstr(ptr: *mut c_char) -> String {
+    // Safety: the caller guarantees ptr is a malloc'd NUL-terminated string
+    unsafe {
+        let s = String::from_utf8_lossy(CStr::from_ptr(ptr).to_bytes()).into_owned();
+        free(ptr as *mut u8);
+        s
+    }
+}
+
+/// Take ownership of a buffer of `len` bytes allocated with `malloc`.
+/// The buffer is overwritten before it is freed, since it may hold a
+/// decrypted payload.
+///
+/// # Safety
+/// `ptr` must point to `len` bytes allocated with `malloc`, which are
+/// not used again after this call.
+pub unsafe fn take_bytes(ptr: *mut u8, len: i32) -> Vec<u8> {
+    // Safety: guaranteed by the caller
+    unsafe {
+        let bytes = core::slice::from_raw_parts(ptr, len as usize).to_vec();
+        ptr.write_bytes(0, len as usize);
+        free(ptr);
+        bytes
+    }
+}
+
+/// A type that can be a parameter of a guest function exported with
+/// [`hyperlight_export`](crate::hyperlight_export).
+///
+/// `Vec<u8>` parameters are also supported, but are handled by the
+/// macro itself since they must be followed by an `i32` length
+/// parameter.
+pub trait FromGuestParam: Sized {
+    /// The type of the value passed by the host
+    type Abi;
+    /// Convert the value passed by the host, taking ownership of any
+    /// memory it points to.
+    ///
+    /// # Safety
+    /// `abi` must have been passed by hyperlight-wasm for a parameter of
+    /// this type.
+    unsafe fn from_param(abi: Self::Abi) -> Self;
+}
+
+/// A type that can be returned from a guest function exported with
+/// [`hyperlight_export`](crate::hyperlight_export).
+pub trait IntoGuestReturn {
+    /// The type of the value returned to the host
+    type Abi;
+    /// Convert into the value returned to the host. Any memory allocated
+    /// is freed by the host on its next call into the module.
+    fn into_return(self) -> Self::Abi;
+}
+
+/// A type that can be a parameter of a host function declared with
+/// [`host_functions!`](crate::host_functions).
+pub trait IntoHostParam {
+    /// The value kept alive for the duration of the host call
+    type Holder;
+    /// The type of the value passed to the host
+    type Abi;
+    /// Prepare the value to be passed to the host.
+    fn into_holder(self) -> Self::Holder;
+    /// Get the value passed to the host. The guest retains ownership of
+    /// any memory it points to.
+    fn abi(holder: &Self::Holder) -> Self::Abi;
+}
+
+/// A type that can be returned from a host function declared with
+/// [`host_functions!`](crate::host_functions).
+pub trait FromHostReturn: Sized {
+    /// The type of the value returned by the host
+    type Abi;
+    /// Convert the value returned by the host, taking ownership of any
+    /// memory it points to.
+    ///
+    /// # Safety
+    /// `abi` must have been returned by hyperlight-wasm for a host
+    /// function returning this type.
+    unsafe fn from_return(abi: Self::Abi) -> Self;
+}
+
+macro_rules! impl_scalar {
+    ($($ty:ty),*) => { $(
+        impl FromGuestParam for $ty {
+            type Abi = $ty;
+            unsafe fn from_param(abi: $ty) -> Self {
+                abi
+            }
+        }
+        impl IntoGuestReturn for $ty {
+            type Abi = $ty;
+            fn into_return(self) -> $ty {
+                self
+            }
+        }
+        impl IntoHostParam for $ty {
+            type Holder = $ty;
+            type Abi = $ty;
+            fn into_holder(self) -> $ty {
+                self
+            }
+            fn abi(holder: &$ty) -> $ty {
+                *holder
+            }
+        }
+        impl FromHostReturn for $ty {
+            type Abi = $ty;
+            unsafe fn from_return(abi: $ty) -> Self {
+                abi
+            }
+        }
+    )* };
+}
+impl_scalar!(i32, u32, i64, u64, f32, f64);
+
+impl FromGuestParam for bool {
+    type Abi = i32;
+    unsafe fn from_param(abi: i32) -> Self {
+        abi != 0
+    }
+}
+
+impl FromHostReturn for bool {
+    type Abi = i32;
+    unsafe fn from_return(abi: i32) -> Self {
+        abi != 0
+    }
+}
+
+impl IntoGuestReturn for () {
+    type Abi = ();
+    fn into_return(self) {}
+}
+
+impl FromHostReturn for () {
+    type Abi = ();
+    unsafe fn from_return(_: ()) {}
+}
+
+impl FromGuestParam for String {
+    type Abi = *mut c_char;
+    unsafe fn from_param(abi: *mut c_char) -> Self {
+        // Safety: the host allocates string parameters with malloc and
+        // hands ownership to the guest
+        unsafe { take_cstr(abi) }
+    }
+}
+
+impl IntoGuestReturn for String {
+    type Abi = *mut c_char;
+    fn into_return(self) -> *mut c_char {
+        alloc_bytes(self.as_bytes(), &[], &[0]) as *mut c_char
+    }
+}
+
+impl IntoGuestReturn for Vec<u8> {
+    type Abi = *mut u8;
+    fn into_return(self) -> *mut u8 {
+        // Buffers are returned with their length in a 4 byte prefix
+        alloc_bytes(&self, &(self.len() as i32).to_le_bytes(), &[])
+    }
+}
+
+impl FromHostReturn for String {
+    type Abi = *mut c_char;
+    unsafe fn from_return(abi: *mut c_char) -> Self {
+        // Safety: the host allocates returned strings with malloc and
+        // hands ownership to the guest
+        unsafe { take_cstr(abi) }
+    }
+}
+
+impl FromHostReturn for Vec<u8> {
+    type Abi = i64;
+    unsafe fn from_return(abi: i64) -> Self {
+        // Returned buffers are packed with the pointer in the lower half
+        // and the length in the upper half
+        let ptr = abi as u32 as usize as *mut u8;
+        let len = (abi as u64 >> 32) as i32;
+        // Safety: the host allocates returned buffers with malloc and
+        // hands ownership to the guest
+        unsafe { take_bytes(ptr, len) }
+    }
+}
+
+impl IntoHostParam for &str {
+    type Holder = Vec<u8>;
+    type Abi = *const c_char;
+    fn into_holder(self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.len() + 1);
+        bytes.extend_from_slice(self.as_bytes());
+        bytes.push(0);
+        bytes
+    }
+    fn abi(holder: &Vec<u8>) -> *const c_char {
+        holder.as_ptr() as *const c_char
+    }
+}
+
+impl<'a> IntoHostParam for &'a [u8] {
+    type Holder = &'a [u8];
+    type Abi = *const u8;
+    fn into_holder(self) -> &'a [u8] {
+        self
+    }
+    fn abi(holder: &&'a [u8]) -> *const u8 {
+        holder.as_ptr()
+    }
+}
@@ -0,0 +1,283 @@
+/*
+Copyright 2025 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Procedural macros for hyperlight-wasm-guest-sdk. See that crate for
+//! documentation.
+
+extern crate proc_macro;
+
+use proc_macro2::{Span, TokenStream};
+use quote::{format_ident, quote};
+use syn::parse::{Parse, ParseStream};
+use syn::{FnArg, ForeignItemFn, Ident, ItemFn, LitStr, Pat, ReturnType, Type, parse_macro_input};
+
+fn is_byte_vec(ty: &Type) -> bool {
+    matches!(ty, Type::Path(p) if p.path.segments.last().is_some_and(|s| s.ident == "Vec"))
+}
+
+fn return_type(output: &ReturnType) -> TokenStream {
+    match output {
+        ReturnType::Default => quote! { () },
+        ReturnType::Type(_, ty) => quote! { #ty },
+    }
+}
+
+/// Export a function so that it can be called from the host with
+/// `LoadedWasmSandbox::call_guest_function`.
+///
+/// Parameters may be any type implementing `FromGuestParam`, or
+/// `Vec<u8>` immediately followed by an `i32` length parameter. The
+/// return type may be any type implementing `IntoGuestReturn`.
+///
+/// The function is exported under its own name, unless a different one
+/// is given with `#[hyperlight_export(name = "...")]`.
+///
+/// With `#[hyperlight_export(json)]`, which needs the `json` feature of
+/// hyperlight-wasm-guest-sdk, the parameters may instead be any types
+/// implementing `serde::Deserialize` and the return type any type
+/// implementing `serde::Serialize`. They are passed as JSON in a single
+/// buffer, and the function is called from the host with
+/// `LoadedWasmSandbox::call_guest_function_json`.
+#[proc_macro_attribute]
+pub fn hyperlight_export(
+    attr: proc_macro::TokenStream,
+    item: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    let mut export_name = None;
+    let mut json = false;
+    let attr_parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("name") {
+            export_name = Some(meta.value()?.parse::<LitStr>()?);
+            Ok(())
+        } else if meta.path.is_ident("json") {
+            json = true;
+            Ok(())
+        } else {
+            Err(meta.error("unsupported hyperlight_export attribute"))
+        }
+    });
+    parse_macro_input!(attr with attr_parser);
+    let func = parse_macro_input!(item as ItemFn);
+    let expanded = if json {
+        expand_json_export(export_name, func)
+    } else {
+        expand_export(export_name, func)
+    };
+    expanded
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn export_inputs(func: &ItemFn) -> syn::Result<Vec<&Type>> {
+    func.sig
+        .inputs
+        .iter()
+        .map(|arg| match arg {
+            FnArg::Typed(pt) => Ok(&*pt.ty),
+            FnArg::Receiver(r) => Err(syn::Error::new_spanned(
+                r,
+                "hyperlight_export functions cannot take self",
+            )),
+        })
+        .collect()
+}
+
+fn expand_export(export_name: Option<LitStr>, func: ItemFn) -> syn::Result<TokenStream> {
+    let sdk = quote! { ::hyperlight_wasm_guest_sdk };
+    let ident = &func.sig.ident;
+    let export_name = export_name.unwrap_or_else(|| LitStr::new(&ident.to_string(), ident.span()));
+    let inputs = export_inputs(&func)?;
+
+    let mut abi_params = Vec::new();
+    let mut args = Vec::new();
+    let mut iter = inputs.iter().enumerate().peekable();
+    while let Some((i, ty)) = iter.next() {
+        let p = format_ident!("p{}", i);
+        if is_byte_vec(ty) {
+            // Buffers are passed as a pointer followed by their length,
+            // which is also passed through to the function
+            let Some((j, len_ty)) = iter.next() else {
+                return Err(syn::Error::new_spanned(
+                    ty,
+                    "Vec<u8> parameters must be followed by an i32 length parameter",
+                ));
+            };
+            let len = format_ident!("p{}", j);
+            abi_params.push(quote! { #p: *mut u8 });
+            abi_params.push(quote! { #len: #len_ty });
+            args.push(quote! { unsafe { #sdk::__private::take_bytes(#p, #len) } });
+            args.push(quote! { #len });
+        } else {
+            abi_params.push(quote! { #p: <#ty as #sdk::FromGuestParam>::Abi });
+            args.push(quote! { unsafe { <#ty as #sdk::FromGuestParam>::from_param(#p) } });
+        }
+    }
+
+    let ret = return_type(&func.sig.output);
+    let wrapper = Ident::new(&format!("__hyperlight_export_{}", ident), Span::call_site());
+    Ok(quote! {
+        #func
+
+        // The pointers are allocated by the host with the guest's malloc
+        #[doc(hidden)]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        #[unsafe(export_name = #export_name)]
+        pub extern "C" fn #wrapper(#(#abi_params),*) -> <#ret as #sdk::IntoGuestReturn>::Abi {
+            #sdk::IntoGuestReturn::into_return(#ident(#(#args),*))
+        }
+    })
+}
+
+fn expand_json_export(export_name: Option<LitStr>, func: ItemFn) -> syn::Result<TokenStream> {
+    let sdk = quote! { ::hyperlight_wasm_guest_sdk };
+    let ident = &func.sig.ident;
+    let export_name = export_name.unwrap_or_else(|| LitStr::new(&ident.to_string(), ident.span()));
+    let inputs = export_inputs(&func)?;
+    let args = (0..inputs.len())
+        .map(|i| format_ident!("p{}", i))
+        .collect::<Vec<_>>();
+
+    let ret = return_type(&func.sig.output);
+    let wrapper = Ident::new(&format!("__hyperlight_export_{}", ident), Span::call_site());
+    // The parameters arrive as a JSON array, which is decoded as a tuple
+    Ok(quote! {
+        #func
+
+        // The pointers are allocated by the host with the guest's malloc
+        #[doc(hidden)]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)]
+        #[unsafe(export_name = #export_name)]
+        pub extern "C" fn #wrapper(ptr: *mut u8, len: i32) -> *mut u8 {
+            let params = unsafe { #sdk::__private::take_bytes(ptr, len) };
+            let result = #sdk::__private::json_call(
+                &params,
+                |(#(#args,)*): (#(#inputs,)*)| -> #ret { #ident(#(#args),*) },
+            );
+            #sdk::IntoGuestReturn::into_return(result)
+        }
+    })
+}
+
+struct HostFunctions(Vec<ForeignItemFn>);
+
+impl Parse for HostFunctions {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut fns = Vec::new();
+        while !input.is_empty() {
+            fns.push(input.parse()?);
+        }
+        Ok(HostFunctions(fns))
+    }
+}
+
+/// Declare functions registered on the host with
+/// `ProtoWasmSandbox::register`, generating a safe Rust function for
+/// each of them.
+///
+/// Each declaration looks like a function without a body, and is
+/// imported from the host under its own name, or the one given with
+/// `#[link_name = "..."]`:
+///
+/// ```ignore
+/// host_functions! {
+///     fn HostLog(msg: &str) -> i32;
+///     pub fn HostSum(buf: &[u8], len: i32) -> i64;
+/// }
+/// ```
+///
+/// Parameters may be any type implementing `IntoHostParam`, and the
+/// return type any type implementing `FromHostReturn`. As on the host,
+/// `&[u8]` parameters must be followed by an `i32` length parameter.
+#[proc_macro]
+pub fn host_functions(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let HostFunctions(fns) = parse_macro_input!(input as HostFunctions);
+    fns.into_iter()
+        .map(expand_host_function)
+        .collect::<syn::Result<TokenStream>>()
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand_host_function(func: ForeignItemFn) -> syn::Result<TokenStream> {
+    let sdk = quote! { ::hyperlight_wasm_guest_sdk };
+    let ForeignItemFn {
+        mut attrs,
+        vis,
+        sig,
+        ..
+    } = func;
+    let mut name = LitStr::new(&sig.ident.to_string(), sig.ident.span());
+    // Allow importing a host function under a different Rust name
+    if let Some(i) = attrs.iter().position(|a| a.path().is_ident("link_name")) {
+        let attr = attrs.remove(i);
+        if let syn::Meta::NameValue(syn::MetaNameValue {
+            value:
+                syn::Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Str(s),
+                    ..
+                }),
+            ..
+        }) = attr.meta
+        {
+            name = s;
+        } else {
+            return Err(syn::Error::new_spanned(
+                attr,
+                "expected #[link_name = \"...\"]",
+            ));
+        }
+    }
+
+    let mut abi_params = Vec::new();
+    let mut holders = Vec::new();
+    let mut abi_args = Vec::new();
+    for (i, arg) in sig.inputs.iter().enumerate() {
+        let FnArg::Typed(pt) = arg else {
+            return Err(syn::Error::new_spanned(
+                arg,
+                "host functions cannot take self",
+            ));
+        };
+        let Pat::Ident(pat) = &*pt.pat else {
+            return Err(syn::Error::new_spanned(
+                &pt.pat,
+                "host function parameters must be identifiers",
+            ));
+        };
+        let (arg, ty) = (&pat.ident, &pt.ty);
+        let holder = format_ident!("h{}", i);
+        abi_params.push(quote! { #arg: <#ty as #sdk::IntoHostParam>::Abi });
+        holders.push(quote! { let #holder = <#ty as #sdk::IntoHostParam>::into_holder(#arg); });
+        abi_args.push(quote! { <#ty as #sdk::IntoHostParam>::abi(&#holder) });
+    }
+
+    let ret = return_type(&sig.output);
+    Ok(quote! {
+        #(#attrs)*
+        #vis #sig {
+            #[link(wasm_import_module = "env")]
+            unsafe extern "C" {
+                #[link_name = #name]
+                fn import(#(#abi_params),*) -> <#ret as #sdk::FromHostReturn>::Abi;
+            }
+            #(#holders)*
+            // Safety: the import matches the signature hyperlight-wasm
+            // gives host functions, and the holders keep any memory
+            // passed to the host alive for the duration of the call
+            unsafe { <#ret as #sdk::FromHostReturn>::from_return(import(#(#abi_args),*)) }
+        }
+    })
+}
@@ -17,27 +17,57 @@ limitations under the License.
 // general todos:
 // - split out the general guest codegen (to do an `impl Imports for
 //   Host {}`) vs the wasmtime-specific codegen
-// - once that is done it will be easy to support resources exported
-//    from the guest properly. (the current issue is that since the
-//    host-interaction code is fused with the wasmtime-interface code,
-//    it is impossible to come up with an <I: Imports> to instantiate
-//    the `Resources` struct with.)
+// - resources exported from the guest are only supported as the
+//   parameters and results of exported functions themselves, not
+//   inside other types, since they are marshalled here rather than by
+//   the shared hl marshalling code. Once the codegen is split, the
+//   `Resources` struct could keep track of them instead.
 
 use hyperlight_component_util::emit::{
     FnName, ResolvedBoundVar, State, WitName, kebab_to_fn, kebab_to_namespace, kebab_to_type,
     kebab_to_var, split_wit_name,
 };
 use hyperlight_component_util::etypes::{
-    self, Component, Defined, ExternDecl, ExternDesc, Handleable, Instance, Tyvar,
+    Component, Defined, ExternDecl, ExternDesc, Handleable, Instance, Tyvar, Value,
 };
 use hyperlight_component_util::hl::{
     emit_fn_hl_name, emit_hl_marshal_param, emit_hl_marshal_result, emit_hl_unmarshal_param,
-    emit_hl_unmarshal_result,
+    emit_hl_unmarshal_result, resolve_handleable_to_resource,
 };
 use hyperlight_component_util::{resource, rtypes};
 use proc_macro2::TokenStream;
 use quote::{format_ident, quote};
 
+// A handle to a resource exported by the component
+enum ExportedHandle {
+    Own,
+    Borrow,
+}
+
+// If `vt` is a handle to a resource exported by the component, which
+// wasmtime represents as a `ResourceAny` and the host as an index into
+// the runtime's table of resources, whether it is owned or borrowed
+fn exported_handle(s: &mut State, vt: &Value) -> Option<ExportedHandle> {
+    let (ht, handle) = match vt {
+        Value::Own(ht) => (ht, ExportedHandle::Own),
+        Value::Borrow(ht) => (ht, ExportedHandle::Borrow),
+        _ => return None,
+    };
+    let rtidx = resolve_handleable_to_resource(s, ht);
+    (!s.bound_vars[rtidx as usize].origin.is_imported()).then_some(handle)
+}
+
+// The name of the function registered with Hyperlight for the export
+// with the kebab name `kebab`, when that cannot be derived from the
+// export's own name, such as for `[method]parser.parse`
+fn export_fn_ident(kebab: &str) -> proc_macro2::Ident {
+    let words = kebab
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>();
+    format_ident!("export_{}", words.join("_"))
+}
+
 // Emit code to register this particular extern definition with the
 // wasmtime linker, calling through Hyperlight.
 //
@@ -83,11 +113,12 @@ fn emit_import_extern_decl<'b>(
             };
             quote! {
                 #li.func_wrap::<_, (#(#pts,)*), #rt>(#edkn, |_, (#(#pds,)*)| {
+                    crate::call_tracker::record_host_call();
                     let #ret = call_host_function::<Vec<u8>>(
                         #fname,
                         ::core::option::Option::Some(vec![#(#pus,)*]),
                         ::hyperlight_common::flatbuffer_wrappers::function_types::ReturnType::VecBytes,
-                    ).unwrap();
+                    ).map_err(|e| crate::host_error::failed(#fname, e))?;
                     ::core::result::Result::Ok(#ur)
                 }).unwrap();
             }
@@ -141,40 +172,62 @@ fn emit_export_extern_decl<'b>(
             let fname = emit_fn_hl_name(s, ed.kebab_name);
             let n = match kebab_to_fn(ed.kebab_name) {
                 FnName::Plain(n) => n,
-                FnName::Associated(_, _) => {
-                    panic!("resources exported from wasm not yet supported")
-                }
+                FnName::Associated(_, _) => export_fn_ident(ed.kebab_name),
             };
             let nlit = ed.kebab_name;
             let pts = ft.params.iter().map(|_| quote! { ::hyperlight_common::flatbuffer_wrappers::function_types::ParameterType::VecBytes }).collect::<Vec<_>>();
             let pwts = ft
                 .params
                 .iter()
-                .map(|p| rtypes::emit_value(s, &p.ty))
+                .map(|p| match exported_handle(s, &p.ty) {
+                    Some(_) => quote! { ::wasmtime::component::ResourceAny },
+                    None => rtypes::emit_value(s, &p.ty),
+                })
                 .collect::<Vec<_>>();
             let (pds, pus) = ft.params.iter().enumerate()
                 .map(|(i, p)| {
                     let id = kebab_to_var(p.name.name);
                     let pd = quote! { let ::hyperlight_common::flatbuffer_wrappers::function_types::ParameterValue::VecBytes(#id) = &fc.parameters.as_ref().unwrap()[#i] else { panic!("invariant violation: host passed non-VecBytes core hyperlight argument"); }; };
-                    let pu = emit_hl_unmarshal_param(s, id, &p.ty);
+                    let pu = match exported_handle(s, &p.ty) {
+                        Some(ExportedHandle::Own) => quote! { crate::guest_resources::take(#id)? },
+                        Some(ExportedHandle::Borrow) => quote! { crate::guest_resources::get(#id)? },
+                        None => emit_hl_unmarshal_param(s, id, &p.ty),
+                    };
                     (pd, pu)
                 })
                 .unzip::<_, _, Vec<_>, Vec<_>>();
             let get_instance = path.iter().map(|export| quote! {
                 let instance_idx = Some(instance.get_export_index(&mut *store, instance_idx.as_ref(), #export).unwrap());
             }).collect::<Vec<_>>();
-            let (function_call, ret) = emit_wasm_function_call(s, &ft.result, pwts, pus);
-            let marshal_result = emit_hl_marshal_result(s, ret.clone(), &ft.result);
+            let ret = format_ident!("ret");
+            let result_handle = ft.result.as_ref().and_then(|vt| exported_handle(s, vt));
+            let (rwt, marshal_result) = match (&ft.result, result_handle) {
+                (None, _) => (None, emit_hl_marshal_result(s, ret.clone(), &ft.result)),
+                (Some(_), Some(_)) => (
+                    Some(quote! { ::wasmtime::component::ResourceAny }),
+                    quote! { crate::guest_resources::insert(#ret) },
+                ),
+                (Some(_), None) => (
+                    Some(rtypes::emit_func_result(s, &ft.result)),
+                    emit_hl_marshal_result(s, ret.clone(), &ft.result),
+                ),
+            };
+            let function_call = emit_wasm_function_call(ret, rwt, pwts, pus);
             quote! {
                 fn #n(fc: ::hyperlight_common::flatbuffer_wrappers::function_call::FunctionCall) -> ::hyperlight_guest::error::Result<::alloc::vec::Vec<u8>> {
                     #(#pds)*
+                    begin_export_call()?;
                     let mut store = CUR_STORE.lock(); let mut store = store.as_mut().unwrap();
                     let instance = CUR_INSTANCE.lock(); let mut instance = instance.unwrap();
                     let instance_idx = None;
                     #(#get_instance;)*
                     let func_idx = instance.get_export_index(&mut *store, instance_idx.as_ref(), #nlit).unwrap();
+                    crate::call_tracker::begin_call();
                     #function_call
-                    ::core::result::Result::Ok(::hyperlight_common::flatbuffer_wrappers::util::get_flatbuffer_result::<&[u8]>(&#marshal_result))
+                    crate::call_tracker::end_call(None, None);
+                    let ret_bytes: &[u8] = &#marshal_result;
+                    crate::limits::check_return_value_size(ret_bytes.len())?;
+                    ::core::result::Result::Ok(::hyperlight_common::flatbuffer_wrappers::util::get_flatbuffer_result::<&[u8]>(ret_bytes))
                 }
                 ::hyperlight_guest_bin::guest_function::register::register_function(
                     ::hyperlight_guest_bin::guest_function::definition::GuestFunctionDefinition::new(
@@ -186,10 +239,35 @@ fn emit_export_extern_decl<'b>(
                 );
             }
         }
-        ExternDesc::Type(_) => {
-            // no runtime representation is needed for types
-            quote! {}
-        }
+        ExternDesc::Type(t) => match t {
+            // Let the host drop the resources the component gives it
+            Defined::Handleable(Handleable::Var(Tyvar::Bound(b))) => {
+                let ResolvedBoundVar::Resource { .. } = s.resolve_bound_var(*b) else {
+                    return quote! {};
+                };
+                let drop_name = format!("[resource-drop]{}", ed.kebab_name);
+                let fname = emit_fn_hl_name(s, &drop_name);
+                let n = export_fn_ident(&drop_name);
+                quote! {
+                    fn #n(fc: ::hyperlight_common::flatbuffer_wrappers::function_call::FunctionCall) -> ::hyperlight_guest::error::Result<::alloc::vec::Vec<u8>> {
+                        let ::hyperlight_common::flatbuffer_wrappers::function_types::ParameterValue::VecBytes(handle) = &fc.parameters.as_ref().unwrap()[0] else { panic!("invariant violation: host passed non-VecBytes core hyperlight argument"); };
+                        let mut store = CUR_STORE.lock(); let mut store = store.as_mut().unwrap();
+                        crate::guest_resources::drop(&mut *store, handle)?;
+                        ::core::result::Result::Ok(::hyperlight_common::flatbuffer_wrappers::util::get_flatbuffer_result::<&[u8]>(&[]))
+                    }
+                    ::hyperlight_guest_bin::guest_function::register::register_function(
+                        ::hyperlight_guest_bin::guest_function::definition::GuestFunctionDefinition::new(
+                            #fname.to_string(),
+                            ::alloc::vec![::hyperlight_common::flatbuffer_wrappers::function_types::ParameterType::VecBytes],
+                            ::hyperlight_common::flatbuffer_wrappers::function_types::ReturnType::VecBytes,
+                            #n
+                        )
+                    );
+                }
+            }
+            // no runtime representation is needed for other types
+            _ => quote! {},
+        },
         ExternDesc::Instance(it) => {
             let wn = split_wit_name(ed.kebab_name);
             let mut path = path.clone();
@@ -202,23 +280,29 @@ fn emit_export_extern_decl<'b>(
     }
 }
 
+// Emit a call of the wasm function `func_idx`, whose parameters have
+// the wasmtime types `pwts` and the values `pus`, binding its result, of
+// wasmtime type `rwt` if it has one, to `ret`
 fn emit_wasm_function_call(
-    s: &mut State,
-    result: &etypes::Result,
+    ret: proc_macro2::Ident,
+    rwt: Option<TokenStream>,
     pwts: Vec<TokenStream>,
     pus: Vec<TokenStream>,
-) -> (TokenStream, proc_macro2::Ident) {
-    let ret = format_ident!("ret");
-
+) -> TokenStream {
     // if the result is empty we don't want a return result with `get_typed_func`
-    let rwt = match result {
+    match rwt {
         None => {
             quote! {
                 let func = instance
                     .get_typed_func::<(#(#pwts,)*), ()>(&mut *store, func_idx)
                     .map_err(crate::map_wasmtime_error)?;
+                crate::guest_abort::begin_call();
+                crate::host_error::begin_call();
+                crate::wasm_limits::begin_call();
+                crate::epoch_deadline::begin_call(&mut *store);
                 func.call(&mut *store, (#(#pus,)*))
-                    .map_err(crate::map_wasmtime_error)?;
+                    .map_err(crate::epoch_deadline::map_call_error)?;
+                crate::epoch_deadline::end_call();
                 // Explicit post_return is only needed for Wasmtime 36 LTS; Wasmtime 45
                 // calls post-return from TypedFunc::call and makes post_return a no-op.
                 // https://github.com/bytecodealliance/wasmtime/pull/12498
@@ -227,15 +311,19 @@ fn emit_wasm_function_call(
                     .map_err(crate::map_wasmtime_error)?;
             }
         }
-        _ => {
-            let r = rtypes::emit_func_result(s, result);
+        Some(r) => {
             quote! {
                 let func = instance
                     .get_typed_func::<(#(#pwts,)*), ((#r,))>(&mut *store, func_idx)
                     .map_err(crate::map_wasmtime_error)?;
+                crate::guest_abort::begin_call();
+                crate::host_error::begin_call();
+                crate::wasm_limits::begin_call();
+                crate::epoch_deadline::begin_call(&mut *store);
                 let #ret = func.call(&mut *store, (#(#pus,)*))
-                    .map_err(crate::map_wasmtime_error)?
+                    .map_err(crate::epoch_deadline::map_call_error)?
                     .0;
+                crate::epoch_deadline::end_call();
                 // Explicit post_return is only needed for Wasmtime 36 LTS; Wasmtime 45
                 // calls post-return from TypedFunc::call and makes post_return a no-op.
                 // https://github.com/bytecodealliance/wasmtime/pull/12498
@@ -244,9 +332,7 @@ fn emit_wasm_function_call(
                     .map_err(crate::map_wasmtime_error)?;
             }
         }
-    };
-
-    (rwt, ret)
+    }
 }
 
 // Emit code to register each export of the given instance with the
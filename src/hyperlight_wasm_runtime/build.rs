@@ -75,8 +75,11 @@ fn main() {
     #[used]
     #[link_section = ".note_hyperlight_metadata"]
     static WASMTIME_VERSION_NUMBER: [u8; 32] = *b"{}";
+
+    // The version recorded by wasmtime in precompiled artifacts
+    pub(crate) const WASMTIME_VERSION: &str = "{}";
     "#,
-        version_number_string
+        version_number_string, version_number
     );
     fs::write(dest_path, file_contents).unwrap();
 
@@ -0,0 +1,75 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use alloc::format;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use hyperlight_common::flatbuffer_wrappers::guest_error::ErrorCode;
+use hyperlight_guest::error::{HyperlightGuestError, Result};
+
+// Set by init_wasm_runtime from the runtime config. The range of accepted
+// versions is [MIN, MAX), and no version is required if MIN is u64::MAX.
+static MIN_ABI_VERSION: AtomicU64 = AtomicU64::new(u64::MAX);
+static MAX_ABI_VERSION: AtomicU64 = AtomicU64::new(u64::MAX);
+
+pub(crate) fn set_required_abi_version(min: Option<u64>, max: Option<u64>) {
+    MIN_ABI_VERSION.store(min.unwrap_or(u64::MAX), Ordering::Relaxed);
+    MAX_ABI_VERSION.store(max.unwrap_or(u64::MAX), Ordering::Relaxed);
+}
+
+/// Whether the host requires loaded guests to declare an ABI version
+pub(crate) fn is_required() -> bool {
+    MIN_ABI_VERSION.load(Ordering::Relaxed) != u64::MAX
+}
+
+/// Fail unless `version`, the ABI version declared by the guest, is in the
+/// range required by the host. `export` is the name of the export the
+/// version is read from, used in the error messages.
+pub(crate) fn check(version: Option<u32>, export: &str) -> Result<()> {
+    let min = MIN_ABI_VERSION.load(Ordering::Relaxed);
+    let max = MAX_ABI_VERSION.load(Ordering::Relaxed);
+    let Some(version) = version else {
+        return Err(HyperlightGuestError::new(
+            ErrorCode::GuestError,
+            format!(
+                "guest ABI version mismatch: the host requires a guest ABI version but the guest does not export {}",
+                export
+            ),
+        ));
+    };
+    if (version as u64) < min || (version as u64) >= max {
+        let required = if max == u64::MAX {
+            format!("{} or later", min)
+        } else {
+            format!("at least {} and below {}", min, max)
+        };
+        return Err(HyperlightGuestError::new(
+            ErrorCode::GuestError,
+            format!(
+                "guest ABI version mismatch: the guest targets version {} but the host requires {}",
+                version, required
+            ),
+        ));
+    }
+    Ok(())
+}
+
+pub(crate) fn invalid_export(export: &str) -> HyperlightGuestError {
+    HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        format!("{} must take no parameters and return a u32", export),
+    )
+}
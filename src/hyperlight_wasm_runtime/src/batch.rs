@@ -0,0 +1,121 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Batches of calls to module functions, which the guest runs one after
+//! another in a single call to the [`CALL_BATCH_FUNCTION`] guest
+//! function.
+//!
+//! Each call is encoded as its function name, a one byte return type
+//! and its parameters encoded as for [`host_dispatch`], with the name
+//! and the parameters each preceded by their little-endian `u32`
+//! length. The results are encoded in order as for [`host_dispatch`],
+//! each preceded by its little-endian `u32` length.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use hyperlight_common::flatbuffer_wrappers::function_call::{FunctionCall, FunctionCallType};
+use hyperlight_common::flatbuffer_wrappers::function_types::{ReturnType, ReturnValue};
+
+use crate::host_dispatch;
+
+/// The guest function that runs a batch of calls. It takes the calls
+/// encoded with [`calls_to_bytes`] as a `VecBytes` and returns their
+/// results encoded with [`results_to_bytes`] as a `VecBytes`.
+pub const CALL_BATCH_FUNCTION: &str = "CallBatch";
+
+const RETURN_TYPES: [ReturnType; 10] = [
+    ReturnType::Int,
+    ReturnType::UInt,
+    ReturnType::Long,
+    ReturnType::ULong,
+    ReturnType::Float,
+    ReturnType::Double,
+    ReturnType::String,
+    ReturnType::Bool,
+    ReturnType::Void,
+    ReturnType::VecBytes,
+];
+
+fn push_bytes(bytes: &mut Vec<u8>, value: &[u8]) {
+    bytes.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(value);
+}
+
+fn split_bytes(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (len, rest) = bytes.split_first_chunk::<4>()?;
+    let len = u32::from_le_bytes(*len) as usize;
+    if rest.len() < len {
+        return None;
+    }
+    Some(rest.split_at(len))
+}
+
+/// Encode `calls` to be run by the guest
+pub fn calls_to_bytes(calls: &[FunctionCall]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for call in calls {
+        push_bytes(&mut bytes, call.function_name.as_bytes());
+        let return_type = RETURN_TYPES
+            .iter()
+            .position(|t| *t == call.expected_return_type)
+            .unwrap_or_default();
+        bytes.push(return_type as u8);
+        let params = call.parameters.as_deref().unwrap_or_default();
+        push_bytes(&mut bytes, &host_dispatch::params_to_bytes(params));
+    }
+    bytes
+}
+
+/// Decode calls encoded with [`calls_to_bytes`], returning `None` if the
+/// encoding is malformed
+pub fn calls_from_bytes(mut bytes: &[u8]) -> Option<Vec<FunctionCall>> {
+    let mut calls = Vec::new();
+    while !bytes.is_empty() {
+        let (name, rest) = split_bytes(bytes)?;
+        let (&return_type, rest) = rest.split_first()?;
+        let (params, rest) = split_bytes(rest)?;
+        calls.push(FunctionCall::new(
+            String::from_utf8(name.to_vec()).ok()?,
+            Some(host_dispatch::params_from_bytes(params)?),
+            FunctionCallType::Guest,
+            *RETURN_TYPES.get(return_type as usize)?,
+        ));
+        bytes = rest;
+    }
+    Some(calls)
+}
+
+/// Encode the results of a batch of calls
+pub fn results_to_bytes(results: &[ReturnValue]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for result in results {
+        push_bytes(&mut bytes, &host_dispatch::return_value_to_bytes(result));
+    }
+    bytes
+}
+
+/// Decode results encoded with [`results_to_bytes`], returning `None` if
+/// the encoding is malformed
+pub fn results_from_bytes(mut bytes: &[u8]) -> Option<Vec<ReturnValue>> {
+    let mut results = Vec::new();
+    while !bytes.is_empty() {
+        let (result, rest) = split_bytes(bytes)?;
+        results.push(host_dispatch::return_value_from_bytes(result)?);
+        bytes = rest;
+    }
+    Some(results)
+}
@@ -0,0 +1,77 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Statistics about the most recent guest function call, recorded in the
+//! guest and fetched by the host with the `GetCallStats` guest function.
+//!
+//! The statistics are encoded as a little-endian `u64` count of host
+//! function calls, followed by a one byte flag that is 1 if the change
+//! in the size of the module's memory is known and a little-endian `i64`
+//! holding that change in bytes, followed by a one byte flag that is 1 if
+//! the size of the module's memory is known and a little-endian `u64`
+//! holding that size in bytes.
+
+/// Statistics about the most recent guest function call
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CallStats {
+    /// The number of host functions called by the guest
+    pub host_calls: u64,
+    /// The change in the size of the wasm memory in bytes, if known.
+    /// This is not tracked for components.
+    pub memory_delta: Option<i64>,
+    /// The size of the wasm memory in bytes when the call returned, if
+    /// known. This is not tracked for components.
+    pub memory_size: Option<u64>,
+}
+
+impl CallStats {
+    /// Encode the statistics to be passed to the host
+    pub fn to_bytes(&self) -> [u8; 26] {
+        let mut bytes = [0u8; 26];
+        bytes[..8].copy_from_slice(&self.host_calls.to_le_bytes());
+        if let Some(delta) = self.memory_delta {
+            bytes[8] = 1;
+            bytes[9..17].copy_from_slice(&delta.to_le_bytes());
+        }
+        if let Some(size) = self.memory_size {
+            bytes[17] = 1;
+            bytes[18..].copy_from_slice(&size.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Decode statistics encoded with [`to_bytes`](Self::to_bytes),
+    /// returning `None` if the encoding is malformed
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let bytes: &[u8; 26] = bytes.try_into().ok()?;
+        let host_calls = u64::from_le_bytes(bytes[..8].try_into().ok()?);
+        let memory_delta = match bytes[8] {
+            0 => None,
+            1 => Some(i64::from_le_bytes(bytes[9..17].try_into().ok()?)),
+            _ => return None,
+        };
+        let memory_size = match bytes[17] {
+            0 => None,
+            1 => Some(u64::from_le_bytes(bytes[18..].try_into().ok()?)),
+            _ => return None,
+        };
+        Some(Self {
+            host_calls,
+            memory_delta,
+            memory_size,
+        })
+    }
+}
@@ -0,0 +1,74 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use hyperlight_common::flatbuffer_wrappers::function_call::FunctionCall;
+use hyperlight_common::flatbuffer_wrappers::function_types::ReturnType;
+use hyperlight_common::flatbuffer_wrappers::util::get_flatbuffer_result;
+use hyperlight_guest::error::Result;
+use hyperlight_guest_bin::guest_function::definition::GuestFunctionDefinition;
+use hyperlight_guest_bin::guest_function::register::register_function;
+use spin::Mutex;
+use tracing::instrument;
+
+use crate::call_stats::CallStats;
+
+// Host functions called since the current guest call started
+static HOST_CALLS: AtomicU64 = AtomicU64::new(0);
+static LAST_CALL: Mutex<CallStats> = Mutex::new(CallStats {
+    host_calls: 0,
+    memory_delta: None,
+    memory_size: None,
+});
+
+/// Start counting host function calls for a new guest call
+pub(crate) fn begin_call() {
+    HOST_CALLS.store(0, Ordering::Relaxed);
+}
+
+/// Record that the guest called a host function
+pub(crate) fn record_host_call() {
+    HOST_CALLS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Record the statistics of the guest call started by [`begin_call`]
+pub(crate) fn end_call(memory_delta: Option<i64>, memory_size: Option<u64>) {
+    *LAST_CALL.lock() = CallStats {
+        host_calls: HOST_CALLS.load(Ordering::Relaxed),
+        memory_delta,
+        memory_size,
+    };
+}
+
+#[instrument(skip_all, level = "Info")]
+/// Return the statistics of the last guest call, see [`crate::call_stats`]
+fn get_call_stats(_function_call: FunctionCall) -> Result<Vec<u8>> {
+    let stats = *LAST_CALL.lock();
+    Ok(get_flatbuffer_result::<&[u8]>(&stats.to_bytes()))
+}
+
+pub(crate) fn register_functions() {
+    register_function(GuestFunctionDefinition::new(
+        "GetCallStats".to_string(),
+        vec![],
+        ReturnType::VecBytes,
+        get_call_stats,
+    ));
+}
@@ -0,0 +1,73 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The arguments and environment variables the host set for guests,
+//! read from the host when the runtime is initialized. Modules read them
+//! with the wasip1 `args_get` and `environ_get` functions, and components
+//! with `wasi:cli/environment`.
+//!
+//! Both are kept as the wasip1 functions return them: each string
+//! followed by a NUL byte, with environment variables as `KEY=VALUE`.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use hyperlight_common::flatbuffer_wrappers::function_types::ReturnType;
+use hyperlight_guest::error::Result;
+use hyperlight_guest_bin::host_comm::call_host_function;
+use spin::Mutex;
+
+use crate::call_tracker;
+use crate::runtime_config::{RuntimeConfig, HOST_ARGS_FUNCTION, HOST_ENVIRONMENT_FUNCTION};
+
+static ARGS: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+static ENVIRONMENT: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+
+pub(crate) fn configure(runtime_config: &RuntimeConfig) -> Result<()> {
+    let (args, environment) = if runtime_config.cli_environment {
+        call_tracker::record_host_call();
+        let args = call_host_function::<Vec<u8>>(HOST_ARGS_FUNCTION, None, ReturnType::VecBytes)?;
+        call_tracker::record_host_call();
+        let environment =
+            call_host_function::<Vec<u8>>(HOST_ENVIRONMENT_FUNCTION, None, ReturnType::VecBytes)?;
+        (args, environment)
+    } else {
+        (Vec::new(), Vec::new())
+    };
+    *ARGS.lock() = args;
+    *ENVIRONMENT.lock() = environment;
+    Ok(())
+}
+
+/// The arguments, each followed by a NUL byte
+pub(crate) fn args() -> Vec<u8> {
+    ARGS.lock().clone()
+}
+
+/// The environment variables as `KEY=VALUE`, each followed by a NUL byte
+pub(crate) fn environment() -> Vec<u8> {
+    ENVIRONMENT.lock().clone()
+}
+
+/// The strings of `bytes`, each of which is followed by a NUL byte
+pub(crate) fn strings(bytes: &[u8]) -> Vec<String> {
+    bytes
+        .strip_suffix(&[0])
+        .into_iter()
+        .flat_map(|bytes| bytes.split(|b| *b == 0))
+        .map(|s| String::from_utf8_lossy(s).into_owned())
+        .collect()
+}
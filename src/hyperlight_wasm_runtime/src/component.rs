@@ -14,54 +14,406 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-use alloc::string::ToString;
+use alloc::format;
+use alloc::string::{String, ToString};
 use alloc::vec;
 use alloc::vec::Vec;
 use core::result::Result::*;
+use core::sync::atomic::{AtomicBool, Ordering};
 
 use hyperlight_common::flatbuffer_wrappers::function_call::FunctionCall;
 use hyperlight_common::flatbuffer_wrappers::function_types::{
     ParameterType, ParameterValue, ReturnType,
 };
 use hyperlight_common::flatbuffer_wrappers::guest_error::ErrorCode;
+use hyperlight_common::flatbuffer_wrappers::host_function_details::HostFunctionDetails;
 use hyperlight_common::flatbuffer_wrappers::util::get_flatbuffer_result;
 use hyperlight_guest::error::{HyperlightGuestError, Result};
 use hyperlight_guest_bin::guest_function::definition::GuestFunctionDefinition;
 use hyperlight_guest_bin::guest_function::register::register_function;
 use hyperlight_guest_bin::host_comm::call_host_function;
 use spin::Mutex;
-use tracing::instrument;
-use wasmtime::component::{Component, Instance, Linker};
-use wasmtime::{Config, Engine, Store};
+use tracing::{info_span, instrument};
+use wasmtime::component::types::{ComponentFunc, ComponentItem};
+use wasmtime::component::{Component, Instance, InstancePre, Linker, Type};
+use wasmtime::{Engine, Store};
 
-use crate::{map_wasmtime_error, platform};
+use crate::guest_functions::{self, GuestFunction};
+use crate::memory_stats::{self, GuestMemoryStats};
+use crate::{
+    abi_version, call_tracker, cli_environment, engine, epoch_deadline, guest_resources,
+    introspection, limits, log_buffer, map_wasmtime_error, output_capture, payload_key, platform,
+    random, wasip2, wasm_limits,
+};
 
 static CUR_ENGINE: Mutex<Option<Engine>> = Mutex::new(None);
 static CUR_LINKER: Mutex<Option<Linker<()>>> = Mutex::new(None);
 static CUR_STORE: Mutex<Option<Store<()>>> = Mutex::new(None);
 static CUR_INSTANCE: Mutex<Option<Instance>> = Mutex::new(None);
+static CUR_COMPONENT: Mutex<Option<Component>> = Mutex::new(None);
+// The loaded component with its imports resolved, from which a fresh
+// instance is made before each call if FRESH_INSTANCE_PER_CALL is set
+static CUR_INSTANCE_PRE: Mutex<Option<InstancePre<()>>> = Mutex::new(None);
+static FRESH_INSTANCE_PER_CALL: AtomicBool = AtomicBool::new(false);
 
 hyperlight_wasm_macro::wasm_guest_bindgen!();
 
-// dummy for compatibility with the module loading approach
+// Host functions are fixed by the component's world, so only the
+// runtime config is used here
 #[instrument(skip_all, level = "Info")]
-fn init_wasm_runtime(_function_call: FunctionCall) -> Result<Vec<u8>> {
+fn init_wasm_runtime(function_call: FunctionCall) -> Result<Vec<u8>> {
+    let params = function_call.parameters.as_deref().unwrap_or_default();
+    let runtime_config = engine::parse_runtime_config(params.get(1))?;
+    let engine = engine::new_engine(&runtime_config)?;
+    log_buffer::set_max_level(runtime_config.guest_log_level.unwrap_or(0));
+    limits::set_max_return_value_size(runtime_config.max_return_value_size);
+    limits::set_sandbox_limits(&runtime_config);
+    random::configure(&runtime_config);
+    output_capture::configure(&runtime_config);
+    cli_environment::configure(&runtime_config)?;
+    wasip2::configure(&runtime_config);
+    epoch_deadline::configure(&runtime_config);
+    wasm_limits::configure(&runtime_config);
+    FRESH_INSTANCE_PER_CALL.store(runtime_config.fresh_instance_per_call, Ordering::Relaxed);
+    abi_version::set_required_abi_version(
+        runtime_config.min_guest_abi_version,
+        runtime_config.max_guest_abi_version,
+    );
+    let linker = Linker::new(&engine);
+    *CUR_ENGINE.lock() = Some(engine);
+    *CUR_LINKER.lock() = Some(linker);
+
+    hyperlight_guest_wasm_init();
+    limits::register_component_handlers(CUR_LINKER.lock().as_mut().unwrap())?;
+    introspection::register_component_handlers(CUR_LINKER.lock().as_mut().unwrap())?;
+    log_buffer::register_component_handlers(CUR_LINKER.lock().as_mut().unwrap())?;
+
     Ok(get_flatbuffer_result::<i32>(0))
 }
 
 #[instrument(skip_all, level = "Info")]
 fn load_component_common(engine: &Engine, component: Component) -> Result<()> {
+    let instance_pre = {
+        let mut linker = CUR_LINKER.lock();
+        let linker = linker.as_mut().unwrap();
+        wasip2::link_imports(linker, &component)?;
+        linker
+            .instantiate_pre(&component)
+            .map_err(map_wasmtime_error)?
+    };
+    let mut store = new_store(engine);
+    let instance = info_span!("instantiate")
+        .in_scope(|| instance_pre.instantiate(&mut store))
+        .map_err(map_wasmtime_error)?;
+    check_abi_version(&mut store, &instance)?;
+    guest_resources::clear();
+    *CUR_STORE.lock() = Some(store);
+    *CUR_INSTANCE.lock() = Some(instance);
+    *CUR_COMPONENT.lock() = Some(component);
+    *CUR_INSTANCE_PRE.lock() = Some(instance_pre);
+    Ok(())
+}
+
+fn new_store(engine: &Engine) -> Store<()> {
     let mut store = Store::new(engine, ());
-    let instance = (*CUR_LINKER.lock())
-        .as_ref()
-        .unwrap()
-        .instantiate(&mut store, &component)
+    epoch_deadline::configure_store(&mut store);
+    wasm_limits::configure_store(&mut store);
+    store
+}
+
+/// Replace the loaded component's instance, and the store holding its
+/// state, with a fresh one if the runtime config asks for one per call.
+/// Called by the bindings generated by wasm_guest_bindgen before each
+/// export is called. Resources the previous instance gave the host are
+/// dropped with it.
+fn begin_export_call() -> Result<()> {
+    if !FRESH_INSTANCE_PER_CALL.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+    let instance_pre = CUR_INSTANCE_PRE.lock();
+    let instance_pre = instance_pre.as_ref().ok_or(HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        "No wasm component loaded".to_string(),
+    ))?;
+    let mut store = new_store(instance_pre.engine());
+    let instance = instance_pre
+        .instantiate(&mut store)
         .map_err(map_wasmtime_error)?;
+    guest_resources::clear();
     *CUR_STORE.lock() = Some(store);
     *CUR_INSTANCE.lock() = Some(instance);
     Ok(())
 }
 
+/// The export guests can provide to declare the version of the guest ABI
+/// they target. `hlwasm_abi_version`, used by modules, is not a valid
+/// component export name.
+const ABI_VERSION_EXPORT: &str = "hlwasm-abi-version";
+
+/// Fail if the host requires a guest ABI version that the component does
+/// not declare or does not match
+fn check_abi_version(store: &mut Store<()>, instance: &Instance) -> Result<()> {
+    if !abi_version::is_required() {
+        return Ok(());
+    }
+    let version = match instance.get_func(&mut *store, ABI_VERSION_EXPORT) {
+        Some(func) => {
+            let func = func
+                .typed::<(), (u32,)>(&*store)
+                .map_err(|_| abi_version::invalid_export(ABI_VERSION_EXPORT))?;
+            let (version,) = func.call(&mut *store, ()).map_err(map_wasmtime_error)?;
+            // Explicit post_return is only needed for Wasmtime 36 LTS
+            #[cfg(feature = "wasmtime_lts")]
+            func.post_return(&mut *store).map_err(map_wasmtime_error)?;
+            Some(version)
+        }
+        None => None,
+    };
+    abi_version::check(version, ABI_VERSION_EXPORT)
+}
+
+/// The export managed-language components can provide to run a garbage
+/// collection when asked by the host. `__hlwasm_gc`, used by modules, is
+/// not a valid component export name.
+const GC_EXPORT: &str = "hlwasm-gc";
+
+/// Components import WASI through their world, so no imports are stubbed
+fn get_stubbed_wasi_imports(_function_call: FunctionCall) -> Result<Vec<u8>> {
+    Ok(get_flatbuffer_result::<&str>(""))
+}
+
+/// Return the memory used by the guest, see [`memory_stats`]. The
+/// memories of components are not reported.
+fn get_memory_stats(_function_call: FunctionCall) -> Result<Vec<u8>> {
+    Ok(get_flatbuffer_result::<&[u8]>(
+        &guest_memory_stats().to_bytes(),
+    ))
+}
+
+/// The memory used by the guest
+fn guest_memory_stats() -> GuestMemoryStats {
+    GuestMemoryStats {
+        mapped_bytes: platform::mapped_bytes(),
+        heap_bytes: platform::heap_bytes(),
+        linear_memories: Vec::new(),
+    }
+}
+
+/// Run a garbage collection in the component if it supports it,
+/// returning whether it did
+#[instrument(skip_all, level = "Info")]
+fn hint_gc(_function_call: FunctionCall) -> Result<Vec<u8>> {
+    let collected = collect_garbage()?;
+    Ok(get_flatbuffer_result::<i32>(collected as i32))
+}
+
+/// Release what the component no longer needs: garbage in the
+/// component's own collector and, with the `gc` feature, wasmtime's GC
+/// heap. Returns the memory used afterwards, see [`memory_stats`].
+#[instrument(skip_all, level = "Info")]
+fn trim_memory(_function_call: FunctionCall) -> Result<Vec<u8>> {
+    collect_garbage()?;
+    #[cfg(feature = "gc")]
+    if let Some(store) = CUR_STORE.lock().as_mut() {
+        #[cfg(feature = "wasmtime_lts")]
+        store.gc(None);
+        #[cfg(not(feature = "wasmtime_lts"))]
+        store.gc(None).map_err(map_wasmtime_error)?;
+    }
+    Ok(get_flatbuffer_result::<&[u8]>(
+        &guest_memory_stats().to_bytes(),
+    ))
+}
+
+/// Run the component's collector, see [`hint_gc`]
+fn collect_garbage() -> Result<bool> {
+    let mut store = CUR_STORE.lock();
+    let store = store.as_mut().ok_or(HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        "No wasm store available".to_string(),
+    ))?;
+    let instance = CUR_INSTANCE.lock();
+    let instance = instance.as_ref().ok_or(HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        "No wasm instance available".to_string(),
+    ))?;
+
+    let Some(func) = instance.get_func(&mut *store, GC_EXPORT) else {
+        return Ok(false);
+    };
+    let func = func.typed::<(), ()>(&*store).map_err(map_wasmtime_error)?;
+    func.call(&mut *store, ()).map_err(map_wasmtime_error)?;
+    // Explicit post_return is only needed for Wasmtime 36 LTS
+    #[cfg(feature = "wasmtime_lts")]
+    func.post_return(&mut *store).map_err(map_wasmtime_error)?;
+    Ok(true)
+}
+
+/// Check that the component exports each of the given functions,
+/// returning a description of each one that is missing, separated by
+/// semicolons. Functions exported from an interface are named
+/// `interface#function`. Their types are fixed by the component's world,
+/// so they are not checked.
+#[instrument(skip_all, level = "Info")]
+fn check_exports(function_call: FunctionCall) -> Result<Vec<u8>> {
+    let bytes = match function_call.parameters.as_deref() {
+        Some([ParameterValue::VecBytes(bytes)]) => bytes,
+        _ => {
+            return Err(HyperlightGuestError::new(
+                ErrorCode::GuestFunctionParameterTypeMismatch,
+                "Invalid parameters passed to CheckExports".to_string(),
+            ));
+        }
+    };
+    let hfd: HostFunctionDetails = bytes.as_slice().try_into().map_err(|e| {
+        HyperlightGuestError::new(
+            ErrorCode::GuestError,
+            alloc::format!("Failed to parse required exports: {:?}", e),
+        )
+    })?;
+    let mut store = CUR_STORE.lock();
+    let store = store.as_mut().ok_or(HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        "No wasm store available".to_string(),
+    ))?;
+    let instance = CUR_INSTANCE.lock();
+    let instance = instance.as_ref().ok_or(HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        "No wasm instance available".to_string(),
+    ))?;
+
+    let mut problems = Vec::new();
+    for export in hfd.host_functions.unwrap_or_default() {
+        let name = &export.function_name;
+        let index = match name.split_once('#') {
+            Some((interface, func)) => instance
+                .get_export_index(&mut *store, None, interface)
+                .and_then(|interface| {
+                    instance.get_export_index(&mut *store, Some(&interface), func)
+                }),
+            None => instance.get_export_index(&mut *store, None, name),
+        };
+        if index.is_none_or(|index| instance.get_func(&mut *store, index).is_none()) {
+            problems.push(alloc::format!("{} is not exported", name));
+        }
+    }
+    Ok(get_flatbuffer_result::<&str>(&problems.join("; ")))
+}
+
+/// The WIT name of `ty`, such as `list<u8>`
+fn wit_type_name(ty: &Type) -> String {
+    let join = |types: &mut dyn Iterator<Item = String>| types.collect::<Vec<_>>().join(", ");
+    let optional = |ty: Option<Type>| ty.map_or("_".to_string(), |ty| wit_type_name(&ty));
+    match ty {
+        Type::Bool => "bool".to_string(),
+        Type::S8 => "s8".to_string(),
+        Type::U8 => "u8".to_string(),
+        Type::S16 => "s16".to_string(),
+        Type::U16 => "u16".to_string(),
+        Type::S32 => "s32".to_string(),
+        Type::U32 => "u32".to_string(),
+        Type::S64 => "s64".to_string(),
+        Type::U64 => "u64".to_string(),
+        Type::Float32 => "f32".to_string(),
+        Type::Float64 => "f64".to_string(),
+        Type::Char => "char".to_string(),
+        Type::String => "string".to_string(),
+        Type::List(list) => format!("list<{}>", wit_type_name(&list.ty())),
+        #[cfg(not(feature = "wasmtime_lts"))]
+        Type::Map(map) => format!(
+            "map<{}, {}>",
+            wit_type_name(&map.key()),
+            wit_type_name(&map.value())
+        ),
+        Type::Record(record) => format!(
+            "record {{ {} }}",
+            join(&mut record.fields().map(|field| format!(
+                "{}: {}",
+                field.name,
+                wit_type_name(&field.ty)
+            )))
+        ),
+        Type::Tuple(tuple) => format!(
+            "tuple<{}>",
+            join(&mut tuple.types().map(|ty| wit_type_name(&ty)))
+        ),
+        Type::Variant(variant) => format!(
+            "variant {{ {} }}",
+            join(&mut variant.cases().map(|case| match case.ty {
+                Some(ty) => format!("{}({})", case.name, wit_type_name(&ty)),
+                None => case.name.to_string(),
+            }))
+        ),
+        Type::Enum(names) => format!("enum {{ {} }}", join(&mut names.names().map(String::from))),
+        Type::Flags(names) => format!("flags {{ {} }}", join(&mut names.names().map(String::from))),
+        Type::Option(option) => format!("option<{}>", wit_type_name(&option.ty())),
+        Type::Result(result) => format!(
+            "result<{}, {}>",
+            optional(result.ok()),
+            optional(result.err())
+        ),
+        Type::Own(_) => "own<resource>".to_string(),
+        Type::Borrow(_) => "borrow<resource>".to_string(),
+        Type::Future(future) => format!("future<{}>", optional(future.ty())),
+        Type::Stream(stream) => format!("stream<{}>", optional(stream.ty())),
+        Type::ErrorContext => "error-context".to_string(),
+    }
+}
+
+fn guest_function(name: String, func: &ComponentFunc) -> GuestFunction {
+    GuestFunction {
+        name,
+        params: func.params().map(|(_, ty)| wit_type_name(&ty)).collect(),
+        results: func.results().map(|ty| wit_type_name(&ty)).collect(),
+    }
+}
+
+/// List the functions the component exports, see [`guest_functions`].
+/// Functions exported from an interface are named `interface#function`.
+#[instrument(skip_all, level = "Info")]
+fn get_guest_functions(_function_call: FunctionCall) -> Result<Vec<u8>> {
+    let engine = CUR_ENGINE.lock();
+    let engine = engine.as_ref().ok_or(HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        "GetGuestFunctions called before InitWasmRuntime".to_string(),
+    ))?;
+    let component = CUR_COMPONENT.lock();
+    let component = component.as_ref().ok_or(HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        "No wasm component loaded".to_string(),
+    ))?;
+    let mut functions = Vec::new();
+    for (name, item) in component.component_type().exports(engine) {
+        match item {
+            ComponentItem::ComponentFunc(func) => {
+                functions.push(guest_function(name.to_string(), &func));
+            }
+            ComponentItem::ComponentInstance(instance) => {
+                for (func_name, item) in instance.exports(engine) {
+                    if let ComponentItem::ComponentFunc(func) = item {
+                        functions.push(guest_function(format!("{}#{}", name, func_name), &func));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(get_flatbuffer_result::<&[u8]>(&guest_functions::to_bytes(
+        &functions,
+    )))
+}
+
+// Component exports are called through the bindings generated by
+// wasm_guest_bindgen, which look the function up on each call, so only
+// the component's code is faulted in
+#[instrument(skip_all, level = "Info")]
+fn warm_up(_function_call: FunctionCall) -> Result<Vec<u8>> {
+    if let Some(component) = CUR_COMPONENT.lock().as_ref() {
+        platform::prefault(component.image_range());
+    }
+    Ok(get_flatbuffer_result::<i32>(0))
+}
+
 #[instrument(skip_all, level = "Info")]
 fn load_wasm_module(function_call: FunctionCall) -> Result<Vec<u8>> {
     if let (
@@ -73,8 +425,9 @@ fn load_wasm_module(function_call: FunctionCall) -> Result<Vec<u8>> {
         &function_call.parameters.as_ref().unwrap()[1],
         &*CUR_ENGINE.lock(),
     ) {
-        let component =
-            unsafe { Component::deserialize(engine, wasm_bytes).map_err(map_wasmtime_error)? };
+        let component = info_span!("deserialize", size = wasm_bytes.len()).in_scope(|| unsafe {
+            Component::deserialize(engine, wasm_bytes).map_err(map_wasmtime_error)
+        })?;
         load_component_common(engine, component)?;
         Ok(get_flatbuffer_result::<i32>(0))
     } else {
@@ -92,10 +445,10 @@ fn load_wasm_module_phys(function_call: FunctionCall) -> Result<Vec<u8>> {
         &function_call.parameters.as_ref().unwrap()[1],
         &*CUR_ENGINE.lock(),
     ) {
-        let component = unsafe {
+        let component = info_span!("deserialize", size = *len).in_scope(|| unsafe {
             Component::deserialize_raw(engine, platform::map_buffer(*phys, *len))
-                .map_err(map_wasmtime_error)?
-        };
+                .map_err(map_wasmtime_error)
+        })?;
         load_component_common(engine, component)?;
         Ok(get_flatbuffer_result::<()>(()))
     } else {
@@ -111,43 +464,70 @@ fn load_wasm_module_phys(function_call: FunctionCall) -> Result<Vec<u8>> {
 pub extern "C" fn hyperlight_main() {
     platform::register_page_fault_handler();
 
-    let mut config = Config::new();
-    // Enable x86_float_abi_ok only for the latest Wasmtime native x86 target.
-    // Safety:
-    // We are using hyperlight cargo to build the guest which
-    // sets the Rust target to be compiled with the hard-float ABI manually via
-    // `-Zbuild-std` and a custom target JSON configuration
-    // See https://github.com/bytecodealliance/wasmtime/pull/11553
-    #[cfg(all(not(feature = "wasmtime_lts"), not(pulley)))]
-    unsafe {
-        config.x86_float_abi_ok(true)
-    };
-    config.with_custom_code_memory(Some(alloc::sync::Arc::new(platform::WasmtimeCodeMemory {})));
-    #[cfg(gdb)]
-    config.debug_info(true);
-    #[cfg(pulley)]
-    config
-        .target("pulley64")
-        .map_err(|_| {
-            HyperlightGuestError::new(
-                ErrorCode::GuestError,
-                "Failed to set wasmtime target: pulley64".to_string(),
-            )
-        })
-        .unwrap();
-    let engine = Engine::new(&config).unwrap();
-    let linker = Linker::new(&engine);
-    *CUR_ENGINE.lock() = Some(engine);
-    *CUR_LINKER.lock() = Some(linker);
+    register_function(GuestFunctionDefinition::new(
+        "InitWasmRuntime".to_string(),
+        vec![ParameterType::VecBytes, ParameterType::VecBytes],
+        ReturnType::Int,
+        init_wasm_runtime,
+    ));
 
-    hyperlight_guest_wasm_init();
+    // Components only write to the log buffer through
+    // hlwasm:introspection and hlwasm:log, but the host drains it regardless of what
+    // kind of guest is loaded
+    log_buffer::register_functions();
+    call_tracker::register_functions();
+    epoch_deadline::register_functions();
+    payload_key::register_functions();
 
     register_function(GuestFunctionDefinition::new(
-        "InitWasmRuntime".to_string(),
+        "GetStubbedWasiImports".to_string(),
+        vec![],
+        ReturnType::String,
+        get_stubbed_wasi_imports,
+    ));
+
+    register_function(GuestFunctionDefinition::new(
+        memory_stats::GET_MEMORY_STATS_FUNCTION.to_string(),
+        vec![],
+        ReturnType::VecBytes,
+        get_memory_stats,
+    ));
+
+    register_function(GuestFunctionDefinition::new(
+        memory_stats::TRIM_MEMORY_FUNCTION.to_string(),
+        vec![],
+        ReturnType::VecBytes,
+        trim_memory,
+    ));
+
+    register_function(GuestFunctionDefinition::new(
+        "HintGc".to_string(),
+        vec![],
+        ReturnType::Int,
+        hint_gc,
+    ));
+
+    register_function(GuestFunctionDefinition::new(
+        "GetGuestFunctions".to_string(),
+        vec![],
+        ReturnType::VecBytes,
+        get_guest_functions,
+    ));
+
+    register_function(GuestFunctionDefinition::new(
+        "CheckExports".to_string(),
+        vec![ParameterType::VecBytes],
+        ReturnType::String,
+        check_exports,
+    ));
+
+    register_function(GuestFunctionDefinition::new(
+        "WarmUp".to_string(),
         vec![ParameterType::VecBytes],
         ReturnType::Int,
-        init_wasm_runtime,
+        warm_up,
     ));
+
     register_function(GuestFunctionDefinition::new(
         "LoadWasmModule".to_string(),
         vec![ParameterType::VecBytes, ParameterType::Int],
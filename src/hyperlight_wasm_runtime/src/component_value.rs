@@ -0,0 +1,320 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Values passed to and returned from the functions of components loaded
+//! without bindings generated for their world, see
+//! [`LOAD_COMPONENT_FUNCTION`].
+//!
+//! Values are encoded in order, each as a one byte type tag followed by
+//! its value: numbers little-endian, strings and names as their UTF-8
+//! bytes and lists of values as their items, each preceded by their
+//! little-endian `u32` length.
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// The guest function that loads a component, deserializing it and
+/// linking each function it imports to the host function named after it,
+/// as `interface#function` or `function`. It takes the precompiled
+/// component as a `VecBytes`. Host functions take their parameters and
+/// return their results encoded with [`to_bytes`] as a `VecBytes`.
+pub const LOAD_COMPONENT_FUNCTION: &str = "LoadComponent";
+
+/// The guest function that calls a function exported by a component
+/// loaded with [`LOAD_COMPONENT_FUNCTION`]. It takes the function's name,
+/// as `interface#function` or `function`, as a `String` and its
+/// parameters encoded with [`to_bytes`] as a `VecBytes`, and returns its
+/// results encoded with [`to_bytes`] as a `VecBytes`.
+pub const CALL_COMPONENT_FUNCTION: &str = "CallComponentFunction";
+
+/// A value of a WIT type. Resources, futures and streams are not
+/// supported.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ComponentValue {
+    /// A `bool`
+    Bool(bool),
+    /// An `s8`
+    S8(i8),
+    /// A `u8`
+    U8(u8),
+    /// An `s16`
+    S16(i16),
+    /// A `u16`
+    U16(u16),
+    /// An `s32`
+    S32(i32),
+    /// A `u32`
+    U32(u32),
+    /// An `s64`
+    S64(i64),
+    /// A `u64`
+    U64(u64),
+    /// An `f32`
+    Float32(f32),
+    /// An `f64`
+    Float64(f64),
+    /// A `char`
+    Char(char),
+    /// A `string`
+    String(String),
+    /// A `list`
+    List(Vec<ComponentValue>),
+    /// A `record`, as the names and values of its fields in order
+    Record(Vec<(String, ComponentValue)>),
+    /// A `tuple`
+    Tuple(Vec<ComponentValue>),
+    /// A case of a `variant`, and its value if it has one
+    Variant(String, Option<Box<ComponentValue>>),
+    /// A case of an `enum`
+    Enum(String),
+    /// An `option`
+    Option(Option<Box<ComponentValue>>),
+    /// A `result`, and the value of the case if it has one
+    Result(Result<Option<Box<ComponentValue>>, Option<Box<ComponentValue>>>),
+    /// The `flags` that are set
+    Flags(Vec<String>),
+}
+
+fn push_len(bytes: &mut Vec<u8>, len: usize) {
+    bytes.extend_from_slice(&(len as u32).to_le_bytes());
+}
+
+fn push_str(bytes: &mut Vec<u8>, value: &str) {
+    push_len(bytes, value.len());
+    bytes.extend_from_slice(value.as_bytes());
+}
+
+fn push_optional(bytes: &mut Vec<u8>, value: &Option<Box<ComponentValue>>) {
+    match value {
+        Some(value) => {
+            bytes.push(1);
+            push_value(bytes, value);
+        }
+        None => bytes.push(0),
+    }
+}
+
+fn push_values(bytes: &mut Vec<u8>, values: &[ComponentValue]) {
+    push_len(bytes, values.len());
+    for value in values {
+        push_value(bytes, value);
+    }
+}
+
+fn push_value(bytes: &mut Vec<u8>, value: &ComponentValue) {
+    match value {
+        ComponentValue::Bool(v) => bytes.extend_from_slice(&[0, *v as u8]),
+        ComponentValue::S8(v) => bytes.extend_from_slice(&[1, *v as u8]),
+        ComponentValue::U8(v) => bytes.extend_from_slice(&[2, *v]),
+        ComponentValue::S16(v) => {
+            bytes.push(3);
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        ComponentValue::U16(v) => {
+            bytes.push(4);
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        ComponentValue::S32(v) => {
+            bytes.push(5);
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        ComponentValue::U32(v) => {
+            bytes.push(6);
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        ComponentValue::S64(v) => {
+            bytes.push(7);
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        ComponentValue::U64(v) => {
+            bytes.push(8);
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        ComponentValue::Float32(v) => {
+            bytes.push(9);
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        ComponentValue::Float64(v) => {
+            bytes.push(10);
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        ComponentValue::Char(v) => {
+            bytes.push(11);
+            bytes.extend_from_slice(&(*v as u32).to_le_bytes());
+        }
+        ComponentValue::String(v) => {
+            bytes.push(12);
+            push_str(bytes, v);
+        }
+        ComponentValue::List(values) => {
+            bytes.push(13);
+            push_values(bytes, values);
+        }
+        ComponentValue::Record(fields) => {
+            bytes.push(14);
+            push_len(bytes, fields.len());
+            for (name, value) in fields {
+                push_str(bytes, name);
+                push_value(bytes, value);
+            }
+        }
+        ComponentValue::Tuple(values) => {
+            bytes.push(15);
+            push_values(bytes, values);
+        }
+        ComponentValue::Variant(name, value) => {
+            bytes.push(16);
+            push_str(bytes, name);
+            push_optional(bytes, value);
+        }
+        ComponentValue::Enum(name) => {
+            bytes.push(17);
+            push_str(bytes, name);
+        }
+        ComponentValue::Option(value) => {
+            bytes.push(18);
+            push_optional(bytes, value);
+        }
+        ComponentValue::Result(Ok(value)) => {
+            bytes.extend_from_slice(&[19, 0]);
+            push_optional(bytes, value);
+        }
+        ComponentValue::Result(Err(value)) => {
+            bytes.extend_from_slice(&[19, 1]);
+            push_optional(bytes, value);
+        }
+        ComponentValue::Flags(names) => {
+            bytes.push(20);
+            push_len(bytes, names.len());
+            for name in names {
+                push_str(bytes, name);
+            }
+        }
+    }
+}
+
+/// Encode `values` to be passed between the host and the guest
+pub fn to_bytes(values: &[ComponentValue]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for value in values {
+        push_value(&mut bytes, value);
+    }
+    bytes
+}
+
+fn take<const N: usize>(bytes: &mut &[u8]) -> Option<[u8; N]> {
+    let (value, rest) = bytes.split_first_chunk::<N>()?;
+    *bytes = rest;
+    Some(*value)
+}
+
+fn take_len(bytes: &mut &[u8]) -> Option<usize> {
+    Some(u32::from_le_bytes(take(bytes)?) as usize)
+}
+
+fn take_str(bytes: &mut &[u8]) -> Option<String> {
+    let len = take_len(bytes)?;
+    if bytes.len() < len {
+        return None;
+    }
+    let (value, rest) = bytes.split_at(len);
+    *bytes = rest;
+    String::from_utf8(value.to_vec()).ok()
+}
+
+fn take_optional(bytes: &mut &[u8]) -> Option<Option<Box<ComponentValue>>> {
+    match take(bytes)? {
+        [0] => Some(None),
+        [1] => Some(Some(Box::new(take_value(bytes)?))),
+        _ => None,
+    }
+}
+
+fn take_values(bytes: &mut &[u8]) -> Option<Vec<ComponentValue>> {
+    let len = take_len(bytes)?;
+    // Each value takes at least one byte, which bounds what a malformed
+    // length can allocate
+    let mut values = Vec::with_capacity(len.min(bytes.len()));
+    for _ in 0..len {
+        values.push(take_value(bytes)?);
+    }
+    Some(values)
+}
+
+fn take_value(bytes: &mut &[u8]) -> Option<ComponentValue> {
+    let [tag] = take(bytes)?;
+    Some(match tag {
+        0 => match take(bytes)? {
+            [0] => ComponentValue::Bool(false),
+            [1] => ComponentValue::Bool(true),
+            _ => return None,
+        },
+        1 => ComponentValue::S8(i8::from_le_bytes(take(bytes)?)),
+        2 => ComponentValue::U8(u8::from_le_bytes(take(bytes)?)),
+        3 => ComponentValue::S16(i16::from_le_bytes(take(bytes)?)),
+        4 => ComponentValue::U16(u16::from_le_bytes(take(bytes)?)),
+        5 => ComponentValue::S32(i32::from_le_bytes(take(bytes)?)),
+        6 => ComponentValue::U32(u32::from_le_bytes(take(bytes)?)),
+        7 => ComponentValue::S64(i64::from_le_bytes(take(bytes)?)),
+        8 => ComponentValue::U64(u64::from_le_bytes(take(bytes)?)),
+        9 => ComponentValue::Float32(f32::from_le_bytes(take(bytes)?)),
+        10 => ComponentValue::Float64(f64::from_le_bytes(take(bytes)?)),
+        11 => ComponentValue::Char(char::from_u32(u32::from_le_bytes(take(bytes)?))?),
+        12 => ComponentValue::String(take_str(bytes)?),
+        13 => ComponentValue::List(take_values(bytes)?),
+        14 => {
+            let len = take_len(bytes)?;
+            let mut fields = Vec::with_capacity(len.min(bytes.len()));
+            for _ in 0..len {
+                let name = take_str(bytes)?;
+                fields.push((name, take_value(bytes)?));
+            }
+            ComponentValue::Record(fields)
+        }
+        15 => ComponentValue::Tuple(take_values(bytes)?),
+        16 => {
+            let name = take_str(bytes)?;
+            ComponentValue::Variant(name, take_optional(bytes)?)
+        }
+        17 => ComponentValue::Enum(take_str(bytes)?),
+        18 => ComponentValue::Option(take_optional(bytes)?),
+        19 => match take(bytes)? {
+            [0] => ComponentValue::Result(Ok(take_optional(bytes)?)),
+            [1] => ComponentValue::Result(Err(take_optional(bytes)?)),
+            _ => return None,
+        },
+        20 => {
+            let len = take_len(bytes)?;
+            let mut names = Vec::with_capacity(len.min(bytes.len()));
+            for _ in 0..len {
+                names.push(take_str(bytes)?);
+            }
+            ComponentValue::Flags(names)
+        }
+        _ => return None,
+    })
+}
+
+/// Decode values encoded with [`to_bytes`], returning `None` if the
+/// encoding is malformed
+pub fn from_bytes(mut bytes: &[u8]) -> Option<Vec<ComponentValue>> {
+    let mut values = Vec::new();
+    while !bytes.is_empty() {
+        values.push(take_value(&mut bytes)?);
+    }
+    Some(values)
+}
@@ -0,0 +1,102 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use alloc::collections::BTreeSet;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use hyperlight_common::flatbuffer_wrappers::function_call::FunctionCall;
+use hyperlight_common::flatbuffer_wrappers::function_types::{
+    ParameterType, ParameterValue, ReturnType, ReturnValue,
+};
+use hyperlight_common::flatbuffer_wrappers::guest_error::ErrorCode;
+use hyperlight_common::flatbuffer_wrappers::util::get_flatbuffer_result;
+use hyperlight_guest::error::{HyperlightGuestError, Result};
+use hyperlight_guest_bin::guest_function::definition::GuestFunctionDefinition;
+use hyperlight_guest_bin::guest_function::register::register_function;
+use hyperlight_guest_bin::host_comm::call_host_function;
+use spin::Mutex;
+use tracing::instrument;
+
+use crate::host_dispatch;
+
+// The host functions the host registered from a manifest, which are
+// called through the dispatch host function
+static DISPATCHED: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+/// Call host function `name`, through the dispatch host function if it
+/// was registered from a manifest
+#[instrument(skip_all, level = "Info")]
+pub(crate) fn call_host(
+    name: &str,
+    params: Vec<ParameterValue>,
+    return_type: ReturnType,
+) -> Result<ReturnValue> {
+    if !DISPATCHED.lock().contains(name) {
+        return call_host_function::<ReturnValue>(name, Some(params), return_type);
+    }
+    let result = call_host_function::<Vec<u8>>(
+        host_dispatch::HOST_DISPATCH_FUNCTION,
+        Some(vec![
+            ParameterValue::String(name.to_string()),
+            ParameterValue::VecBytes(host_dispatch::params_to_bytes(&params)),
+        ]),
+        ReturnType::VecBytes,
+    )?;
+    host_dispatch::return_value_from_bytes(&result).ok_or_else(|| {
+        HyperlightGuestError::new(
+            ErrorCode::GuestError,
+            alloc::format!("Malformed result from dispatched host function {}", name),
+        )
+    })
+}
+
+#[instrument(skip_all, level = "Info")]
+/// Set the host functions to call through the dispatch host function,
+/// see [`crate::host_dispatch`]
+fn configure_host_dispatch(function_call: FunctionCall) -> Result<Vec<u8>> {
+    let names = match function_call.parameters.as_deref() {
+        Some([ParameterValue::VecBytes(bytes)]) => bytes,
+        _ => {
+            return Err(HyperlightGuestError::new(
+                ErrorCode::GuestFunctionParameterTypeMismatch,
+                "Invalid parameters passed to ConfigureHostDispatch".to_string(),
+            ));
+        }
+    };
+    let mut dispatched = DISPATCHED.lock();
+    dispatched.clear();
+    for name in names.split(|b| *b == 0).filter(|name| !name.is_empty()) {
+        let name = core::str::from_utf8(name).map_err(|_| {
+            HyperlightGuestError::new(
+                ErrorCode::GuestError,
+                "ConfigureHostDispatch: host function name is not valid UTF-8".to_string(),
+            )
+        })?;
+        dispatched.insert(name.to_string());
+    }
+    Ok(get_flatbuffer_result::<i32>(0))
+}
+
+pub(crate) fn register_functions() {
+    register_function(GuestFunctionDefinition::new(
+        "ConfigureHostDispatch".to_string(),
+        vec![ParameterType::VecBytes],
+        ReturnType::Int,
+        configure_host_dispatch,
+    ));
+}
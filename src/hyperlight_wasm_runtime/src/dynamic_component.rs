@@ -0,0 +1,416 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Components loaded into the runtime for modules, without bindings
+//! generated for their world, see [`crate::component_value`]. Each
+//! function a component imports calls the host function named after it,
+//! unless it is a WASI interface linked by [`crate::wasip2`].
+
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::{format, vec};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use hyperlight_common::flatbuffer_wrappers::function_call::FunctionCall;
+use hyperlight_common::flatbuffer_wrappers::function_types::{
+    ParameterType, ParameterValue, ReturnType,
+};
+use hyperlight_common::flatbuffer_wrappers::guest_error::ErrorCode;
+use hyperlight_common::flatbuffer_wrappers::util::get_flatbuffer_result;
+use hyperlight_guest::error::{HyperlightGuestError, Result};
+use hyperlight_guest_bin::guest_function::definition::GuestFunctionDefinition;
+use hyperlight_guest_bin::guest_function::register::register_function;
+use hyperlight_guest_bin::host_comm::call_host_function;
+use spin::Mutex;
+use tracing::{info_span, instrument};
+use wasmtime::component::types::ComponentItem;
+use wasmtime::component::{Component, Func, Instance, InstancePre, Linker, LinkerInstance, Val};
+use wasmtime::{Engine, Store};
+
+use crate::component_value::{
+    self, ComponentValue, CALL_COMPONENT_FUNCTION, LOAD_COMPONENT_FUNCTION,
+};
+use crate::module::{CUR_ENGINE, CUR_HOST_FUNCS};
+use crate::runtime_config::RuntimeConfig;
+use crate::{
+    abi_version, call_tracker, epoch_deadline, guest_abort, host_error, limits, log_buffer,
+    map_wasmtime_error, wasip2, wasm_limits,
+};
+
+// Set by load_component
+static CUR_STORE: Mutex<Option<Store<()>>> = Mutex::new(None);
+static CUR_INSTANCE: Mutex<Option<Instance>> = Mutex::new(None);
+// The loaded component with its imports resolved, from which a fresh
+// instance is made before each call if FRESH_INSTANCE_PER_CALL is set
+static CUR_INSTANCE_PRE: Mutex<Option<InstancePre<()>>> = Mutex::new(None);
+// Set by init_wasm_runtime from the runtime config
+static FRESH_INSTANCE_PER_CALL: AtomicBool = AtomicBool::new(false);
+
+pub(crate) fn configure(runtime_config: &RuntimeConfig) {
+    FRESH_INSTANCE_PER_CALL.store(runtime_config.fresh_instance_per_call, Ordering::Relaxed);
+}
+
+/// The export components can provide to declare the version of the guest
+/// ABI they target
+const ABI_VERSION_EXPORT: &str = "hlwasm-abi-version";
+
+fn to_component_value(value: &Val) -> wasmtime::Result<ComponentValue> {
+    let optional = |value: &Option<Box<Val>>| {
+        value
+            .as_deref()
+            .map(|value| to_component_value(value).map(Box::new))
+            .transpose()
+    };
+    let values = |values: &[Val]| -> wasmtime::Result<Vec<ComponentValue>> {
+        values.iter().map(to_component_value).collect()
+    };
+    Ok(match value {
+        Val::Bool(v) => ComponentValue::Bool(*v),
+        Val::S8(v) => ComponentValue::S8(*v),
+        Val::U8(v) => ComponentValue::U8(*v),
+        Val::S16(v) => ComponentValue::S16(*v),
+        Val::U16(v) => ComponentValue::U16(*v),
+        Val::S32(v) => ComponentValue::S32(*v),
+        Val::U32(v) => ComponentValue::U32(*v),
+        Val::S64(v) => ComponentValue::S64(*v),
+        Val::U64(v) => ComponentValue::U64(*v),
+        Val::Float32(v) => ComponentValue::Float32(*v),
+        Val::Float64(v) => ComponentValue::Float64(*v),
+        Val::Char(v) => ComponentValue::Char(*v),
+        Val::String(v) => ComponentValue::String(v.clone()),
+        Val::List(v) => ComponentValue::List(values(v)?),
+        Val::Record(fields) => ComponentValue::Record(
+            fields
+                .iter()
+                .map(|(name, value)| Ok((name.clone(), to_component_value(value)?)))
+                .collect::<wasmtime::Result<_>>()?,
+        ),
+        Val::Tuple(v) => ComponentValue::Tuple(values(v)?),
+        Val::Variant(name, value) => ComponentValue::Variant(name.clone(), optional(value)?),
+        Val::Enum(name) => ComponentValue::Enum(name.clone()),
+        Val::Option(value) => ComponentValue::Option(optional(value)?),
+        Val::Result(Ok(value)) => ComponentValue::Result(Ok(optional(value)?)),
+        Val::Result(Err(value)) => ComponentValue::Result(Err(optional(value)?)),
+        Val::Flags(names) => ComponentValue::Flags(names.clone()),
+        _ => {
+            return Err(wasmtime::Error::msg(
+                "resources, futures, streams and maps cannot be passed to or from the host",
+            ))
+        }
+    })
+}
+
+fn to_val(value: ComponentValue) -> Val {
+    let optional = |value: Option<Box<ComponentValue>>| value.map(|value| Box::new(to_val(*value)));
+    let values = |values: Vec<ComponentValue>| values.into_iter().map(to_val).collect();
+    match value {
+        ComponentValue::Bool(v) => Val::Bool(v),
+        ComponentValue::S8(v) => Val::S8(v),
+        ComponentValue::U8(v) => Val::U8(v),
+        ComponentValue::S16(v) => Val::S16(v),
+        ComponentValue::U16(v) => Val::U16(v),
+        ComponentValue::S32(v) => Val::S32(v),
+        ComponentValue::U32(v) => Val::U32(v),
+        ComponentValue::S64(v) => Val::S64(v),
+        ComponentValue::U64(v) => Val::U64(v),
+        ComponentValue::Float32(v) => Val::Float32(v),
+        ComponentValue::Float64(v) => Val::Float64(v),
+        ComponentValue::Char(v) => Val::Char(v),
+        ComponentValue::String(v) => Val::String(v),
+        ComponentValue::List(v) => Val::List(values(v)),
+        ComponentValue::Record(fields) => Val::Record(
+            fields
+                .into_iter()
+                .map(|(name, value)| (name, to_val(value)))
+                .collect(),
+        ),
+        ComponentValue::Tuple(v) => Val::Tuple(values(v)),
+        ComponentValue::Variant(name, value) => Val::Variant(name, optional(value)),
+        ComponentValue::Enum(name) => Val::Enum(name),
+        ComponentValue::Option(value) => Val::Option(optional(value)),
+        ComponentValue::Result(Ok(value)) => Val::Result(Ok(optional(value))),
+        ComponentValue::Result(Err(value)) => Val::Result(Err(optional(value))),
+        ComponentValue::Flags(names) => Val::Flags(names),
+    }
+}
+
+/// Call the host function `name` with `params`, storing what it returns
+/// in `results`
+fn call_host(name: &str, params: &[Val], results: &mut [Val]) -> wasmtime::Result<()> {
+    let params = params
+        .iter()
+        .map(to_component_value)
+        .collect::<wasmtime::Result<Vec<_>>>()?;
+    call_tracker::record_host_call();
+    let bytes = call_host_function::<Vec<u8>>(
+        name,
+        Some(vec![ParameterValue::VecBytes(component_value::to_bytes(
+            &params,
+        ))]),
+        ReturnType::VecBytes,
+    )
+    .map_err(|e| host_error::failed(name, e))?;
+    let values = component_value::from_bytes(&bytes)
+        .filter(|values| values.len() == results.len())
+        .ok_or_else(|| {
+            wasmtime::Error::msg(format!(
+                "host function {} returned {} results",
+                name,
+                component_value::from_bytes(&bytes).map_or(0, |values| values.len())
+            ))
+        })?;
+    for (result, value) in results.iter_mut().zip(values) {
+        *result = to_val(value);
+    }
+    Ok(())
+}
+
+/// Define the import `function` of `instance` to call the host function
+/// `name`
+fn define_import(
+    instance: &mut LinkerInstance<'_, ()>,
+    function: &str,
+    name: String,
+) -> wasmtime::Result<()> {
+    #[cfg(feature = "wasmtime_lts")]
+    return instance.func_new(function, move |_store, params, results| {
+        call_host(&name, params, results)
+    });
+    #[cfg(not(feature = "wasmtime_lts"))]
+    return instance.func_new(function, move |_store, _ty, params, results| {
+        call_host(&name, params, results)
+    });
+}
+
+/// Link each function `component` imports to the host function named
+/// after it, if the host registered one
+fn link_imports(linker: &mut Linker<()>, engine: &Engine, component: &Component) -> Result<()> {
+    let host_functions: Vec<String> = CUR_HOST_FUNCS
+        .lock()
+        .iter()
+        .map(|f| f.function_name.clone())
+        .collect();
+    let is_registered = |name: &str| host_functions.iter().any(|f| f == name);
+    for (name, item) in component.component_type().imports(engine) {
+        match item {
+            ComponentItem::ComponentFunc(_) if is_registered(name) => {
+                define_import(&mut linker.root(), name, name.to_string())
+                    .map_err(map_wasmtime_error)?;
+            }
+            ComponentItem::ComponentInstance(instance) => {
+                let functions: Vec<String> = instance
+                    .exports(engine)
+                    .filter(|(_, item)| matches!(item, ComponentItem::ComponentFunc(_)))
+                    .map(|(function, _)| function.to_string())
+                    .filter(|function| is_registered(&format!("{}#{}", name, function)))
+                    .collect();
+                if functions.is_empty() {
+                    continue;
+                }
+                let mut linker_instance = linker.instance(name).map_err(map_wasmtime_error)?;
+                for function in functions {
+                    let host_function = format!("{}#{}", name, function);
+                    define_import(&mut linker_instance, &function, host_function)
+                        .map_err(map_wasmtime_error)?;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Fail if the host requires a guest ABI version that the component does
+/// not declare or does not match
+fn check_abi_version(store: &mut Store<()>, instance: &Instance) -> Result<()> {
+    if !abi_version::is_required() {
+        return Ok(());
+    }
+    let version = match instance.get_func(&mut *store, ABI_VERSION_EXPORT) {
+        Some(func) => {
+            let func = func
+                .typed::<(), (u32,)>(&*store)
+                .map_err(|_| abi_version::invalid_export(ABI_VERSION_EXPORT))?;
+            let (version,) = func.call(&mut *store, ()).map_err(map_wasmtime_error)?;
+            // Explicit post_return is only needed for Wasmtime 36 LTS
+            #[cfg(feature = "wasmtime_lts")]
+            func.post_return(&mut *store).map_err(map_wasmtime_error)?;
+            Some(version)
+        }
+        None => None,
+    };
+    abi_version::check(version, ABI_VERSION_EXPORT)
+}
+
+/// Deserialize and instantiate a component, making it the loaded
+/// component
+#[instrument(skip_all, level = "Info")]
+fn load_component(function_call: FunctionCall) -> Result<Vec<u8>> {
+    let Some([ParameterValue::VecBytes(bytes)]) = function_call.parameters.as_deref() else {
+        return Err(HyperlightGuestError::new(
+            ErrorCode::GuestFunctionParameterTypeMismatch,
+            format!("Invalid parameters passed to {}", LOAD_COMPONENT_FUNCTION),
+        ));
+    };
+    let engine = CUR_ENGINE.lock().clone().ok_or(HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        format!("{} called before InitWasmRuntime", LOAD_COMPONENT_FUNCTION),
+    ))?;
+    let component = info_span!("deserialize", size = bytes.len()).in_scope(|| unsafe {
+        Component::deserialize(&engine, bytes).map_err(map_wasmtime_error)
+    })?;
+
+    let mut linker = Linker::new(&engine);
+    link_imports(&mut linker, &engine, &component)?;
+    limits::register_component_handlers(&mut linker)?;
+    log_buffer::register_component_handlers(&mut linker)?;
+    wasip2::link_imports(&mut linker, &component)?;
+    let instance_pre = linker
+        .instantiate_pre(&component)
+        .map_err(map_wasmtime_error)?;
+    let mut store = new_store(&engine);
+    let instance = info_span!("instantiate")
+        .in_scope(|| instance_pre.instantiate(&mut store))
+        .map_err(map_wasmtime_error)?;
+    check_abi_version(&mut store, &instance)?;
+    *CUR_STORE.lock() = Some(store);
+    *CUR_INSTANCE.lock() = Some(instance);
+    *CUR_INSTANCE_PRE.lock() = Some(instance_pre);
+    Ok(get_flatbuffer_result::<()>(()))
+}
+
+fn new_store(engine: &Engine) -> Store<()> {
+    let mut store = Store::new(engine, ());
+    epoch_deadline::configure_store(&mut store);
+    wasm_limits::configure_store(&mut store);
+    store
+}
+
+/// Replace the loaded component's instance, and the store holding its
+/// state, with a fresh one, if the runtime config asks for one per call
+fn refresh_instance() -> Result<()> {
+    if !FRESH_INSTANCE_PER_CALL.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+    let instance_pre = CUR_INSTANCE_PRE.lock();
+    let Some(instance_pre) = instance_pre.as_ref() else {
+        // Reported as a missing component by the caller
+        return Ok(());
+    };
+    let mut store = new_store(instance_pre.engine());
+    let instance = instance_pre
+        .instantiate(&mut store)
+        .map_err(map_wasmtime_error)?;
+    *CUR_STORE.lock() = Some(store);
+    *CUR_INSTANCE.lock() = Some(instance);
+    Ok(())
+}
+
+/// The function the loaded component exports as `name`, which is
+/// `interface#function` for functions exported from an interface
+fn find_func(store: &mut Store<()>, instance: &Instance, name: &str) -> Option<Func> {
+    let index = match name.split_once('#') {
+        Some((interface, func)) => {
+            let interface = instance.get_export_index(&mut *store, None, interface)?;
+            instance.get_export_index(&mut *store, Some(&interface), func)?
+        }
+        None => instance.get_export_index(&mut *store, None, name)?,
+    };
+    instance.get_func(&mut *store, index)
+}
+
+/// Call a function exported by the loaded component
+#[instrument(skip_all, level = "Info")]
+fn call_component_function(function_call: FunctionCall) -> Result<Vec<u8>> {
+    let Some([ParameterValue::String(name), ParameterValue::VecBytes(params)]) =
+        function_call.parameters.as_deref()
+    else {
+        return Err(HyperlightGuestError::new(
+            ErrorCode::GuestFunctionParameterTypeMismatch,
+            format!("Invalid parameters passed to {}", CALL_COMPONENT_FUNCTION),
+        ));
+    };
+    refresh_instance()?;
+    let mut store = CUR_STORE.lock();
+    let store = store.as_mut().ok_or(HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        "No wasm component loaded".to_string(),
+    ))?;
+    let instance = CUR_INSTANCE.lock();
+    let instance = instance.as_ref().ok_or(HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        "No wasm component loaded".to_string(),
+    ))?;
+    let func = find_func(store, instance, name).ok_or_else(|| {
+        HyperlightGuestError::new(
+            ErrorCode::GuestFunctionNotFound,
+            format!("the component does not export {}", name),
+        )
+    })?;
+    let params: Vec<Val> = component_value::from_bytes(params)
+        .ok_or_else(|| {
+            HyperlightGuestError::new(
+                ErrorCode::GuestFunctionParameterTypeMismatch,
+                format!("the parameters passed to {} are malformed", name),
+            )
+        })?
+        .into_iter()
+        .map(to_val)
+        .collect();
+    #[cfg(feature = "wasmtime_lts")]
+    let n_results = func.results(&*store).len();
+    #[cfg(not(feature = "wasmtime_lts"))]
+    let n_results = func.ty(&*store).results().len();
+    let mut results = vec![Val::Bool(false); n_results];
+
+    call_tracker::begin_call();
+    guest_abort::begin_call();
+    host_error::begin_call();
+    wasm_limits::begin_call();
+    epoch_deadline::begin_call(&mut *store);
+    let result = func.call(&mut *store, &params, &mut results);
+    epoch_deadline::end_call();
+    call_tracker::end_call(None, None);
+    result.map_err(epoch_deadline::map_call_error)?;
+    // Explicit post_return is only needed for Wasmtime 36 LTS
+    #[cfg(feature = "wasmtime_lts")]
+    func.post_return(&mut *store).map_err(map_wasmtime_error)?;
+
+    let results = results
+        .iter()
+        .map(to_component_value)
+        .collect::<wasmtime::Result<Vec<_>>>()
+        .map_err(map_wasmtime_error)?;
+    Ok(get_flatbuffer_result::<&[u8]>(&component_value::to_bytes(
+        &results,
+    )))
+}
+
+pub(crate) fn register_functions() {
+    register_function(GuestFunctionDefinition::new(
+        LOAD_COMPONENT_FUNCTION.to_string(),
+        vec![ParameterType::VecBytes],
+        ReturnType::Void,
+        load_component,
+    ));
+    register_function(GuestFunctionDefinition::new(
+        CALL_COMPONENT_FUNCTION.to_string(),
+        vec![ParameterType::String, ParameterType::VecBytes],
+        ReturnType::VecBytes,
+        call_component_function,
+    ));
+}
@@ -0,0 +1,113 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use alloc::string::ToString;
+
+use hyperlight_common::flatbuffer_wrappers::function_types::ParameterValue;
+use hyperlight_common::flatbuffer_wrappers::guest_error::ErrorCode;
+use hyperlight_guest::error::{HyperlightGuestError, Result};
+use tracing::instrument;
+use wasmtime::{Config, Engine, ModuleVersionStrategy};
+
+use crate::runtime_config::{RuntimeConfig, NAN_CANONICALIZATION_VERSION_SUFFIX};
+use crate::{map_wasmtime_error, platform, WASMTIME_VERSION};
+
+/// Parse the runtime config passed by the host to InitWasmRuntime
+pub(crate) fn parse_runtime_config(param: Option<&ParameterValue>) -> Result<RuntimeConfig> {
+    match param {
+        Some(ParameterValue::VecBytes(bytes)) => RuntimeConfig::from_bytes(bytes).map_err(|e| {
+            HyperlightGuestError::new(
+                ErrorCode::GuestFunctionParameterTypeMismatch,
+                alloc::format!("InitWasmRuntime: {}", e),
+            )
+        }),
+        _ => Err(HyperlightGuestError::new(
+            ErrorCode::GuestFunctionParameterTypeMismatch,
+            "InitWasmRuntime: runtime config parameter must be VecBytes".to_string(),
+        )),
+    }
+}
+
+/// Create the wasmtime engine used to run modules and components
+#[instrument(skip_all, level = "Info")]
+pub(crate) fn new_engine(runtime_config: &RuntimeConfig) -> Result<Engine> {
+    let mut config = Config::new();
+    // Enable x86_float_abi_ok only for the latest Wasmtime native x86 target.
+    // Safety:
+    // We are using hyperlight cargo to build the guest which
+    // sets the Rust target to be compiled with the hard-float ABI manually via
+    // `-Zbuild-std` and a custom target JSON configuration
+    // See https://github.com/bytecodealliance/wasmtime/pull/11553
+    #[cfg(all(not(feature = "wasmtime_lts"), not(pulley)))]
+    unsafe {
+        config.x86_float_abi_ok(true)
+    };
+
+    config.with_custom_code_memory(Some(alloc::sync::Arc::new(platform::WasmtimeCodeMemory {})));
+    #[cfg(gdb)]
+    config.debug_info(true);
+    #[cfg(pulley)]
+    config.target("pulley64").map_err(|_| {
+        HyperlightGuestError::new(
+            ErrorCode::GuestError,
+            "Failed to set wasmtime target: pulley64".to_string(),
+        )
+    })?;
+
+    if let Some(max_wasm_stack) = runtime_config.max_wasm_stack {
+        config.max_wasm_stack(max_wasm_stack as usize);
+    }
+
+    // The proposals must match those modules were precompiled with, which
+    // deserialization checks. The engine has no thread support.
+    let features = runtime_config.wasm_features;
+    if features.threads {
+        return Err(HyperlightGuestError::new(
+            ErrorCode::GuestError,
+            "the WebAssembly threads proposal is not supported".to_string(),
+        ));
+    }
+    config.wasm_simd(features.simd);
+    config.wasm_relaxed_simd(features.simd && features.relaxed_simd);
+    config.wasm_bulk_memory(features.bulk_memory);
+    config.wasm_tail_call(features.tail_call);
+    config.wasm_multi_memory(features.multi_memory);
+    // GC objects are allocated from the guest heap. Whether the proposal is
+    // supported is fixed when the runtime is built, and must match the aot
+    // compiler's `gc` feature.
+    #[cfg(feature = "gc")]
+    config.wasm_function_references(true).wasm_gc(true);
+
+    // Like NaN canonicalization, this must match the setting modules were
+    // precompiled with, which deserialization checks
+    config.epoch_interruption(runtime_config.epoch_interruption);
+    config.relaxed_simd_deterministic(runtime_config.deterministic_relaxed_simd);
+
+    // There is no compiler in the guest, so NaN canonicalization happens when
+    // modules are precompiled. The setting is recorded in the artifact's
+    // version string, which makes deserialization fail on a mismatch.
+    if runtime_config.canonicalize_nans {
+        config
+            .module_version(ModuleVersionStrategy::Custom(alloc::format!(
+                "{}{}",
+                WASMTIME_VERSION,
+                NAN_CANONICALIZATION_VERSION_SUFFIX
+            )))
+            .map_err(map_wasmtime_error)?;
+    }
+
+    Engine::new(&config).map_err(map_wasmtime_error)
+}
@@ -0,0 +1,163 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Deadlines for guest calls, enforced with wasmtime's epoch
+//! interruption so that a call that runs past its deadline traps at the
+//! next function entry or loop back-edge rather than being killed.
+//!
+//! Nothing increments the engine's epoch while wasm runs in the guest,
+//! so while a deadline is armed the store's epoch deadline is kept
+//! reached and the deadline callback, run at every check, compares the
+//! time stamp counter against the deadline instead.
+
+use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use hyperlight_common::flatbuffer_wrappers::function_call::FunctionCall;
+use hyperlight_common::flatbuffer_wrappers::function_types::{
+    ParameterType, ParameterValue, ReturnType,
+};
+use hyperlight_common::flatbuffer_wrappers::guest_error::ErrorCode;
+use hyperlight_common::flatbuffer_wrappers::util::get_flatbuffer_result;
+use hyperlight_guest::error::{HyperlightGuestError, Result};
+use hyperlight_guest_bin::guest_function::definition::GuestFunctionDefinition;
+use hyperlight_guest_bin::guest_function::register::register_function;
+use wasmtime::{Store, UpdateDeadline};
+
+use crate::runtime_config::{RuntimeConfig, EPOCH_DEADLINE_EXCEEDED};
+use crate::{guest_abort, guest_trap, host_error, map_wasmtime_error, wasm_limits};
+
+// Set by init_wasm_runtime from the runtime config
+static ENABLED: AtomicBool = AtomicBool::new(false);
+// How long each guest call may run for in time stamp counter ticks, or
+// 0 if calls have no deadline
+static BUDGET: AtomicU64 = AtomicU64::new(0);
+// The time stamp counter value at which the current guest call's
+// deadline passes
+static DEADLINE: AtomicU64 = AtomicU64::new(0);
+// Whether the current guest call has a deadline
+static ARMED: AtomicBool = AtomicBool::new(false);
+// Whether the current guest call was stopped at its deadline
+static EXCEEDED: AtomicBool = AtomicBool::new(false);
+
+// An epoch deadline that is never reached, since the epoch is never
+// incremented
+const NEVER: u64 = u64::MAX;
+
+pub(crate) fn configure(runtime_config: &RuntimeConfig) {
+    ENABLED.store(runtime_config.epoch_interruption, Ordering::Relaxed);
+}
+
+fn read_tsc() -> u64 {
+    // SAFETY: rdtsc has no side effects
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+/// Install the deadline callback on a newly created store. Stores start
+/// with a reached epoch deadline, which would trap the first wasm code
+/// they run.
+pub(crate) fn configure_store<T: 'static>(store: &mut Store<T>) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    store.epoch_deadline_callback(|_| {
+        if !ARMED.load(Ordering::Relaxed) {
+            return Ok(UpdateDeadline::Continue(NEVER));
+        }
+        if read_tsc() < DEADLINE.load(Ordering::Relaxed) {
+            return Ok(UpdateDeadline::Continue(0));
+        }
+        ARMED.store(false, Ordering::Relaxed);
+        EXCEEDED.store(true, Ordering::Relaxed);
+        Err(wasmtime::Error::msg(EPOCH_DEADLINE_EXCEEDED))
+    });
+    store.set_epoch_deadline(NEVER);
+}
+
+/// Arm the deadline of a guest call about to run in `store`, if calls
+/// have one
+pub(crate) fn begin_call<T>(store: &mut Store<T>) {
+    EXCEEDED.store(false, Ordering::Relaxed);
+    let budget = BUDGET.load(Ordering::Relaxed);
+    if !ENABLED.load(Ordering::Relaxed) || budget == 0 {
+        return;
+    }
+    DEADLINE.store(read_tsc().saturating_add(budget), Ordering::Relaxed);
+    ARMED.store(true, Ordering::Relaxed);
+    store.set_epoch_deadline(0);
+}
+
+/// Disarm the deadline of the guest call started by [`begin_call`]. The
+/// next epoch check then pushes the store's epoch deadline back out.
+pub(crate) fn end_call() {
+    ARMED.store(false, Ordering::Relaxed);
+}
+
+/// End the guest call started by [`begin_call`], which failed with
+/// `error`
+pub(crate) fn map_call_error(error: wasmtime::Error) -> HyperlightGuestError {
+    end_call();
+    if EXCEEDED.load(Ordering::Relaxed) {
+        return HyperlightGuestError::new(
+            ErrorCode::GuestError,
+            EPOCH_DEADLINE_EXCEEDED.to_string(),
+        );
+    }
+    if let Some(error) = wasm_limits::call_error() {
+        return error;
+    }
+    // A host function error unwinds the guest, which cannot handle it
+    if let Some(error) = host_error::call_error() {
+        return error;
+    }
+    if let Some(error) = guest_abort::call_error(&error) {
+        return error;
+    }
+    if let Some(error) = guest_trap::call_error(&error) {
+        return error;
+    }
+    map_wasmtime_error(error)
+}
+
+/// Set how long each guest call may run for, in time stamp counter
+/// ticks, or remove the deadline if it is 0
+fn set_epoch_deadline(function_call: FunctionCall) -> Result<Vec<u8>> {
+    let Some([ParameterValue::ULong(ticks)]) = function_call.parameters.as_deref() else {
+        return Err(HyperlightGuestError::new(
+            ErrorCode::GuestFunctionParameterTypeMismatch,
+            "Invalid parameters passed to SetEpochDeadline".to_string(),
+        ));
+    };
+    if !ENABLED.load(Ordering::Relaxed) {
+        return Err(HyperlightGuestError::new(
+            ErrorCode::GuestError,
+            "epoch interruption is not enabled in this sandbox".to_string(),
+        ));
+    }
+    BUDGET.store(*ticks, Ordering::Relaxed);
+    Ok(get_flatbuffer_result::<()>(()))
+}
+
+pub(crate) fn register_functions() {
+    register_function(GuestFunctionDefinition::new(
+        "SetEpochDeadline".to_string(),
+        vec![ParameterType::ULong],
+        ReturnType::Void,
+        set_epoch_deadline,
+    ));
+}
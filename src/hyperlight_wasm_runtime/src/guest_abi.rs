@@ -0,0 +1,62 @@
+/*
+Copyright 2025 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The encodings the guest ABIs other than C use for strings and
+//! buffers, see the `marshal` module of the runtime.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// The id the host passes to `SetGuestAbi` for modules built with C or
+/// Rust toolchains
+pub const C: i32 = 0;
+
+/// The id the host passes to `SetGuestAbi` for modules built with
+/// AssemblyScript
+pub const ASSEMBLY_SCRIPT: i32 = 1;
+
+/// The id the host passes to `SetGuestAbi` for modules built with TinyGo
+pub const TINYGO: i32 = 2;
+
+/// Encode `s` as the UTF-16 payload of an AssemblyScript `String`
+pub fn encode_utf16(s: &str) -> Vec<u8> {
+    s.encode_utf16().flat_map(u16::to_le_bytes).collect()
+}
+
+/// Decode the UTF-16 payload of an AssemblyScript `String`, or return
+/// `None` if it has an odd length or holds an unpaired surrogate
+pub fn decode_utf16(bytes: &[u8]) -> Option<String> {
+    if !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+    let units = bytes
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]));
+    char::decode_utf16(units)
+        .collect::<Result<String, _>>()
+        .ok()
+}
+
+/// Pack a wasm32 pointer and length into the `i64` TinyGo guests return
+/// strings and buffers as, with the pointer in the lower half
+pub fn pack_ptr_len(addr: i32, len: u32) -> i64 {
+    ((len as i64) << 32) | (addr as u32 as i64)
+}
+
+/// Unpack an `i64` produced by [`pack_ptr_len`]
+pub fn unpack_ptr_len(packed: i64) -> (i32, u32) {
+    (packed as u32 as i32, (packed as u64 >> 32) as u32)
+}
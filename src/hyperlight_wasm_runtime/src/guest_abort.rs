@@ -0,0 +1,89 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Guest calls that ended because the guest aborted, by calling the WASI
+//! `proc_exit` or `wasi:cli/exit` functions or, as Rust guests do when
+//! they panic, by trapping on `unreachable` after writing a message to
+//! stderr. The end of what the guest wrote to stderr during the call is
+//! kept, whether or not the host captures stderr, so that the message is
+//! passed to the host along with the exit code.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+
+use hyperlight_common::flatbuffer_wrappers::guest_error::ErrorCode;
+use hyperlight_guest::error::HyperlightGuestError;
+use spin::Mutex;
+use wasmtime::Trap;
+
+use crate::runtime_config::GUEST_ABORTED;
+
+// How much of the guest's stderr output is kept for the message
+const MAX_MESSAGE_LEN: usize = 4096;
+
+// The end of what the guest wrote to stderr during the current call
+static STDERR_TAIL: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+// Whether the guest exited during the current call, and its exit code
+static EXITED: AtomicBool = AtomicBool::new(false);
+static EXIT_CODE: AtomicI32 = AtomicI32::new(0);
+
+/// Forget the output and exit code of the previous guest call
+pub(crate) fn begin_call() {
+    STDERR_TAIL.lock().clear();
+    EXITED.store(false, Ordering::Relaxed);
+}
+
+/// Keep `bytes`, written by the guest to stderr, in case it aborts
+pub(crate) fn record_stderr(bytes: &[u8]) {
+    let mut tail = STDERR_TAIL.lock();
+    tail.extend_from_slice(bytes);
+    let excess = tail.len().saturating_sub(MAX_MESSAGE_LEN);
+    tail.drain(..excess);
+}
+
+/// Record that the guest exited with `code`, returning the error that
+/// stops the guest call
+pub(crate) fn exit(code: i32) -> wasmtime::Error {
+    EXIT_CODE.store(code, Ordering::Relaxed);
+    EXITED.store(true, Ordering::Relaxed);
+    wasmtime::Error::msg(format!("guest exited with code {}", code))
+}
+
+/// The error to return for a guest call that failed with `error`, or
+/// `None` if the guest did not abort
+pub(crate) fn call_error(error: &wasmtime::Error) -> Option<HyperlightGuestError> {
+    let exit_code = if EXITED.load(Ordering::Relaxed) {
+        Some(EXIT_CODE.load(Ordering::Relaxed))
+    } else {
+        None
+    };
+    let tail = STDERR_TAIL.lock();
+    let panicked = matches!(
+        error.downcast_ref::<Trap>(),
+        Some(Trap::UnreachableCodeReached)
+    ) && !tail.is_empty();
+    if exit_code.is_none() && !panicked {
+        return None;
+    }
+    let message = String::from_utf8_lossy(&tail);
+    let message = match exit_code {
+        Some(code) => format!("{}{}\n{}", GUEST_ABORTED, code, message.trim_end()),
+        None => format!("{}-\n{}", GUEST_ABORTED, message.trim_end()),
+    };
+    Some(HyperlightGuestError::new(ErrorCode::GuestError, message))
+}
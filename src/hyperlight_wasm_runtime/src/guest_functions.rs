@@ -0,0 +1,104 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The functions exported by the loaded module or component, listed in
+//! the guest and fetched by the host with the `GetGuestFunctions` guest
+//! function.
+//!
+//! The list is encoded as a little-endian `u32` count of functions, each
+//! encoded as its name, a little-endian `u32` count of parameter types
+//! followed by the types, and a little-endian `u32` count of result
+//! types followed by the types. Each string is encoded as a
+//! little-endian `u32` length followed by its UTF-8 bytes.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// A function exported by the loaded module or component
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GuestFunction {
+    /// The name of the function. Functions that components export from
+    /// an interface are named `interface#function`.
+    pub name: String,
+    /// The types of the function's parameters: core wasm types such as
+    /// `i32` for modules, and WIT types such as `list<u8>` for
+    /// components
+    pub params: Vec<String>,
+    /// The types of the function's results, in the same form as
+    /// [`params`](Self::params)
+    pub results: Vec<String>,
+}
+
+fn push_str(bytes: &mut Vec<u8>, s: &str) {
+    bytes.extend_from_slice(&(s.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(s.as_bytes());
+}
+
+fn push_strs(bytes: &mut Vec<u8>, strs: &[String]) {
+    bytes.extend_from_slice(&(strs.len() as u32).to_le_bytes());
+    for s in strs {
+        push_str(bytes, s);
+    }
+}
+
+fn take_u32(bytes: &mut &[u8]) -> Option<u32> {
+    let (value, rest) = bytes.split_first_chunk::<4>()?;
+    *bytes = rest;
+    Some(u32::from_le_bytes(*value))
+}
+
+fn take_str(bytes: &mut &[u8]) -> Option<String> {
+    let len = take_u32(bytes)? as usize;
+    if bytes.len() < len {
+        return None;
+    }
+    let (s, rest) = bytes.split_at(len);
+    *bytes = rest;
+    String::from_utf8(s.to_vec()).ok()
+}
+
+fn take_strs(bytes: &mut &[u8]) -> Option<Vec<String>> {
+    let count = take_u32(bytes)?;
+    (0..count).map(|_| take_str(bytes)).collect()
+}
+
+/// Encode `functions` to be passed to the host
+pub fn to_bytes(functions: &[GuestFunction]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&(functions.len() as u32).to_le_bytes());
+    for function in functions {
+        push_str(&mut bytes, &function.name);
+        push_strs(&mut bytes, &function.params);
+        push_strs(&mut bytes, &function.results);
+    }
+    bytes
+}
+
+/// Decode functions encoded with [`to_bytes`], returning `None` if the
+/// encoding is malformed
+pub fn from_bytes(mut bytes: &[u8]) -> Option<Vec<GuestFunction>> {
+    let count = take_u32(&mut bytes)?;
+    let functions = (0..count)
+        .map(|_| {
+            Some(GuestFunction {
+                name: take_str(&mut bytes)?,
+                params: take_strs(&mut bytes)?,
+                results: take_strs(&mut bytes)?,
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    bytes.is_empty().then_some(functions)
+}
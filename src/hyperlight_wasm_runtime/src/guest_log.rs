@@ -0,0 +1,196 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Log records buffered in the guest and drained by the host after each
+//! guest call, so that logging does not cost a VM exit per record.
+//!
+//! Wasm modules write records with the `log` function imported from the
+//! `hyperlight` module:
+//!
+//! ```text
+//! (import "hyperlight" "log" (func (param $level i32) (param $ptr i32) (param $len i32)))
+//! ```
+//!
+//! where `level` is 1 (error) to 5 (trace), matching `log::Level`, and
+//! `ptr`/`len` describe a UTF-8 message in the module's memory.
+//!
+//! Structured records, with a target and key-value fields, are written
+//! with the `log_record` function:
+//!
+//! ```text
+//! (import "hyperlight" "log_record" (func (param $level i32)
+//!     (param $target_ptr i32) (param $target_len i32)
+//!     (param $message_ptr i32) (param $message_len i32)
+//!     (param $fields_ptr i32) (param $fields_len i32)))
+//! ```
+//!
+//! where the fields are UTF-8 keys and values, each followed by a NUL
+//! byte. Components write them with the `hlwasm:log` interface:
+//!
+//! ```text
+//! package hlwasm:log;
+//!
+//! interface log {
+//!     log: func(level: u8, target: string, message: string,
+//!               fields: list<tuple<string, string>>);
+//! }
+//! ```
+//!
+//! The buffer is drained with the `DrainLogBuffer` guest function, which
+//! returns the records encoded as a little-endian `u32` count of records
+//! dropped because the buffer was full, followed by each record as a
+//! one byte level, the target, the message, a little-endian `u32` count
+//! of fields and each field's key and value. Strings are encoded as a
+//! little-endian `u32` length and their bytes.
+
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// The maximum number of bytes of targets, messages and fields held in
+/// the guest. When a new record does not fit, the oldest records are
+/// dropped.
+pub const LOG_BUFFER_CAPACITY: usize = 64 * 1024;
+
+/// A log record written by the guest
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GuestLogRecord {
+    /// The severity of the record, from 1 (error) to 5 (trace)
+    pub level: u8,
+    /// The part of the guest the record comes from, empty if the guest
+    /// did not give one
+    pub target: String,
+    /// The message
+    pub message: String,
+    /// The key-value fields of the record, in the order the guest gave
+    /// them
+    pub fields: Vec<(String, String)>,
+}
+
+impl GuestLogRecord {
+    /// The number of bytes the record takes up in the buffer
+    fn size(&self) -> usize {
+        self.target.len()
+            + self.message.len()
+            + self
+                .fields
+                .iter()
+                .map(|(key, value)| key.len() + value.len())
+                .sum::<usize>()
+    }
+}
+
+/// Log records buffered in the guest
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GuestLogs {
+    /// The number of records dropped because the buffer was full
+    pub dropped: u32,
+    /// The buffered records, oldest first
+    pub records: VecDeque<GuestLogRecord>,
+    // The total size of the buffered records
+    size: usize,
+}
+
+impl GuestLogs {
+    /// Create an empty buffer
+    pub const fn new() -> Self {
+        Self {
+            dropped: 0,
+            records: VecDeque::new(),
+            size: 0,
+        }
+    }
+
+    /// Add a record, dropping the oldest records if the records would
+    /// exceed [`LOG_BUFFER_CAPACITY`]
+    pub fn push(&mut self, record: GuestLogRecord) {
+        let size = record.size();
+        if size > LOG_BUFFER_CAPACITY {
+            self.dropped = self.dropped.saturating_add(1);
+            return;
+        }
+        while self.size + size > LOG_BUFFER_CAPACITY {
+            let Some(oldest) = self.records.pop_front() else {
+                break;
+            };
+            self.size -= oldest.size();
+            self.dropped = self.dropped.saturating_add(1);
+        }
+        self.size += size;
+        self.records.push_back(record);
+    }
+
+    /// Encode the records to be passed to the host
+    pub fn to_bytes(&self) -> Vec<u8> {
+        fn push_str(bytes: &mut Vec<u8>, s: &str) {
+            bytes.extend_from_slice(&(s.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(s.as_bytes());
+        }
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&self.dropped.to_le_bytes());
+        for record in &self.records {
+            bytes.push(record.level);
+            push_str(&mut bytes, &record.target);
+            push_str(&mut bytes, &record.message);
+            bytes.extend_from_slice(&(record.fields.len() as u32).to_le_bytes());
+            for (key, value) in &record.fields {
+                push_str(&mut bytes, key);
+                push_str(&mut bytes, value);
+            }
+        }
+        bytes
+    }
+
+    /// Decode records encoded with [`to_bytes`](Self::to_bytes), returning
+    /// `None` if the encoding is malformed
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        fn take_u32(bytes: &mut &[u8]) -> Option<u32> {
+            let (value, rest) = bytes.split_first_chunk::<4>()?;
+            *bytes = rest;
+            Some(u32::from_le_bytes(*value))
+        }
+        fn take_str(bytes: &mut &[u8]) -> Option<String> {
+            let len = take_u32(bytes)? as usize;
+            if bytes.len() < len {
+                return None;
+            }
+            let (s, rest) = bytes.split_at(len);
+            *bytes = rest;
+            Some(String::from_utf8_lossy(s).into_owned())
+        }
+        let mut bytes = bytes;
+        let mut logs = GuestLogs::new();
+        logs.dropped = take_u32(&mut bytes)?;
+        while let Some((&level, rest)) = bytes.split_first() {
+            bytes = rest;
+            let target = take_str(&mut bytes)?;
+            let message = take_str(&mut bytes)?;
+            let mut fields = Vec::new();
+            for _ in 0..take_u32(&mut bytes)? {
+                fields.push((take_str(&mut bytes)?, take_str(&mut bytes)?));
+            }
+            let record = GuestLogRecord {
+                level,
+                target,
+                message,
+                fields,
+            };
+            logs.size += record.size();
+            logs.records.push_back(record);
+        }
+        Some(logs)
+    }
+}
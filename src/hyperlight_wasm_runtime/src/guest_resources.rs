@@ -0,0 +1,106 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The resources exported by the loaded component that have been given
+//! to the host. The host refers to each by a handle, its index in a
+//! table of the `ResourceAny`s wasmtime returned for them, which the
+//! functions generated by `wasm_guest_bindgen!` translate to and from.
+
+use alloc::format;
+use alloc::vec::Vec;
+
+use hyperlight_common::flatbuffer_wrappers::guest_error::ErrorCode;
+use hyperlight_guest::error::{HyperlightGuestError, Result};
+use spin::Mutex;
+use wasmtime::component::ResourceAny;
+use wasmtime::Store;
+
+use crate::map_wasmtime_error;
+
+static RESOURCES: Mutex<Vec<Option<ResourceAny>>> = Mutex::new(Vec::new());
+
+/// Forget the resources of the previously loaded component
+pub(crate) fn clear() {
+    RESOURCES.lock().clear();
+}
+
+/// Add `resource` to the table, returning its handle encoded as the
+/// host expects it
+pub(crate) fn insert(resource: ResourceAny) -> Vec<u8> {
+    let mut resources = RESOURCES.lock();
+    let handle = match resources.iter().position(Option::is_none) {
+        Some(free) => {
+            resources[free] = Some(resource);
+            free
+        }
+        None => {
+            resources.push(Some(resource));
+            resources.len() - 1
+        }
+    };
+    Vec::from(u32::to_ne_bytes(handle as u32))
+}
+
+/// The resource whose handle the host passed in `bytes`, which stays in
+/// the table, for a parameter that borrows it
+pub(crate) fn get(bytes: &[u8]) -> Result<ResourceAny> {
+    let handle = decode_handle(bytes)?;
+    RESOURCES
+        .lock()
+        .get(handle)
+        .copied()
+        .flatten()
+        .ok_or_else(|| invalid_handle(handle))
+}
+
+/// Remove the resource whose handle the host passed in `bytes` from the
+/// table, for a parameter that takes ownership of it
+pub(crate) fn take(bytes: &[u8]) -> Result<ResourceAny> {
+    let handle = decode_handle(bytes)?;
+    RESOURCES
+        .lock()
+        .get_mut(handle)
+        .and_then(Option::take)
+        .ok_or_else(|| invalid_handle(handle))
+}
+
+/// Drop the resource whose handle the host passed in `bytes`, running
+/// its destructor in the component
+pub(crate) fn drop(store: &mut Store<()>, bytes: &[u8]) -> Result<()> {
+    take(bytes)?
+        .resource_drop(store)
+        .map_err(map_wasmtime_error)
+}
+
+fn decode_handle(bytes: &[u8]) -> Result<usize> {
+    let handle: [u8; 4] = bytes
+        .get(0..4)
+        .and_then(|b| b.try_into().ok())
+        .ok_or_else(|| {
+            HyperlightGuestError::new(
+                ErrorCode::GuestError,
+                format!("malformed resource handle of {} bytes", bytes.len()),
+            )
+        })?;
+    Ok(u32::from_ne_bytes(handle) as usize)
+}
+
+fn invalid_handle(handle: usize) -> HyperlightGuestError {
+    HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        format!("no resource with handle {}", handle),
+    )
+}
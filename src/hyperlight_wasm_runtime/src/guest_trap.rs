@@ -0,0 +1,46 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The errors of guest calls that trapped, which carry the trap code and
+//! wasm backtrace to the host so that it can tell, for example, a stack
+//! overflow from an out of bounds memory access.
+
+use alloc::format;
+use core::fmt::Write;
+
+use hyperlight_common::flatbuffer_wrappers::guest_error::ErrorCode;
+use hyperlight_guest::error::HyperlightGuestError;
+use wasmtime::{Trap, WasmBacktrace};
+
+use crate::runtime_config::WASM_TRAP;
+
+/// The error to return for a guest call that failed with `error`, or
+/// `None` if it did not trap
+pub(crate) fn call_error(error: &wasmtime::Error) -> Option<HyperlightGuestError> {
+    let trap = error.downcast_ref::<Trap>()?;
+    let mut message = format!("{}{:?}", WASM_TRAP, trap);
+    if let Some(backtrace) = error.downcast_ref::<WasmBacktrace>() {
+        for frame in backtrace.frames() {
+            let _ = write!(message, "\n{} ", frame.func_index());
+            let _ = match frame.module_offset() {
+                Some(offset) => write!(message, "{}", offset),
+                None => write!(message, "-"),
+            };
+            let _ = write!(message, " {}", frame.func_name().unwrap_or_default());
+        }
+    }
+    Some(HyperlightGuestError::new(ErrorCode::GuestError, message))
+}
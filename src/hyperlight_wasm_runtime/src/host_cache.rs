@@ -0,0 +1,142 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use hyperlight_common::flatbuffer_wrappers::function_call::FunctionCall;
+use hyperlight_common::flatbuffer_wrappers::function_types::{
+    ParameterType, ParameterValue, ReturnType, ReturnValue,
+};
+use hyperlight_common::flatbuffer_wrappers::guest_error::ErrorCode;
+use hyperlight_common::flatbuffer_wrappers::util::get_flatbuffer_result;
+use hyperlight_guest::error::{HyperlightGuestError, Result};
+use hyperlight_guest_bin::guest_function::definition::GuestFunctionDefinition;
+use hyperlight_guest_bin::guest_function::register::register_function;
+use spin::Mutex;
+use tracing::instrument;
+
+use crate::host_function_cache;
+
+struct FunctionCache {
+    max_entries: usize,
+    // Oldest first. Caches are expected to be small, so they are searched
+    // linearly.
+    entries: VecDeque<(Vec<ParameterValue>, ReturnValue)>,
+}
+
+// Caches for the host functions the host asked to cache, by name
+static CACHES: Mutex<BTreeMap<String, FunctionCache>> = Mutex::new(BTreeMap::new());
+
+/// Return the result of calling host function `name` with `params`, from
+/// the cache if the function is cached and was called with the same
+/// parameters before, and otherwise by calling `call`. Errors from
+/// `call` are not cached.
+pub(crate) fn get_or_call(
+    name: &str,
+    params: Vec<ParameterValue>,
+    call: impl FnOnce(Vec<ParameterValue>) -> Result<ReturnValue>,
+) -> Result<ReturnValue> {
+    {
+        let caches = CACHES.lock();
+        let Some(cache) = caches.get(name) else {
+            drop(caches);
+            return call(params);
+        };
+        if let Some((_, rv)) = cache.entries.iter().find(|(p, _)| *p == params) {
+            return Ok(rv.clone());
+        }
+    }
+    let rv = call(params.clone())?;
+    let mut caches = CACHES.lock();
+    if let Some(cache) = caches.get_mut(name) {
+        if cache.max_entries > 0 {
+            while cache.entries.len() >= cache.max_entries {
+                cache.entries.pop_front();
+            }
+            cache.entries.push_back((params, rv.clone()));
+        }
+    }
+    Ok(rv)
+}
+
+fn vec_bytes_param<'a>(function_call: &'a FunctionCall, name: &str) -> Result<&'a [u8]> {
+    match function_call.parameters.as_deref() {
+        Some([ParameterValue::VecBytes(bytes)]) => Ok(bytes),
+        _ => Err(HyperlightGuestError::new(
+            ErrorCode::GuestFunctionParameterTypeMismatch,
+            alloc::format!("Invalid parameters passed to {}", name),
+        )),
+    }
+}
+
+#[instrument(skip_all, level = "Info")]
+/// Set the host functions whose results are cached, see
+/// [`crate::host_function_cache`]
+fn configure_host_function_cache(function_call: FunctionCall) -> Result<Vec<u8>> {
+    let bytes = vec_bytes_param(&function_call, "ConfigureHostFunctionCache")?;
+    let functions = host_function_cache::from_bytes(bytes).ok_or_else(|| {
+        HyperlightGuestError::new(
+            ErrorCode::GuestError,
+            "ConfigureHostFunctionCache: malformed cached host functions".to_string(),
+        )
+    })?;
+    let mut caches = CACHES.lock();
+    caches.clear();
+    for function in functions {
+        caches.insert(
+            function.name,
+            FunctionCache {
+                max_entries: usize::try_from(function.max_entries).unwrap_or(usize::MAX),
+                entries: VecDeque::new(),
+            },
+        );
+    }
+    Ok(get_flatbuffer_result::<i32>(0))
+}
+
+#[instrument(skip_all, level = "Info")]
+/// Discard the cached results of the named host functions
+fn flush_host_function_cache(function_call: FunctionCall) -> Result<Vec<u8>> {
+    let names = vec_bytes_param(&function_call, "FlushHostFunctionCache")?;
+    let mut caches = CACHES.lock();
+    for name in names.split(|b| *b == 0) {
+        let Ok(name) = core::str::from_utf8(name) else {
+            continue;
+        };
+        if let Some(cache) = caches.get_mut(name) {
+            cache.entries.clear();
+        }
+    }
+    Ok(get_flatbuffer_result::<i32>(0))
+}
+
+pub(crate) fn register_functions() {
+    register_function(GuestFunctionDefinition::new(
+        "ConfigureHostFunctionCache".to_string(),
+        vec![ParameterType::VecBytes],
+        ReturnType::Int,
+        configure_host_function_cache,
+    ));
+    register_function(GuestFunctionDefinition::new(
+        "FlushHostFunctionCache".to_string(),
+        vec![ParameterType::VecBytes],
+        ReturnType::Int,
+        flush_host_function_cache,
+    ));
+}
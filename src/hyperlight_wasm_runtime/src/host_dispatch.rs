@@ -0,0 +1,205 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Host functions registered from a manifest have no compile-time
+//! signature on the host, so the guest calls them all through the
+//! [`HOST_DISPATCH_FUNCTION`] host function, passing the name of the
+//! function and its encoded parameters and receiving its encoded result.
+//!
+//! The guest learns which functions to dispatch from the
+//! `ConfigureHostDispatch` guest function, which takes their names
+//! separated by NUL bytes.
+//!
+//! Each value is encoded as a one byte tag followed by its payload:
+//! fixed-size numbers in little-endian order, booleans as one byte, and
+//! strings and buffers as a little-endian `u32` length followed by their
+//! bytes.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use hyperlight_common::flatbuffer_wrappers::function_types::{ParameterValue, ReturnValue};
+
+/// The name of the host function the guest calls to call a host function
+/// registered from a manifest. It takes the function's name as a
+/// `String` and its parameters encoded with [`params_to_bytes`] as a
+/// `VecBytes`, and returns the result encoded with
+/// [`return_value_to_bytes`] as a `VecBytes`.
+pub const HOST_DISPATCH_FUNCTION: &str = "HostDispatch";
+
+const TAG_INT: u8 = 0;
+const TAG_UINT: u8 = 1;
+const TAG_LONG: u8 = 2;
+const TAG_ULONG: u8 = 3;
+const TAG_FLOAT: u8 = 4;
+const TAG_DOUBLE: u8 = 5;
+const TAG_STRING: u8 = 6;
+const TAG_BOOL: u8 = 7;
+const TAG_VEC_BYTES: u8 = 8;
+const TAG_VOID: u8 = 9;
+
+fn push_bytes(bytes: &mut Vec<u8>, tag: u8, value: &[u8]) {
+    bytes.push(tag);
+    bytes.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(value);
+}
+
+fn push_param(bytes: &mut Vec<u8>, value: &ParameterValue) {
+    match value {
+        ParameterValue::Int(v) => {
+            bytes.push(TAG_INT);
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        ParameterValue::UInt(v) => {
+            bytes.push(TAG_UINT);
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        ParameterValue::Long(v) => {
+            bytes.push(TAG_LONG);
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        ParameterValue::ULong(v) => {
+            bytes.push(TAG_ULONG);
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        ParameterValue::Float(v) => {
+            bytes.push(TAG_FLOAT);
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        ParameterValue::Double(v) => {
+            bytes.push(TAG_DOUBLE);
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+        ParameterValue::String(v) => push_bytes(bytes, TAG_STRING, v.as_bytes()),
+        ParameterValue::Bool(v) => {
+            bytes.push(TAG_BOOL);
+            bytes.push(*v as u8);
+        }
+        ParameterValue::VecBytes(v) => push_bytes(bytes, TAG_VEC_BYTES, v),
+    }
+}
+
+// Decode one value, returning it as a parameter, or `None` for a void
+// return value, along with the rest of the bytes
+fn split_value(bytes: &[u8]) -> Option<(Option<ParameterValue>, &[u8])> {
+    let (&tag, rest) = bytes.split_first()?;
+    let (value, rest) = match tag {
+        TAG_INT => {
+            let (v, rest) = rest.split_first_chunk()?;
+            (ParameterValue::Int(i32::from_le_bytes(*v)), rest)
+        }
+        TAG_UINT => {
+            let (v, rest) = rest.split_first_chunk()?;
+            (ParameterValue::UInt(u32::from_le_bytes(*v)), rest)
+        }
+        TAG_LONG => {
+            let (v, rest) = rest.split_first_chunk()?;
+            (ParameterValue::Long(i64::from_le_bytes(*v)), rest)
+        }
+        TAG_ULONG => {
+            let (v, rest) = rest.split_first_chunk()?;
+            (ParameterValue::ULong(u64::from_le_bytes(*v)), rest)
+        }
+        TAG_FLOAT => {
+            let (v, rest) = rest.split_first_chunk()?;
+            (ParameterValue::Float(f32::from_le_bytes(*v)), rest)
+        }
+        TAG_DOUBLE => {
+            let (v, rest) = rest.split_first_chunk()?;
+            (ParameterValue::Double(f64::from_le_bytes(*v)), rest)
+        }
+        TAG_BOOL => {
+            let (&v, rest) = rest.split_first()?;
+            (ParameterValue::Bool(v != 0), rest)
+        }
+        TAG_STRING | TAG_VEC_BYTES => {
+            let (len, rest) = rest.split_first_chunk::<4>()?;
+            let len = u32::from_le_bytes(*len) as usize;
+            if rest.len() < len {
+                return None;
+            }
+            let (value, rest) = rest.split_at(len);
+            let value = if tag == TAG_STRING {
+                ParameterValue::String(String::from_utf8(value.to_vec()).ok()?)
+            } else {
+                ParameterValue::VecBytes(value.to_vec())
+            };
+            (value, rest)
+        }
+        TAG_VOID => return Some((None, rest)),
+        _ => return None,
+    };
+    Some((Some(value), rest))
+}
+
+/// Encode the parameters of a call to a dispatched host function
+pub fn params_to_bytes(params: &[ParameterValue]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for param in params {
+        push_param(&mut bytes, param);
+    }
+    bytes
+}
+
+/// Decode parameters encoded with [`params_to_bytes`], returning `None`
+/// if the encoding is malformed
+pub fn params_from_bytes(mut bytes: &[u8]) -> Option<Vec<ParameterValue>> {
+    let mut params = Vec::new();
+    while !bytes.is_empty() {
+        let (param, rest) = split_value(bytes)?;
+        params.push(param?);
+        bytes = rest;
+    }
+    Some(params)
+}
+
+/// Encode the result of a dispatched host function
+pub fn return_value_to_bytes(value: &ReturnValue) -> Vec<u8> {
+    let param = match value {
+        ReturnValue::Int(v) => ParameterValue::Int(*v),
+        ReturnValue::UInt(v) => ParameterValue::UInt(*v),
+        ReturnValue::Long(v) => ParameterValue::Long(*v),
+        ReturnValue::ULong(v) => ParameterValue::ULong(*v),
+        ReturnValue::Float(v) => ParameterValue::Float(*v),
+        ReturnValue::Double(v) => ParameterValue::Double(*v),
+        ReturnValue::String(v) => ParameterValue::String(v.clone()),
+        ReturnValue::Bool(v) => ParameterValue::Bool(*v),
+        ReturnValue::VecBytes(v) => ParameterValue::VecBytes(v.clone()),
+        ReturnValue::Void(()) => return alloc::vec![TAG_VOID],
+    };
+    params_to_bytes(&[param])
+}
+
+/// Decode a result encoded with [`return_value_to_bytes`], returning
+/// `None` if the encoding is malformed
+pub fn return_value_from_bytes(bytes: &[u8]) -> Option<ReturnValue> {
+    let (value, rest) = split_value(bytes)?;
+    if !rest.is_empty() {
+        return None;
+    }
+    Some(match value {
+        None => ReturnValue::Void(()),
+        Some(ParameterValue::Int(v)) => ReturnValue::Int(v),
+        Some(ParameterValue::UInt(v)) => ReturnValue::UInt(v),
+        Some(ParameterValue::Long(v)) => ReturnValue::Long(v),
+        Some(ParameterValue::ULong(v)) => ReturnValue::ULong(v),
+        Some(ParameterValue::Float(v)) => ReturnValue::Float(v),
+        Some(ParameterValue::Double(v)) => ReturnValue::Double(v),
+        Some(ParameterValue::String(v)) => ReturnValue::String(v),
+        Some(ParameterValue::Bool(v)) => ReturnValue::Bool(v),
+        Some(ParameterValue::VecBytes(v)) => ReturnValue::VecBytes(v),
+    })
+}
@@ -0,0 +1,67 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Guest calls that failed because a host function imported by the guest
+//! returned an error. The error traps the guest, and the name of the host
+//! function and the message of its error are passed to the host, which
+//! can then tell the failure from a trap in the guest itself.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+
+use hyperlight_common::flatbuffer_wrappers::guest_error::ErrorCode;
+use hyperlight_guest::error::HyperlightGuestError;
+use spin::Mutex;
+
+use crate::runtime_config::HOST_FUNCTION_FAILED;
+
+// The host function that failed during the current call, and the
+// message of its error
+static FAILED: Mutex<Option<(String, String)>> = Mutex::new(None);
+
+/// Forget the host function that failed during the previous guest call
+pub(crate) fn begin_call() {
+    *FAILED.lock() = None;
+}
+
+/// Record that the host function `name` returned `error`
+pub(crate) fn record(name: &str, error: &HyperlightGuestError) {
+    *FAILED.lock() = Some((name.to_string(), error.message.clone()));
+}
+
+/// Take the host function that failed during a call back into the
+/// module from a re-entrant host function, which may handle the failure,
+/// and the message of its error
+pub(crate) fn take() -> Option<(String, String)> {
+    FAILED.lock().take()
+}
+
+/// Record that the host function `name` returned `error`, returning the
+/// error that traps the guest
+pub(crate) fn failed(name: &str, error: HyperlightGuestError) -> wasmtime::Error {
+    record(name, &error);
+    wasmtime::Error::msg(format!("host function {} failed: {}", name, error.message))
+}
+
+/// The error to return for a guest call that failed, or `None` if no
+/// host function failed during it
+pub(crate) fn call_error() -> Option<HyperlightGuestError> {
+    let (name, message) = FAILED.lock().take()?;
+    Some(HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        format!("{}{}\n{}", HOST_FUNCTION_FAILED, name, message),
+    ))
+}
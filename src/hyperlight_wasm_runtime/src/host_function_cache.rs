@@ -0,0 +1,70 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Host functions whose results are cached in the guest, chosen by the
+//! host with `ProtoWasmSandbox::register_cached` and passed to the
+//! `ConfigureHostFunctionCache` guest function.
+//!
+//! Each function is encoded as a little-endian `u32` name length, the
+//! name and a little-endian `u64` maximum number of cached results. The
+//! host expires cached results with the `FlushHostFunctionCache` guest
+//! function, which takes the names of the functions whose results should
+//! be discarded separated by NUL bytes.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// A host function whose results are cached in the guest
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CachedHostFunction {
+    /// The name the function is registered with
+    pub name: String,
+    /// The maximum number of results cached for different parameters.
+    /// When a new result does not fit, the oldest result is discarded.
+    pub max_entries: u64,
+}
+
+/// Encode `functions` to be passed to the guest
+pub fn to_bytes(functions: &[CachedHostFunction]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for function in functions {
+        bytes.extend_from_slice(&(function.name.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(function.name.as_bytes());
+        bytes.extend_from_slice(&function.max_entries.to_le_bytes());
+    }
+    bytes
+}
+
+/// Decode functions encoded with [`to_bytes`], returning `None` if the
+/// encoding is malformed
+pub fn from_bytes(mut bytes: &[u8]) -> Option<Vec<CachedHostFunction>> {
+    let mut functions = Vec::new();
+    while !bytes.is_empty() {
+        let (len, rest) = bytes.split_first_chunk::<4>()?;
+        let len = u32::from_le_bytes(*len) as usize;
+        if rest.len() < len {
+            return None;
+        }
+        let (name, rest) = rest.split_at(len);
+        let (max_entries, rest) = rest.split_first_chunk::<8>()?;
+        functions.push(CachedHostFunction {
+            name: String::from_utf8(name.to_vec()).ok()?,
+            max_entries: u64::from_le_bytes(*max_entries),
+        });
+        bytes = rest;
+    }
+    Some(functions)
+}
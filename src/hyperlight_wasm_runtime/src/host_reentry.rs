@@ -0,0 +1,209 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Host functions registered as re-entrant may call functions exported
+//! by the module calling them before they return. The guest calls them
+//! through the [`HOST_REENTER_FUNCTION`] host function, which either
+//! returns the host function's result or asks the guest to call an
+//! export and pass its result back through the same host function, until
+//! the host function returns.
+//!
+//! The guest learns which functions are re-entrant from the
+//! `ConfigureReentrantHostFunctions` guest function, which takes their
+//! names separated by NUL bytes.
+//!
+//! Messages are a one byte tag followed by values encoded as by
+//! [`crate::host_dispatch`].
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use hyperlight_common::flatbuffer_wrappers::function_types::{
+    ParameterValue, ReturnType, ReturnValue,
+};
+
+use crate::host_dispatch;
+
+/// The name of the host function the guest calls re-entrant host
+/// functions through. It takes a [`GuestMessage`] encoded with
+/// [`GuestMessage::to_bytes`] as a `VecBytes`, and returns a
+/// [`HostMessage`] encoded with [`HostMessage::to_bytes`] as a
+/// `VecBytes`.
+pub const HOST_REENTER_FUNCTION: &str = "HostReenter";
+
+const TAG_CALL: u8 = 0;
+const TAG_RETURNED: u8 = 1;
+const TAG_FAILED: u8 = 2;
+
+const TAG_RETURN: u8 = 0;
+const TAG_CALL_EXPORT: u8 = 1;
+
+/// A message from the guest to a re-entrant host function
+#[derive(Debug, Clone, PartialEq)]
+pub enum GuestMessage {
+    /// Call the re-entrant host function `name` with `params`
+    Call {
+        /// The name of the host function
+        name: String,
+        /// Its parameters
+        params: Vec<ParameterValue>,
+    },
+    /// The export the host function asked for returned this value
+    Returned(ReturnValue),
+    /// The export the host function asked for failed with this message
+    Failed(String),
+}
+
+/// A message from a re-entrant host function to the guest
+#[derive(Debug, Clone, PartialEq)]
+pub enum HostMessage {
+    /// The host function returned this value
+    Return(ReturnValue),
+    /// Call the module's export `name` with `params`, expecting a
+    /// result of type `return_type`, and pass its result back
+    CallExport {
+        /// The name of the export
+        name: String,
+        /// Its parameters
+        params: Vec<ParameterValue>,
+        /// The type of its result
+        return_type: ReturnType,
+    },
+}
+
+// A name followed by parameters, encoded as parameters
+fn call_to_bytes(tag: u8, name: &str, params: &[ParameterValue]) -> Vec<u8> {
+    let mut bytes = alloc::vec![tag];
+    bytes.extend(host_dispatch::params_to_bytes(&[ParameterValue::String(
+        name.to_string(),
+    )]));
+    bytes.extend(host_dispatch::params_to_bytes(params));
+    bytes
+}
+
+fn call_from_bytes(bytes: &[u8]) -> Option<(String, Vec<ParameterValue>)> {
+    let mut params = host_dispatch::params_from_bytes(bytes)?.into_iter();
+    match params.next()? {
+        ParameterValue::String(name) => Some((name, params.collect())),
+        _ => None,
+    }
+}
+
+fn return_type_code(ty: ReturnType) -> u8 {
+    match ty {
+        ReturnType::Int => 0,
+        ReturnType::UInt => 1,
+        ReturnType::Long => 2,
+        ReturnType::ULong => 3,
+        ReturnType::Float => 4,
+        ReturnType::Double => 5,
+        ReturnType::String => 6,
+        ReturnType::Bool => 7,
+        ReturnType::Void => 8,
+        ReturnType::VecBytes => 9,
+    }
+}
+
+fn return_type_from_code(code: u8) -> Option<ReturnType> {
+    Some(match code {
+        0 => ReturnType::Int,
+        1 => ReturnType::UInt,
+        2 => ReturnType::Long,
+        3 => ReturnType::ULong,
+        4 => ReturnType::Float,
+        5 => ReturnType::Double,
+        6 => ReturnType::String,
+        7 => ReturnType::Bool,
+        8 => ReturnType::Void,
+        9 => ReturnType::VecBytes,
+        _ => return None,
+    })
+}
+
+impl GuestMessage {
+    /// Encode the message
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            GuestMessage::Call { name, params } => call_to_bytes(TAG_CALL, name, params),
+            GuestMessage::Returned(value) => {
+                let mut bytes = alloc::vec![TAG_RETURNED];
+                bytes.extend(host_dispatch::return_value_to_bytes(value));
+                bytes
+            }
+            GuestMessage::Failed(message) => {
+                let mut bytes = alloc::vec![TAG_FAILED];
+                bytes.extend_from_slice(message.as_bytes());
+                bytes
+            }
+        }
+    }
+
+    /// Decode a message encoded with [`to_bytes`](Self::to_bytes),
+    /// returning `None` if the encoding is malformed
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let (&tag, rest) = bytes.split_first()?;
+        Some(match tag {
+            TAG_CALL => {
+                let (name, params) = call_from_bytes(rest)?;
+                GuestMessage::Call { name, params }
+            }
+            TAG_RETURNED => GuestMessage::Returned(host_dispatch::return_value_from_bytes(rest)?),
+            TAG_FAILED => GuestMessage::Failed(String::from_utf8(rest.to_vec()).ok()?),
+            _ => return None,
+        })
+    }
+}
+
+impl HostMessage {
+    /// Encode the message
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            HostMessage::Return(value) => {
+                let mut bytes = alloc::vec![TAG_RETURN];
+                bytes.extend(host_dispatch::return_value_to_bytes(value));
+                bytes
+            }
+            HostMessage::CallExport {
+                name,
+                params,
+                return_type,
+            } => {
+                let mut bytes = call_to_bytes(TAG_CALL_EXPORT, name, params);
+                bytes.insert(1, return_type_code(*return_type));
+                bytes
+            }
+        }
+    }
+
+    /// Decode a message encoded with [`to_bytes`](Self::to_bytes),
+    /// returning `None` if the encoding is malformed
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let (&tag, rest) = bytes.split_first()?;
+        Some(match tag {
+            TAG_RETURN => HostMessage::Return(host_dispatch::return_value_from_bytes(rest)?),
+            TAG_CALL_EXPORT => {
+                let (&code, rest) = rest.split_first()?;
+                let (name, params) = call_from_bytes(rest)?;
+                HostMessage::CallExport {
+                    name,
+                    params,
+                    return_type: return_type_from_code(code)?,
+                }
+            }
+            _ => return None,
+        })
+    }
+}
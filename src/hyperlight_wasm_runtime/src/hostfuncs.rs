@@ -22,10 +22,11 @@ use hyperlight_common::flatbuffer_wrappers::function_types::{
 };
 use hyperlight_common::flatbuffer_wrappers::guest_error::ErrorCode;
 use hyperlight_guest::error::{HyperlightGuestError, Result};
-use hyperlight_guest_bin::host_comm::call_host_function;
+use tracing::{info_span, instrument};
 use wasmtime::{Caller, Engine, FuncType, Val, ValType};
 
-use crate::marshal;
+use crate::marshal::GuestAbi;
+use crate::{call_tracker, dispatch, host_cache, host_error, marshal, reentry};
 
 pub(crate) type HostFunctionDefinition =
     hyperlight_common::flatbuffer_wrappers::host_function_definition::HostFunctionDefinition;
@@ -33,6 +34,7 @@ pub(crate) type HostFunctionDetails =
     hyperlight_common::flatbuffer_wrappers::host_function_details::HostFunctionDetails;
 
 pub(crate) fn hostfunc_type(d: &HostFunctionDefinition, e: &Engine) -> Result<FuncType> {
+    let abi = marshal::guest_abi();
     let mut params = Vec::new();
     let mut last_was_vec = false;
     for p in (d.parameter_types).iter().flatten() {
@@ -43,18 +45,24 @@ pub(crate) fn hostfunc_type(d: &HostFunctionDefinition, e: &Engine) -> Result<Fu
             ));
         }
 
-        params.push(match p {
+        let ty = match p {
             ParameterType::Int | ParameterType::UInt => ValType::I32,
             ParameterType::Long | ParameterType::ULong => ValType::I64,
             ParameterType::Bool => ValType::I32,
             ParameterType::Float => ValType::F32,
             ParameterType::Double => ValType::F64,
+            // TinyGo passes strings as a (ptr, len) pair
+            ParameterType::String if abi == GuestAbi::TinyGo => {
+                params.push(ValType::I32);
+                ValType::I32
+            }
             ParameterType::String => ValType::I32,
             ParameterType::VecBytes => {
                 last_was_vec = true;
                 ValType::I32
             }
-        });
+        };
+        params.push(ty);
     }
     let mut results = Vec::new();
     match d.return_type {
@@ -64,9 +72,8 @@ pub(crate) fn hostfunc_type(d: &HostFunctionDefinition, e: &Engine) -> Result<Fu
         ReturnType::Bool => results.push(ValType::I32),
         ReturnType::Float => results.push(ValType::F32),
         ReturnType::Double => results.push(ValType::F64),
+        ReturnType::String if abi == GuestAbi::TinyGo => results.push(ValType::I64),
         ReturnType::String => results.push(ValType::I32),
-        // TODO: this comment about using i64 for VecBytes doesn't seem to match with what
-        //       hl_return_to_val was doing, check if this is still correct.
         /* For compatibility with old host, we return
          * a packed i64 with a (wasm32) pointer in the lower half and
          * a length in the upper half. */
@@ -75,23 +82,35 @@ pub(crate) fn hostfunc_type(d: &HostFunctionDefinition, e: &Engine) -> Result<Fu
     Ok(FuncType::new(e, params, results))
 }
 
+#[instrument(skip_all, level = "Info", fields(function_name = %d.function_name))]
 pub(crate) fn call<T>(
     d: &HostFunctionDefinition,
     mut c: Caller<'_, T>,
     ps: &[Val],
     rs: &mut [Val],
 ) -> Result<()> {
-    let params = d
-        .parameter_types
-        .iter()
-        .flatten()
-        .scan((ps.iter(), None), |s, t| {
-            marshal::val_to_hl_param(&mut c, |c, n| c.get_export(n), s, t)
-        })
-        .collect();
-
-    let rv = call_host_function::<ReturnValue>(&d.function_name, Some(params), d.return_type)
-        .expect("Host function call failed");
+    let params = info_span!("marshal_params").in_scope(|| {
+        let mut state = (ps.iter(), None);
+        d.parameter_types
+            .iter()
+            .flatten()
+            .map(|t| marshal::val_to_hl_param(&mut c, |c, n| c.get_export(n), &mut state, t))
+            .collect::<Result<Vec<_>>>()
+    })?;
+
+    // Re-entrant host functions call back into the module, so their
+    // results are never cached
+    let rv = if reentry::is_reentrant(&d.function_name) {
+        call_tracker::record_host_call();
+        reentry::call_host(&mut c, &d.function_name, params)
+            .inspect_err(|e| host_error::record(&d.function_name, e))?
+    } else {
+        host_cache::get_or_call(&d.function_name, params, |params| {
+            call_tracker::record_host_call();
+            dispatch::call_host(&d.function_name, params, d.return_type)
+                .inspect_err(|e| host_error::record(&d.function_name, e))
+        })?
+    };
 
     assert!(
         return_type_from_val(&rv) == d.return_type,
@@ -106,7 +125,8 @@ pub(crate) fn call<T>(
         return Ok(());
     }
 
-    rs[0] = marshal::hl_return_to_val(&mut c, |c, n| c.get_export(n), rv)?;
+    rs[0] = info_span!("marshal_result")
+        .in_scope(|| marshal::hl_return_to_val(&mut c, |c, n| c.get_export(n), rv))?;
 
     Ok(())
 }
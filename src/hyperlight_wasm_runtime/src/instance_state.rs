@@ -0,0 +1,149 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The state of the loaded module's instance, which the host reads from
+//! the guest to serialize it and writes back to restore it, possibly in
+//! another process.
+//!
+//! The instance's layout, the size of its `memory` export and the values
+//! of its exported mutable globals, is fetched with the
+//! `GetInstanceLayout` guest function and reset with `ResetInstance`.
+//! The memory itself is read and written in chunks of at most
+//! [`CHUNK_SIZE`] bytes with `ReadInstanceMemory` and
+//! `WriteInstanceMemory`, so that it fits in the sandbox's input and
+//! output buffers.
+//!
+//! The layout is encoded as the little-endian `u64` size of the memory
+//! in bytes, followed by a little-endian `u32` count of globals, each
+//! encoded as its name, a one byte type tag and its little-endian value.
+//! Names are encoded as a little-endian `u32` length followed by their
+//! UTF-8 bytes.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// The guest function that returns the instance's [`InstanceLayout`]
+pub const GET_INSTANCE_LAYOUT_FUNCTION: &str = "GetInstanceLayout";
+
+/// The guest function that resizes and zeroes the instance's memory and
+/// sets its globals to an [`InstanceLayout`]
+pub const RESET_INSTANCE_FUNCTION: &str = "ResetInstance";
+
+/// The guest function that reads a chunk of the instance's memory
+pub const READ_INSTANCE_MEMORY_FUNCTION: &str = "ReadInstanceMemory";
+
+/// The guest function that writes a chunk of the instance's memory
+pub const WRITE_INSTANCE_MEMORY_FUNCTION: &str = "WriteInstanceMemory";
+
+/// The largest chunk of memory read or written by one guest call, which
+/// fits in the default input and output buffers
+pub const CHUNK_SIZE: usize = 8 * 1024;
+
+/// The value of a global exported by the module
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GlobalValue {
+    /// An `i32` global
+    I32(i32),
+    /// An `i64` global
+    I64(i64),
+    /// An `f32` global, by its bits
+    F32(u32),
+    /// An `f64` global, by its bits
+    F64(u64),
+}
+
+/// The size of the instance's memory and the values of its exported
+/// mutable globals
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InstanceLayout {
+    /// The size of the `memory` export in bytes
+    pub memory_size: u64,
+    /// The names and values of the exported mutable globals
+    pub globals: Vec<(String, GlobalValue)>,
+}
+
+fn take<const N: usize>(bytes: &mut &[u8]) -> Option<[u8; N]> {
+    let (value, rest) = bytes.split_first_chunk::<N>()?;
+    *bytes = rest;
+    Some(*value)
+}
+
+fn take_str(bytes: &mut &[u8]) -> Option<String> {
+    let len = u32::from_le_bytes(take(bytes)?) as usize;
+    if bytes.len() < len {
+        return None;
+    }
+    let (s, rest) = bytes.split_at(len);
+    *bytes = rest;
+    String::from_utf8(s.to_vec()).ok()
+}
+
+fn take_global(bytes: &mut &[u8]) -> Option<GlobalValue> {
+    let [tag] = take(bytes)?;
+    Some(match tag {
+        0 => GlobalValue::I32(i32::from_le_bytes(take(bytes)?)),
+        1 => GlobalValue::I64(i64::from_le_bytes(take(bytes)?)),
+        2 => GlobalValue::F32(u32::from_le_bytes(take(bytes)?)),
+        3 => GlobalValue::F64(u64::from_le_bytes(take(bytes)?)),
+        _ => return None,
+    })
+}
+
+impl InstanceLayout {
+    /// Encode the layout to be passed between the guest and the host
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&self.memory_size.to_le_bytes());
+        bytes.extend_from_slice(&(self.globals.len() as u32).to_le_bytes());
+        for (name, value) in &self.globals {
+            bytes.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(name.as_bytes());
+            match value {
+                GlobalValue::I32(v) => {
+                    bytes.push(0);
+                    bytes.extend_from_slice(&v.to_le_bytes());
+                }
+                GlobalValue::I64(v) => {
+                    bytes.push(1);
+                    bytes.extend_from_slice(&v.to_le_bytes());
+                }
+                GlobalValue::F32(v) => {
+                    bytes.push(2);
+                    bytes.extend_from_slice(&v.to_le_bytes());
+                }
+                GlobalValue::F64(v) => {
+                    bytes.push(3);
+                    bytes.extend_from_slice(&v.to_le_bytes());
+                }
+            }
+        }
+        bytes
+    }
+
+    /// Decode a layout encoded with [`to_bytes`](Self::to_bytes),
+    /// returning `None` if the encoding is malformed
+    pub fn from_bytes(mut bytes: &[u8]) -> Option<Self> {
+        let memory_size = u64::from_le_bytes(take(&mut bytes)?);
+        let count = u32::from_le_bytes(take(&mut bytes)?);
+        let globals = (0..count)
+            .map(|_| Some((take_str(&mut bytes)?, take_global(&mut bytes)?)))
+            .collect::<Option<Vec<_>>>()?;
+        bytes.is_empty().then_some(Self {
+            memory_size,
+            globals,
+        })
+    }
+}
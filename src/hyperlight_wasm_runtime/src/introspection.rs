@@ -0,0 +1,93 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use hyperlight_guest::error::Result;
+use wasmtime::component::Linker;
+
+use crate::{log_buffer, map_wasmtime_error, WASMTIME_VERSION};
+
+/// The interface through which components learn about the runtime they
+/// run in and emit diagnostics:
+///
+/// ```text
+/// package hlwasm:introspection;
+///
+/// interface introspection {
+///     /// The version of hyperlight-wasm
+///     runtime-version: func() -> string;
+///     /// The version of wasmtime running the component
+///     wasmtime-version: func() -> string;
+///     /// The cargo features the runtime was built with
+///     features: func() -> list<string>;
+///     /// Emit a diagnostic through the sandbox's guest log, at a level
+///     /// from 1 (error) to 5 (trace)
+///     diagnostic: func(level: u8, code: string, message: string);
+/// }
+/// ```
+const INTROSPECTION_INTERFACE: &str = "hlwasm:introspection/introspection";
+
+fn features() -> Vec<String> {
+    let features = [
+        ("wasmtime_latest", cfg!(feature = "wasmtime_latest")),
+        ("wasmtime_lts", cfg!(feature = "wasmtime_lts")),
+        ("gdb", cfg!(feature = "gdb")),
+        ("pulley", cfg!(feature = "pulley")),
+        ("gc", cfg!(feature = "gc")),
+        ("trace_guest", cfg!(feature = "trace_guest")),
+    ];
+    features
+        .into_iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| name.to_string())
+        .collect()
+}
+
+/// Add the hlwasm:introspection interface. This replaces any definition
+/// of the interface generated from the component's world.
+pub(crate) fn register_component_handlers<T: 'static>(linker: &mut Linker<T>) -> Result<()> {
+    linker.allow_shadowing(true);
+    let mut instance = linker
+        .instance(INTROSPECTION_INTERFACE)
+        .map_err(map_wasmtime_error)?;
+    instance
+        .func_wrap("runtime-version", |_, ()| {
+            Ok((env!("CARGO_PKG_VERSION").to_string(),))
+        })
+        .map_err(map_wasmtime_error)?;
+    instance
+        .func_wrap("wasmtime-version", |_, ()| {
+            Ok((WASMTIME_VERSION.to_string(),))
+        })
+        .map_err(map_wasmtime_error)?;
+    instance
+        .func_wrap("features", |_, ()| Ok((features(),)))
+        .map_err(map_wasmtime_error)?;
+    instance
+        .func_wrap(
+            "diagnostic",
+            |_, (level, code, message): (u8, String, String)| {
+                log_buffer::push(level, format!("{code}: {message}").as_bytes());
+                Ok(())
+            },
+        )
+        .map_err(map_wasmtime_error)?;
+    linker.allow_shadowing(false);
+    Ok(())
+}
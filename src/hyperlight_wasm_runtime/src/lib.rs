@@ -15,48 +15,181 @@ limitations under the License.
 */
 
 #![no_std]
-#![cfg(hyperlight)]
 
 extern crate alloc;
 
+/// Engine settings chosen by the host. This module is also built for
+/// the host, so that both sides agree on how the settings are encoded.
+pub mod runtime_config;
+
+/// Log records buffered in the guest. This module is also built for the
+/// host, which decodes the records drained from the guest.
+pub mod guest_log;
+
+/// Statistics about guest calls. This module is also built for the host,
+/// which decodes the statistics fetched from the guest.
+pub mod call_stats;
+
+/// Host functions whose results are cached in the guest. This module is
+/// also built for the host, which chooses the functions.
+pub mod host_function_cache;
+
+/// Host functions registered from a manifest, which the guest calls
+/// through a single dispatch host function. This module is also built
+/// for the host, so that both sides agree on how calls are encoded.
+pub mod host_dispatch;
+
+/// Host functions that call back into the module calling them. This
+/// module is also built for the host, so that both sides agree on how
+/// the calls are encoded.
+pub mod host_reentry;
+
+/// Batches of calls to module functions run in one guest call. This
+/// module is also built for the host, which encodes the calls and
+/// decodes their results.
+pub mod batch;
+
+/// The functions exported by the loaded guest. This module is also
+/// built for the host, which decodes the list fetched from the guest.
+pub mod guest_functions;
+
+/// How modules built with AssemblyScript and TinyGo encode strings and
+/// buffers. This module is also built for the host, which identifies
+/// the ABI of the modules it loads.
+pub mod guest_abi;
+
+/// Encryption of buffers passed to and returned from guest functions.
+/// This module is also built for the host, which encrypts the
+/// parameters and decrypts the results.
+pub mod payload_cipher;
+
+/// The state of the loaded module's instance. This module is also built
+/// for the host, which serializes the state read from the guest.
+pub mod instance_state;
+
+/// The results of module functions that return more than one value.
+/// This module is also built for the host, which decodes the results.
+pub mod multi_value;
+
+/// The memory used by the guest. This module is also built for the
+/// host, which decodes the statistics fetched from the guest.
+pub mod memory_stats;
+
+/// Where the loaded module's linear memories are in the guest. This
+/// module is also built for the host, which decodes the memories fetched
+/// from the guest.
+pub mod linear_memories;
+
+/// Host directories preopened for modules. This module is also built for
+/// the host, so that both sides agree on how paths are normalized and
+/// how lookups are encoded.
+pub mod wasi_fs;
+
+/// Values passed to and returned from components loaded without bindings
+/// generated for their world. This module is also built for the host, so
+/// that both sides agree on how values are encoded.
+pub mod component_value;
+
+#[cfg(hyperlight)]
 use alloc::string::ToString;
 
+#[cfg(hyperlight)]
 use hyperlight_common::flatbuffer_wrappers::guest_error::ErrorCode;
+#[cfg(hyperlight)]
 use hyperlight_guest::error::HyperlightGuestError;
 
 // Re-export wasmtime based on expected version
-#[cfg(all(feature = "wasmtime_latest", feature = "wasmtime_lts"))]
+#[cfg(all(hyperlight, feature = "wasmtime_latest", feature = "wasmtime_lts"))]
 compile_error!(
     "Features 'wasmtime_latest' and 'wasmtime_lts' are mutually exclusive. Please enable only one."
 );
 
-#[cfg(not(any(feature = "wasmtime_latest", feature = "wasmtime_lts")))]
+#[cfg(all(
+    hyperlight,
+    not(any(feature = "wasmtime_latest", feature = "wasmtime_lts"))
+))]
 compile_error!("Either 'wasmtime_latest' or 'wasmtime_lts' feature must be enabled.");
 
-#[cfg(not(feature = "wasmtime_lts"))]
+#[cfg(all(hyperlight, not(feature = "wasmtime_lts")))]
 extern crate wasmtime;
-#[cfg(feature = "wasmtime_lts")]
+#[cfg(all(hyperlight, feature = "wasmtime_lts"))]
 extern crate wasmtime_lts as wasmtime;
 
 // Keep this conversion local: HyperlightGuestError is owned by hyperlight-guest,
 // so this crate cannot implement From<wasmtime::Error> for it.
+#[cfg(hyperlight)]
 pub(crate) fn map_wasmtime_error(error: wasmtime::Error) -> HyperlightGuestError {
     HyperlightGuestError::new(ErrorCode::GuestError, error.to_string())
 }
 
+#[cfg(hyperlight)]
+mod abi_version;
+#[cfg(hyperlight)]
+mod call_tracker;
+#[cfg(hyperlight)]
+mod cli_environment;
+#[cfg(hyperlight)]
+mod engine;
+#[cfg(hyperlight)]
+mod epoch_deadline;
+#[cfg(hyperlight)]
+mod guest_abort;
+#[cfg(hyperlight)]
+mod guest_trap;
+#[cfg(hyperlight)]
+mod host_error;
+#[cfg(hyperlight)]
+mod limits;
+#[cfg(hyperlight)]
+mod log_buffer;
+#[cfg(hyperlight)]
+mod output_capture;
+#[cfg(hyperlight)]
+mod payload_key;
+#[cfg(hyperlight)]
 mod platform;
-
-#[cfg(not(component))]
+#[cfg(hyperlight)]
+mod random;
+#[cfg(hyperlight)]
+mod wasip2;
+#[cfg(hyperlight)]
+mod wasm_limits;
+
+#[cfg(all(hyperlight, not(component)))]
+mod dispatch;
+#[cfg(all(hyperlight, not(component)))]
+mod dynamic_component;
+#[cfg(all(hyperlight, not(component)))]
+mod host_cache;
+#[cfg(all(hyperlight, not(component)))]
 mod hostfuncs;
-#[cfg(not(component))]
+#[cfg(all(hyperlight, not(component)))]
+mod mapped_buffers;
+#[cfg(all(hyperlight, not(component)))]
 mod marshal;
-#[cfg(not(component))]
+#[cfg(all(hyperlight, not(component)))]
 mod module;
-#[cfg(not(component))]
+#[cfg(all(hyperlight, not(component)))]
+mod reentry;
+#[cfg(all(hyperlight, not(component)))]
+mod staged_params;
+#[cfg(all(hyperlight, not(component)))]
+mod state_cells;
+#[cfg(all(hyperlight, not(component)))]
+mod vfs;
+#[cfg(all(hyperlight, not(component)))]
 mod wasip1;
+#[cfg(all(hyperlight, not(component)))]
+mod wasip1_fs;
 
-#[cfg(component)]
+#[cfg(all(hyperlight, component))]
 mod component;
+#[cfg(all(hyperlight, component))]
+// Unused by the bindings of worlds that do not export resources
+#[allow(dead_code)]
+mod guest_resources;
+#[cfg(all(hyperlight, component))]
+mod introspection;
 
 // The file referenced in this include! macro is created by the
 // build.rs script.  The build.rs script gets the current version of
@@ -65,4 +198,5 @@ mod component;
 // hyperlight-wasm-runtime binary. This allows hyperlight-wasm to then this
 // metadata and log it when the hyperlight-wasm crate is loaded.
 
+#[cfg(hyperlight)]
 include!(concat!(env!("OUT_DIR"), "/metadata.rs"));
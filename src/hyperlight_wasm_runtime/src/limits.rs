@@ -0,0 +1,134 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use alloc::format;
+use core::fmt::Display;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use hyperlight_common::flatbuffer_wrappers::guest_error::ErrorCode;
+use hyperlight_guest::error::{HyperlightGuestError, Result};
+
+use crate::map_wasmtime_error;
+use crate::runtime_config::RuntimeConfig;
+
+// Set by init_wasm_runtime from the runtime config
+static MAX_RETURN_VALUE_SIZE: AtomicU64 = AtomicU64::new(u64::MAX);
+// Reported to guests through the hlwasm:limits interface, 0 if unknown
+// or unlimited
+static HEAP_SIZE: AtomicU64 = AtomicU64::new(0);
+static INPUT_BUFFER_SIZE: AtomicU64 = AtomicU64::new(0);
+static OUTPUT_BUFFER_SIZE: AtomicU64 = AtomicU64::new(0);
+static CALL_TIMEOUT_MICROS: AtomicU64 = AtomicU64::new(0);
+
+/// The name of the import module through which modules read their
+/// sandbox's limits, for example
+/// `(import "hlwasm:limits" "heap-size" (func (result i64)))`
+#[cfg(not(component))]
+const LIMITS_MODULE: &str = "hlwasm:limits";
+
+/// The interface through which components read their sandbox's limits:
+///
+/// ```text
+/// package hlwasm:limits;
+///
+/// interface limits {
+///     heap-size: func() -> u64;
+///     input-buffer-size: func() -> u64;
+///     output-buffer-size: func() -> u64;
+///     call-timeout-us: func() -> u64;
+/// }
+/// ```
+const LIMITS_INTERFACE: &str = "hlwasm:limits/limits";
+
+// The functions of the hlwasm:limits interface, and the values they return
+static LIMITS: [(&str, &AtomicU64); 4] = [
+    ("heap-size", &HEAP_SIZE),
+    ("input-buffer-size", &INPUT_BUFFER_SIZE),
+    ("output-buffer-size", &OUTPUT_BUFFER_SIZE),
+    ("call-timeout-us", &CALL_TIMEOUT_MICROS),
+];
+
+/// Record the sandbox's limits reported through the hlwasm:limits interface
+pub(crate) fn set_sandbox_limits(runtime_config: &RuntimeConfig) {
+    let set = |limit: &AtomicU64, value: Option<u64>| {
+        limit.store(value.unwrap_or(0), Ordering::Relaxed);
+    };
+    set(&HEAP_SIZE, runtime_config.heap_size);
+    set(&INPUT_BUFFER_SIZE, runtime_config.input_buffer_size);
+    set(&OUTPUT_BUFFER_SIZE, runtime_config.output_buffer_size);
+    set(&CALL_TIMEOUT_MICROS, runtime_config.call_timeout_micros);
+}
+
+/// Add the hlwasm:limits imports, each returning an `i64`
+#[cfg(not(component))]
+pub(crate) fn register_handlers<T: 'static>(linker: &mut wasmtime::Linker<T>) -> Result<()> {
+    for (name, value) in LIMITS {
+        linker
+            .func_wrap(LIMITS_MODULE, name, move || {
+                value.load(Ordering::Relaxed) as i64
+            })
+            .map_err(map_wasmtime_error)?;
+    }
+    Ok(())
+}
+
+/// Add the hlwasm:limits interface. This replaces any definition of the
+/// interface generated from the component's world.
+pub(crate) fn register_component_handlers<T: 'static>(
+    linker: &mut wasmtime::component::Linker<T>,
+) -> Result<()> {
+    linker.allow_shadowing(true);
+    let mut instance = linker
+        .instance(LIMITS_INTERFACE)
+        .map_err(map_wasmtime_error)?;
+    for (name, value) in LIMITS {
+        instance
+            .func_wrap(name, move |_, ()| Ok((value.load(Ordering::Relaxed),)))
+            .map_err(map_wasmtime_error)?;
+    }
+    linker.allow_shadowing(false);
+    Ok(())
+}
+
+pub(crate) fn set_max_return_value_size(size: Option<u64>) {
+    MAX_RETURN_VALUE_SIZE.store(size.unwrap_or(u64::MAX), Ordering::Relaxed);
+}
+
+/// The maximum size in bytes of a String or VecBytes returned from a
+/// guest function
+pub(crate) fn max_return_value_size() -> usize {
+    usize::try_from(MAX_RETURN_VALUE_SIZE.load(Ordering::Relaxed)).unwrap_or(usize::MAX)
+}
+
+/// Fail if a return value of `len` bytes is larger than allowed. This is
+/// checked before the value is copied out of wasm memory.
+pub(crate) fn check_return_value_size(len: usize) -> Result<()> {
+    let max = max_return_value_size();
+    if len > max {
+        return Err(return_value_too_large(len, max));
+    }
+    Ok(())
+}
+
+pub(crate) fn return_value_too_large(len: impl Display, max: usize) -> HyperlightGuestError {
+    HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        format!(
+            "guest function return value of {} bytes exceeds the maximum return value size of {} bytes",
+            len, max
+        ),
+    )
+}
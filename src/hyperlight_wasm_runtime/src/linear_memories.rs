@@ -0,0 +1,86 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Where the loaded module's linear memories are in the guest, fetched by
+//! the host with the `GetLinearMemories` guest function and recorded next
+//! to crash dumps, so that tooling can find the memories in a dump.
+//!
+//! The memories are encoded as a little-endian `u32` count, followed by
+//! each memory's name and the little-endian `u64` guest virtual address
+//! and size in bytes of its data. Names are encoded as a little-endian
+//! `u32` length followed by their UTF-8 bytes.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// The guest function that returns the module's [`LinearMemory`]s
+pub const GET_LINEAR_MEMORIES_FUNCTION: &str = "GetLinearMemories";
+
+/// A linear memory exported by the loaded module
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LinearMemory {
+    /// The name the memory is exported under
+    pub name: String,
+    /// The guest virtual address of the memory's data
+    pub guest_addr: u64,
+    /// The size of the memory in bytes
+    pub size: u64,
+}
+
+fn take<const N: usize>(bytes: &mut &[u8]) -> Option<[u8; N]> {
+    let (value, rest) = bytes.split_first_chunk::<N>()?;
+    *bytes = rest;
+    Some(*value)
+}
+
+fn take_str(bytes: &mut &[u8]) -> Option<String> {
+    let len = u32::from_le_bytes(take(bytes)?) as usize;
+    if bytes.len() < len {
+        return None;
+    }
+    let (s, rest) = bytes.split_at(len);
+    *bytes = rest;
+    String::from_utf8(s.to_vec()).ok()
+}
+
+/// Encode `memories` to be passed to the host
+pub fn to_bytes(memories: &[LinearMemory]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&(memories.len() as u32).to_le_bytes());
+    for memory in memories {
+        bytes.extend_from_slice(&(memory.name.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(memory.name.as_bytes());
+        bytes.extend_from_slice(&memory.guest_addr.to_le_bytes());
+        bytes.extend_from_slice(&memory.size.to_le_bytes());
+    }
+    bytes
+}
+
+/// Decode memories encoded with [`to_bytes`], returning `None` if the
+/// encoding is malformed
+pub fn from_bytes(mut bytes: &[u8]) -> Option<Vec<LinearMemory>> {
+    let count = u32::from_le_bytes(take(&mut bytes)?);
+    let memories = (0..count)
+        .map(|_| {
+            Some(LinearMemory {
+                name: take_str(&mut bytes)?,
+                guest_addr: u64::from_le_bytes(take(&mut bytes)?),
+                size: u64::from_le_bytes(take(&mut bytes)?),
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    bytes.is_empty().then_some(memories)
+}
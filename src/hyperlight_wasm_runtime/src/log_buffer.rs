@@ -0,0 +1,188 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use hyperlight_common::flatbuffer_wrappers::function_call::FunctionCall;
+use hyperlight_common::flatbuffer_wrappers::function_types::ReturnType;
+use hyperlight_common::flatbuffer_wrappers::util::get_flatbuffer_result;
+use hyperlight_guest::error::Result;
+use hyperlight_guest_bin::guest_function::definition::GuestFunctionDefinition;
+use hyperlight_guest_bin::guest_function::register::register_function;
+use spin::Mutex;
+use tracing::instrument;
+
+use crate::guest_log::{GuestLogRecord, GuestLogs};
+
+static LOG_BUFFER: Mutex<GuestLogs> = Mutex::new(GuestLogs::new());
+// Records with a level above this are discarded; 0 discards all records
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(0);
+
+/// The interface through which components write structured records, see
+/// [`crate::guest_log`]
+const LOG_INTERFACE: &str = "hlwasm:log/log";
+
+/// Set the most verbose level that will be buffered, as a `log::LevelFilter`
+pub(crate) fn set_max_level(level: u64) {
+    MAX_LEVEL.store(level.min(u8::MAX as u64) as u8, Ordering::Relaxed);
+}
+
+/// Whether records at `level` are buffered
+fn enabled(level: u8) -> bool {
+    level != 0 && level <= MAX_LEVEL.load(Ordering::Relaxed)
+}
+
+/// Buffer a record without a target or fields, unless its level is
+/// filtered out
+pub(crate) fn push(level: u8, message: &[u8]) {
+    if !enabled(level) {
+        return;
+    }
+    LOG_BUFFER.lock().push(GuestLogRecord {
+        level,
+        target: String::new(),
+        message: String::from_utf8_lossy(message).into_owned(),
+        fields: Vec::new(),
+    });
+}
+
+/// Buffer a record, unless its level is filtered out
+fn push_record(level: u8, target: &[u8], message: &[u8], fields: Vec<(String, String)>) {
+    if !enabled(level) {
+        return;
+    }
+    LOG_BUFFER.lock().push(GuestLogRecord {
+        level,
+        target: String::from_utf8_lossy(target).into_owned(),
+        message: String::from_utf8_lossy(message).into_owned(),
+        fields,
+    });
+}
+
+/// Decode the fields passed to `log_record`, keys and values each
+/// followed by a NUL byte
+#[cfg(not(component))]
+fn parse_fields(bytes: &[u8]) -> Vec<(String, String)> {
+    let mut parts = bytes
+        .split(|&b| b == 0)
+        .map(|part| String::from_utf8_lossy(part).into_owned());
+    let mut fields = Vec::new();
+    while let (Some(key), Some(value)) = (parts.next(), parts.next()) {
+        fields.push((key, value));
+    }
+    fields
+}
+
+/// Add the `hyperlight` `log` and `log_record` imports used by modules to
+/// write records
+#[cfg(not(component))]
+pub(crate) fn register_handlers<T: 'static>(linker: &mut wasmtime::Linker<T>) -> Result<()> {
+    use wasmtime::{Caller, Extern};
+
+    // Read `len` bytes at `ptr` from the caller's memory
+    fn read<T>(ctx: &mut Caller<'_, T>, ptr: i32, len: i32) -> Option<Vec<u8>> {
+        let memory = ctx.get_export("memory").and_then(Extern::into_memory)?;
+        let mut bytes = vec![0u8; len as u32 as usize];
+        memory.read(ctx, ptr as u32 as usize, &mut bytes).ok()?;
+        Some(bytes)
+    }
+
+    linker
+        .func_wrap(
+            "hyperlight",
+            "log",
+            |mut ctx: Caller<'_, T>, level: i32, ptr: i32, len: i32| {
+                let level = level.clamp(0, u8::MAX as i32) as u8;
+                if !enabled(level) {
+                    return;
+                }
+                if let Some(message) = read(&mut ctx, ptr, len) {
+                    push(level, &message);
+                }
+            },
+        )
+        .map_err(crate::map_wasmtime_error)?;
+    linker
+        .func_wrap(
+            "hyperlight",
+            "log_record",
+            |mut ctx: Caller<'_, T>,
+             level: i32,
+             target_ptr: i32,
+             target_len: i32,
+             message_ptr: i32,
+             message_len: i32,
+             fields_ptr: i32,
+             fields_len: i32| {
+                let level = level.clamp(0, u8::MAX as i32) as u8;
+                if !enabled(level) {
+                    return;
+                }
+                let (Some(target), Some(message), Some(fields)) = (
+                    read(&mut ctx, target_ptr, target_len),
+                    read(&mut ctx, message_ptr, message_len),
+                    read(&mut ctx, fields_ptr, fields_len),
+                ) else {
+                    return;
+                };
+                push_record(level, &target, &message, parse_fields(&fields));
+            },
+        )
+        .map_err(crate::map_wasmtime_error)?;
+    Ok(())
+}
+
+/// Add the `hlwasm:log` interface, see [`crate::guest_log`]. This
+/// replaces any definition of the interface generated from the
+/// component's world.
+pub(crate) fn register_component_handlers<T: 'static>(
+    linker: &mut wasmtime::component::Linker<T>,
+) -> Result<()> {
+    linker.allow_shadowing(true);
+    let mut instance = linker
+        .instance(LOG_INTERFACE)
+        .map_err(crate::map_wasmtime_error)?;
+    instance
+        .func_wrap(
+            "log",
+            |_, (level, target, message, fields): (u8, String, String, Vec<(String, String)>)| {
+                push_record(level, target.as_bytes(), message.as_bytes(), fields);
+                Ok(())
+            },
+        )
+        .map_err(crate::map_wasmtime_error)?;
+    linker.allow_shadowing(false);
+    Ok(())
+}
+
+#[instrument(skip_all, level = "Info")]
+/// Return and clear the buffered records, see [`crate::guest_log`]
+fn drain_log_buffer(_function_call: FunctionCall) -> Result<Vec<u8>> {
+    let logs = core::mem::take(&mut *LOG_BUFFER.lock());
+    Ok(get_flatbuffer_result::<&[u8]>(&logs.to_bytes()))
+}
+
+pub(crate) fn register_functions() {
+    register_function(GuestFunctionDefinition::new(
+        "DrainLogBuffer".to_string(),
+        vec![],
+        ReturnType::VecBytes,
+        drain_log_buffer,
+    ));
+}
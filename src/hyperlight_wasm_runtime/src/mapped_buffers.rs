@@ -0,0 +1,198 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Host buffers mapped into the guest, and the `hlwasm:buffers` import
+//! module through which modules access them without the data passing
+//! through a host function call:
+//!
+//! ```text
+//! (import "hlwasm:buffers" "len" (func (param $addr i64) (result i64)))
+//! (import "hlwasm:buffers" "read" (func (param $addr i64) (param $offset i64) (param $buf_ptr i32) (param $buf_len i32) (result i32)))
+//! (import "hlwasm:buffers" "write" (func (param $addr i64) (param $offset i64) (param $buf_ptr i32) (param $buf_len i32) (result i32)))
+//! ```
+//!
+//! Buffers are identified by the guest address the host mapped them at.
+//! `len` returns the length of a buffer, or -1 if nothing is mapped at
+//! `addr`. `read` and `write` copy between the buffer, from `offset`,
+//! and linear memory, returning how many bytes they copied. Reading or
+//! writing a buffer that is not mapped, or writing one that was mapped
+//! read-only, traps.
+
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use alloc::{format, vec};
+
+use hyperlight_common::flatbuffer_wrappers::function_call::FunctionCall;
+use hyperlight_common::flatbuffer_wrappers::function_types::{
+    ParameterType, ParameterValue, ReturnType,
+};
+use hyperlight_common::flatbuffer_wrappers::guest_error::ErrorCode;
+use hyperlight_common::flatbuffer_wrappers::util::get_flatbuffer_result;
+use hyperlight_guest::error::{HyperlightGuestError, Result};
+use hyperlight_guest_bin::guest_function::definition::GuestFunctionDefinition;
+use hyperlight_guest_bin::guest_function::register::register_function;
+use spin::Mutex;
+use wasmtime::{Caller, Extern, Linker, Memory};
+
+use crate::{map_wasmtime_error, platform};
+
+const BUFFERS_MODULE: &str = "hlwasm:buffers";
+
+#[derive(Clone, Copy)]
+struct MappedBuffer {
+    addr: u64,
+    len: u64,
+    writable: bool,
+}
+
+static BUFFERS: Mutex<Vec<MappedBuffer>> = Mutex::new(Vec::new());
+
+fn find(addr: i64) -> Option<MappedBuffer> {
+    BUFFERS
+        .lock()
+        .iter()
+        .find(|buffer| buffer.addr == addr as u64)
+        .copied()
+}
+
+fn memory<T>(ctx: &mut Caller<'_, T>) -> wasmtime::Result<Memory> {
+    ctx.get_export("memory")
+        .and_then(Extern::into_memory)
+        .ok_or_else(|| wasmtime::Error::msg("hlwasm:buffers requires an exported memory"))
+}
+
+/// The part of the buffer at `addr` that `len` bytes from `offset`
+/// cover, as its address and length
+fn range(addr: i64, offset: i64, len: i32, write: bool) -> wasmtime::Result<(u64, usize)> {
+    let buffer = find(addr).ok_or_else(|| wasmtime::Error::msg("no buffer is mapped there"))?;
+    if write && !buffer.writable {
+        return Err(wasmtime::Error::msg("the buffer is mapped read-only"));
+    }
+    let offset = (offset as u64).min(buffer.len);
+    let len = (len as u32 as u64).min(buffer.len - offset);
+    Ok((buffer.addr + offset, len as usize))
+}
+
+pub(crate) fn register_handlers<T: 'static>(linker: &mut Linker<T>) -> Result<()> {
+    linker
+        .func_wrap(BUFFERS_MODULE, "len", |addr: i64| -> i64 {
+            find(addr).map_or(-1, |buffer| buffer.len as i64)
+        })
+        .map_err(map_wasmtime_error)?;
+    linker
+        .func_wrap(
+            BUFFERS_MODULE,
+            "read",
+            |mut ctx: Caller<'_, T>,
+             addr: i64,
+             offset: i64,
+             buf_ptr: i32,
+             buf_len: i32|
+             -> wasmtime::Result<i32> {
+                let (start, len) = range(addr, offset, buf_len, false)?;
+                // Safety: the range lies within a buffer mapped by
+                // map_buffer, which stays mapped until it is unmapped
+                let bytes = unsafe { core::slice::from_raw_parts(start as *const u8, len) };
+                memory(&mut ctx)?.write(&mut ctx, buf_ptr as u32 as usize, bytes)?;
+                Ok(len as i32)
+            },
+        )
+        .map_err(map_wasmtime_error)?;
+    linker
+        .func_wrap(
+            BUFFERS_MODULE,
+            "write",
+            |mut ctx: Caller<'_, T>,
+             addr: i64,
+             offset: i64,
+             buf_ptr: i32,
+             buf_len: i32|
+             -> wasmtime::Result<i32> {
+                let (start, len) = range(addr, offset, buf_len, true)?;
+                let mut bytes = vec![0u8; len];
+                memory(&mut ctx)?.read(&mut ctx, buf_ptr as u32 as usize, &mut bytes)?;
+                // Safety: the range lies within a writable buffer mapped
+                // by map_buffer, which stays mapped until it is unmapped
+                unsafe { core::ptr::copy_nonoverlapping(bytes.as_ptr(), start as *mut u8, len) };
+                Ok(len as i32)
+            },
+        )
+        .map_err(map_wasmtime_error)?;
+    Ok(())
+}
+
+fn parameter_error(function: &str) -> HyperlightGuestError {
+    HyperlightGuestError::new(
+        ErrorCode::GuestFunctionParameterTypeMismatch,
+        format!("Invalid parameters passed to {}", function),
+    )
+}
+
+/// Map the host region the host mapped at `addr` into the guest's page
+/// tables, and let modules access it
+fn map_buffer(function_call: FunctionCall) -> Result<Vec<u8>> {
+    let Some(
+        [ParameterValue::ULong(addr), ParameterValue::ULong(len), ParameterValue::Bool(writable)],
+    ) = function_call.parameters.as_deref()
+    else {
+        return Err(parameter_error("MapBuffer"));
+    };
+    unsafe { platform::map_buffer(*addr, *len) };
+    let mut buffers = BUFFERS.lock();
+    buffers.retain(|buffer| buffer.addr != *addr);
+    buffers.push(MappedBuffer {
+        addr: *addr,
+        len: *len,
+        writable: *writable,
+    });
+    Ok(get_flatbuffer_result::<()>(()))
+}
+
+/// Stop modules accessing the buffer at `addr`
+fn unmap_buffer(function_call: FunctionCall) -> Result<Vec<u8>> {
+    let Some([ParameterValue::ULong(addr)]) = function_call.parameters.as_deref() else {
+        return Err(parameter_error("UnmapBuffer"));
+    };
+    let mut buffers = BUFFERS.lock();
+    let count = buffers.len();
+    buffers.retain(|buffer| buffer.addr != *addr);
+    if buffers.len() == count {
+        return Err(HyperlightGuestError::new(
+            ErrorCode::GuestError,
+            format!("no buffer is mapped at {:#x}", addr),
+        ));
+    }
+    Ok(get_flatbuffer_result::<()>(()))
+}
+
+pub(crate) fn register_functions() {
+    register_function(GuestFunctionDefinition::new(
+        "MapBuffer".to_string(),
+        vec![
+            ParameterType::ULong,
+            ParameterType::ULong,
+            ParameterType::Bool,
+        ],
+        ReturnType::Void,
+        map_buffer,
+    ));
+    register_function(GuestFunctionDefinition::new(
+        "UnmapBuffer".to_string(),
+        vec![ParameterType::ULong],
+        ReturnType::Void,
+        unmap_buffer,
+    ));
+}
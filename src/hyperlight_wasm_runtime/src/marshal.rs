@@ -42,11 +42,43 @@ limitations under the License.
 //! - When host functions return String or VecBytes values to the guest, the host allocates memory
 //!   in the guest's memory space and returns pointers.
 //! - **The guest owns these allocations and must free them** when no longer needed.
+//!
+//! ## Payload Encryption
+//! - When the host sets a payload key (see [`crate::payload_cipher`]), VecBytes guest function
+//!   parameters arrive encrypted and are decrypted just before they are written into the
+//!   guest's memory. Guests should overwrite them before freeing them, as the guest SDK does.
+//! - VecBytes guest function return values are overwritten in the guest's memory once they are
+//!   read, except for AssemblyScript objects which the guest may still reference, and are
+//!   encrypted before they are returned to the host.
+//!
+//! ## Multiple Return Values
+//! - Functions that return more than one value must be called with a VecBytes return type.
+//!   Their results are returned encoded as described in [`crate::multi_value`], rather than as
+//!   buffers in the guest's memory, so nothing is allocated or freed.
+//!
+//! # Guest ABIs
+//!
+//! The contract above describes the default [`GuestAbi::C`] ABI used by
+//! C and Rust toolchains. Modules built with other toolchains can be
+//! loaded with a different [`GuestAbi`], which changes how strings and
+//! buffers are laid out and which exports are used to allocate them:
+//!
+//! - [`GuestAbi::C`]: `malloc`/`free` exports, NUL-terminated UTF-8
+//!   strings passed as a single pointer.
+//! - [`GuestAbi::AssemblyScript`]: `__new`/`__pin`/`__unpin` exports,
+//!   UTF-16 strings and `ArrayBuffer`s whose byte length is stored in
+//!   the object header. Objects allocated by the host for guest function
+//!   parameters are pinned, and unpinned by the host on the next VM entry
+//!   rather than freed by the guest.
+//! - [`GuestAbi::TinyGo`]: `malloc`/`free` exports, strings passed as a
+//!   `(ptr, len)` pair of parameters. Strings and buffers returned from
+//!   guest functions are packed into an `i64` with the pointer in the
+//!   lower half and the length in the upper half.
 
 extern crate alloc;
 
 use alloc::ffi::CString;
-use alloc::string::ToString;
+use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 use alloc::{format, vec};
 
@@ -60,7 +92,54 @@ use spin::Mutex;
 use tracing::instrument;
 use wasmtime::{AsContextMut, Extern, Val};
 
-use crate::map_wasmtime_error;
+use crate::multi_value::{self, WasmValue};
+use crate::{guest_abi, limits, map_wasmtime_error, payload_key};
+
+/// The convention used to pass strings and buffers to and from the
+/// currently loaded module. See the module level documentation for
+/// details.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GuestAbi {
+    /// `malloc`/`free` and NUL-terminated strings (C, Rust)
+    C,
+    /// `__new`/`__pin`/`__unpin` and UTF-16 strings (AssemblyScript)
+    AssemblyScript,
+    /// `malloc`/`free` and `(ptr, len)` strings (TinyGo)
+    TinyGo,
+}
+
+impl TryFrom<i32> for GuestAbi {
+    type Error = HyperlightGuestError;
+
+    fn try_from(value: i32) -> Result<Self> {
+        match value {
+            guest_abi::C => Ok(GuestAbi::C),
+            guest_abi::ASSEMBLY_SCRIPT => Ok(GuestAbi::AssemblyScript),
+            guest_abi::TINYGO => Ok(GuestAbi::TinyGo),
+            _ => Err(HyperlightGuestError::new(
+                ErrorCode::GuestError,
+                format!("unknown guest ABI: {}", value),
+            )),
+        }
+    }
+}
+
+// AssemblyScript runtime class ids for the builtin managed types
+const AS_ARRAY_BUFFER_ID: i32 = 1;
+const AS_STRING_ID: i32 = 2;
+
+// Set by the host (via SetGuestAbi) before a module is loaded
+static GUEST_ABI: Mutex<GuestAbi> = Mutex::new(GuestAbi::C);
+
+/// Set the ABI used to marshal values for the module that is about to be loaded
+pub fn set_guest_abi(abi: GuestAbi) {
+    *GUEST_ABI.lock() = abi;
+}
+
+/// Get the ABI used to marshal values for the currently loaded module
+pub fn guest_abi() -> GuestAbi {
+    *GUEST_ABI.lock()
+}
 
 // Global tracking for return value allocations that need to be freed on next VM entry
 static RETURN_VALUE_ALLOCATIONS: Mutex<Vec<i32>> = Mutex::new(Vec::new());
@@ -79,11 +158,108 @@ pub fn free_return_value_allocations<C: AsContextMut>(
 ) -> Result<()> {
     let mut allocations = RETURN_VALUE_ALLOCATIONS.lock();
     for addr in allocations.drain(..) {
-        free(ctx, get_export, addr)?;
+        match guest_abi() {
+            GuestAbi::C | GuestAbi::TinyGo => free(ctx, get_export, addr)?,
+            // Objects tracked for AssemblyScript guests are parameters
+            // that were pinned by the host, which just need unpinning
+            GuestAbi::AssemblyScript => {
+                call_export::<_, i32, ()>(ctx, get_export, "__unpin", addr)?
+            }
+        }
     }
     Ok(())
 }
 
+#[instrument(skip_all, level = "Trace")]
+fn call_export<C: AsContextMut, P: wasmtime::WasmParams, R: wasmtime::WasmResults>(
+    ctx: &mut C,
+    get_export: &impl Fn(&mut C, &str) -> Option<Extern>,
+    name: &str,
+    params: P,
+) -> Result<R> {
+    let func = get_export(&mut *ctx, name)
+        .and_then(Extern::into_func)
+        .ok_or(HyperlightGuestError::new(
+            ErrorCode::GuestError,
+            format!("{} function not exported", name),
+        ))?;
+    func.typed::<P, R>(&mut *ctx)
+        .map_err(map_wasmtime_error)?
+        .call(&mut *ctx, params)
+        .map_err(map_wasmtime_error)
+}
+
+/// Allocate a managed AssemblyScript object of the given class and write
+/// `bytes` into it, optionally pinning it so that it survives until the
+/// host unpins it on the next VM entry.
+#[instrument(skip_all, level = "Trace")]
+fn as_new<C: AsContextMut>(
+    ctx: &mut C,
+    get_export: &impl Fn(&mut C, &str) -> Option<Extern>,
+    class_id: i32,
+    bytes: &[u8],
+    pin: bool,
+) -> Result<i32> {
+    let addr: i32 = call_export(ctx, get_export, "__new", (bytes.len() as i32, class_id))?;
+    write(ctx, get_export, addr, bytes)?;
+    if pin {
+        let addr: i32 = call_export(ctx, get_export, "__pin", addr)?;
+        track_return_value_allocation(addr);
+    }
+    Ok(addr)
+}
+
+/// Read the payload of a managed AssemblyScript object, whose byte
+/// length is stored in the 4 bytes preceding it.
+#[instrument(skip_all, level = "Trace")]
+fn as_read<C: AsContextMut>(
+    ctx: &mut C,
+    get_export: &impl Fn(&mut C, &str) -> Option<Extern>,
+    addr: i32,
+    max_len: usize,
+) -> Result<Vec<u8>> {
+    let mut size_bytes = [0; 4];
+    read(ctx, get_export, addr - 4, &mut size_bytes)?;
+    let len = u32::from_le_bytes(size_bytes) as usize;
+    if len > max_len {
+        return Err(limits::return_value_too_large(len, max_len));
+    }
+    let mut bytes = vec![0; len];
+    read(ctx, get_export, addr, &mut bytes)?;
+    Ok(bytes)
+}
+
+fn as_decode_string(bytes: &[u8]) -> Result<String> {
+    guest_abi::decode_utf16(bytes).ok_or_else(|| {
+        HyperlightGuestError::new(
+            ErrorCode::GuestError,
+            "invalid UTF-16 string in memory".to_string(),
+        )
+    })
+}
+
+/// Pack a wasm32 pointer and the length of the buffer it points to into
+/// an i64, see [`guest_abi::pack_ptr_len`]
+fn pack_ptr_len(addr: i32, len: usize) -> Result<i64> {
+    let len = u32::try_from(len).map_err(|_| {
+        HyperlightGuestError::new(
+            ErrorCode::GuestError,
+            format!("buffer of {} bytes is too large for wasm32", len),
+        )
+    })?;
+    Ok(guest_abi::pack_ptr_len(addr, len))
+}
+
+/// The length of a buffer passed by the guest
+fn guest_len(len: i32) -> Result<usize> {
+    usize::try_from(len).map_err(|_| {
+        HyperlightGuestError::new(
+            ErrorCode::GuestError,
+            format!("negative buffer length: {}", len),
+        )
+    })
+}
+
 #[instrument(skip_all, level = "Trace")]
 fn malloc<C: AsContextMut>(
     ctx: &mut C,
@@ -172,6 +348,7 @@ fn read_cstr<C: AsContextMut>(
     ctx: &mut C,
     get_export: &impl Fn(&mut C, &str) -> Option<Extern>,
     addr: i32,
+    max_len: usize,
 ) -> Result<CString> {
     let mut addr = addr;
     let memory = get_export(&mut *ctx, "memory")
@@ -194,6 +371,12 @@ fn read_cstr<C: AsContextMut>(
         if byte[0] == 0 {
             break;
         }
+        if string.len() == max_len {
+            return Err(limits::return_value_too_large(
+                format!("more than {}", max_len),
+                max_len,
+            ));
+        }
         string.push(byte[0]);
         addr += 1;
     }
@@ -206,39 +389,82 @@ fn read_cstr<C: AsContextMut>(
     })
 }
 
-/// Convert a hyperlight parameter value to a wasmtime value.
+/// Convert a hyperlight parameter value to wasmtime values, appending them to `vals`.
+///
+/// Most parameters map to exactly one wasm value, but depending on the
+/// [`GuestAbi`] a string may be passed as a pointer and a length.
 ///
 /// For String and VecBytes parameter types, this allocates memory in the guest's memory space
-/// and returns a pointer. The guest function is responsible for freeing this memory when it is no
+/// and passes a pointer. The guest function is responsible for freeing this memory when it is no
 /// longer needed using the `free` function exported from the guest module.
 #[instrument(skip_all, level = "Trace")]
 pub fn hl_param_to_val<C: AsContextMut>(
     mut ctx: C,
     get_export: impl Fn(&mut C, &str) -> Option<Extern>,
     param: &ParameterValue,
-) -> Result<Val> {
-    match param {
-        ParameterValue::Int(i) => Ok(Val::I32(*i)),
-        ParameterValue::UInt(u) => Ok(Val::I32(*u as i32)),
-        ParameterValue::Long(l) => Ok(Val::I64(*l)),
-        ParameterValue::ULong(u) => Ok(Val::I64(*u as i64)),
-        ParameterValue::Bool(b) => Ok(Val::I32(if *b { 1 } else { 0 })),
-        ParameterValue::Float(f) => Ok(Val::F32(f.to_bits())),
-        ParameterValue::Double(f) => Ok(Val::F64(f.to_bits())),
-        ParameterValue::String(s) => {
-            let s = CString::new(s.as_str()).unwrap();
-            let nbytes = s.count_bytes() + 1; // include the NUL terminator
-            let addr = malloc(&mut ctx, &get_export, nbytes)?;
-            write(&mut ctx, &get_export, addr, s.as_bytes_with_nul())?;
-            Ok(Val::I32(addr))
-        }
-        ParameterValue::VecBytes(b) => {
-            let addr = malloc(&mut ctx, &get_export, b.len())?;
-            write(&mut ctx, &get_export, addr, b)?;
-            Ok(Val::I32(addr))
-            // TODO: check that the next parameter is the correct length
-        }
+    vals: &mut Vec<Val>,
+) -> Result<()> {
+    let val = match param {
+        ParameterValue::Int(i) => Val::I32(*i),
+        ParameterValue::UInt(u) => Val::I32(*u as i32),
+        ParameterValue::Long(l) => Val::I64(*l),
+        ParameterValue::ULong(u) => Val::I64(*u as i64),
+        ParameterValue::Bool(b) => Val::I32(if *b { 1 } else { 0 }),
+        ParameterValue::Float(f) => Val::F32(f.to_bits()),
+        ParameterValue::Double(f) => Val::F64(f.to_bits()),
+        ParameterValue::String(s) => match guest_abi() {
+            GuestAbi::C => {
+                let s = CString::new(s.as_str()).unwrap();
+                let nbytes = s.count_bytes() + 1; // include the NUL terminator
+                let addr = malloc(&mut ctx, &get_export, nbytes)?;
+                write(&mut ctx, &get_export, addr, s.as_bytes_with_nul())?;
+                Val::I32(addr)
+            }
+            GuestAbi::AssemblyScript => Val::I32(as_new(
+                &mut ctx,
+                &get_export,
+                AS_STRING_ID,
+                &guest_abi::encode_utf16(s),
+                true,
+            )?),
+            GuestAbi::TinyGo => {
+                let addr = malloc(&mut ctx, &get_export, s.len())?;
+                write(&mut ctx, &get_export, addr, s.as_bytes())?;
+                vals.push(Val::I32(addr));
+                Val::I32(s.len() as i32)
+            }
+        },
+        ParameterValue::VecBytes(b) => match guest_abi() {
+            GuestAbi::C | GuestAbi::TinyGo => {
+                let addr = malloc(&mut ctx, &get_export, b.len())?;
+                write(&mut ctx, &get_export, addr, b)?;
+                Val::I32(addr)
+                // TODO: check that the next parameter is the correct length
+            }
+            GuestAbi::AssemblyScript => {
+                Val::I32(as_new(&mut ctx, &get_export, AS_ARRAY_BUFFER_ID, b, true)?)
+            }
+        },
+    };
+    vals.push(val);
+    Ok(())
+}
+
+/// Encrypt a `VecBytes` result read from guest memory at `addr`, if the
+/// host set a payload key, and overwrite it in guest memory. The host
+/// owns the allocation, so the guest no longer uses it.
+fn seal_result<C: AsContextMut>(
+    ctx: &mut C,
+    get_export: &impl Fn(&mut C, &str) -> Option<Extern>,
+    addr: i32,
+    bytes: &mut Vec<u8>,
+) -> Result<()> {
+    if !payload_key::enabled() {
+        return Ok(());
     }
+    let len = bytes.len();
+    payload_key::seal_result(bytes)?;
+    write(ctx, get_export, addr, &vec![0; len])
 }
 
 /// Convert guest function return values to hyperlight return value.
@@ -256,6 +482,9 @@ pub fn val_to_hl_result<C: AsContextMut>(
     if let ReturnType::Void = rt {
         return Ok(get_flatbuffer_result::<()>(()));
     }
+    if rvs.len() > 1 {
+        return multi_value_result(rt, rvs);
+    }
     match (rt, rvs[0]) {
         (ReturnType::Int, Val::I32(i)) => Ok(get_flatbuffer_result::<i32>(i)),
         (ReturnType::UInt, Val::I32(u)) => Ok(get_flatbuffer_result::<u32>(u as u32)),
@@ -264,17 +493,54 @@ pub fn val_to_hl_result<C: AsContextMut>(
         /* todo: get_flatbuffer_result_from_bool is missing */
         (ReturnType::Float, Val::F32(f)) => Ok(get_flatbuffer_result::<f32>(f32::from_bits(f))),
         (ReturnType::Double, Val::F64(f)) => Ok(get_flatbuffer_result::<f64>(f64::from_bits(f))),
+        (ReturnType::String, Val::I32(p)) if guest_abi() == GuestAbi::AssemblyScript => {
+            // Managed objects are owned by the guest's garbage collector
+            let bytes = as_read(&mut ctx, &get_export, p, limits::max_return_value_size())?;
+            Ok(get_flatbuffer_result::<&str>(&as_decode_string(&bytes)?))
+        }
         (ReturnType::String, Val::I32(p)) => {
             // Track this allocation so it can be freed on next VM entry
             track_return_value_allocation(p);
             Ok(get_flatbuffer_result::<&str>(
-                read_cstr(&mut ctx, &get_export, p)?.to_str().map_err(|e| {
+                read_cstr(&mut ctx, &get_export, p, limits::max_return_value_size())?
+                    .to_str()
+                    .map_err(|e| {
+                        HyperlightGuestError::new(
+                            ErrorCode::GuestError,
+                            format!("non-UTF-8 c string in guest function return: {}", e),
+                        )
+                    })?,
+            ))
+        }
+        (ReturnType::String | ReturnType::VecBytes, Val::I64(packed))
+            if guest_abi() == GuestAbi::TinyGo =>
+        {
+            let (p, len) = guest_abi::unpack_ptr_len(packed);
+            let len = len as usize;
+            // Track this allocation so it can be freed on next VM entry
+            track_return_value_allocation(p);
+            limits::check_return_value_size(len)?;
+            let mut bytes = vec![0; len];
+            read(&mut ctx, &get_export, p, &mut bytes)?;
+            if rt == ReturnType::String {
+                let s = String::from_utf8(bytes).map_err(|e| {
                     HyperlightGuestError::new(
                         ErrorCode::GuestError,
-                        format!("non-UTF-8 c string in guest function return: {}", e),
+                        format!("non-UTF-8 string in guest function return: {}", e),
                     )
-                })?,
-            ))
+                })?;
+                Ok(get_flatbuffer_result::<&str>(&s))
+            } else {
+                seal_result(&mut ctx, &get_export, p, &mut bytes)?;
+                Ok(get_flatbuffer_result::<&[u8]>(&bytes))
+            }
+        }
+        (ReturnType::VecBytes, Val::I32(p)) if guest_abi() == GuestAbi::AssemblyScript => {
+            // Managed objects may still be referenced by the guest, so
+            // they are not overwritten
+            let mut bytes = as_read(&mut ctx, &get_export, p, limits::max_return_value_size())?;
+            payload_key::seal_result(&mut bytes)?;
+            Ok(get_flatbuffer_result::<&[u8]>(&bytes))
         }
         (ReturnType::VecBytes, Val::I32(ret)) => {
             // Track this allocation so it can be freed on next VM entry
@@ -282,8 +548,10 @@ pub fn val_to_hl_result<C: AsContextMut>(
             let mut size_bytes = [0; 4];
             read(&mut ctx, &get_export, ret, &mut size_bytes)?;
             let size = i32::from_le_bytes(size_bytes);
+            limits::check_return_value_size(size as usize)?;
             let mut bytes = vec![0; size as usize];
             read(&mut ctx, &get_export, ret + 4, &mut bytes)?;
+            seal_result(&mut ctx, &get_export, ret + 4, &mut bytes)?;
             Ok(get_flatbuffer_result::<&[u8]>(&bytes))
         }
         (_, _) => Err(HyperlightGuestError::new(
@@ -296,6 +564,40 @@ pub fn val_to_hl_result<C: AsContextMut>(
     }
 }
 
+/// Convert the results of a function that returns more than one value
+/// to a `VecBytes` return value, see [`crate::multi_value`]
+fn multi_value_result(rt: ReturnType, rvs: &[Val]) -> Result<Vec<u8>> {
+    if rt != ReturnType::VecBytes {
+        return Err(HyperlightGuestError::new(
+            ErrorCode::GuestError,
+            format!(
+                "Hyperlight/wasm function returns {} values, which must be returned as VecBytes, not {:?}",
+                rvs.len(),
+                rt
+            ),
+        ));
+    }
+    let values = rvs
+        .iter()
+        .map(|rv| match rv {
+            Val::I32(i) => Ok(WasmValue::I32(*i)),
+            Val::I64(l) => Ok(WasmValue::I64(*l)),
+            Val::F32(f) => Ok(WasmValue::F32(f32::from_bits(*f))),
+            Val::F64(f) => Ok(WasmValue::F64(f64::from_bits(*f))),
+            rv => Err(HyperlightGuestError::new(
+                ErrorCode::GuestError,
+                format!(
+                    "Hyperlight/wasm function return value unsupported: {:?}",
+                    rv
+                ),
+            )),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut bytes = multi_value::to_bytes(&values);
+    payload_key::seal_result(&mut bytes)?;
+    Ok(get_flatbuffer_result::<&[u8]>(&bytes))
+}
+
 /// Convert guest-provided WASM values to hyperlight parameters for host function calls.
 ///
 /// For String and VecBytes parameter types, the guest passes pointers to data in its own
@@ -307,48 +609,82 @@ pub fn val_to_hl_param<'a, C: AsContextMut>(
     get_export: impl Fn(&mut C, &str) -> Option<Extern>,
     state: &mut (impl Iterator<Item = &'a Val>, Option<u32>),
     pt: &ParameterType,
-) -> Option<ParameterValue> {
+) -> Result<ParameterValue> {
     let ps = &mut state.0;
     let last_vec_len = &mut state.1;
     if let Some(l) = *last_vec_len {
         if *pt == ParameterType::Int {
             *last_vec_len = None;
-            return Some(ParameterValue::Int(l as i32));
+            return Ok(ParameterValue::Int(l as i32));
         } else {
-            panic!("Host function details missing expected vector buffer length");
+            return Err(HyperlightGuestError::new(
+                ErrorCode::GuestError,
+                "Host function details missing expected vector buffer length".to_string(),
+            ));
         }
     }
     let Some(v) = ps.next() else {
-        panic!("Host function call missing parameter of type {:?}", pt);
+        return Err(HyperlightGuestError::new(
+            ErrorCode::GuestError,
+            format!("Host function call missing parameter of type {:?}", pt),
+        ));
     };
     match (pt, v) {
-        (ParameterType::Int, Val::I32(i)) => Some(ParameterValue::Int(*i)),
-        (ParameterType::UInt, Val::I32(u)) => Some(ParameterValue::UInt(*u as u32)),
-        (ParameterType::Long, Val::I64(l)) => Some(ParameterValue::Long(*l)),
-        (ParameterType::ULong, Val::I64(u)) => Some(ParameterValue::ULong(*u as u64)),
-        (ParameterType::Bool, Val::I32(b)) => Some(ParameterValue::Bool(*b == 0)),
-        (ParameterType::Float, Val::F32(f)) => Some(ParameterValue::Float(f32::from_bits(*f))),
-        (ParameterType::Double, Val::F64(f)) => Some(ParameterValue::Double(f64::from_bits(*f))),
-        (ParameterType::String, Val::I32(p)) => Some(ParameterValue::String(
-            read_cstr(ctx, &get_export, *p)
-                .unwrap()
-                .to_str()
-                .unwrap()
-                .to_string(),
-        )),
+        (ParameterType::Int, Val::I32(i)) => Ok(ParameterValue::Int(*i)),
+        (ParameterType::UInt, Val::I32(u)) => Ok(ParameterValue::UInt(*u as u32)),
+        (ParameterType::Long, Val::I64(l)) => Ok(ParameterValue::Long(*l)),
+        (ParameterType::ULong, Val::I64(u)) => Ok(ParameterValue::ULong(*u as u64)),
+        (ParameterType::Bool, Val::I32(b)) => Ok(ParameterValue::Bool(*b == 0)),
+        (ParameterType::Float, Val::F32(f)) => Ok(ParameterValue::Float(f32::from_bits(*f))),
+        (ParameterType::Double, Val::F64(f)) => Ok(ParameterValue::Double(f64::from_bits(*f))),
+        (ParameterType::String, Val::I32(p)) => Ok(ParameterValue::String(match guest_abi() {
+            GuestAbi::C => read_cstr(ctx, &get_export, *p, usize::MAX)?
+                .into_string()
+                .map_err(|e| {
+                    HyperlightGuestError::new(
+                        ErrorCode::GuestError,
+                        format!("non-UTF-8 c string in host function call: {}", e),
+                    )
+                })?,
+            GuestAbi::AssemblyScript => {
+                as_decode_string(&as_read(ctx, &get_export, *p, usize::MAX)?)?
+            }
+            GuestAbi::TinyGo => {
+                let Some(Val::I32(l)) = ps.next() else {
+                    return Err(HyperlightGuestError::new(
+                        ErrorCode::GuestError,
+                        "Host function call missing string length parameter".to_string(),
+                    ));
+                };
+                let mut bytes = vec![0; guest_len(*l)?];
+                read(ctx, &get_export, *p, &mut bytes)?;
+                String::from_utf8(bytes).map_err(|e| {
+                    HyperlightGuestError::new(
+                        ErrorCode::GuestError,
+                        format!("non-UTF-8 string in host function call: {}", e),
+                    )
+                })?
+            }
+        })),
         (ParameterType::VecBytes, Val::I32(p)) => {
             let Some(Val::I32(l)) = ps.next() else {
-                panic!("Host function call missing vecbytes length parameter");
+                return Err(HyperlightGuestError::new(
+                    ErrorCode::GuestError,
+                    "Host function call missing vecbytes length parameter".to_string(),
+                ));
             };
+            let mut bytes = vec![0; guest_len(*l)?];
             *last_vec_len = Some(*l as u32);
-            let mut bytes = vec![0; *l as usize];
-            read(ctx, &get_export, *p, &mut bytes).unwrap();
-            Some(ParameterValue::VecBytes(bytes.clone()))
+            read(ctx, &get_export, *p, &mut bytes)?;
+            Ok(ParameterValue::VecBytes(bytes))
         }
-        (_, _) => panic!(
-            "Host function return type combination unsupported: {:?} / {:?}",
-            pt, v
-        ),
+        (_, _) => Err(HyperlightGuestError::new(
+            ErrorCode::GuestError,
+            format!(
+                "Host function return type combination unsupported: {:?} / {:?}",
+                pt, v
+            ),
+        )),
     }
 }
 
@@ -371,18 +707,41 @@ pub fn hl_return_to_val<C: AsContextMut>(
         ReturnValue::Bool(b) => Ok(Val::I32(if b { 1 } else { 0 })),
         ReturnValue::Float(f) => Ok(Val::F32(f.to_bits())),
         ReturnValue::Double(f) => Ok(Val::F64(f.to_bits())),
-        ReturnValue::String(s) => {
-            let s = CString::new(s.as_str()).unwrap();
-            let nbytes = s.count_bytes() + 1; // include the NUL terminator
-            let addr = malloc(ctx, &get_export, nbytes)?;
-            write(ctx, &get_export, addr, s.as_bytes_with_nul())?;
-            Ok(Val::I32(addr))
-        }
+        ReturnValue::String(s) => match guest_abi() {
+            GuestAbi::C => {
+                let s = CString::new(s.as_str()).unwrap();
+                let nbytes = s.count_bytes() + 1; // include the NUL terminator
+                let addr = malloc(ctx, &get_export, nbytes)?;
+                write(ctx, &get_export, addr, s.as_bytes_with_nul())?;
+                Ok(Val::I32(addr))
+            }
+            GuestAbi::AssemblyScript => Ok(Val::I32(as_new(
+                ctx,
+                &get_export,
+                AS_STRING_ID,
+                &guest_abi::encode_utf16(&s),
+                false,
+            )?)),
+            GuestAbi::TinyGo => {
+                let addr = malloc(ctx, &get_export, s.len())?;
+                write(ctx, &get_export, addr, s.as_bytes())?;
+                Ok(Val::I64(pack_ptr_len(addr, s.len())?))
+            }
+        },
         ReturnValue::VecBytes(b) => {
-            let addr = malloc(ctx, &get_export, b.len())?;
-            write(ctx, &get_export, addr, b.as_ref())?;
-            Ok(Val::I32(addr))
-            // TODO: check that the next parameter is the correct length
+            // VecBytes are returned as a packed i64 regardless of the ABI,
+            // see hostfuncs::hostfunc_type
+            let addr = match guest_abi() {
+                GuestAbi::C | GuestAbi::TinyGo => {
+                    let addr = malloc(ctx, &get_export, b.len())?;
+                    write(ctx, &get_export, addr, b.as_ref())?;
+                    addr
+                }
+                GuestAbi::AssemblyScript => {
+                    as_new(ctx, &get_export, AS_ARRAY_BUFFER_ID, &b, false)?
+                }
+            };
+            Ok(Val::I64(pack_ptr_len(addr, b.len())?))
         }
         ReturnValue::Void(()) => Ok(Val::I32(0)),
     }
@@ -0,0 +1,98 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The memory used by the guest, gathered in the guest and fetched by
+//! the host with the `GetMemoryStats` guest function, or with the
+//! `TrimMemory` guest function after the guest has released what it can.
+//!
+//! The statistics are encoded as the little-endian `u64` number of bytes
+//! of memory mapped for wasmtime and the little-endian `u64` size of the
+//! runtime's heap, followed by a little-endian `u32`
+//! count of linear memories, each encoded as its name and its
+//! little-endian `u64` size in bytes. Names are encoded as a
+//! little-endian `u32` length followed by their UTF-8 bytes.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// The guest function that returns the guest's [`GuestMemoryStats`]
+pub const GET_MEMORY_STATS_FUNCTION: &str = "GetMemoryStats";
+
+/// The guest function that releases the memory the loaded guest no
+/// longer needs and returns the guest's [`GuestMemoryStats`] afterwards
+pub const TRIM_MEMORY_FUNCTION: &str = "TrimMemory";
+
+/// The memory used by the guest
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GuestMemoryStats {
+    /// The bytes of memory mapped, as they are first touched, for
+    /// wasmtime's linear memories, tables and code
+    pub mapped_bytes: u64,
+    /// The size in bytes of the heap the runtime allocates from, a fixed
+    /// region of guest memory
+    pub heap_bytes: u64,
+    /// The names and sizes in bytes of the linear memories exported by
+    /// the loaded module. Components do not report their memories.
+    pub linear_memories: Vec<(String, u64)>,
+}
+
+fn take<const N: usize>(bytes: &mut &[u8]) -> Option<[u8; N]> {
+    let (value, rest) = bytes.split_first_chunk::<N>()?;
+    *bytes = rest;
+    Some(*value)
+}
+
+fn take_str(bytes: &mut &[u8]) -> Option<String> {
+    let len = u32::from_le_bytes(take(bytes)?) as usize;
+    if bytes.len() < len {
+        return None;
+    }
+    let (s, rest) = bytes.split_at(len);
+    *bytes = rest;
+    String::from_utf8(s.to_vec()).ok()
+}
+
+impl GuestMemoryStats {
+    /// Encode the statistics to be passed to the host
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&self.mapped_bytes.to_le_bytes());
+        bytes.extend_from_slice(&self.heap_bytes.to_le_bytes());
+        bytes.extend_from_slice(&(self.linear_memories.len() as u32).to_le_bytes());
+        for (name, size) in &self.linear_memories {
+            bytes.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(name.as_bytes());
+            bytes.extend_from_slice(&size.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Decode statistics encoded with [`to_bytes`](Self::to_bytes),
+    /// returning `None` if the encoding is malformed
+    pub fn from_bytes(mut bytes: &[u8]) -> Option<Self> {
+        let mapped_bytes = u64::from_le_bytes(take(&mut bytes)?);
+        let heap_bytes = u64::from_le_bytes(take(&mut bytes)?);
+        let count = u32::from_le_bytes(take(&mut bytes)?);
+        let linear_memories = (0..count)
+            .map(|_| Some((take_str(&mut bytes)?, u64::from_le_bytes(take(&mut bytes)?))))
+            .collect::<Option<Vec<_>>>()?;
+        bytes.is_empty().then_some(Self {
+            mapped_bytes,
+            heap_bytes,
+            linear_memories,
+        })
+    }
+}
@@ -18,10 +18,11 @@ use alloc::string::ToString;
 use alloc::vec::Vec;
 use alloc::{format, vec};
 use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, Ordering};
 
 use hyperlight_common::flatbuffer_wrappers::function_call::FunctionCall;
 use hyperlight_common::flatbuffer_wrappers::function_types::{
-    ParameterType, ParameterValue, ReturnType,
+    FunctionCallResult, ParameterType, ParameterValue, ReturnType,
 };
 use hyperlight_common::flatbuffer_wrappers::guest_error::ErrorCode;
 use hyperlight_common::flatbuffer_wrappers::util::get_flatbuffer_result;
@@ -30,22 +31,35 @@ use hyperlight_guest_bin::guest_function::definition::GuestFunctionDefinition;
 use hyperlight_guest_bin::guest_function::register::register_function;
 use hyperlight_guest_bin::host_comm::print_output_with_host_print;
 use spin::Mutex;
-use tracing::instrument;
-use wasmtime::{Config, Engine, Linker, Module, Store, Val};
+use tracing::{info_span, instrument};
+use wasmtime::{Engine, ExternType, FuncType, Linker, Module, Mutability, Store, Val, ValType};
 
-use crate::{hostfuncs, map_wasmtime_error, marshal, platform, wasip1};
+use crate::guest_functions::{self, GuestFunction};
+use crate::instance_state::{self, GlobalValue, InstanceLayout};
+use crate::linear_memories::{self, LinearMemory};
+use crate::memory_stats::{self, GuestMemoryStats};
+use crate::{
+    abi_version, batch, call_tracker, cli_environment, dispatch, dynamic_component, engine,
+    epoch_deadline, guest_abort, host_cache, host_error, hostfuncs, limits, log_buffer,
+    map_wasmtime_error, mapped_buffers, marshal, output_capture, payload_key, platform, random,
+    reentry, staged_params, state_cells, vfs, wasip1, wasip1_fs, wasip2, wasm_limits,
+};
 
 // Set by transition to WasmSandbox (by init_wasm_runtime)
-static CUR_ENGINE: Mutex<Option<Engine>> = Mutex::new(None);
+pub(crate) static CUR_ENGINE: Mutex<Option<Engine>> = Mutex::new(None);
 static CUR_LINKER: Mutex<Option<Linker<()>>> = Mutex::new(None);
+pub(crate) static CUR_HOST_FUNCS: Mutex<Vec<hostfuncs::HostFunctionDefinition>> =
+    Mutex::new(Vec::new());
 // Set by transition to LoadedWasmSandbox (by load_wasm_module/load_wasm_module_phys)
 static CUR_MODULE: Mutex<Option<Module>> = Mutex::new(None);
 static CUR_STORE: Mutex<Option<Store<()>>> = Mutex::new(None);
 static CUR_INSTANCE: Mutex<Option<wasmtime::Instance>> = Mutex::new(None);
+// Whether module memories are faulted in when modules are loaded
+static PREFAULT_MEMORY: AtomicBool = AtomicBool::new(false);
 
 #[no_mangle]
-#[instrument(skip_all, level = "Info")]
-pub fn guest_dispatch_function(function_call: FunctionCall) -> Result<Vec<u8>> {
+#[instrument(skip_all, level = "Info", fields(function_name = %function_call.function_name))]
+pub fn guest_dispatch_function(mut function_call: FunctionCall) -> Result<Vec<u8>> {
     let mut store = CUR_STORE.lock();
     let store = store.deref_mut().as_mut().ok_or(HyperlightGuestError::new(
         ErrorCode::GuestError,
@@ -71,59 +85,96 @@ pub fn guest_dispatch_function(function_call: FunctionCall) -> Result<Vec<u8>> {
             "Function not found".to_string(),
         ))?;
 
+    let params = function_call.parameters.as_deref_mut().unwrap_or_default();
+    staged_params::take_staged(params)?;
+    payload_key::open_params(params)?;
     let mut w_params = vec![];
-    for f_param in (function_call.parameters)
-        .as_ref()
-        .unwrap_or(&vec![])
-        .iter()
-    {
-        w_params.push(marshal::hl_param_to_val(
-            &mut *store,
-            |ctx, name| instance.get_export(ctx, name),
-            f_param,
-        )?);
-    }
+    info_span!("marshal_params").in_scope(|| {
+        params.iter().try_for_each(|f_param| {
+            marshal::hl_param_to_val(
+                &mut *store,
+                |ctx, name| instance.get_export(ctx, name),
+                f_param,
+                &mut w_params,
+            )
+        })
+    })?;
     let is_void = ReturnType::Void == function_call.expected_return_type;
-    let n_results = if is_void { 0 } else { 1 };
+    // Functions without results that are called for a value fail in the
+    // call, rather than when the missing result is read
+    let n_results = if is_void {
+        0
+    } else {
+        func.ty(&*store).results().len().max(1)
+    };
     let mut results = vec![Val::I32(0); n_results];
-    func.call(&mut *store, &w_params, &mut results)
-        .map_err(map_wasmtime_error)?;
-    marshal::val_to_hl_result(
-        &mut *store,
-        |ctx, name| instance.get_export(ctx, name),
-        function_call.expected_return_type,
-        &results,
-    )
+    let memory = instance.get_memory(&mut *store, "memory");
+    let memory_before = memory.map(|m| m.data_size(&*store));
+    call_tracker::begin_call();
+    guest_abort::begin_call();
+    host_error::begin_call();
+    wasm_limits::begin_call();
+    epoch_deadline::begin_call(&mut *store);
+    let result = info_span!("call_wasm_function")
+        .in_scope(|| func.call(&mut *store, &w_params, &mut results));
+    epoch_deadline::end_call();
+    payload_key::zero_params(params);
+    let memory_after = memory.map(|m| m.data_size(&*store));
+    call_tracker::end_call(
+        memory_before
+            .zip(memory_after)
+            .map(|(before, after)| after as i64 - before as i64),
+        memory_after.map(|size| size as u64),
+    );
+    result.map_err(epoch_deadline::map_call_error)?;
+    info_span!("marshal_result").in_scope(|| {
+        marshal::val_to_hl_result(
+            &mut *store,
+            |ctx, name| instance.get_export(ctx, name),
+            function_call.expected_return_type,
+            &results,
+        )
+    })
 }
 
 #[instrument(skip_all, level = "Info")]
-fn init_wasm_runtime(function_call: FunctionCall) -> Result<Vec<u8>> {
-    let mut config = Config::new();
-    // Enable x86_float_abi_ok only for the latest Wasmtime native x86 target.
-    // Safety:
-    // We are using hyperlight cargo to build the guest which
-    // sets the Rust target to be compiled with the hard-float ABI manually via
-    // `-Zbuild-std` and a custom target JSON configuration
-    // See https://github.com/bytecodealliance/wasmtime/pull/11553
-    #[cfg(all(not(feature = "wasmtime_lts"), not(pulley)))]
-    unsafe {
-        config.x86_float_abi_ok(true)
+/// Run a batch of calls to module functions one after another, stopping
+/// at the first that fails, see [`crate::batch`]
+fn call_batch(mut function_call: FunctionCall) -> Result<Vec<u8>> {
+    let params = function_call.parameters.as_deref_mut().unwrap_or_default();
+    payload_key::open_params(params)?;
+    let calls = match &*params {
+        [ParameterValue::VecBytes(bytes)] => batch::calls_from_bytes(bytes),
+        _ => None,
     };
-
-    config.with_custom_code_memory(Some(alloc::sync::Arc::new(platform::WasmtimeCodeMemory {})));
-    #[cfg(gdb)]
-    config.debug_info(true);
-    #[cfg(pulley)]
-    config.target("pulley64").map_err(|_| {
+    payload_key::zero_params(params);
+    let calls = calls.ok_or_else(|| {
         HyperlightGuestError::new(
-            ErrorCode::GuestError,
-            "Failed to set wasmtime target: pulley64".to_string(),
+            ErrorCode::GuestFunctionParameterTypeMismatch,
+            "Invalid parameters passed to CallBatch".to_string(),
         )
     })?;
-    let engine = Engine::new(&config).map_err(map_wasmtime_error)?;
-    let mut linker = Linker::new(&engine);
-    wasip1::register_handlers(&mut linker)?;
+    let mut results = Vec::with_capacity(calls.len());
+    for call in calls {
+        let result = guest_dispatch_function(call)?;
+        let result = FunctionCallResult::try_from(result.as_slice())
+            .map_err(|e| {
+                HyperlightGuestError::new(
+                    ErrorCode::GuestError,
+                    format!("CallBatch: malformed result: {}", e),
+                )
+            })?
+            .into_inner()
+            .map_err(|e| HyperlightGuestError::new(e.code, e.message))?;
+        results.push(result);
+    }
+    let mut bytes = batch::results_to_bytes(&results);
+    payload_key::seal_result(&mut bytes)?;
+    Ok(get_flatbuffer_result::<&[u8]>(&bytes))
+}
 
+#[instrument(skip_all, level = "Info")]
+fn init_wasm_runtime(function_call: FunctionCall) -> Result<Vec<u8>> {
     // Parse host function details pushed by the host as a parameter
     let params = function_call.parameters.as_ref().ok_or_else(|| {
         HyperlightGuestError::new(
@@ -143,11 +194,32 @@ fn init_wasm_runtime(function_call: FunctionCall) -> Result<Vec<u8>> {
         None => {
             return Err(HyperlightGuestError::new(
                 ErrorCode::GuestFunctionParameterTypeMismatch,
-                "InitWasmRuntime: expected 1 parameter, got 0".to_string(),
+                "InitWasmRuntime: expected 2 parameters, got 0".to_string(),
             ))
         }
     };
 
+    let runtime_config = engine::parse_runtime_config(params.get(1))?;
+    let engine = engine::new_engine(&runtime_config)?;
+    log_buffer::set_max_level(runtime_config.guest_log_level.unwrap_or(0));
+    limits::set_max_return_value_size(runtime_config.max_return_value_size);
+    limits::set_sandbox_limits(&runtime_config);
+    random::configure(&runtime_config);
+    output_capture::configure(&runtime_config);
+    wasip1::configure(&runtime_config);
+    wasip2::configure(&runtime_config);
+    cli_environment::configure(&runtime_config)?;
+    vfs::configure(&runtime_config)?;
+    wasip1_fs::configure(&runtime_config)?;
+    epoch_deadline::configure(&runtime_config);
+    wasm_limits::configure(&runtime_config);
+    dynamic_component::configure(&runtime_config);
+    PREFAULT_MEMORY.store(runtime_config.prefault_memory, Ordering::Relaxed);
+    abi_version::set_required_abi_version(
+        runtime_config.min_guest_abi_version,
+        runtime_config.max_guest_abi_version,
+    );
+
     let hfd: hostfuncs::HostFunctionDetails = bytes.as_slice().try_into().map_err(|e| {
         HyperlightGuestError::new(
             ErrorCode::GuestError,
@@ -155,6 +227,28 @@ fn init_wasm_runtime(function_call: FunctionCall) -> Result<Vec<u8>> {
         )
     })?;
     let hostfuncs = hfd.host_functions.unwrap_or_default();
+    let linker = build_linker(&engine, &hostfuncs)?;
+
+    *CUR_ENGINE.lock() = Some(engine);
+    *CUR_LINKER.lock() = Some(linker);
+    *CUR_HOST_FUNCS.lock() = hostfuncs;
+    Ok(get_flatbuffer_result::<i32>(0))
+}
+
+/// Build a linker exposing the wasip1 shims and the given host functions,
+/// with host function signatures matching the current guest ABI.
+#[instrument(skip_all, level = "Info")]
+fn build_linker(
+    engine: &Engine,
+    hostfuncs: &[hostfuncs::HostFunctionDefinition],
+) -> Result<Linker<()>> {
+    let mut linker = Linker::new(engine);
+    wasip1::register_handlers(&mut linker)?;
+    wasip1_fs::register_handlers(&mut linker)?;
+    log_buffer::register_handlers(&mut linker)?;
+    limits::register_handlers(&mut linker)?;
+    state_cells::register_handlers(&mut linker)?;
+    mapped_buffers::register_handlers(&mut linker)?;
 
     for hostfunc in hostfuncs.iter() {
         let captured = hostfunc.clone();
@@ -162,7 +256,7 @@ fn init_wasm_runtime(function_call: FunctionCall) -> Result<Vec<u8>> {
             .func_new(
                 "env",
                 &hostfunc.function_name,
-                hostfuncs::hostfunc_type(hostfunc, &engine)?,
+                hostfuncs::hostfunc_type(hostfunc, engine)?,
                 move |c, ps, rs| {
                     hostfuncs::call(&captured, c, ps, rs)
                         .map_err(|e| wasmtime::Error::msg(format!("{:?}", e)))
@@ -170,12 +264,719 @@ fn init_wasm_runtime(function_call: FunctionCall) -> Result<Vec<u8>> {
             )
             .map_err(map_wasmtime_error)?;
     }
+    Ok(linker)
+}
 
-    *CUR_ENGINE.lock() = Some(engine);
-    *CUR_LINKER.lock() = Some(linker);
+#[instrument(skip_all, level = "Info")]
+fn set_guest_abi(function_call: FunctionCall) -> Result<Vec<u8>> {
+    let abi = match function_call.parameters.as_deref() {
+        Some([ParameterValue::Int(abi)]) => marshal::GuestAbi::try_from(*abi)?,
+        _ => {
+            return Err(HyperlightGuestError::new(
+                ErrorCode::GuestFunctionParameterTypeMismatch,
+                "Invalid parameters passed to SetGuestAbi".to_string(),
+            ));
+        }
+    };
+    marshal::set_guest_abi(abi);
+
+    // Host function signatures depend on the ABI, so rebuild the linker
+    let engine = CUR_ENGINE.lock();
+    let engine = engine.deref().as_ref().ok_or(HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        "SetGuestAbi called before InitWasmRuntime".to_string(),
+    ))?;
+    *CUR_LINKER.lock() = Some(build_linker(engine, &CUR_HOST_FUNCS.lock())?);
+    Ok(get_flatbuffer_result::<i32>(0))
+}
+
+/// Add host functions registered after InitWasmRuntime, replacing any
+/// with the same names, so that modules loaded afterwards can import them
+#[instrument(skip_all, level = "Info")]
+fn add_host_functions(function_call: FunctionCall) -> Result<Vec<u8>> {
+    let bytes = match function_call.parameters.as_deref() {
+        Some([ParameterValue::VecBytes(bytes)]) => bytes,
+        _ => {
+            return Err(HyperlightGuestError::new(
+                ErrorCode::GuestFunctionParameterTypeMismatch,
+                "Invalid parameters passed to AddHostFunctions".to_string(),
+            ));
+        }
+    };
+    let hfd: hostfuncs::HostFunctionDetails = bytes.as_slice().try_into().map_err(|e| {
+        HyperlightGuestError::new(
+            ErrorCode::GuestError,
+            alloc::format!("Failed to parse host function details: {:?}", e),
+        )
+    })?;
+
+    let engine = CUR_ENGINE.lock();
+    let engine = engine.deref().as_ref().ok_or(HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        "AddHostFunctions called before InitWasmRuntime".to_string(),
+    ))?;
+    let mut hostfuncs = CUR_HOST_FUNCS.lock();
+    for hostfunc in hfd.host_functions.unwrap_or_default() {
+        hostfuncs.retain(|h| h.function_name != hostfunc.function_name);
+        hostfuncs.push(hostfunc);
+    }
+    *CUR_LINKER.lock() = Some(build_linker(engine, &hostfuncs)?);
     Ok(get_flatbuffer_result::<i32>(0))
 }
 
+/// Rebuild the linker with only the host functions named in the
+/// comma-separated parameter, so that the module loaded next can only
+/// import those
+#[instrument(skip_all, level = "Info")]
+fn restrict_host_functions(function_call: FunctionCall) -> Result<Vec<u8>> {
+    let Some([ParameterValue::String(names)]) = function_call.parameters.as_deref() else {
+        return Err(HyperlightGuestError::new(
+            ErrorCode::GuestFunctionParameterTypeMismatch,
+            "Invalid parameters passed to RestrictHostFunctions".to_string(),
+        ));
+    };
+    let engine = CUR_ENGINE.lock();
+    let engine = engine.deref().as_ref().ok_or(HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        "RestrictHostFunctions called before InitWasmRuntime".to_string(),
+    ))?;
+    let hostfuncs = CUR_HOST_FUNCS.lock();
+    let mut allowed = Vec::new();
+    for name in names.split(',').filter(|name| !name.is_empty()) {
+        let hostfunc = hostfuncs
+            .iter()
+            .find(|h| h.function_name == name)
+            .ok_or_else(|| {
+                HyperlightGuestError::new(
+                    ErrorCode::GuestError,
+                    format!("{} is not a registered host function", name),
+                )
+            })?;
+        allowed.push(hostfunc.clone());
+    }
+    *CUR_LINKER.lock() = Some(build_linker(engine, &allowed)?);
+    Ok(get_flatbuffer_result::<()>(()))
+}
+
+/// The export guests can provide to declare the version of the guest ABI
+/// they target, checked against the range required by the host
+const ABI_VERSION_EXPORT: &str = "hlwasm_abi_version";
+
+/// Fail if the host requires a guest ABI version that the module does
+/// not declare or does not match
+fn check_abi_version(store: &mut Store<()>, instance: &wasmtime::Instance) -> Result<()> {
+    if !abi_version::is_required() {
+        return Ok(());
+    }
+    let version = match instance.get_func(&mut *store, ABI_VERSION_EXPORT) {
+        Some(func) => {
+            let func = func
+                .typed::<(), i32>(&*store)
+                .map_err(|_| abi_version::invalid_export(ABI_VERSION_EXPORT))?;
+            Some(func.call(&mut *store, ()).map_err(map_wasmtime_error)? as u32)
+        }
+        None => None,
+    };
+    abi_version::check(version, ABI_VERSION_EXPORT)
+}
+
+/// The type an export must have to be called with the parameter and
+/// return types of `d`. Parameters are passed as they are to host
+/// functions, but buffers are returned as a pointer to a length-prefixed
+/// buffer, except by TinyGo modules.
+fn export_type(d: &hostfuncs::HostFunctionDefinition, engine: &Engine) -> Result<FuncType> {
+    let import_type = hostfuncs::hostfunc_type(d, engine)?;
+    let results: Vec<ValType> = match d.return_type {
+        ReturnType::VecBytes if marshal::guest_abi() != marshal::GuestAbi::TinyGo => {
+            vec![ValType::I32]
+        }
+        _ => import_type.results().collect(),
+    };
+    Ok(FuncType::new(engine, import_type.params(), results))
+}
+
+/// Check that the module exports each of the given functions, with the
+/// given types if they have parameter types, returning a description of
+/// each export that is missing or has the wrong type, separated by
+/// semicolons
+#[instrument(skip_all, level = "Info")]
+fn check_exports(function_call: FunctionCall) -> Result<Vec<u8>> {
+    let bytes = match function_call.parameters.as_deref() {
+        Some([ParameterValue::VecBytes(bytes)]) => bytes,
+        _ => {
+            return Err(HyperlightGuestError::new(
+                ErrorCode::GuestFunctionParameterTypeMismatch,
+                "Invalid parameters passed to CheckExports".to_string(),
+            ));
+        }
+    };
+    let hfd: hostfuncs::HostFunctionDetails = bytes.as_slice().try_into().map_err(|e| {
+        HyperlightGuestError::new(
+            ErrorCode::GuestError,
+            alloc::format!("Failed to parse required exports: {:?}", e),
+        )
+    })?;
+    let engine = CUR_ENGINE.lock();
+    let engine = engine.deref().as_ref().ok_or(HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        "CheckExports called before InitWasmRuntime".to_string(),
+    ))?;
+    let mut store = CUR_STORE.lock();
+    let store = store.deref_mut().as_mut().ok_or(HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        "No wasm store available".to_string(),
+    ))?;
+    let instance = CUR_INSTANCE.lock();
+    let instance = instance.deref().as_ref().ok_or(HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        "No wasm instance available".to_string(),
+    ))?;
+
+    let mut problems = Vec::new();
+    for export in hfd.host_functions.unwrap_or_default() {
+        let Some(func) = instance.get_func(&mut *store, &export.function_name) else {
+            problems.push(format!("{} is not exported", export.function_name));
+            continue;
+        };
+        if export.parameter_types.is_none() {
+            continue;
+        }
+        let expected = export_type(&export, engine)?;
+        let actual = func.ty(&*store);
+        if !actual.matches(&expected) || !expected.matches(&actual) {
+            problems.push(format!(
+                "{} has type {}, expected {}",
+                export.function_name, actual, expected
+            ));
+        }
+    }
+    Ok(get_flatbuffer_result::<&str>(&problems.join("; ")))
+}
+
+/// List the functions the module exports, see [`guest_functions`]
+#[instrument(skip_all, level = "Info")]
+fn get_guest_functions(_function_call: FunctionCall) -> Result<Vec<u8>> {
+    let module = CUR_MODULE.lock();
+    let module = module.deref().as_ref().ok_or(HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        "No wasm module loaded".to_string(),
+    ))?;
+    let functions: Vec<_> = module
+        .exports()
+        .filter_map(|export| {
+            let ExternType::Func(ty) = export.ty() else {
+                return None;
+            };
+            Some(GuestFunction {
+                name: export.name().to_string(),
+                params: ty.params().map(|ty| ty.to_string()).collect(),
+                results: ty.results().map(|ty| ty.to_string()).collect(),
+            })
+        })
+        .collect();
+    Ok(get_flatbuffer_result::<&[u8]>(&guest_functions::to_bytes(
+        &functions,
+    )))
+}
+
+/// The memory whose contents are part of the instance's state
+const STATE_MEMORY_EXPORT: &str = "memory";
+
+fn global_value(val: Val) -> Option<GlobalValue> {
+    match val {
+        Val::I32(v) => Some(GlobalValue::I32(v)),
+        Val::I64(v) => Some(GlobalValue::I64(v)),
+        Val::F32(v) => Some(GlobalValue::F32(v)),
+        Val::F64(v) => Some(GlobalValue::F64(v)),
+        _ => None,
+    }
+}
+
+/// The exported mutable globals of `module` whose values can be part of
+/// the instance's state
+fn state_globals(module: &Module) -> impl Iterator<Item = &str> {
+    module.exports().filter_map(|export| match export.ty() {
+        ExternType::Global(ty) if ty.mutability() == Mutability::Var => Some(export.name()),
+        _ => None,
+    })
+}
+
+/// Return the memory used by the guest, see [`memory_stats`]
+#[instrument(skip_all, level = "Info")]
+fn get_memory_stats(_function_call: FunctionCall) -> Result<Vec<u8>> {
+    Ok(get_flatbuffer_result::<&[u8]>(
+        &guest_memory_stats().to_bytes(),
+    ))
+}
+
+/// The memory used by the guest and the loaded module's linear memories
+fn guest_memory_stats() -> GuestMemoryStats {
+    let mut linear_memories = Vec::new();
+    let mut store = CUR_STORE.lock();
+    let instance = CUR_INSTANCE.lock();
+    let module = CUR_MODULE.lock();
+    if let (Some(store), Some(instance), Some(module)) =
+        (store.deref_mut(), instance.deref(), module.deref())
+    {
+        for export in module.exports() {
+            if !matches!(export.ty(), ExternType::Memory(_)) {
+                continue;
+            }
+            if let Some(memory) = instance.get_memory(&mut *store, export.name()) {
+                linear_memories.push((export.name().to_string(), memory.data_size(&*store) as u64));
+            }
+        }
+    }
+    GuestMemoryStats {
+        mapped_bytes: platform::mapped_bytes(),
+        heap_bytes: platform::heap_bytes(),
+        linear_memories,
+    }
+}
+
+/// Return the guest addresses of the module's memories, see
+/// [`linear_memories`]
+#[instrument(skip_all, level = "Info")]
+fn get_linear_memories(_function_call: FunctionCall) -> Result<Vec<u8>> {
+    let mut memories = Vec::new();
+    let mut store = CUR_STORE.lock();
+    let instance = CUR_INSTANCE.lock();
+    let module = CUR_MODULE.lock();
+    if let (Some(store), Some(instance), Some(module)) =
+        (store.deref_mut(), instance.deref(), module.deref())
+    {
+        for export in module.exports() {
+            if !matches!(export.ty(), ExternType::Memory(_)) {
+                continue;
+            }
+            if let Some(memory) = instance.get_memory(&mut *store, export.name()) {
+                memories.push(LinearMemory {
+                    name: export.name().to_string(),
+                    guest_addr: memory.data_ptr(&*store) as u64,
+                    size: memory.data_size(&*store) as u64,
+                });
+            }
+        }
+    }
+    Ok(get_flatbuffer_result::<&[u8]>(&linear_memories::to_bytes(
+        &memories,
+    )))
+}
+
+/// Return the instance's memory size and globals, see [`instance_state`]
+#[instrument(skip_all, level = "Info")]
+fn get_instance_layout(_function_call: FunctionCall) -> Result<Vec<u8>> {
+    let mut store = CUR_STORE.lock();
+    let store = store.deref_mut().as_mut().ok_or(HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        "No wasm store available".to_string(),
+    ))?;
+    let instance = CUR_INSTANCE.lock();
+    let instance = instance.deref().as_ref().ok_or(HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        "No wasm instance available".to_string(),
+    ))?;
+    let module = CUR_MODULE.lock();
+    let module = module.deref().as_ref().ok_or(HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        "No wasm module loaded".to_string(),
+    ))?;
+
+    let memory_size = instance
+        .get_memory(&mut *store, STATE_MEMORY_EXPORT)
+        .map_or(0, |memory| memory.data_size(&*store) as u64);
+    let mut globals = Vec::new();
+    for name in state_globals(module) {
+        let Some(global) = instance.get_global(&mut *store, name) else {
+            continue;
+        };
+        let value = global_value(global.get(&mut *store)).ok_or(HyperlightGuestError::new(
+            ErrorCode::GuestError,
+            format!("Global {} has a type that cannot be serialized", name),
+        ))?;
+        globals.push((name.to_string(), value));
+    }
+    let layout = InstanceLayout {
+        memory_size,
+        globals,
+    };
+    Ok(get_flatbuffer_result::<&[u8]>(&layout.to_bytes()))
+}
+
+/// Grow the instance's memory to the size in a layout, zero it and set
+/// the instance's globals to the layout's values
+#[instrument(skip_all, level = "Info")]
+fn reset_instance(function_call: FunctionCall) -> Result<Vec<u8>> {
+    let layout = match function_call.parameters.as_deref() {
+        Some([ParameterValue::VecBytes(layout)]) => InstanceLayout::from_bytes(layout),
+        _ => None,
+    }
+    .ok_or(HyperlightGuestError::new(
+        ErrorCode::GuestFunctionParameterTypeMismatch,
+        "Invalid parameters passed to ResetInstance".to_string(),
+    ))?;
+    let mut store = CUR_STORE.lock();
+    let store = store.deref_mut().as_mut().ok_or(HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        "No wasm store available".to_string(),
+    ))?;
+    let instance = CUR_INSTANCE.lock();
+    let instance = instance.deref().as_ref().ok_or(HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        "No wasm instance available".to_string(),
+    ))?;
+
+    match instance.get_memory(&mut *store, STATE_MEMORY_EXPORT) {
+        Some(memory) => {
+            let size = memory.data_size(&*store) as u64;
+            let page_size = memory.page_size(&*store);
+            if layout.memory_size < size || layout.memory_size % page_size != 0 {
+                return Err(HyperlightGuestError::new(
+                    ErrorCode::GuestError,
+                    format!(
+                        "Cannot resize memory of {} bytes to {} bytes",
+                        size, layout.memory_size
+                    ),
+                ));
+            }
+            memory
+                .grow(&mut *store, (layout.memory_size - size) / page_size)
+                .map_err(map_wasmtime_error)?;
+            memory.data_mut(&mut *store).fill(0);
+        }
+        None if layout.memory_size == 0 => {}
+        None => {
+            return Err(HyperlightGuestError::new(
+                ErrorCode::GuestError,
+                "The module does not export its memory".to_string(),
+            ));
+        }
+    }
+    for (name, value) in layout.globals {
+        let global = instance
+            .get_global(&mut *store, &name)
+            .ok_or(HyperlightGuestError::new(
+                ErrorCode::GuestError,
+                format!("Global {} not found", name),
+            ))?;
+        let value = match value {
+            GlobalValue::I32(v) => Val::I32(v),
+            GlobalValue::I64(v) => Val::I64(v),
+            GlobalValue::F32(v) => Val::F32(v),
+            GlobalValue::F64(v) => Val::F64(v),
+        };
+        global.set(&mut *store, value).map_err(map_wasmtime_error)?;
+    }
+    Ok(get_flatbuffer_result::<()>(()))
+}
+
+/// Read up to [`instance_state::CHUNK_SIZE`] bytes of the instance's
+/// memory, from the offset given by the first parameter
+#[instrument(skip_all, level = "Info")]
+fn read_instance_memory(function_call: FunctionCall) -> Result<Vec<u8>> {
+    let Some(&[ParameterValue::ULong(offset), ParameterValue::Int(len)]) =
+        function_call.parameters.as_deref()
+    else {
+        return Err(HyperlightGuestError::new(
+            ErrorCode::GuestFunctionParameterTypeMismatch,
+            "Invalid parameters passed to ReadInstanceMemory".to_string(),
+        ));
+    };
+    let mut store = CUR_STORE.lock();
+    let store = store.deref_mut().as_mut().ok_or(HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        "No wasm store available".to_string(),
+    ))?;
+    let instance = CUR_INSTANCE.lock();
+    let instance = instance.deref().as_ref().ok_or(HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        "No wasm instance available".to_string(),
+    ))?;
+    let memory = instance
+        .get_memory(&mut *store, STATE_MEMORY_EXPORT)
+        .ok_or(HyperlightGuestError::new(
+            ErrorCode::GuestError,
+            "The module does not export its memory".to_string(),
+        ))?;
+    let len = (len.max(0) as usize).min(instance_state::CHUNK_SIZE);
+    let chunk = (offset as usize)
+        .checked_add(len)
+        .and_then(|end| memory.data(&*store).get(offset as usize..end))
+        .ok_or(HyperlightGuestError::new(
+            ErrorCode::GuestError,
+            format!(
+                "Memory read at {} of {} bytes is out of bounds",
+                offset, len
+            ),
+        ))?;
+    Ok(get_flatbuffer_result::<&[u8]>(chunk))
+}
+
+/// Read up to [`instance_state::CHUNK_SIZE`] bytes of guest memory, from
+/// the guest virtual address given by the first parameter
+#[instrument(skip_all, level = "Info")]
+fn read_guest_memory(function_call: FunctionCall) -> Result<Vec<u8>> {
+    let Some(&[ParameterValue::ULong(addr), ParameterValue::Int(len)]) =
+        function_call.parameters.as_deref()
+    else {
+        return Err(HyperlightGuestError::new(
+            ErrorCode::GuestFunctionParameterTypeMismatch,
+            "Invalid parameters passed to ReadGuestMemory".to_string(),
+        ));
+    };
+    let len = (len.max(0) as usize).min(instance_state::CHUNK_SIZE);
+    let range = addr
+        .checked_add(len as u64)
+        .map(|end| addr..end)
+        .filter(|range| platform::is_readable(range.clone()))
+        .ok_or(HyperlightGuestError::new(
+            ErrorCode::GuestError,
+            format!("Guest memory at {:#x} of {} bytes is not mapped", addr, len),
+        ))?;
+    if range.is_empty() {
+        return Ok(get_flatbuffer_result::<&[u8]>(&[]));
+    }
+    // Safety: every page of the range is mapped readable, and the bytes
+    // are copied out before anything else runs in the guest
+    let chunk = unsafe { core::slice::from_raw_parts(range.start as *const u8, len) };
+    Ok(get_flatbuffer_result::<&[u8]>(chunk))
+}
+
+/// Write the bytes given by the second parameter to the instance's
+/// memory, at the offset given by the first parameter
+#[instrument(skip_all, level = "Info")]
+fn write_instance_memory(function_call: FunctionCall) -> Result<Vec<u8>> {
+    let Some([ParameterValue::ULong(offset), ParameterValue::VecBytes(bytes)]) =
+        function_call.parameters.as_deref()
+    else {
+        return Err(HyperlightGuestError::new(
+            ErrorCode::GuestFunctionParameterTypeMismatch,
+            "Invalid parameters passed to WriteInstanceMemory".to_string(),
+        ));
+    };
+    let mut store = CUR_STORE.lock();
+    let store = store.deref_mut().as_mut().ok_or(HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        "No wasm store available".to_string(),
+    ))?;
+    let instance = CUR_INSTANCE.lock();
+    let instance = instance.deref().as_ref().ok_or(HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        "No wasm instance available".to_string(),
+    ))?;
+    let memory = instance
+        .get_memory(&mut *store, STATE_MEMORY_EXPORT)
+        .ok_or(HyperlightGuestError::new(
+            ErrorCode::GuestError,
+            "The module does not export its memory".to_string(),
+        ))?;
+    memory
+        .write(&mut *store, *offset as usize, bytes)
+        .map_err(|_| {
+            HyperlightGuestError::new(
+                ErrorCode::GuestError,
+                format!(
+                    "Memory write at {} of {} bytes is out of bounds",
+                    offset,
+                    bytes.len()
+                ),
+            )
+        })?;
+    Ok(get_flatbuffer_result::<()>(()))
+}
+
+/// The export managed-language modules can provide to run a garbage
+/// collection when asked by the host
+const GC_EXPORT: &str = "__hlwasm_gc";
+
+/// Run a garbage collection in the module if it supports it, returning
+/// whether it did. Modules without a collection export are left alone.
+#[instrument(skip_all, level = "Info")]
+fn hint_gc(_function_call: FunctionCall) -> Result<Vec<u8>> {
+    let collected = collect_garbage()?;
+    Ok(get_flatbuffer_result::<i32>(collected as i32))
+}
+
+/// Release what the module no longer needs: the return values of the
+/// last call, garbage in the module's own collector and, with the `gc`
+/// feature, wasmtime's GC heap. Returns the memory used afterwards, see
+/// [`memory_stats`].
+#[instrument(skip_all, level = "Info")]
+fn trim_memory(_function_call: FunctionCall) -> Result<Vec<u8>> {
+    collect_garbage()?;
+    #[cfg(feature = "gc")]
+    if let Some(store) = CUR_STORE.lock().deref_mut() {
+        #[cfg(feature = "wasmtime_lts")]
+        store.gc(None);
+        #[cfg(not(feature = "wasmtime_lts"))]
+        store.gc(None).map_err(map_wasmtime_error)?;
+    }
+    Ok(get_flatbuffer_result::<&[u8]>(
+        &guest_memory_stats().to_bytes(),
+    ))
+}
+
+/// Run the module's collector, see [`hint_gc`]
+fn collect_garbage() -> Result<bool> {
+    let mut store = CUR_STORE.lock();
+    let store = store.deref_mut().as_mut().ok_or(HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        "No wasm store available".to_string(),
+    ))?;
+    let instance = CUR_INSTANCE.lock();
+    let instance = instance.deref().as_ref().ok_or(HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        "No wasm instance available".to_string(),
+    ))?;
+
+    // Release return values from the last call so they can be collected
+    marshal::free_return_value_allocations(&mut *store, &|ctx, name| {
+        instance.get_export(ctx, name)
+    })?;
+
+    // AssemblyScript's runtime exports its collector when built with
+    // --exportRuntime, so use that if there is no dedicated export
+    let func = instance.get_func(&mut *store, GC_EXPORT).or_else(|| {
+        (marshal::guest_abi() == marshal::GuestAbi::AssemblyScript)
+            .then(|| instance.get_func(&mut *store, "__collect"))
+            .flatten()
+    });
+    let Some(func) = func else {
+        return Ok(false);
+    };
+    func.typed::<(), ()>(&*store)
+        .map_err(map_wasmtime_error)?
+        .call(&mut *store, ())
+        .map_err(map_wasmtime_error)?;
+    Ok(true)
+}
+
+#[instrument(skip_all, level = "Info")]
+fn warm_up(function_call: FunctionCall) -> Result<Vec<u8>> {
+    let names = match function_call.parameters.as_deref() {
+        Some([ParameterValue::VecBytes(names)]) => names,
+        _ => {
+            return Err(HyperlightGuestError::new(
+                ErrorCode::GuestFunctionParameterTypeMismatch,
+                "Invalid parameters passed to WarmUp".to_string(),
+            ));
+        }
+    };
+    let mut store = CUR_STORE.lock();
+    let store = store.deref_mut().as_mut().ok_or(HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        "No wasm store available".to_string(),
+    ))?;
+    let instance = CUR_INSTANCE.lock();
+    let instance = instance.deref().as_ref().ok_or(HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        "No wasm instance available".to_string(),
+    ))?;
+
+    // Looking up each function initialises its lazily created function
+    // reference and type information
+    for name in names.split(|b| *b == 0).filter(|n| !n.is_empty()) {
+        let name = core::str::from_utf8(name).map_err(|_| {
+            HyperlightGuestError::new(
+                ErrorCode::GuestError,
+                "WarmUp: function name is not valid UTF-8".to_string(),
+            )
+        })?;
+        let func = instance
+            .get_func(&mut *store, name)
+            .ok_or(HyperlightGuestError::new(
+                ErrorCode::GuestError,
+                format!("Function {} not found", name),
+            ))?;
+        let _ = func.ty(&*store);
+    }
+
+    // Wasmtime does not expose where each function's code is, so fault in
+    // all of the module's code
+    if let Some(module) = CUR_MODULE.lock().as_ref() {
+        platform::prefault(module.image_range());
+    }
+    Ok(get_flatbuffer_result::<i32>(0))
+}
+
+/// Instantiate `module`, stubbing any WASI functions it imports that the
+/// runtime does not implement, and make it the current module. It shares
+/// its store with the modules linked before it.
+#[instrument(skip_all, level = "Info")]
+fn instantiate(engine: &Engine, module: Module) -> Result<()> {
+    let mut linker = CUR_LINKER.lock();
+    let linker = linker
+        .deref_mut()
+        .as_mut()
+        .ok_or(HyperlightGuestError::new(
+            ErrorCode::GuestError,
+            "impossible: wasm runtime has no valid linker".to_string(),
+        ))?;
+
+    let mut store = CUR_STORE.lock().take().unwrap_or_else(|| new_store(engine));
+    wasip1::stub_unsupported_imports(linker, &mut store, &module)?;
+    let instance = linker
+        .instantiate(&mut store, &module)
+        .map_err(map_wasmtime_error)?;
+    check_abi_version(&mut store, &instance)?;
+    if PREFAULT_MEMORY.load(Ordering::Relaxed) {
+        if let Some(memory) = instance.get_memory(&mut store, "memory") {
+            let start = memory.data_ptr(&store) as *const u8;
+            platform::prefault(start..start.wrapping_add(memory.data_size(&store)));
+        }
+    }
+
+    *CUR_MODULE.lock() = Some(module);
+    *CUR_STORE.lock() = Some(store);
+    *CUR_INSTANCE.lock() = Some(instance);
+    Ok(())
+}
+
+fn new_store(engine: &Engine) -> Store<()> {
+    let mut store = Store::new(engine, ());
+    epoch_deadline::configure_store(&mut store);
+    wasm_limits::configure_store(&mut store);
+    store
+}
+
+/// Instantiate a library module, whose exports modules loaded after it
+/// can import from the module named by the first parameter
+#[instrument(skip_all, level = "Info")]
+fn link_wasm_module(function_call: FunctionCall) -> Result<Vec<u8>> {
+    let Some([ParameterValue::String(name), ParameterValue::VecBytes(wasm_bytes)]) =
+        function_call.parameters.as_deref()
+    else {
+        return Err(HyperlightGuestError::new(
+            ErrorCode::GuestFunctionParameterTypeMismatch,
+            "Invalid parameters passed to LinkWasmModule".to_string(),
+        ));
+    };
+    let engine = CUR_ENGINE.lock();
+    let engine = engine.as_ref().ok_or(HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        "impossible: wasm runtime has no valid engine".to_string(),
+    ))?;
+    let mut linker = CUR_LINKER.lock();
+    let linker = linker.as_mut().ok_or(HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        "impossible: wasm runtime has no valid linker".to_string(),
+    ))?;
+    let module = info_span!("deserialize", size = wasm_bytes.len()).in_scope(|| unsafe {
+        Module::deserialize(engine, wasm_bytes).map_err(map_wasmtime_error)
+    })?;
+
+    let mut store = CUR_STORE.lock();
+    let store = store.get_or_insert_with(|| new_store(engine));
+    wasip1::stub_unsupported_imports(linker, store, &module)?;
+    let instance = info_span!("instantiate")
+        .in_scope(|| linker.instantiate(&mut *store, &module))
+        .map_err(map_wasmtime_error)?;
+    linker
+        .instance(&mut *store, name, instance)
+        .map_err(map_wasmtime_error)?;
+    Ok(get_flatbuffer_result::<()>(()))
+}
+
 #[instrument(skip_all, level = "Info")]
 fn load_wasm_module(function_call: FunctionCall) -> Result<Vec<u8>> {
     if let (
@@ -187,22 +988,10 @@ fn load_wasm_module(function_call: FunctionCall) -> Result<Vec<u8>> {
         &function_call.parameters.as_ref().unwrap()[1],
         &*CUR_ENGINE.lock(),
     ) {
-        let linker = CUR_LINKER.lock();
-        let linker = linker.deref().as_ref().ok_or(HyperlightGuestError::new(
-            ErrorCode::GuestError,
-            "impossible: wasm runtime has no valid linker".to_string(),
-        ))?;
-
-        let module =
-            unsafe { Module::deserialize(engine, wasm_bytes).map_err(map_wasmtime_error)? };
-        let mut store = Store::new(engine, ());
-        let instance = linker
-            .instantiate(&mut store, &module)
-            .map_err(map_wasmtime_error)?;
-
-        *CUR_MODULE.lock() = Some(module);
-        *CUR_STORE.lock() = Some(store);
-        *CUR_INSTANCE.lock() = Some(instance);
+        let module = info_span!("deserialize", size = wasm_bytes.len()).in_scope(|| unsafe {
+            Module::deserialize(engine, wasm_bytes).map_err(map_wasmtime_error)
+        })?;
+        instantiate(engine, module)?;
         Ok(get_flatbuffer_result::<i32>(0))
     } else {
         Err(HyperlightGuestError::new(
@@ -219,24 +1008,11 @@ fn load_wasm_module_phys(function_call: FunctionCall) -> Result<Vec<u8>> {
         &function_call.parameters.as_ref().unwrap()[1],
         &*CUR_ENGINE.lock(),
     ) {
-        let linker = CUR_LINKER.lock();
-        let linker = linker.deref().as_ref().ok_or(HyperlightGuestError::new(
-            ErrorCode::GuestError,
-            "impossible: wasm runtime has no valid linker".to_string(),
-        ))?;
-
-        let module = unsafe {
+        let module = info_span!("deserialize", size = *len).in_scope(|| unsafe {
             Module::deserialize_raw(engine, platform::map_buffer(*phys, *len))
-                .map_err(map_wasmtime_error)?
-        };
-        let mut store = Store::new(engine, ());
-        let instance = linker
-            .instantiate(&mut store, &module)
-            .map_err(map_wasmtime_error)?;
-
-        *CUR_MODULE.lock() = Some(module);
-        *CUR_STORE.lock() = Some(store);
-        *CUR_INSTANCE.lock() = Some(instance);
+                .map_err(map_wasmtime_error)
+        })?;
+        instantiate(engine, module)?;
         Ok(get_flatbuffer_result::<()>(()))
     } else {
         Err(HyperlightGuestError::new(
@@ -261,11 +1037,142 @@ pub extern "C" fn hyperlight_main() {
 
     register_function(GuestFunctionDefinition::new(
         "InitWasmRuntime".to_string(),
-        vec![ParameterType::VecBytes],
+        vec![ParameterType::VecBytes, ParameterType::VecBytes],
         ReturnType::Int,
         init_wasm_runtime,
     ));
 
+    log_buffer::register_functions();
+    call_tracker::register_functions();
+    epoch_deadline::register_functions();
+    payload_key::register_functions();
+    staged_params::register_functions();
+    mapped_buffers::register_functions();
+    host_cache::register_functions();
+    dispatch::register_functions();
+    reentry::register_functions();
+    wasip1::register_functions();
+    dynamic_component::register_functions();
+
+    register_function(GuestFunctionDefinition::new(
+        batch::CALL_BATCH_FUNCTION.to_string(),
+        vec![ParameterType::VecBytes],
+        ReturnType::VecBytes,
+        call_batch,
+    ));
+
+    register_function(GuestFunctionDefinition::new(
+        "HintGc".to_string(),
+        vec![],
+        ReturnType::Int,
+        hint_gc,
+    ));
+
+    register_function(GuestFunctionDefinition::new(
+        "WarmUp".to_string(),
+        vec![ParameterType::VecBytes],
+        ReturnType::Int,
+        warm_up,
+    ));
+
+    register_function(GuestFunctionDefinition::new(
+        "SetGuestAbi".to_string(),
+        vec![ParameterType::Int],
+        ReturnType::Int,
+        set_guest_abi,
+    ));
+
+    register_function(GuestFunctionDefinition::new(
+        "CheckExports".to_string(),
+        vec![ParameterType::VecBytes],
+        ReturnType::String,
+        check_exports,
+    ));
+
+    register_function(GuestFunctionDefinition::new(
+        "GetGuestFunctions".to_string(),
+        vec![],
+        ReturnType::VecBytes,
+        get_guest_functions,
+    ));
+
+    register_function(GuestFunctionDefinition::new(
+        memory_stats::GET_MEMORY_STATS_FUNCTION.to_string(),
+        vec![],
+        ReturnType::VecBytes,
+        get_memory_stats,
+    ));
+
+    register_function(GuestFunctionDefinition::new(
+        memory_stats::TRIM_MEMORY_FUNCTION.to_string(),
+        vec![],
+        ReturnType::VecBytes,
+        trim_memory,
+    ));
+
+    register_function(GuestFunctionDefinition::new(
+        linear_memories::GET_LINEAR_MEMORIES_FUNCTION.to_string(),
+        vec![],
+        ReturnType::VecBytes,
+        get_linear_memories,
+    ));
+
+    register_function(GuestFunctionDefinition::new(
+        instance_state::GET_INSTANCE_LAYOUT_FUNCTION.to_string(),
+        vec![],
+        ReturnType::VecBytes,
+        get_instance_layout,
+    ));
+
+    register_function(GuestFunctionDefinition::new(
+        instance_state::RESET_INSTANCE_FUNCTION.to_string(),
+        vec![ParameterType::VecBytes],
+        ReturnType::Void,
+        reset_instance,
+    ));
+
+    register_function(GuestFunctionDefinition::new(
+        instance_state::READ_INSTANCE_MEMORY_FUNCTION.to_string(),
+        vec![ParameterType::ULong, ParameterType::Int],
+        ReturnType::VecBytes,
+        read_instance_memory,
+    ));
+
+    register_function(GuestFunctionDefinition::new(
+        "ReadGuestMemory".to_string(),
+        vec![ParameterType::ULong, ParameterType::Int],
+        ReturnType::VecBytes,
+        read_guest_memory,
+    ));
+
+    register_function(GuestFunctionDefinition::new(
+        instance_state::WRITE_INSTANCE_MEMORY_FUNCTION.to_string(),
+        vec![ParameterType::ULong, ParameterType::VecBytes],
+        ReturnType::Void,
+        write_instance_memory,
+    ));
+
+    register_function(GuestFunctionDefinition::new(
+        "AddHostFunctions".to_string(),
+        vec![ParameterType::VecBytes],
+        ReturnType::Int,
+        add_host_functions,
+    ));
+
+    register_function(GuestFunctionDefinition::new(
+        "RestrictHostFunctions".to_string(),
+        vec![ParameterType::String],
+        ReturnType::Void,
+        restrict_host_functions,
+    ));
+
+    register_function(GuestFunctionDefinition::new(
+        "LinkWasmModule".to_string(),
+        vec![ParameterType::String, ParameterType::VecBytes],
+        ReturnType::Void,
+        link_wasm_module,
+    ));
+
     register_function(GuestFunctionDefinition::new(
         "LoadWasmModule".to_string(),
         vec![ParameterType::VecBytes, ParameterType::Int],
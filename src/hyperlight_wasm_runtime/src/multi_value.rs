@@ -0,0 +1,84 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The results of module functions that return more than one value,
+//! which are returned to the host as a `VecBytes` value.
+//!
+//! The results are encoded in order, each as a one byte type tag
+//! followed by its little-endian value.
+
+use alloc::vec::Vec;
+
+/// A value returned by a module function
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WasmValue {
+    /// An `i32` value
+    I32(i32),
+    /// An `i64` value
+    I64(i64),
+    /// An `f32` value
+    F32(f32),
+    /// An `f64` value
+    F64(f64),
+}
+
+/// Encode `values` to be passed to the host
+pub fn to_bytes(values: &[WasmValue]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for value in values {
+        match value {
+            WasmValue::I32(v) => {
+                bytes.push(0);
+                bytes.extend_from_slice(&v.to_le_bytes());
+            }
+            WasmValue::I64(v) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&v.to_le_bytes());
+            }
+            WasmValue::F32(v) => {
+                bytes.push(2);
+                bytes.extend_from_slice(&v.to_le_bytes());
+            }
+            WasmValue::F64(v) => {
+                bytes.push(3);
+                bytes.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+    }
+    bytes
+}
+
+fn take<const N: usize>(bytes: &mut &[u8]) -> Option<[u8; N]> {
+    let (value, rest) = bytes.split_first_chunk::<N>()?;
+    *bytes = rest;
+    Some(*value)
+}
+
+/// Decode values encoded with [`to_bytes`], returning `None` if the
+/// encoding is malformed
+pub fn from_bytes(mut bytes: &[u8]) -> Option<Vec<WasmValue>> {
+    let mut values = Vec::new();
+    while let Some([tag]) = take(&mut bytes) {
+        values.push(match tag {
+            0 => WasmValue::I32(i32::from_le_bytes(take(&mut bytes)?)),
+            1 => WasmValue::I64(i64::from_le_bytes(take(&mut bytes)?)),
+            2 => WasmValue::F32(f32::from_le_bytes(take(&mut bytes)?)),
+            3 => WasmValue::F64(f64::from_le_bytes(take(&mut bytes)?)),
+            _ => return None,
+        });
+    }
+    Some(values)
+}
@@ -0,0 +1,78 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Guest writes to stdout and stderr. Writes to a stream the host
+//! captures are passed to it with [`HOST_WRITE_FUNCTION`]; otherwise
+//! stdout is printed with `HostPrint` and writes to stderr fail.
+
+use alloc::string::String;
+use alloc::vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use hyperlight_common::flatbuffer_wrappers::function_types::{ParameterValue, ReturnType};
+use hyperlight_guest::error::Result;
+use hyperlight_guest_bin::host_comm::call_host_function;
+
+use crate::call_tracker;
+use crate::runtime_config::{RuntimeConfig, HOST_WRITE_FUNCTION};
+
+pub(crate) const STDOUT: i32 = 1;
+pub(crate) const STDERR: i32 = 2;
+
+// Set by init_wasm_runtime from the runtime config
+static CAPTURE_STDOUT: AtomicBool = AtomicBool::new(false);
+static CAPTURE_STDERR: AtomicBool = AtomicBool::new(false);
+
+pub(crate) fn configure(runtime_config: &RuntimeConfig) {
+    CAPTURE_STDOUT.store(runtime_config.capture_stdout, Ordering::Relaxed);
+    CAPTURE_STDERR.store(runtime_config.capture_stderr, Ordering::Relaxed);
+}
+
+/// Whether writes to `fd` are captured by the host
+pub(crate) fn is_captured(fd: i32) -> bool {
+    match fd {
+        STDOUT => CAPTURE_STDOUT.load(Ordering::Relaxed),
+        STDERR => CAPTURE_STDERR.load(Ordering::Relaxed),
+        _ => false,
+    }
+}
+
+/// Write `bytes` to `fd`, returning the number of bytes written, or
+/// `None` if the guest cannot write to `fd`
+pub(crate) fn write(fd: i32, bytes: &[u8]) -> Option<Result<i32>> {
+    if is_captured(fd) {
+        call_tracker::record_host_call();
+        Some(call_host_function::<i32>(
+            HOST_WRITE_FUNCTION,
+            Some(vec![
+                ParameterValue::Int(fd),
+                ParameterValue::VecBytes(bytes.to_vec()),
+            ]),
+            ReturnType::Int,
+        ))
+    } else if fd == STDOUT {
+        call_tracker::record_host_call();
+        Some(call_host_function::<i32>(
+            "HostPrint",
+            Some(vec![ParameterValue::String(
+                String::from_utf8_lossy(bytes).into_owned(),
+            )]),
+            ReturnType::Int,
+        ))
+    } else {
+        None
+    }
+}
@@ -0,0 +1,84 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! `VecBytes` parameters and return values of guest functions can be
+//! encrypted with a per-sandbox key while they cross the hyperlight
+//! boundary, so that they are only in plaintext in guest memory while
+//! the wasm function that uses them runs.
+//!
+//! Each buffer is encrypted with XChaCha20-Poly1305 under its own
+//! random 24 byte nonce, and sent as the nonce followed by the
+//! ciphertext and its 16 byte tag. Buffers that fail authentication are
+//! rejected. The host draws the nonces of the parameters, and the guest
+//! asks the host for the nonce of each result with
+//! [`HOST_PAYLOAD_NONCE_FUNCTION`], since the guest's own state is
+//! rolled back when a snapshot is restored and could repeat a nonce.
+
+use alloc::vec::Vec;
+
+use chacha20poly1305::aead::{AeadInPlace, KeyInit};
+use chacha20poly1305::{Tag, XChaCha20Poly1305, XNonce};
+
+/// The guest function the host calls to give the guest the key, as a
+/// `VecBytes` of [`KEY_SIZE`] bytes
+pub const SET_PAYLOAD_KEY_FUNCTION: &str = "SetPayloadKey";
+
+/// The guest function the host calls to remove the key from the guest
+pub const CLEAR_PAYLOAD_KEY_FUNCTION: &str = "ClearPayloadKey";
+
+/// The host function the guest calls for the nonce of each result it
+/// encrypts, which returns a `VecBytes` of [`NONCE_SIZE`] random bytes
+pub const HOST_PAYLOAD_NONCE_FUNCTION: &str = "HostPayloadNonce";
+
+/// The size of a key in bytes
+pub const KEY_SIZE: usize = 32;
+
+/// The size of a nonce in bytes
+pub const NONCE_SIZE: usize = 24;
+
+/// The size of the authentication tag in bytes
+pub const TAG_SIZE: usize = 16;
+
+/// Encrypt `plaintext` under `key` and `nonce`, which must not be used
+/// to encrypt anything else, or return `None` if `plaintext` is too long
+/// to encrypt
+pub fn seal(key: &[u8; KEY_SIZE], nonce: [u8; NONCE_SIZE], plaintext: &[u8]) -> Option<Vec<u8>> {
+    let mut sealed = Vec::with_capacity(NONCE_SIZE + plaintext.len() + TAG_SIZE);
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(plaintext);
+    let tag = XChaCha20Poly1305::new(key.into())
+        .encrypt_in_place_detached(XNonce::from_slice(&nonce), &[], &mut sealed[NONCE_SIZE..])
+        .ok()?;
+    sealed.extend_from_slice(&tag);
+    Some(sealed)
+}
+
+/// Decrypt a buffer encrypted with [`seal`], or return `None` if it is
+/// too short to hold a nonce and a tag or fails authentication
+pub fn open(key: &[u8; KEY_SIZE], sealed: &[u8]) -> Option<Vec<u8>> {
+    let (nonce, rest) = sealed.split_first_chunk::<NONCE_SIZE>()?;
+    let (ciphertext, tag) = rest.split_last_chunk::<TAG_SIZE>()?;
+    let mut plaintext = ciphertext.to_vec();
+    XChaCha20Poly1305::new(key.into())
+        .decrypt_in_place_detached(
+            XNonce::from_slice(nonce),
+            &[],
+            &mut plaintext,
+            Tag::from_slice(tag),
+        )
+        .ok()?;
+    Some(plaintext)
+}
@@ -0,0 +1,175 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The guest's copy of the payload key, see [`crate::payload_cipher`].
+//! `VecBytes` parameters are decrypted just before they are copied into
+//! wasm memory, and `VecBytes` results are encrypted as soon as they
+//! are copied out of it.
+
+use alloc::format;
+use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use hyperlight_common::flatbuffer_wrappers::function_call::FunctionCall;
+use hyperlight_common::flatbuffer_wrappers::function_types::{
+    ParameterType, ParameterValue, ReturnType,
+};
+use hyperlight_common::flatbuffer_wrappers::guest_error::ErrorCode;
+use hyperlight_common::flatbuffer_wrappers::util::get_flatbuffer_result;
+use hyperlight_guest::error::{HyperlightGuestError, Result};
+use hyperlight_guest_bin::guest_function::definition::GuestFunctionDefinition;
+use hyperlight_guest_bin::guest_function::register::register_function;
+use hyperlight_guest_bin::host_comm::call_host_function;
+use spin::Mutex;
+
+use crate::payload_cipher::{
+    self, CLEAR_PAYLOAD_KEY_FUNCTION, HOST_PAYLOAD_NONCE_FUNCTION, KEY_SIZE, NONCE_SIZE,
+    SET_PAYLOAD_KEY_FUNCTION,
+};
+
+// Set by the host while a module is loaded, and cleared before the
+// sandbox is snapshotted
+static KEY: Mutex<Option<[u8; KEY_SIZE]>> = Mutex::new(None);
+
+/// Decrypt the `VecBytes` parameters of a guest call, if the host set a
+/// key
+#[cfg_attr(component, allow(dead_code))]
+pub(crate) fn open_params(params: &mut [ParameterValue]) -> Result<()> {
+    let Some(key) = *KEY.lock() else {
+        return Ok(());
+    };
+    for param in params {
+        if let ParameterValue::VecBytes(sealed) = param {
+            *sealed = payload_cipher::open(&key, sealed).ok_or_else(|| {
+                HyperlightGuestError::new(
+                    ErrorCode::GuestFunctionParameterTypeMismatch,
+                    "encrypted parameter failed authentication".to_string(),
+                )
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Overwrite the decrypted `VecBytes` parameters of a guest call that
+/// has returned
+#[cfg_attr(component, allow(dead_code))]
+pub(crate) fn zero_params(params: &mut [ParameterValue]) {
+    if KEY.lock().is_none() {
+        return;
+    }
+    for param in params {
+        if let ParameterValue::VecBytes(bytes) = param {
+            bytes.fill(0);
+        }
+    }
+}
+
+/// Encrypt the `VecBytes` result of a guest call in place, if the host
+/// set a key, overwriting the plaintext. The nonce is drawn by the host,
+/// since a counter or generator in guest memory would be rolled back
+/// with it when a snapshot is restored.
+#[cfg_attr(component, allow(dead_code))]
+pub(crate) fn seal_result(bytes: &mut Vec<u8>) -> Result<()> {
+    let Some(key) = *KEY.lock() else {
+        return Ok(());
+    };
+    let nonce =
+        call_host_function::<Vec<u8>>(HOST_PAYLOAD_NONCE_FUNCTION, None, ReturnType::VecBytes)?;
+    let nonce = <[u8; NONCE_SIZE]>::try_from(nonce.as_slice()).map_err(|_| {
+        HyperlightGuestError::new(
+            ErrorCode::GuestError,
+            format!("payload nonce must be {} bytes", NONCE_SIZE),
+        )
+    })?;
+    let sealed = payload_cipher::seal(&key, nonce, bytes).ok_or_else(|| {
+        HyperlightGuestError::new(
+            ErrorCode::GuestError,
+            "result is too long to encrypt".to_string(),
+        )
+    })?;
+    bytes.fill(0);
+    *bytes = sealed;
+    Ok(())
+}
+
+/// Whether the host set a key
+#[cfg_attr(component, allow(dead_code))]
+pub(crate) fn enabled() -> bool {
+    KEY.lock().is_some()
+}
+
+fn set_payload_key(function_call: FunctionCall) -> Result<Vec<u8>> {
+    let Some(mut params) = function_call.parameters else {
+        return Err(invalid_key_parameters());
+    };
+    // The parameter is overwritten whatever the outcome, so that neither
+    // the key nor a malformed one stays on the heap to be snapshotted
+    let result = match params.as_slice() {
+        [ParameterValue::VecBytes(_)] if cfg!(component) => Err(HyperlightGuestError::new(
+            ErrorCode::GuestError,
+            "payload encryption is not supported for components".to_string(),
+        )),
+        [ParameterValue::VecBytes(key)] if key.len() == KEY_SIZE => {
+            // Copied straight into place, so that no copy is left on the
+            // stack
+            KEY.lock().insert([0; KEY_SIZE]).copy_from_slice(key);
+            Ok(get_flatbuffer_result::<()>(()))
+        }
+        [ParameterValue::VecBytes(_)] => Err(HyperlightGuestError::new(
+            ErrorCode::GuestFunctionParameterTypeMismatch,
+            "payload key must be 32 bytes".to_string(),
+        )),
+        _ => Err(invalid_key_parameters()),
+    };
+    for param in &mut params {
+        if let ParameterValue::VecBytes(bytes) = param {
+            bytes.fill(0);
+        }
+    }
+    result
+}
+
+fn invalid_key_parameters() -> HyperlightGuestError {
+    HyperlightGuestError::new(
+        ErrorCode::GuestFunctionParameterTypeMismatch,
+        "Invalid parameters passed to SetPayloadKey".to_string(),
+    )
+}
+
+fn clear_payload_key(_function_call: FunctionCall) -> Result<Vec<u8>> {
+    if let Some(key) = KEY.lock().as_mut() {
+        key.fill(0);
+    }
+    *KEY.lock() = None;
+    Ok(get_flatbuffer_result::<()>(()))
+}
+
+pub(crate) fn register_functions() {
+    register_function(GuestFunctionDefinition::new(
+        SET_PAYLOAD_KEY_FUNCTION.to_string(),
+        vec![ParameterType::VecBytes],
+        ReturnType::Void,
+        set_payload_key,
+    ));
+    register_function(GuestFunctionDefinition::new(
+        CLEAR_PAYLOAD_KEY_FUNCTION.to_string(),
+        vec![],
+        ReturnType::Void,
+        clear_payload_key,
+    ));
+}
@@ -27,6 +27,9 @@ use hyperlight_guest_bin::paging;
 // we start at
 // 0x100_0000_0000 and go up from there
 static FIRST_VADDR: AtomicU64 = AtomicU64::new(0x100_0000_0000u64);
+// The pages mapped by the page fault handler, reported to the host by
+// the GetMemoryStats guest function
+static MAPPED_PAGES: AtomicU64 = AtomicU64::new(0);
 fn page_fault_handler(
     _exception_number: u64,
     info: *mut arch::ExceptionInfo,
@@ -43,6 +46,7 @@ fn page_fault_handler(
     if (error_code & 0x1) == 0x0 && page_fault_address >= 0x100_0000_0000u64 {
         unsafe {
             let phys_page = hyperlight_guest::prim_alloc::alloc_phys_pages(1);
+            MAPPED_PAGES.fetch_add(1, Ordering::Relaxed);
             let virt_base = (page_fault_address & !0xFFF) as *mut u8;
             paging::map_region(
                 phys_page,
@@ -118,6 +122,53 @@ pub extern "C" fn wasmtime_mprotect(_ptr: *mut u8, _size: usize, prot_flags: u32
     -1
 }
 
+/// The bytes of memory mapped for wasmtime as it is first touched
+pub(crate) fn mapped_bytes() -> u64 {
+    let page_size = unsafe { hyperlight_guest_bin::OS_PAGE_SIZE as u64 };
+    MAPPED_PAGES.load(Ordering::Relaxed) * page_size
+}
+
+/// The size in bytes of the heap the runtime allocates from, which
+/// hyperlight sets up when the guest starts
+pub(crate) fn heap_bytes() -> u64 {
+    #[allow(static_mut_refs)]
+    let peb = unsafe { hyperlight_guest_bin::GUEST_HANDLE.peb() };
+    match peb {
+        // Safety: the PEB is set up before the runtime's functions are
+        // called and is not moved afterwards
+        Some(peb) => unsafe { (*peb).guest_heap.size },
+        None => 0,
+    }
+}
+
+/// Touch every page of `range`, such as a loaded module or component
+/// image or a module's linear memory, so that the first calls into the
+/// module or component do not take page faults
+pub(crate) fn prefault(range: core::ops::Range<*const u8>) {
+    let page_size = unsafe { hyperlight_guest_bin::OS_PAGE_SIZE as usize };
+    let len = range.end as usize - range.start as usize;
+    for offset in (0..len).step_by(page_size) {
+        // Safety: wasmtime keeps images and memories mapped while the
+        // module or component is alive. A volatile read is used so that
+        // the access is not optimised away
+        unsafe { core::ptr::read_volatile(range.start.add(offset)) };
+    }
+}
+
+/// Whether every page of `range` of guest virtual addresses is readable,
+/// so that reading it neither faults nor has the page fault handler map
+/// fresh pages
+pub(crate) fn is_readable(range: core::ops::Range<u64>) -> bool {
+    let start = range.start & !(vmem::PAGE_SIZE as u64 - 1);
+    (start..range.end).step_by(vmem::PAGE_SIZE).all(|page| {
+        paging::virt_to_phys(page).any(|mapping| match mapping.kind {
+            vmem::MappingKind::Unmapped => false,
+            vmem::MappingKind::Basic(mapping) => mapping.readable,
+            vmem::MappingKind::Cow(mapping) => mapping.readable,
+        })
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn wasmtime_page_size() -> usize {
     unsafe { hyperlight_guest_bin::OS_PAGE_SIZE as usize }
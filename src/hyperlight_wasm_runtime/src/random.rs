@@ -0,0 +1,82 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use hyperlight_common::flatbuffer_wrappers::function_types::{ParameterValue, ReturnType};
+use hyperlight_guest_bin::host_comm::call_host_function;
+use spin::Mutex;
+
+use crate::call_tracker;
+use crate::runtime_config::{EntropyPolicy, RuntimeConfig, HOST_RANDOM_FUNCTION};
+
+struct Rng {
+    policy: EntropyPolicy,
+    // SplitMix64 state, used under EntropyPolicy::Deterministic
+    state: u64,
+}
+
+// Set by init_wasm_runtime from the runtime config. The generator state is
+// part of the sandbox's memory, so snapshots capture and restore it.
+static RNG: Mutex<Rng> = Mutex::new(Rng {
+    policy: EntropyPolicy::Denied,
+    state: 0,
+});
+
+pub(crate) fn configure(runtime_config: &RuntimeConfig) {
+    *RNG.lock() = Rng {
+        policy: runtime_config.entropy_policy,
+        state: runtime_config.rng_seed.unwrap_or(0),
+    };
+}
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+}
+
+/// Return `len` random bytes according to the sandbox's entropy policy,
+/// or `None` if random numbers are denied or could not be read
+pub(crate) fn random_bytes(len: usize) -> Option<Vec<u8>> {
+    let mut rng = RNG.lock();
+    match rng.policy {
+        EntropyPolicy::Denied => None,
+        EntropyPolicy::Deterministic => {
+            let mut bytes = vec![0u8; len];
+            for chunk in bytes.chunks_mut(8) {
+                chunk.copy_from_slice(&rng.next_u64().to_le_bytes()[..chunk.len()]);
+            }
+            Some(bytes)
+        }
+        EntropyPolicy::HostEntropy => {
+            drop(rng);
+            call_tracker::record_host_call();
+            let bytes = call_host_function::<Vec<u8>>(
+                HOST_RANDOM_FUNCTION,
+                Some(vec![ParameterValue::Int(i32::try_from(len).ok()?)]),
+                ReturnType::VecBytes,
+            )
+            .ok()?;
+            (bytes.len() == len).then_some(bytes)
+        }
+    }
+}
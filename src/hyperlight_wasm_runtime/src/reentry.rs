@@ -0,0 +1,169 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+use alloc::collections::BTreeSet;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use hyperlight_common::flatbuffer_wrappers::function_call::FunctionCall;
+use hyperlight_common::flatbuffer_wrappers::function_types::{
+    FunctionCallResult, ParameterType, ParameterValue, ReturnType, ReturnValue,
+};
+use hyperlight_common::flatbuffer_wrappers::guest_error::ErrorCode;
+use hyperlight_common::flatbuffer_wrappers::util::get_flatbuffer_result;
+use hyperlight_guest::error::{HyperlightGuestError, Result};
+use hyperlight_guest_bin::guest_function::definition::GuestFunctionDefinition;
+use hyperlight_guest_bin::guest_function::register::register_function;
+use hyperlight_guest_bin::host_comm::call_host_function;
+use spin::Mutex;
+use tracing::instrument;
+use wasmtime::{Caller, Extern, Val};
+
+use crate::host_reentry::{GuestMessage, HostMessage, HOST_REENTER_FUNCTION};
+use crate::{host_error, map_wasmtime_error, marshal};
+
+// The host functions the host registered as re-entrant, which are
+// called through the re-entry host function
+static REENTRANT: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+/// Whether host function `name` is called through the re-entry host
+/// function
+pub(crate) fn is_reentrant(name: &str) -> bool {
+    REENTRANT.lock().contains(name)
+}
+
+/// Call the re-entrant host function `name`, calling the exports it asks
+/// for on `caller` until it returns
+#[instrument(skip_all, level = "Info")]
+pub(crate) fn call_host<T>(
+    caller: &mut Caller<'_, T>,
+    name: &str,
+    params: Vec<ParameterValue>,
+) -> Result<ReturnValue> {
+    let mut message = GuestMessage::Call {
+        name: name.to_string(),
+        params,
+    };
+    loop {
+        let reply = call_host_function::<Vec<u8>>(
+            HOST_REENTER_FUNCTION,
+            Some(vec![ParameterValue::VecBytes(message.to_bytes())]),
+            ReturnType::VecBytes,
+        )?;
+        let reply = HostMessage::from_bytes(&reply).ok_or_else(|| {
+            HyperlightGuestError::new(
+                ErrorCode::GuestError,
+                alloc::format!("Malformed message from re-entrant host function {}", name),
+            )
+        })?;
+        message = match reply {
+            HostMessage::Return(value) => return Ok(value),
+            HostMessage::CallExport {
+                name,
+                params,
+                return_type,
+            } => match call_export(caller, &name, &params, return_type) {
+                Ok(value) => GuestMessage::Returned(value),
+                Err(e) => GuestMessage::Failed(e.message),
+            },
+        };
+    }
+}
+
+// Call the export `name` of the module calling the host function
+fn call_export<T>(
+    caller: &mut Caller<'_, T>,
+    name: &str,
+    params: &[ParameterValue],
+    return_type: ReturnType,
+) -> Result<ReturnValue> {
+    let func = caller
+        .get_export(name)
+        .and_then(Extern::into_func)
+        .ok_or_else(|| {
+            HyperlightGuestError::new(
+                ErrorCode::GuestError,
+                alloc::format!("Function {} not found", name),
+            )
+        })?;
+    let mut w_params = vec![];
+    for param in params {
+        marshal::hl_param_to_val(&mut *caller, |c, n| c.get_export(n), param, &mut w_params)?;
+    }
+    let n_results = if return_type == ReturnType::Void {
+        0
+    } else {
+        func.ty(&*caller).results().len().max(1)
+    };
+    let mut results = vec![Val::I32(0); n_results];
+    func.call(&mut *caller, &w_params, &mut results)
+        .map_err(|e| match host_error::take() {
+            Some((name, message)) => HyperlightGuestError::new(
+                ErrorCode::GuestError,
+                alloc::format!("host function {} failed: {}", name, message),
+            ),
+            None => map_wasmtime_error(e),
+        })?;
+    let result =
+        marshal::val_to_hl_result(&mut *caller, |c, n| c.get_export(n), return_type, &results)?;
+    FunctionCallResult::try_from(result.as_slice())
+        .map_err(|e| {
+            HyperlightGuestError::new(
+                ErrorCode::GuestError,
+                alloc::format!("Malformed result from function {}: {}", name, e),
+            )
+        })?
+        .into_inner()
+        .map_err(|e| HyperlightGuestError::new(e.code, e.message))
+}
+
+#[instrument(skip_all, level = "Info")]
+/// Set the host functions to call through the re-entry host function,
+/// see [`crate::host_reentry`]
+fn configure_reentrant_host_functions(function_call: FunctionCall) -> Result<Vec<u8>> {
+    let names = match function_call.parameters.as_deref() {
+        Some([ParameterValue::VecBytes(bytes)]) => bytes,
+        _ => {
+            return Err(HyperlightGuestError::new(
+                ErrorCode::GuestFunctionParameterTypeMismatch,
+                "Invalid parameters passed to ConfigureReentrantHostFunctions".to_string(),
+            ));
+        }
+    };
+    let mut reentrant = REENTRANT.lock();
+    reentrant.clear();
+    for name in names.split(|b| *b == 0).filter(|name| !name.is_empty()) {
+        let name = core::str::from_utf8(name).map_err(|_| {
+            HyperlightGuestError::new(
+                ErrorCode::GuestError,
+                "ConfigureReentrantHostFunctions: host function name is not valid UTF-8"
+                    .to_string(),
+            )
+        })?;
+        reentrant.insert(name.to_string());
+    }
+    Ok(get_flatbuffer_result::<i32>(0))
+}
+
+pub(crate) fn register_functions() {
+    register_function(GuestFunctionDefinition::new(
+        "ConfigureReentrantHostFunctions".to_string(),
+        vec![ParameterType::VecBytes],
+        ReturnType::Int,
+        configure_reentrant_host_functions,
+    ));
+}
@@ -0,0 +1,518 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Settings for the wasmtime engine inside the guest, chosen by the host
+//! through `SandboxBuilder` and passed to the `InitWasmRuntime` guest
+//! function.
+//!
+//! Settings are encoded as a sequence of entries, each a one byte tag
+//! followed by a little-endian `u64` value. Unset settings are omitted,
+//! leaving the engine default in place.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+const TAG_MAX_WASM_STACK: u8 = 1;
+const TAG_CANONICALIZE_NANS: u8 = 2;
+const TAG_GUEST_LOG_LEVEL: u8 = 3;
+const TAG_MAX_RETURN_VALUE_SIZE: u8 = 4;
+const TAG_MIN_GUEST_ABI_VERSION: u8 = 5;
+const TAG_MAX_GUEST_ABI_VERSION: u8 = 6;
+const TAG_ENTROPY_POLICY: u8 = 7;
+const TAG_RNG_SEED: u8 = 8;
+const TAG_HEAP_SIZE: u8 = 9;
+const TAG_INPUT_BUFFER_SIZE: u8 = 10;
+const TAG_OUTPUT_BUFFER_SIZE: u8 = 11;
+const TAG_CALL_TIMEOUT_MICROS: u8 = 12;
+const TAG_UNSUPPORTED_WASI_ERRNO: u8 = 13;
+const TAG_PREFAULT_MEMORY: u8 = 14;
+const TAG_WASI_P2: u8 = 15;
+const TAG_EPOCH_INTERRUPTION: u8 = 16;
+const TAG_CAPTURE_STDOUT: u8 = 17;
+const TAG_CAPTURE_STDERR: u8 = 18;
+const TAG_DETERMINISTIC_RELAXED_SIMD: u8 = 19;
+const TAG_MAX_WASM_MEMORY: u8 = 20;
+const TAG_MAX_TABLE_ELEMENTS: u8 = 21;
+const TAG_PREOPENED_DIRS: u8 = 22;
+const TAG_VFS: u8 = 23;
+const TAG_CLI_ENVIRONMENT: u8 = 24;
+const TAG_WASM_FEATURES: u8 = 25;
+const TAG_OPT_LEVEL: u8 = 26;
+const TAG_FRESH_INSTANCE_PER_CALL: u8 = 27;
+
+/// Appended to the wasmtime version recorded in artifacts precompiled with
+/// NaN canonicalization, so that the engine refuses to load artifacts whose
+/// NaN canonicalization setting does not match its own. hyperlight-wasm-aot
+/// uses the same suffix.
+pub const NAN_CANONICALIZATION_VERSION_SUFFIX: &str = "+nan-canonicalization";
+
+/// The name of the host function the guest calls to get random bytes
+/// under [`EntropyPolicy::HostEntropy`]. It takes the number of bytes as
+/// an `i32` and returns them as a `VecBytes`.
+pub const HOST_RANDOM_FUNCTION: &str = "HostRandomGet";
+
+/// The name of the host function the guest calls to read a WASI clock.
+/// It takes the clock id as an `i32` and returns the time in nanoseconds
+/// as an `i64`, or -1 if the clock is not supported.
+pub const HOST_CLOCK_FUNCTION: &str = "HostClockTimeGet";
+
+/// The name of the host function the guest calls to read a `u64` state
+/// cell. It takes the cell's name as a `String` and returns a `ULong`.
+pub const HOST_STATE_CELL_GET_U64_FUNCTION: &str = "HostStateCellGetU64";
+
+/// The name of the host function the guest calls to write a `u64` state
+/// cell. It takes the cell's name as a `String` and the value as a
+/// `ULong`.
+pub const HOST_STATE_CELL_SET_U64_FUNCTION: &str = "HostStateCellSetU64";
+
+/// The name of the host function the guest calls to read a bytes state
+/// cell. It takes the cell's name as a `String` and returns a `VecBytes`.
+pub const HOST_STATE_CELL_GET_BYTES_FUNCTION: &str = "HostStateCellGetBytes";
+
+/// The name of the host function the guest calls to write a bytes state
+/// cell. It takes the cell's name as a `String` and the value as a
+/// `VecBytes`.
+pub const HOST_STATE_CELL_SET_BYTES_FUNCTION: &str = "HostStateCellSetBytes";
+
+/// The name of the host function the guest calls to write to a captured
+/// stdout or stderr. It takes the file descriptor, 1 or 2, as an `i32`
+/// and the bytes written as a `VecBytes`, and returns the number of bytes
+/// written as an `i32`.
+pub const HOST_WRITE_FUNCTION: &str = "HostWrite";
+
+/// The name of the host function the guest calls to read the arguments
+/// set for guests. It takes no parameters and returns the arguments,
+/// each followed by a NUL byte, as a `VecBytes`.
+pub const HOST_ARGS_FUNCTION: &str = "HostArgs";
+
+/// The name of the host function the guest calls to read the environment
+/// variables set for guests. It takes no parameters and returns the
+/// variables as `KEY=VALUE`, each followed by a NUL byte, as a
+/// `VecBytes`.
+pub const HOST_ENVIRONMENT_FUNCTION: &str = "HostEnvironment";
+
+/// The message of the guest error returned by a guest call that was
+/// stopped at its epoch deadline
+pub const EPOCH_DEADLINE_EXCEEDED: &str = "guest call exceeded its epoch deadline";
+
+/// The message of the guest error returned by a guest call that failed
+/// after a linear memory was refused growth beyond
+/// [`RuntimeConfig::max_wasm_memory`]
+pub const WASM_MEMORY_LIMIT_EXCEEDED: &str = "guest call exceeded its wasm memory limit";
+
+/// The message of the guest error returned by a guest call that failed
+/// after a table was refused growth beyond
+/// [`RuntimeConfig::max_table_elements`]
+pub const WASM_TABLE_LIMIT_EXCEEDED: &str = "guest call exceeded its wasm table element limit";
+
+/// Starts the message of the guest error returned by a guest call that
+/// ended because the guest aborted. It is followed by the exit code the
+/// guest passed to `proc_exit` or `wasi:cli/exit`, or `-` if it trapped
+/// after writing to stderr, as Rust guests do when they panic, and on the
+/// next line by the end of what the guest wrote to stderr during the
+/// call.
+pub const GUEST_ABORTED: &str = "guest aborted: ";
+
+/// Starts the message of the guest error returned by a guest call that
+/// trapped. It is followed by the name of the wasmtime trap code, such as
+/// `StackOverflow`, and a line for each frame of the wasm backtrace,
+/// innermost first, holding the function index, the offset in the module
+/// or `-` if it is unknown, and the function name, if any, separated by
+/// spaces.
+pub const WASM_TRAP: &str = "guest call trapped: ";
+
+/// Starts the message of the guest error returned by a guest call that
+/// failed because a host function it called returned an error. It is
+/// followed by the name of the host function and, on the next line, by
+/// the message of the error.
+pub const HOST_FUNCTION_FAILED: &str = "host function failed: ";
+
+/// Where random numbers requested by guests, for example with the WASI
+/// `random_get` function, come from
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EntropyPolicy {
+    /// Requests for random numbers fail
+    #[default]
+    Denied,
+    /// Random numbers are generated by a pseudo-random number generator
+    /// in the guest seeded with the sandbox's RNG seed, so the same seed
+    /// always produces the same sequence
+    Deterministic,
+    /// Random numbers are read from the host's entropy source
+    HostEntropy,
+}
+
+impl EntropyPolicy {
+    fn to_u64(self) -> u64 {
+        match self {
+            EntropyPolicy::Denied => 0,
+            EntropyPolicy::Deterministic => 1,
+            EntropyPolicy::HostEntropy => 2,
+        }
+    }
+
+    fn from_u64(value: u64) -> Option<Self> {
+        match value {
+            0 => Some(EntropyPolicy::Denied),
+            1 => Some(EntropyPolicy::Deterministic),
+            2 => Some(EntropyPolicy::HostEntropy),
+            _ => None,
+        }
+    }
+}
+
+/// The WebAssembly proposals guests may use. Modules and components must
+/// have been precompiled with the same proposals enabled, which
+/// deserialization checks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WasmFeatures {
+    /// The SIMD proposal, which adds the `v128` type. Disabling it
+    /// disables relaxed SIMD too. Enabled by default.
+    pub simd: bool,
+    /// The relaxed SIMD proposal. Enabled by default.
+    pub relaxed_simd: bool,
+    /// The bulk memory proposal, which adds `memory.copy`, `memory.fill`
+    /// and passive segments. Enabled by default.
+    pub bulk_memory: bool,
+    /// The tail call proposal, which adds `return_call`. Enabled by
+    /// default.
+    pub tail_call: bool,
+    /// The multi-memory proposal, which lets modules have more than one
+    /// linear memory. Enabled by default.
+    pub multi_memory: bool,
+    /// The threads proposal, which adds shared memories and atomics. The
+    /// runtime runs guests on a single thread, so this cannot be enabled.
+    pub threads: bool,
+}
+
+impl Default for WasmFeatures {
+    fn default() -> Self {
+        Self {
+            simd: true,
+            relaxed_simd: true,
+            bulk_memory: true,
+            tail_call: true,
+            multi_memory: true,
+            threads: false,
+        }
+    }
+}
+
+impl WasmFeatures {
+    const SIMD: u64 = 1 << 0;
+    const RELAXED_SIMD: u64 = 1 << 1;
+    const BULK_MEMORY: u64 = 1 << 2;
+    const TAIL_CALL: u64 = 1 << 3;
+    const MULTI_MEMORY: u64 = 1 << 4;
+    const THREADS: u64 = 1 << 5;
+
+    fn to_u64(self) -> u64 {
+        [
+            (self.simd, Self::SIMD),
+            (self.relaxed_simd, Self::RELAXED_SIMD),
+            (self.bulk_memory, Self::BULK_MEMORY),
+            (self.tail_call, Self::TAIL_CALL),
+            (self.multi_memory, Self::MULTI_MEMORY),
+            (self.threads, Self::THREADS),
+        ]
+        .into_iter()
+        .filter(|(enabled, _)| *enabled)
+        .fold(0, |bits, (_, bit)| bits | bit)
+    }
+
+    fn from_u64(value: u64) -> Option<Self> {
+        if value >= Self::THREADS << 1 {
+            return None;
+        }
+        Some(Self {
+            simd: value & Self::SIMD != 0,
+            relaxed_simd: value & Self::RELAXED_SIMD != 0,
+            bulk_memory: value & Self::BULK_MEMORY != 0,
+            tail_call: value & Self::TAIL_CALL != 0,
+            multi_memory: value & Self::MULTI_MEMORY != 0,
+            threads: value & Self::THREADS != 0,
+        })
+    }
+}
+
+/// How much the compiler optimizes the code of guests precompiled on the
+/// host. There is no compiler in the guest, so this only applies where
+/// guests are compiled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OptLevel {
+    /// No optimizations
+    None,
+    /// Optimize for speed
+    #[default]
+    Speed,
+    /// Optimize for speed and size
+    SpeedAndSize,
+}
+
+impl OptLevel {
+    fn to_u64(self) -> u64 {
+        match self {
+            OptLevel::None => 0,
+            OptLevel::Speed => 1,
+            OptLevel::SpeedAndSize => 2,
+        }
+    }
+
+    fn from_u64(value: u64) -> Option<Self> {
+        match value {
+            0 => Some(OptLevel::None),
+            1 => Some(OptLevel::Speed),
+            2 => Some(OptLevel::SpeedAndSize),
+            _ => None,
+        }
+    }
+}
+
+/// Settings for the wasmtime engine inside the guest
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RuntimeConfig {
+    /// The maximum stack size, in bytes, that wasm code may use. See
+    /// `wasmtime::Config::max_wasm_stack`.
+    pub max_wasm_stack: Option<u64>,
+    /// Whether modules must have been precompiled with NaN canonicalization
+    /// enabled. See `wasmtime::Config::cranelift_nan_canonicalization`.
+    pub canonicalize_nans: bool,
+    /// The most verbose level of guest log records to buffer, as a
+    /// `log::LevelFilter` (0 is off, 5 is trace). Records are discarded
+    /// if this is not set.
+    pub guest_log_level: Option<u64>,
+    /// The maximum size, in bytes, of a String or VecBytes returned from a
+    /// guest function. Return values are not limited if this is not set.
+    pub max_return_value_size: Option<u64>,
+    /// The lowest guest ABI version a loaded guest may declare. If this
+    /// is set, guests that do not declare an ABI version fail to load.
+    pub min_guest_abi_version: Option<u64>,
+    /// The lowest guest ABI version above those a loaded guest may
+    /// declare. Versions are not limited from above if this is not set.
+    pub max_guest_abi_version: Option<u64>,
+    /// Where random numbers requested by guests come from
+    pub entropy_policy: EntropyPolicy,
+    /// The seed of the guest's pseudo-random number generator under
+    /// [`EntropyPolicy::Deterministic`]. Defaults to 0.
+    pub rng_seed: Option<u64>,
+    /// The size of the sandbox's heap in bytes, reported to guests
+    /// through the `hlwasm:limits` interface
+    pub heap_size: Option<u64>,
+    /// The size of the sandbox's input buffer in bytes, reported to
+    /// guests through the `hlwasm:limits` interface
+    pub input_buffer_size: Option<u64>,
+    /// The size of the sandbox's output buffer in bytes, reported to
+    /// guests through the `hlwasm:limits` interface
+    pub output_buffer_size: Option<u64>,
+    /// The longest a guest call may run for in microseconds, reported to
+    /// guests through the `hlwasm:limits` interface. Calls are not
+    /// limited if this is not set.
+    pub call_timeout_micros: Option<u64>,
+    /// The WASI errno returned by WASI functions imported by a module
+    /// that the runtime does not implement, `ERRNO_NOSYS` (52) if not set
+    pub unsupported_wasi_errno: Option<u64>,
+    /// Whether the linear memory of each module is faulted in when the
+    /// module is loaded, rather than page by page as it is first used
+    pub prefault_memory: bool,
+    /// Whether the WASI preview 2 interfaces imported by a component are
+    /// provided by the runtime, backed by host functions, rather than
+    /// defined from the component's world
+    pub wasi_p2: bool,
+    /// Whether wasm code checks for epoch deadlines, so that guest calls
+    /// can be given one. Modules and components must have been
+    /// precompiled with epoch interruption enabled. See
+    /// `wasmtime::Config::epoch_interruption`.
+    pub epoch_interruption: bool,
+    /// Whether guest writes to stdout are passed to the host with
+    /// [`HOST_WRITE_FUNCTION`] rather than printed with `HostPrint`
+    pub capture_stdout: bool,
+    /// Whether guest writes to stderr are passed to the host with
+    /// [`HOST_WRITE_FUNCTION`]. They fail if this is not set.
+    pub capture_stderr: bool,
+    /// Whether modules and components must have been precompiled with
+    /// deterministic relaxed SIMD instructions, so that their results
+    /// are reproducible across hosts. See
+    /// `wasmtime::Config::relaxed_simd_deterministic`.
+    pub deterministic_relaxed_simd: bool,
+    /// The largest size, in bytes, each linear memory of a loaded guest
+    /// may grow to. Memories are not limited if this is not set.
+    pub max_wasm_memory: Option<u64>,
+    /// The most elements each table of a loaded guest may grow to.
+    /// Tables are not limited if this is not set.
+    pub max_table_elements: Option<u64>,
+    /// Whether the host preopened directories for modules, which the
+    /// guest then lists and reads with the host functions of
+    /// [`wasi_fs`](crate::wasi_fs)
+    pub preopened_dirs: bool,
+    /// Whether the host provided an in-memory filesystem for modules,
+    /// which the guest then copies with the host functions of
+    /// [`wasi_fs`](crate::wasi_fs) when the runtime is initialized
+    pub vfs: bool,
+    /// Whether the host set arguments or environment variables for
+    /// guests, which the guest then reads with [`HOST_ARGS_FUNCTION`] and
+    /// [`HOST_ENVIRONMENT_FUNCTION`] when the runtime is initialized
+    pub cli_environment: bool,
+    /// The WebAssembly proposals guests may use
+    pub wasm_features: WasmFeatures,
+    /// The optimization level guests are precompiled with on the host
+    pub opt_level: OptLevel,
+    /// Whether a loaded component is instantiated afresh before each
+    /// call of one of its exports, so that no state is kept between
+    /// calls. Modules are not affected.
+    pub fresh_instance_per_call: bool,
+}
+
+/// An error decoding a [`RuntimeConfig`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuntimeConfigError {
+    /// The encoding ended in the middle of an entry
+    Truncated,
+    /// An entry had a tag this version of the runtime does not know
+    UnknownTag(u8),
+    /// An entry had a value that is not valid for its setting
+    InvalidValue(u8, u64),
+}
+
+impl fmt::Display for RuntimeConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeConfigError::Truncated => write!(f, "runtime config is truncated"),
+            RuntimeConfigError::UnknownTag(tag) => {
+                write!(f, "runtime config has unknown setting {}", tag)
+            }
+            RuntimeConfigError::InvalidValue(tag, value) => {
+                write!(
+                    f,
+                    "runtime config setting {} has invalid value {}",
+                    tag, value
+                )
+            }
+        }
+    }
+}
+
+impl RuntimeConfig {
+    /// Encode the settings to be passed to the guest
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut push = |tag: u8, value: Option<u64>| {
+            if let Some(value) = value {
+                bytes.push(tag);
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        };
+        push(TAG_MAX_WASM_STACK, self.max_wasm_stack);
+        push(TAG_CANONICALIZE_NANS, self.canonicalize_nans.then_some(1));
+        push(TAG_GUEST_LOG_LEVEL, self.guest_log_level);
+        push(TAG_MAX_RETURN_VALUE_SIZE, self.max_return_value_size);
+        push(TAG_MIN_GUEST_ABI_VERSION, self.min_guest_abi_version);
+        push(TAG_MAX_GUEST_ABI_VERSION, self.max_guest_abi_version);
+        push(
+            TAG_ENTROPY_POLICY,
+            (self.entropy_policy != EntropyPolicy::Denied).then_some(self.entropy_policy.to_u64()),
+        );
+        push(TAG_RNG_SEED, self.rng_seed);
+        push(TAG_HEAP_SIZE, self.heap_size);
+        push(TAG_INPUT_BUFFER_SIZE, self.input_buffer_size);
+        push(TAG_OUTPUT_BUFFER_SIZE, self.output_buffer_size);
+        push(TAG_CALL_TIMEOUT_MICROS, self.call_timeout_micros);
+        push(TAG_UNSUPPORTED_WASI_ERRNO, self.unsupported_wasi_errno);
+        push(TAG_PREFAULT_MEMORY, self.prefault_memory.then_some(1));
+        push(TAG_WASI_P2, self.wasi_p2.then_some(1));
+        push(TAG_EPOCH_INTERRUPTION, self.epoch_interruption.then_some(1));
+        push(TAG_CAPTURE_STDOUT, self.capture_stdout.then_some(1));
+        push(TAG_CAPTURE_STDERR, self.capture_stderr.then_some(1));
+        push(
+            TAG_DETERMINISTIC_RELAXED_SIMD,
+            self.deterministic_relaxed_simd.then_some(1),
+        );
+        push(TAG_MAX_WASM_MEMORY, self.max_wasm_memory);
+        push(TAG_MAX_TABLE_ELEMENTS, self.max_table_elements);
+        push(TAG_PREOPENED_DIRS, self.preopened_dirs.then_some(1));
+        push(TAG_VFS, self.vfs.then_some(1));
+        push(TAG_CLI_ENVIRONMENT, self.cli_environment.then_some(1));
+        push(
+            TAG_WASM_FEATURES,
+            (self.wasm_features != WasmFeatures::default()).then_some(self.wasm_features.to_u64()),
+        );
+        push(
+            TAG_OPT_LEVEL,
+            (self.opt_level != OptLevel::default()).then_some(self.opt_level.to_u64()),
+        );
+        push(
+            TAG_FRESH_INSTANCE_PER_CALL,
+            self.fresh_instance_per_call.then_some(1),
+        );
+        bytes
+    }
+
+    /// Decode settings encoded with [`to_bytes`](Self::to_bytes)
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, RuntimeConfigError> {
+        let mut config = RuntimeConfig::default();
+        while let Some((&tag, rest)) = bytes.split_first() {
+            let (value, rest) = rest
+                .split_first_chunk::<8>()
+                .ok_or(RuntimeConfigError::Truncated)?;
+            let value = u64::from_le_bytes(*value);
+            match tag {
+                TAG_MAX_WASM_STACK => config.max_wasm_stack = Some(value),
+                TAG_CANONICALIZE_NANS => config.canonicalize_nans = value != 0,
+                TAG_GUEST_LOG_LEVEL => config.guest_log_level = Some(value),
+                TAG_MAX_RETURN_VALUE_SIZE => config.max_return_value_size = Some(value),
+                TAG_MIN_GUEST_ABI_VERSION => config.min_guest_abi_version = Some(value),
+                TAG_MAX_GUEST_ABI_VERSION => config.max_guest_abi_version = Some(value),
+                TAG_ENTROPY_POLICY => {
+                    config.entropy_policy = EntropyPolicy::from_u64(value)
+                        .ok_or(RuntimeConfigError::InvalidValue(tag, value))?
+                }
+                TAG_RNG_SEED => config.rng_seed = Some(value),
+                TAG_HEAP_SIZE => config.heap_size = Some(value),
+                TAG_INPUT_BUFFER_SIZE => config.input_buffer_size = Some(value),
+                TAG_OUTPUT_BUFFER_SIZE => config.output_buffer_size = Some(value),
+                TAG_CALL_TIMEOUT_MICROS => config.call_timeout_micros = Some(value),
+                TAG_UNSUPPORTED_WASI_ERRNO => {
+                    if value > u16::MAX as u64 {
+                        return Err(RuntimeConfigError::InvalidValue(tag, value));
+                    }
+                    config.unsupported_wasi_errno = Some(value)
+                }
+                TAG_PREFAULT_MEMORY => config.prefault_memory = value != 0,
+                TAG_WASI_P2 => config.wasi_p2 = value != 0,
+                TAG_EPOCH_INTERRUPTION => config.epoch_interruption = value != 0,
+                TAG_CAPTURE_STDOUT => config.capture_stdout = value != 0,
+                TAG_CAPTURE_STDERR => config.capture_stderr = value != 0,
+                TAG_DETERMINISTIC_RELAXED_SIMD => config.deterministic_relaxed_simd = value != 0,
+                TAG_MAX_WASM_MEMORY => config.max_wasm_memory = Some(value),
+                TAG_MAX_TABLE_ELEMENTS => config.max_table_elements = Some(value),
+                TAG_PREOPENED_DIRS => config.preopened_dirs = value != 0,
+                TAG_VFS => config.vfs = value != 0,
+                TAG_CLI_ENVIRONMENT => config.cli_environment = value != 0,
+                TAG_WASM_FEATURES => {
+                    config.wasm_features = WasmFeatures::from_u64(value)
+                        .ok_or(RuntimeConfigError::InvalidValue(tag, value))?
+                }
+                TAG_OPT_LEVEL => {
+                    config.opt_level = OptLevel::from_u64(value)
+                        .ok_or(RuntimeConfigError::InvalidValue(tag, value))?
+                }
+                TAG_FRESH_INSTANCE_PER_CALL => config.fresh_instance_per_call = value != 0,
+                _ => return Err(RuntimeConfigError::UnknownTag(tag)),
+            }
+            bytes = rest;
+        }
+        Ok(config)
+    }
+}
@@ -0,0 +1,93 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! `VecBytes` parameters too large for the input buffer, which the host
+//! passes in chunks before making the guest call. Each chunk is appended
+//! to the parameter it belongs to, and the staged parameters replace the
+//! placeholders the call is made with.
+
+use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use hyperlight_common::flatbuffer_wrappers::function_call::FunctionCall;
+use hyperlight_common::flatbuffer_wrappers::function_types::{
+    ParameterType, ParameterValue, ReturnType,
+};
+use hyperlight_common::flatbuffer_wrappers::guest_error::ErrorCode;
+use hyperlight_common::flatbuffer_wrappers::util::get_flatbuffer_result;
+use hyperlight_guest::error::{HyperlightGuestError, Result};
+use hyperlight_guest_bin::guest_function::definition::GuestFunctionDefinition;
+use hyperlight_guest_bin::guest_function::register::register_function;
+use spin::Mutex;
+
+// The index and contents of each parameter staged for the next call
+static STAGED: Mutex<Vec<(usize, Vec<u8>)>> = Mutex::new(Vec::new());
+
+/// Replace the parameters of a guest call with the parameters staged for
+/// it, if any
+pub(crate) fn take_staged(params: &mut [ParameterValue]) -> Result<()> {
+    for (index, bytes) in core::mem::take(&mut *STAGED.lock()) {
+        match params.get_mut(index) {
+            Some(ParameterValue::VecBytes(param)) => *param = bytes,
+            _ => {
+                return Err(HyperlightGuestError::new(
+                    ErrorCode::GuestFunctionParameterTypeMismatch,
+                    alloc::format!("staged parameter {} is not a VecBytes parameter", index),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn stage_parameter(function_call: FunctionCall) -> Result<Vec<u8>> {
+    let Some([ParameterValue::Int(index), ParameterValue::VecBytes(chunk)]) =
+        function_call.parameters.as_deref()
+    else {
+        return Err(HyperlightGuestError::new(
+            ErrorCode::GuestFunctionParameterTypeMismatch,
+            "Invalid parameters passed to StageParameter".to_string(),
+        ));
+    };
+    let index = *index as usize;
+    let mut staged = STAGED.lock();
+    match staged.iter_mut().find(|(i, _)| *i == index) {
+        Some((_, bytes)) => bytes.extend_from_slice(chunk),
+        None => staged.push((index, chunk.clone())),
+    }
+    Ok(get_flatbuffer_result::<()>(()))
+}
+
+fn clear_staged_parameters(_function_call: FunctionCall) -> Result<Vec<u8>> {
+    STAGED.lock().clear();
+    Ok(get_flatbuffer_result::<()>(()))
+}
+
+pub(crate) fn register_functions() {
+    register_function(GuestFunctionDefinition::new(
+        "StageParameter".to_string(),
+        vec![ParameterType::Int, ParameterType::VecBytes],
+        ReturnType::Void,
+        stage_parameter,
+    ));
+    register_function(GuestFunctionDefinition::new(
+        "ClearStagedParameters".to_string(),
+        vec![],
+        ReturnType::Void,
+        clear_staged_parameters,
+    ));
+}
@@ -0,0 +1,159 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The `hlwasm:state` import module, through which modules read and
+//! write the state cells declared by the host:
+//!
+//! ```text
+//! (import "hlwasm:state" "get-u64" (func (param $name_ptr i32) (param $name_len i32) (result i64)))
+//! (import "hlwasm:state" "set-u64" (func (param $name_ptr i32) (param $name_len i32) (param $value i64)))
+//! (import "hlwasm:state" "get-bytes" (func (param $name_ptr i32) (param $name_len i32) (param $buf_ptr i32) (param $buf_len i32) (result i32)))
+//! (import "hlwasm:state" "set-bytes" (func (param $name_ptr i32) (param $name_len i32) (param $buf_ptr i32) (param $buf_len i32)))
+//! ```
+//!
+//! `get-bytes` copies as much of the cell as fits in the buffer and
+//! returns the cell's full length. Accessing a cell that has not been
+//! declared, or that holds a different type, traps.
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use hyperlight_common::flatbuffer_wrappers::function_types::{
+    ParameterValue, ReturnType, ReturnValue,
+};
+use hyperlight_guest::error::Result;
+use hyperlight_guest_bin::host_comm::call_host_function;
+use wasmtime::{Caller, Extern, Linker, Memory};
+
+use crate::runtime_config::{
+    HOST_STATE_CELL_GET_BYTES_FUNCTION, HOST_STATE_CELL_GET_U64_FUNCTION,
+    HOST_STATE_CELL_SET_BYTES_FUNCTION, HOST_STATE_CELL_SET_U64_FUNCTION,
+};
+use crate::{call_tracker, map_wasmtime_error};
+
+const STATE_MODULE: &str = "hlwasm:state";
+
+fn memory<T>(ctx: &mut Caller<'_, T>) -> wasmtime::Result<Memory> {
+    ctx.get_export("memory")
+        .and_then(Extern::into_memory)
+        .ok_or_else(|| wasmtime::Error::msg("hlwasm:state requires an exported memory"))
+}
+
+fn read_bytes<T>(ctx: &mut Caller<'_, T>, ptr: i32, len: i32) -> wasmtime::Result<Vec<u8>> {
+    let memory = memory(ctx)?;
+    let mut bytes = vec![0u8; len as u32 as usize];
+    memory.read(&mut *ctx, ptr as u32 as usize, &mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_name<T>(ctx: &mut Caller<'_, T>, ptr: i32, len: i32) -> wasmtime::Result<String> {
+    String::from_utf8(read_bytes(ctx, ptr, len)?)
+        .map_err(|_| wasmtime::Error::msg("state cell name is not valid UTF-8"))
+}
+
+fn call_host<R: TryFrom<ReturnValue>>(
+    function: &str,
+    params: Vec<ParameterValue>,
+    return_type: ReturnType,
+) -> wasmtime::Result<R> {
+    call_tracker::record_host_call();
+    call_host_function::<R>(function, Some(params), return_type)
+        .map_err(|e| wasmtime::Error::msg(e.message))
+}
+
+pub(crate) fn register_handlers<T: 'static>(linker: &mut Linker<T>) -> Result<()> {
+    linker
+        .func_wrap(
+            STATE_MODULE,
+            "get-u64",
+            |mut ctx: Caller<'_, T>, name_ptr: i32, name_len: i32| -> wasmtime::Result<i64> {
+                let name = read_name(&mut ctx, name_ptr, name_len)?;
+                let value: u64 = call_host(
+                    HOST_STATE_CELL_GET_U64_FUNCTION,
+                    vec![ParameterValue::String(name)],
+                    ReturnType::ULong,
+                )?;
+                Ok(value as i64)
+            },
+        )
+        .map_err(map_wasmtime_error)?;
+    linker
+        .func_wrap(
+            STATE_MODULE,
+            "set-u64",
+            |mut ctx: Caller<'_, T>, name_ptr: i32, name_len: i32, value: i64| {
+                let name = read_name(&mut ctx, name_ptr, name_len)?;
+                call_host::<()>(
+                    HOST_STATE_CELL_SET_U64_FUNCTION,
+                    vec![
+                        ParameterValue::String(name),
+                        ParameterValue::ULong(value as u64),
+                    ],
+                    ReturnType::Void,
+                )
+            },
+        )
+        .map_err(map_wasmtime_error)?;
+    linker
+        .func_wrap(
+            STATE_MODULE,
+            "get-bytes",
+            |mut ctx: Caller<'_, T>,
+             name_ptr: i32,
+             name_len: i32,
+             buf_ptr: i32,
+             buf_len: i32|
+             -> wasmtime::Result<i32> {
+                let name = read_name(&mut ctx, name_ptr, name_len)?;
+                let value: Vec<u8> = call_host(
+                    HOST_STATE_CELL_GET_BYTES_FUNCTION,
+                    vec![ParameterValue::String(name)],
+                    ReturnType::VecBytes,
+                )?;
+                let len = value.len().min(buf_len as u32 as usize);
+                let memory = memory(&mut ctx)?;
+                memory.write(&mut ctx, buf_ptr as u32 as usize, &value[..len])?;
+                i32::try_from(value.len())
+                    .map_err(|_| wasmtime::Error::msg("state cell is too large"))
+            },
+        )
+        .map_err(map_wasmtime_error)?;
+    linker
+        .func_wrap(
+            STATE_MODULE,
+            "set-bytes",
+            |mut ctx: Caller<'_, T>,
+             name_ptr: i32,
+             name_len: i32,
+             buf_ptr: i32,
+             buf_len: i32|
+             -> wasmtime::Result<()> {
+                let name = read_name(&mut ctx, name_ptr, name_len)?;
+                let value = read_bytes(&mut ctx, buf_ptr, buf_len)?;
+                call_host::<()>(
+                    HOST_STATE_CELL_SET_BYTES_FUNCTION,
+                    vec![
+                        ParameterValue::String(name),
+                        ParameterValue::VecBytes(value),
+                    ],
+                    ReturnType::Void,
+                )
+            },
+        )
+        .map_err(map_wasmtime_error)?;
+    Ok(())
+}
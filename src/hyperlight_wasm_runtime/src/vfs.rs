@@ -0,0 +1,128 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The in-memory filesystem the host provided for modules. Its files are
+//! copied from the host when the runtime is initialized, so they are part
+//! of the sandbox's snapshots, and modules read them through the wasip1
+//! functions of [`wasip1_fs`](crate::wasip1_fs) without calling the host.
+//! Its directories are those in the paths of its files.
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use hyperlight_common::flatbuffer_wrappers::function_types::{ParameterValue, ReturnType};
+use hyperlight_common::flatbuffer_wrappers::guest_error::ErrorCode;
+use hyperlight_guest::error::{HyperlightGuestError, Result};
+use hyperlight_guest_bin::host_comm::call_host_function;
+use spin::Mutex;
+
+use crate::call_tracker;
+use crate::runtime_config::RuntimeConfig;
+use crate::wasi_fs::{
+    self, Stat, FILETYPE_DIRECTORY, FILETYPE_REGULAR_FILE, HOST_VFS_FILES_FUNCTION,
+    HOST_VFS_READ_FUNCTION,
+};
+
+// Copied in chunks of at most this many bytes if the size of the
+// sandbox's input buffer is not known
+const DEFAULT_CHUNK_SIZE: usize = 8 * 1024;
+
+// The paths, normalized, and contents of the files. Set by
+// init_wasm_runtime from the host.
+static FILES: Mutex<Vec<(String, Vec<u8>)>> = Mutex::new(Vec::new());
+
+pub(crate) fn configure(runtime_config: &RuntimeConfig) -> Result<()> {
+    let mut files = FILES.lock();
+    files.clear();
+    if !runtime_config.vfs {
+        return Ok(());
+    }
+    call_tracker::record_host_call();
+    let listing =
+        call_host_function::<Vec<u8>>(HOST_VFS_FILES_FUNCTION, None, ReturnType::VecBytes)?;
+    let listing = wasi_fs::decode_vfs_files(&listing).ok_or_else(|| {
+        HyperlightGuestError::new(
+            ErrorCode::GuestError,
+            String::from("malformed in-memory filesystem listing"),
+        )
+    })?;
+    // Each chunk must fit in the input buffer
+    let chunk_size = runtime_config
+        .input_buffer_size
+        .map_or(DEFAULT_CHUNK_SIZE, |size| size as usize / 2);
+    for (index, (path, size)) in listing.into_iter().enumerate() {
+        let mut contents = Vec::with_capacity(size as usize);
+        while (contents.len() as u64) < size {
+            call_tracker::record_host_call();
+            let chunk = call_host_function::<Vec<u8>>(
+                HOST_VFS_READ_FUNCTION,
+                Some(vec![
+                    ParameterValue::Int(index as i32),
+                    ParameterValue::ULong(contents.len() as u64),
+                    ParameterValue::Int(chunk_size as i32),
+                ]),
+                ReturnType::VecBytes,
+            )?;
+            if chunk.is_empty() {
+                break;
+            }
+            contents.extend_from_slice(&chunk);
+        }
+        files.push((path, contents));
+    }
+    Ok(())
+}
+
+/// The file or directory at the normalized `path`, with the index of the
+/// file, or `None` if the filesystem has none there
+pub(crate) fn stat(path: &str) -> Option<(Stat, Option<usize>)> {
+    let files = FILES.lock();
+    if files.is_empty() {
+        return None;
+    }
+    if let Some(index) = files.iter().position(|(file, _)| file == path) {
+        let stat = Stat {
+            filetype: FILETYPE_REGULAR_FILE,
+            size: files[index].1.len() as u64,
+        };
+        return Some((stat, Some(index)));
+    }
+    let is_dir = path == "/"
+        || files
+            .iter()
+            .any(|(file, _)| wasi_fs::relative_path(file, path).is_some());
+    is_dir.then_some((
+        Stat {
+            filetype: FILETYPE_DIRECTORY,
+            size: 0,
+        },
+        None,
+    ))
+}
+
+/// At most `len` bytes of the file at `index` from `offset`
+pub(crate) fn read(index: usize, offset: u64, len: usize) -> Vec<u8> {
+    let files = FILES.lock();
+    let Some((_, contents)) = files.get(index) else {
+        return Vec::new();
+    };
+    let start = usize::try_from(offset)
+        .unwrap_or(usize::MAX)
+        .min(contents.len());
+    let end = start.saturating_add(len).min(contents.len());
+    contents[start..end].to_vec()
+}
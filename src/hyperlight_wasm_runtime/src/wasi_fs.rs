@@ -0,0 +1,192 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Host directories preopened for modules as WASI filesystems. The guest
+//! keeps the file descriptors and their offsets, and reads the files
+//! through the host functions below, passing their paths in the guest,
+//! which the host resolves against the preopened directories.
+//!
+//! The files of the in-memory filesystem are instead copied into the
+//! guest once, when the runtime is initialized, after which modules read
+//! them without calling the host.
+//!
+//! Paths are normalized before they are passed to the host: `.`
+//! components and empty components are dropped, `..` components remove
+//! the component before them, and there is no trailing `/`. The root of
+//! a relative preopen such as `.` is the empty path.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// The name of the host function the guest calls to list the preopened
+/// directories. It takes no parameters and returns their paths in the
+/// guest, normalized and separated by newlines, as a `String`.
+pub const HOST_FS_PREOPENS_FUNCTION: &str = "HostFsPreopens";
+
+/// The name of the host function the guest calls to look up a file or
+/// directory. It takes its normalized path in the guest as a `String`
+/// and returns the result encoded with [`encode_stat`] as a `VecBytes`.
+pub const HOST_FS_STAT_FUNCTION: &str = "HostFsStat";
+
+/// The name of the host function the guest calls to read a file. It
+/// takes its normalized path in the guest as a `String`, the offset to
+/// read from as a `ULong` and the most bytes to read as an `Int`, and
+/// returns the bytes read, which are fewer only at the end of the file,
+/// as a `VecBytes`.
+pub const HOST_FS_READ_FUNCTION: &str = "HostFsRead";
+
+/// The name of the host function the guest calls to list the files of
+/// the in-memory filesystem. It takes no parameters and returns their
+/// paths and sizes encoded with [`encode_vfs_files`] as a `VecBytes`.
+pub const HOST_VFS_FILES_FUNCTION: &str = "HostVfsFiles";
+
+/// The name of the host function the guest calls to copy a file of the
+/// in-memory filesystem. It takes the file's index in the list returned
+/// by [`HOST_VFS_FILES_FUNCTION`] as an `Int`, the offset to copy from
+/// as a `ULong` and the most bytes to copy as an `Int`, and returns the
+/// bytes as a `VecBytes`.
+pub const HOST_VFS_READ_FUNCTION: &str = "HostVfsRead";
+
+/// The WASI filetype of directories
+pub const FILETYPE_DIRECTORY: u8 = 3;
+/// The WASI filetype of regular files
+pub const FILETYPE_REGULAR_FILE: u8 = 4;
+
+/// The WASI errno for a path that escapes the preopened directories
+pub const ERRNO_NOTCAPABLE: u16 = 76;
+/// The WASI errno for a path that does not exist
+pub const ERRNO_NOENT: u16 = 44;
+/// The WASI errno for a file the host may not read
+pub const ERRNO_ACCES: u16 = 2;
+/// The WASI errno for a file the host failed to look up or read
+pub const ERRNO_IO: u16 = 29;
+/// The WASI errno for a path that is neither a regular file nor a
+/// directory
+pub const ERRNO_INVAL: u16 = 28;
+
+/// A file or directory in a preopened directory
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Stat {
+    /// Its WASI filetype, [`FILETYPE_DIRECTORY`] or
+    /// [`FILETYPE_REGULAR_FILE`]
+    pub filetype: u8,
+    /// Its size in bytes
+    pub size: u64,
+}
+
+/// Encode the result of looking up a path, as the filetype followed by
+/// the size as a little-endian `u64`, or the errno as a little-endian
+/// `u16` if the lookup failed
+pub fn encode_stat(stat: Result<Stat, u16>) -> Vec<u8> {
+    match stat {
+        Ok(stat) => {
+            let mut bytes = Vec::with_capacity(9);
+            bytes.push(stat.filetype);
+            bytes.extend_from_slice(&stat.size.to_le_bytes());
+            bytes
+        }
+        Err(errno) => errno.to_le_bytes().to_vec(),
+    }
+}
+
+/// Decode a result encoded with [`encode_stat`]
+pub fn decode_stat(bytes: &[u8]) -> Result<Stat, u16> {
+    match bytes {
+        [filetype, size @ ..] if size.len() == 8 => Ok(Stat {
+            filetype: *filetype,
+            size: u64::from_le_bytes(size.try_into().unwrap_or_default()),
+        }),
+        [lo, hi] => Err(u16::from_le_bytes([*lo, *hi])),
+        _ => Err(ERRNO_INVAL),
+    }
+}
+
+/// Encode the paths and sizes of the files of the in-memory filesystem,
+/// each as the length of the path as a little-endian `u32`, the path and
+/// the size as a little-endian `u64`
+pub fn encode_vfs_files<'a>(files: impl IntoIterator<Item = (&'a str, u64)>) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for (path, size) in files {
+        bytes.extend_from_slice(&(path.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(path.as_bytes());
+        bytes.extend_from_slice(&size.to_le_bytes());
+    }
+    bytes
+}
+
+/// Decode the paths and sizes encoded with [`encode_vfs_files`], or
+/// return `None` if they are malformed
+pub fn decode_vfs_files(mut bytes: &[u8]) -> Option<Vec<(String, u64)>> {
+    let mut files = Vec::new();
+    while let Some((len, rest)) = bytes.split_first_chunk::<4>() {
+        let len = u32::from_le_bytes(*len) as usize;
+        let path = rest.get(..len)?;
+        let (size, rest) = rest[len..].split_first_chunk::<8>()?;
+        files.push((
+            String::from(core::str::from_utf8(path).ok()?),
+            u64::from_le_bytes(*size),
+        ));
+        bytes = rest;
+    }
+    bytes.is_empty().then_some(files)
+}
+
+/// Normalize `path`, or return `None` if a `..` component climbs above
+/// its root
+pub fn normalize_path(path: &str) -> Option<String> {
+    let mut components: Vec<&str> = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop()?;
+            }
+            component => components.push(component),
+        }
+    }
+    let mut normalized = String::new();
+    if path.starts_with('/') {
+        normalized.push('/');
+    }
+    normalized.push_str(&components.join("/"));
+    Some(normalized)
+}
+
+/// Normalize the relative `path` in the directory `dir`, which is
+/// normalized, or return `None` if it climbs above the root
+pub fn join_path(dir: &str, path: &str) -> Option<String> {
+    let mut joined = String::from(dir);
+    joined.push('/');
+    joined.push_str(path);
+    if dir.is_empty() {
+        // The root of a relative preopen
+        joined.remove(0);
+    }
+    normalize_path(&joined)
+}
+
+/// The path of the normalized `path` relative to the normalized `dir`,
+/// or `None` if it is not in `dir`
+pub fn relative_path<'a>(path: &'a str, dir: &str) -> Option<&'a str> {
+    if path == dir {
+        return Some("");
+    }
+    match dir {
+        "" => (!path.starts_with('/')).then_some(path),
+        "/" => path.strip_prefix('/'),
+        dir => path.strip_prefix(dir)?.strip_prefix('/'),
+    }
+}
@@ -15,34 +15,218 @@ limitations under the License.
 */
 
 /// A very minimal implementation of just enough wasip1 functions for the
-/// things that were working in the old host to continue working
-use alloc::string::ToString;
+/// things that were working in the old host to continue working.
+///
+/// Other WASI functions imported by a module are linked to stubs that
+/// return the errno chosen with
+/// [`RuntimeConfig::unsupported_wasi_errno`], or trap if the function has
+/// no errno result. The names of the stubbed functions are returned to
+/// the host by the `GetStubbedWasiImports` guest function.
+use alloc::format;
+use alloc::string::{String, ToString};
 use alloc::vec;
 use alloc::vec::Vec;
+use core::sync::atomic::{AtomicI32, Ordering};
 
+use hyperlight_common::flatbuffer_wrappers::function_call::FunctionCall;
 use hyperlight_common::flatbuffer_wrappers::function_types::{ParameterValue, ReturnType};
+use hyperlight_common::flatbuffer_wrappers::util::get_flatbuffer_result;
 use hyperlight_guest::error::Result;
+use hyperlight_guest_bin::guest_function::definition::GuestFunctionDefinition;
+use hyperlight_guest_bin::guest_function::register::register_function;
 use hyperlight_guest_bin::host_comm::call_host_function;
-use wasmtime::{Caller, Extern, Linker};
+use spin::Mutex;
+use wasmtime::{Caller, Extern, ExternType, Linker, Module, Store, Val, ValType};
 
-use crate::map_wasmtime_error;
+use crate::runtime_config::{RuntimeConfig, HOST_CLOCK_FUNCTION};
+use crate::{
+    call_tracker, cli_environment, guest_abort, map_wasmtime_error, output_capture, random,
+    wasip1_fs,
+};
+
+// WASI errno values
+const ERRNO_FAULT: i32 = 21;
+const ERRNO_INVAL: i32 = 28;
+const ERRNO_NOSYS: i32 = 52;
+const ERRNO_NOTCAPABLE: i32 = 76;
+
+const WASI_MODULE: &str = "wasi_snapshot_preview1";
+
+// Set by init_wasm_runtime from the runtime config
+static UNSUPPORTED_ERRNO: AtomicI32 = AtomicI32::new(ERRNO_NOSYS);
+// The WASI functions imported by the loaded module that were stubbed
+static STUBBED_IMPORTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+pub(crate) fn configure(runtime_config: &RuntimeConfig) {
+    let errno = runtime_config
+        .unsupported_wasi_errno
+        .map_or(ERRNO_NOSYS, |errno| errno as i32);
+    UNSUPPORTED_ERRNO.store(errno, Ordering::Relaxed);
+}
+
+/// Link a stub for each WASI function imported by `module` that `linker`
+/// does not define
+pub(crate) fn stub_unsupported_imports(
+    linker: &mut Linker<()>,
+    store: &mut Store<()>,
+    module: &Module,
+) -> Result<()> {
+    let mut stubbed = Vec::new();
+    for import in module.imports() {
+        if import.module() != WASI_MODULE || linker.get_by_import(&mut *store, &import).is_some() {
+            continue;
+        }
+        let ExternType::Func(ty) = import.ty() else {
+            continue;
+        };
+        let name = import.name().to_string();
+        let returns_errno =
+            ty.results().len() == 1 && ty.results().all(|r| matches!(r, ValType::I32));
+        let trap_message = format!("unsupported WASI function {} called", name);
+        linker
+            .func_new(WASI_MODULE, &name, ty, move |_, _, results: &mut [Val]| {
+                if !returns_errno {
+                    return Err(wasmtime::Error::msg(trap_message.clone()));
+                }
+                results[0] = Val::I32(UNSUPPORTED_ERRNO.load(Ordering::Relaxed));
+                Ok(())
+            })
+            .map_err(map_wasmtime_error)?;
+        stubbed.push(name);
+    }
+    // Modules linked before the current one may have had stubs too
+    STUBBED_IMPORTS.lock().extend(stubbed);
+    Ok(())
+}
+
+/// Return the names of the stubbed WASI functions, separated by commas
+fn get_stubbed_wasi_imports(_function_call: FunctionCall) -> Result<Vec<u8>> {
+    let stubbed = STUBBED_IMPORTS.lock().join(",");
+    Ok(get_flatbuffer_result::<&str>(&stubbed))
+}
+
+pub(crate) fn register_functions() {
+    register_function(GuestFunctionDefinition::new(
+        "GetStubbedWasiImports".to_string(),
+        vec![],
+        ReturnType::String,
+        get_stubbed_wasi_imports,
+    ));
+}
+
+/// Whether modules can write to `fd`, which is stdout unless the host
+/// captures stderr
+fn is_writable(fd: i32) -> bool {
+    fd == output_capture::STDOUT || output_capture::is_captured(fd)
+}
+
+/// Write the number of NUL-terminated strings in `strings` and their
+/// total size, as `args_sizes_get` and `environ_sizes_get` return them
+fn strings_sizes_get<T>(
+    ctx: &mut Caller<'_, T>,
+    strings: &[u8],
+    count_ptr: i32,
+    size_ptr: i32,
+) -> i32 {
+    let Some(memory) = ctx.get_export("memory").and_then(Extern::into_memory) else {
+        return ERRNO_INVAL;
+    };
+    let count = strings.iter().filter(|b| **b == 0).count() as u32;
+    let size = strings.len() as u32;
+    if memory
+        .write(&mut *ctx, count_ptr as u32 as usize, &count.to_le_bytes())
+        .and_then(|_| memory.write(&mut *ctx, size_ptr as u32 as usize, &size.to_le_bytes()))
+        .is_err()
+    {
+        return ERRNO_FAULT;
+    }
+    0
+}
+
+/// Write the NUL-terminated strings in `strings` to `buf` and a pointer
+/// to each of them to `ptrs`, as `args_get` and `environ_get` return them
+fn strings_get<T>(ctx: &mut Caller<'_, T>, strings: &[u8], ptrs: i32, buf: i32) -> i32 {
+    let Some(memory) = ctx.get_export("memory").and_then(Extern::into_memory) else {
+        return ERRNO_INVAL;
+    };
+    let buf = buf as u32;
+    let mut offsets = vec![0u32];
+    offsets.extend(
+        strings
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| **b == 0)
+            .map(|(i, _)| i as u32 + 1),
+    );
+    // The last offset is the end of the last string
+    offsets.pop();
+    let mut ptr_bytes = Vec::with_capacity(offsets.len() * 4);
+    for offset in offsets {
+        ptr_bytes.extend_from_slice(&buf.wrapping_add(offset).to_le_bytes());
+    }
+    if memory
+        .write(&mut *ctx, buf as usize, strings)
+        .and_then(|_| memory.write(&mut *ctx, ptrs as u32 as usize, &ptr_bytes))
+        .is_err()
+    {
+        return ERRNO_FAULT;
+    }
+    0
+}
 
 pub(crate) fn register_handlers<T: 'static>(linker: &mut Linker<T>) -> Result<()> {
     linker
         .func_wrap(
-            "wasi_snapshot_preview1",
-            "fd_seek",
-            |fd: i32, filedelta: i64, whence: i32, _retptr: i32| -> i32 {
-                panic!("fd_seek called {} {} {}", fd, filedelta, whence);
+            WASI_MODULE,
+            "args_sizes_get",
+            |mut ctx: Caller<'_, T>, argc: i32, argv_buf_size: i32| -> i32 {
+                strings_sizes_get(&mut ctx, &cli_environment::args(), argc, argv_buf_size)
             },
         )
         .map_err(map_wasmtime_error)?;
     linker
         .func_wrap(
-            "wasi_snapshot_preview1",
+            WASI_MODULE,
+            "args_get",
+            |mut ctx: Caller<'_, T>, argv: i32, argv_buf: i32| -> i32 {
+                strings_get(&mut ctx, &cli_environment::args(), argv, argv_buf)
+            },
+        )
+        .map_err(map_wasmtime_error)?;
+    linker
+        .func_wrap(
+            WASI_MODULE,
+            "environ_sizes_get",
+            |mut ctx: Caller<'_, T>, environc: i32, environ_buf_size: i32| -> i32 {
+                strings_sizes_get(
+                    &mut ctx,
+                    &cli_environment::environment(),
+                    environc,
+                    environ_buf_size,
+                )
+            },
+        )
+        .map_err(map_wasmtime_error)?;
+    linker
+        .func_wrap(
+            WASI_MODULE,
+            "environ_get",
+            |mut ctx: Caller<'_, T>, environ: i32, environ_buf: i32| -> i32 {
+                strings_get(
+                    &mut ctx,
+                    &cli_environment::environment(),
+                    environ,
+                    environ_buf,
+                )
+            },
+        )
+        .map_err(map_wasmtime_error)?;
+    linker
+        .func_wrap(
+            WASI_MODULE,
             "fd_write",
             |mut ctx: Caller<'_, T>, fd: i32, iovs: i32, iovs_len: i32, retptr: i32| {
-                if fd != 1 {
+                if !is_writable(fd) && fd != output_capture::STDERR {
                     return -1;
                 }
                 let iovs = iovs as usize;
@@ -50,7 +234,7 @@ pub(crate) fn register_handlers<T: 'static>(linker: &mut Linker<T>) -> Result<()
                 let Some(memory) = ctx.get_export("memory").and_then(Extern::into_memory) else {
                     return -1;
                 };
-                let mut total_written: i32 = 0;
+                let mut bufs = Vec::with_capacity(iovs_len as usize);
                 for i in 0..iovs_len as usize {
                     // iovec is size 8
                     let iov = iovs + 8 * i;
@@ -61,18 +245,26 @@ pub(crate) fn register_handlers<T: 'static>(linker: &mut Linker<T>) -> Result<()
                     // offset 4 is iovec.buf_len
                     memory.read(&mut ctx, iov + 4, &mut bytes).unwrap();
                     let buf_len = i32::from_le_bytes(bytes);
-                    let mut string_bytes = vec![0u8; buf_len as usize];
-                    memory
-                        .read(&mut ctx, buf as usize, &mut string_bytes)
-                        .unwrap();
-                    let Ok(str) = core::str::from_utf8(&string_bytes) else {
+                    let mut buf_bytes = vec![0u8; buf_len as usize];
+                    memory.read(&mut ctx, buf as usize, &mut buf_bytes).unwrap();
+                    bufs.push(buf_bytes);
+                }
+                // Kept in case the guest aborts, even if the host does
+                // not capture stderr
+                if fd == output_capture::STDERR {
+                    bufs.iter().for_each(|buf| guest_abort::record_stderr(buf));
+                }
+                if !is_writable(fd) {
+                    return -1;
+                }
+                let mut total_written: i32 = 0;
+                for buf_bytes in bufs {
+                    // Printed output must be a string
+                    if !output_capture::is_captured(fd) && core::str::from_utf8(&buf_bytes).is_err()
+                    {
                         return -2;
-                    };
-                    let Ok(written) = call_host_function::<i32>(
-                        "HostPrint",
-                        Some(Vec::from(&[ParameterValue::String(str.to_string())])),
-                        ReturnType::Int,
-                    ) else {
+                    }
+                    let Some(Ok(written)) = output_capture::write(fd, &buf_bytes) else {
                         return -3;
                     };
                     total_written += written;
@@ -85,45 +277,116 @@ pub(crate) fn register_handlers<T: 'static>(linker: &mut Linker<T>) -> Result<()
         )
         .map_err(map_wasmtime_error)?;
     linker
-        .func_wrap("wasi_snapshot_preview1", "fd_close", |fd: i32| -> i32 {
-            panic!("fd_close called {}", fd);
-        })
+        .func_wrap(
+            WASI_MODULE,
+            "proc_exit",
+            |_: Caller<'_, T>, code: i32| -> wasmtime::Result<()> { Err(guest_abort::exit(code)) },
+        )
         .map_err(map_wasmtime_error)?;
     linker
         .func_wrap(
-            "wasi_snapshot_preview1",
+            WASI_MODULE,
             "random_get",
-            |buff: i32, len: i32| -> i32 {
-                panic!("random_get called for {} with len {}", buff, len);
+            |mut ctx: Caller<'_, T>, buf: i32, len: i32| -> i32 {
+                let Some(memory) = ctx.get_export("memory").and_then(Extern::into_memory) else {
+                    return ERRNO_INVAL;
+                };
+                // Check the buffer before generating its contents, so that a
+                // bad length cannot make the guest allocate or ask the host
+                // for more bytes than the module's memory can hold
+                let (buf, len) = (buf as u32 as usize, len as u32 as usize);
+                if buf.saturating_add(len) > memory.data_size(&ctx) {
+                    return ERRNO_FAULT;
+                }
+                let Some(bytes) = random::random_bytes(len) else {
+                    return ERRNO_NOTCAPABLE;
+                };
+                if memory.write(&mut ctx, buf, &bytes).is_err() {
+                    return ERRNO_FAULT;
+                }
+                0
             },
         )
         .map_err(map_wasmtime_error)?;
     linker
         .func_wrap(
-            "wasi_snapshot_preview1",
+            WASI_MODULE,
+            "clock_time_get",
+            |mut ctx: Caller<'_, T>, clock_id: i32, _precision: i64, retptr: i32| -> i32 {
+                let Some(memory) = ctx.get_export("memory").and_then(Extern::into_memory) else {
+                    return ERRNO_INVAL;
+                };
+                // The host applies the sandbox's virtual time offset and scale
+                call_tracker::record_host_call();
+                let time = match call_host_function::<i64>(
+                    HOST_CLOCK_FUNCTION,
+                    Some(vec![ParameterValue::Int(clock_id)]),
+                    ReturnType::Long,
+                ) {
+                    Ok(time) if time >= 0 => time as u64,
+                    _ => return ERRNO_INVAL,
+                };
+                if memory
+                    .write(&mut ctx, retptr as u32 as usize, &time.to_le_bytes())
+                    .is_err()
+                {
+                    return ERRNO_FAULT;
+                }
+                0
+            },
+        )
+        .map_err(map_wasmtime_error)?;
+    linker
+        .func_wrap(
+            WASI_MODULE,
+            "clock_res_get",
+            |mut ctx: Caller<'_, T>, clock_id: i32, retptr: i32| -> i32 {
+                if !(0..=3).contains(&clock_id) {
+                    return ERRNO_INVAL;
+                }
+                let Some(memory) = ctx.get_export("memory").and_then(Extern::into_memory) else {
+                    return ERRNO_INVAL;
+                };
+                // All clocks are read on the host in nanoseconds
+                if memory
+                    .write(&mut ctx, retptr as u32 as usize, &1u64.to_le_bytes())
+                    .is_err()
+                {
+                    return ERRNO_FAULT;
+                }
+                0
+            },
+        )
+        .map_err(map_wasmtime_error)?;
+    linker
+        .func_wrap(
+            WASI_MODULE,
             "fd_fdstat_get",
             |mut ctx: Caller<'_, T>, fd: i32, retptr: i32| {
-                if fd != 1 {
-                    return -1;
-                }
+                // Files in preopened directories may only be read
+                let (filetype, rights) = match wasip1_fs::fdstat(fd) {
+                    Some(fdstat) => fdstat,
+                    None if is_writable(fd) => (4, 0b100011),
+                    None => return -1,
+                };
                 if let Some(()) = (|| {
                     let retptr = retptr as usize;
                     let memory = ctx.get_export("memory")?.into_memory()?;
                     // offset 0 is fdstat.fs_filetype; 4 is regular_file
                     memory
-                        .write(&mut ctx, retptr, &(4_u16).to_le_bytes())
+                        .write(&mut ctx, retptr, &(filetype as u16).to_le_bytes())
                         .unwrap();
                     // offset 2 is fdstat.fs_flags; 0 is no particular flags
                     memory
                         .write(&mut ctx, retptr + 2, &(0_u16).to_le_bytes())
                         .unwrap();
-                    // offset 8 fdstat.fs_rights_base; 0b100011 is read/write/seek
+                    // offset 8 fdstat.fs_rights_base; 0b100011 is read/write/seek for stdio
                     memory
-                        .write(&mut ctx, retptr + 8, &(0b100011_u64).to_le_bytes())
+                        .write(&mut ctx, retptr + 8, &rights.to_le_bytes())
                         .unwrap();
-                    // offset 8 fdstat.fs_rights_inheriting; 0b100011 is read/write/seek
+                    // offset 8 fdstat.fs_rights_inheriting, the same
                     memory
-                        .write(&mut ctx, retptr + 16, &(0b100011_u64).to_le_bytes())
+                        .write(&mut ctx, retptr + 16, &rights.to_le_bytes())
                         .unwrap();
                     Some(())
                 })() {
@@ -0,0 +1,533 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! The wasip1 functions that read the directories the host preopened,
+//! see [`wasi_fs`](crate::wasi_fs), and the in-memory filesystem, see
+//! [`vfs`](crate::vfs), which is preopened as `/` after them. They are
+//! only linked if the host provided either, and only read: requests to
+//! create, truncate or write files fail with `ERRNO_ROFS`.
+//!
+//! The file descriptors, starting with the preopened directories at 3,
+//! are kept in the guest with the path of each file and its offset. The
+//! in-memory filesystem is looked up first, and each lookup or read of a
+//! file it does not have is a host call.
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use hyperlight_common::flatbuffer_wrappers::function_types::{ParameterValue, ReturnType};
+use hyperlight_guest::error::Result;
+use hyperlight_guest_bin::host_comm::call_host_function;
+use spin::Mutex;
+use wasmtime::{Caller, Extern, Linker, Memory};
+
+use crate::runtime_config::RuntimeConfig;
+use crate::wasi_fs::{
+    self, Stat, FILETYPE_DIRECTORY, FILETYPE_REGULAR_FILE, HOST_FS_PREOPENS_FUNCTION,
+    HOST_FS_READ_FUNCTION, HOST_FS_STAT_FUNCTION,
+};
+use crate::{call_tracker, map_wasmtime_error, vfs};
+
+// WASI errno values
+const ERRNO_BADF: i32 = 8;
+const ERRNO_FAULT: i32 = 21;
+const ERRNO_INVAL: i32 = 28;
+const ERRNO_IO: i32 = 29;
+const ERRNO_ISDIR: i32 = 31;
+const ERRNO_NOENT: i32 = 44;
+const ERRNO_NOTDIR: i32 = 54;
+const ERRNO_ROFS: i32 = 69;
+const ERRNO_NOTCAPABLE: i32 = 76;
+
+// path_open flags that would modify files
+const OFLAGS_CREAT: i32 = 1 << 0;
+const OFLAGS_DIRECTORY: i32 = 1 << 1;
+const OFLAGS_EXCL: i32 = 1 << 2;
+const OFLAGS_TRUNC: i32 = 1 << 3;
+const FDFLAGS_APPEND: i32 = 1 << 0;
+const RIGHTS_FD_WRITE: i64 = 1 << 6;
+
+// The rights of file descriptors: read, seek, tell and filestat_get for
+// files, and path_open and path_filestat_get for directories
+const RIGHTS_FILE: u64 = (1 << 1) | (1 << 2) | (1 << 5) | (1 << 21);
+const RIGHTS_DIRECTORY: u64 = (1 << 13) | (1 << 18) | (1 << 21);
+
+const WASI_MODULE: &str = "wasi_snapshot_preview1";
+const FIRST_FD: i32 = 3;
+
+// Bounds each read of a host file so that the bytes read fit in the sandbox's input
+// buffer. Reads may return fewer bytes than requested, so modules read
+// larger files in several calls.
+const MAX_READ: usize = 8 * 1024;
+
+// The result of a WASI function, whose error is an errno
+type WasiResult<R> = core::result::Result<R, i32>;
+
+struct OpenFd {
+    // The normalized path in the guest
+    path: String,
+    filetype: u8,
+    offset: u64,
+    preopen: bool,
+    // The index of the file in the in-memory filesystem, for its files
+    vfs_file: Option<usize>,
+}
+
+// Set by init_wasm_runtime from the runtime config
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static HOST_DIRS: AtomicBool = AtomicBool::new(false);
+// The open file descriptors, indexed from FIRST_FD. They are part of the
+// sandbox's memory, so restoring a snapshot closes the files opened since.
+static FDS: Mutex<Vec<Option<OpenFd>>> = Mutex::new(Vec::new());
+
+pub(crate) fn configure(runtime_config: &RuntimeConfig) -> Result<()> {
+    ENABLED.store(
+        runtime_config.preopened_dirs || runtime_config.vfs,
+        Ordering::Relaxed,
+    );
+    HOST_DIRS.store(runtime_config.preopened_dirs, Ordering::Relaxed);
+    let mut fds = FDS.lock();
+    fds.clear();
+    let mut preopens = Vec::new();
+    if runtime_config.preopened_dirs {
+        call_tracker::record_host_call();
+        let host_dirs =
+            call_host_function::<String>(HOST_FS_PREOPENS_FUNCTION, None, ReturnType::String)?;
+        preopens.extend(host_dirs.split('\n').map(String::from));
+    }
+    if runtime_config.vfs {
+        preopens.push(String::from("/"));
+    }
+    fds.extend(preopens.into_iter().map(|path| {
+        Some(OpenFd {
+            path,
+            filetype: FILETYPE_DIRECTORY,
+            offset: 0,
+            preopen: true,
+            vfs_file: None,
+        })
+    }));
+    Ok(())
+}
+
+/// The WASI filetype and rights of `fd`, or `None` if it is not a file
+/// or directory opened in a preopened directory
+pub(crate) fn fdstat(fd: i32) -> Option<(u8, u64)> {
+    with_fd(fd, |open| {
+        let rights = match open.filetype {
+            FILETYPE_DIRECTORY => RIGHTS_DIRECTORY,
+            _ => RIGHTS_FILE,
+        };
+        Ok((open.filetype, rights))
+    })
+    .ok()
+}
+
+fn with_fd<R>(fd: i32, f: impl FnOnce(&mut OpenFd) -> WasiResult<R>) -> WasiResult<R> {
+    let index = usize::try_from(fd - FIRST_FD).map_err(|_| ERRNO_BADF)?;
+    match FDS.lock().get_mut(index) {
+        Some(Some(open)) => f(open),
+        _ => Err(ERRNO_BADF),
+    }
+}
+
+fn memory<T>(ctx: &mut Caller<'_, T>) -> WasiResult<Memory> {
+    ctx.get_export("memory")
+        .and_then(Extern::into_memory)
+        .ok_or(ERRNO_INVAL)
+}
+
+fn read_path<T>(ctx: &mut Caller<'_, T>, memory: Memory, ptr: i32, len: i32) -> WasiResult<String> {
+    let mut bytes = vec![0u8; len as u32 as usize];
+    memory
+        .read(&*ctx, ptr as u32 as usize, &mut bytes)
+        .map_err(|_| ERRNO_FAULT)?;
+    String::from_utf8(bytes).map_err(|_| ERRNO_INVAL)
+}
+
+/// The normalized path in the guest of `path` in the directory `dirfd`
+fn resolve(dirfd: i32, path: &str) -> WasiResult<String> {
+    let dir = with_fd(dirfd, |dir| match dir.filetype {
+        FILETYPE_DIRECTORY => Ok(dir.path.clone()),
+        _ => Err(ERRNO_NOTDIR),
+    })?;
+    // WASI paths are relative to their directory
+    if path.starts_with('/') {
+        return Err(ERRNO_NOTCAPABLE);
+    }
+    wasi_fs::join_path(&dir, path).ok_or(ERRNO_NOTCAPABLE)
+}
+
+/// The file or directory at `path`, with its index in the in-memory
+/// filesystem if it is one of its files
+fn stat(path: &str) -> WasiResult<(Stat, Option<usize>)> {
+    if let Some(found) = vfs::stat(path) {
+        return Ok(found);
+    }
+    if !HOST_DIRS.load(Ordering::Relaxed) {
+        return Err(ERRNO_NOENT);
+    }
+    call_tracker::record_host_call();
+    let bytes = call_host_function::<Vec<u8>>(
+        HOST_FS_STAT_FUNCTION,
+        Some(vec![ParameterValue::String(String::from(path))]),
+        ReturnType::VecBytes,
+    )
+    .map_err(|_| ERRNO_IO)?;
+    let stat = wasi_fs::decode_stat(&bytes).map_err(i32::from)?;
+    Ok((stat, None))
+}
+
+fn write_filestat<T>(
+    ctx: &mut Caller<'_, T>,
+    memory: Memory,
+    ptr: i32,
+    stat: Stat,
+) -> WasiResult<()> {
+    // filestat is size 64: filetype at offset 16, the link count at 24
+    // and size at offset 32, with the device, inode and times left 0
+    let mut filestat = [0u8; 64];
+    filestat[16] = stat.filetype;
+    filestat[24..32].copy_from_slice(&1u64.to_le_bytes());
+    filestat[32..40].copy_from_slice(&stat.size.to_le_bytes());
+    memory
+        .write(&mut *ctx, ptr as u32 as usize, &filestat)
+        .map_err(|_| ERRNO_FAULT)
+}
+
+fn errno(result: WasiResult<()>) -> i32 {
+    result.err().unwrap_or(0)
+}
+
+fn fd_prestat_get<T>(ctx: &mut Caller<'_, T>, fd: i32, retptr: i32) -> WasiResult<()> {
+    let name_len = with_fd(fd, |open| {
+        if !open.preopen {
+            return Err(ERRNO_BADF);
+        }
+        Ok(prestat_name(&open.path).len() as u32)
+    })?;
+    // prestat is size 8: the tag at offset 0, 0 for a directory, and the
+    // length of its name at offset 4
+    let mut prestat = [0u8; 8];
+    prestat[4..].copy_from_slice(&name_len.to_le_bytes());
+    memory(ctx)?
+        .write(&mut *ctx, retptr as u32 as usize, &prestat)
+        .map_err(|_| ERRNO_FAULT)
+}
+
+fn prestat_name(path: &str) -> &str {
+    match path {
+        "" => ".",
+        path => path,
+    }
+}
+
+fn fd_prestat_dir_name<T>(ctx: &mut Caller<'_, T>, fd: i32, ptr: i32, len: i32) -> WasiResult<()> {
+    let name = with_fd(fd, |open| {
+        if !open.preopen {
+            return Err(ERRNO_BADF);
+        }
+        Ok(String::from(prestat_name(&open.path)))
+    })?;
+    let len = name.len().min(len as u32 as usize);
+    memory(ctx)?
+        .write(&mut *ctx, ptr as u32 as usize, &name.as_bytes()[..len])
+        .map_err(|_| ERRNO_FAULT)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn path_open<T>(
+    ctx: &mut Caller<'_, T>,
+    dirfd: i32,
+    path_ptr: i32,
+    path_len: i32,
+    oflags: i32,
+    rights_base: i64,
+    fdflags: i32,
+    retptr: i32,
+) -> WasiResult<()> {
+    if oflags & (OFLAGS_CREAT | OFLAGS_EXCL | OFLAGS_TRUNC) != 0
+        || rights_base & RIGHTS_FD_WRITE != 0
+        || fdflags & FDFLAGS_APPEND != 0
+    {
+        return Err(ERRNO_ROFS);
+    }
+    let memory = memory(ctx)?;
+    let path = read_path(ctx, memory, path_ptr, path_len)?;
+    let path = resolve(dirfd, &path)?;
+    let (stat, vfs_file) = stat(&path)?;
+    if oflags & OFLAGS_DIRECTORY != 0 && stat.filetype != FILETYPE_DIRECTORY {
+        return Err(ERRNO_NOTDIR);
+    }
+    let open = OpenFd {
+        path,
+        filetype: stat.filetype,
+        offset: 0,
+        preopen: false,
+        vfs_file,
+    };
+    let fd = {
+        let mut fds = FDS.lock();
+        match fds.iter().position(Option::is_none) {
+            Some(free) => {
+                fds[free] = Some(open);
+                free
+            }
+            None => {
+                fds.push(Some(open));
+                fds.len() - 1
+            }
+        }
+    };
+    let fd = fd as u32 + FIRST_FD as u32;
+    memory
+        .write(&mut *ctx, retptr as u32 as usize, &fd.to_le_bytes())
+        .map_err(|_| ERRNO_FAULT)
+}
+
+fn fd_read<T>(
+    ctx: &mut Caller<'_, T>,
+    fd: i32,
+    iovs: i32,
+    iovs_len: i32,
+    retptr: i32,
+) -> WasiResult<()> {
+    let memory = memory(ctx)?;
+    let mut bufs = Vec::with_capacity(iovs_len as u32 as usize);
+    for i in 0..iovs_len as u32 as usize {
+        // iovec is size 8: the buffer at offset 0 and its length at 4
+        let mut iov = [0u8; 8];
+        memory
+            .read(&*ctx, iovs as u32 as usize + 8 * i, &mut iov)
+            .map_err(|_| ERRNO_FAULT)?;
+        let buf = u32::from_le_bytes([iov[0], iov[1], iov[2], iov[3]]) as usize;
+        let buf_len = u32::from_le_bytes([iov[4], iov[5], iov[6], iov[7]]) as usize;
+        bufs.push((buf, buf_len));
+    }
+    let mut len = bufs.iter().map(|(_, len)| len).sum::<usize>();
+    let (path, offset, vfs_file) = with_fd(fd, |open| match open.filetype {
+        FILETYPE_DIRECTORY => Err(ERRNO_ISDIR),
+        _ => Ok((open.path.clone(), open.offset, open.vfs_file)),
+    })?;
+    let bytes = match vfs_file {
+        Some(index) => vfs::read(index, offset, len),
+        None => {
+            len = len.min(MAX_READ);
+            call_tracker::record_host_call();
+            call_host_function::<Vec<u8>>(
+                HOST_FS_READ_FUNCTION,
+                Some(vec![
+                    ParameterValue::String(path),
+                    ParameterValue::ULong(offset),
+                    ParameterValue::Int(len as i32),
+                ]),
+                ReturnType::VecBytes,
+            )
+            .map_err(|_| ERRNO_IO)?
+        }
+    };
+    let mut remaining = &bytes[..bytes.len().min(len)];
+    for (buf, buf_len) in bufs {
+        if remaining.is_empty() {
+            break;
+        }
+        let (chunk, rest) = remaining.split_at(buf_len.min(remaining.len()));
+        memory
+            .write(&mut *ctx, buf, chunk)
+            .map_err(|_| ERRNO_FAULT)?;
+        remaining = rest;
+    }
+    let read = bytes.len().min(len) as u32;
+    with_fd(fd, |open| {
+        open.offset += read as u64;
+        Ok(())
+    })?;
+    memory
+        .write(&mut *ctx, retptr as u32 as usize, &read.to_le_bytes())
+        .map_err(|_| ERRNO_FAULT)
+}
+
+fn fd_seek<T>(
+    ctx: &mut Caller<'_, T>,
+    fd: i32,
+    offset: i64,
+    whence: i32,
+    retptr: i32,
+) -> WasiResult<()> {
+    let (path, current) = with_fd(fd, |open| match open.filetype {
+        FILETYPE_REGULAR_FILE => Ok((open.path.clone(), open.offset)),
+        _ => Err(ERRNO_INVAL),
+    })?;
+    // whence is 0 for the start, 1 for the current offset and 2 for the
+    // end of the file
+    let base = match whence {
+        0 => 0,
+        1 => current,
+        2 => stat(&path)?.0.size,
+        _ => return Err(ERRNO_INVAL),
+    };
+    let new_offset = base.checked_add_signed(offset).ok_or(ERRNO_INVAL)?;
+    with_fd(fd, |open| {
+        open.offset = new_offset;
+        Ok(())
+    })?;
+    memory(ctx)?
+        .write(&mut *ctx, retptr as u32 as usize, &new_offset.to_le_bytes())
+        .map_err(|_| ERRNO_FAULT)
+}
+
+fn fd_tell<T>(ctx: &mut Caller<'_, T>, fd: i32, retptr: i32) -> WasiResult<()> {
+    let offset = with_fd(fd, |open| Ok(open.offset))?;
+    memory(ctx)?
+        .write(&mut *ctx, retptr as u32 as usize, &offset.to_le_bytes())
+        .map_err(|_| ERRNO_FAULT)
+}
+
+fn fd_filestat_get<T>(ctx: &mut Caller<'_, T>, fd: i32, retptr: i32) -> WasiResult<()> {
+    let path = with_fd(fd, |open| Ok(open.path.clone()))?;
+    let (stat, _) = stat(&path)?;
+    let memory = memory(ctx)?;
+    write_filestat(ctx, memory, retptr, stat)
+}
+
+fn path_filestat_get<T>(
+    ctx: &mut Caller<'_, T>,
+    dirfd: i32,
+    path_ptr: i32,
+    path_len: i32,
+    retptr: i32,
+) -> WasiResult<()> {
+    let memory = memory(ctx)?;
+    let path = read_path(ctx, memory, path_ptr, path_len)?;
+    let (stat, _) = stat(&resolve(dirfd, &path)?)?;
+    write_filestat(ctx, memory, retptr, stat)
+}
+
+fn fd_close(fd: i32) -> WasiResult<()> {
+    let index = usize::try_from(fd - FIRST_FD).map_err(|_| ERRNO_BADF)?;
+    FDS.lock()
+        .get_mut(index)
+        .and_then(Option::take)
+        .map(|_| ())
+        .ok_or(ERRNO_BADF)
+}
+
+pub(crate) fn register_handlers<T: 'static>(linker: &mut Linker<T>) -> Result<()> {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+    linker
+        .func_wrap(
+            WASI_MODULE,
+            "fd_prestat_get",
+            |mut ctx: Caller<'_, T>, fd: i32, retptr: i32| {
+                errno(fd_prestat_get(&mut ctx, fd, retptr))
+            },
+        )
+        .map_err(map_wasmtime_error)?;
+    linker
+        .func_wrap(
+            WASI_MODULE,
+            "fd_prestat_dir_name",
+            |mut ctx: Caller<'_, T>, fd: i32, ptr: i32, len: i32| {
+                errno(fd_prestat_dir_name(&mut ctx, fd, ptr, len))
+            },
+        )
+        .map_err(map_wasmtime_error)?;
+    linker
+        .func_wrap(
+            WASI_MODULE,
+            "path_open",
+            |mut ctx: Caller<'_, T>,
+             dirfd: i32,
+             _dirflags: i32,
+             path_ptr: i32,
+             path_len: i32,
+             oflags: i32,
+             rights_base: i64,
+             _rights_inheriting: i64,
+             fdflags: i32,
+             retptr: i32| {
+                errno(path_open(
+                    &mut ctx,
+                    dirfd,
+                    path_ptr,
+                    path_len,
+                    oflags,
+                    rights_base,
+                    fdflags,
+                    retptr,
+                ))
+            },
+        )
+        .map_err(map_wasmtime_error)?;
+    linker
+        .func_wrap(
+            WASI_MODULE,
+            "fd_read",
+            |mut ctx: Caller<'_, T>, fd: i32, iovs: i32, iovs_len: i32, retptr: i32| {
+                errno(fd_read(&mut ctx, fd, iovs, iovs_len, retptr))
+            },
+        )
+        .map_err(map_wasmtime_error)?;
+    linker
+        .func_wrap(
+            WASI_MODULE,
+            "fd_seek",
+            |mut ctx: Caller<'_, T>, fd: i32, offset: i64, whence: i32, retptr: i32| {
+                errno(fd_seek(&mut ctx, fd, offset, whence, retptr))
+            },
+        )
+        .map_err(map_wasmtime_error)?;
+    linker
+        .func_wrap(
+            WASI_MODULE,
+            "fd_tell",
+            |mut ctx: Caller<'_, T>, fd: i32, retptr: i32| errno(fd_tell(&mut ctx, fd, retptr)),
+        )
+        .map_err(map_wasmtime_error)?;
+    linker
+        .func_wrap(
+            WASI_MODULE,
+            "fd_filestat_get",
+            |mut ctx: Caller<'_, T>, fd: i32, retptr: i32| {
+                errno(fd_filestat_get(&mut ctx, fd, retptr))
+            },
+        )
+        .map_err(map_wasmtime_error)?;
+    linker
+        .func_wrap(
+            WASI_MODULE,
+            "path_filestat_get",
+            |mut ctx: Caller<'_, T>,
+             dirfd: i32,
+             _flags: i32,
+             path_ptr: i32,
+             path_len: i32,
+             retptr: i32| {
+                errno(path_filestat_get(
+                    &mut ctx, dirfd, path_ptr, path_len, retptr,
+                ))
+            },
+        )
+        .map_err(map_wasmtime_error)?;
+    linker
+        .func_wrap(WASI_MODULE, "fd_close", |fd: i32| errno(fd_close(fd)))
+        .map_err(map_wasmtime_error)?;
+    Ok(())
+}
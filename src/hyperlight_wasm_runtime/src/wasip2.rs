@@ -0,0 +1,363 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! A minimal implementation of the WASI preview 2 interfaces that
+//! components commonly import, backed by host functions: clocks are read
+//! with the host clock function, random numbers follow the sandbox's
+//! entropy policy, and stdout and stderr are written with `HostPrint`, or passed to the
+//! host if it captures them.
+//!
+//! It is enabled with [`RuntimeConfig::wasi_p2`]. Each supported
+//! interface a component imports is then linked to this implementation
+//! when the component is loaded, at the version the component imports,
+//! replacing the definition generated from the component's world. Other
+//! WASI interfaces are left to the world.
+
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use hyperlight_common::flatbuffer_wrappers::function_types::{ParameterValue, ReturnType};
+use hyperlight_guest::error::Result;
+use hyperlight_guest_bin::host_comm::call_host_function;
+use spin::Mutex;
+use wasmtime::component::{
+    Component, ComponentType, Linker, LinkerInstance, Lower, Resource, ResourceType,
+};
+
+use crate::runtime_config::{RuntimeConfig, HOST_CLOCK_FUNCTION};
+use crate::{
+    call_tracker, cli_environment, guest_abort, map_wasmtime_error, output_capture, random,
+};
+
+// Set by init_wasm_runtime from the runtime config
+static ENABLED: AtomicBool = AtomicBool::new(false);
+// The message of the last failed write, returned by the error resource
+static LAST_ERROR: Mutex<String> = Mutex::new(String::new());
+
+// The number of bytes a component may write to an output stream at once
+const WRITE_PERMIT: u64 = 4096;
+
+// WASI clock ids understood by the host clock function
+const CLOCK_REALTIME: i32 = 0;
+const CLOCK_MONOTONIC: i32 = 1;
+
+// Representations of the host resources. Streams are stateless, so
+// every handle to a stream shares the same representation.
+const STDIN: u32 = 0;
+const STDOUT: u32 = 1;
+const STDERR: u32 = 2;
+
+/// `wasi:io/error.error`
+struct IoError;
+/// `wasi:io/poll.pollable`
+struct Pollable;
+/// `wasi:io/streams.input-stream`
+struct InputStream;
+/// `wasi:io/streams.output-stream`
+struct OutputStream;
+
+/// `wasi:io/streams.stream-error`
+#[derive(ComponentType, Lower)]
+#[component(variant)]
+enum StreamError {
+    #[component(name = "last-operation-failed")]
+    LastOperationFailed(Resource<IoError>),
+    #[component(name = "closed")]
+    Closed,
+}
+
+/// `wasi:clocks/wall-clock.datetime`
+#[derive(ComponentType, Lower)]
+#[component(record)]
+struct Datetime {
+    seconds: u64,
+    nanoseconds: u32,
+}
+
+pub(crate) fn configure(runtime_config: &RuntimeConfig) {
+    ENABLED.store(runtime_config.wasi_p2, Ordering::Relaxed);
+}
+
+/// Link each supported WASI interface imported by `component`, if WASI
+/// preview 2 support is enabled
+pub(crate) fn link_imports<T: 'static>(
+    linker: &mut Linker<T>,
+    component: &Component,
+) -> Result<()> {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+    let engine = linker.engine().clone();
+    let imports: Vec<String> = component
+        .component_type()
+        .imports(&engine)
+        .map(|(name, _)| name.to_string())
+        .collect();
+    linker.allow_shadowing(true);
+    let result = imports.iter().try_for_each(|name| {
+        let interface = name.split_once('@').map_or(name.as_str(), |(i, _)| i);
+        let define = match interface {
+            "wasi:cli/environment" => define_environment::<T>,
+            "wasi:cli/exit" => define_exit::<T>,
+            "wasi:cli/stdin" => define_stdin::<T>,
+            "wasi:cli/stdout" => define_stdout::<T>,
+            "wasi:cli/stderr" => define_stderr::<T>,
+            "wasi:clocks/monotonic-clock" => define_monotonic_clock::<T>,
+            "wasi:clocks/wall-clock" => define_wall_clock::<T>,
+            "wasi:io/error" => define_error::<T>,
+            "wasi:io/poll" => define_poll::<T>,
+            "wasi:io/streams" => define_streams::<T>,
+            "wasi:random/insecure" => define_insecure_random::<T>,
+            "wasi:random/insecure-seed" => define_insecure_seed::<T>,
+            "wasi:random/random" => define_random::<T>,
+            _ => return Ok(()),
+        };
+        define(&mut linker.instance(name)?)
+    });
+    linker.allow_shadowing(false);
+    result.map_err(map_wasmtime_error)
+}
+
+/// Read a WASI clock on the host, which applies the sandbox's virtual
+/// time offset and scale
+fn clock_nanos(clock_id: i32) -> wasmtime::Result<u64> {
+    call_tracker::record_host_call();
+    match call_host_function::<i64>(
+        HOST_CLOCK_FUNCTION,
+        Some(vec![ParameterValue::Int(clock_id)]),
+        ReturnType::Long,
+    ) {
+        Ok(time) if time >= 0 => Ok(time as u64),
+        _ => Err(wasmtime::Error::msg("failed to read the host clock")),
+    }
+}
+
+fn random_bytes(len: u64) -> wasmtime::Result<Vec<u8>> {
+    usize::try_from(len)
+        .ok()
+        .and_then(random::random_bytes)
+        .ok_or_else(|| wasmtime::Error::msg("random numbers are not available to the guest"))
+}
+
+fn random_u64() -> wasmtime::Result<u64> {
+    let bytes = random_bytes(8)?;
+    let mut value = [0u8; 8];
+    value.copy_from_slice(&bytes);
+    Ok(u64::from_le_bytes(value))
+}
+
+/// Write `contents` to the stream with representation `stream`. Streams
+/// the host does not capture are printed, as stdout is.
+fn print(stream: u32, contents: &[u8]) -> core::result::Result<(), StreamError> {
+    if contents.is_empty() {
+        return Ok(());
+    }
+    if stream as i32 == output_capture::STDERR {
+        guest_abort::record_stderr(contents);
+    }
+    let fd = match stream as i32 {
+        fd if output_capture::is_captured(fd) => fd,
+        _ => output_capture::STDOUT,
+    };
+    let result = output_capture::write(fd, contents).expect("stdout is always writable");
+    result.map(|_| ()).map_err(|e| {
+        *LAST_ERROR.lock() = alloc::format!("failed to print: {}", e.message);
+        StreamError::LastOperationFailed(Resource::new_own(0))
+    })
+}
+
+fn define_environment<T: 'static>(instance: &mut LinkerInstance<'_, T>) -> wasmtime::Result<()> {
+    instance.func_wrap("get-environment", |_, ()| {
+        let vars = cli_environment::strings(&cli_environment::environment())
+            .into_iter()
+            .map(|var| match var.split_once('=') {
+                Some((key, value)) => (String::from(key), String::from(value)),
+                None => (var, String::new()),
+            })
+            .collect::<Vec<_>>();
+        Ok((vars,))
+    })?;
+    instance.func_wrap("get-arguments", |_, ()| {
+        Ok((cli_environment::strings(&cli_environment::args()),))
+    })?;
+    instance.func_wrap("initial-cwd", |_, ()| Ok((None::<String>,)))?;
+    Ok(())
+}
+
+fn define_exit<T: 'static>(instance: &mut LinkerInstance<'_, T>) -> wasmtime::Result<()> {
+    instance.func_wrap("exit", |_, (status,): (core::result::Result<(), ()>,)| {
+        Err::<(), _>(guest_abort::exit(match status {
+            Ok(()) => 0,
+            Err(()) => 1,
+        }))
+    })
+}
+
+fn define_stdin<T: 'static>(instance: &mut LinkerInstance<'_, T>) -> wasmtime::Result<()> {
+    instance.func_wrap("get-stdin", |_, ()| {
+        Ok((Resource::<InputStream>::new_own(STDIN),))
+    })
+}
+
+fn define_stdout<T: 'static>(instance: &mut LinkerInstance<'_, T>) -> wasmtime::Result<()> {
+    instance.func_wrap("get-stdout", |_, ()| {
+        Ok((Resource::<OutputStream>::new_own(STDOUT),))
+    })
+}
+
+fn define_stderr<T: 'static>(instance: &mut LinkerInstance<'_, T>) -> wasmtime::Result<()> {
+    instance.func_wrap("get-stderr", |_, ()| {
+        Ok((Resource::<OutputStream>::new_own(STDERR),))
+    })
+}
+
+fn define_monotonic_clock<T: 'static>(
+    instance: &mut LinkerInstance<'_, T>,
+) -> wasmtime::Result<()> {
+    instance.func_wrap("now", |_, ()| Ok((clock_nanos(CLOCK_MONOTONIC)?,)))?;
+    // All clocks are read on the host in nanoseconds
+    instance.func_wrap("resolution", |_, ()| Ok((1u64,)))?;
+    Ok(())
+}
+
+fn define_wall_clock<T: 'static>(instance: &mut LinkerInstance<'_, T>) -> wasmtime::Result<()> {
+    instance.func_wrap("now", |_, ()| {
+        let nanos = clock_nanos(CLOCK_REALTIME)?;
+        Ok((Datetime {
+            seconds: nanos / 1_000_000_000,
+            nanoseconds: (nanos % 1_000_000_000) as u32,
+        },))
+    })?;
+    instance.func_wrap("resolution", |_, ()| {
+        Ok((Datetime {
+            seconds: 0,
+            nanoseconds: 1,
+        },))
+    })?;
+    Ok(())
+}
+
+fn define_error<T: 'static>(instance: &mut LinkerInstance<'_, T>) -> wasmtime::Result<()> {
+    instance.resource("error", ResourceType::host::<IoError>(), |_, _| Ok(()))?;
+    instance.func_wrap(
+        "[method]error.to-debug-string",
+        |_, (_,): (Resource<IoError>,)| Ok((LAST_ERROR.lock().clone(),)),
+    )?;
+    Ok(())
+}
+
+// Output streams write synchronously and input streams are always at
+// their end, so every pollable is ready
+fn define_poll<T: 'static>(instance: &mut LinkerInstance<'_, T>) -> wasmtime::Result<()> {
+    instance.resource("pollable", ResourceType::host::<Pollable>(), |_, _| Ok(()))?;
+    instance.func_wrap(
+        "[method]pollable.ready",
+        |_, (_,): (Resource<Pollable>,)| Ok((true,)),
+    )?;
+    instance.func_wrap(
+        "[method]pollable.block",
+        |_, (_,): (Resource<Pollable>,)| Ok(()),
+    )?;
+    instance.func_wrap("poll", |_, (pollables,): (Vec<Resource<Pollable>>,)| {
+        Ok(((0..pollables.len() as u32).collect::<Vec<u32>>(),))
+    })?;
+    Ok(())
+}
+
+fn define_streams<T: 'static>(instance: &mut LinkerInstance<'_, T>) -> wasmtime::Result<()> {
+    instance.resource(
+        "input-stream",
+        ResourceType::host::<InputStream>(),
+        |_, _| Ok(()),
+    )?;
+    instance.resource(
+        "output-stream",
+        ResourceType::host::<OutputStream>(),
+        |_, _| Ok(()),
+    )?;
+
+    // stdin is always at its end
+    for name in ["read", "blocking-read", "skip", "blocking-skip"] {
+        instance.func_wrap(
+            &alloc::format!("[method]input-stream.{}", name),
+            |_, (_, _): (Resource<InputStream>, u64)| Ok((Err::<Vec<u8>, _>(StreamError::Closed),)),
+        )?;
+    }
+    instance.func_wrap(
+        "[method]input-stream.subscribe",
+        |_, (_,): (Resource<InputStream>,)| Ok((Resource::<Pollable>::new_own(0),)),
+    )?;
+
+    instance.func_wrap(
+        "[method]output-stream.check-write",
+        |_, (_,): (Resource<OutputStream>,)| Ok((Ok::<_, StreamError>(WRITE_PERMIT),)),
+    )?;
+    for name in ["write", "blocking-write-and-flush"] {
+        instance.func_wrap(
+            &alloc::format!("[method]output-stream.{}", name),
+            |_, (stream, contents): (Resource<OutputStream>, Vec<u8>)| {
+                Ok((print(stream.rep(), &contents),))
+            },
+        )?;
+    }
+    for name in ["write-zeroes", "blocking-write-zeroes-and-flush"] {
+        instance.func_wrap(
+            &alloc::format!("[method]output-stream.{}", name),
+            |_, (stream, len): (Resource<OutputStream>, u64)| {
+                let len = len.min(WRITE_PERMIT) as usize;
+                Ok((print(stream.rep(), &vec![0u8; len]),))
+            },
+        )?;
+    }
+    // Writes are not buffered, so there is nothing to flush
+    for name in ["flush", "blocking-flush"] {
+        instance.func_wrap(
+            &alloc::format!("[method]output-stream.{}", name),
+            |_, (_,): (Resource<OutputStream>,)| Ok((Ok::<(), StreamError>(()),)),
+        )?;
+    }
+    instance.func_wrap(
+        "[method]output-stream.subscribe",
+        |_, (_,): (Resource<OutputStream>,)| Ok((Resource::<Pollable>::new_own(0),)),
+    )?;
+    Ok(())
+}
+
+fn define_random<T: 'static>(instance: &mut LinkerInstance<'_, T>) -> wasmtime::Result<()> {
+    instance.func_wrap("get-random-bytes", |_, (len,): (u64,)| {
+        Ok((random_bytes(len)?,))
+    })?;
+    instance.func_wrap("get-random-u64", |_, ()| Ok((random_u64()?,)))?;
+    Ok(())
+}
+
+fn define_insecure_random<T: 'static>(
+    instance: &mut LinkerInstance<'_, T>,
+) -> wasmtime::Result<()> {
+    instance.func_wrap("get-insecure-random-bytes", |_, (len,): (u64,)| {
+        Ok((random_bytes(len)?,))
+    })?;
+    instance.func_wrap("get-insecure-random-u64", |_, ()| Ok((random_u64()?,)))?;
+    Ok(())
+}
+
+fn define_insecure_seed<T: 'static>(instance: &mut LinkerInstance<'_, T>) -> wasmtime::Result<()> {
+    instance.func_wrap("insecure-seed", |_, ()| {
+        Ok(((random_u64()?, random_u64()?),))
+    })
+}
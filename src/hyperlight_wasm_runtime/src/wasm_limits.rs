@@ -0,0 +1,114 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+//! Limits on the size of the linear memories and tables of wasm
+//! instances, enforced by a wasmtime `ResourceLimiter` so that
+//! `memory.grow` and `table.grow` fail at the limits set by the host
+//! rather than when the guest's heap runs out.
+
+use alloc::boxed::Box;
+use alloc::string::ToString;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use hyperlight_common::flatbuffer_wrappers::guest_error::ErrorCode;
+use hyperlight_guest::error::HyperlightGuestError;
+use wasmtime::{ResourceLimiter, Store};
+
+use crate::runtime_config::{RuntimeConfig, WASM_MEMORY_LIMIT_EXCEEDED, WASM_TABLE_LIMIT_EXCEEDED};
+
+// Set by init_wasm_runtime from the runtime config
+static MAX_MEMORY: AtomicU64 = AtomicU64::new(u64::MAX);
+static MAX_TABLE_ELEMENTS: AtomicU64 = AtomicU64::new(u64::MAX);
+// Whether a linear memory or table was refused growth during the
+// current guest call
+static MEMORY_EXCEEDED: AtomicBool = AtomicBool::new(false);
+static TABLE_EXCEEDED: AtomicBool = AtomicBool::new(false);
+
+pub(crate) fn configure(runtime_config: &RuntimeConfig) {
+    MAX_MEMORY.store(
+        runtime_config.max_wasm_memory.unwrap_or(u64::MAX),
+        Ordering::Relaxed,
+    );
+    MAX_TABLE_ELEMENTS.store(
+        runtime_config.max_table_elements.unwrap_or(u64::MAX),
+        Ordering::Relaxed,
+    );
+}
+
+struct Limiter;
+
+impl ResourceLimiter for Limiter {
+    fn memory_growing(
+        &mut self,
+        _current: usize,
+        desired: usize,
+        _maximum: Option<usize>,
+    ) -> wasmtime::Result<bool> {
+        let allowed = desired as u64 <= MAX_MEMORY.load(Ordering::Relaxed);
+        if !allowed {
+            MEMORY_EXCEEDED.store(true, Ordering::Relaxed);
+        }
+        Ok(allowed)
+    }
+
+    fn table_growing(
+        &mut self,
+        _current: usize,
+        desired: usize,
+        _maximum: Option<usize>,
+    ) -> wasmtime::Result<bool> {
+        let allowed = desired as u64 <= MAX_TABLE_ELEMENTS.load(Ordering::Relaxed);
+        if !allowed {
+            TABLE_EXCEEDED.store(true, Ordering::Relaxed);
+        }
+        Ok(allowed)
+    }
+}
+
+/// Install the limiter on a newly created store, if the host set any
+/// limits
+pub(crate) fn configure_store<T: 'static>(store: &mut Store<T>) {
+    if MAX_MEMORY.load(Ordering::Relaxed) == u64::MAX
+        && MAX_TABLE_ELEMENTS.load(Ordering::Relaxed) == u64::MAX
+    {
+        return;
+    }
+    // The limiter keeps its state in statics, and boxing a zero-sized
+    // type does not allocate, so leaking it is free
+    store.limiter(|_| Box::leak(Box::new(Limiter)));
+}
+
+/// Forget the growth refused during the previous guest call
+pub(crate) fn begin_call() {
+    MEMORY_EXCEEDED.store(false, Ordering::Relaxed);
+    TABLE_EXCEEDED.store(false, Ordering::Relaxed);
+}
+
+/// The error to return for a guest call that failed after a linear
+/// memory or table was refused growth, or `None` if none was
+pub(crate) fn call_error() -> Option<HyperlightGuestError> {
+    let message = if MEMORY_EXCEEDED.load(Ordering::Relaxed) {
+        WASM_MEMORY_LIMIT_EXCEEDED
+    } else if TABLE_EXCEEDED.load(Ordering::Relaxed) {
+        WASM_TABLE_LIMIT_EXCEEDED
+    } else {
+        return None;
+    };
+    Some(HyperlightGuestError::new(
+        ErrorCode::GuestError,
+        message.to_string(),
+    ))
+}
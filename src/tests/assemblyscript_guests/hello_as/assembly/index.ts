@@ -0,0 +1,37 @@
+/*
+Copyright 2025 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+// Load this module with GuestAbi::AssemblyScript
+
+@external("env", "HostLog")
+declare function HostLog(msg: string): i32;
+
+export function Greet(name: string): string {
+  return "Hello, " + name + "!";
+}
+
+export function LogMessage(msg: string): i32 {
+  return HostLog(msg);
+}
+
+export function ByteLength(buf: ArrayBuffer, len: i32): i32 {
+  return buf.byteLength == len ? len : -1;
+}
+
+// Called by LoadedWasmSandbox::hint_gc
+export function __hlwasm_gc(): void {
+  __collect();
+}
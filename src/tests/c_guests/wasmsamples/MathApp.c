@@ -0,0 +1,28 @@
+/*
+Copyright 2024 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+// Linked from MathLibrary.wasm, loaded under the name "math"
+__attribute__((import_module("math"), import_name("add")))
+int add(int a, int b);
+
+__attribute__((import_module("math"), import_name("multiply")))
+int multiply(int a, int b);
+
+__attribute__((export_name("MultiplyAdd")))
+int MultiplyAdd(int a, int b, int c)
+{
+    return add(multiply(a, b), c);
+}
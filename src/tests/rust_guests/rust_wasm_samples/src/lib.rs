@@ -14,59 +14,151 @@ See the License for the specific language governing permissions and
 limitations under the License.
 */
 
-mod hostfuncs {
+use core::sync::atomic::{AtomicI32, Ordering};
 
-    mod host {
-        extern "C" {
-            #[link_name = "TestHostFunc"]
-            pub(super) fn test_host_func(a: i32) -> i32;
+use hyperlight_wasm_guest_sdk::{
+    fill_random, hleprint, hlprint, host_functions, hyperlight_export,
+};
+use serde::{Deserialize, Serialize};
+
+hyperlight_wasm_guest_sdk::abi_version!(1);
+
+host_functions! {
+    #[link_name = "TestHostFunc"]
+    fn test_host_func(a: i32) -> i32;
+}
+
+#[hyperlight_export]
+fn hello_world() -> i32 {
+    hlprint!("Hello from Wasm in Hyperlight!\n");
+    0
+}
+
+#[hyperlight_export]
+fn hello_stderr() -> i32 {
+    hleprint!("Error from Wasm in Hyperlight!\n") as i32
+}
+
+#[hyperlight_export]
+fn add(left: u32, right: u32) -> u32 {
+    left + right
+}
+
+#[hyperlight_export]
+fn call_host_function(a: i32) -> i32 {
+    test_host_func(a)
+}
+
+static COUNTER: AtomicI32 = AtomicI32::new(0);
+
+#[hyperlight_export]
+fn spin(iterations: u64) -> u64 {
+    let mut count = 0;
+    for _ in 0..iterations {
+        count = core::hint::black_box(count + 1);
+    }
+    count
+}
+
+#[hyperlight_export]
+fn increment_counter() -> i32 {
+    COUNTER.fetch_add(1, Ordering::Relaxed) + 1
+}
+
+#[hyperlight_export]
+fn export_state() -> Vec<u8> {
+    COUNTER.load(Ordering::Relaxed).to_le_bytes().to_vec()
+}
+
+#[hyperlight_export]
+fn import_state(state: Vec<u8>, _len: i32) -> i32 {
+    match <[u8; 4]>::try_from(state.as_slice()) {
+        Ok(bytes) => {
+            COUNTER.store(i32::from_le_bytes(bytes), Ordering::Relaxed);
+            0
         }
+        Err(_) => -1,
     }
-    pub(super) fn test_host_func(a: i32) -> i32 {
-        unsafe { host::test_host_func(a) }
+}
+
+#[hyperlight_export]
+fn checksum(data: Vec<u8>, _len: i32) -> u64 {
+    data.iter().map(|b| *b as u64).sum()
+}
+
+#[hyperlight_export]
+fn random_u32() -> i64 {
+    let mut bytes = [0u8; 4];
+    if !fill_random(&mut bytes) {
+        return -1;
     }
+    u32::from_le_bytes(bytes) as i64
 }
 
-#[link(wasm_import_module = "wasi_snapshot_preview1")]
-extern "C" {
-    fn fd_write(fd: i32, iovs: i32, iovs_len: i32, retptr: i32) -> i32;
-}
-
-fn wasi_print(s: &str) -> i32 {
-    let buf = s.as_ptr();
-    let len = s.len();
-    let iov: [u32; 2] = [buf as u32, len as u32];
-    let mut written: u32 = 0;
-    unsafe {
-        fd_write(
-            1, // stdout
-            iov.as_ptr() as i32,
-            1, // one iovec
-            &mut written as *mut u32 as i32,
-        );
+#[hyperlight_export]
+fn heap_size() -> u64 {
+    hyperlight_wasm_guest_sdk::limits::heap_size()
+}
+
+#[hyperlight_export]
+fn increment_cell(name: String) -> u64 {
+    let value = hyperlight_wasm_guest_sdk::state::get_u64(&name) + 1;
+    hyperlight_wasm_guest_sdk::state::set_u64(&name, value);
+    value
+}
+
+#[hyperlight_export]
+fn count_with_progress(n: u32) -> u32 {
+    for i in 1..=n {
+        hyperlight_wasm_guest_sdk::report_progress(i, &i.to_le_bytes());
     }
-    written as i32
+    n
 }
 
-macro_rules! hlprint {
-    ($($arg:tt)*) => {{
-        let f = format!($($arg)*);
-        wasi_print(&f)
-    }}
+#[hyperlight_export]
+fn stream_numbers(n: u32) -> u32 {
+    for i in 1..=n {
+        hyperlight_wasm_guest_sdk::emit_chunk(&i.to_le_bytes());
+    }
+    n
 }
 
-#[no_mangle]
-pub extern "C" fn hello_world() -> i32 {
-    hlprint!("Hello from Wasm in Hyperlight!\n");
-    0
+#[link(wasm_import_module = "wasi_snapshot_preview1")]
+unsafe extern "C" {
+    // Not implemented by hyperlight-wasm, so linked to a stub
+    fn fd_sync(fd: u32) -> u16;
 }
 
-#[no_mangle]
-pub extern "C" fn add(left: usize, right: usize) -> usize {
-    left + right
+#[hyperlight_export]
+fn sync_stdout() -> i32 {
+    // Safety: fd_sync takes no pointers
+    unsafe { fd_sync(1) as i32 }
 }
 
-#[no_mangle]
-pub extern "C" fn call_host_function(a: i32) -> i32 {
-    hostfuncs::test_host_func(a)
+#[derive(Deserialize)]
+struct Order {
+    item: String,
+    quantity: u32,
+    unit_price: u64,
+}
+
+#[derive(Serialize)]
+struct Invoice {
+    customer: String,
+    lines: Vec<(String, u64)>,
+    total: u64,
+}
+
+#[hyperlight_export(json)]
+fn make_invoice(customer: String, orders: Vec<Order>) -> Invoice {
+    let lines: Vec<(String, u64)> = orders
+        .into_iter()
+        .map(|o| (o.item, o.quantity as u64 * o.unit_price))
+        .collect();
+    let total = lines.iter().map(|(_, cost)| cost).sum();
+    Invoice {
+        customer,
+        lines,
+        total,
+    }
 }
@@ -0,0 +1,3 @@
+module github.com/hyperlight-dev/hyperlight-wasm/src/tests/tinygo_guests/hello_tinygo
+
+go 1.22
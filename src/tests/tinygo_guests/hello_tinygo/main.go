@@ -0,0 +1,75 @@
+/*
+Copyright 2025 The Hyperlight Authors.
+
+Licensed under the Apache License, Version 2.0 (the "License");
+you may not use this file except in compliance with the License.
+You may obtain a copy of the License at
+
+    http://www.apache.org/licenses/LICENSE-2.0
+
+Unless required by applicable law or agreed to in writing, software
+distributed under the License is distributed on an "AS IS" BASIS,
+WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+See the License for the specific language governing permissions and
+limitations under the License.
+*/
+
+// Load this module with GuestAbi::TinyGo
+
+package main
+
+/*
+#include <stdlib.h>
+*/
+import "C"
+
+import (
+	"runtime"
+	"unsafe"
+)
+
+//go:wasmimport env HostLog
+func hostLog(ptr *byte, size int32) int32
+
+// takeString copies a string passed by the host and frees the
+// allocation, since the guest owns its parameters.
+func takeString(ptr *byte, size int32) string {
+	s := string(unsafe.Slice(ptr, size))
+	C.free(unsafe.Pointer(ptr))
+	return s
+}
+
+// returnString allocates s with malloc and packs the pointer (low 32
+// bits) and length (high 32 bits) into a single value. The host frees
+// the allocation on the next call.
+func returnString(s string) uint64 {
+	ptr := C.malloc(C.size_t(len(s)))
+	copy(unsafe.Slice((*byte)(ptr), len(s)), s)
+	return uint64(len(s))<<32 | uint64(uintptr(ptr))
+}
+
+//export Greet
+func Greet(ptr *byte, size int32) uint64 {
+	return returnString("Hello, " + takeString(ptr, size) + "!")
+}
+
+//export LogMessage
+func LogMessage(ptr *byte, size int32) int32 {
+	msg := takeString(ptr, size)
+	return hostLog(unsafe.StringData(msg), int32(len(msg)))
+}
+
+//export ByteLength
+func ByteLength(ptr *byte, size int32) int32 {
+	C.free(unsafe.Pointer(ptr))
+	return size
+}
+
+// Called by LoadedWasmSandbox::hint_gc
+//
+//export __hlwasm_gc
+func hlwasmGC() {
+	runtime.GC()
+}
+
+func main() {}
@@ -0,0 +1,99 @@
+;; The functions of the hello_as sample guest, written by hand to the
+;; AssemblyScript guest ABI so that it can be tested without npm. Load
+;; this module with GuestAbi::AssemblyScript.
+(module
+  (import "env" "HostLog" (func $host_log (param i32) (result i32)))
+
+  (memory (export "memory") 1)
+  ;; "Hello, " in UTF-16
+  (data (i32.const 0) "H\00e\00l\00l\00o\00,\00 \00")
+
+  ;; A bump allocator for managed objects, which are never collected.
+  ;; Objects start after a 20 byte header that ends with their class id
+  ;; and their size in bytes.
+  (global $heap (mut i32) (i32.const 1024))
+
+  (func $new (export "__new") (param $size i32) (param $id i32) (result i32)
+    (local $ptr i32)
+    global.get $heap
+    i32.const 20
+    i32.add
+    local.set $ptr
+    local.get $ptr
+    i32.const 8
+    i32.sub
+    local.get $id
+    i32.store
+    local.get $ptr
+    i32.const 4
+    i32.sub
+    local.get $size
+    i32.store
+    local.get $ptr
+    local.get $size
+    i32.add
+    i32.const 15
+    i32.add
+    i32.const -16
+    i32.and
+    global.set $heap
+    local.get $ptr)
+
+  (func (export "__pin") (param $ptr i32) (result i32)
+    local.get $ptr)
+
+  (func (export "__unpin") (param i32))
+
+  (func (export "__collect"))
+
+  (func $size (param $ptr i32) (result i32)
+    local.get $ptr
+    i32.const 4
+    i32.sub
+    i32.load)
+
+  (func (export "Greet") (param $name i32) (result i32)
+    (local $len i32)
+    (local $out i32)
+    local.get $name
+    call $size
+    local.set $len
+    local.get $len
+    i32.const 16
+    i32.add
+    i32.const 2
+    call $new
+    local.set $out
+    local.get $out
+    i32.const 0
+    i32.const 14
+    memory.copy
+    local.get $out
+    i32.const 14
+    i32.add
+    local.get $name
+    local.get $len
+    memory.copy
+    local.get $out
+    i32.const 14
+    i32.add
+    local.get $len
+    i32.add
+    i32.const 33
+    i32.store16
+    local.get $out)
+
+  (func (export "LogMessage") (param $msg i32) (result i32)
+    local.get $msg
+    call $host_log)
+
+  (func (export "ByteLength") (param $buf i32) (param $len i32) (result i32)
+    local.get $buf
+    call $size
+    local.get $len
+    i32.eq
+    if (result i32)
+      local.get $len
+    else
+      i32.const -1
+    end))
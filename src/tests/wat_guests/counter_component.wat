@@ -0,0 +1,14 @@
+;; A component that counts the calls of its export, written by hand
+;; since the sample components keep no state between calls
+(component
+  (core module $counter
+    (global $count (mut i32) (i32.const 0))
+    (func (export "increment") (result i32)
+      global.get $count
+      i32.const 1
+      i32.add
+      global.set $count
+      global.get $count))
+  (core instance $counter (instantiate $counter))
+  (func (export "increment") (result u32)
+    (canon lift (core func $counter "increment"))))
@@ -0,0 +1,17 @@
+;; Functions returning more than one value, which compilers do not
+;; produce without experimental ABI flags, so written by hand
+(module
+  (memory (export "memory") 1)
+
+  (func (export "divmod") (param i32 i32) (result i32 i32)
+    local.get 0
+    local.get 1
+    i32.div_s
+    local.get 0
+    local.get 1
+    i32.rem_s)
+
+  (func (export "mixed") (result i64 f32 f64)
+    i64.const 1099511627776
+    f32.const 1.5
+    f64.const -2.25))
@@ -0,0 +1,112 @@
+;; The functions of the hello_tinygo sample guest, written by hand to
+;; the TinyGo guest ABI so that it can be tested without the TinyGo
+;; toolchain. Load this module with GuestAbi::TinyGo.
+(module
+  (import "env" "HostLog" (func $host_log (param i32 i32) (result i32)))
+  (import "env" "HostBytes" (func $host_bytes (param i32) (result i64)))
+
+  (memory (export "memory") 1)
+  (data (i32.const 0) "Hello, ")
+
+  ;; A bump allocator, whose allocations are never reused
+  (global $heap (mut i32) (i32.const 1024))
+
+  (func $malloc (export "malloc") (param $size i32) (result i32)
+    (local $addr i32)
+    global.get $heap
+    local.set $addr
+    local.get $addr
+    local.get $size
+    i32.add
+    i32.const 7
+    i32.add
+    i32.const -8
+    i32.and
+    global.set $heap
+    local.get $addr)
+
+  (func (export "free") (param i32))
+
+  ;; Strings are returned as the pointer in the lower 32 bits and the
+  ;; length in the upper 32 bits
+  (func (export "Greet") (param $name i32) (param $len i32) (result i64)
+    (local $out i32)
+    local.get $len
+    i32.const 8
+    i32.add
+    call $malloc
+    local.set $out
+    local.get $out
+    i32.const 0
+    i32.const 7
+    memory.copy
+    local.get $out
+    i32.const 7
+    i32.add
+    local.get $name
+    local.get $len
+    memory.copy
+    local.get $out
+    i32.const 7
+    i32.add
+    local.get $len
+    i32.add
+    i32.const 33
+    i32.store8
+    local.get $len
+    i32.const 8
+    i32.add
+    i64.extend_i32_u
+    i64.const 32
+    i64.shl
+    local.get $out
+    i64.extend_i32_u
+    i64.or)
+
+  (func (export "LogMessage") (param $msg i32) (param $len i32) (result i32)
+    local.get $msg
+    local.get $len
+    call $host_log)
+
+  (func (export "ByteLength") (param $buf i32) (param $len i32) (result i32)
+    local.get $len)
+
+  ;; Sum the bytes returned by the host, which are returned to every
+  ;; guest as a packed pointer and length
+  (func (export "SumHostBytes") (param $n i32) (result i32)
+    (local $packed i64)
+    (local $addr i32)
+    (local $end i32)
+    (local $sum i32)
+    local.get $n
+    call $host_bytes
+    local.set $packed
+    local.get $packed
+    i32.wrap_i64
+    local.set $addr
+    local.get $addr
+    local.get $packed
+    i64.const 32
+    i64.shr_u
+    i32.wrap_i64
+    i32.add
+    local.set $end
+    block $done
+      loop $next
+        local.get $addr
+        local.get $end
+        i32.ge_u
+        br_if $done
+        local.get $sum
+        local.get $addr
+        i32.load8_u
+        i32.add
+        local.set $sum
+        local.get $addr
+        i32.const 1
+        i32.add
+        local.set $addr
+        br $next
+      end
+    end
+    local.get $sum))